@@ -0,0 +1,299 @@
+use std::{
+    future::Future,
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::Result;
+use crypto::{digest::Digest, sha2::Sha256};
+use tokio::{
+    fs::{self, OpenOptions},
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+};
+use tracing::warn;
+
+/// size of the chunks pulled by the agent
+pub const CHUNK_SIZE: u64 = 1024 * 1024;
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input(data);
+    hasher.result_str()
+}
+
+/// sha256 of the file, read by chunks
+pub async fn file_sha256(path: impl AsRef<Path>) -> Result<(String, u64)> {
+    let mut file = fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.input(&buf[..n]);
+        size += n as u64;
+    }
+    Ok((hasher.result_str(), size))
+}
+
+/// the hash is used as the file name, anything else is rejected
+pub fn is_valid_hash(sha256: &str) -> bool {
+    sha256.len() == 64
+        && sha256
+            .bytes()
+            .all(|v| v.is_ascii_digit() || (b'a'..=b'f').contains(&v))
+}
+
+/// files stored by the sha256 of the content, an artifact is only visible when it is complete
+#[derive(Clone)]
+pub struct ArtifactStore {
+    dir: PathBuf,
+    /// the least recently used artifacts are evicted beyond this size
+    max_size: Option<u64>,
+}
+
+impl ArtifactStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            max_size: None,
+        }
+    }
+
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    pub fn path(&self, sha256: &str) -> Result<PathBuf> {
+        if !is_valid_hash(sha256) {
+            anyhow::bail!("invalid artifact hash {sha256}");
+        }
+        Ok(self.dir.join(sha256))
+    }
+
+    /// size of the artifact, none if it does not exist
+    pub async fn size(&self, sha256: &str) -> Result<Option<u64>> {
+        match fs::metadata(self.path(sha256)?).await {
+            Ok(v) => Ok(Some(v.len())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn save(&self, sha256: &str, data: &[u8]) -> Result<()> {
+        let path = self.path(sha256)?;
+        if sha256_hex(data) != sha256 {
+            anyhow::bail!("the content does not match the hash {sha256}");
+        }
+        fs::create_dir_all(&self.dir).await?;
+        // the concurrent uploads of the same artifact write their own file
+        let tmp = self
+            .dir
+            .join(format!("{sha256}.{}.tmp", nanoid::nanoid!(8)));
+        fs::write(&tmp, data).await?;
+        fs::rename(&tmp, &path).await?;
+        Ok(())
+    }
+
+    pub async fn read(&self, sha256: &str) -> Result<Vec<u8>> {
+        Ok(fs::read(self.path(sha256)?).await?)
+    }
+
+    /// at most CHUNK_SIZE bytes from the offset, empty at the end of the artifact
+    pub async fn read_chunk(&self, sha256: &str, offset: u64, limit: u64) -> Result<Vec<u8>> {
+        let mut file = fs::File::open(self.path(sha256)?).await?;
+        file.seek(SeekFrom::Start(offset)).await?;
+        let mut buf = Vec::new();
+        file.take(limit.min(CHUNK_SIZE))
+            .read_to_end(&mut buf)
+            .await?;
+        Ok(buf)
+    }
+
+    /// pull the missing artifact by chunks, the chunks already pulled by
+    /// the interrupted transfer are kept in the .part file and not pulled again
+    pub async fn pull<F, Fut>(&self, sha256: &str, size: u64, fetch: F) -> Result<PathBuf>
+    where
+        F: Fn(u64, u64) -> Fut,
+        Fut: Future<Output = Result<Vec<u8>>>,
+    {
+        let path = self.path(sha256)?;
+        if self.size(sha256).await? == Some(size) {
+            if file_sha256(&path).await?.0 == sha256 {
+                self.touch(&path).await?;
+                return Ok(path);
+            }
+            warn!("cached artifact {sha256} is corrupted, pull it again");
+            fs::remove_file(&path).await?;
+        }
+
+        fs::create_dir_all(&self.dir).await?;
+        let part = self.dir.join(format!("{sha256}.part"));
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&part)
+            .await?;
+        let mut offset = file.metadata().await?.len();
+        if offset > size {
+            file.set_len(0).await?;
+            offset = 0;
+        }
+
+        while offset < size {
+            let chunk = fetch(offset, (size - offset).min(CHUNK_SIZE)).await?;
+            if chunk.is_empty() {
+                anyhow::bail!("artifact {sha256} ends at {offset}, expect {size} bytes");
+            }
+            file.write_all(&chunk).await?;
+            offset += chunk.len() as u64;
+        }
+        file.flush().await?;
+        drop(file);
+
+        let (got, _) = file_sha256(&part).await?;
+        if got != sha256 {
+            fs::remove_file(&part).await?;
+            anyhow::bail!("the pulled artifact does not match the hash {sha256}");
+        }
+        fs::rename(&part, &path).await?;
+        self.evict(sha256).await?;
+        Ok(path)
+    }
+
+    /// the modified time of an artifact is the last time it was used
+    async fn touch(&self, path: &Path) -> Result<()> {
+        let file = fs::OpenOptions::new().write(true).open(path).await?;
+        file.into_std().await.set_modified(SystemTime::now())?;
+        Ok(())
+    }
+
+    /// remove the least recently used artifacts until the store fits in max_size,
+    /// the artifact just pulled is kept even if it exceeds max_size alone
+    pub async fn evict(&self, keep: &str) -> Result<Vec<String>> {
+        let Some(max_size) = self.max_size else {
+            return Ok(vec![]);
+        };
+        let mut artifacts = vec![];
+        let mut entries = fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !is_valid_hash(&name) {
+                continue;
+            }
+            let meta = entry.metadata().await?;
+            artifacts.push((meta.modified()?, meta.len(), name));
+        }
+
+        let mut total: u64 = artifacts.iter().map(|v| v.1).sum();
+        artifacts.sort();
+        let mut evicted = vec![];
+        for (_, size, name) in artifacts {
+            if total <= max_size {
+                break;
+            }
+            if name == keep {
+                continue;
+            }
+            fs::remove_file(self.dir.join(&name)).await?;
+            total -= size;
+            evicted.push(name);
+        }
+        Ok(evicted)
+    }
+}
+
+#[tokio::test]
+async fn test_artifact_store() {
+    let dir = std::env::temp_dir().join(format!("jiascheduler-artifact-{}", nanoid::nanoid!(8)));
+    let (source, cache) = (
+        ArtifactStore::new(dir.join("source")),
+        ArtifactStore::new(dir.join("cache")),
+    );
+    let data = (0..CHUNK_SIZE * 2 + 10)
+        .map(|v| (v % 251) as u8)
+        .collect::<Vec<_>>();
+    let sha256 = sha256_hex(&data);
+
+    assert!(source.path("../etc/passwd").is_err());
+    assert!(source.save(&sha256, b"other").await.is_err());
+    source.save(&sha256, &data).await.unwrap();
+    assert_eq!(source.size(&sha256).await.unwrap(), Some(data.len() as u64));
+
+    // resume the interrupted transfer from the .part file
+    fs::create_dir_all(dir.join("cache")).await.unwrap();
+    fs::write(
+        dir.join("cache").join(format!("{sha256}.part")),
+        &data[..100],
+    )
+    .await
+    .unwrap();
+    let fetched = std::sync::atomic::AtomicU64::new(0);
+    let (src, hash, counter) = (&source, sha256.as_str(), &fetched);
+    let path = cache
+        .pull(&sha256, data.len() as u64, move |offset, limit| {
+            counter.fetch_add(limit, std::sync::atomic::Ordering::Relaxed);
+            src.read_chunk(hash, offset, limit)
+        })
+        .await
+        .unwrap();
+    assert_eq!(fs::read(path).await.unwrap(), data);
+    assert_eq!(
+        fetched.load(std::sync::atomic::Ordering::Relaxed),
+        data.len() as u64 - 100
+    );
+
+    // the cached artifact is not pulled again
+    cache
+        .pull(&sha256, data.len() as u64, |_, _| async {
+            Err(anyhow::anyhow!("pulled again"))
+        })
+        .await
+        .unwrap();
+
+    // the corrupted cache is pulled again
+    fs::write(dir.join("cache").join(&sha256), vec![0u8; data.len()])
+        .await
+        .unwrap();
+    let path = cache
+        .pull(&sha256, data.len() as u64, move |offset, limit| {
+            src.read_chunk(hash, offset, limit)
+        })
+        .await
+        .unwrap();
+    assert_eq!(fs::read(path).await.unwrap(), data);
+
+    let _ = fs::remove_dir_all(dir).await;
+}
+
+#[tokio::test]
+async fn test_artifact_evict() {
+    let dir = std::env::temp_dir().join(format!("jiascheduler-artifact-{}", nanoid::nanoid!(8)));
+    let store = ArtifactStore::new(&dir).with_max_size(25);
+    let mut hashes = vec![];
+    for i in 0..3u8 {
+        let data = vec![i; 10];
+        let sha256 = sha256_hex(&data);
+        store.save(&sha256, &data).await.unwrap();
+        let modified = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(100 * i as u64);
+        std::fs::File::options()
+            .write(true)
+            .open(store.path(&sha256).unwrap())
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        hashes.push(sha256);
+    }
+
+    // the least recently used one is evicted, except the kept one
+    let evicted = store.evict(&hashes[0]).await.unwrap();
+    assert_eq!(evicted, vec![hashes[1].clone()]);
+    assert!(store.size(&hashes[0]).await.unwrap().is_some());
+    assert!(store.size(&hashes[2]).await.unwrap().is_some());
+
+    let _ = fs::remove_dir_all(dir).await;
+}
@@ -1,5 +1,6 @@
 // mod bridge;
 pub mod client;
+pub mod lane;
 pub mod msg;
 pub mod protocol;
 // pub mod server;
@@ -9,22 +10,22 @@ use std::{collections::HashMap, sync::Arc, time::Duration};
 use anyhow::{anyhow, Context, Ok, Result};
 use serde_json::Value;
 use tokio::{
-    sync::{
-        mpsc::{self, Sender},
-        Mutex,
-    },
+    sync::{mpsc, Mutex},
     time::timeout,
 };
 use tracing::info;
 
 use crate::bridge::msg::Msg;
 
-use self::msg::{MsgKind, MsgReqKind, MsgState};
+use self::{
+    lane::LaneSender,
+    msg::{MsgKind, MsgReqKind, MsgState},
+};
 
 #[derive(Clone)]
 pub struct Bridge {
     // server: WsServer,
-    server_clients: Arc<Mutex<HashMap<String, Sender<(Msg, Option<Sender<MsgState>>)>>>>,
+    server_clients: Arc<Mutex<HashMap<String, LaneSender>>>,
 }
 
 impl Default for Bridge {
@@ -41,11 +42,7 @@ impl Bridge {
         }
     }
 
-    pub async fn append_client(
-        &mut self,
-        key: impl Into<String>,
-        client: Sender<(Msg, Option<Sender<MsgState>>)>,
-    ) {
+    pub async fn append_client(&mut self, key: impl Into<String>, client: LaneSender) {
         self.server_clients.lock().await.insert(key.into(), client);
     }
 
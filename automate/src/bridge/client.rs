@@ -16,29 +16,30 @@ use poem::web::websocket::{Message as PMessage, WebSocketStream as PWebSocketStr
 use serde_json::{json, Value};
 use tokio::{
     net::TcpStream,
-    sync::mpsc::{self, Receiver, Sender},
+    sync::mpsc,
     time::timeout,
 };
 use tokio_tungstenite::{
-    connect_async,
+    connect_async_tls_with_config,
     tungstenite::{ClientRequestBuilder, Message},
     MaybeTlsStream, WebSocketStream,
 };
 use tracing::{error, info};
 
 use crate::{
-    get_endpoint,
+    get_endpoint, get_ws_connector,
     scheduler::types::{AssignUserOption, SshConnectionOption},
 };
 
 use super::{
-    msg::{AuthParams, Msg, MsgKind, MsgReqKind, MsgState, TransactionMsg},
-    protocol::Protocol,
+    lane::{self, Lane, LaneReceiver, LaneSender},
+    msg::{AuthParams, AuthResult, Msg, MsgKind, MsgReqKind, MsgState, TransactionMsg},
+    protocol::{Encoding, Protocol},
     Bridge,
 };
 
 pub struct WsClient<W, R> {
-    sender: Sender<(Msg, Option<Sender<MsgState>>)>,
+    sender: LaneSender,
     ws_writer: Option<W>,
     ws_reader: Option<R>,
     comet_secret: Option<String>,
@@ -49,13 +50,14 @@ pub struct WsClient<W, R> {
     ssh_connection_option: Option<SshConnectionOption>,
     assign_user_option: Option<AssignUserOption>,
     msg_box: Cache<u64, TransactionMsg>,
+    encoding: Encoding,
     bridge: Option<Bridge>,
-    receiver: Option<Receiver<(Msg, Option<Sender<MsgState>>)>>,
+    receiver: Option<LaneReceiver>,
 }
 
 impl<W, R> WsClient<W, R> {
     pub fn new(bridge: Option<Bridge>) -> Self {
-        let (sender, receiver) = mpsc::channel::<(Msg, Option<Sender<MsgState>>)>(100);
+        let (sender, receiver) = lane::channel(100);
         let cache: Cache<u64, TransactionMsg> = Cache::builder()
             .time_to_live(Duration::from_secs(5))
             .build();
@@ -69,6 +71,7 @@ impl<W, R> WsClient<W, R> {
             comet_secret: None,
             is_initialized: None,
             msg_box: cache,
+            encoding: Encoding::default(),
             assign_user_option: None,
             ssh_connection_option: None,
             ws_writer: None,
@@ -107,7 +110,7 @@ impl<W, R> WsClient<W, R> {
         self
     }
 
-    pub fn sender(&self) -> Sender<(Msg, Option<Sender<MsgState>>)> {
+    pub fn sender(&self) -> LaneSender {
         self.sender.clone()
     }
 
@@ -147,6 +150,10 @@ impl<W, R> WsClient<W, R> {
         self.namespace.clone().unwrap_or_default()
     }
 
+    pub fn get_encoding(&self) -> Encoding {
+        self.encoding
+    }
+
     pub fn get_local_ip(&self) -> String {
         self.local_ip
             .clone()
@@ -166,12 +173,13 @@ impl WsClient<SplitSink<PWebSocketStream, PMessage>, SplitStream<PWebSocketStrea
         let mut receiver = self.receiver.take().unwrap();
         let mut ws_writer = self.ws_writer.take().unwrap();
         let msg_box = self.msg_box.clone();
+        let encoding = self.encoding;
 
         tokio::spawn(async move {
             let id_count = AtomicU64::new(1);
             while let Some(mut v) = receiver.recv().await {
                 let buf = if let MsgKind::Response(_) = v.0.data {
-                    Protocol::pack_response(v.0)
+                    Protocol::pack_response(v.0, encoding)
                 } else {
                     v.0.id = id_count.fetch_add(1, Ordering::Relaxed);
 
@@ -179,7 +187,7 @@ impl WsClient<SplitSink<PWebSocketStream, PMessage>, SplitStream<PWebSocketStrea
                         let tran = TransactionMsg::new(tx.clone(), v.0.id);
                         msg_box.insert(v.0.id, tran).await;
                     }
-                    Protocol::pack_request(v.0)
+                    Protocol::pack_request(v.0, encoding)
                 };
 
                 ws_writer
@@ -219,12 +227,22 @@ impl WsClient<SplitSink<PWebSocketStream, PMessage>, SplitStream<PWebSocketStrea
                         self.is_initialized.replace(v.is_initialized);
                         self.ws_reader.replace(ws_reader);
 
+                        let protocol_version = Protocol::negotiate(v.protocol_version);
+                        let result = AuthResult {
+                            protocol_version,
+                            compress: Protocol::encoding(protocol_version, v.compress).compress,
+                        };
+                        // the auth response is always json, the agent knows nothing before it
                         let _ = ws_writer
-                            .send(PMessage::Binary(Protocol::pack_response(Msg {
-                                id: 0,
-                                data: MsgKind::Response(json!("ok")),
-                            })))
+                            .send(PMessage::Binary(Protocol::pack_response(
+                                Msg {
+                                    id: 0,
+                                    data: MsgKind::Response(json!(result)),
+                                },
+                                Encoding::default(),
+                            )))
                             .await?;
+                        self.encoding = Protocol::encoding(protocol_version, result.compress);
 
                         self.ws_writer.replace(ws_writer);
 
@@ -285,27 +303,35 @@ impl WsClient<SplitSink<PWebSocketStream, PMessage>, SplitStream<PWebSocketStrea
                             return;
                         }
 
-                        let resp = Protocol::unpack_request(buf)
+                        let (lane, resp) = Protocol::unpack_request(buf)
                             .map(|msg| async move {
                                 let id = msg.id;
                                 if let MsgKind::Request(req) = msg.data {
+                                    // the response is as urgent as its request
+                                    let lane = Lane::of_request(&req);
                                     let resp = handler(req).await;
-                                    Msg {
-                                        id,
-                                        data: MsgKind::Response(resp),
-                                    }
+                                    (
+                                        lane,
+                                        Msg {
+                                            id,
+                                            data: MsgKind::Response(resp),
+                                        },
+                                    )
                                 } else {
-                                    Msg {
-                                        id,
-                                        data: MsgKind::Response(json!("invalid data type")),
-                                    }
+                                    (
+                                        Lane::Dispatch,
+                                        Msg {
+                                            id,
+                                            data: MsgKind::Response(json!("invalid data type")),
+                                        },
+                                    )
                                 }
                             })
                             .map_err(|e| error!("failed unpack_request -{e}"))
                             .unwrap()
                             .await;
                         let _ = sender
-                            .send_timeout((resp, None), Duration::from_secs(1))
+                            .send_timeout_on(lane, (resp, None), Duration::from_secs(1))
                             .await
                             .map_err(|e| error!("failed send message - {e}"));
                     }
@@ -367,9 +393,12 @@ impl
                 .with_header("X-Ssh-Port", ssh_opt.port.to_string());
         }
 
-        let (ws_stream, _b) = timeout(Duration::from_secs(5), connect_async(req))
-            .await
-            .context("connect timeout")??;
+        let (ws_stream, _b) = timeout(
+            Duration::from_secs(5),
+            connect_async_tls_with_config(req, None, false, get_ws_connector()),
+        )
+        .await
+        .context("connect timeout")??;
         let (ws_writer, ws_reader) = ws_stream.split();
         self.ws_reader = Some(ws_reader);
         self.ws_writer = Some(ws_writer);
@@ -380,6 +409,9 @@ impl
 
         info!("success auth got response {auth_resp}");
 
+        let result = serde_json::from_value::<AuthResult>(auth_resp).unwrap_or_default();
+        self.encoding = Protocol::encoding(result.protocol_version, result.compress);
+
         self.start_processing_to_server_msg();
         Ok(self)
     }
@@ -388,19 +420,20 @@ impl
         let mut receiver = self.receiver.take().unwrap();
         let mut ws_writer = self.ws_writer.take().unwrap();
         let msg_box = self.msg_box.clone();
+        let encoding = self.encoding;
 
         tokio::spawn(async move {
             let id_count = AtomicU64::new(1);
             while let Some(mut v) = receiver.recv().await {
                 let buf = if let MsgKind::Response(_) = v.0.data {
-                    Protocol::pack_response(v.0)
+                    Protocol::pack_response(v.0, encoding)
                 } else {
                     v.0.id = id_count.fetch_add(1, Ordering::Relaxed);
                     if let Some(tx) = v.1 {
                         let tran = TransactionMsg::new(tx.clone(), v.0.id);
                         msg_box.insert(v.0.id, tran).await;
                     }
-                    Protocol::pack_request(v.0)
+                    Protocol::pack_request(v.0, encoding)
                 };
 
                 let ret = timeout(
@@ -430,14 +463,19 @@ impl
 
         let _ = timeout(
             Duration::from_secs(5),
-            ws_writer.send(Message::Binary(Protocol::pack_request(Msg {
-                id: 0,
-                data: MsgKind::Request(MsgReqKind::Auth(AuthParams {
-                    is_initialized,
-                    agent_ip: self.local_ip.unwrap().to_string(),
-                    secret,
-                })),
-            }))),
+            ws_writer.send(Message::Binary(Protocol::pack_request(
+                Msg {
+                    id: 0,
+                    data: MsgKind::Request(MsgReqKind::Auth(AuthParams {
+                        is_initialized,
+                        agent_ip: self.local_ip.unwrap().to_string(),
+                        secret,
+                        protocol_version: Protocol::VERSION,
+                        compress: true,
+                    })),
+                },
+                Encoding::default(),
+            ))),
         )
         .await
         .context("ws writer send timeout")??;
@@ -513,27 +551,35 @@ impl
                             return;
                         }
 
-                        let resp = Protocol::unpack_request(buf)
+                        let (lane, resp) = Protocol::unpack_request(buf)
                             .map(|msg| async move {
                                 let id = msg.id;
                                 if let MsgKind::Request(req) = msg.data {
+                                    // the response is as urgent as its request
+                                    let lane = Lane::of_request(&req);
                                     let resp = handler(req).await;
-                                    Msg {
-                                        id,
-                                        data: MsgKind::Response(resp),
-                                    }
+                                    (
+                                        lane,
+                                        Msg {
+                                            id,
+                                            data: MsgKind::Response(resp),
+                                        },
+                                    )
                                 } else {
-                                    Msg {
-                                        id,
-                                        data: MsgKind::Response(json!("invalid data type")),
-                                    }
+                                    (
+                                        Lane::Dispatch,
+                                        Msg {
+                                            id,
+                                            data: MsgKind::Response(json!("invalid data type")),
+                                        },
+                                    )
                                 }
                             })
                             .map_err(|e| error!("failed unpack_request -{e}"))
                             .unwrap()
                             .await;
                         let _ = sender
-                            .send_timeout((resp, None), Duration::from_secs(1))
+                            .send_timeout_on(lane, (resp, None), Duration::from_secs(1))
                             .await
                             .map_err(|e| error!("failed send message - {e}"));
                     }
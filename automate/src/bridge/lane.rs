@@ -0,0 +1,179 @@
+use std::time::Duration;
+
+use tokio::sync::mpsc::{
+    self, Receiver, Sender,
+    error::{SendError, SendTimeoutError},
+};
+
+use super::msg::{Msg, MsgKind, MsgReqKind, MsgState};
+
+/// a message waiting to be written to the connection, with the sender of its response
+pub type Envelope = (Msg, Option<Sender<MsgState>>);
+
+/// the queues of the messages to the connection, a lane is only served when
+/// the lanes before it are empty, so a flood of status updates never delays a kill
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lane {
+    /// the liveness of the connection
+    Control = 0,
+    /// the dispatches and the actions of the jobs, in order, so a kill never overtakes
+    /// the exec of the same job queued before it
+    Dispatch = 1,
+    /// the status updates and the logs reported by the agents
+    Telemetry = 2,
+}
+
+impl Lane {
+    const COUNT: usize = 3;
+
+    pub fn of(data: &MsgKind) -> Self {
+        match data {
+            MsgKind::Request(req) => Self::of_request(req),
+            // the responses are sent on the lane of their request by the receiver
+            MsgKind::Response(_) => Self::Dispatch,
+        }
+    }
+
+    pub fn of_request(req: &MsgReqKind) -> Self {
+        match req {
+            MsgReqKind::Auth(_) | MsgReqKind::HeartbeatRequest(_) => Self::Control,
+            MsgReqKind::UpdateJobRequest(_)
+            | MsgReqKind::AgentLogRequest(_)
+            | MsgReqKind::PullJobRequest(_) => Self::Telemetry,
+            _ => Self::Dispatch,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct LaneSender {
+    lanes: [Sender<Envelope>; Lane::COUNT],
+}
+
+pub struct LaneReceiver {
+    lanes: [Receiver<Envelope>; Lane::COUNT],
+}
+
+/// a bounded queue of each lane with the capacity
+pub fn channel(capacity: usize) -> (LaneSender, LaneReceiver) {
+    let (control_tx, control_rx) = mpsc::channel(capacity);
+    let (dispatch_tx, dispatch_rx) = mpsc::channel(capacity);
+    let (telemetry_tx, telemetry_rx) = mpsc::channel(capacity);
+    (
+        LaneSender {
+            lanes: [control_tx, dispatch_tx, telemetry_tx],
+        },
+        LaneReceiver {
+            lanes: [control_rx, dispatch_rx, telemetry_rx],
+        },
+    )
+}
+
+impl LaneSender {
+    /// send on the lane of the message
+    pub async fn send(&self, v: Envelope) -> Result<(), SendError<Envelope>> {
+        let lane = Lane::of(&v.0.data);
+        self.send_on(lane, v).await
+    }
+
+    pub async fn send_on(&self, lane: Lane, v: Envelope) -> Result<(), SendError<Envelope>> {
+        self.lanes[lane as usize].send(v).await
+    }
+
+    pub async fn send_timeout_on(
+        &self,
+        lane: Lane,
+        v: Envelope,
+        timeout: Duration,
+    ) -> Result<(), SendTimeoutError<Envelope>> {
+        self.lanes[lane as usize].send_timeout(v, timeout).await
+    }
+}
+
+impl LaneReceiver {
+    /// the next message of the most urgent non-empty lane, None when all the senders are dropped
+    pub async fn recv(&mut self) -> Option<Envelope> {
+        let [control, dispatch, telemetry] = &mut self.lanes;
+        tokio::select! {
+            biased;
+            Some(v) = control.recv() => Some(v),
+            Some(v) = dispatch.recv() => Some(v),
+            Some(v) = telemetry.recv() => Some(v),
+            else => None,
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_lane_priority() {
+    use serde_json::json;
+
+    use crate::{
+        bridge::msg::{DispatchJobParams, HeartbeatParams, RuntimeActionParams},
+        scheduler::types::{BaseJob, JobAction, RuntimeAction},
+    };
+
+    let msg = |data: MsgReqKind| Msg {
+        id: 0,
+        data: MsgKind::Request(data),
+    };
+    let exec = msg(MsgReqKind::DispatchJobRequest(DispatchJobParams {
+        base_job: BaseJob {
+            eid: "e1".to_string(),
+            ..Default::default()
+        },
+        schedule_id: "s1".to_string(),
+        instance_id: None,
+        run_id: "r1".to_string(),
+        fields: None,
+        timer_expr: None,
+        restart_interval: None,
+        is_sync: false,
+        created_user: "admin".to_string(),
+        action: JobAction::Exec,
+        blackout_windows: vec![],
+        request_id: String::new(),
+        dispatch_id: String::new(),
+    }));
+    let kill = msg(MsgReqKind::RuntimeActionRequest(RuntimeActionParams {
+        eid: "e1".to_string(),
+        fields: None,
+        is_sync: false,
+        created_user: "admin".to_string(),
+        action: RuntimeAction::Kill,
+    }));
+    let heartbeat = msg(MsgReqKind::HeartbeatRequest(HeartbeatParams {
+        namespace: "default".to_string(),
+        mac_addr: String::new(),
+        source_ip: String::new(),
+        facts: None,
+        agent_config: None,
+    }));
+    assert_eq!(Lane::of(&kill.data), Lane::Dispatch);
+    assert_eq!(Lane::of(&heartbeat.data), Lane::Control);
+
+    let (tx, mut rx) = channel(10);
+    for i in 0..5 {
+        tx.send((msg(MsgReqKind::PullJobRequest(json!(i))), None))
+            .await
+            .unwrap();
+    }
+    tx.send((exec.clone(), None)).await.unwrap();
+    tx.send((kill.clone(), None)).await.unwrap();
+    tx.send((heartbeat.clone(), None)).await.unwrap();
+
+    // the heartbeat jumps the queues, the kill stays behind the exec queued before it,
+    // then the telemetry in order
+    assert!(rx.recv().await.unwrap().0 == heartbeat);
+    assert!(rx.recv().await.unwrap().0 == exec);
+    assert!(rx.recv().await.unwrap().0 == kill);
+    for i in 0..5 {
+        assert!(
+            rx.recv().await.unwrap().0.data
+                == MsgKind::Request(MsgReqKind::PullJobRequest(json!(i)))
+        );
+    }
+
+    drop(tx);
+    assert!(rx.recv().await.is_none());
+}
@@ -9,8 +9,10 @@ use tokio::sync::mpsc::Sender;
 use crate::{
     comet::handler::SecretHeader,
     scheduler::types::{
-        BaseJob, BundleOutput, JobAction, RunStatus, RuntimeAction, ScheduleStatus, ScheduleType,
+        BaseJob, BlackoutWindow, BundleOutput, JobAction, RunStatus, RuntimeAction, ScheduleStatus,
+        ScheduleType,
     },
+    ssh::PrivateKey,
 };
 
 pub enum MsgState {
@@ -34,6 +36,9 @@ impl TransactionMsg {
 pub struct SftpReadDirParams {
     pub user: String,
     pub password: String,
+    /// used instead of the password if set
+    #[serde(default)]
+    pub private_key: Option<PrivateKey>,
     pub ip: String,
     pub port: u16,
     pub dir: Option<String>,
@@ -45,6 +50,9 @@ pub struct SftpUploadParams {
     pub port: u16,
     pub user: String,
     pub password: String,
+    /// used instead of the password if set
+    #[serde(default)]
+    pub private_key: Option<PrivateKey>,
     pub filepath: String,
     pub data: Vec<u8>,
 }
@@ -55,6 +63,9 @@ pub struct SftpDownloadParams {
     pub port: u16,
     pub user: String,
     pub password: String,
+    /// used instead of the password if set
+    #[serde(default)]
+    pub private_key: Option<PrivateKey>,
     pub filepath: String,
 }
 
@@ -64,6 +75,9 @@ pub struct SftpRemoveParams {
     pub port: u16,
     pub user: String,
     pub password: String,
+    /// used instead of the password if set
+    #[serde(default)]
+    pub private_key: Option<PrivateKey>,
     pub remove_type: String,
     pub filepath: String,
 }
@@ -80,6 +94,12 @@ pub enum MsgReqKind {
     Auth(AuthParams),
     UpdateJobRequest(UpdateJobParams),
     HeartbeatRequest(HeartbeatParams),
+    DispatchAckRequest(DispatchAckParams),
+    ReconcileDispatchRequest(ReconcileDispatchParams),
+    PruneScheduleRequest(PruneScheduleParams),
+    RunLockRequest(RunLockParams),
+    AgentLogRequest(AgentLogParams),
+    AgentConfigRequest(AgentConfigParams),
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
@@ -98,6 +118,23 @@ pub struct Msg {
 pub struct TimerExpr {
     pub timezone: String,
     pub expr: String,
+    /// run every interval seconds instead of the cron expr
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval: Option<u64>,
+    /// percentage of the interval each run is randomly delayed by, spreads the runs of many agents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jitter: Option<u8>,
+}
+
+impl TimerExpr {
+    /// random delay of the next run, None if no jitter is set
+    pub fn jitter_delay(&self) -> Option<Duration> {
+        let max = self.interval? * 1000 * self.jitter?.min(100) as u64 / 100;
+        if max == 0 {
+            return None;
+        }
+        Some(Duration::from_millis(rand::random_range(0..max)))
+    }
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
@@ -113,6 +150,140 @@ pub struct DispatchJobParams {
     pub is_sync: bool,
     pub created_user: String,
     pub action: JobAction,
+    #[serde(default)]
+    pub blackout_windows: Vec<BlackoutWindow>,
+    /// id of the console request which dispatched the job
+    #[serde(default)]
+    pub request_id: String,
+    /// retries of the same dispatch share the id so that the agent runs it once, empty to disable
+    #[serde(default)]
+    pub dispatch_id: String,
+}
+
+impl DispatchJobParams {
+    pub fn get_blackout_window(&self, now: DateTime<Utc>) -> Option<&BlackoutWindow> {
+        self.blackout_windows.iter().find(|v| v.contains(now))
+    }
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum DispatchAckStatus {
+    /// the agent accepted the dispatch and started the job
+    Ack,
+    /// the agent rejected the dispatch, it can be retried with the same dispatch id
+    Nack,
+}
+
+/// asked by the agent before running a tick of a singleton job,
+/// the tick is granted to the first instance asking for it
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+pub struct RunLockParams {
+    pub eid: String,
+    pub schedule_id: String,
+    /// the tick in seconds, the agents firing the same tick with a small clock skew agree on it
+    pub tick: i64,
+}
+
+impl RunLockParams {
+    pub fn new(params: &DispatchJobParams, fired_at: DateTime<Utc>) -> Self {
+        Self {
+            eid: params.base_job.eid.clone(),
+            schedule_id: params.schedule_id.clone(),
+            tick: Self::tick_of(fired_at),
+        }
+    }
+
+    /// the fired time rounded to seconds
+    pub fn tick_of(fired_at: DateTime<Utc>) -> i64 {
+        (fired_at.timestamp_millis() + 500).div_euclid(1000)
+    }
+}
+
+/// sent by the agent once a dispatch is accepted or rejected
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+pub struct DispatchAckParams {
+    pub dispatch_id: String,
+    pub schedule_id: String,
+    pub instance_id: Option<String>,
+    pub run_id: String,
+    pub action: JobAction,
+    pub status: DispatchAckStatus,
+    pub reason: Option<String>,
+    pub time: DateTime<Utc>,
+}
+
+impl DispatchAckParams {
+    pub fn new(params: &DispatchJobParams, status: DispatchAckStatus) -> Self {
+        Self {
+            dispatch_id: params.dispatch_id.clone(),
+            schedule_id: params.schedule_id.clone(),
+            instance_id: params.instance_id.clone(),
+            run_id: params.run_id.clone(),
+            action: params.action,
+            status,
+            reason: None,
+            time: Utc::now(),
+        }
+    }
+
+    pub fn is_ack(&self) -> bool {
+        self.status == DispatchAckStatus::Ack
+    }
+}
+
+/// ask the agent which dispatches of the schedule it has received
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct ReconcileDispatchParams {
+    pub schedule_id: String,
+    #[serde(default)]
+    pub dispatch_id: Option<String>,
+}
+
+impl ReconcileDispatchParams {
+    pub fn matches(&self, ack: &DispatchAckParams) -> bool {
+        ack.schedule_id == self.schedule_id
+            && self
+                .dispatch_id
+                .as_ref()
+                .is_none_or(|v| *v == ack.dispatch_id)
+    }
+}
+
+/// timers and supervisors the console expects on the agent,
+/// the other ones restored from the local store are stopped
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct PruneScheduleParams {
+    pub timer_eids: Vec<String>,
+    pub daemon_eids: Vec<String>,
+}
+
+/// read the recent log lines of the agent itself, poll with the next_seq of the last output
+/// to follow the log
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct AgentLogParams {
+    /// only the lines after the seq, 0 for the recent ones
+    #[serde(default)]
+    pub since: u64,
+    pub limit: usize,
+    /// only the lines containing the keyword if not empty
+    #[serde(default)]
+    pub keyword: String,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct AgentLogLine {
+    pub seq: u64,
+    pub line: String,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct AgentLogOutput {
+    /// false if the agent does not keep its logs, eg: started with --log-buffer-lines 0
+    pub enabled: bool,
+    pub lines: Vec<AgentLogLine>,
+    /// the since of the next poll
+    pub next_seq: u64,
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
@@ -129,6 +300,67 @@ pub struct HeartbeatParams {
     pub namespace: String,
     pub mac_addr: String,
     pub source_ip: String,
+    /// none if the agent does not report facts
+    #[serde(default)]
+    pub facts: Option<InstanceFacts>,
+    /// none if the agent does not support the config pushed by the console
+    #[serde(default)]
+    pub agent_config: Option<AgentConfigState>,
+}
+
+/// hardware and os inventory reported by the agent, sizes are in bytes
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct InstanceFacts {
+    pub os: String,
+    pub os_version: String,
+    pub kernel_version: String,
+    pub arch: String,
+    pub cpu_count: u32,
+    pub mem_total: u64,
+    pub mem_used: u64,
+    pub disk_total: u64,
+    pub disk_used: u64,
+    pub agent_version: String,
+}
+
+/// settings of the agent managed by the console, the unset ones fall back to the flags
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct AgentConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_dir: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_level: Option<String>,
+    /// seconds between the heartbeats
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heartbeat_interval: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comet_addr: Option<Vec<String>>,
+}
+
+impl AgentConfig {
+    /// the settings of self, those unset are taken from other
+    pub fn or(&self, other: &AgentConfig) -> AgentConfig {
+        AgentConfig {
+            output_dir: self.output_dir.clone().or(other.output_dir.clone()),
+            log_level: self.log_level.clone().or(other.log_level.clone()),
+            heartbeat_interval: self.heartbeat_interval.or(other.heartbeat_interval),
+            comet_addr: self.comet_addr.clone().or(other.comet_addr.clone()),
+        }
+    }
+}
+
+/// replace the config saved by the agent
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct AgentConfigParams {
+    pub config: AgentConfig,
+}
+
+/// the config saved by the agent and the settings in effect,
+/// the output_dir and comet_addr saved take effect after the agent restarts
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct AgentConfigState {
+    pub config: AgentConfig,
+    pub effective: AgentConfig,
 }
 
 impl HeartbeatParams {
@@ -164,6 +396,57 @@ pub struct UpdateJobParams {
     pub prev_time: Option<DateTime<Utc>>,
     pub next_time: Option<DateTime<Utc>>,
     pub is_timeout: bool,
+    #[serde(default)]
+    pub request_id: String,
+    /// periodic sample of a supervised job, the other status fields are not set
+    #[serde(default)]
+    pub resource_usage: Option<ResourceUsage>,
+    /// what the run was started with, set when the run stops
+    #[serde(default)]
+    pub exec_context: Option<ExecContext>,
+    /// the timeout actions fired in order, eg: sigterm,cleanup,notify, set when the run stops
+    #[serde(default)]
+    pub timeout_action: Option<String>,
+    /// stdout or stderr was cut to the output limit of the job
+    #[serde(default)]
+    pub output_truncated: bool,
+    /// where the full output of the truncated run was uploaded
+    #[serde(default)]
+    pub output_url: Option<String>,
+}
+
+/// the agent, host, executors and environment a run was started with
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct ExecContext {
+    pub agent_version: String,
+    pub os: String,
+    pub os_version: String,
+    pub kernel_version: String,
+    pub arch: String,
+    pub commands: Vec<ExecCommand>,
+    /// sha256 of the environment variables of the process, sorted by name
+    pub env_hash: String,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct ExecCommand {
+    pub cmd_name: String,
+    /// the first line printed by `cmd_name --version`, empty if not probed
+    pub version: String,
+    /// the image the command is run in, the version is not probed in a container
+    #[serde(default)]
+    pub image: Option<String>,
+}
+
+/// cpu and memory of a process and its descendants
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct ResourceUsage {
+    /// percentage of a single core, may exceed 100 on multiple cores
+    pub cpu_usage: f32,
+    /// resident memory in bytes
+    pub rss: u64,
+    pub process_count: u32,
+    pub sample_time: DateTime<Utc>,
 }
 
 impl UpdateJobParams {
@@ -184,26 +467,30 @@ pub struct BundleOutputParams {
     pub exit_status: Option<String>,
     pub stdout: Option<String>,
     pub stderr: Option<String>,
+    /// the order the script was started in
+    #[serde(default)]
+    pub seq: Option<usize>,
 }
 
 impl BundleOutputParams {
     pub fn parse(value: &BundleOutput) -> Option<Vec<BundleOutputParams>> {
         match value {
-            BundleOutput::Output(_) => None,
+            BundleOutput::Output(_) | BundleOutput::Http(_) => None,
             BundleOutput::Bundle(v) => Some(
                 v.iter()
                     .map(|v| BundleOutputParams {
-                        eid: v.0.to_owned(),
+                        eid: v.eid.to_owned(),
                         exit_code: {
-                            if v.1.status.success() {
-                                v.1.status.code()
+                            if v.output.status.success() {
+                                v.output.status.code()
                             } else {
-                                v.1.status.code().or(Some(9))
+                                v.output.status.code().or(Some(9))
                             }
                         },
-                        exit_status: Some(v.1.status.to_string()),
-                        stdout: Some(String::from_utf8_lossy(&v.1.stdout).to_string()),
-                        stderr: Some(String::from_utf8_lossy(&v.1.stderr).to_string()),
+                        exit_status: Some(v.output.status.to_string()),
+                        stdout: Some(String::from_utf8_lossy(&v.output.stdout).to_string()),
+                        stderr: Some(String::from_utf8_lossy(&v.output.stderr).to_string()),
+                        seq: Some(v.seq),
                     })
                     .collect::<Vec<BundleOutputParams>>(),
             ),
@@ -216,6 +503,18 @@ pub struct AuthParams {
     pub agent_ip: String,
     pub secret: String,
     pub is_initialized: bool,
+    #[serde(default)]
+    pub protocol_version: u32,
+    /// the agent accepts the zstd compressed messages
+    #[serde(default)]
+    pub compress: bool,
+}
+
+/// response of the auth, the comets before the protocol version respond "ok"
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct AuthResult {
+    pub protocol_version: u32,
+    pub compress: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -232,3 +531,53 @@ pub struct AgentOfflineParams {
     pub agent_ip: String,
     pub mac_addr: String,
 }
+
+#[test]
+fn test_timer_expr_jitter_delay() {
+    let mut expr = TimerExpr {
+        interval: Some(60),
+        jitter: Some(10),
+        ..Default::default()
+    };
+    for _ in 0..100 {
+        assert!(expr.jitter_delay().unwrap() < Duration::from_secs(6));
+    }
+
+    expr.jitter = Some(0);
+    assert!(expr.jitter_delay().is_none());
+    expr.jitter = None;
+    assert!(expr.jitter_delay().is_none());
+}
+
+#[test]
+fn test_run_lock_tick() {
+    let at = |ms| DateTime::from_timestamp_millis(ms).unwrap();
+    assert_eq!(RunLockParams::tick_of(at(59_700)), 60);
+    assert_eq!(RunLockParams::tick_of(at(60_300)), 60);
+    assert_eq!(RunLockParams::tick_of(at(60_600)), 61);
+}
+
+#[test]
+fn test_heartbeat_without_facts() {
+    let v: HeartbeatParams = serde_json::from_value(serde_json::json!({
+        "namespace": "default",
+        "mac_addr": "00:11:22:33:44:55",
+        "source_ip": "10.0.0.1",
+    }))
+    .unwrap();
+    assert!(v.facts.is_none());
+    assert!(v.agent_config.is_none());
+}
+
+#[test]
+fn test_sftp_params_without_private_key() {
+    let v: SftpDownloadParams = serde_json::from_value(serde_json::json!({
+        "ip": "10.0.0.1",
+        "port": 22,
+        "user": "deploy",
+        "password": "secret",
+        "filepath": "/tmp/a.txt",
+    }))
+    .unwrap();
+    assert!(v.private_key.is_none());
+}
@@ -1,47 +1,134 @@
+use std::io::Read;
+
 use anyhow::{anyhow, Result};
 use bytes::{BufMut, BytesMut};
+use serde::{Deserialize, Serialize};
 
 use super::msg::Msg;
 
 pub struct Protocol {}
 
+/// how the messages of a connection are encoded, negotiated on auth
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Encoding {
+    /// messagepack instead of json
+    pub binary: bool,
+    /// zstd compress the large messages
+    pub compress: bool,
+}
+
 impl Protocol {
     const REQ_MARK: u8 = 0;
     const RESP_MARK: u8 = 1;
+    /// flags of the mark byte, the agents before version 2 only send the bare marks
+    const MSGPACK_FLAG: u8 = 0b10;
+    const ZSTD_FLAG: u8 = 0b100;
+
+    /// the smaller messages are not worth compressing
+    const COMPRESS_THRESHOLD: usize = 1024;
+    const ZSTD_LEVEL: i32 = 3;
+    /// the decompressed size of a message is limited like the websocket messages,
+    /// so a small frame from the socket cannot expand into gigabytes
+    pub const MAX_FRAME: u64 = 64 << 20;
+
+    /// version of the messages sent by the agent on auth, 0 for the agents before it
+    pub const VERSION: u32 = 3;
+    /// the first version speaking messagepack and zstd
+    const BINARY_VERSION: u32 = 2;
+    /// the first version pulling the upload files by chunks instead of receiving them inline
+    pub const ARTIFACT_VERSION: u32 = 3;
+
+    /// both sides speak the lower version
+    pub fn negotiate(agent_version: u32) -> u32 {
+        agent_version.min(Self::VERSION)
+    }
+
+    /// the encoding of the negotiated version, json for the old agents
+    pub fn encoding(version: u32, compress: bool) -> Encoding {
+        let binary = version >= Self::BINARY_VERSION;
+        Encoding {
+            binary,
+            compress: binary && compress,
+        }
+    }
 
     pub fn is_response(data: &Vec<u8>) -> bool {
-        data[0] == Self::RESP_MARK
+        data[0] & Self::RESP_MARK == Self::RESP_MARK
     }
 
-    pub fn pack_request(data: Msg) -> Vec<u8> {
-        let mut b = BytesMut::new();
-        b.put_u8(Self::REQ_MARK);
-        b.extend(serde_json::to_vec(&data).unwrap());
-        b.to_vec()
+    pub fn pack_request(data: Msg, encoding: Encoding) -> Vec<u8> {
+        Self::pack(Self::REQ_MARK, data, encoding)
     }
 
     pub fn unpack_request(data: Vec<u8>) -> Result<Msg> {
-        if data[0] != Self::REQ_MARK {
-            return Err(anyhow!("invalid request msg format"));
-        }
-        let val = &data[1..];
-        Ok(serde_json::from_slice::<Msg>(val)?)
+        Self::unpack(Self::REQ_MARK, data).map_err(|e| anyhow!("invalid request msg format, {e}"))
+    }
+
+    pub fn pack_response(data: Msg, encoding: Encoding) -> Vec<u8> {
+        Self::pack(Self::RESP_MARK, data, encoding)
+    }
+
+    pub fn unpack_response(data: Vec<u8>) -> Result<Msg> {
+        Self::unpack(Self::RESP_MARK, data).map_err(|e| anyhow!("invalid response msg format, {e}"))
     }
 
-    pub fn pack_response(data: Msg) -> Vec<u8> {
-        let mut b = BytesMut::new();
-        b.put_u8(Self::RESP_MARK);
-        let data = serde_json::to_vec(&data).unwrap();
+    fn pack(mark: u8, data: Msg, encoding: Encoding) -> Vec<u8> {
+        let (mut mark, mut data) = if encoding.binary {
+            (
+                mark | Self::MSGPACK_FLAG,
+                rmp_serde::to_vec_named(&data).unwrap(),
+            )
+        } else {
+            (mark, serde_json::to_vec(&data).unwrap())
+        };
+
+        if encoding.compress
+            && data.len() > Self::COMPRESS_THRESHOLD
+            && let Ok(v) = zstd::bulk::compress(&data, Self::ZSTD_LEVEL)
+        {
+            mark |= Self::ZSTD_FLAG;
+            data = v;
+        }
+
+        let mut b = BytesMut::with_capacity(data.len() + 1);
+        b.put_u8(mark);
         b.extend(data);
         b.to_vec()
     }
 
-    pub fn unpack_response(data: Vec<u8>) -> Result<Msg> {
-        if data[0] != Self::RESP_MARK {
-            return Err(anyhow!("invalid response msg format"));
+    /// the flags of the mark byte describe the message, so any encoding can be read
+    fn unpack(mark: u8, data: Vec<u8>) -> Result<Msg> {
+        let flags = *data.first().ok_or(anyhow!("empty msg"))?;
+        if flags & Self::RESP_MARK != mark {
+            return Err(anyhow!("unexpected msg type"));
+        }
+        if flags & !(Self::RESP_MARK | Self::MSGPACK_FLAG | Self::ZSTD_FLAG) != 0 {
+            return Err(anyhow!("unknown msg flags {flags:#b}"));
         }
-        let val = &data[1..];
-        Ok(serde_json::from_slice::<Msg>(val)?)
+
+        let val = if flags & Self::ZSTD_FLAG != 0 {
+            Self::decompress(&data[1..], Self::MAX_FRAME)?
+        } else {
+            data[1..].to_vec()
+        };
+
+        if flags & Self::MSGPACK_FLAG != 0 {
+            Ok(rmp_serde::from_slice::<Msg>(&val)?)
+        } else {
+            Ok(serde_json::from_slice::<Msg>(&val)?)
+        }
+    }
+
+    /// decode at most limit bytes, the frames expanding beyond it are rejected
+    fn decompress(data: &[u8], limit: u64) -> Result<Vec<u8>> {
+        let mut val = vec![];
+        zstd::stream::Decoder::new(data)?
+            .take(limit + 1)
+            .read_to_end(&mut val)?;
+        if val.len() as u64 > limit {
+            return Err(anyhow!("msg exceeds the limit of {limit} bytes"));
+        }
+        Ok(val)
     }
 }
 
@@ -56,7 +143,7 @@ fn pack_request() {
         )),
     };
 
-    let data = Protocol::pack_request(old.clone());
+    let data = Protocol::pack_request(old.clone(), Encoding::default());
 
     match Protocol::unpack_request(data) {
         Ok(new) => {
@@ -65,3 +152,52 @@ fn pack_request() {
         Err(_) => todo!(),
     }
 }
+
+#[test]
+fn pack_encodings() {
+    use crate::bridge::msg::MsgKind;
+    use serde_json::json;
+    let old = Msg {
+        id: 3,
+        data: MsgKind::Response(json!({"content": "x".repeat(4096)})),
+    };
+
+    // the legacy agents get the bare json
+    assert_eq!(
+        Protocol::encoding(Protocol::negotiate(0), true),
+        Encoding::default()
+    );
+    let data = Protocol::pack_response(old.clone(), Encoding::default());
+    assert_eq!(data[0], Protocol::RESP_MARK);
+    assert_eq!(&data[1..], serde_json::to_vec(&old).unwrap());
+
+    let encoding = Protocol::encoding(Protocol::negotiate(Protocol::VERSION), true);
+    let data = Protocol::pack_response(old.clone(), encoding);
+    assert!(Protocol::is_response(&data));
+    assert_ne!(data[0] & Protocol::ZSTD_FLAG, 0);
+    assert!(data.len() < 1024);
+    assert!(old == Protocol::unpack_response(data.clone()).unwrap());
+    assert!(Protocol::unpack_request(data).is_err());
+
+    let encoding = Protocol::encoding(Protocol::VERSION, false);
+    let data = Protocol::pack_request(old.clone(), encoding);
+    assert!(!Protocol::is_response(&data));
+    assert_eq!(data[0], Protocol::REQ_MARK | Protocol::MSGPACK_FLAG);
+    assert!(old == Protocol::unpack_request(data).unwrap());
+}
+
+#[test]
+fn unpack_oversized_frame() {
+    let data = vec![0u8; Protocol::MAX_FRAME as usize + 1];
+    let mut frame = vec![Protocol::RESP_MARK | Protocol::MSGPACK_FLAG | Protocol::ZSTD_FLAG];
+    frame.extend(zstd::bulk::compress(&data, Protocol::ZSTD_LEVEL).unwrap());
+    // a few kilobytes on the wire
+    assert!(frame.len() < 64 << 10);
+
+    let err = Protocol::unpack_response(frame).unwrap_err();
+    assert!(err.to_string().contains("exceeds the limit"));
+
+    let small = zstd::bulk::compress(&[1u8; 100], Protocol::ZSTD_LEVEL).unwrap();
+    assert_eq!(Protocol::decompress(&small, 100).unwrap(), vec![1u8; 100]);
+    assert!(Protocol::decompress(&small, 99).is_err());
+}
@@ -1,19 +1,21 @@
-use std::pin::Pin;
+use std::{pin::Pin, sync::Arc};
 
 use anyhow::Result;
+use async_trait::async_trait;
 use futures::Future;
-use local_ip_address::local_ip;
-use redis::{
-    AsyncCommands, Client, from_redis_value,
-    streams::{StreamMaxlen, StreamReadOptions, StreamReadReply},
-};
+use redis::Client;
 use redis_macros::{FromRedisValue, ToRedisArgs};
 use serde::{Deserialize, Serialize};
-
-use tracing::{error, info, warn};
+use tokio::sync::watch;
 
 use crate::bridge::msg::{AgentOfflineParams, AgentOnlineParams, HeartbeatParams, UpdateJobParams};
 
+mod nats;
+mod redis_stream;
+
+pub use nats::NatsBus;
+pub use redis_stream::RedisBus;
+
 #[derive(Debug, Serialize, Deserialize, FromRedisValue, ToRedisArgs)]
 pub enum Msg {
     UpdateJob(UpdateJobParams),
@@ -22,9 +24,27 @@ pub enum Msg {
     AgentOffline(AgentOfflineParams),
 }
 
+pub type MsgFuture = Pin<Box<dyn Future<Output = Result<()>> + Send>>;
+pub type MsgHandler<'a> = &'a mut (dyn FnMut(String, Msg) -> MsgFuture + Send + Sync);
+
+/// transport of the agent events from comet to console
+#[async_trait]
+pub trait BusBackend: Send + Sync {
+    async fn send(&self, msg: Msg) -> Result<String>;
+
+    /// consume the events as a member of the consumer group shared by the consoles,
+    /// returns once shutdown is set
+    async fn recv(
+        &self,
+        shutdown: Option<watch::Receiver<bool>>,
+        cb: MsgHandler<'_>,
+    ) -> Result<String>;
+}
+
 #[derive(Clone)]
 pub struct Bus {
-    pub redis_client: Client,
+    backend: Arc<dyn BusBackend>,
+    shutdown: Option<watch::Receiver<bool>>,
 }
 
 impl Bus {
@@ -32,108 +52,66 @@ impl Bus {
     pub const CONSUMER_GROUP: &'static str = "jiascheduler-group";
 
     pub fn new(redis_client: Client) -> Self {
-        Self { redis_client }
+        Self::with_backend(Arc::new(RedisBus::new(redis_client)))
+    }
+
+    pub fn with_backend(backend: Arc<dyn BusBackend>) -> Self {
+        Self {
+            backend,
+            shutdown: None,
+        }
+    }
+
+    /// nats:// urls use nats jetstream, redis:// urls use a redis stream,
+    /// the redis of the deployment is used when the url is not set
+    pub fn from_url(url: Option<&str>, redis_client: Client) -> Result<Self> {
+        match url {
+            None | Some("") => Ok(Self::new(redis_client)),
+            Some(v) if v.starts_with("nats://") || v.starts_with("tls://") => {
+                Ok(Self::with_backend(Arc::new(NatsBus::new(v))))
+            }
+            Some(v) if v.starts_with("redis://") || v.starts_with("rediss://") => {
+                Ok(Self::new(Client::open(v)?))
+            }
+            Some(v) => anyhow::bail!("unsupported bus url {v}"),
+        }
+    }
+
+    /// stop recv after the current batch is handled once shutdown is set
+    pub fn with_shutdown(mut self, shutdown: watch::Receiver<bool>) -> Self {
+        self.shutdown = Some(shutdown);
+        self
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.shutdown.as_ref().is_some_and(|v| *v.borrow())
     }
 
     pub async fn update_job(&self, msg: UpdateJobParams) -> Result<String> {
-        self.send_msg(&[("event", Msg::UpdateJob(msg))]).await
+        self.send_msg(Msg::UpdateJob(msg)).await
     }
 
     pub async fn heartbeat(&self, msg: HeartbeatParams) -> Result<String> {
-        self.send_msg(&[("event", Msg::Heartbeat(msg))]).await
+        self.send_msg(Msg::Heartbeat(msg)).await
     }
 
     pub async fn agent_online(&self, msg: AgentOnlineParams) -> Result<String> {
-        self.send_msg(&[("event", Msg::AgentOnline(msg))]).await
+        self.send_msg(Msg::AgentOnline(msg)).await
     }
 
     pub async fn agent_offline(&self, msg: AgentOfflineParams) -> Result<String> {
-        self.send_msg(&[("event", Msg::AgentOffline(msg))]).await
+        self.send_msg(Msg::AgentOffline(msg)).await
     }
 
-    pub async fn send_msg<'a>(&self, items: &'a [(&'a str, Msg)]) -> Result<String> {
-        let mut conn = self.redis_client.get_multiplexed_async_connection().await?;
-
-        let v: String = conn.xadd(Self::JOB_TOPIC, "*", items).await?;
-        Ok(v)
+    pub async fn send_msg(&self, msg: Msg) -> Result<String> {
+        self.backend.send(msg).await
     }
 
     pub async fn recv(
         &self,
-        mut cb: impl Sync
-        + Send
-        + FnMut(String, Msg) -> Pin<Box<dyn Future<Output = Result<()>> + Send>>,
+        mut cb: impl Sync + Send + FnMut(String, Msg) -> MsgFuture,
     ) -> Result<String> {
-        let mut conn = self.redis_client.get_multiplexed_async_connection().await?;
-
-        let ret: String = conn
-            .xgroup_create_mkstream(Self::JOB_TOPIC, Self::CONSUMER_GROUP, "$")
-            .await
-            .map_or_else(
-                |e| {
-                    if e.code() != Some("BUSYGROUP") {
-                        warn!("failed create stream group - {}", e);
-                    }
-
-                    "".to_string()
-                },
-                |v| v,
-            );
-
-        info!("create stream group {}", ret);
-
-        let opts = StreamReadOptions::default()
-            .group(Self::CONSUMER_GROUP, local_ip()?.to_string())
-            .block(100)
-            .count(100);
-
-        loop {
-            let ret: StreamReadReply = conn
-                .xread_options(&[Self::JOB_TOPIC], &[">"], &opts)
-                .await?;
-
-            if let Err(e) = conn
-                .xtrim::<_, u64>(Self::JOB_TOPIC, StreamMaxlen::Equals(5000))
-                .await
-            {
-                error!("failed to trim stream - {e}");
-            };
-
-            for stream_key in ret.keys {
-                let msg_key = stream_key.key;
-
-                for stream_id in stream_key.ids {
-                    for (k, v) in stream_id.map {
-                        let ret = match from_redis_value::<Msg>(&v) {
-                            Ok(msg) => cb(k, msg).await,
-                            Err(e) => {
-                                error!("failed to parse redis val - {e}");
-                                Ok(())
-                            }
-                        };
-
-                        if let Err(e) = ret {
-                            error!("failed to handle msg - {e}");
-                        }
-
-                        let _: i32 = conn
-                            .xack(
-                                msg_key.clone(),
-                                Self::CONSUMER_GROUP,
-                                &[stream_id.id.clone()],
-                            )
-                            .await
-                            .map_or_else(
-                                |v| {
-                                    error!("faile to exec xack - {}", v);
-                                    0
-                                },
-                                |v| v,
-                            );
-                    }
-                }
-            }
-        }
+        self.backend.recv(self.shutdown.clone(), &mut cb).await
     }
 }
 
@@ -142,23 +120,17 @@ async fn test_bus() {
     let redis_client =
         redis::Client::open("redis://:wang@127.0.0.1").expect("failed connect to redis");
     let bus = Bus::new(redis_client);
-    bus.send_msg(&[(
-        "event",
-        Msg::UpdateJob(UpdateJobParams {
-            exit_code: Some(1),
-            ..Default::default()
-        }),
-    )])
+    bus.send_msg(Msg::UpdateJob(UpdateJobParams {
+        exit_code: Some(1),
+        ..Default::default()
+    }))
     .await
     .unwrap();
 
-    bus.send_msg(&[(
-        "event",
-        Msg::UpdateJob(UpdateJobParams {
-            exit_code: Some(2),
-            ..Default::default()
-        }),
-    )])
+    bus.send_msg(Msg::UpdateJob(UpdateJobParams {
+        exit_code: Some(2),
+        ..Default::default()
+    }))
     .await
     .unwrap();
 
@@ -171,3 +143,11 @@ async fn test_bus() {
     .await
     .unwrap();
 }
+
+#[test]
+fn test_bus_from_url() {
+    let redis_client = redis::Client::open("redis://127.0.0.1").unwrap();
+    assert!(Bus::from_url(None, redis_client.clone()).is_ok());
+    assert!(Bus::from_url(Some("nats://127.0.0.1:4222"), redis_client.clone()).is_ok());
+    assert!(Bus::from_url(Some("kafka://127.0.0.1:9092"), redis_client).is_err());
+}
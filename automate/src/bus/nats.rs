@@ -0,0 +1,126 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow};
+use async_nats::jetstream::{
+    self,
+    consumer::{Consumer, pull},
+    stream,
+};
+use async_trait::async_trait;
+use futures::StreamExt;
+use tokio::{
+    sync::{OnceCell, watch},
+    time::timeout,
+};
+use tracing::error;
+
+use super::{Bus, BusBackend, Msg, MsgHandler};
+
+const STREAM_NAME: &str = "JIASCHEDULER_EVENT";
+const SUBJECT: &str = "jiascheduler.job.event";
+
+/// events are kept in a jetstream stream and consumed by a durable pull consumer,
+/// the connection is established on first use
+pub struct NatsBus {
+    url: String,
+    js: OnceCell<jetstream::Context>,
+}
+
+impl NatsBus {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            js: OnceCell::new(),
+        }
+    }
+
+    async fn jetstream(&self) -> Result<&jetstream::Context> {
+        self.js
+            .get_or_try_init(|| async {
+                let client = async_nats::connect(&self.url)
+                    .await
+                    .with_context(|| format!("failed connect to {}", self.url))?;
+                let js = jetstream::new(client);
+                js.get_or_create_stream(stream::Config {
+                    name: STREAM_NAME.to_string(),
+                    subjects: vec![SUBJECT.to_string()],
+                    max_messages: 5000,
+                    ..Default::default()
+                })
+                .await
+                .map_err(|e| anyhow!("failed create stream - {e}"))?;
+                Ok(js)
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl BusBackend for NatsBus {
+    async fn send(&self, msg: Msg) -> Result<String> {
+        let ack = self
+            .jetstream()
+            .await?
+            .publish(SUBJECT, serde_json::to_vec(&msg)?.into())
+            .await
+            .map_err(|e| anyhow!("failed publish event - {e}"))?
+            .await
+            .map_err(|e| anyhow!("failed publish event - {e}"))?;
+        Ok(ack.sequence.to_string())
+    }
+
+    async fn recv(
+        &self,
+        shutdown: Option<watch::Receiver<bool>>,
+        cb: MsgHandler<'_>,
+    ) -> Result<String> {
+        let consumer: Consumer<pull::Config> = self
+            .jetstream()
+            .await?
+            .get_stream(STREAM_NAME)
+            .await
+            .map_err(|e| anyhow!("failed get stream - {e}"))?
+            .get_or_create_consumer(
+                Bus::CONSUMER_GROUP,
+                pull::Config {
+                    durable_name: Some(Bus::CONSUMER_GROUP.to_string()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .map_err(|e| anyhow!("failed create consumer - {e}"))?;
+
+        let mut messages = consumer
+            .messages()
+            .await
+            .map_err(|e| anyhow!("failed consume stream - {e}"))?;
+
+        loop {
+            if shutdown.as_ref().is_some_and(|v| *v.borrow()) {
+                return Ok("shutdown".to_string());
+            }
+
+            let Ok(next) = timeout(Duration::from_millis(100), messages.next()).await else {
+                continue;
+            };
+            let message = next
+                .context("consumer closed")?
+                .map_err(|e| anyhow!("failed recv event - {e}"))?;
+
+            let ret = match serde_json::from_slice::<Msg>(&message.payload) {
+                Ok(msg) => cb(message.subject.to_string(), msg).await,
+                Err(e) => {
+                    error!("failed to parse nats payload - {e}");
+                    Ok(())
+                }
+            };
+            if let Err(e) = ret {
+                error!("failed to handle msg - {e}");
+            }
+
+            if let Err(e) = message.ack().await {
+                error!("failed to ack msg - {e}");
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use local_ip_address::local_ip;
+use redis::{
+    AsyncCommands, Client, from_redis_value,
+    streams::{StreamMaxlen, StreamReadOptions, StreamReadReply},
+};
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+
+use super::{Bus, BusBackend, Msg, MsgHandler};
+
+/// events are kept in a redis stream and consumed by a consumer group
+pub struct RedisBus {
+    redis_client: Client,
+}
+
+impl RedisBus {
+    pub fn new(redis_client: Client) -> Self {
+        Self { redis_client }
+    }
+}
+
+#[async_trait]
+impl BusBackend for RedisBus {
+    async fn send(&self, msg: Msg) -> Result<String> {
+        let mut conn = self.redis_client.get_multiplexed_async_connection().await?;
+
+        let v: String = conn.xadd(Bus::JOB_TOPIC, "*", &[("event", msg)]).await?;
+        Ok(v)
+    }
+
+    async fn recv(
+        &self,
+        shutdown: Option<watch::Receiver<bool>>,
+        cb: MsgHandler<'_>,
+    ) -> Result<String> {
+        let mut conn = self.redis_client.get_multiplexed_async_connection().await?;
+
+        let ret: String = conn
+            .xgroup_create_mkstream(Bus::JOB_TOPIC, Bus::CONSUMER_GROUP, "$")
+            .await
+            .unwrap_or_else(|e| {
+                if e.code() != Some("BUSYGROUP") {
+                    warn!("failed create stream group - {}", e);
+                }
+
+                "".to_string()
+            });
+
+        info!("create stream group {}", ret);
+
+        let opts = StreamReadOptions::default()
+            .group(Bus::CONSUMER_GROUP, local_ip()?.to_string())
+            .block(100)
+            .count(100);
+
+        loop {
+            if shutdown.as_ref().is_some_and(|v| *v.borrow()) {
+                return Ok("shutdown".to_string());
+            }
+
+            let ret: StreamReadReply = conn.xread_options(&[Bus::JOB_TOPIC], &[">"], &opts).await?;
+
+            if let Err(e) = conn
+                .xtrim::<_, u64>(Bus::JOB_TOPIC, StreamMaxlen::Equals(5000))
+                .await
+            {
+                error!("failed to trim stream - {e}");
+            };
+
+            for stream_key in ret.keys {
+                let msg_key = stream_key.key;
+
+                for stream_id in stream_key.ids {
+                    for (k, v) in stream_id.map {
+                        let ret = match from_redis_value::<Msg>(&v) {
+                            Ok(msg) => cb(k, msg).await,
+                            Err(e) => {
+                                error!("failed to parse redis val - {e}");
+                                Ok(())
+                            }
+                        };
+
+                        if let Err(e) = ret {
+                            error!("failed to handle msg - {e}");
+                        }
+
+                        let _: i32 = conn
+                            .xack(
+                                msg_key.clone(),
+                                Bus::CONSUMER_GROUP,
+                                std::slice::from_ref(&stream_id.id),
+                            )
+                            .await
+                            .unwrap_or_else(|v| {
+                                error!("faile to exec xack - {}", v);
+                                0
+                            });
+                    }
+                }
+            }
+        }
+    }
+}
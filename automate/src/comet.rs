@@ -1,32 +1,56 @@
-use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use anyhow::{Context, Ok};
 use futures::SinkExt;
 
-use handler::{middleware::bearer_auth, SecretHeader};
+use handler::{
+    middleware::{agent_auth, bearer_auth},
+    SecretHeader,
+};
 use poem::{
-    get, listener::TcpListener, post, web::websocket::WebSocketStream, EndpointExt, Route, Server,
+    get,
+    listener::{Listener, TcpListener},
+    post,
+    web::websocket::WebSocketStream,
+    EndpointExt, Route, Server,
 };
 use serde_json::{json, Value};
-use tokio::sync::{mpsc::Sender, oneshot::Sender as OneSender, Mutex};
+use tokio::{
+    sync::{oneshot::Sender as OneSender, watch, Mutex},
+    time::{sleep, Instant},
+};
 use tracing::{debug, error, info};
 use types::SshLoginParams;
 
 use crate::{
+    artifact::ArtifactStore,
     bridge::{
+        lane::LaneSender,
         msg::{
-            AgentOfflineParams, AgentOnlineParams, HeartbeatParams, Msg, MsgReqKind, MsgState,
-            UpdateJobParams,
+            AgentOfflineParams, AgentOnlineParams, DispatchAckParams, HeartbeatParams, MsgReqKind,
+            RunLockParams, UpdateJobParams,
         },
+        protocol::Protocol,
         Bridge,
     },
-    get_endpoint,
+    bus::Bus,
+    get_endpoint, shutdown_signal,
+    tls::TlsOptions,
 };
 
 use anyhow::Result;
 
-use self::logic::Logic;
+use self::{connection::Connections, logic::Logic};
 
+pub mod connection;
 pub mod handler;
 pub mod logic;
 mod macros;
@@ -39,17 +63,193 @@ pub struct Comet {
     secret: String,
     port: u16,
     pub ssh_ws_streams: Arc<Mutex<HashMap<String, WebSocketStream>>>,
+    inflight: Arc<AtomicUsize>,
+    draining: Arc<AtomicBool>,
+    closed: Arc<watch::Sender<bool>>,
+    group: Option<String>,
+    /// comet_secret is refused from the agents, they must use a credential
+    require_agent_credential: bool,
+    /// comet group assignments of the namespaces, refreshed from redis
+    assignments: Arc<watch::Sender<HashMap<String, String>>>,
+    pub connections: Connections,
+    /// the upload files pushed by the console, pulled by the agents in chunks
+    pub artifacts: ArtifactStore,
+}
+
+/// count the request waiting for the agent response until dropped
+struct InflightGuard(Arc<AtomicUsize>);
+
+impl Drop for InflightGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 impl Comet {
-    pub fn new(redis_client: redis::Client, port: u16, secret: String) -> Self {
+    pub fn new(redis_client: redis::Client, port: u16, secret: String, tls: bool) -> Self {
         Self {
             bridge: Bridge::new(),
-            logic: Logic::new(redis_client),
+            logic: Logic::new(redis_client).with_tls(tls),
             ssh_ws_streams: Arc::new(Mutex::new(HashMap::new())),
             port,
             secret,
+            inflight: Arc::new(AtomicUsize::new(0)),
+            draining: Arc::new(AtomicBool::new(false)),
+            closed: Arc::new(watch::channel(false).0),
+            group: None,
+            require_agent_credential: false,
+            assignments: Arc::new(watch::channel(HashMap::new()).0),
+            connections: Connections::default(),
+            artifacts: ArtifactStore::new(ARTIFACT_DIR),
+        }
+    }
+
+    pub fn with_bus(mut self, bus: Bus) -> Self {
+        self.logic = self.logic.with_bus(bus);
+        self
+    }
+
+    /// only accept the agents of the namespaces assigned to the group
+    pub fn with_group(mut self, group: Option<String>) -> Self {
+        self.logic = self.logic.with_group(group.clone());
+        self.group = group;
+        self
+    }
+
+    /// refuse comet_secret from the agents once all of them use a credential,
+    /// so that a leaked comet_secret cannot be used to connect as an agent
+    pub fn with_require_agent_credential(mut self, require: bool) -> Self {
+        self.require_agent_credential = require;
+        self
+    }
+
+    fn is_assigned(&self, assignments: &HashMap<String, String>, namespace: &str) -> bool {
+        match (&self.group, assignments.get(namespace)) {
+            (Some(group), Some(assigned)) => group == assigned,
+            _ => true,
+        }
+    }
+
+    /// false if the namespace is assigned to another comet group
+    pub fn accepts_namespace(&self, namespace: &str) -> bool {
+        self.is_assigned(&self.assignments.borrow(), namespace)
+    }
+
+    /// resolves when the namespace is moved to another comet group
+    pub async fn reassigned(&self, namespace: &str) {
+        let mut rx = self.assignments.subscribe();
+        if rx
+            .wait_for(|v| !self.is_assigned(v, namespace))
+            .await
+            .is_err()
+        {
+            std::future::pending::<()>().await;
+        }
+    }
+
+    /// reload the group assignments periodically, the last ones are kept if redis is unavailable
+    pub async fn refresh_assignments(&self) {
+        if self.group.is_none() {
+            return;
+        }
+        loop {
+            match self.logic.get_comet_group_assignments().await {
+                std::result::Result::Ok(v) => {
+                    self.assignments.send_if_modified(|old| {
+                        let changed = *old != v;
+                        *old = v;
+                        changed
+                    });
+                }
+                Err(e) => error!("failed load comet group assignments - {e}"),
+            }
+            sleep(ASSIGNMENT_REFRESH_INTERVAL).await;
+        }
+    }
+
+    /// register the comet periodically so that the console can find its connections
+    pub async fn register(&self) {
+        loop {
+            if let Err(e) = self.logic.register_comet(self.port).await {
+                error!("failed register comet - {e}");
+            }
+            sleep(REGISTRY_INTERVAL).await;
+        }
+    }
+
+    pub async fn list_connections(&self) -> Vec<types::AgentConnectionInfo> {
+        self.connections
+            .list(&self.logic.comet_addr(self.port))
+            .await
+    }
+
+    /// close the connection of a misbehaving agent, the agent reconnects by itself
+    pub async fn disconnect_agent(&self, req: types::DisconnectAgentRequest) -> Result<()> {
+        let key = get_endpoint(&req.agent_ip, &req.mac_addr);
+        if !self.connections.disconnect(&key).await {
+            anyhow::bail!("agent {key} is not connected to this comet");
+        }
+        info!("disconnect agent {key} by request");
+        Ok(())
+    }
+
+    /// the agents before the artifact protocol only accept the upload file inline
+    async fn inline_artifact(&self, req: &mut types::DispatchJobRequest) -> Result<()> {
+        let Some(file) = req.dispatch_params.base_job.upload_file.as_mut() else {
+            return Ok(());
+        };
+        let Some(sha256) = file.sha256.clone().filter(|_| file.data.is_none()) else {
+            return Ok(());
+        };
+        let key = get_endpoint(&req.agent_ip, &req.mac_addr);
+        match self.connections.get(&key).await {
+            Some(conn) if conn.protocol_version() < Protocol::ARTIFACT_VERSION => {
+                file.data = Some(self.artifacts.read(&sha256).await?);
+            }
+            _ => {}
         }
+        Ok(())
+    }
+
+    /// send the request to the agent and count it in the connection
+    async fn send_msg(&self, agent_key: &str, data: MsgReqKind) -> Result<Value> {
+        if let Some(conn) = self.connections.get(agent_key).await {
+            conn.sent();
+        }
+        self.bridge.send_msg(agent_key, data).await
+    }
+
+    fn track_inflight(&self) -> Result<InflightGuard> {
+        if self.draining.load(Ordering::SeqCst) {
+            anyhow::bail!("comet is shutting down, please retry later");
+        }
+        self.inflight.fetch_add(1, Ordering::SeqCst);
+        Ok(InflightGuard(self.inflight.clone()))
+    }
+
+    /// resolves when the agent connections should be closed
+    pub fn closed(&self) -> watch::Receiver<bool> {
+        self.closed.subscribe()
+    }
+
+    /// refuse new dispatches, wait for the in-flight ones up to the timeout,
+    /// then close the agent connections so that they reconnect to another comet
+    pub async fn drain(&self, wait: Duration) {
+        self.draining.store(true, Ordering::SeqCst);
+        let deadline = Instant::now() + wait;
+        loop {
+            let n = self.inflight.load(Ordering::SeqCst);
+            if n == 0 {
+                break;
+            }
+            if Instant::now() >= deadline {
+                error!("drain timeout, {n} in-flight requests are dropped");
+                break;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+        info!("closing agent connections");
+        self.closed.send_replace(true);
     }
 
     pub async fn register_ssh_stream(&mut self, key: String, ws: WebSocketStream) {
@@ -84,7 +284,7 @@ impl Comet {
         is_initialized: bool,
         namespace: String,
         ip: String,
-        client: Sender<(Msg, Option<Sender<MsgState>>)>,
+        client: LaneSender,
     ) {
         let mac_address = secret_header.mac_addr.clone();
         let key = get_endpoint(ip.clone(), mac_address.clone());
@@ -125,38 +325,123 @@ impl Comet {
         }
     }
 
-    pub async fn dispatch(&self, req: types::DispatchJobRequest) -> Result<Value> {
+    /// if the agent response is lost, the dispatch is still successful when the agent has acked it
+    pub async fn dispatch(&self, mut req: types::DispatchJobRequest) -> Result<Value> {
+        let _guard = self.track_inflight()?;
+        self.inline_artifact(&mut req).await?;
+        let dispatch_id = req.dispatch_params.dispatch_id.clone();
         let val = self.logic.dispath(req).await?;
-        let ret = self.bridge.send_msg(&val.0, val.1).await?;
+        let err = match self.send_msg(&val.0, val.1).await {
+            Err(e) if !dispatch_id.is_empty() => e,
+            ret => return ret,
+        };
+        match self.logic.get_dispatch_ack(&val.0, &dispatch_id).await? {
+            Some(ack) if ack.is_ack() => {
+                info!("response of dispatch {dispatch_id} is lost, but it has been acked - {err}");
+                Ok(json!({"code": 20000, "msg": "success", "data": {"ack": ack}}))
+            }
+            _ => Err(err),
+        }
+    }
+
+    pub async fn dispatch_ack(&self, agent_key: &str, ack: DispatchAckParams) -> Result<Value> {
+        self.logic.save_dispatch_ack(agent_key, ack).await?;
+        Ok(json!(null))
+    }
+
+    /// acks reported by the agent, falls back to the ones saved in redis if the agent is unreachable
+    pub async fn reconcile_dispatch(
+        &self,
+        req: types::ReconcileDispatchRequest,
+    ) -> Result<Vec<DispatchAckParams>> {
+        let params = req.params.clone();
+        let val = self.logic.reconcile_dispatch(req).await?;
+        let err = match self.send_msg(&val.0, val.1).await {
+            Err(e) => e,
+            ret => {
+                let v = ret?;
+                if v["code"] != 20000 {
+                    anyhow::bail!("failed to reconcile dispatch, {}", v["msg"]);
+                }
+                return Ok(serde_json::from_value(v["data"].clone())?);
+            }
+        };
+        let Some(dispatch_id) = params.dispatch_id.as_deref() else {
+            return Err(err);
+        };
+        match self.logic.get_dispatch_ack(&val.0, dispatch_id).await? {
+            Some(ack) => Ok(vec![ack]),
+            None => Err(err),
+        }
+    }
+
+    pub async fn prune_schedule(&self, req: types::PruneScheduleRequest) -> Result<Value> {
+        let _guard = self.track_inflight()?;
+        let val = self.logic.prune_schedule(req).await?;
+        let ret = self.send_msg(&val.0, val.1).await?;
         Ok(ret)
     }
 
+    /// auth middleware of agent connections, accepting comet_secret or an agent credential
+    pub fn agent_auth(&self) -> handler::middleware::AgentAuth {
+        agent_auth(
+            &self.secret,
+            self.logic.clone(),
+            self.require_agent_credential,
+        )
+    }
+
+    pub async fn exchange_bootstrap_token(
+        &self,
+        req: types::ExchangeTokenRequest,
+    ) -> Result<String> {
+        self.logic.exchange_bootstrap_token(req).await
+    }
+
+    pub async fn is_dispatch_allowed(&self, ip: IpAddr) -> Result<bool> {
+        let setting = self.logic.get_security_setting().await?;
+        Ok(setting.is_dispatch_allowed(ip))
+    }
+
     pub async fn runtime_action(&self, req: types::RuntimeActionRequest) -> Result<Value> {
+        let _guard = self.track_inflight()?;
         let val = self.logic.runtime_action(req).await?;
-        let ret = self.bridge.send_msg(&val.0, val.1).await?;
+        let ret = self.send_msg(&val.0, val.1).await?;
+        Ok(ret)
+    }
+
+    pub async fn agent_log(&self, req: types::AgentLogRequest) -> Result<Value> {
+        let val = self.logic.agent_log(req).await?;
+        let ret = self.send_msg(&val.0, val.1).await?;
+        Ok(ret)
+    }
+
+    pub async fn agent_config(&self, req: types::AgentConfigRequest) -> Result<Value> {
+        let val = self.logic.agent_config(req).await?;
+        let ret = self.send_msg(&val.0, val.1).await?;
         Ok(ret)
     }
 
     pub async fn sftp_read_dir(&self, req: types::SftpReadDirRequest) -> Result<Value> {
         let val = self.logic.sfpt_read_dir(req).await?;
-        let ret = self.bridge.send_msg(&val.0, val.1).await?;
+        let ret = self.send_msg(&val.0, val.1).await?;
         Ok(ret)
     }
 
     pub async fn sftp_upload(&self, req: types::SftpUploadRequest) -> Result<Value> {
         let val = self.logic.sftp_upload(req).await?;
-        let ret = self.bridge.send_msg(&val.0, val.1).await?;
+        let ret = self.send_msg(&val.0, val.1).await?;
         Ok(ret)
     }
     pub async fn sftp_download(&self, req: types::SftpDownloadRequest) -> Result<Value> {
         let val = self.logic.sftp_download(req).await?;
-        let ret = self.bridge.send_msg(&val.0, val.1).await?;
+        let ret = self.send_msg(&val.0, val.1).await?;
         Ok(ret)
     }
 
     pub async fn sftp_remove(&self, req: types::SftpRemoveRequest) -> Result<Value> {
         let val = self.logic.sftp_remove(req).await?;
-        let ret = self.bridge.send_msg(&val.0, val.1).await?;
+        let ret = self.send_msg(&val.0, val.1).await?;
         Ok(ret)
     }
 
@@ -170,11 +455,24 @@ impl Comet {
         Ok(ret)
     }
 
-    pub async fn handle(&self, msg: MsgReqKind) -> Value {
+    pub async fn acquire_run_lock(&self, agent_key: &str, req: RunLockParams) -> Result<Value> {
+        let acquired = self.logic.acquire_run_lock(agent_key, req).await?;
+        Ok(json!({ "acquired": acquired }))
+    }
+
+    pub async fn handle(&self, msg: MsgReqKind, agent_key: String) -> Value {
+        if let Some(conn) = self.connections.get(&agent_key).await {
+            conn.received();
+            if let MsgReqKind::HeartbeatRequest(_) = msg {
+                conn.heartbeat();
+            }
+        }
         match msg {
             MsgReqKind::PullJobRequest(v) => self.pull_job(v).await,
             MsgReqKind::HeartbeatRequest(v) => self.heartbeat(v).await,
             MsgReqKind::UpdateJobRequest(v) => self.update_job(v).await,
+            MsgReqKind::DispatchAckRequest(v) => self.dispatch_ack(&agent_key, v).await,
+            MsgReqKind::RunLockRequest(v) => self.acquire_run_lock(&agent_key, v).await,
             _ => todo!(),
         }
         .map_or_else(
@@ -189,10 +487,31 @@ impl Comet {
     }
 }
 
+/// in-flight dispatches are waited up to this duration on shutdown
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// a rebalanced namespace moves to the new comet group within this interval
+const ASSIGNMENT_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// the comet is registered in redis at this interval
+const REGISTRY_INTERVAL: Duration = Duration::from_secs(30);
+
+const ARTIFACT_DIR: &str = "/tmp/jiascheduler-comet/artifacts";
+
 pub struct CometOptions {
     pub redis_url: String,
     pub bind_addr: String,
     pub secret: String,
+    /// serve https and wss, agents and console must present a certificate issued by the ca
+    pub tls: Option<TlsOptions>,
+    /// url of the bus which agent events are sent to, eg: nats://127.0.0.1:4222, defaults to redis_url
+    pub bus_url: Option<String>,
+    /// comet group of the console config, the agents of the namespaces assigned
+    /// to other groups are refused
+    pub group: Option<String>,
+    /// refuse comet_secret from the agents, they must connect with a credential
+    /// exchanged by a bootstrap token
+    pub require_agent_credential: bool,
 }
 
 pub async fn run(opts: CometOptions, signal: Option<OneSender<()>>) -> Result<()> {
@@ -202,7 +521,15 @@ pub async fn run(opts: CometOptions, signal: Option<OneSender<()>>) -> Result<()
         .parse::<SocketAddr>()
         .context("failed parse bind address")?
         .port();
-    let comet = Comet::new(redis_client, port, opts.secret.clone());
+    let bus = Bus::from_url(opts.bus_url.as_deref(), redis_client.clone())?;
+    let comet = Comet::new(redis_client, port, opts.secret.clone(), opts.tls.is_some())
+        .with_bus(bus)
+        .with_group(opts.group)
+        .with_require_agent_credential(opts.require_agent_credential);
+    let refresher = comet.clone();
+    tokio::spawn(async move { refresher.refresh_assignments().await });
+    let registry = comet.clone();
+    tokio::spawn(async move { registry.register().await });
     let app = Route::new()
         .at(
             "/dispatch",
@@ -212,6 +539,22 @@ pub async fn run(opts: CometOptions, signal: Option<OneSender<()>>) -> Result<()
                     .data(comet.clone()),
             ),
         )
+        .at(
+            "/dispatch/reconcile",
+            post(
+                handler::reconcile_dispatch
+                    .with(bearer_auth(&opts.secret))
+                    .data(comet.clone()),
+            ),
+        )
+        .at(
+            "/schedule/prune",
+            post(
+                handler::prune_schedule
+                    .with(bearer_auth(&opts.secret))
+                    .data(comet.clone()),
+            ),
+        )
         .at(
             "runtime/action",
             post(
@@ -220,6 +563,53 @@ pub async fn run(opts: CometOptions, signal: Option<OneSender<()>>) -> Result<()
                     .data(comet.clone()),
             ),
         )
+        .at(
+            "/agent/connections",
+            get(handler::list_connections
+                .with(bearer_auth(&opts.secret))
+                .data(comet.clone())),
+        )
+        .at(
+            "/agent/disconnect",
+            post(
+                handler::disconnect_agent
+                    .with(bearer_auth(&opts.secret))
+                    .data(comet.clone()),
+            ),
+        )
+        .at(
+            "/agent/log",
+            post(
+                handler::agent_log
+                    .with(bearer_auth(&opts.secret))
+                    .data(comet.clone()),
+            ),
+        )
+        .at(
+            "/agent/config",
+            post(
+                handler::agent_config
+                    .with(bearer_auth(&opts.secret))
+                    .data(comet.clone()),
+            ),
+        )
+        .at(
+            "/artifact/:sha256",
+            get(handler::stat_artifact
+                .with(bearer_auth(&opts.secret))
+                .data(comet.clone()))
+            .post(
+                handler::save_artifact
+                    .with(bearer_auth(&opts.secret))
+                    .data(comet.clone()),
+            ),
+        )
+        .at(
+            "/artifact/:sha256/chunk",
+            get(handler::get_artifact_chunk
+                .with(comet.agent_auth())
+                .data(comet.clone())),
+        )
         .at(
             "/file/get/:filename",
             get(handler::get_file
@@ -228,16 +618,18 @@ pub async fn run(opts: CometOptions, signal: Option<OneSender<()>>) -> Result<()
         )
         .at(
             "/evt/:namespace",
-            get(handler::ws
-                .with(bearer_auth(&opts.secret))
-                .data(comet.clone())),
+            get(handler::ws.with(comet.agent_auth()).data(comet.clone())),
         )
         .at(
             "/ssh/register/:ip",
             handler::ssh_register
-                .with(bearer_auth(&opts.secret))
+                .with(comet.agent_auth())
                 .data(comet.clone()),
         )
+        .at(
+            "/agent/exchange-token",
+            post(handler::exchange_token.data(comet.clone())),
+        )
         .at(
             "/ssh/tunnel",
             handler::proxy_ssh
@@ -271,7 +663,46 @@ pub async fn run(opts: CometOptions, signal: Option<OneSender<()>>) -> Result<()
     if let Some(tx) = signal {
         tx.send(()).expect("failed send signal");
     }
-    Ok(Server::new(TcpListener::bind(opts.bind_addr))
-        .run(app)
-        .await?)
+    let signal = async move {
+        shutdown_signal().await;
+        info!("comet is shutting down");
+        tokio::spawn(async move { comet.drain(SHUTDOWN_TIMEOUT).await });
+    };
+    // leave a few seconds for the agent connections to be closed after draining
+    let timeout = Some(SHUTDOWN_TIMEOUT + Duration::from_secs(5));
+
+    let listener = TcpListener::bind(opts.bind_addr);
+    match opts.tls {
+        Some(tls) => Ok(Server::new(listener.rustls(tls.server_config()?))
+            .run_with_graceful_shutdown(app, signal, timeout)
+            .await?),
+        None => Ok(Server::new(listener)
+            .run_with_graceful_shutdown(app, signal, timeout)
+            .await?),
+    }
+}
+
+#[tokio::test]
+async fn test_drain() {
+    let comet = Comet::new(
+        redis::Client::open("redis://127.0.0.1").unwrap(),
+        3000,
+        "secret".to_string(),
+        false,
+    );
+    let closed = comet.closed();
+    let guard = comet.track_inflight().unwrap();
+
+    let drain = tokio::spawn({
+        let comet = comet.clone();
+        async move { comet.drain(Duration::from_secs(10)).await }
+    });
+    sleep(Duration::from_millis(300)).await;
+    // the new requests are refused, the connections wait for the in-flight one
+    assert!(comet.track_inflight().is_err());
+    assert!(!*closed.borrow());
+
+    drop(guard);
+    drain.await.unwrap();
+    assert!(*closed.borrow());
 }
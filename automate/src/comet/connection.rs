@@ -0,0 +1,177 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use chrono::Utc;
+use tokio::sync::{watch, RwLock};
+
+use super::types::AgentConnectionInfo;
+
+/// a websocket connection of an agent, the counters are updated by the comet
+pub struct AgentConnection {
+    agent_key: String,
+    namespace: String,
+    agent_ip: String,
+    mac_addr: String,
+    protocol_version: u32,
+    connected_at: i64,
+    last_heartbeat: AtomicI64,
+    msgs_received: AtomicU64,
+    msgs_sent: AtomicU64,
+    disconnect: watch::Sender<bool>,
+}
+
+impl AgentConnection {
+    pub fn protocol_version(&self) -> u32 {
+        self.protocol_version
+    }
+
+    pub fn received(&self) {
+        self.msgs_received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn sent(&self) {
+        self.msgs_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn heartbeat(&self) {
+        self.last_heartbeat
+            .store(Utc::now().timestamp(), Ordering::Relaxed);
+    }
+
+    /// resolves when the connection is closed by the console
+    pub async fn disconnected(&self) {
+        let mut rx = self.disconnect.subscribe();
+        if rx.wait_for(|v| *v).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+
+    pub fn info(&self, comet_addr: &str) -> AgentConnectionInfo {
+        let last_heartbeat = self.last_heartbeat.load(Ordering::Relaxed);
+        AgentConnectionInfo {
+            agent_key: self.agent_key.clone(),
+            namespace: self.namespace.clone(),
+            agent_ip: self.agent_ip.clone(),
+            mac_addr: self.mac_addr.clone(),
+            protocol_version: self.protocol_version,
+            comet_addr: comet_addr.to_string(),
+            connected_at: self.connected_at,
+            last_heartbeat: (last_heartbeat > 0).then_some(last_heartbeat),
+            msgs_received: self.msgs_received.load(Ordering::Relaxed),
+            msgs_sent: self.msgs_sent.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// the agents connected to this comet, keyed by the agent key
+#[derive(Clone, Default)]
+pub struct Connections(Arc<RwLock<HashMap<String, Arc<AgentConnection>>>>);
+
+impl Connections {
+    pub async fn register(
+        &self,
+        agent_key: String,
+        namespace: String,
+        agent_ip: String,
+        mac_addr: String,
+        protocol_version: u32,
+    ) -> Arc<AgentConnection> {
+        let conn = Arc::new(AgentConnection {
+            agent_key: agent_key.clone(),
+            namespace,
+            agent_ip,
+            mac_addr,
+            protocol_version,
+            connected_at: Utc::now().timestamp(),
+            last_heartbeat: AtomicI64::new(0),
+            msgs_received: AtomicU64::new(0),
+            msgs_sent: AtomicU64::new(0),
+            disconnect: watch::channel(false).0,
+        });
+        self.0.write().await.insert(agent_key, conn.clone());
+        conn
+    }
+
+    /// the agent may have reconnected before the old connection is closed,
+    /// only the connection itself is removed
+    pub async fn unregister(&self, conn: &Arc<AgentConnection>) {
+        let mut conns = self.0.write().await;
+        if conns
+            .get(&conn.agent_key)
+            .is_some_and(|v| Arc::ptr_eq(v, conn))
+        {
+            conns.remove(&conn.agent_key);
+        }
+    }
+
+    pub async fn get(&self, agent_key: &str) -> Option<Arc<AgentConnection>> {
+        self.0.read().await.get(agent_key).cloned()
+    }
+
+    pub async fn list(&self, comet_addr: &str) -> Vec<AgentConnectionInfo> {
+        let mut list = self
+            .0
+            .read()
+            .await
+            .values()
+            .map(|v| v.info(comet_addr))
+            .collect::<Vec<_>>();
+        list.sort_by(|a, b| a.agent_key.cmp(&b.agent_key));
+        list
+    }
+
+    /// close the connection of the agent, false if the agent is not connected
+    pub async fn disconnect(&self, agent_key: &str) -> bool {
+        match self.get(agent_key).await {
+            Some(conn) => {
+                conn.disconnect.send_replace(true);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_connections() {
+    let conns = Connections::default();
+    let old = conns
+        .register(
+            "a".into(),
+            "default".into(),
+            "10.0.0.1".into(),
+            "m".into(),
+            1,
+        )
+        .await;
+    old.received();
+    old.sent();
+    old.heartbeat();
+    let info = conns.list("127.0.0.1:3000").await;
+    assert_eq!(info.len(), 1);
+    assert_eq!((info[0].msgs_received, info[0].msgs_sent), (1, 1));
+    assert!(info[0].last_heartbeat.is_some());
+
+    // the old connection closed after reconnecting keeps the new one
+    let new = conns
+        .register(
+            "a".into(),
+            "default".into(),
+            "10.0.0.1".into(),
+            "m".into(),
+            1,
+        )
+        .await;
+    conns.unregister(&old).await;
+    assert!(conns.get("a").await.is_some());
+
+    assert!(conns.disconnect("a").await);
+    new.disconnected().await;
+    conns.unregister(&new).await;
+    assert!(!conns.disconnect("a").await);
+}
@@ -16,7 +16,8 @@ use poem::{
         Data,
         Json,
         Path,
-        Query, // RemoteAddr,
+        Query,
+        RemoteAddr,
     },
     FromRequest, IntoResponse, Request, RequestBody, Response, Result as PoemResult,
 };
@@ -27,15 +28,15 @@ use tokio::{
     io::AsyncWriteExt,
     sync::RwLock,
 };
-use tracing::error;
+use tracing::{error, info};
 
 use crate::{
-    bridge::client::WsClient,
+    bridge::{client::WsClient, protocol::Protocol},
     comet::{
         types::{self, SshLoginParams},
         Comet,
     },
-    return_response,
+    get_endpoint, return_response,
     scheduler::types::{SshConnectionOption, UploadFile},
 };
 
@@ -45,6 +46,9 @@ pub mod middleware {
         web::headers::{self, authorization::Bearer, HeaderMapExt},
         Endpoint, Error, Middleware, Request, Result,
     };
+    use tracing::error;
+
+    use crate::comet::{logic::Logic, types::AgentCredential};
 
     pub fn bearer_auth(secret: &str) -> BearerAuth {
         BearerAuth {
@@ -83,6 +87,91 @@ pub mod middleware {
             Err(Error::from_status(StatusCode::UNAUTHORIZED))
         }
     }
+
+    /// the identity of the connected agent, credential is none when comet_secret is used
+    #[derive(Clone)]
+    pub struct AgentIdentity {
+        pub token: String,
+        pub credential: Option<AgentCredential>,
+    }
+
+    /// accept a credential exchanged by bootstrap token, or comet_secret unless
+    /// require_credential is set
+    pub fn agent_auth(secret: &str, logic: Logic, require_credential: bool) -> AgentAuth {
+        AgentAuth {
+            secret: secret.to_string(),
+            logic,
+            require_credential,
+        }
+    }
+
+    pub struct AgentAuth {
+        secret: String,
+        logic: Logic,
+        require_credential: bool,
+    }
+
+    impl<E: Endpoint> Middleware<E> for AgentAuth {
+        type Output = AgentAuthEndpoint<E>;
+
+        fn transform(&self, ep: E) -> Self::Output {
+            AgentAuthEndpoint {
+                ep,
+                secret: self.secret.clone(),
+                logic: self.logic.clone(),
+                require_credential: self.require_credential,
+            }
+        }
+    }
+
+    pub struct AgentAuthEndpoint<E> {
+        ep: E,
+        secret: String,
+        logic: Logic,
+        require_credential: bool,
+    }
+
+    impl<E: Endpoint> Endpoint for AgentAuthEndpoint<E> {
+        type Output = E::Output;
+        async fn call(&self, mut req: Request) -> Result<Self::Output> {
+            let Some(auth) = req.headers().typed_get::<headers::Authorization<Bearer>>() else {
+                return Err(Error::from_status(StatusCode::UNAUTHORIZED));
+            };
+            let token = auth.token().to_string();
+
+            let credential = if token == self.secret {
+                if self.require_credential {
+                    error!("comet_secret is refused, the agent must use a credential");
+                    return Err(Error::from_status(StatusCode::UNAUTHORIZED));
+                }
+                None
+            } else {
+                let credential = self
+                    .logic
+                    .get_agent_credential(&token)
+                    .await
+                    .map_err(|e| {
+                        error!("failed get agent credential - {e}");
+                        Error::from_status(StatusCode::INTERNAL_SERVER_ERROR)
+                    })?
+                    .ok_or(Error::from_status(StatusCode::UNAUTHORIZED))?;
+
+                // the credential is bound to the mac address of the agent it was issued to
+                let mac_addr = req
+                    .headers()
+                    .get("X-Mac-Address")
+                    .and_then(|v| v.to_str().ok());
+                if mac_addr != Some(credential.mac_addr.as_str()) {
+                    return Err(Error::from_status(StatusCode::UNAUTHORIZED));
+                }
+                Some(credential)
+            };
+
+            req.extensions_mut()
+                .insert(AgentIdentity { token, credential });
+            self.ep.call(req).await
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -154,13 +243,30 @@ pub fn ws(
     secret_header: SecretHeader,
     // _remote_addr: &RemoteAddr,
     Path(namespace): Path<String>,
-
+    identity: Data<&middleware::AgentIdentity>,
     comet: Data<&Comet>,
 ) -> impl IntoResponse {
+    if !comet.accepts_namespace(&namespace) {
+        // the agent tries its next comet
+        info!("refuse agent of namespace {namespace} assigned to another comet group");
+        return StatusCode::MISDIRECTED_REQUEST.into_response();
+    }
+
     let mut bridge = comet.bridge.clone();
     let mut comet = comet.clone();
+    let identity = identity.clone();
 
     ws.on_upgrade(|socket| async move {
+        if let Some(ref credential) = identity.credential
+            && credential.namespace != namespace
+        {
+            error!(
+                "credential {} is not allowed for namespace {namespace}",
+                credential.id
+            );
+            return;
+        }
+
         let (mut sink, mut stream) = socket.split();
         let mac_addr = secret_header.mac_addr.clone();
         let mut client: WsClient<
@@ -172,10 +278,13 @@ pub fn ws(
             .set_mac_address(mac_addr.clone())
             .set_rw(sink, stream);
 
-        if let Err(e) = client.auth(namespace, comet.secret.clone()).await {
-            error!("failed to auth incoming connection - {e}");
-            return;
-        }
+        let auth = match client.auth(namespace, identity.token.clone()).await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("failed to auth incoming connection - {e}");
+                return;
+            }
+        };
 
         client.start_processing_to_client_msg();
 
@@ -191,15 +300,40 @@ pub fn ws(
             )
             .await;
 
-        let ncomet = comet.clone();
-        client
-            .recv(|msg| async move { ncomet.handle(msg).await })
+        let agent_key = get_endpoint(agent_ip.clone(), mac_addr.clone());
+        let conn = comet
+            .connections
+            .register(
+                agent_key.clone(),
+                namespace.clone(),
+                agent_ip.clone(),
+                mac_addr.clone(),
+                Protocol::negotiate(auth.protocol_version),
+            )
             .await;
 
+        let ncomet = comet.clone();
+        let mut closed = comet.closed();
+        tokio::select! {
+            _ = client.recv(|msg| async move { ncomet.handle(msg, agent_key).await }) => {}
+            _ = closed.wait_for(|v| *v) => {
+                info!("comet is shutting down, disconnect {agent_ip}:{namespace}");
+            }
+            _ = comet.reassigned(&namespace) => {
+                info!("namespace is moved to another comet group, disconnect {agent_ip}:{namespace}");
+            }
+            _ = conn.disconnected() => {
+                info!("disconnect {agent_ip}:{namespace} by request");
+            }
+        }
+
+        comet.connections.unregister(&conn).await;
+
         comet.client_offline(agent_ip, mac_addr).await;
 
         client.drop().await;
     })
+    .into_response()
 }
 
 const UPLOAD_DIR: &str = "/tmp/jiascheduler-comet";
@@ -220,9 +354,55 @@ async fn upload_file(file: Option<UploadFile>) -> Result<Option<UploadFile>> {
     Ok(Some(UploadFile {
         filename: file.filename,
         data: None,
+        sha256: None,
+        size: 0,
     }))
 }
 
+/// size of the artifact in the comet, null if it is not pushed yet
+#[handler]
+pub async fn stat_artifact(
+    comet: Data<&Comet>,
+    Path(sha256): Path<String>,
+) -> Json<serde_json::Value> {
+    match comet.artifacts.size(&sha256).await {
+        Ok(v) => return_response!(v),
+        Err(e) => return_response!(code: 50000, e.to_string()),
+    }
+}
+
+#[handler]
+pub async fn save_artifact(
+    comet: Data<&Comet>,
+    Path(sha256): Path<String>,
+    data: Vec<u8>,
+) -> Json<serde_json::Value> {
+    match comet.artifacts.save(&sha256, &data).await {
+        Ok(_) => return_response!(),
+        Err(e) => return_response!(code: 50000, e.to_string()),
+    }
+}
+
+#[handler]
+pub async fn get_artifact_chunk(
+    comet: Data<&Comet>,
+    Path(sha256): Path<String>,
+    Query(query): Query<types::ArtifactChunkQuery>,
+) -> Response {
+    match comet
+        .artifacts
+        .read_chunk(&sha256, query.offset, query.limit)
+        .await
+    {
+        Ok(data) => Response::builder()
+            .content_type("application/octet-stream")
+            .body(data),
+        Err(e) => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(e.to_string()),
+    }
+}
+
 #[handler]
 pub async fn get_file(Path(filename): Path<String>) -> impl IntoResponse {
     let buf = PathBuf::from(filename.clone());
@@ -256,8 +436,17 @@ pub async fn get_file(Path(filename): Path<String>) -> impl IntoResponse {
 #[handler]
 pub async fn dispatch(
     comet: Data<&Comet>,
+    remote_addr: &RemoteAddr,
     Json(mut req): Json<types::DispatchJobRequest>,
 ) -> Json<serde_json::Value> {
+    if let Some(ip) = remote_addr.as_socket_addr().map(|v| v.ip()) {
+        match comet.is_dispatch_allowed(ip).await {
+            Ok(true) => {}
+            Ok(false) => return_response!(code: 50403, format!("{ip} is not allowed to dispatch")),
+            Err(e) => return_response!(code: 50000, e.to_string()),
+        }
+    }
+
     // let upload = req.dispatch_params.base_job.upload_file.take();
 
     // req.dispatch_params.base_job.upload_file = match upload_file(upload).await {
@@ -274,6 +463,64 @@ pub async fn dispatch(
     }
 }
 
+/// ask the agent whether it has received the dispatches of a schedule,
+/// used by the server when the response of /dispatch is lost
+#[handler]
+pub async fn reconcile_dispatch(
+    comet: Data<&Comet>,
+    Json(req): Json<types::ReconcileDispatchRequest>,
+) -> Json<serde_json::Value> {
+    match comet.reconcile_dispatch(req).await {
+        Ok(v) => {
+            return_response!(v);
+        }
+        Err(e) => return_response!(code: 50000, e.to_string()),
+    }
+}
+
+#[handler]
+pub async fn prune_schedule(
+    comet: Data<&Comet>,
+    Json(req): Json<types::PruneScheduleRequest>,
+) -> Json<serde_json::Value> {
+    match comet.prune_schedule(req).await {
+        Ok(v) => {
+            return_response!(json: v);
+        }
+        Err(e) => return_response!(code: 50000, e.to_string()),
+    }
+}
+
+/// the agents connected to this comet
+#[handler]
+pub async fn list_connections(comet: Data<&Comet>) -> Json<serde_json::Value> {
+    return_response!(comet.list_connections().await);
+}
+
+#[handler]
+pub async fn disconnect_agent(
+    comet: Data<&Comet>,
+    Json(req): Json<types::DisconnectAgentRequest>,
+) -> Json<serde_json::Value> {
+    match comet.disconnect_agent(req).await {
+        Ok(_) => return_response!(),
+        Err(e) => return_response!(code: 50000, e.to_string()),
+    }
+}
+
+#[handler]
+pub async fn exchange_token(
+    comet: Data<&Comet>,
+    Json(req): Json<types::ExchangeTokenRequest>,
+) -> Json<serde_json::Value> {
+    match comet.exchange_bootstrap_token(req).await {
+        Ok(credential) => {
+            return_response!(types::ExchangeTokenResponse { credential });
+        }
+        Err(e) => return_response!(code: 50401, e.to_string()),
+    }
+}
+
 #[handler]
 pub async fn runtime_action(
     comet: Data<&Comet>,
@@ -288,6 +535,32 @@ pub async fn runtime_action(
     }
 }
 
+#[handler]
+pub async fn agent_log(
+    comet: Data<&Comet>,
+    Json(req): Json<types::AgentLogRequest>,
+) -> Json<serde_json::Value> {
+    match comet.agent_log(req).await {
+        Ok(v) => {
+            return_response!(json:v);
+        }
+        Err(e) => return_response!(code: 50000, e.to_string()),
+    }
+}
+
+#[handler]
+pub async fn agent_config(
+    comet: Data<&Comet>,
+    Json(req): Json<types::AgentConfigRequest>,
+) -> Json<serde_json::Value> {
+    match comet.agent_config(req).await {
+        Ok(v) => {
+            return_response!(json:v);
+        }
+        Err(e) => return_response!(code: 50000, e.to_string()),
+    }
+}
+
 #[handler]
 pub async fn ssh_register(
     webssh: WebSocket,
@@ -1,25 +1,46 @@
-use std::net::IpAddr;
+use std::{cmp::Reverse, collections::HashMap, net::IpAddr};
 
 use crate::{
     bridge::msg::{
-        AgentOfflineParams, AgentOnlineParams, HeartbeatParams, MsgReqKind, UpdateJobParams,
+        AgentOfflineParams, AgentOnlineParams, DispatchAckParams, HeartbeatParams, MsgReqKind,
+        RunLockParams, UpdateJobParams,
     },
     bus::Bus,
     get_endpoint, LinkPair,
 };
 use anyhow::{Ok, Result};
+use chrono::Utc;
 use local_ip_address::local_ip;
+use nanoid::nanoid;
 use redis::{AsyncCommands, FromRedisValue, RedisResult};
 
 use serde_json::{json, Value};
 
 use super::types::{self};
 
+const SECURITY_SETTING_KEY: &str = "jiascheduler:security:setting";
+const BOOTSTRAP_TOKEN_KEY: &str = "jiascheduler:agent:bootstrap-token";
+const AGENT_CREDENTIAL_KEY: &str = "jiascheduler:agent:credential";
+const DISPATCH_ACK_KEY: &str = "jiascheduler:dispatch:ack";
+/// seconds the dispatch acks are kept in redis
+const DISPATCH_ACK_TTL: u64 = 6 * 3600;
+const RUN_LOCK_KEY: &str = "jiascheduler:run-lock";
+/// seconds a tick of a singleton job is locked, longer than the clock skew of the agents
+const RUN_LOCK_TTL: u64 = 3600;
+/// hash of namespace to the comet group serving its agents
+const COMET_GROUP_ASSIGNMENT_KEY: &str = "jiascheduler:comet:group-assignment";
+/// hash of comet address to the running comet
+const COMET_REGISTRY_KEY: &str = "jiascheduler:comet:registry";
+/// a comet not registered again within the seconds is stopped
+const COMET_REGISTRY_TTL: i64 = 90;
+
 #[derive(Clone)]
 pub struct Logic {
     pub redis_client: redis::Client,
     local_ip: IpAddr,
     bus: Bus,
+    tls: bool,
+    group: Option<String>,
 }
 
 impl Logic {
@@ -28,13 +49,37 @@ impl Logic {
             local_ip: local_ip().expect("failed get local ip"),
             redis_client: redis.clone(),
             bus: Bus::new(redis),
+            tls: false,
+            group: None,
         }
     }
 
+    /// send the agent events through the given bus instead of the redis stream
+    pub fn with_bus(mut self, bus: Bus) -> Self {
+        self.bus = bus;
+        self
+    }
+
+    /// mark the comet address registered by heartbeat as https
+    pub fn with_tls(mut self, tls: bool) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// comet group recorded in the link pairs registered by heartbeat
+    pub fn with_group(mut self, group: Option<String>) -> Self {
+        self.group = group;
+        self
+    }
+
     pub fn get_agent_key(&self, ip: impl Into<String>, mac_addr: impl Into<String>) -> String {
         get_endpoint(ip, mac_addr)
     }
 
+    pub fn comet_addr(&self, port: u16) -> String {
+        format!("{}:{}", self.local_ip, port)
+    }
+
     async fn set_link_pair<T: Into<String>>(
         &self,
         namespace: T,
@@ -48,8 +93,10 @@ impl Logic {
             .set_ex(
                 key,
                 types::LinkPair {
-                    comet_addr: format!("{}:{}", self.local_ip.to_string(), port),
+                    comet_addr: self.comet_addr(port),
                     namespace: namespace.into(),
+                    tls: self.tls,
+                    group: self.group.clone(),
                 },
                 90,
             )
@@ -74,15 +121,261 @@ impl Logic {
         Ok((key.clone(), LinkPair::from_redis_value(&val)?))
     }
 
+    /// namespace to comet group, the agents of an unassigned namespace are accepted by any comet
+    pub async fn get_comet_group_assignments(&self) -> Result<HashMap<String, String>> {
+        let mut conn = self.get_async_connection().await?;
+        Ok(conn.hgetall(COMET_GROUP_ASSIGNMENT_KEY).await?)
+    }
+
+    /// replace the assignments, the comets close the agent connections of the moved namespaces
+    pub async fn save_comet_group_assignments(
+        &self,
+        assignments: &HashMap<String, String>,
+    ) -> Result<()> {
+        let mut conn = self.get_async_connection().await?;
+        let mut pipe = redis::pipe();
+        pipe.atomic().del(COMET_GROUP_ASSIGNMENT_KEY);
+        if !assignments.is_empty() {
+            pipe.hset_multiple(
+                COMET_GROUP_ASSIGNMENT_KEY,
+                &assignments.iter().collect::<Vec<_>>(),
+            );
+        }
+        let _: () = pipe.query_async(&mut conn).await?;
+        Ok(())
+    }
+
+    /// announce the comet, the console queries the registered comets for their connections
+    pub async fn register_comet(&self, port: u16) -> Result<()> {
+        let mut conn = self.get_async_connection().await?;
+        let comet = types::CometInstance {
+            comet_addr: self.comet_addr(port),
+            tls: self.tls,
+            group: self.group.clone(),
+            updated_at: Utc::now().timestamp(),
+        };
+        let _: () = conn
+            .hset(COMET_REGISTRY_KEY, comet.comet_addr.clone(), comet)
+            .await?;
+        Ok(())
+    }
+
+    /// the comets registered recently, the stale ones are removed
+    pub async fn get_comets(&self) -> Result<Vec<types::CometInstance>> {
+        let mut conn = self.get_async_connection().await?;
+        let all: HashMap<String, types::CometInstance> = conn.hgetall(COMET_REGISTRY_KEY).await?;
+        let now = Utc::now().timestamp();
+        let (alive, stale): (Vec<_>, Vec<_>) = all
+            .into_values()
+            .partition(|v| now - v.updated_at <= COMET_REGISTRY_TTL);
+        if !stale.is_empty() {
+            let _: () = conn
+                .hdel(
+                    COMET_REGISTRY_KEY,
+                    stale.into_iter().map(|v| v.comet_addr).collect::<Vec<_>>(),
+                )
+                .await?;
+        }
+        Ok(alive)
+    }
+
     pub async fn get_async_connection(&self) -> RedisResult<redis::aio::MultiplexedConnection> {
         self.redis_client.get_multiplexed_async_connection().await
     }
 
+    pub async fn get_security_setting(&self) -> Result<types::SecuritySetting> {
+        let mut conn = self.get_async_connection().await?;
+        let val: Option<types::SecuritySetting> = conn.get(SECURITY_SETTING_KEY).await?;
+        Ok(val.unwrap_or_default())
+    }
+
+    pub async fn save_security_setting(&self, setting: types::SecuritySetting) -> Result<()> {
+        let mut conn = self.get_async_connection().await?;
+        let _: () = conn.set(SECURITY_SETTING_KEY, setting).await?;
+        Ok(())
+    }
+
+    pub async fn create_bootstrap_token(&self, token: types::BootstrapToken) -> Result<String> {
+        let mut conn = self.get_async_connection().await?;
+        let secret = nanoid!(40);
+        let _: () = conn.hset(BOOTSTRAP_TOKEN_KEY, &secret, token).await?;
+        Ok(secret)
+    }
+
+    /// list the bootstrap tokens, expired tokens are removed
+    pub async fn get_bootstrap_tokens(&self) -> Result<Vec<(String, types::BootstrapToken)>> {
+        let mut conn = self.get_async_connection().await?;
+        let all: HashMap<String, types::BootstrapToken> = conn.hgetall(BOOTSTRAP_TOKEN_KEY).await?;
+        let now = Utc::now().timestamp();
+
+        let mut list = Vec::new();
+        for (secret, token) in all {
+            if token.is_expired(now) {
+                let _: () = conn.hdel(BOOTSTRAP_TOKEN_KEY, &secret).await?;
+                continue;
+            }
+            list.push((secret, token));
+        }
+        list.sort_by_key(|v| Reverse(v.1.created_time));
+        Ok(list)
+    }
+
+    pub async fn revoke_bootstrap_token(&self, id: &str) -> Result<u64> {
+        let mut conn = self.get_async_connection().await?;
+        let mut affected = 0;
+        for (secret, token) in self.get_bootstrap_tokens().await? {
+            if token.id == id {
+                let n: u64 = conn.hdel(BOOTSTRAP_TOKEN_KEY, &secret).await?;
+                affected += n;
+            }
+        }
+        Ok(affected)
+    }
+
+    /// verify the bootstrap token and issue a credential bound to the agent
+    pub async fn exchange_bootstrap_token(
+        &self,
+        req: types::ExchangeTokenRequest,
+    ) -> Result<String> {
+        let mut conn = self.get_async_connection().await?;
+        let token: Option<types::BootstrapToken> =
+            conn.hget(BOOTSTRAP_TOKEN_KEY, &req.token).await?;
+        let Some(token) = token else {
+            anyhow::bail!("invalid bootstrap token");
+        };
+
+        token.verify(&req.namespace, Utc::now().timestamp())?;
+        if token.single_use {
+            // only the first agent deleting the token wins
+            let n: u64 = conn.hdel(BOOTSTRAP_TOKEN_KEY, &req.token).await?;
+            if n == 0 {
+                anyhow::bail!("bootstrap token has been used");
+            }
+        }
+
+        let credential = nanoid!(48);
+        let _: () = conn
+            .hset(
+                AGENT_CREDENTIAL_KEY,
+                &credential,
+                types::AgentCredential {
+                    id: nanoid!(12),
+                    namespace: req.namespace,
+                    mac_addr: req.mac_addr,
+                    agent_ip: req.agent_ip,
+                    token_id: token.id,
+                    created_time: Utc::now().timestamp(),
+                },
+            )
+            .await?;
+        Ok(credential)
+    }
+
+    pub async fn get_agent_credential(
+        &self,
+        credential: &str,
+    ) -> Result<Option<types::AgentCredential>> {
+        let mut conn = self.get_async_connection().await?;
+        let val: Option<types::AgentCredential> =
+            conn.hget(AGENT_CREDENTIAL_KEY, credential).await?;
+        Ok(val)
+    }
+
+    pub async fn get_agent_credentials(&self) -> Result<Vec<types::AgentCredential>> {
+        let mut conn = self.get_async_connection().await?;
+        let all: HashMap<String, types::AgentCredential> =
+            conn.hgetall(AGENT_CREDENTIAL_KEY).await?;
+        let mut list: Vec<types::AgentCredential> = all.into_values().collect();
+        list.sort_by_key(|v| Reverse(v.created_time));
+        Ok(list)
+    }
+
+    /// revoke credentials by id or by the mac address of the agent
+    pub async fn revoke_agent_credential(
+        &self,
+        id: Option<&str>,
+        mac_addr: Option<&str>,
+    ) -> Result<Vec<types::AgentCredential>> {
+        let mut conn = self.get_async_connection().await?;
+        let all: HashMap<String, types::AgentCredential> =
+            conn.hgetall(AGENT_CREDENTIAL_KEY).await?;
+        let mut revoked = vec![];
+        for (credential, v) in all {
+            if v.matches(id, mac_addr) {
+                let n: u64 = conn.hdel(AGENT_CREDENTIAL_KEY, &credential).await?;
+                if n > 0 {
+                    revoked.push(v);
+                }
+            }
+        }
+        Ok(revoked)
+    }
+
     pub async fn dispath(&self, req: types::DispatchJobRequest) -> Result<(String, MsgReqKind)> {
         let pair = self.get_link_pair(&req.agent_ip, &req.mac_addr).await?;
         Ok((pair.0, MsgReqKind::DispatchJobRequest(req.dispatch_params)))
     }
 
+    fn get_dispatch_ack_key(&self, agent_key: &str, dispatch_id: &str) -> String {
+        format!("{DISPATCH_ACK_KEY}:{agent_key}:{dispatch_id}")
+    }
+
+    /// true if the agent is the first one asking for the tick of the singleton job
+    pub async fn acquire_run_lock(&self, agent_key: &str, params: RunLockParams) -> Result<bool> {
+        let mut conn = self.get_async_connection().await?;
+        let key = format!(
+            "{RUN_LOCK_KEY}:{}:{}:{}",
+            params.eid, params.schedule_id, params.tick
+        );
+        let acquired: Option<String> = redis::cmd("SET")
+            .arg(key)
+            .arg(agent_key)
+            .arg("NX")
+            .arg("EX")
+            .arg(RUN_LOCK_TTL)
+            .query_async(&mut conn)
+            .await?;
+        Ok(acquired.is_some())
+    }
+
+    /// keep the ack so that any comet can answer whether the dispatch reached the agent
+    pub async fn save_dispatch_ack(&self, agent_key: &str, ack: DispatchAckParams) -> Result<()> {
+        let mut conn = self.get_async_connection().await?;
+        let key = self.get_dispatch_ack_key(agent_key, &ack.dispatch_id);
+        let _: () = conn
+            .set_ex(key, serde_json::to_string(&ack)?, DISPATCH_ACK_TTL)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_dispatch_ack(
+        &self,
+        agent_key: &str,
+        dispatch_id: &str,
+    ) -> Result<Option<DispatchAckParams>> {
+        let mut conn = self.get_async_connection().await?;
+        let val: Option<String> = conn
+            .get(self.get_dispatch_ack_key(agent_key, dispatch_id))
+            .await?;
+        Ok(val.map(|v| serde_json::from_str(&v)).transpose()?)
+    }
+
+    pub async fn reconcile_dispatch(
+        &self,
+        req: types::ReconcileDispatchRequest,
+    ) -> Result<(String, MsgReqKind)> {
+        let pair = self.get_link_pair(&req.agent_ip, &req.mac_addr).await?;
+        Ok((pair.0, MsgReqKind::ReconcileDispatchRequest(req.params)))
+    }
+
+    pub async fn prune_schedule(
+        &self,
+        req: types::PruneScheduleRequest,
+    ) -> Result<(String, MsgReqKind)> {
+        let pair = self.get_link_pair(&req.agent_ip, &req.mac_addr).await?;
+        Ok((pair.0, MsgReqKind::PruneScheduleRequest(req.params)))
+    }
+
     pub async fn sfpt_read_dir(
         &self,
         req: types::SftpReadDirRequest,
@@ -121,6 +414,19 @@ impl Logic {
         Ok((pair.0, MsgReqKind::RuntimeActionRequest(req.action_params)))
     }
 
+    pub async fn agent_log(&self, req: types::AgentLogRequest) -> Result<(String, MsgReqKind)> {
+        let pair = self.get_link_pair(&req.agent_ip, &req.mac_addr).await?;
+        Ok((pair.0, MsgReqKind::AgentLogRequest(req.params)))
+    }
+
+    pub async fn agent_config(
+        &self,
+        req: types::AgentConfigRequest,
+    ) -> Result<(String, MsgReqKind)> {
+        let pair = self.get_link_pair(&req.agent_ip, &req.mac_addr).await?;
+        Ok((pair.0, MsgReqKind::AgentConfigRequest(req.params)))
+    }
+
     pub async fn update_job(&self, req: UpdateJobParams) -> Result<Value> {
         self.bus.update_job(req).await?;
         Ok(json!(null))
@@ -1,7 +1,10 @@
+use std::net::IpAddr;
+
 use serde::{Deserialize, Serialize};
 
 use crate::bridge::msg::{
-    DispatchJobParams, RuntimeActionParams, SftpDownloadParams, SftpReadDirParams,
+    AgentConfigParams, AgentLogParams, DispatchJobParams, PruneScheduleParams,
+    ReconcileDispatchParams, RuntimeActionParams, SftpDownloadParams, SftpReadDirParams,
     SftpRemoveParams, SftpUploadParams,
 };
 use redis_macros::{FromRedisValue, ToRedisArgs};
@@ -14,6 +17,22 @@ pub struct DispatchJobRequest {
     pub dispatch_params: DispatchJobParams,
 }
 
+/// ask whether the agent has received the dispatches of a schedule
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReconcileDispatchRequest {
+    pub agent_ip: String,
+    pub mac_addr: String,
+    pub params: ReconcileDispatchParams,
+}
+
+/// stop the stored schedules of the agent which the console does not expect
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PruneScheduleRequest {
+    pub agent_ip: String,
+    pub mac_addr: String,
+    pub params: PruneScheduleParams,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RuntimeActionRequest {
     pub agent_ip: String,
@@ -53,10 +72,269 @@ pub struct SftpDownloadRequest {
     pub params: SftpDownloadParams,
 }
 
+/// security settings shared by the console and comet through redis
+#[derive(Serialize, Deserialize, Clone, Debug, FromRedisValue, ToRedisArgs)]
+pub struct SecuritySetting {
+    /// ip or cidr allowed to login the console, any address if empty
+    #[serde(default)]
+    pub login_allowlist: Vec<String>,
+    /// ip or cidr allowed to call the comet /dispatch endpoint, any address if empty
+    #[serde(default)]
+    pub dispatch_allowlist: Vec<String>,
+    /// failed attempts allowed before the account is locked, 0 disables the lockout
+    #[serde(default)]
+    pub login_max_attempts: u32,
+    /// first lockout duration in seconds, doubled on every further failure
+    #[serde(default)]
+    pub login_lockout_secs: u64,
+    /// upper bound of the lockout duration in seconds
+    #[serde(default)]
+    pub login_max_lockout_secs: u64,
+    /// ip or cidr of the reverse proxies whose X-Real-IP, Forwarded and X-Forwarded-For
+    /// headers are trusted, the peer address is used if empty
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+}
+
+impl Default for SecuritySetting {
+    fn default() -> Self {
+        Self {
+            login_allowlist: vec![],
+            dispatch_allowlist: vec![],
+            login_max_attempts: 5,
+            login_lockout_secs: 60,
+            login_max_lockout_secs: 86400,
+            trusted_proxies: vec![],
+        }
+    }
+}
+
+impl SecuritySetting {
+    pub fn is_login_allowed(&self, ip: IpAddr) -> bool {
+        match_allowlist(&self.login_allowlist, ip)
+    }
+
+    pub fn is_dispatch_allowed(&self, ip: IpAddr) -> bool {
+        match_allowlist(&self.dispatch_allowlist, ip)
+    }
+
+    /// the address of the client, the forwarded address is only used if the peer is a trusted proxy
+    pub fn client_ip(&self, peer: Option<IpAddr>, forwarded: Option<IpAddr>) -> Option<IpAddr> {
+        match peer {
+            Some(ip)
+                if !self.trusted_proxies.is_empty()
+                    && match_allowlist(&self.trusted_proxies, ip) =>
+            {
+                forwarded.or(peer)
+            }
+            _ => peer,
+        }
+    }
+
+    /// lockout duration after the given number of failed attempts, None if not locked
+    pub fn lockout_secs(&self, failed_attempts: u32) -> Option<u64> {
+        if self.login_max_attempts == 0 || failed_attempts < self.login_max_attempts {
+            return None;
+        }
+        let exp = (failed_attempts - self.login_max_attempts).min(32);
+        let secs = self.login_lockout_secs.saturating_mul(1u64 << exp);
+        Some(secs.min(self.login_max_lockout_secs.max(self.login_lockout_secs)))
+    }
+}
+
+/// check that every entry is a valid ip or cidr
+pub fn validate_allowlist(list: &[String]) -> anyhow::Result<()> {
+    for v in list {
+        parse_cidr(v).ok_or(anyhow::anyhow!("invalid ip or cidr {v}"))?;
+    }
+    Ok(())
+}
+
+fn parse_cidr(v: &str) -> Option<(IpAddr, u32)> {
+    let (addr, prefix) = match v.trim().split_once('/') {
+        Some((addr, prefix)) => (addr.parse::<IpAddr>().ok()?, Some(prefix.parse().ok()?)),
+        None => (v.trim().parse::<IpAddr>().ok()?, None),
+    };
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    let prefix = prefix.unwrap_or(max);
+    if prefix > max {
+        return None;
+    }
+    Some((addr, prefix))
+}
+
+fn match_allowlist(list: &[String], ip: IpAddr) -> bool {
+    if list.is_empty() {
+        return true;
+    }
+    let ip = match ip {
+        IpAddr::V6(v) => v.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        v => v,
+    };
+
+    list.iter()
+        .filter_map(|v| parse_cidr(v))
+        .any(|(net, prefix)| match (net, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        })
+}
+
+/// token generated in the console, exchanged by the agent for a long-lived credential
+#[derive(Serialize, Deserialize, Clone, Debug, FromRedisValue, ToRedisArgs)]
+pub struct BootstrapToken {
+    pub id: String,
+    /// the token can only be exchanged by agents of this namespace, any namespace if empty
+    #[serde(default)]
+    pub namespace: String,
+    #[serde(default)]
+    pub single_use: bool,
+    /// unix timestamp, never expires if none
+    pub expire_time: Option<i64>,
+    #[serde(default)]
+    pub info: String,
+    pub created_user: String,
+    pub created_time: i64,
+}
+
+impl BootstrapToken {
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expire_time.is_some_and(|v| v <= now)
+    }
+
+    /// whether an agent of the namespace may exchange the token now
+    pub fn verify(&self, namespace: &str, now: i64) -> anyhow::Result<()> {
+        if self.is_expired(now) {
+            anyhow::bail!("bootstrap token is expired");
+        }
+        if !self.namespace.is_empty() && self.namespace != namespace {
+            anyhow::bail!("bootstrap token is not allowed for namespace {namespace}");
+        }
+        Ok(())
+    }
+}
+
+/// a websocket connection of an agent to the comet
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct AgentConnectionInfo {
+    pub agent_key: String,
+    pub namespace: String,
+    pub agent_ip: String,
+    pub mac_addr: String,
+    /// negotiated on auth, 0 for the agents which don't report their version
+    pub protocol_version: u32,
+    pub comet_addr: String,
+    /// unix timestamp
+    pub connected_at: i64,
+    /// unix timestamp, none if no heartbeat since connected
+    pub last_heartbeat: Option<i64>,
+    /// requests sent by the agent
+    pub msgs_received: u64,
+    /// requests sent to the agent
+    pub msgs_sent: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DisconnectAgentRequest {
+    pub agent_ip: String,
+    pub mac_addr: String,
+}
+
+/// read the recent log lines of the agent
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AgentLogRequest {
+    pub agent_ip: String,
+    pub mac_addr: String,
+    pub params: AgentLogParams,
+}
+
+/// push the config managed by the console to the agent
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AgentConfigRequest {
+    pub agent_ip: String,
+    pub mac_addr: String,
+    pub params: AgentConfigParams,
+}
+
+/// a running comet, registered in redis periodically
+#[derive(Serialize, Deserialize, Clone, Debug, FromRedisValue, ToRedisArgs)]
+pub struct CometInstance {
+    pub comet_addr: String,
+    #[serde(default)]
+    pub tls: bool,
+    #[serde(default)]
+    pub group: Option<String>,
+    /// unix timestamp
+    pub updated_at: i64,
+}
+
+impl CometInstance {
+    pub fn http_url(&self, path: &str) -> String {
+        let scheme = if self.tls { "https" } else { "http" };
+        format!("{scheme}://{}{path}", self.comet_addr)
+    }
+}
+
+/// credential issued to one agent, used in place of comet_secret
+#[derive(Serialize, Deserialize, Clone, Debug, FromRedisValue, ToRedisArgs)]
+pub struct AgentCredential {
+    pub id: String,
+    pub namespace: String,
+    pub mac_addr: String,
+    pub agent_ip: String,
+    pub token_id: String,
+    pub created_time: i64,
+}
+
+impl AgentCredential {
+    /// revoked by its id or by the mac address of the agent
+    pub fn matches(&self, id: Option<&str>, mac_addr: Option<&str>) -> bool {
+        id.is_some_and(|id| id == self.id) || mac_addr.is_some_and(|v| v == self.mac_addr)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ExchangeTokenRequest {
+    pub token: String,
+    pub namespace: String,
+    pub mac_addr: String,
+    pub agent_ip: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ExchangeTokenResponse {
+    pub credential: String,
+}
+
 #[derive(Serialize, Clone, FromRedisValue, Deserialize, ToRedisArgs)]
 pub struct LinkPair {
     pub namespace: String,
     pub comet_addr: String,
+    /// comet serves https and wss
+    #[serde(default)]
+    pub tls: bool,
+    /// comet group of the comet, None if the comet is not in a group
+    #[serde(default)]
+    pub group: Option<String>,
+}
+
+impl LinkPair {
+    pub fn http_url(&self, path: &str) -> String {
+        let scheme = if self.tls { "https" } else { "http" };
+        format!("{scheme}://{}{path}", self.comet_addr)
+    }
+
+    pub fn ws_url(&self, path: &str) -> String {
+        let scheme = if self.tls { "wss" } else { "ws" };
+        format!("{scheme}://{}{path}", self.comet_addr)
+    }
 }
 impl ToString for LinkPair {
     fn to_string(&self) -> String {
@@ -97,7 +375,86 @@ pub struct SshLoginParams {
     pub namespace: String,
     pub user: String,
     pub password: String,
+    /// used instead of the password if set
+    #[serde(default)]
+    pub private_key: Option<String>,
+    #[serde(default)]
+    pub key_passphrase: Option<String>,
     pub port: u16,
     pub ip: String,
     pub mac_addr: String,
 }
+
+#[test]
+fn test_security_setting() {
+    let setting = SecuritySetting {
+        login_allowlist: vec!["10.0.0.0/8".to_string(), "192.168.1.10".to_string()],
+        ..Default::default()
+    };
+    assert!(setting.is_login_allowed("10.2.3.4".parse().unwrap()));
+    assert!(setting.is_login_allowed("192.168.1.10".parse().unwrap()));
+    assert!(!setting.is_login_allowed("192.168.1.11".parse().unwrap()));
+    assert!(setting.is_login_allowed("::ffff:10.0.0.1".parse().unwrap()));
+    assert!(setting.is_dispatch_allowed("8.8.8.8".parse().unwrap()));
+
+    assert_eq!(setting.lockout_secs(4), None);
+    assert_eq!(setting.lockout_secs(5), Some(60));
+    assert_eq!(setting.lockout_secs(7), Some(240));
+    assert_eq!(setting.lockout_secs(100), Some(86400));
+    assert!(validate_allowlist(&["10.0.0.0/33".to_string()]).is_err());
+
+    let peer = "10.1.1.1".parse().ok();
+    let forwarded = "1.2.3.4".parse().ok();
+    assert_eq!(setting.client_ip(peer, forwarded), peer);
+    let setting = SecuritySetting {
+        trusted_proxies: vec!["10.1.1.0/24".to_string()],
+        ..Default::default()
+    };
+    assert_eq!(setting.client_ip(peer, forwarded), forwarded);
+    assert_eq!(setting.client_ip(peer, None), peer);
+    assert_eq!(
+        setting.client_ip("10.2.1.1".parse().ok(), forwarded),
+        "10.2.1.1".parse().ok()
+    );
+}
+
+#[test]
+fn test_agent_credential() {
+    let token = BootstrapToken {
+        id: "t1".to_string(),
+        namespace: "prod".to_string(),
+        single_use: true,
+        expire_time: Some(100),
+        info: String::new(),
+        created_user: "admin".to_string(),
+        created_time: 0,
+    };
+    assert!(token.verify("prod", 99).is_ok());
+    assert!(token.verify("prod", 100).is_err());
+    assert!(token.verify("dev", 99).is_err());
+    let any = BootstrapToken {
+        namespace: String::new(),
+        expire_time: None,
+        ..token
+    };
+    assert!(any.verify("dev", i64::MAX).is_ok());
+
+    let credential = AgentCredential {
+        id: "c1".to_string(),
+        namespace: "prod".to_string(),
+        mac_addr: "00:11".to_string(),
+        agent_ip: "10.0.0.1".to_string(),
+        token_id: "t1".to_string(),
+        created_time: 0,
+    };
+    assert!(credential.matches(Some("c1"), None));
+    assert!(credential.matches(None, Some("00:11")));
+    assert!(!credential.matches(Some("c2"), Some("00:22")));
+    assert!(!credential.matches(None, None));
+}
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct ArtifactChunkQuery {
+    pub offset: u64,
+    pub limit: u64,
+}
@@ -3,25 +3,33 @@ use local_ip_address::local_ip;
 use std::net::IpAddr;
 use std::sync::{Mutex, OnceLock};
 
+pub mod artifact;
 pub mod bridge;
 pub mod comet;
 pub mod scheduler;
+#[cfg(feature = "simulator")]
+pub mod simulator;
 pub mod ssh;
+pub mod tls;
 pub use bridge::msg::DispatchJobParams;
 pub use comet::logic::Logic;
 pub use comet::types::{
-    DispatchJobRequest, LinkPair, SftpDownloadRequest, SftpReadDirRequest, SftpRemoveRequest,
-    SftpUploadRequest,
+    AgentLogRequest, DispatchJobRequest, LinkPair, PruneScheduleRequest, ReconcileDispatchRequest,
+    SecuritySetting, SftpDownloadRequest, SftpReadDirRequest, SftpRemoveRequest, SftpUploadRequest,
 };
 use reqwest::Client;
 pub use scheduler::types::BaseJob;
 pub use scheduler::types::JobAction;
+use tls::TlsOptions;
+use tokio_tungstenite::Connector;
 
 pub mod bus;
 
 static LOCAL_IP: OnceLock<IpAddr> = OnceLock::new();
 static HTTP_CLIENT: OnceLock<Client> = OnceLock::new();
 static COMET_ADDR: Mutex<Option<String>> = Mutex::new(None);
+static COMET_SECRET: Mutex<Option<String>> = Mutex::new(None);
+static WS_CONNECTOR: OnceLock<Connector> = OnceLock::new();
 
 pub fn get_local_ip() -> IpAddr {
     let ip = LOCAL_IP.get_or_init(|| local_ip().expect("failed get local ip"));
@@ -33,6 +41,21 @@ pub fn get_endpoint(ip: impl Into<String>, mac_address: impl Into<String>) -> St
     format!("jiascheduler:ins:{ip}:{mac_address}")
 }
 
+/// set the certificates used to connect to comet, must be called before the first connection
+pub fn set_tls_options(opts: TlsOptions) -> Result<()> {
+    let connector = opts.ws_connector()?;
+    // build the http client before it is initialized without certificates
+    let client = opts.http_client(Client::builder())?.build()?;
+    if WS_CONNECTOR.set(connector).is_err() || HTTP_CLIENT.set(client).is_err() {
+        anyhow::bail!("tls options already initialized");
+    }
+    Ok(())
+}
+
+pub fn get_ws_connector() -> Option<Connector> {
+    WS_CONNECTOR.get().cloned()
+}
+
 pub fn get_http_client() -> Client {
     let c = HTTP_CLIENT.get_or_init(|| reqwest::Client::new());
     c.clone()
@@ -46,6 +69,39 @@ pub fn get_comet_addr() -> Option<String> {
     COMET_ADDR.lock().unwrap().clone()
 }
 
+/// comet_secret or the credential of the agent, used by the http requests to comet
+pub fn set_comet_secret(secret: impl Into<String>) {
+    COMET_SECRET.lock().unwrap().replace(secret.into());
+}
+
+pub fn get_comet_secret() -> Option<String> {
+    COMET_SECRET.lock().unwrap().clone()
+}
+
+/// resolves on SIGINT or SIGTERM
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed install ctrl_c handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
 pub fn get_mac_address() -> Result<String> {
     match mac_address::get_mac_address()? {
         Some(ma) => Ok(ma.to_string()),
@@ -70,9 +126,12 @@ macro_rules! local_time {
     };
 }
 
-#[macro_export]
-macro_rules! run_id {
-    () => {
-        chrono::Local::now().format("%Y%m%d%H%M%S").to_string()
-    };
+/// run id of a dispatch, shared by the instances it is dispatched to
+pub fn new_run_id() -> String {
+    format!("r-{}", nanoid::nanoid!(16))
+}
+
+/// run id of a timer tick, the same on all the instances running the schedule
+pub fn tick_run_id(schedule_id: &str, time: chrono::DateTime<chrono::Utc>) -> String {
+    format!("{schedule_id}-{}", time.timestamp())
 }
@@ -1,7 +1,21 @@
+pub mod agent_config;
+pub mod clock;
 mod cmd;
+mod container;
+mod exec_context;
+mod http_check;
 pub(self) mod executor;
 pub(self) mod file;
+pub(crate) mod ledger;
+pub mod metrics;
+mod output;
+mod schedule_store;
 pub mod scheduler;
+mod spool;
+mod supervisor;
+mod throttle;
+pub mod timer;
 pub mod types;
+mod usage;
 
 pub use scheduler::*;
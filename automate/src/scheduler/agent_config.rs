@@ -0,0 +1,85 @@
+use std::{
+    path::PathBuf,
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+use anyhow::{Context, Result, anyhow};
+use tokio::fs;
+
+use crate::bridge::msg::{AgentConfig, AgentConfigState};
+
+pub const DEFAULT_HEARTBEAT_INTERVAL: u64 = 60;
+
+static STORE: OnceLock<AgentConfigStore> = OnceLock::new();
+
+/// the config pushed by the console, saved in a file and preferred over the flags on restart
+struct AgentConfigStore {
+    path: PathBuf,
+    flags: AgentConfig,
+    state: Mutex<AgentConfigState>,
+}
+
+/// load the config saved in the file, returns the settings in effect,
+/// those not saved are taken from the flags
+pub fn init(path: impl Into<PathBuf>, flags: AgentConfig) -> Result<AgentConfig> {
+    let path = path.into();
+    let config = match std::fs::read(&path) {
+        Ok(v) => serde_json::from_slice::<AgentConfig>(&v).with_context(|| {
+            format!(
+                "failed parse agent config {}, remove it to use the flags",
+                path.display()
+            )
+        })?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => AgentConfig::default(),
+        Err(e) => return Err(e.into()),
+    };
+    let effective = config.or(&flags);
+    STORE
+        .set(AgentConfigStore {
+            path,
+            flags,
+            state: Mutex::new(AgentConfigState {
+                config,
+                effective: effective.clone(),
+            }),
+        })
+        .map_err(|_| anyhow!("agent config already initialized"))?;
+    Ok(effective)
+}
+
+/// reported in the heartbeat, none if the agent is not started with a config file
+pub fn state() -> Option<AgentConfigState> {
+    STORE.get().map(|v| v.state.lock().unwrap().clone())
+}
+
+pub fn heartbeat_interval() -> Duration {
+    let secs = STORE
+        .get()
+        .and_then(|v| v.state.lock().unwrap().effective.heartbeat_interval)
+        .filter(|v| *v > 0)
+        .unwrap_or(DEFAULT_HEARTBEAT_INTERVAL);
+    Duration::from_secs(secs)
+}
+
+/// save the config pushed by the console, the log level and the heartbeat interval
+/// take effect at once, the others after restart
+pub async fn apply(config: AgentConfig) -> Result<AgentConfigState> {
+    let store = STORE
+        .get()
+        .ok_or(anyhow!("the agent is not started with a config file"))?;
+    let live = config.or(&store.flags);
+    if let Some(level) = &live.log_level {
+        utils::set_log_level(level)?;
+    }
+
+    let tmp = store.path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_vec_pretty(&config)?).await?;
+    fs::rename(&tmp, &store.path).await?;
+
+    let mut state = store.state.lock().unwrap();
+    state.config = config;
+    state.effective.log_level = live.log_level;
+    state.effective.heartbeat_interval = live.heartbeat_interval;
+    Ok(state.clone())
+}
@@ -0,0 +1,110 @@
+use std::{future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use tokio::sync::watch;
+
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// source of the time of the timers and the timeouts,
+/// the tests replace the system clock with a MockClock to run them without sleeping
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+
+    fn sleep(&self, duration: Duration) -> Sleep;
+
+    fn sleep_until(&self, deadline: DateTime<Utc>) -> Sleep {
+        self.sleep((deadline - self.now()).to_std().unwrap_or_default())
+    }
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+pub fn system_clock() -> SharedClock {
+    Arc::new(SystemClock)
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// time only moves when it is advanced, the sleeps wake up once their deadline is reached
+#[derive(Clone)]
+pub struct MockClock {
+    now: Arc<watch::Sender<DateTime<Utc>>>,
+}
+
+impl MockClock {
+    pub fn new(start: DateTime<Utc>) -> Self {
+        Self {
+            now: Arc::new(watch::Sender::new(start)),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let delta = TimeDelta::from_std(duration).unwrap_or(TimeDelta::max_value());
+        self.now.send_modify(|v| {
+            *v = v
+                .checked_add_signed(delta)
+                .unwrap_or(DateTime::<Utc>::MAX_UTC)
+        });
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        self.now.send_replace(now);
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.borrow()
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        let deadline = TimeDelta::from_std(duration)
+            .ok()
+            .and_then(|v| self.now().checked_add_signed(v))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC);
+        let mut rx = self.now.subscribe();
+        Box::pin(async move {
+            while *rx.borrow_and_update() < deadline {
+                if rx.changed().await.is_err() {
+                    // the clock is dropped, time never comes
+                    std::future::pending::<()>().await;
+                }
+            }
+        })
+    }
+}
+
+#[tokio::test]
+async fn test_mock_clock() {
+    use chrono::TimeZone;
+
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap());
+    let sleep = clock.sleep(Duration::from_secs(60));
+    let handle = tokio::spawn(sleep);
+
+    clock.advance(Duration::from_secs(59));
+    tokio::task::yield_now().await;
+    assert!(!handle.is_finished());
+
+    clock.advance(Duration::from_secs(1));
+    handle.await.unwrap();
+    assert_eq!(
+        clock.now(),
+        Utc.with_ymd_and_hms(2026, 1, 1, 0, 1, 0).unwrap()
+    );
+
+    // the deadline already passed
+    clock
+        .sleep_until(Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap())
+        .await;
+}
@@ -1,20 +1,29 @@
 use std::{ffi::OsStr, process::Output, time::Duration};
 
-use anyhow::{anyhow, Result};
-use bytes::BufMut;
+use anyhow::{Result, anyhow};
 
 use tokio::{
     io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt},
     process::Command,
-    sync::mpsc::{Receiver, UnboundedSender},
+    sync::{
+        mpsc::{Receiver, UnboundedSender},
+        oneshot,
+    },
 };
 use tracing::{error, info};
 
+use super::{
+    clock::{SharedClock, system_clock},
+    output::OutputBuffer,
+    types::{OutputLimit, TIMEOUT_SIGKILL, TIMEOUT_SIGTERM},
+};
+
+/// every line is sent to tx, the buffer only keeps what the limit allows
 async fn read_to_end<A: AsyncRead + Unpin>(
     io: &mut Option<A>,
     tx: UnboundedSender<String>,
-) -> std::io::Result<Vec<u8>> {
-    let mut vec = Vec::new();
+    mut buf: OutputBuffer,
+) -> std::io::Result<OutputBuffer> {
     if let Some(io) = io.as_mut() {
         let mut reader = tokio::io::BufReader::new(io);
         loop {
@@ -29,17 +38,23 @@ async fn read_to_end<A: AsyncRead + Unpin>(
                 error!("failed send job lot - {e}");
             }
 
-            vec.put(line.as_bytes());
+            buf.push(line.as_bytes());
         }
     }
 
-    std::result::Result::Ok(vec)
+    std::result::Result::Ok(buf)
 }
 
 pub struct Cmd<'a> {
     inner: Command,
     timeout: Option<Duration>,
+    grace_period: Duration,
+    /// the signal the process group got on timeout
+    timeout_signal: Option<&'static str>,
+    output_limit: Option<OutputLimit>,
+    output_truncated: bool,
     read_code_from_stdin: (bool, &'a str),
+    clock: SharedClock,
 }
 
 impl<'a> Cmd<'a> {
@@ -48,6 +63,11 @@ impl<'a> Cmd<'a> {
             inner: Command::new(program),
             read_code_from_stdin: (false, ""),
             timeout: None,
+            grace_period: Duration::ZERO,
+            timeout_signal: None,
+            output_limit: None,
+            output_truncated: false,
+            clock: system_clock(),
         }
     }
 
@@ -60,11 +80,41 @@ impl<'a> Cmd<'a> {
         self
     }
 
+    /// the timeout and the grace period are measured on it
+    pub fn clock(&mut self, clock: SharedClock) -> &mut Self {
+        self.clock = clock;
+        self
+    }
+
     pub fn timeout(&mut self, timeout: u64) -> &mut Self {
         self.timeout = Some(Duration::from_secs(timeout));
         self
     }
 
+    /// SIGTERM the process group on timeout and SIGKILL it if it is still running
+    /// after grace_period seconds
+    pub fn grace_period(&mut self, grace_period: u64) -> &mut Self {
+        self.grace_period = Duration::from_secs(grace_period);
+        self
+    }
+
+    /// sigterm if the process exited within the grace period after the timeout,
+    /// sigkill if it was killed, none if it did not time out
+    pub fn timeout_signal(&self) -> Option<&'static str> {
+        self.timeout_signal
+    }
+
+    /// the head and the tail of stdout and stderr beyond the limit are kept in the output
+    pub fn output_limit(&mut self, limit: Option<OutputLimit>) -> &mut Self {
+        self.output_limit = limit;
+        self
+    }
+
+    /// true if stdout or stderr was cut to the output limit
+    pub fn output_truncated(&self) -> bool {
+        self.output_truncated
+    }
+
     #[cfg(unix)]
     pub fn work_user(&mut self, user: &str) -> Result<&mut Self> {
         let u = users::get_user_by_name(user).ok_or(anyhow!("invalid system user {user}"))?;
@@ -87,17 +137,34 @@ impl<'a> Cmd<'a> {
         Ok(())
     }
 
+    /// the group is gone already if the killed process has no children left
     #[cfg(unix)]
     pub fn killpg(pid: u32) -> Result<()> {
         let pid = nix::unistd::Pid::from_raw(pid as i32);
-        nix::sys::signal::killpg(pid, nix::sys::signal::SIGKILL)?;
-        Ok(())
+        match nix::sys::signal::killpg(pid, nix::sys::signal::SIGKILL) {
+            Ok(_) | Err(nix::errno::Errno::ESRCH) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    #[cfg(windows)]
+    pub fn termpg(_pid: u32) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// false if the platform cannot terminate the process gracefully
+    #[cfg(unix)]
+    pub fn termpg(pid: u32) -> Result<bool> {
+        let pid = nix::unistd::Pid::from_raw(pid as i32);
+        nix::sys::signal::killpg(pid, nix::sys::signal::SIGTERM)?;
+        Ok(true)
     }
 
     pub async fn wait_with_output(
         &mut self,
         tx: UnboundedSender<String>,
         mut kill_signal_rx: Receiver<()>,
+        pid_tx: Option<oneshot::Sender<u32>>,
     ) -> Result<Output> {
         // kill process group See https://github.com/rust-lang/rust/issues/115241
         #[cfg(unix)]
@@ -116,22 +183,36 @@ impl<'a> Cmd<'a> {
         let mut stdout_pipe = child.stdout.take();
         let mut stderr_pipe = child.stderr.take();
 
-        let stdout_fut = read_to_end(&mut stdout_pipe, tx.clone());
-        let stderr_fut = read_to_end(&mut stderr_pipe, tx.clone());
+        let limit = self.output_limit.as_ref();
+        let stdout_fut = read_to_end(&mut stdout_pipe, tx.clone(), OutputBuffer::new(limit));
+        let stderr_fut = read_to_end(&mut stderr_pipe, tx.clone(), OutputBuffer::new(limit));
 
-        let sleep = self.timeout.map_or(
-            tokio::time::sleep(Duration::from_secs(86400 * 365 * 10)),
-            |v| tokio::time::sleep(v),
+        let mut sleep = self.clock.sleep(
+            self.timeout
+                .unwrap_or(Duration::from_secs(86400 * 365 * 10)),
         );
-        tokio::pin!(sleep);
 
         let pid = child.id().unwrap();
+        if let Some(pid_tx) = pid_tx {
+            let _ = pid_tx.send(pid);
+        }
         tokio::select! {
             _ = &mut sleep =>  {
-                info!("timeout kill");
-                child.kill().await?;
-                Self::killpg(pid)?;
-
+                let terminated = !self.grace_period.is_zero()
+                    && Self::termpg(pid)?
+                    && tokio::select! {
+                        _ = child.wait() => true,
+                        _ = self.clock.sleep(self.grace_period) => false,
+                    };
+                if terminated {
+                    info!("timeout terminate");
+                    self.timeout_signal = Some(TIMEOUT_SIGTERM);
+                } else {
+                    info!("timeout kill");
+                    child.kill().await?;
+                    Self::killpg(pid)?;
+                    self.timeout_signal = Some(TIMEOUT_SIGKILL);
+                }
             },
             _ = kill_signal_rx.recv() => {
                 info!("manual kill");
@@ -151,10 +232,42 @@ impl<'a> Cmd<'a> {
         drop(stdout_pipe);
         drop(stderr_pipe);
 
+        self.output_truncated = stdout.is_truncated() || stderr.is_truncated();
         Ok(Output {
             status,
-            stderr,
-            stdout,
+            stderr: stderr.into_bytes(),
+            stdout: stdout.into_bytes(),
         })
     }
 }
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_timeout_on_clock() {
+    use std::sync::Arc;
+
+    use chrono::Utc;
+    use tokio::sync::mpsc::{channel, unbounded_channel};
+
+    use super::clock::MockClock;
+
+    let clock = MockClock::new(Utc::now());
+    let (tx, _rx) = unbounded_channel();
+    let (_kill_tx, kill_rx) = channel(1);
+    let (pid_tx, pid_rx) = oneshot::channel();
+
+    let mut cmd = Cmd::new("sleep");
+    cmd.get_ref().arg("30");
+    cmd.clock(Arc::new(clock.clone())).timeout(3600);
+    let handle = tokio::spawn(async move {
+        cmd.wait_with_output(tx, kill_rx, Some(pid_tx))
+            .await
+            .unwrap();
+        cmd.timeout_signal()
+    });
+
+    // the hour passes at once, the process is killed without waiting for it
+    pid_rx.await.unwrap();
+    clock.advance(Duration::from_secs(3600));
+    assert_eq!(handle.await.unwrap(), Some(TIMEOUT_SIGKILL));
+}
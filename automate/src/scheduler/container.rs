@@ -0,0 +1,232 @@
+use std::{
+    collections::HashMap,
+    env,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use anyhow::{Result, anyhow};
+use nanoid::nanoid;
+use tokio::process::Command;
+use tracing::{error, info};
+
+use super::types::{BaseJob, ContainerOption};
+
+const RUNTIMES: [&str; 2] = ["docker", "podman"];
+
+static DETECTED_RUNTIMES: OnceLock<Vec<String>> = OnceLock::new();
+
+fn find_in_path(program: &str) -> Option<PathBuf> {
+    let paths = env::var_os("PATH")?;
+    let filename = if cfg!(windows) {
+        format!("{program}.exe")
+    } else {
+        program.to_string()
+    };
+    env::split_paths(&paths)
+        .map(|dir| dir.join(&filename))
+        .find(|v| v.is_file())
+}
+
+/// detect available container runtimes on this agent, the result is cached
+pub fn detect_runtimes() -> Vec<String> {
+    DETECTED_RUNTIMES
+        .get_or_init(|| {
+            let list: Vec<String> = RUNTIMES
+                .iter()
+                .filter(|v| find_in_path(v).is_some())
+                .map(|v| v.to_string())
+                .collect();
+            info!("detected container runtimes: {:?}", list);
+            list
+        })
+        .clone()
+}
+
+/// pick the runtime to use, the preferred runtime must be installed on this agent
+pub fn resolve_runtime(preferred: Option<&str>) -> Result<String> {
+    let runtimes = detect_runtimes();
+    match preferred.filter(|v| !v.is_empty()) {
+        Some(v) => {
+            if !RUNTIMES.contains(&v) {
+                anyhow::bail!("unsupported container runtime {v}");
+            }
+            runtimes
+                .into_iter()
+                .find(|r| r == v)
+                .ok_or(anyhow!("container runtime {v} is not installed"))
+        }
+        None => runtimes.into_iter().next().ok_or(anyhow!(
+            "no container runtime found, please install docker or podman"
+        )),
+    }
+}
+
+/// the arguments of `<runtime> run`, the work_dir is mounted into the container at the same path
+fn run_args(
+    job: &BaseJob,
+    opt: &ContainerOption,
+    container_name: &str,
+    cmd_name: String,
+    cmd_args: Vec<String>,
+    env: &HashMap<String, String>,
+    interactive: bool,
+) -> Result<Vec<String>> {
+    let mut args = vec![
+        "run".to_string(),
+        "--rm".to_string(),
+        "--name".to_string(),
+        container_name.to_string(),
+    ];
+
+    if interactive {
+        args.push("-i".to_string());
+    }
+
+    if let Some(ref work_dir) = job.work_dir {
+        let dir = Path::new(work_dir);
+        if !dir.is_absolute() {
+            anyhow::bail!("work_dir must be an absolute path in container mode");
+        }
+        args.extend(["-v".to_string(), format!("{work_dir}:{work_dir}")]);
+        args.extend(["-w".to_string(), work_dir.to_owned()]);
+    }
+
+    if let Some(ref work_user) = job.work_user {
+        args.extend(["--user".to_string(), work_user.to_owned()]);
+    }
+
+    if let Some(cpus) = opt.cpus.as_ref().filter(|v| !v.is_empty()) {
+        args.extend(["--cpus".to_string(), cpus.to_owned()]);
+    }
+
+    if let Some(memory) = opt.memory.as_ref().filter(|v| !v.is_empty()) {
+        args.extend(["--memory".to_string(), memory.to_owned()]);
+    }
+
+    if let Some(network) = opt.network.as_ref().filter(|v| !v.is_empty()) {
+        args.extend(["--network".to_string(), network.to_owned()]);
+    }
+
+    for (k, v) in env.iter() {
+        args.extend(["-e".to_string(), format!("{k}={v}")]);
+    }
+
+    args.extend(opt.extra_args.iter().cloned());
+    args.push(opt.image.clone());
+    args.push(cmd_name);
+    args.extend(cmd_args);
+    Ok(args)
+}
+
+pub struct ContainerCmd {
+    pub runtime: String,
+    pub container_name: String,
+    pub args: Vec<String>,
+}
+
+impl ContainerCmd {
+    /// wrap the job command into `<runtime> run` of an installed runtime
+    pub fn build(
+        job: &BaseJob,
+        opt: &ContainerOption,
+        cmd_name: String,
+        cmd_args: Vec<String>,
+        env: &HashMap<String, String>,
+        interactive: bool,
+    ) -> Result<Self> {
+        if opt.image.is_empty() {
+            anyhow::bail!("container image is empty");
+        }
+        let runtime = resolve_runtime(opt.runtime.as_deref())?;
+        let container_name = format!("jiascheduler-{}-{}", job.eid, nanoid!(6));
+        let args = run_args(
+            job,
+            opt,
+            &container_name,
+            cmd_name,
+            cmd_args,
+            env,
+            interactive,
+        )?;
+
+        Ok(Self {
+            runtime,
+            container_name,
+            args,
+        })
+    }
+
+    /// make sure the container is gone, killing the runtime client does not always stop it
+    pub async fn cleanup(&self) {
+        match Command::new(&self.runtime)
+            .args(["rm", "-f", &self.container_name])
+            .output()
+            .await
+        {
+            Ok(_) => {}
+            Err(e) => error!("failed remove container {} - {e}", self.container_name),
+        }
+    }
+}
+
+#[test]
+fn test_run_args() {
+    let job = BaseJob {
+        eid: "e1".to_string(),
+        work_dir: Some("/data/job".to_string()),
+        work_user: Some("nobody".to_string()),
+        ..Default::default()
+    };
+    let opt = ContainerOption {
+        image: "alpine:3".to_string(),
+        cpus: Some("0.5".to_string()),
+        memory: Some("".to_string()),
+        extra_args: vec!["--read-only".to_string()],
+        ..Default::default()
+    };
+    let env = HashMap::from([("K".to_string(), "v".to_string())]);
+
+    let args = run_args(
+        &job,
+        &opt,
+        "jiascheduler-e1-abc",
+        "sh".to_string(),
+        vec!["-c".to_string(), "echo 1".to_string()],
+        &env,
+        true,
+    )
+    .unwrap();
+    assert_eq!(
+        args,
+        [
+            "run",
+            "--rm",
+            "--name",
+            "jiascheduler-e1-abc",
+            "-i",
+            "-v",
+            "/data/job:/data/job",
+            "-w",
+            "/data/job",
+            "--user",
+            "nobody",
+            "--cpus",
+            "0.5",
+            "-e",
+            "K=v",
+            "--read-only",
+            "alpine:3",
+            "sh",
+            "-c",
+            "echo 1",
+        ]
+    );
+
+    let job = BaseJob {
+        work_dir: Some("data".to_string()),
+        ..Default::default()
+    };
+    assert!(run_args(&job, &opt, "c", "sh".to_string(), vec![], &env, false).is_err());
+    assert!(resolve_runtime(Some("lxc")).is_err());
+}
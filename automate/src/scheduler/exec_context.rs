@@ -0,0 +1,162 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    process::Stdio,
+    sync::OnceLock,
+    time::Duration,
+};
+
+use crypto::{digest::Digest, sha2::Sha256};
+use moka::future::Cache;
+use tokio::{process::Command, time::timeout};
+
+use super::types::{BaseJob, ContainerOption};
+use crate::bridge::msg::{ExecCommand, ExecContext};
+
+/// the probed versions are kept for a while, the executors are rarely upgraded
+const VERSION_CACHE_TTL: Duration = Duration::from_secs(600);
+const VERSION_PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+const VERSION_MAX_LEN: usize = 200;
+
+static AGENT_VERSION: OnceLock<String> = OnceLock::new();
+static VERSION_CACHE: OnceLock<Cache<String, String>> = OnceLock::new();
+
+pub fn set_agent_version(agent_version: impl Into<String>) {
+    let _ = AGENT_VERSION.set(agent_version.into());
+}
+
+/// what the job runs with on this agent, recorded with the exec history
+pub async fn collect(base_job: &BaseJob) -> ExecContext {
+    let mut commands = Vec::new();
+    if base_job.http_check.is_none() {
+        match &base_job.bundle_script {
+            Some(list) => {
+                for v in list {
+                    push_command(&mut commands, &v.cmd_name, v.container.as_ref()).await;
+                }
+            }
+            None => {
+                push_command(
+                    &mut commands,
+                    &base_job.cmd_name,
+                    base_job.container.as_ref(),
+                )
+                .await
+            }
+        }
+    }
+
+    ExecContext {
+        agent_version: AGENT_VERSION.get().cloned().unwrap_or_default(),
+        os: sysinfo::System::name().unwrap_or_default(),
+        os_version: sysinfo::System::os_version().unwrap_or_default(),
+        kernel_version: sysinfo::System::kernel_version().unwrap_or_default(),
+        arch: std::env::consts::ARCH.to_string(),
+        commands,
+        env_hash: env_hash(
+            std::env::vars_os().map(|(k, v)| {
+                (
+                    k.to_string_lossy().into_owned(),
+                    v.to_string_lossy().into_owned(),
+                )
+            }),
+            &base_job.env,
+        ),
+    }
+}
+
+/// the commands run in a container are not probed, the image is recorded instead
+async fn push_command(
+    commands: &mut Vec<ExecCommand>,
+    cmd_name: &str,
+    container: Option<&ContainerOption>,
+) {
+    if cmd_name.is_empty()
+        || commands
+            .iter()
+            .any(|v| v.cmd_name == cmd_name && v.image == container.map(|c| c.image.clone()))
+    {
+        return;
+    }
+    let (version, image) = match container {
+        Some(c) => (String::new(), Some(c.image.clone())),
+        None => (probe_version(cmd_name).await, None),
+    };
+    commands.push(ExecCommand {
+        cmd_name: cmd_name.to_string(),
+        version,
+        image,
+    });
+}
+
+/// the first line printed by `cmd_name --version`, empty if it cannot be probed
+async fn probe_version(cmd_name: &str) -> String {
+    let cache = VERSION_CACHE.get_or_init(|| {
+        Cache::builder()
+            .max_capacity(100)
+            .time_to_live(VERSION_CACHE_TTL)
+            .build()
+    });
+    let cmd_name = cmd_name.to_string();
+    cache
+        .get_with(cmd_name.clone(), async move {
+            let output = Command::new(&cmd_name)
+                .arg("--version")
+                .stdin(Stdio::null())
+                .kill_on_drop(true)
+                .output();
+            let Ok(Ok(output)) = timeout(VERSION_PROBE_TIMEOUT, output).await else {
+                return String::new();
+            };
+            // some interpreters print the version to stderr
+            [output.stdout, output.stderr]
+                .iter()
+                .filter_map(|v| {
+                    String::from_utf8_lossy(v)
+                        .lines()
+                        .map(str::trim)
+                        .find(|v| !v.is_empty())
+                        .map(|v| v.chars().take(VERSION_MAX_LEN).collect::<String>())
+                })
+                .next()
+                .unwrap_or_default()
+        })
+        .await
+}
+
+/// sha256 of the environment variables the process is started with, sorted by name
+fn env_hash(
+    vars: impl Iterator<Item = (String, String)>,
+    job_env: &HashMap<String, String>,
+) -> String {
+    let mut env: BTreeMap<String, String> = vars.collect();
+    env.extend(job_env.iter().map(|(k, v)| (k.clone(), v.clone())));
+
+    let mut hasher = Sha256::new();
+    for (k, v) in env {
+        hasher.input_str(&k);
+        hasher.input_str("=");
+        hasher.input_str(&v);
+        hasher.input_str("\n");
+    }
+    hasher.result_str()
+}
+
+#[test]
+fn test_env_hash() {
+    let vars = || {
+        vec![
+            ("PATH".to_string(), "/usr/bin".to_string()),
+            ("LANG".to_string(), "C".to_string()),
+        ]
+        .into_iter()
+    };
+    let hash = env_hash(vars(), &HashMap::new());
+    assert_eq!(hash, env_hash(vars().rev(), &HashMap::new()));
+    assert_ne!(
+        hash,
+        env_hash(
+            vars(),
+            &HashMap::from([("LANG".to_string(), "en_US.UTF-8".to_string())])
+        )
+    );
+}
@@ -1,22 +1,34 @@
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use file_rotate::{FileRotate, compression::Compression, suffix::AppendCount};
+use futures::{StreamExt, stream};
 
 use std::io::Write;
 
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::{
     collections::HashMap,
     process::{Output, Stdio},
 };
 use tokio::sync::mpsc::Receiver;
 
-use tokio::sync::{Mutex, mpsc};
+use tokio::sync::{Mutex, mpsc, oneshot};
 use tracing::error;
 
+use crate::scheduler::clock::{SharedClock, system_clock};
 use crate::scheduler::cmd::Cmd;
+use crate::scheduler::container::ContainerCmd;
+use crate::scheduler::http_check;
+use crate::scheduler::output;
 
-use super::types::{BaseJob, BundleOutput};
+use super::types::{
+    BaseJob, BundleOption, BundleOutput, BundleScriptOutput, ContainerOption, TIMEOUT_CLEANUP,
+    TIMEOUT_CLEANUP_FAILED, TIMEOUT_NOTIFY,
+};
+
+/// the cleanup script of a timed out job is killed after so many seconds
+const CLEANUP_TIMEOUT_SECS: u64 = 60;
 
 #[derive(Default)]
 pub struct ExecutorBuilder {
@@ -24,6 +36,7 @@ pub struct ExecutorBuilder {
     output_dir: String,
     disable_log: bool,
     pub env: HashMap<String, String>,
+    clock: Option<SharedClock>,
 }
 
 #[allow(unused)]
@@ -55,18 +68,33 @@ impl ExecutorBuilder {
         self
     }
 
+    /// the timeouts are measured on it, the system clock by default
+    pub fn clock(mut self, clock: SharedClock) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
     pub fn build(self) -> Executor {
+        let mut env = self.job.env.clone();
+        env.extend(self.env);
         Executor {
             job: self.job,
             output_dir: self.output_dir,
-            env: self.env,
+            env,
             disable_log: self.disable_log,
+            clock: self.clock.unwrap_or_else(system_clock),
+            timeout_actions: std::sync::Mutex::new(vec![]),
+            output_truncated: AtomicBool::new(false),
+            output_url: std::sync::Mutex::new(None),
+            spool_id: nanoid::nanoid!(),
         }
     }
 }
 
 pub struct Ctx {
     pub kill_signal_rx: Receiver<()>,
+    /// receives the pid of the process once spawned, not sent by http and bundle jobs
+    pub pid_tx: Option<oneshot::Sender<u32>>,
 }
 
 pub struct Executor {
@@ -74,6 +102,12 @@ pub struct Executor {
     output_dir: String,
     disable_log: bool,
     env: HashMap<String, String>,
+    clock: SharedClock,
+    timeout_actions: std::sync::Mutex<Vec<&'static str>>,
+    output_truncated: AtomicBool,
+    output_url: std::sync::Mutex<Option<String>>,
+    /// names the file the full output is written to until it is uploaded
+    spool_id: String,
 }
 
 impl Executor {
@@ -85,7 +119,138 @@ impl Executor {
         PathBuf::from(&self.output_dir).join(format!("{}.log", self.job.eid))
     }
 
-    pub async fn run(&self, mut ctx: Ctx) -> Result<BundleOutput> {
+    /// the actions fired since the job timed out in the order they fired, eg: sigterm,cleanup,
+    /// none if the job did not time out
+    pub fn timeout_actions(&self) -> Option<String> {
+        let actions = self.timeout_actions.lock().unwrap();
+        let mut actions: Vec<&str> = actions.to_vec();
+        // each script of a bundle may time out
+        actions.dedup();
+        (!actions.is_empty()).then(|| actions.join(","))
+    }
+
+    /// true if the stdout or the stderr of the job, or of a script of the bundle, was cut
+    /// to the output limit
+    pub fn output_truncated(&self) -> bool {
+        self.output_truncated.load(Ordering::SeqCst)
+    }
+
+    /// where the full output of the truncated run was uploaded
+    pub fn output_url(&self) -> Option<String> {
+        self.output_url.lock().unwrap().clone()
+    }
+
+    /// the full output is written here while the job runs if it may be uploaded
+    fn get_spool_file_path(&self) -> Option<PathBuf> {
+        self.job
+            .output_limit
+            .as_ref()
+            .and_then(|v| v.upload_url.as_ref())
+            .filter(|v| !v.trim().is_empty())
+            .map(|_| {
+                PathBuf::from(&self.output_dir)
+                    .join(format!("{}.{}.output", self.job.eid, self.spool_id))
+            })
+    }
+
+    /// upload the full output of the truncated run, the spooled file is always removed
+    async fn upload_output(&self) {
+        let Some(path) = self.get_spool_file_path() else {
+            return;
+        };
+        if self.output_truncated() {
+            let upload_url = self
+                .job
+                .output_limit
+                .as_ref()
+                .and_then(|v| v.upload_url.clone())
+                .unwrap_or_default();
+            match output::upload(&path, &upload_url).await {
+                Ok(url) => *self.output_url.lock().unwrap() = Some(url),
+                Err(e) => error!("failed to upload output of {} - {e}", self.job.eid),
+            }
+        }
+        if let Err(e) = tokio::fs::remove_file(&path).await {
+            error!("failed to remove spooled output {} - {e}", path.display());
+        }
+    }
+
+    fn push_timeout_action(&self, action: &'static str) {
+        self.timeout_actions.lock().unwrap().push(action);
+    }
+
+    /// run the cleanup script and mark the notification once the job timed out
+    async fn escalate_timeout(&self) {
+        if self.timeout_actions.lock().unwrap().is_empty() {
+            return;
+        }
+        let Some(ref timeout_action) = self.job.timeout_action else {
+            return;
+        };
+
+        if let Some(script) = timeout_action
+            .cleanup_script
+            .as_ref()
+            .filter(|v| !v.trim().is_empty())
+        {
+            let (cmd_name, arg) = if cfg!(windows) {
+                ("powershell", "-Command")
+            } else {
+                ("sh", "-c")
+            };
+            let mut cmd = Cmd::new(cmd_name);
+            cmd.clock(self.clock.clone()).timeout(CLEANUP_TIMEOUT_SECS);
+            if let Some(ref work_dir) = self.job.work_dir {
+                cmd.work_dir(work_dir);
+            }
+            cmd.get_ref()
+                .envs(self.env.iter())
+                .args([arg, script.as_str()])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped());
+
+            let (tx, _rx) = mpsc::unbounded_channel::<String>();
+            let (_kill_signal_tx, kill_signal_rx) = mpsc::channel::<()>(1);
+            let ret = cmd.wait_with_output(tx, kill_signal_rx, None).await;
+            match ret {
+                Ok(v) if v.status.success() && cmd.timeout_signal().is_none() => {
+                    self.push_timeout_action(TIMEOUT_CLEANUP)
+                }
+                Ok(v) => {
+                    error!(
+                        "cleanup script of {} failed, {}",
+                        self.job.eid,
+                        String::from_utf8_lossy(&v.stderr)
+                    );
+                    self.push_timeout_action(TIMEOUT_CLEANUP_FAILED)
+                }
+                Err(e) => {
+                    error!("failed to run cleanup script of {} - {e}", self.job.eid);
+                    self.push_timeout_action(TIMEOUT_CLEANUP_FAILED)
+                }
+            }
+        }
+
+        if timeout_action.notify {
+            self.push_timeout_action(TIMEOUT_NOTIFY);
+        }
+    }
+
+    pub async fn run(&self, ctx: Ctx) -> Result<BundleOutput> {
+        let ret = self.run_job(ctx).await;
+        self.escalate_timeout().await;
+        self.upload_output().await;
+        ret
+    }
+
+    async fn run_job(&self, mut ctx: Ctx) -> Result<BundleOutput> {
+        if let Some(ref opt) = self.job.http_check {
+            return tokio::select! {
+                ret = http_check::check(opt, self.job.timeout) => Ok(BundleOutput::Http(ret?)),
+                _ = ctx.kill_signal_rx.recv() => Err(anyhow!("http check was killed")),
+            };
+        }
+
         if self.job.bundle_script.is_none() {
             let output = self
                 .exec(
@@ -93,6 +258,7 @@ impl Executor {
                     self.job.cmd_name.clone(),
                     self.job.args.clone(),
                     self.job.code.clone(),
+                    self.job.container.as_ref(),
                 )
                 .await?;
 
@@ -101,7 +267,6 @@ impl Executor {
 
         let kill_signal_tx: Arc<Mutex<Vec<mpsc::Sender<()>>>> = Arc::new(Mutex::new(vec![]));
         let kill_signal_tx_clone = kill_signal_tx.clone();
-        let mut outputs = HashMap::new();
 
         let handler = tokio::spawn(async move {
             match ctx.kill_signal_rx.recv().await {
@@ -118,21 +283,67 @@ impl Executor {
             };
         });
 
-        for v in self.job.bundle_script.clone().unwrap().clone().into_iter() {
-            let (tx, kill_signal_rx) = mpsc::channel::<()>(1);
-            kill_signal_tx.lock().await.push(tx);
-            let output = self
-                .exec(
-                    Ctx { kill_signal_rx },
-                    v.cmd_name.clone(),
-                    v.args.clone(),
-                    v.code.clone(),
-                )
-                .await?;
-            outputs.insert(v.eid, output);
-        }
+        let bundle_script = self.job.bundle_script.clone().unwrap();
+        let bundle_option = self.job.bundle_option.clone().unwrap_or(BundleOption {
+            continue_on_error: true,
+            ..Default::default()
+        });
+        let parallelism = bundle_option.parallelism(bundle_script.len());
+        let seq = AtomicUsize::new(0);
+        let aborted = AtomicBool::new(false);
+
+        let ret: Vec<Result<Option<BundleScriptOutput>>> = stream::iter(bundle_script)
+            .map(|v| {
+                let kill_signal_tx = kill_signal_tx.clone();
+                let seq = &seq;
+                let aborted = &aborted;
+                let continue_on_error = bundle_option.continue_on_error;
+                async move {
+                    // the scripts behind a failed one are skipped
+                    if aborted.load(Ordering::SeqCst) {
+                        return Ok(None);
+                    }
+                    let (tx, kill_signal_rx) = mpsc::channel::<()>(1);
+                    kill_signal_tx.lock().await.push(tx);
+                    let seq = seq.fetch_add(1, Ordering::SeqCst);
+                    let output = self
+                        .exec(
+                            Ctx {
+                                kill_signal_rx,
+                                pid_tx: None,
+                            },
+                            v.cmd_name.clone(),
+                            v.args.clone(),
+                            v.code.clone(),
+                            v.container.as_ref(),
+                        )
+                        .await;
+
+                    let failed = match output {
+                        Ok(ref v) => !continue_on_error && !v.status.success(),
+                        Err(_) => true,
+                    };
+                    if failed {
+                        aborted.store(true, Ordering::SeqCst);
+                    }
+                    Ok(Some(BundleScriptOutput {
+                        eid: v.eid,
+                        seq,
+                        output: output?,
+                    }))
+                }
+            })
+            .buffer_unordered(parallelism)
+            .collect()
+            .await;
 
         handler.abort();
+
+        let mut outputs = ret
+            .into_iter()
+            .filter_map(|v| v.transpose())
+            .collect::<Result<Vec<BundleScriptOutput>>>()?;
+        outputs.sort_by_key(|v| v.seq);
         return Ok(BundleOutput::Bundle(outputs));
     }
 
@@ -142,32 +353,66 @@ impl Executor {
         cmd_name: String,
         args: Vec<String>,
         code: String,
+        container: Option<&ContainerOption>,
     ) -> Result<Output> {
-        let mut cmd = Cmd::new(cmd_name);
         let mut args = args;
+        if !self.job.read_code_from_stdin {
+            args.push(code.clone());
+        }
+
+        let container_cmd = match container {
+            Some(opt) => Some(ContainerCmd::build(
+                &self.job,
+                opt,
+                cmd_name.clone(),
+                args.clone(),
+                &self.env,
+                self.job.read_code_from_stdin,
+            )?),
+            None => None,
+        };
+
+        let mut cmd = match container_cmd {
+            Some(ref v) => Cmd::new(&v.runtime),
+            None => Cmd::new(cmd_name),
+        };
+
         if self.job.read_code_from_stdin {
             cmd = cmd.read_code_from_stdin(&code);
             cmd.get_ref().stdin(Stdio::piped());
-        } else {
-            args.push(code.clone());
         }
 
-        if let Some(ref work_dir) = self.job.work_dir {
-            cmd.work_dir(work_dir);
-        }
+        cmd.output_limit(self.job.output_limit.clone());
+        cmd.clock(self.clock.clone());
 
-        if let Some(ref work_user) = self.job.work_user {
-            cmd.work_user(work_user)?;
-        }
         if self.job.timeout > 0 {
             cmd.timeout(self.job.timeout);
+            if let Some(ref v) = self.job.timeout_action {
+                cmd.grace_period(v.grace_period);
+            }
         }
 
-        for (key, val) in self.env.iter() {
-            cmd.get_ref().env(key, val);
-        }
+        match container_cmd {
+            Some(ref v) => {
+                // work_dir, work_user and env are passed to the container
+                cmd.get_ref().args(&v.args);
+            }
+            None => {
+                if let Some(ref work_dir) = self.job.work_dir {
+                    cmd.work_dir(work_dir);
+                }
 
-        cmd.get_ref().args(&args);
+                if let Some(ref work_user) = self.job.work_user {
+                    cmd.work_user(work_user)?;
+                }
+
+                for (key, val) in self.env.iter() {
+                    cmd.get_ref().env(key, val);
+                }
+
+                cmd.get_ref().args(&args);
+            }
+        }
 
         let (tx, mut rx) = mpsc::unbounded_channel::<String>();
 
@@ -184,12 +429,33 @@ impl Executor {
             ))
         };
 
-        tokio::spawn(async move {
+        // the scripts of a bundle append to the same spooled file
+        let mut spool_file = match self.get_spool_file_path() {
+            Some(path) => {
+                if let Some(dir) = path.parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+                Some(
+                    std::fs::OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(path)?,
+                )
+            }
+            None => None,
+        };
+
+        let writer = tokio::spawn(async move {
             while let Some(line) = rx.recv().await {
-                if let Some(f) = logfile.as_mut() {
-                    if let Err(e) = write!(f, "{}", line) {
-                        error!("cannot write to log file - {e}");
-                    }
+                if let Some(f) = logfile.as_mut()
+                    && let Err(e) = write!(f, "{}", line)
+                {
+                    error!("cannot write to log file - {e}");
+                }
+                if let Some(f) = spool_file.as_mut()
+                    && let Err(e) = f.write_all(line.as_bytes())
+                {
+                    error!("cannot write to spooled output - {e}");
                 }
             }
         });
@@ -197,9 +463,25 @@ impl Executor {
         cmd.get_ref().stdout(Stdio::piped());
         cmd.get_ref().stderr(Stdio::piped());
 
-        let output = cmd.wait_with_output(tx, ctx.kill_signal_rx).await?;
+        let output = cmd
+            .wait_with_output(tx, ctx.kill_signal_rx, ctx.pid_tx)
+            .await;
+        // the full output is spooled before it is uploaded
+        let _ = writer.await;
+        if let Some(v) = cmd.timeout_signal() {
+            self.push_timeout_action(v);
+        }
+        if cmd.output_truncated() {
+            self.output_truncated.store(true, Ordering::SeqCst);
+        }
+
+        if let Some(ref v) = container_cmd
+            && !output.as_ref().is_ok_and(|v| v.status.success())
+        {
+            v.cleanup().await;
+        }
 
-        Ok(output)
+        output
     }
 }
 
@@ -229,6 +511,14 @@ async fn test_command_exec() {
             work_user: None,
             max_retry: None,
             max_parallel: None,
+            container: None,
+            http_check: None,
+            env: HashMap::new(),
+            bundle_option: None,
+            supervisor_option: None,
+            singleton: false,
+            timeout_action: None,
+            output_limit: None,
         })
         .build();
 
@@ -239,10 +529,65 @@ async fn test_command_exec() {
         kill_signal_tx.send(()).await.unwrap();
         info!("end manual kill");
     });
-    let output = c.run(Ctx { kill_signal_rx }).await.unwrap();
+    let output = c
+        .run(Ctx {
+            kill_signal_rx,
+            pid_tx: None,
+        })
+        .await
+        .unwrap();
 
     println!("stdout: {:?}", output.get_stdout());
     println!("stderr: {:?}", output.get_stderr());
     println!("exit_status: {:?}", output.get_exit_status());
     println!("exit_code: {:?}", output.get_exit_code())
 }
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_timeout_action() {
+    use super::types::{TIMEOUT_SIGKILL, TIMEOUT_SIGTERM, TimeoutAction};
+
+    let run = |code: &str, grace_period: u64| {
+        let c = Executor::builder()
+            .job(BaseJob {
+                eid: "timeout-action".to_string(),
+                cmd_name: "sh".to_string(),
+                code: code.to_string(),
+                args: vec!["-c".to_string()],
+                timeout: 1,
+                timeout_action: Some(TimeoutAction {
+                    grace_period,
+                    cleanup_script: Some("true".to_string()),
+                    notify: true,
+                }),
+                ..Default::default()
+            })
+            .disable_write_log(true)
+            .build();
+        async move {
+            let (_kill_signal_tx, kill_signal_rx) = mpsc::channel::<()>(1);
+            c.run(Ctx {
+                kill_signal_rx,
+                pid_tx: None,
+            })
+            .await
+            .unwrap();
+            c.timeout_actions()
+        }
+    };
+
+    assert_eq!(
+        run("trap 'exit 0' TERM; sleep 5 & wait", 3).await,
+        Some(format!(
+            "{TIMEOUT_SIGTERM},{TIMEOUT_CLEANUP},{TIMEOUT_NOTIFY}"
+        ))
+    );
+    assert_eq!(
+        run("sleep 5", 0).await,
+        Some(format!(
+            "{TIMEOUT_SIGKILL},{TIMEOUT_CLEANUP},{TIMEOUT_NOTIFY}"
+        ))
+    );
+    assert_eq!(run("true", 3).await, None);
+}
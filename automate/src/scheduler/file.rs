@@ -1,6 +1,13 @@
-// use crate::get_http_client;
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
 
 use super::types::UploadFile;
+use crate::{
+    artifact::{ArtifactStore, CHUNK_SIZE},
+    get_comet_secret, get_http_client, get_mac_address,
+};
 use anyhow::Result;
 use tokio::{
     fs::{create_dir_all, File},
@@ -8,30 +15,77 @@ use tokio::{
 };
 
 const UPLOAD_DIR: &str = "/tmp/jiascheduler-agent";
+/// the pulled artifacts are kept by hash, the same file is not transferred again
+const ARTIFACT_DIR: &str = "/tmp/jiascheduler-agent/artifacts";
+
+static ARTIFACT_CACHE_SIZE: AtomicU64 = AtomicU64::new(1024 * 1024 * 1024);
+
+/// the least recently used artifacts are evicted beyond this size
+pub fn set_artifact_cache_size(size: u64) {
+    ARTIFACT_CACHE_SIZE.store(size, Ordering::Relaxed);
+}
+
+fn artifact_store() -> ArtifactStore {
+    ArtifactStore::new(ARTIFACT_DIR).with_max_size(ARTIFACT_CACHE_SIZE.load(Ordering::Relaxed))
+}
 
-pub async fn try_download_file(_host: String, file: Option<UploadFile>) -> Result<()> {
+pub async fn try_download_file(host: String, file: Option<UploadFile>) -> Result<()> {
     let file = match file {
         Some(v) => v,
         None => return Ok(()),
     };
 
-    let data = if let Some(data) = file.data {
-        data
-    } else {
-        return Ok(());
-    };
-
-    // let client = get_http_client();
-    // let data = client
-    //     .get(format!("http://{}/file/get/{}", host, file.filename))
-    //     .send()
-    //     .await?
-    //     .bytes()
-    //     .await?;
-
     create_dir_all(UPLOAD_DIR).await?;
     let target_file = format!("{}/{}", UPLOAD_DIR, file.filename);
+
+    let data = match (file.data, file.sha256) {
+        // the comets before the artifact protocol send the content inline
+        (Some(data), Some(sha256)) => {
+            let store = artifact_store();
+            store.save(&sha256, &data).await?;
+            store.evict(&sha256).await?;
+            data
+        }
+        (Some(data), None) => data,
+        (None, Some(sha256)) => {
+            let path = pull_artifact(&host, &sha256, file.size).await?;
+            tokio::fs::copy(path, target_file).await?;
+            return Ok(());
+        }
+        (None, None) => return Ok(()),
+    };
+
     let mut tmp_file = File::create(target_file.clone()).await?;
     tmp_file.write_all(&data).await?;
     Ok(())
 }
+
+async fn pull_artifact(host: &str, sha256: &str, size: u64) -> Result<std::path::PathBuf> {
+    let url = format!(
+        "{}/artifact/{sha256}/chunk",
+        host.replacen("ws", "http", 1).trim_end_matches('/')
+    );
+    let secret = get_comet_secret().unwrap_or_default();
+    let mac_addr = get_mac_address()?;
+    let client = get_http_client();
+
+    artifact_store()
+        .pull(sha256, size, |offset, limit| {
+            let req = client
+                .get(&url)
+                .query(&[("offset", offset), ("limit", limit.min(CHUNK_SIZE))])
+                .bearer_auth(&secret)
+                .header("X-Mac-Address", &mac_addr)
+                .timeout(Duration::from_secs(60));
+            async move {
+                Ok(req
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .bytes()
+                    .await?
+                    .to_vec())
+            }
+        })
+        .await
+}
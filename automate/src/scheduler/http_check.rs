@@ -0,0 +1,177 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use reqwest::{
+    Method,
+    header::{HeaderMap, HeaderName, HeaderValue},
+};
+use serde_json::Value;
+
+use crate::get_http_client;
+
+use super::types::{HttpAssertion, HttpCheck, HttpCheckOutput};
+
+/// max length of the response body kept in the output
+const MAX_BODY_LEN: usize = 64 * 1024;
+
+fn build_headers(opt: &HttpCheck) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    for (k, v) in opt.headers.iter() {
+        headers.insert(
+            HeaderName::from_bytes(k.as_bytes())?,
+            HeaderValue::from_str(v)?,
+        );
+    }
+    Ok(headers)
+}
+
+fn check_assertion(
+    assertion: &HttpAssertion,
+    headers: &HeaderMap,
+    body: &str,
+) -> std::result::Result<(), String> {
+    match assertion.kind.as_str() {
+        "body_contains" => {
+            if !body.contains(&assertion.value) {
+                return Err(format!("body does not contain {}", assertion.value));
+            }
+        }
+        "body_not_contains" => {
+            if body.contains(&assertion.value) {
+                return Err(format!("body contains {}", assertion.value));
+            }
+        }
+        "header_equals" => {
+            let val = headers
+                .get(&assertion.target)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default();
+            if val != assertion.value {
+                return Err(format!(
+                    "header {} expect {}, got {}",
+                    assertion.target, assertion.value, val
+                ));
+            }
+        }
+        "json_equals" => {
+            let json: Value = serde_json::from_str(body)
+                .map_err(|e| format!("response is not valid json - {e}"))?;
+            let val = match json.pointer(&assertion.target) {
+                Some(Value::String(v)) => v.to_owned(),
+                Some(v) => v.to_string(),
+                None => return Err(format!("cannot found {} in response", assertion.target)),
+            };
+            if val != assertion.value {
+                return Err(format!(
+                    "{} expect {}, got {}",
+                    assertion.target, assertion.value, val
+                ));
+            }
+        }
+        v => return Err(format!("unsupported assertion {v}")),
+    }
+    Ok(())
+}
+
+/// perform the request and verify status, latency and assertions
+pub async fn check(opt: &HttpCheck, timeout: u64) -> Result<HttpCheckOutput> {
+    let method = Method::from_bytes(opt.method.to_uppercase().as_bytes())?;
+    let mut req = get_http_client()
+        .request(method, &opt.url)
+        .headers(build_headers(opt)?);
+
+    if let Some(ref body) = opt.body {
+        req = req.body(body.to_owned());
+    }
+    if timeout > 0 {
+        req = req.timeout(Duration::from_secs(timeout));
+    }
+
+    let mut output = HttpCheckOutput::default();
+    let start = Instant::now();
+
+    let response = match req.send().await {
+        Ok(v) => v,
+        Err(e) => {
+            output.latency = start.elapsed().as_millis() as u64;
+            output.failures.push(format!("request failed - {e}"));
+            return Ok(output);
+        }
+    };
+
+    let status = response.status().as_u16();
+    let headers = response.headers().clone();
+    let body = match response.text().await {
+        Ok(v) => v,
+        Err(e) => {
+            output
+                .failures
+                .push(format!("failed read response body - {e}"));
+            String::new()
+        }
+    };
+    output.latency = start.elapsed().as_millis() as u64;
+    output.status = Some(status);
+
+    let status_ok = if opt.expected_status.is_empty() {
+        (200..300).contains(&status)
+    } else {
+        opt.expected_status.contains(&status)
+    };
+    if !status_ok {
+        output
+            .failures
+            .push(format!("unexpected http status {status}"));
+    }
+
+    if let Some(max_latency) = opt.max_latency
+        && output.latency > max_latency
+    {
+        output.failures.push(format!(
+            "latency {}ms exceeds {}ms",
+            output.latency, max_latency
+        ));
+    }
+
+    for assertion in opt.assertions.iter() {
+        if let Err(e) = check_assertion(assertion, &headers, &body) {
+            output.failures.push(e);
+        }
+    }
+
+    output.body = if body.len() > MAX_BODY_LEN {
+        let mut end = MAX_BODY_LEN;
+        while !body.is_char_boundary(end) {
+            end -= 1;
+        }
+        body[..end].to_string()
+    } else {
+        body
+    };
+
+    Ok(output)
+}
+
+#[test]
+fn test_check_assertion() {
+    let headers = HeaderMap::new();
+    let body = r#"{"data":{"status":"ok","count":3}}"#;
+    let assert = |kind: &str, target: &str, value: &str| {
+        check_assertion(
+            &HttpAssertion {
+                kind: kind.to_string(),
+                target: target.to_string(),
+                value: value.to_string(),
+            },
+            &headers,
+            body,
+        )
+    };
+
+    assert!(assert("body_contains", "", "status").is_ok());
+    assert!(assert("body_not_contains", "", "error").is_ok());
+    assert!(assert("json_equals", "/data/status", "ok").is_ok());
+    assert!(assert("json_equals", "/data/count", "3").is_ok());
+    assert!(assert("json_equals", "/data/count", "4").is_err());
+    assert!(assert("header_equals", "x-version", "1").is_err());
+}
@@ -0,0 +1,111 @@
+use std::{collections::HashMap, sync::Arc};
+
+use chrono::{Duration, Utc};
+use tokio::sync::Mutex;
+
+use crate::bridge::msg::{
+    DispatchAckParams, DispatchAckStatus, DispatchJobParams, ReconcileDispatchParams,
+};
+
+/// dispatches are remembered for this long, retries after it are executed again
+const LEDGER_TTL: Duration = Duration::hours(6);
+
+/// acks of the dispatches received by the agent, keyed by dispatch id,
+/// a dispatch which has been acked is not executed again when comet retries it
+#[derive(Clone, Default)]
+pub struct DispatchLedger {
+    records: Arc<Mutex<HashMap<String, DispatchAckParams>>>,
+}
+
+impl DispatchLedger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// record the dispatch as acked, return the previous ack if the dispatch has been accepted before
+    pub async fn accept(&self, params: &DispatchJobParams) -> Option<DispatchAckParams> {
+        let mut records = self.records.lock().await;
+        let deadline = Utc::now() - LEDGER_TTL;
+        records.retain(|_, v| v.time > deadline);
+
+        if let Some(v) = records.get(&params.dispatch_id).filter(|v| v.is_ack()) {
+            return Some(v.clone());
+        }
+        records.insert(
+            params.dispatch_id.clone(),
+            DispatchAckParams::new(params, DispatchAckStatus::Ack),
+        );
+        None
+    }
+
+    /// mark the dispatch as rejected so that a retry is executed
+    pub async fn reject(&self, params: &DispatchJobParams, reason: String) -> DispatchAckParams {
+        let ack = DispatchAckParams {
+            reason: Some(reason),
+            ..DispatchAckParams::new(params, DispatchAckStatus::Nack)
+        };
+        self.records
+            .lock()
+            .await
+            .insert(params.dispatch_id.clone(), ack.clone());
+        ack
+    }
+
+    pub async fn reconcile(&self, params: &ReconcileDispatchParams) -> Vec<DispatchAckParams> {
+        let mut list: Vec<DispatchAckParams> = self
+            .records
+            .lock()
+            .await
+            .values()
+            .filter(|v| params.matches(v))
+            .cloned()
+            .collect();
+        list.sort_by_key(|v| v.time);
+        list
+    }
+}
+
+#[tokio::test]
+async fn test_dispatch_ledger() {
+    use crate::scheduler::types::{BaseJob, JobAction};
+
+    let params = DispatchJobParams {
+        base_job: BaseJob::default(),
+        schedule_id: "s1".to_string(),
+        instance_id: None,
+        run_id: String::new(),
+        fields: None,
+        timer_expr: None,
+        restart_interval: None,
+        is_sync: false,
+        created_user: String::new(),
+        action: JobAction::Exec,
+        blackout_windows: vec![],
+        request_id: String::new(),
+        dispatch_id: "d1".to_string(),
+    };
+    let ledger = DispatchLedger::new();
+
+    assert!(ledger.accept(&params).await.is_none());
+    assert!(ledger.accept(&params).await.is_some_and(|v| v.is_ack()));
+
+    ledger.reject(&params, "busy".to_string()).await;
+    assert!(ledger.accept(&params).await.is_none());
+
+    let list = ledger
+        .reconcile(&ReconcileDispatchParams {
+            schedule_id: "s1".to_string(),
+            dispatch_id: None,
+        })
+        .await;
+    assert_eq!(list.len(), 1);
+    assert!(
+        ledger
+            .reconcile(&ReconcileDispatchParams {
+                schedule_id: "s2".to_string(),
+                dispatch_id: None,
+            })
+            .await
+            .is_empty()
+    );
+}
@@ -0,0 +1,218 @@
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+
+use anyhow::Result;
+use chrono::Utc;
+use poem::{
+    IntoResponse, Response, Route, Server, get, handler, http::StatusCode, listener::TcpListener,
+    web::Json,
+};
+use serde::Serialize;
+use serde_json::json;
+
+/// heartbeat older than this is considered failed
+pub const HEARTBEAT_STALE_SECS: i64 = 180;
+
+/// counters of the agent, exposed by the health server
+pub struct AgentMetrics {
+    pub running_jobs: AtomicI64,
+    pub scheduled_timer_jobs: AtomicI64,
+    pub ws_connected: AtomicBool,
+    pub ws_reconnects: AtomicU64,
+    /// unix timestamp, 0 if never succeeded
+    pub last_heartbeat_success: AtomicI64,
+    pub heartbeat_failures: AtomicU64,
+    pub started_at: AtomicI64,
+    /// job updates waiting in the local spool for comet
+    pub spooled_updates: AtomicI64,
+    /// timer ticks skipped because they were missed, eg: the host was suspended
+    pub misfired_timer_ticks: AtomicU64,
+    /// running-status updates dropped by the throttle in favor of a later one
+    pub coalesced_updates: AtomicU64,
+}
+
+static METRICS: AgentMetrics = AgentMetrics {
+    running_jobs: AtomicI64::new(0),
+    scheduled_timer_jobs: AtomicI64::new(0),
+    ws_connected: AtomicBool::new(false),
+    ws_reconnects: AtomicU64::new(0),
+    last_heartbeat_success: AtomicI64::new(0),
+    heartbeat_failures: AtomicU64::new(0),
+    started_at: AtomicI64::new(0),
+    spooled_updates: AtomicI64::new(0),
+    misfired_timer_ticks: AtomicU64::new(0),
+    coalesced_updates: AtomicU64::new(0),
+};
+
+pub fn metrics() -> &'static AgentMetrics {
+    &METRICS
+}
+
+/// decrease the running jobs when dropped
+pub struct RunningGuard;
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        METRICS.running_jobs.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl AgentMetrics {
+    pub fn track_running(&self) -> RunningGuard {
+        self.running_jobs.fetch_add(1, Ordering::Relaxed);
+        RunningGuard
+    }
+
+    pub fn heartbeat_result(&self, ok: bool) {
+        if ok {
+            self.last_heartbeat_success
+                .store(Utc::now().timestamp(), Ordering::Relaxed);
+            self.heartbeat_failures.store(0, Ordering::Relaxed);
+        } else {
+            self.heartbeat_failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn snapshot(&self) -> AgentHealth {
+        let last_heartbeat_success = self.last_heartbeat_success.load(Ordering::Relaxed);
+        let ws_connected = self.ws_connected.load(Ordering::Relaxed);
+        let heartbeat_ok = last_heartbeat_success > 0
+            && Utc::now().timestamp() - last_heartbeat_success < HEARTBEAT_STALE_SECS;
+
+        AgentHealth {
+            healthy: ws_connected && heartbeat_ok,
+            running_jobs: self.running_jobs.load(Ordering::Relaxed),
+            scheduled_timer_jobs: self.scheduled_timer_jobs.load(Ordering::Relaxed),
+            ws_connected,
+            ws_reconnects: self.ws_reconnects.load(Ordering::Relaxed),
+            last_heartbeat_success,
+            heartbeat_failures: self.heartbeat_failures.load(Ordering::Relaxed),
+            uptime_secs: Utc::now().timestamp() - self.started_at.load(Ordering::Relaxed),
+            spooled_updates: self.spooled_updates.load(Ordering::Relaxed),
+            misfired_timer_ticks: self.misfired_timer_ticks.load(Ordering::Relaxed),
+            coalesced_updates: self.coalesced_updates.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AgentHealth {
+    pub healthy: bool,
+    pub running_jobs: i64,
+    pub scheduled_timer_jobs: i64,
+    pub ws_connected: bool,
+    pub ws_reconnects: u64,
+    pub last_heartbeat_success: i64,
+    pub heartbeat_failures: u64,
+    pub uptime_secs: i64,
+    pub spooled_updates: i64,
+    pub misfired_timer_ticks: u64,
+    pub coalesced_updates: u64,
+}
+
+impl AgentHealth {
+    /// prometheus text exposition format
+    pub fn to_prometheus(&self) -> String {
+        let gauges = [
+            ("running_jobs", "jobs being executed", self.running_jobs),
+            (
+                "scheduled_timer_jobs",
+                "timer jobs waiting in the scheduler",
+                self.scheduled_timer_jobs,
+            ),
+            (
+                "ws_connected",
+                "whether the websocket to comet is connected",
+                self.ws_connected as i64,
+            ),
+            (
+                "last_heartbeat_success_timestamp_seconds",
+                "unix time of the last successful heartbeat",
+                self.last_heartbeat_success,
+            ),
+            (
+                "heartbeat_failures",
+                "consecutive failed heartbeats",
+                self.heartbeat_failures as i64,
+            ),
+            (
+                "uptime_seconds",
+                "seconds since the agent started",
+                self.uptime_secs,
+            ),
+            (
+                "spooled_updates",
+                "job updates waiting in the local spool for comet",
+                self.spooled_updates,
+            ),
+        ];
+
+        let mut out = String::new();
+        for (name, help, value) in gauges {
+            out.push_str(&format!(
+                "# HELP jiascheduler_agent_{name} {help}\n# TYPE jiascheduler_agent_{name} gauge\njiascheduler_agent_{name} {value}\n"
+            ));
+        }
+        out.push_str(&format!(
+            "# HELP jiascheduler_agent_ws_reconnects_total websocket reconnects to comet\n# TYPE jiascheduler_agent_ws_reconnects_total counter\njiascheduler_agent_ws_reconnects_total {}\n",
+            self.ws_reconnects
+        ));
+        out.push_str(&format!(
+            "# HELP jiascheduler_agent_misfired_timer_ticks_total timer ticks skipped because they were missed\n# TYPE jiascheduler_agent_misfired_timer_ticks_total counter\njiascheduler_agent_misfired_timer_ticks_total {}\n",
+            self.misfired_timer_ticks
+        ));
+        out.push_str(&format!(
+            "# HELP jiascheduler_agent_coalesced_updates_total running-status updates coalesced by the throttle\n# TYPE jiascheduler_agent_coalesced_updates_total counter\njiascheduler_agent_coalesced_updates_total {}\n",
+            self.coalesced_updates
+        ));
+        out
+    }
+}
+
+#[handler]
+fn healthz() -> Response {
+    let health = METRICS.snapshot();
+    let status = if health.healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    Json(json!(health)).with_status(status).into_response()
+}
+
+#[handler]
+fn prometheus_metrics() -> Response {
+    Response::builder()
+        .content_type("text/plain; version=0.0.4")
+        .body(METRICS.snapshot().to_prometheus())
+}
+
+/// serve /healthz and /metrics of the agent
+pub async fn serve(bind_addr: String) -> Result<()> {
+    let app = Route::new()
+        .at("/healthz", get(healthz))
+        .at("/metrics", get(prometheus_metrics));
+    Ok(Server::new(TcpListener::bind(bind_addr)).run(app).await?)
+}
+
+#[test]
+fn test_agent_health() {
+    let m = metrics();
+    m.started_at
+        .store(Utc::now().timestamp(), Ordering::Relaxed);
+    assert!(!m.snapshot().healthy);
+
+    m.ws_connected.store(true, Ordering::Relaxed);
+    m.heartbeat_result(true);
+    let guard = m.track_running();
+    let health = m.snapshot();
+    assert!(health.healthy);
+    assert_eq!(health.running_jobs, 1);
+    assert!(
+        health
+            .to_prometheus()
+            .contains("jiascheduler_agent_running_jobs 1\n")
+    );
+
+    drop(guard);
+    assert_eq!(m.snapshot().running_jobs, 0);
+}
@@ -0,0 +1,105 @@
+use std::{collections::VecDeque, path::Path};
+
+use anyhow::Result;
+use tokio::fs;
+
+use crate::get_http_client;
+
+use super::types::OutputLimit;
+
+/// keeps the first and the last bytes of an output beyond the limit, the middle is dropped
+#[derive(Default)]
+pub struct OutputBuffer {
+    head: Vec<u8>,
+    tail: VecDeque<u8>,
+    /// none if the output is unlimited
+    limit: Option<(usize, usize)>,
+    skipped: u64,
+}
+
+impl OutputBuffer {
+    pub fn new(limit: Option<&OutputLimit>) -> Self {
+        Self {
+            limit: limit.filter(|v| v.max_bytes > 0).map(|v| {
+                let tail = v.tail_bytes.min(v.max_bytes);
+                ((v.max_bytes - tail) as usize, tail as usize)
+            }),
+            ..Default::default()
+        }
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        let Some((head_limit, tail_limit)) = self.limit else {
+            self.head.extend_from_slice(data);
+            return;
+        };
+
+        let n = head_limit.saturating_sub(self.head.len()).min(data.len());
+        self.head.extend_from_slice(&data[..n]);
+        self.tail.extend(&data[n..]);
+        if self.tail.len() > tail_limit {
+            let over = self.tail.len() - tail_limit;
+            self.tail.drain(..over);
+            self.skipped += over as u64;
+        }
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        self.skipped > 0
+    }
+
+    /// the head and the tail joined by a marker telling how many bytes were dropped
+    pub fn into_bytes(self) -> Vec<u8> {
+        let mut data = self.head;
+        if self.skipped > 0 {
+            data.extend_from_slice(
+                format!("\n... {} bytes truncated ...\n", self.skipped).as_bytes(),
+            );
+        }
+        data.extend(self.tail);
+        data
+    }
+}
+
+/// PUT the full output to the upload url, eg: a presigned url of an object storage bucket.
+/// returns the url without the query string, which may carry the signature
+pub async fn upload(path: impl AsRef<Path>, upload_url: &str) -> Result<String> {
+    let body = fs::read(path).await?;
+    let resp = get_http_client().put(upload_url).body(body).send().await?;
+    if !resp.status().is_success() {
+        anyhow::bail!("upload output failed, status {}", resp.status());
+    }
+    let mut url = url::Url::parse(upload_url)?;
+    url.set_query(None);
+    Ok(url.to_string())
+}
+
+#[test]
+fn test_output_buffer() {
+    let limit = OutputLimit {
+        max_bytes: 8,
+        tail_bytes: 3,
+        upload_url: None,
+    };
+
+    let mut buf = OutputBuffer::new(Some(&limit));
+    buf.push(b"abc");
+    buf.push(b"defg");
+    assert!(!buf.is_truncated());
+    assert_eq!(buf.into_bytes(), b"abcdefg");
+
+    let mut buf = OutputBuffer::new(Some(&limit));
+    for line in ["line1\n", "line2\n", "line3\n"] {
+        buf.push(line.as_bytes());
+    }
+    assert!(buf.is_truncated());
+    assert_eq!(
+        String::from_utf8(buf.into_bytes()).unwrap(),
+        "line1\n... 10 bytes truncated ...\ne3\n"
+    );
+
+    let mut buf = OutputBuffer::new(None);
+    buf.push(&[b'a'; 1024]);
+    assert!(!buf.is_truncated());
+    assert_eq!(buf.into_bytes().len(), 1024);
+}
@@ -0,0 +1,146 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use tokio::fs;
+use tracing::error;
+
+use crate::bridge::msg::DispatchJobParams;
+
+use super::types::JobAction;
+
+/// file backed copy of the timers and supervisors started by the console,
+/// they are restored when the agent restarts without waiting for the console
+#[derive(Clone)]
+pub struct ScheduleStore {
+    dir: PathBuf,
+}
+
+fn file_name(kind: &str, eid: &str) -> String {
+    let eid: String = eid
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{kind}-{eid}.json")
+}
+
+fn kind_of(action: JobAction) -> Option<&'static str> {
+    match action {
+        JobAction::StartTimer => Some("timer"),
+        JobAction::StartSupervising | JobAction::RestartSupervising => Some("daemon"),
+        _ => None,
+    }
+}
+
+impl ScheduleStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// keep the params of the started timer or supervisor
+    pub async fn save(&self, params: &DispatchJobParams) -> Result<()> {
+        let Some(kind) = kind_of(params.action) else {
+            anyhow::bail!("cannot store the schedule of {}", params.action);
+        };
+        fs::create_dir_all(&self.dir).await?;
+        let name = file_name(kind, &params.base_job.eid);
+        let tmp = self.dir.join(format!("{name}.tmp"));
+        fs::write(&tmp, serde_json::to_vec(params)?).await?;
+        fs::rename(&tmp, self.dir.join(name)).await?;
+        Ok(())
+    }
+
+    pub async fn remove_timer(&self, eid: &str) -> Result<()> {
+        self.remove(file_name("timer", eid)).await
+    }
+
+    pub async fn remove_daemon(&self, eid: &str) -> Result<()> {
+        self.remove(file_name("daemon", eid)).await
+    }
+
+    async fn remove(&self, name: String) -> Result<()> {
+        match fs::remove_file(self.dir.join(name)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// stored schedules, the broken ones are dropped
+    pub async fn list(&self) -> Result<Vec<DispatchJobParams>> {
+        let mut list = vec![];
+        let mut entries = match fs::read_dir(&self.dir).await {
+            Ok(v) => v,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(list),
+            Err(e) => return Err(e.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_none_or(|v| v != "json") {
+                continue;
+            }
+            match serde_json::from_slice(&fs::read(&path).await?) {
+                Ok(v) => list.push(v),
+                Err(e) => {
+                    error!("drop broken stored schedule {} - {e}", path.display());
+                    fs::remove_file(&path).await?;
+                }
+            }
+        }
+        Ok(list)
+    }
+}
+
+#[tokio::test]
+async fn test_schedule_store() {
+    use super::types::BaseJob;
+
+    let dir = std::env::temp_dir().join(format!("jiascheduler-schedule-{}", nanoid::nanoid!(8)));
+    let store = ScheduleStore::new(&dir);
+    let params = |eid: &str, action| DispatchJobParams {
+        base_job: BaseJob {
+            eid: eid.to_string(),
+            ..Default::default()
+        },
+        schedule_id: "s1".to_string(),
+        instance_id: None,
+        run_id: String::new(),
+        fields: None,
+        timer_expr: None,
+        restart_interval: None,
+        is_sync: false,
+        created_user: String::new(),
+        action,
+        blackout_windows: vec![],
+        request_id: String::new(),
+        dispatch_id: String::new(),
+    };
+
+    assert!(store.list().await.unwrap().is_empty());
+    store
+        .save(&params("e/1", JobAction::StartTimer))
+        .await
+        .unwrap();
+    store
+        .save(&params("e/1", JobAction::StartSupervising))
+        .await
+        .unwrap();
+    store
+        .save(&params("e/1", JobAction::StartTimer))
+        .await
+        .unwrap();
+    assert!(store.save(&params("e2", JobAction::Exec)).await.is_err());
+    assert_eq!(store.list().await.unwrap().len(), 2);
+
+    store.remove_timer("e/1").await.unwrap();
+    store.remove_timer("e/1").await.unwrap();
+    let list = store.list().await.unwrap();
+    assert_eq!(list.len(), 1);
+    assert_eq!(list[0].action, JobAction::StartSupervising);
+
+    let _ = fs::remove_dir_all(dir).await;
+}
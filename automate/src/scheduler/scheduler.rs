@@ -1,24 +1,26 @@
 use std::{
     collections::HashMap,
+    path::PathBuf,
     sync::{Arc, atomic},
     time::Duration,
 };
 
 use anyhow::{Context, Result, anyhow};
-use chrono::{DateTime, Local, Utc};
+use chrono::{DateTime, Utc};
 use futures::{SinkExt, StreamExt};
-use nanoid::nanoid;
 
 use crate::{
     bridge::msg::{
-        BundleOutputParams, RuntimeActionParams, SftpDownloadParams, SftpReadDirParams,
+        AgentConfigParams, AgentLogLine, AgentLogOutput, AgentLogParams, BundleOutputParams,
+        RunLockParams, RuntimeActionParams, SftpDownloadParams, SftpReadDirParams,
         SftpRemoveParams, SftpUploadParams, UpdateJobParams,
     },
-    comet::types::SshLoginParams,
-    get_comet_addr, get_local_ip, get_mac_address, run_id,
+    comet::types::{ExchangeTokenRequest, ExchangeTokenResponse, SshLoginParams},
+    get_comet_addr, get_http_client, get_local_ip, get_mac_address, get_ws_connector,
     scheduler::types::JobAction,
-    set_comet_addr,
-    ssh::{self, ConnectParams, Session},
+    set_comet_addr, set_comet_secret,
+    ssh::{self, ConnectParams, PrivateKey, Session},
+    tick_run_id,
 };
 use futures_util::stream::{SplitSink, SplitStream};
 
@@ -29,36 +31,53 @@ use tokio::{
     sync::{
         Mutex,
         mpsc::{Receiver, Sender, UnboundedSender, channel, unbounded_channel},
+        oneshot,
     },
     task,
     time::{sleep, timeout},
 };
-use tokio_cron_scheduler::{Job, JobScheduler};
 use tokio_tungstenite::{
-    MaybeTlsStream, WebSocketStream, connect_async,
+    MaybeTlsStream, WebSocketStream, connect_async_tls_with_config,
     tungstenite::{ClientRequestBuilder, Message},
 };
-use tracing::{debug, error, info};
-use uuid::Uuid;
+use tracing::{debug, error, info, warn};
 
 use super::{
+    agent_config,
+    clock::{SharedClock, system_clock},
+    exec_context,
     executor::Ctx,
-    file::try_download_file,
+    file::{set_artifact_cache_size, try_download_file},
+    ledger::DispatchLedger,
+    metrics::metrics,
+    schedule_store::ScheduleStore,
+    spool::Spool,
+    supervisor::{self, RestartTracker},
+    throttle::{Admission, RunKey, UPDATE_INTERVAL, UpdateThrottle},
+    timer::{Tick, Timer, TimerSchedule},
     types::{
-        self, AssignUserOption, BundleOutput, RuntimeAction, ScheduleType, SshConnectionOption,
+        self, AssignUserOption, BundleOutput, HealthCheck, RuntimeAction, ScheduleType,
+        SshConnectionOption,
     },
+    usage,
 };
 
 use crate::{
     bridge::{
         Bridge,
         client::WsClient,
-        msg::{DispatchJobParams, HeartbeatParams, MsgReqKind},
+        msg::{
+            DispatchAckParams, DispatchAckStatus, DispatchJobParams, HeartbeatParams,
+            InstanceFacts, MsgReqKind, PruneScheduleParams, ReconcileDispatchParams,
+        },
     },
     get_endpoint,
     scheduler::executor::Executor,
 };
 
+/// resource usage of the supervised jobs is reported at this interval
+const USAGE_SAMPLE_INTERVAL: Duration = Duration::from_secs(60);
+
 pub struct RunningJobContext {
     timer_running_counter: atomic::AtomicU32,
     once_running_counter: atomic::AtomicU32,
@@ -69,15 +88,19 @@ pub struct RunningJobContext {
 
 #[derive(Clone)]
 pub struct React {
-    sched: JobScheduler,
+    clock: SharedClock,
     bridge: Bridge,
     output_dir: String,
     namespace: String,
     local_ip: String,
     client_key: String,
-    schedule_uuid_mapping: Arc<Mutex<HashMap<String, Uuid>>>,
+    schedule_timers: Arc<Mutex<HashMap<String, task::JoinHandle<()>>>>,
     supervisor_jobs: Arc<Mutex<HashMap<String, UnboundedSender<SupervisorSignal>>>>,
     running_job_contexts: Arc<Mutex<HashMap<String, RunningJobContext>>>,
+    spool: Spool,
+    ledger: DispatchLedger,
+    schedules: ScheduleStore,
+    update_throttle: UpdateThrottle,
 }
 
 pub enum SupervisorSignal {
@@ -86,7 +109,8 @@ pub enum SupervisorSignal {
 }
 
 impl React {
-    async fn new(
+    fn new(
+        clock: SharedClock,
         bridge: Bridge,
         namespace: String,
         local_ip: String,
@@ -94,9 +118,13 @@ impl React {
         output_dir: String,
     ) -> Self {
         Self {
-            sched: JobScheduler::new().await.unwrap(),
+            update_throttle: UpdateThrottle::new(clock.clone(), UPDATE_INTERVAL),
+            clock,
+            spool: Spool::new(PathBuf::from(&output_dir).join("spool")),
+            ledger: DispatchLedger::new(),
+            schedules: ScheduleStore::new(PathBuf::from(&output_dir).join("schedules")),
             output_dir,
-            schedule_uuid_mapping: Arc::new(Mutex::new(HashMap::new())),
+            schedule_timers: Arc::new(Mutex::new(HashMap::new())),
             running_job_contexts: Arc::new(Mutex::new(HashMap::new())),
             supervisor_jobs: Arc::new(Mutex::new(HashMap::new())),
             bridge,
@@ -106,35 +134,151 @@ impl React {
         }
     }
 
+    /// the running-status updates of each run are throttled, the held one is flushed
+    /// at the end of the interval unless the stop of its run supersedes it
     async fn send_update_job_msg(&self, data: UpdateJobParams) -> Result<Value> {
-        self.send_bridge_msg(MsgReqKind::UpdateJobRequest(data))
+        match self.update_throttle.admit(&data) {
+            Admission::Send(gate) => {
+                let _guard = match gate {
+                    Some(ref v) => Some(v.sequence().await),
+                    None => None,
+                };
+                self.deliver_update_job_msg(data).await
+            }
+            Admission::Flush(at) => {
+                self.flush_update_job_msg(UpdateThrottle::key(&data), at);
+                Ok(Value::Null)
+            }
+            Admission::Coalesced => Ok(Value::Null),
+        }
+    }
+
+    fn flush_update_job_msg(&self, key: RunKey, at: DateTime<Utc>) {
+        let react = self.clone();
+        tokio::spawn(async move {
+            react.clock.sleep_until(at).await;
+            let Some((data, gate)) = react.update_throttle.take(&key) else {
+                return;
+            };
+            let _guard = gate.sequence().await;
+            // the run stopped meanwhile, its stop update is the last word
+            if gate.is_stopped() {
+                return;
+            }
+            if let Err(e) = react.deliver_update_job_msg(data).await {
+                error!("failed to flush job update of {} - {e}", key.0);
+            }
+        });
+    }
+
+    /// updates that cannot reach comet are spooled and replayed after reconnect,
+    /// the later ones are spooled too until the spool is drained to keep them in order
+    async fn deliver_update_job_msg(&self, data: UpdateJobParams) -> Result<Value> {
+        if metrics().spooled_updates.load(atomic::Ordering::Relaxed) == 0 {
+            match self
+                .send_bridge_msg(MsgReqKind::UpdateJobRequest(data.clone()))
+                .await
+            {
+                Ok(v) => return Ok(v),
+                Err(e) => error!("failed to send job update, spool it - {e}"),
+            }
+        }
+        self.spool.push(&data).await?;
+        Ok(Value::Null)
+    }
+
+    async fn replay_spool(&self) -> Result<usize> {
+        self.spool
+            .replay(|data| async move {
+                self.send_bridge_msg(MsgReqKind::UpdateJobRequest(data))
+                    .await?;
+                Ok(())
+            })
             .await
     }
 
+    /// tell comet the dispatch is accepted or rejected, comet answers the server from it
+    /// when the dispatch response is lost. the ack is sent after the previous one of the
+    /// same dispatch, so that a nack never arrives before the ack it overrides
+    fn send_dispatch_ack(
+        &self,
+        ack: DispatchAckParams,
+        previous: Option<task::JoinHandle<()>>,
+    ) -> task::JoinHandle<()> {
+        let react = self.clone();
+        tokio::spawn(async move {
+            if let Some(previous) = previous {
+                let _ = previous.await;
+            }
+            if let Err(e) = react
+                .send_bridge_msg(MsgReqKind::DispatchAckRequest(ack))
+                .await
+            {
+                error!("failed to send dispatch ack - {e}");
+            }
+        })
+    }
+
+    /// false if another instance runs the tick of the singleton job
+    async fn acquire_run_lock(&self, params: RunLockParams) -> Result<bool> {
+        let ret = self
+            .send_bridge_msg(MsgReqKind::RunLockRequest(params))
+            .await?;
+        if !ret["error"].is_null() {
+            anyhow::bail!("failed to acquire run lock, {}", ret["error"]);
+        }
+        Ok(ret["acquired"].as_bool().unwrap_or_default())
+    }
+
     async fn send_bridge_msg(&self, data: MsgReqKind) -> Result<Value> {
         self.bridge.send_msg(&self.client_key, data).await
     }
 
-    async fn add_job_schedule(&mut self, eid: String, job: Job) -> Result<Option<DateTime<Utc>>> {
+    /// run on_tick at each tick of the timer until the schedule of the job is removed
+    async fn add_job_schedule<F, Fut>(
+        &mut self,
+        eid: String,
+        mut timer: Timer,
+        on_tick: F,
+    ) -> Result<Option<DateTime<Utc>>>
+    where
+        F: Fn(Tick) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
         self.remove_job_schedule(eid.as_str()).await?;
 
-        let mut locked_map = self.schedule_uuid_mapping.lock().await;
+        let mut locked_map = self.schedule_timers.lock().await;
         if locked_map.get(&eid).is_some() {
             anyhow::bail!("{eid} is existed");
         }
 
-        let uuid = self.sched.add(job).await?;
-
-        let next_time = self.sched.next_tick_for_job(uuid).await?;
-        locked_map.insert(eid, uuid);
+        let next_time = timer.next_time();
+        let timer_eid = eid.clone();
+        let handle = tokio::spawn(async move {
+            while let Some(tick) = timer.tick().await {
+                if tick.misfired > 0 {
+                    warn!("timer {timer_eid} skipped {} misfired ticks", tick.misfired);
+                    metrics()
+                        .misfired_timer_ticks
+                        .fetch_add(tick.misfired as u64, atomic::Ordering::Relaxed);
+                }
+                tokio::spawn(on_tick(tick));
+            }
+        });
+        locked_map.insert(eid, handle);
+        metrics()
+            .scheduled_timer_jobs
+            .store(locked_map.len() as i64, atomic::Ordering::Relaxed);
         Ok(next_time)
     }
 
     async fn remove_job_schedule(&mut self, eid: &str) -> Result<()> {
-        let mut locked_map = self.schedule_uuid_mapping.lock().await;
-        if let Some(uuid) = locked_map.get(eid) {
-            self.sched.remove(uuid).await?;
-            locked_map.remove(eid);
+        let mut locked_map = self.schedule_timers.lock().await;
+        if let Some(handle) = locked_map.remove(eid) {
+            handle.abort();
+            metrics()
+                .scheduled_timer_jobs
+                .store(locked_map.len() as i64, atomic::Ordering::Relaxed);
         }
         Ok(())
     }
@@ -196,7 +340,7 @@ impl React {
         kill_signal_tx: Sender<()>,
     ) -> String {
         let eid = params.base_job.eid.clone();
-        let run_id = nanoid!();
+        let run_id = params.run_id.clone();
         let mut locked_map = self.running_job_contexts.lock().await;
         if let Some(ctx) = locked_map.get_mut(&eid) {
             match params.action {
@@ -295,11 +439,6 @@ impl React {
         }
     }
 
-    async fn start(&mut self) -> Result<()> {
-        self.sched.start().await?;
-        Ok(())
-    }
-
     async fn update_supervising(
         &mut self,
         eid: String,
@@ -335,6 +474,29 @@ impl React {
     }
 }
 
+/// collect the inventory of the host, disks mounted more than once are counted once per mount
+fn collect_facts(agent_version: &str) -> InstanceFacts {
+    let mut sys = sysinfo::System::new();
+    sys.refresh_memory();
+    let disks = sysinfo::Disks::new_with_refreshed_list();
+    let (disk_total, disk_available) = disks.list().iter().fold((0, 0), |acc, v| {
+        (acc.0 + v.total_space(), acc.1 + v.available_space())
+    });
+
+    InstanceFacts {
+        os: sysinfo::System::name().unwrap_or_default(),
+        os_version: sysinfo::System::os_version().unwrap_or_default(),
+        kernel_version: sysinfo::System::kernel_version().unwrap_or_default(),
+        arch: std::env::consts::ARCH.to_string(),
+        cpu_count: std::thread::available_parallelism().map_or(0, |v| v.get() as u32),
+        mem_total: sys.total_memory(),
+        mem_used: sys.used_memory(),
+        disk_total,
+        disk_used: disk_total.saturating_sub(disk_available),
+        agent_version: agent_version.to_string(),
+    }
+}
+
 pub struct Scheduler<T> {
     comet_addr: Vec<String>,
     comet_secret: String,
@@ -346,6 +508,8 @@ pub struct Scheduler<T> {
     bridge: Bridge,
     ssh_connection_option: Option<SshConnectionOption>,
     assign_user_option: Option<AssignUserOption>,
+    agent_version: String,
+    clock: SharedClock,
 }
 
 impl
@@ -375,9 +539,30 @@ impl
             bridge: Bridge::new(),
             ssh_connection_option,
             assign_user_option,
+            agent_version: String::new(),
+            clock: system_clock(),
         }
     }
 
+    /// size in bytes of the cache of the pulled upload files
+    pub fn with_artifact_cache_size(self, size: u64) -> Self {
+        set_artifact_cache_size(size);
+        self
+    }
+
+    /// version reported in the heartbeat facts
+    pub fn with_agent_version(mut self, agent_version: impl Into<String>) -> Self {
+        self.agent_version = agent_version.into();
+        exec_context::set_agent_version(self.agent_version.clone());
+        self
+    }
+
+    /// time of the timers and the timeouts, the system clock by default
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
     pub fn client_key(&self) -> String {
         get_endpoint(get_local_ip().to_string(), self.mac_addr.clone())
     }
@@ -425,9 +610,12 @@ impl
         let u = addr.parse::<poem::http::Uri>()?;
         let req = ClientRequestBuilder::new(u)
             .with_header("Authorization", format!("Bearer {}", comet_secret));
-        let (ws_stream, _b) = timeout(Duration::from_secs(5), connect_async(req))
-            .await
-            .context("connect timeout")??;
+        let (ws_stream, _b) = timeout(
+            Duration::from_secs(5),
+            connect_async_tls_with_config(req, None, false, get_ws_connector()),
+        )
+        .await
+        .context("connect timeout")??;
 
         let (mut sink, mut stream) = ws_stream.split();
 
@@ -458,6 +646,10 @@ impl
             let sess = match Session::connect(ConnectParams {
                 user: login_params.user,
                 password: login_params.password,
+                private_key: login_params.private_key.map(|key| PrivateKey {
+                    key,
+                    passphrase: login_params.key_passphrase,
+                }),
                 addrs: (local_ip, login_params.port),
             })
             .await
@@ -498,6 +690,56 @@ impl
         Ok(())
     }
 
+    /// exchange the bootstrap token for a credential which replaces comet_secret,
+    /// the credential is saved in credential_file and reused on restart
+    pub async fn exchange_bootstrap_token(
+        &mut self,
+        token: String,
+        credential_file: String,
+    ) -> anyhow::Result<()> {
+        if let Ok(v) = tokio::fs::read_to_string(&credential_file).await
+            && !v.trim().is_empty()
+        {
+            self.comet_secret = v.trim().to_string();
+            return Ok(());
+        }
+
+        let addr = self.get_comet_addr();
+        let url = format!(
+            "{}/agent/exchange-token",
+            addr.replacen("ws", "http", 1).trim_end_matches('/')
+        );
+        let ret: Value = get_http_client()
+            .post(url)
+            .json(&ExchangeTokenRequest {
+                token,
+                namespace: self.namespace.clone(),
+                mac_addr: self.mac_addr.clone(),
+                agent_ip: get_local_ip().to_string(),
+            })
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await?
+            .json()
+            .await?;
+        if ret["code"] != 20000 {
+            anyhow::bail!("failed exchange bootstrap token - {}", ret["msg"]);
+        }
+        let resp: ExchangeTokenResponse = serde_json::from_value(ret["data"].clone())?;
+
+        tokio::fs::write(&credential_file, &resp.credential).await?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            tokio::fs::set_permissions(&credential_file, std::fs::Permissions::from_mode(0o600))
+                .await?;
+        }
+        info!("saved agent credential to {credential_file}");
+
+        self.comet_secret = resp.credential;
+        Ok(())
+    }
+
     pub async fn connect_comet(&mut self) -> anyhow::Result<()> {
         let addr = self.get_comet_addr();
         let local_ip = get_local_ip();
@@ -525,6 +767,7 @@ impl
         let client_key = self.client_key();
 
         set_comet_addr(addr);
+        set_comet_secret(self.comet_secret.clone());
 
         info!("append new sender {client_key} to {ws_addr}");
 
@@ -534,6 +777,9 @@ impl
 
         self.client.replace(client);
         self.is_initialized = true;
+        metrics()
+            .ws_connected
+            .store(true, atomic::Ordering::Relaxed);
         Ok(())
     }
 
@@ -546,10 +792,12 @@ impl
         next_time: Option<DateTime<Utc>>,
         job_params: &DispatchJobParams,
     ) -> Result<BundleOutput> {
+        let _running = metrics().track_running();
         let start_time = Utc::now();
         let schedule_id = job_params.schedule_id.clone();
         let base_job = job_params.base_job.clone();
         let instance_id = job_params.instance_id.to_owned().unwrap();
+        let exec_context = exec_context::collect(&base_job).await;
 
         let _ = react
             .send_update_job_msg(UpdateJobParams {
@@ -566,11 +814,31 @@ impl
                 run_id: job_params.run_id.clone(),
                 start_time: Some(start_time.clone()),
                 instance_id: instance_id.clone(),
+                request_id: job_params.request_id.clone(),
                 ..Default::default()
             })
             .await?;
 
-        let output = match e.run(Ctx { kill_signal_rx }).await {
+        let (pid_tx, usage_watcher) = if schedule_type == Some(ScheduleType::Daemon) {
+            let (tx, rx) = oneshot::channel();
+            (
+                Some(tx),
+                Some(Self::watch_usage(rx, job_params.clone(), react.clone())),
+            )
+        } else {
+            (None, None)
+        };
+        let ret = e
+            .run(Ctx {
+                kill_signal_rx,
+                pid_tx,
+            })
+            .await;
+        usage_watcher.inspect(|v| v.abort());
+        let timeout_action = e.timeout_actions();
+        let (output_truncated, output_url) = (e.output_truncated(), e.output_url());
+
+        let output = match ret {
             Ok(v) => v,
             Err(e) => {
                 let bundle_output = if base_job.bundle_script.is_none() {
@@ -597,6 +865,12 @@ impl
                         created_user: job_params.created_user.clone(),
                         bundle_output,
                         run_id: job_params.run_id.clone(),
+                        request_id: job_params.request_id.clone(),
+                        exec_context: Some(exec_context),
+                        is_timeout: timeout_action.is_some(),
+                        timeout_action,
+                        output_truncated,
+                        output_url,
                         ..Default::default()
                     })
                     .await?;
@@ -623,6 +897,12 @@ impl
                 created_user: job_params.created_user.clone(),
                 bundle_output: BundleOutputParams::parse(&output),
                 run_id: job_params.run_id.clone(),
+                request_id: job_params.request_id.clone(),
+                exec_context: Some(exec_context),
+                is_timeout: timeout_action.is_some(),
+                timeout_action,
+                output_truncated,
+                output_url,
                 ..Default::default()
             })
             .await?;
@@ -630,6 +910,46 @@ impl
         Ok(output)
     }
 
+    /// report the resource usage of the supervised job every USAGE_SAMPLE_INTERVAL until aborted,
+    /// the samples are dropped rather than spooled while comet is unreachable
+    fn watch_usage(
+        pid_rx: oneshot::Receiver<u32>,
+        job_params: DispatchJobParams,
+        react: React,
+    ) -> task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let Ok(pid) = pid_rx.await else {
+                return;
+            };
+            let mut sys = sysinfo::System::new();
+            // the cpu usage of the next sample is computed since this refresh
+            usage::sample(&mut sys, pid);
+            loop {
+                sleep(USAGE_SAMPLE_INTERVAL).await;
+                let Some(v) = usage::sample(&mut sys, pid) else {
+                    return;
+                };
+                let ret = react
+                    .send_bridge_msg(MsgReqKind::UpdateJobRequest(UpdateJobParams {
+                        base_job: job_params.base_job.to_pure_job(),
+                        schedule_id: job_params.schedule_id.clone(),
+                        instance_id: job_params.instance_id.clone().unwrap_or_default(),
+                        bind_namespace: react.namespace.clone(),
+                        bind_ip: react.local_ip.clone(),
+                        schedule_type: Some(ScheduleType::Daemon),
+                        created_user: job_params.created_user.clone(),
+                        run_id: job_params.run_id.clone(),
+                        resource_usage: Some(v),
+                        ..Default::default()
+                    }))
+                    .await;
+                if let Err(e) = ret {
+                    error!("supervising: failed to report resource usage - {e}");
+                }
+            }
+        })
+    }
+
     async fn start_timer(dispatch_params: DispatchJobParams, mut react: React) -> Result<Value> {
         let timer_expr = dispatch_params.timer_expr.clone().unwrap_or_default();
         let base_job = dispatch_params.base_job.clone();
@@ -639,30 +959,67 @@ impl
         let created_user = dispatch_params.created_user.clone();
         let schedule_id = dispatch_params.schedule_id.clone();
         let instance_id = dispatch_params.instance_id.to_owned().unwrap();
+        let request_id = dispatch_params.request_id.clone();
+        let stored_params = dispatch_params.clone();
 
-        let handler = move |job_id, mut job_scheduler: JobScheduler| {
+        let handler = move |tick: Tick| {
             let base_job = base_job.clone();
             let (kill_signal_tx, kill_signal_rx) = channel::<()>(1);
             let mut react_clone = react_clone.clone();
             let mut dispatch_params = dispatch_params.clone();
-            dispatch_params.run_id = run_id!();
+            dispatch_params.run_id = tick_run_id(&dispatch_params.schedule_id, tick.time);
+            let jitter_delay = dispatch_params
+                .timer_expr
+                .as_ref()
+                .and_then(|v| v.jitter_delay());
+            let run_lock = base_job
+                .singleton
+                .then(|| RunLockParams::new(&dispatch_params, tick.time));
+
+            async move {
+                if let Some(delay) = jitter_delay {
+                    react_clone.clock.sleep(delay).await;
+                }
+
+                if let Some(w) = dispatch_params.get_blackout_window(react_clone.clock.now()) {
+                    info!(
+                        "ignore execute job {} in blackout window {}",
+                        base_job.eid, w.name
+                    );
+                    return;
+                }
 
-            Box::pin(async move {
-                sleep(Duration::from_millis(10)).await;
+                if let Some(params) = run_lock {
+                    match react_clone.acquire_run_lock(params).await {
+                        Ok(true) => {}
+                        Ok(false) => {
+                            info!(
+                                "ignore execute job {}, the tick is run by another instance",
+                                base_job.eid
+                            );
+                            return;
+                        }
+                        Err(e) => {
+                            error!("ignore execute singleton job {} - {e}", base_job.eid);
+                            return;
+                        }
+                    }
+                }
 
                 if let Err(e) = react_clone.can_execute(&dispatch_params).await {
                     error!("ignore execute job - {e}");
                     return;
                 }
 
-                let next_time = job_scheduler.next_tick_for_job(job_id).await.unwrap();
+                let next_time = tick.next_time;
 
-                let prev_time = Some(Utc::now());
+                let prev_time = Some(react_clone.clock.now());
 
                 let e = Executor::builder()
                     .job(base_job.clone())
                     .output_dir(react_clone.output_dir.clone())
                     .disable_write_log(true)
+                    .clock(react_clone.clock.clone())
                     .build();
 
                 react_clone
@@ -683,20 +1040,12 @@ impl
                     error!("failed exec {} - detail: {e}", base_job.eid);
                 }
                 react_clone.end_execute(&dispatch_params).await;
-            })
+            }
         };
 
         info!("start timer {} {:?}", &euid, timer_expr);
 
-        let job = match timer_expr.timezone.as_str() {
-            "utc" => Job::new_async_tz(&timer_expr.expr, Utc, move |uuid, l: JobScheduler| {
-                handler(uuid, l)
-            }),
-            _ => Job::new_async_tz(&timer_expr.expr, Local, move |uuid, l: JobScheduler| {
-                handler(uuid, l)
-            }),
-        }
-        .map_err(|v| {
+        let schedule = TimerSchedule::new(&timer_expr, react.clock.now()).map_err(|v| {
             anyhow!(
                 "failed parse timer expr {}:{} - {}",
                 &timer_expr.timezone,
@@ -704,14 +1053,18 @@ impl
                 v
             )
         })?;
+        let timer = Timer::new(react.clock.clone(), schedule);
 
-        let next_time = react.add_job_schedule(euid.clone(), job).await?;
+        let next_time = react.add_job_schedule(euid.clone(), timer, handler).await?;
+        if let Err(e) = react.schedules.save(&stored_params).await {
+            error!("failed to store timer {euid} - {e}");
+        }
 
         info!(
             "euid: {}, next_time:{:?}, current_time:{:?}",
             &euid,
-            next_time.unwrap().to_string(),
-            Utc::now().to_string()
+            next_time,
+            react.clock.now().to_string()
         );
 
         let _ = react
@@ -730,6 +1083,7 @@ impl
                 bind_ip: react.local_ip.clone(),
                 schedule_type: Some(ScheduleType::Timer),
                 created_user,
+                request_id,
                 start_time: None,
                 ..Default::default()
             })
@@ -743,6 +1097,10 @@ impl
         react
             .remove_job_schedule(&dispatch_params.base_job.eid)
             .await?;
+        react
+            .schedules
+            .remove_timer(&dispatch_params.base_job.eid)
+            .await?;
         let _ = react
             .send_update_job_msg(UpdateJobParams {
                 base_job: dispatch_params.base_job.to_pure_job(),
@@ -759,6 +1117,7 @@ impl
                 bind_ip: react.local_ip.clone(),
                 schedule_type: Some(ScheduleType::Timer),
                 created_user: dispatch_params.created_user,
+                request_id: dispatch_params.request_id.clone(),
                 start_time: None,
                 ..Default::default()
             })
@@ -792,11 +1151,15 @@ impl
                 bind_ip: react.local_ip.clone(),
                 schedule_type: Some(ScheduleType::Daemon),
                 created_user: dispatch_params.created_user.clone(),
+                request_id: dispatch_params.request_id.clone(),
                 start_time: None,
                 ..Default::default()
             })
             .await?;
 
+        if let Err(e) = react.schedules.save(&dispatch_params).await {
+            error!("failed to store supervisor {eid} - {e}");
+        }
         if !react
             .update_supervising(eid.clone(), dispatch_params.clone(), tx)
             .await
@@ -806,11 +1169,45 @@ impl
 
         tokio::spawn(async move {
             let mut dispatch_params = dispatch_params;
+            let mut is_restart = false;
+            let mut tracker = RestartTracker::default();
             'main: loop {
-                let ret = Scheduler::wait_exec(dispatch_params.clone(), react.clone()).await;
-                if let Err(e) = ret {
-                    error!("supervising: failed exec job - {e}");
+                let supervisor_option = dispatch_params
+                    .base_job
+                    .supervisor_option
+                    .clone()
+                    .unwrap_or_default();
+                match dispatch_params
+                    .get_blackout_window(Utc::now())
+                    .filter(|_| is_restart)
+                {
+                    Some(w) => {
+                        info!(
+                            "supervising: restart suppressed by blackout window {}",
+                            w.name
+                        );
+                    }
+                    None => {
+                        let health_watcher = supervisor_option
+                            .health_check
+                            .clone()
+                            .map(|v| Scheduler::watch_health(v, eid.clone(), react.clone()));
+                        let ret =
+                            Scheduler::wait_exec(dispatch_params.clone(), react.clone()).await;
+                        health_watcher.inspect(|v| v.abort());
+                        if let Err(e) = ret {
+                            error!("supervising: failed exec job - {e}");
+                        }
+
+                        tracker.record(&supervisor_option, Utc::now());
+                        if tracker.is_flapping(&supervisor_option) {
+                            error!("supervising: {eid} is flapping, stop restarting it");
+                            Scheduler::stop_flapping(dispatch_params, react).await;
+                            return;
+                        }
+                    }
                 }
+                is_restart = true;
                 let dur =
                     dispatch_params
                         .restart_interval
@@ -822,7 +1219,7 @@ impl
                                 Duration::from_secs(1)
                             }
                         });
-                let sleep_time = sleep(dur.to_owned());
+                let sleep_time = sleep(tracker.backoff(&supervisor_option, dur));
 
                 tokio::pin!(sleep_time);
 
@@ -854,6 +1251,60 @@ impl
         Ok(json!(null))
     }
 
+    /// probe the supervised job until it is killed after failure_threshold consecutive failures,
+    /// the supervisor restarts it then
+    fn watch_health(check: HealthCheck, eid: String, mut react: React) -> task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let interval = Duration::from_secs(check.interval.max(1));
+            let mut failures = 0;
+            loop {
+                sleep(interval).await;
+                match supervisor::probe(&check).await {
+                    Ok(_) => failures = 0,
+                    Err(e) => {
+                        failures += 1;
+                        error!("supervising: health check of {eid} failed {failures} times - {e}");
+                        if failures >= check.failure_threshold.max(1) {
+                            react.kill_job(&eid, ScheduleType::Daemon).await;
+                            return;
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    /// stop supervising the job which is restarted too often and report it to raise an alert
+    async fn stop_flapping(dispatch_params: DispatchJobParams, mut react: React) {
+        if let Err(e) = react.stop_supervising(&dispatch_params.base_job.eid).await {
+            error!("supervising: failed to stop flapping job - {e}");
+        }
+        if let Err(e) = react
+            .schedules
+            .remove_daemon(&dispatch_params.base_job.eid)
+            .await
+        {
+            error!("supervising: failed to remove stored flapping job - {e}");
+        }
+        if let Err(e) = react
+            .send_update_job_msg(UpdateJobParams {
+                base_job: dispatch_params.base_job.to_pure_job(),
+                schedule_status: Some(types::ScheduleStatus::Flapping),
+                schedule_id: dispatch_params.schedule_id,
+                instance_id: dispatch_params.instance_id.unwrap_or_default(),
+                bind_namespace: react.namespace.clone(),
+                bind_ip: react.local_ip.clone(),
+                schedule_type: Some(ScheduleType::Daemon),
+                created_user: dispatch_params.created_user,
+                request_id: dispatch_params.request_id,
+                ..Default::default()
+            })
+            .await
+        {
+            error!("supervising: failed to report flapping job - {e}");
+        }
+    }
+
     async fn stop_supervising(
         dispatch_params: DispatchJobParams,
         mut react: React,
@@ -861,6 +1312,7 @@ impl
         let eid = dispatch_params.base_job.eid.clone();
         react.kill_job(&eid, ScheduleType::Daemon).await;
         react.stop_supervising(&eid).await?;
+        react.schedules.remove_daemon(&eid).await?;
 
         let instance_id = dispatch_params
             .instance_id
@@ -883,6 +1335,7 @@ impl
                 bind_ip: react.local_ip.clone(),
                 schedule_type: Some(ScheduleType::Daemon),
                 created_user: dispatch_params.created_user,
+                request_id: dispatch_params.request_id.clone(),
                 start_time: None,
                 ..Default::default()
             })
@@ -919,6 +1372,7 @@ impl
             .job(base_job.clone())
             .output_dir(react.output_dir.clone())
             .disable_write_log(true)
+            .clock(react.clock.clone())
             .build();
 
         Self::exec_job(
@@ -958,6 +1412,7 @@ impl
             .job(base_job.clone())
             .output_dir(react.output_dir.clone())
             .disable_write_log(true)
+            .clock(react.clock.clone())
             .build();
 
         react
@@ -1040,6 +1495,115 @@ impl
         }
     }
 
+    /// run the dispatch once per dispatch id, a retry of an accepted dispatch returns the previous ack
+    pub async fn accept_dispatch(
+        dispatch_params: DispatchJobParams,
+        react: React,
+    ) -> Result<Value> {
+        if dispatch_params.dispatch_id.is_empty() {
+            return Self::dispatch_job(dispatch_params, react).await;
+        }
+        if let Some(ack) = react.ledger.accept(&dispatch_params).await {
+            info!(
+                "dispatch {} of schedule {} has been accepted, skip it",
+                ack.dispatch_id, ack.schedule_id
+            );
+            return Ok(json!({"duplicate": true, "ack": ack}));
+        }
+        let ack = react.send_dispatch_ack(
+            DispatchAckParams::new(&dispatch_params, DispatchAckStatus::Ack),
+            None,
+        );
+
+        match Self::dispatch_job(dispatch_params.clone(), react.clone()).await {
+            Ok(v) => Ok(v),
+            Err(e) => {
+                let nack = react.ledger.reject(&dispatch_params, e.to_string()).await;
+                react.send_dispatch_ack(nack, Some(ack));
+                Err(e)
+            }
+        }
+    }
+
+    pub async fn reconcile_dispatch(
+        params: ReconcileDispatchParams,
+        react: React,
+    ) -> Result<Value> {
+        Ok(serde_json::to_value(react.ledger.reconcile(&params).await)?)
+    }
+
+    /// start the stored timers and supervisors, so that they keep running after a restart
+    /// while the console is unreachable. the console dispatches them again once connected
+    async fn restore_schedules(react: React) {
+        let list = match react.schedules.list().await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("failed to load stored schedules - {e}");
+                return;
+            }
+        };
+        for params in list {
+            let eid = params.base_job.eid.clone();
+            let ret = match params.action {
+                JobAction::StartTimer => Self::start_timer(params, react.clone()).await,
+                _ => Self::start_supervising(params, react.clone()).await,
+            };
+            match ret {
+                Ok(_) => info!("restored stored schedule {eid}"),
+                Err(e) => error!("failed to restore stored schedule {eid} - {e}"),
+            }
+        }
+    }
+
+    /// stop the stored schedules which are not expected by the console,
+    /// they were stopped or deleted while the agent was unreachable
+    pub async fn prune_schedule(params: PruneScheduleParams, react: React) -> Result<Value> {
+        let mut pruned = vec![];
+        for stored in react.schedules.list().await? {
+            let eid = stored.base_job.eid.clone();
+            let ret = match stored.action {
+                JobAction::StartTimer if !params.timer_eids.contains(&eid) => {
+                    Self::stop_timer(stored, react.clone()).await
+                }
+                JobAction::StartSupervising | JobAction::RestartSupervising
+                    if !params.daemon_eids.contains(&eid) =>
+                {
+                    Self::stop_supervising(stored, react.clone()).await
+                }
+                JobAction::StartSupervising | JobAction::RestartSupervising => {
+                    // the console marks the supervisors of a restarted agent unsupervised
+                    if let Err(e) = react
+                        .send_update_job_msg(UpdateJobParams {
+                            base_job: stored.base_job.to_pure_job(),
+                            schedule_status: Some(types::ScheduleStatus::Supervising),
+                            schedule_id: stored.schedule_id,
+                            instance_id: stored.instance_id.unwrap_or_default(),
+                            bind_namespace: react.namespace.clone(),
+                            bind_ip: react.local_ip.clone(),
+                            schedule_type: Some(ScheduleType::Daemon),
+                            created_user: stored.created_user,
+                            request_id: stored.request_id,
+                            ..Default::default()
+                        })
+                        .await
+                    {
+                        error!("failed to report restored supervisor {eid} - {e}");
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            match ret {
+                Ok(_) => pruned.push(eid),
+                Err(e) => error!("failed to prune stored schedule {eid} - {e}"),
+            }
+        }
+        if !pruned.is_empty() {
+            info!("pruned stored schedules {pruned:?}");
+        }
+        Ok(json!({ "pruned": pruned }))
+    }
+
     pub async fn runtime_action(
         action_params: RuntimeActionParams,
         mut react: React,
@@ -1053,12 +1617,39 @@ impl
         Ok(json!(null))
     }
 
+    pub async fn agent_config(req: AgentConfigParams) -> Result<Value> {
+        let state = agent_config::apply(req.config).await?;
+        info!("applied agent config {:?}", state.config);
+        Ok(serde_json::to_value(state)?)
+    }
+
+    /// the recent log lines of the agent kept by utils::init_tracing_with_buffer
+    pub async fn agent_log(req: AgentLogParams) -> Result<Value> {
+        let Some(buffer) = utils::log_buffer::get() else {
+            return Ok(serde_json::to_value(AgentLogOutput::default())?);
+        };
+        let lines = buffer.read(req.since, usize::MAX);
+        let next_seq = lines.last().map_or(req.since, |v| v.0);
+        let lines = lines
+            .into_iter()
+            .filter(|v| req.keyword.is_empty() || v.1.contains(&req.keyword))
+            .map(|(seq, line)| AgentLogLine { seq, line })
+            .collect::<Vec<_>>();
+        let lines = lines[lines.len().saturating_sub(req.limit)..].to_vec();
+        Ok(serde_json::to_value(AgentLogOutput {
+            enabled: true,
+            lines,
+            next_seq,
+        })?)
+    }
+
     pub async fn sftp_read_dir(req: SftpReadDirParams) -> Result<Value> {
         let ret = ssh::read_dir(
             &req.ip,
             req.port,
             &req.user,
             &req.password,
+            req.private_key,
             req.dir.filter(|v| v != "").as_deref(),
         )
         .await?;
@@ -1072,6 +1663,7 @@ impl
             req.port,
             &req.user,
             &req.password,
+            req.private_key,
             &req.filepath,
             req.data,
         )
@@ -1081,7 +1673,15 @@ impl
     }
 
     pub async fn sftp_download(req: SftpDownloadParams) -> Result<Value> {
-        let ret = ssh::download(&req.ip, req.port, &req.user, &req.password, &req.filepath).await?;
+        let ret = ssh::download(
+            &req.ip,
+            req.port,
+            &req.user,
+            &req.password,
+            req.private_key,
+            &req.filepath,
+        )
+        .await?;
         let ret = serde_json::to_value(ret)?;
         Ok(ret)
     }
@@ -1092,6 +1692,7 @@ impl
             req.port,
             &req.user,
             &req.password,
+            req.private_key,
             &req.remove_type,
             &req.filepath,
         )
@@ -1102,12 +1703,18 @@ impl
 
     pub async fn handle(msg: MsgReqKind, _bridge: Bridge, react: React) -> Value {
         let ret = match msg {
-            MsgReqKind::DispatchJobRequest(v) => Self::dispatch_job(v, react.clone()).await,
+            MsgReqKind::DispatchJobRequest(v) => Self::accept_dispatch(v, react.clone()).await,
+            MsgReqKind::ReconcileDispatchRequest(v) => {
+                Self::reconcile_dispatch(v, react.clone()).await
+            }
+            MsgReqKind::PruneScheduleRequest(v) => Self::prune_schedule(v, react.clone()).await,
             MsgReqKind::RuntimeActionRequest(v) => Self::runtime_action(v, react.clone()).await,
             MsgReqKind::SftpReadDirRequest(v) => Self::sftp_read_dir(v).await,
             MsgReqKind::SftpUploadRequest(v) => Self::sftp_upload(v).await,
             MsgReqKind::SftpRemoveRequest(v) => Self::sftp_remove(v).await,
             MsgReqKind::SftpDownloadRequest(v) => Self::sftp_download(v).await,
+            MsgReqKind::AgentLogRequest(v) => Self::agent_log(v).await,
+            MsgReqKind::AgentConfigRequest(v) => Self::agent_config(v).await,
             MsgReqKind::PullJobRequest(_) => todo!(),
             MsgReqKind::HeartbeatRequest(_) => todo!(),
             _ => todo!(),
@@ -1150,6 +1757,7 @@ impl
         let namespace = self.namespace.clone();
         let source_ip = get_local_ip().to_string();
         let mac_addr = self.mac_addr.clone();
+        let agent_version = self.agent_version.clone();
         tokio::spawn(async move {
             loop {
                 match bridge
@@ -1159,43 +1767,68 @@ impl
                             namespace: namespace.clone(),
                             mac_addr: mac_addr.clone(),
                             source_ip: source_ip.clone(),
+                            facts: Some(collect_facts(&agent_version)),
+                            agent_config: agent_config::state(),
                         }),
                     )
                     .await
                 {
-                    Ok(_v) => {}
-                    Err(e) => error!("failed heartbeat {e}, client_key:{client_key}"),
+                    Ok(_v) => metrics().heartbeat_result(true),
+                    Err(e) => {
+                        metrics().heartbeat_result(false);
+                        error!("failed heartbeat {e}, client_key:{client_key}")
+                    }
                 }
-                sleep(Duration::from_secs(60)).await;
+                sleep(agent_config::heartbeat_interval()).await;
                 debug!("heartbeat!")
             }
         });
     }
 
     pub async fn run(&mut self) -> anyhow::Result<()> {
+        metrics()
+            .started_at
+            .store(Utc::now().timestamp(), atomic::Ordering::Relaxed);
         let react = React::new(
+            self.clock.clone(),
             self.bridge.clone(),
             self.namespace.clone(),
             get_local_ip().to_string(),
             self.client_key(),
             self.output_dir.clone(),
-        )
-        .await;
-        let mut react_clone: React = react.clone();
+        );
 
+        // detect container runtimes in advance, so that the result is printed at startup
+        super::container::detect_runtimes();
         self.ssh_poll().await;
 
+        tokio::spawn(Self::restore_schedules(react.clone()));
+        self.heartbeat().await;
+
+        let react_clone = react.clone();
         tokio::spawn(async move {
-            react_clone
-                .start()
-                .await
-                .expect("failed start cron scheduler");
+            loop {
+                if metrics().ws_connected.load(atomic::Ordering::Relaxed)
+                    && let Err(e) = react_clone.replay_spool().await
+                {
+                    error!("failed to replay spooled job updates - {e}");
+                }
+                sleep(Duration::from_secs(10)).await;
+            }
         });
-        self.heartbeat().await;
+
         loop {
             self.recv(react.clone()).await;
+            metrics()
+                .ws_connected
+                .store(false, atomic::Ordering::Relaxed);
+            metrics()
+                .ws_reconnects
+                .fetch_add(1, atomic::Ordering::Relaxed);
             info!("reconnect after 1s");
             sleep(Duration::from_secs(1)).await;
+            // try the next comet, the previous one may be shutting down
+            self.comet_addr.rotate_left(1);
             if let Err(e) = self.connect_comet().await {
                 error!("failed reconnect to comet {:?} - {e}", self.comet_addr);
             }
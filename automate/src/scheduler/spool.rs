@@ -0,0 +1,161 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+};
+
+use anyhow::Result;
+use chrono::Utc;
+use nanoid::nanoid;
+use tokio::fs;
+use tracing::{error, info, warn};
+
+use super::metrics::metrics;
+use crate::bridge::msg::UpdateJobParams;
+
+/// oldest updates are dropped beyond this
+const MAX_SPOOLED: usize = 10000;
+
+/// file backed queue of the job status updates that failed to reach comet,
+/// one json file per update, named by the spooled time so that they are replayed in order
+#[derive(Clone)]
+pub struct Spool {
+    dir: PathBuf,
+}
+
+impl Spool {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub async fn push(&self, data: &UpdateJobParams) -> Result<()> {
+        fs::create_dir_all(&self.dir).await?;
+        let name = format!(
+            "{:020}-{}.json",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default(),
+            nanoid!(8)
+        );
+        let tmp = self.dir.join(format!("{name}.tmp"));
+        fs::write(&tmp, serde_json::to_vec(data)?).await?;
+        fs::rename(&tmp, self.dir.join(name)).await?;
+
+        let mut files = self.list().await?;
+        while files.len() > MAX_SPOOLED {
+            let v = files.remove(0);
+            warn!("spool is full, drop {}", v.display());
+            fs::remove_file(v).await?;
+        }
+        metrics()
+            .spooled_updates
+            .store(files.len() as i64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<PathBuf>> {
+        let mut files = vec![];
+        let mut entries = match fs::read_dir(&self.dir).await {
+            Ok(v) => v,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(files),
+            Err(e) => return Err(e.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|v| v == "json") {
+                files.push(path);
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// send the spooled updates in order, stop at the first failure and keep the rest for the next replay
+    pub async fn replay<F, Fut>(&self, send: F) -> Result<usize>
+    where
+        F: Fn(UpdateJobParams) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let files = self.list().await?;
+        let mut pending = vec![];
+        for path in files.iter() {
+            match read(path).await {
+                Ok(v) => pending.push((path, v)),
+                Err(e) => {
+                    error!("drop broken spooled update {} - {e}", path.display());
+                    fs::remove_file(path).await?;
+                }
+            }
+        }
+
+        let skipped = dedup(&pending.iter().map(|v| &v.1).collect::<Vec<_>>());
+        let total = pending.len();
+        let mut sent = 0;
+        for (i, (path, data)) in pending.into_iter().enumerate() {
+            if !skipped.contains(&i) {
+                if let Err(e) = send(data).await {
+                    metrics()
+                        .spooled_updates
+                        .store((total - i) as i64, Ordering::Relaxed);
+                    return Err(e);
+                }
+                sent += 1;
+            }
+            fs::remove_file(path).await?;
+        }
+        if sent > 0 {
+            info!("replayed {sent} spooled job updates");
+        }
+        // updates spooled during the replay are left for the next round
+        metrics()
+            .spooled_updates
+            .store(self.list().await?.len() as i64, Ordering::Relaxed);
+        Ok(sent)
+    }
+}
+
+async fn read(path: &Path) -> Result<UpdateJobParams> {
+    Ok(serde_json::from_slice(&fs::read(path).await?)?)
+}
+
+/// indexes of the updates superseded by a later one of the same run,
+/// a run is identified by schedule_id, instance_id and start_time
+fn dedup(list: &[&UpdateJobParams]) -> HashSet<usize> {
+    let mut seen = HashSet::new();
+    let mut skipped = HashSet::new();
+    for (i, v) in list.iter().enumerate().rev() {
+        let Some(start_time) = v.start_time else {
+            continue;
+        };
+        if !seen.insert((&v.schedule_id, &v.instance_id, start_time)) {
+            skipped.insert(i);
+        }
+    }
+    skipped
+}
+
+#[test]
+fn test_spool_dedup() {
+    use super::types::RunStatus;
+
+    let start_time = Some(Utc::now());
+    let running = UpdateJobParams {
+        schedule_id: "s1".to_string(),
+        instance_id: "i1".to_string(),
+        run_status: Some(RunStatus::Running),
+        start_time,
+        ..Default::default()
+    };
+    let stop = UpdateJobParams {
+        run_status: Some(RunStatus::Stop),
+        ..running.clone()
+    };
+    let other = UpdateJobParams {
+        instance_id: "i2".to_string(),
+        ..running.clone()
+    };
+    let prepare = UpdateJobParams {
+        schedule_id: "s1".to_string(),
+        ..Default::default()
+    };
+    let skipped = dedup(&[&prepare, &running, &other, &stop, &prepare]);
+    assert_eq!(skipped, HashSet::from([1]));
+}
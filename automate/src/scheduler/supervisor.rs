@@ -0,0 +1,114 @@
+use std::{collections::VecDeque, time::Duration};
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, TimeDelta, Utc};
+use tokio::{net::TcpStream, process::Command, time::timeout};
+
+use super::{
+    http_check,
+    types::{HealthCheck, HttpCheck, SupervisorOption},
+};
+
+/// restarts of a supervised job within the flapping window, used for the backoff and flapping detection
+#[derive(Default)]
+pub struct RestartTracker {
+    restarts: VecDeque<DateTime<Utc>>,
+}
+
+impl RestartTracker {
+    /// record a restart and return the restarts within the window
+    pub fn record(&mut self, opt: &SupervisorOption, now: DateTime<Utc>) -> usize {
+        let window = TimeDelta::minutes(opt.flapping_window as i64);
+        while self.restarts.front().is_some_and(|v| *v + window <= now) {
+            self.restarts.pop_front();
+        }
+        self.restarts.push_back(now);
+        self.restarts.len()
+    }
+
+    pub fn is_flapping(&self, opt: &SupervisorOption) -> bool {
+        opt.flapping_threshold
+            .is_some_and(|v| v > 0 && self.restarts.len() >= v as usize)
+    }
+
+    /// restart_interval doubled on every restart within the window, up to max_backoff
+    pub fn backoff(&self, opt: &SupervisorOption, interval: Duration) -> Duration {
+        let Some(max_backoff) = opt.max_backoff else {
+            return interval;
+        };
+        let exp = self.restarts.len().saturating_sub(1).min(16) as u32;
+        interval
+            .saturating_mul(2u32.pow(exp))
+            .min(Duration::from_secs(max_backoff).max(interval))
+    }
+}
+
+/// run the probe once, an error is returned if the job is unhealthy
+pub async fn probe(check: &HealthCheck) -> Result<()> {
+    let dur = Duration::from_secs(check.timeout.max(1));
+    match check.kind.as_str() {
+        "tcp" => {
+            timeout(dur, TcpStream::connect(&check.target))
+                .await
+                .map_err(|_| anyhow!("connect {} timeout", check.target))??;
+        }
+        "http" => {
+            let opt = HttpCheck {
+                url: check.target.clone(),
+                method: "GET".to_string(),
+                ..Default::default()
+            };
+            let output = http_check::check(&opt, check.timeout.max(1)).await?;
+            if !output.is_success() {
+                anyhow::bail!("{}", output.failures.join(", "));
+            }
+        }
+        "script" => {
+            let status = timeout(
+                dur,
+                Command::new("sh")
+                    .arg("-c")
+                    .arg(&check.target)
+                    .kill_on_drop(true)
+                    .status(),
+            )
+            .await
+            .map_err(|_| anyhow!("health check script timeout"))??;
+            if !status.success() {
+                anyhow::bail!("health check script exited with {status}");
+            }
+        }
+        v => anyhow::bail!("unsupported health check {v}"),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_restart_tracker() {
+    let opt = SupervisorOption {
+        health_check: None,
+        max_backoff: Some(10),
+        flapping_threshold: Some(3),
+        flapping_window: 5,
+    };
+    let interval = Duration::from_secs(1);
+    let now = Utc::now();
+    let mut tracker = RestartTracker::default();
+
+    assert_eq!(tracker.record(&opt, now), 1);
+    assert_eq!(tracker.backoff(&opt, interval), Duration::from_secs(1));
+    assert_eq!(tracker.record(&opt, now + TimeDelta::minutes(1)), 2);
+    assert_eq!(tracker.backoff(&opt, interval), Duration::from_secs(2));
+    assert!(!tracker.is_flapping(&opt));
+
+    // the first restart is out of the window
+    assert_eq!(tracker.record(&opt, now + TimeDelta::minutes(5)), 2);
+    assert_eq!(tracker.record(&opt, now + TimeDelta::seconds(330)), 3);
+    assert!(tracker.is_flapping(&opt));
+    assert_eq!(tracker.backoff(&opt, interval), Duration::from_secs(4));
+
+    for i in 0..5 {
+        tracker.record(&opt, now + TimeDelta::seconds(331 + i));
+    }
+    assert_eq!(tracker.backoff(&opt, interval), Duration::from_secs(10));
+}
@@ -0,0 +1,245 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+use super::{clock::SharedClock, metrics::metrics, types::RunStatus};
+use crate::bridge::msg::UpdateJobParams;
+
+/// at most one running-status update of a run is sent within it
+pub const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// the eid and the run id of the job, the overlapping runs of a job are throttled apart
+pub type RunKey = (String, String);
+
+pub enum Admission {
+    /// send the update now, in order with the updates of the run already being delivered
+    Send(Option<Arc<RunGate>>),
+    /// the update is held as the pending one of the run, flush it at the time
+    Flush(DateTime<Utc>),
+    /// the update replaced the pending one, which is already waiting for its flush
+    Coalesced,
+}
+
+/// orders the deliveries of a run, a flush in flight when the run stops is dropped
+/// instead of showing the stopped run as running again
+#[derive(Default)]
+pub struct RunGate {
+    delivery: Arc<AsyncMutex<()>>,
+    stopped: AtomicBool,
+}
+
+impl RunGate {
+    /// hold it until the update is delivered
+    pub async fn sequence(&self) -> OwnedMutexGuard<()> {
+        self.delivery.clone().lock_owned().await
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Acquire)
+    }
+}
+
+#[derive(Default)]
+struct Slot {
+    last_sent: Option<DateTime<Utc>>,
+    pending: Option<UpdateJobParams>,
+    gate: Arc<RunGate>,
+}
+
+/// rate limit of the running-status updates of each run, the ones within the interval
+/// are coalesced into the latest, the stop and schedule updates are authoritative,
+/// they are always sent and supersede the pending update of the same run
+#[derive(Clone)]
+pub struct UpdateThrottle {
+    clock: SharedClock,
+    interval: TimeDelta,
+    slots: Arc<Mutex<HashMap<RunKey, Slot>>>,
+}
+
+impl UpdateThrottle {
+    pub fn new(clock: SharedClock, interval: Duration) -> Self {
+        Self {
+            clock,
+            interval: TimeDelta::from_std(interval).unwrap_or(TimeDelta::max_value()),
+            slots: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn key(data: &UpdateJobParams) -> RunKey {
+        (data.base_job.eid.clone(), data.run_id.clone())
+    }
+
+    fn is_progress(data: &UpdateJobParams) -> bool {
+        data.run_status == Some(RunStatus::Running)
+            && data.schedule_status.is_none()
+            && data.end_time.is_none()
+    }
+
+    fn is_stop(data: &UpdateJobParams) -> bool {
+        data.run_status == Some(RunStatus::Stop) || data.end_time.is_some()
+    }
+
+    pub fn admit(&self, data: &UpdateJobParams) -> Admission {
+        let now = self.clock.now();
+        let key = Self::key(data);
+        let mut slots = self.slots.lock().unwrap();
+
+        if !Self::is_progress(data) {
+            let Some(slot) = slots.get_mut(&key) else {
+                return Admission::Send(None);
+            };
+            if slot.pending.take().is_some() {
+                metrics().coalesced_updates.fetch_add(1, Ordering::Relaxed);
+            }
+            let gate = slot.gate.clone();
+            if Self::is_stop(data) {
+                // the run is over, the slot is forgotten so the slots only grow with the active runs
+                gate.stopped.store(true, Ordering::Release);
+                slots.remove(&key);
+            }
+            return Admission::Send(Some(gate));
+        }
+
+        let slot = slots.entry(key).or_default();
+        if slot.pending.is_none() && slot.last_sent.is_none_or(|t| now - t >= self.interval) {
+            slot.last_sent = Some(now);
+            return Admission::Send(Some(slot.gate.clone()));
+        }
+        if slot.pending.replace(data.clone()).is_some() {
+            metrics().coalesced_updates.fetch_add(1, Ordering::Relaxed);
+            return Admission::Coalesced;
+        }
+        Admission::Flush(slot.last_sent.map_or(now, |t| t + self.interval))
+    }
+
+    /// the pending update of the run to flush with the gate of the run,
+    /// None if it was superseded
+    pub fn take(&self, key: &RunKey) -> Option<(UpdateJobParams, Arc<RunGate>)> {
+        let mut slots = self.slots.lock().unwrap();
+        let slot = slots.get_mut(key)?;
+        let data = slot.pending.take()?;
+        slot.last_sent = Some(self.clock.now());
+        Some((data, slot.gate.clone()))
+    }
+}
+
+#[cfg(test)]
+fn update(run_id: &str, run_status: RunStatus) -> UpdateJobParams {
+    use super::types::BaseJob;
+
+    UpdateJobParams {
+        base_job: BaseJob {
+            eid: "e1".to_string(),
+            ..Default::default()
+        },
+        run_id: run_id.to_string(),
+        run_status: Some(run_status),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+fn key(run_id: &str) -> RunKey {
+    ("e1".to_string(), run_id.to_string())
+}
+
+#[test]
+fn test_update_throttle() {
+    use chrono::TimeZone;
+
+    use super::clock::MockClock;
+
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap());
+    let throttle = UpdateThrottle::new(Arc::new(clock.clone()), UPDATE_INTERVAL);
+
+    assert!(matches!(
+        throttle.admit(&update("r1", RunStatus::Running)),
+        Admission::Send(Some(_))
+    ));
+
+    // the next updates of the run within the interval are held, the latest one is flushed
+    clock.advance(Duration::from_millis(200));
+    let Admission::Flush(at) = throttle.admit(&update("r1", RunStatus::Running)) else {
+        panic!("the update is not held");
+    };
+    assert_eq!(at, Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 1).unwrap());
+    assert!(matches!(
+        throttle.admit(&update("r1", RunStatus::Running)),
+        Admission::Coalesced
+    ));
+
+    // an overlapping run of the job is throttled apart and keeps the pending update of r1
+    assert!(matches!(
+        throttle.admit(&update("r2", RunStatus::Running)),
+        Admission::Send(Some(_))
+    ));
+    assert!(matches!(
+        throttle.admit(&update("r2", RunStatus::Stop)),
+        Admission::Send(Some(_))
+    ));
+    assert_eq!(throttle.take(&key("r1")).unwrap().0.run_id, "r1");
+    assert!(throttle.take(&key("r1")).is_none());
+
+    // the stop of the run supersedes its pending update
+    clock.advance(Duration::from_millis(500));
+    assert!(matches!(
+        throttle.admit(&update("r1", RunStatus::Running)),
+        Admission::Flush(_)
+    ));
+    assert!(matches!(
+        throttle.admit(&update("r1", RunStatus::Stop)),
+        Admission::Send(Some(_))
+    ));
+    assert!(throttle.take(&key("r1")).is_none());
+
+    // the run never throttled is sent as is
+    assert!(matches!(
+        throttle.admit(&update("r3", RunStatus::Stop)),
+        Admission::Send(None)
+    ));
+}
+
+#[tokio::test]
+async fn test_stop_orders_after_inflight_flush() {
+    use chrono::TimeZone;
+
+    use super::clock::MockClock;
+
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap());
+    let throttle = UpdateThrottle::new(Arc::new(clock.clone()), UPDATE_INTERVAL);
+
+    throttle.admit(&update("r1", RunStatus::Running));
+    throttle.admit(&update("r1", RunStatus::Running));
+    // the flush took the pending update and is delivering it
+    let (_, flush_gate) = throttle.take(&key("r1")).unwrap();
+    let delivering = flush_gate.sequence().await;
+
+    let Admission::Send(Some(stop_gate)) = throttle.admit(&update("r1", RunStatus::Stop)) else {
+        panic!("the stop is not sequenced with the run");
+    };
+    let stop = tokio::spawn(async move {
+        let _guard = stop_gate.sequence().await;
+    });
+    tokio::task::yield_now().await;
+    assert!(!stop.is_finished());
+
+    // the stop is delivered once the flush is
+    drop(delivering);
+    stop.await.unwrap();
+
+    // a flush reaching the gate after the stop is dropped
+    throttle.admit(&update("r4", RunStatus::Running));
+    throttle.admit(&update("r4", RunStatus::Running));
+    let (_, gate) = throttle.take(&key("r4")).unwrap();
+    throttle.admit(&update("r4", RunStatus::Stop));
+    let _guard = gate.sequence().await;
+    assert!(gate.is_stopped());
+}
@@ -0,0 +1,262 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+use chrono::{DateTime, Local, TimeDelta, Utc};
+use croner::Cron;
+
+use crate::bridge::msg::TimerExpr;
+
+use super::clock::{MockClock, SharedClock};
+
+/// a tick later than it is a misfire, eg: after the host wakes from suspend,
+/// the misfired ticks are skipped instead of being run late
+pub const MISFIRE_GRACE: Duration = Duration::from_secs(30);
+
+/// the misfired ticks are counted one by one up to it, the rest are skipped at once
+const MAX_COUNTED_MISFIRES: u32 = 10000;
+
+pub enum TimerSchedule {
+    /// every interval since the timer is started
+    Interval {
+        start: DateTime<Utc>,
+        interval: TimeDelta,
+    },
+    Cron {
+        cron: Box<Cron>,
+        utc: bool,
+    },
+}
+
+impl TimerSchedule {
+    /// the interval timers start at start, the cron expr is evaluated in utc or the local time
+    pub fn new(expr: &TimerExpr, start: DateTime<Utc>) -> Result<Self> {
+        if let Some(interval) = expr.interval {
+            if interval == 0 {
+                anyhow::bail!("the interval of timer must be greater than 0");
+            }
+            return Ok(Self::Interval {
+                start,
+                interval: TimeDelta::seconds(interval as i64),
+            });
+        }
+
+        Ok(Self::Cron {
+            cron: Box::new(utils::parse_cron_expr(&expr.expr)?),
+            utc: expr.timezone == "utc",
+        })
+    }
+
+    /// the first tick after t
+    pub fn next_after(&self, t: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Self::Interval { start, interval } => {
+                if t < *start {
+                    return start.checked_add_signed(*interval);
+                }
+                let n = (t - *start).num_milliseconds() / interval.num_milliseconds() + 1;
+                start.checked_add_signed(*interval * n as i32)
+            }
+            Self::Cron { cron, utc: true } => cron.find_next_occurrence(&t, false).ok(),
+            Self::Cron { cron, utc: false } => cron
+                .find_next_occurrence(&t.with_timezone(&Local), false)
+                .ok()
+                .map(|v| v.with_timezone(&Utc)),
+        }
+    }
+}
+
+pub struct Tick {
+    /// the time the tick is scheduled at
+    pub time: DateTime<Utc>,
+    pub next_time: Option<DateTime<Utc>>,
+    /// ticks skipped since the previous one because they were missed by more than the grace
+    pub misfired: u32,
+}
+
+/// fires the ticks of the schedule on the clock
+pub struct Timer {
+    clock: SharedClock,
+    schedule: TimerSchedule,
+    misfire_grace: TimeDelta,
+    next: Option<DateTime<Utc>>,
+}
+
+impl Timer {
+    pub fn new(clock: SharedClock, schedule: TimerSchedule) -> Self {
+        let next = schedule.next_after(clock.now());
+        Self {
+            clock,
+            schedule,
+            misfire_grace: TimeDelta::from_std(MISFIRE_GRACE).unwrap(),
+            next,
+        }
+    }
+
+    pub fn with_misfire_grace(mut self, misfire_grace: Duration) -> Self {
+        self.misfire_grace = TimeDelta::from_std(misfire_grace).unwrap_or(TimeDelta::max_value());
+        self
+    }
+
+    pub fn next_time(&self) -> Option<DateTime<Utc>> {
+        self.next
+    }
+
+    /// wait for the next tick, None if the schedule has no more ticks
+    pub async fn tick(&mut self) -> Option<Tick> {
+        let mut misfired = 0;
+        loop {
+            let time = self.next?;
+            let now = self.clock.now();
+            if now < time {
+                // sleep again if woken early, eg: the system time is set back
+                self.clock.sleep_until(time).await;
+                continue;
+            }
+
+            if now - time > self.misfire_grace {
+                misfired += self.skip_misfires(now - self.misfire_grace);
+                continue;
+            }
+
+            self.next = self.schedule.next_after(time);
+            return Some(Tick {
+                time,
+                next_time: self.next,
+                misfired,
+            });
+        }
+    }
+
+    /// move the next tick to the deadline or later, return the number of the skipped ticks
+    fn skip_misfires(&mut self, deadline: DateTime<Utc>) -> u32 {
+        let mut skipped = 0;
+        while let Some(time) = self.next.filter(|v| *v < deadline) {
+            skipped += 1;
+            self.next = if skipped < MAX_COUNTED_MISFIRES {
+                self.schedule.next_after(time)
+            } else {
+                self.schedule.next_after(deadline)
+            };
+        }
+        skipped
+    }
+}
+
+/// the ticks of the schedule within the duration since start, at most limit of them,
+/// the timer runs on a mock clock so nothing sleeps
+pub async fn simulate(
+    schedule: TimerSchedule,
+    start: DateTime<Utc>,
+    duration: Duration,
+    limit: usize,
+) -> Vec<DateTime<Utc>> {
+    let end = TimeDelta::from_std(duration)
+        .ok()
+        .and_then(|v| start.checked_add_signed(v))
+        .unwrap_or(DateTime::<Utc>::MAX_UTC);
+    let clock = MockClock::new(start);
+    let mut timer = Timer::new(Arc::new(clock.clone()), schedule);
+
+    let mut ticks = vec![];
+    while ticks.len() < limit {
+        let Some(next) = timer.next_time().filter(|v| *v <= end) else {
+            break;
+        };
+        clock.set(next);
+        match timer.tick().await {
+            Some(tick) => ticks.push(tick.time),
+            None => break,
+        }
+    }
+    ticks
+}
+
+#[cfg(test)]
+fn utc(h: u32, m: u32, s: u32) -> DateTime<Utc> {
+    use chrono::TimeZone;
+
+    Utc.with_ymd_and_hms(2026, 1, 1, h, m, s).unwrap()
+}
+
+#[cfg(test)]
+fn cron_expr(expr: &str) -> TimerExpr {
+    TimerExpr {
+        timezone: "utc".to_string(),
+        expr: expr.to_string(),
+        interval: None,
+        jitter: None,
+    }
+}
+
+#[tokio::test]
+async fn test_timer_fires_on_clock() {
+    use super::clock::Clock;
+
+    let clock = MockClock::new(utc(0, 0, 0));
+    let schedule = TimerSchedule::new(&cron_expr("0 */5 * * * *"), clock.now()).unwrap();
+    let mut timer = Timer::new(Arc::new(clock.clone()), schedule);
+    assert_eq!(timer.next_time(), Some(utc(0, 5, 0)));
+
+    let handle = tokio::spawn(async move {
+        let tick = timer.tick().await.unwrap();
+        (tick.time, tick.next_time, tick.misfired)
+    });
+    clock.advance(Duration::from_secs(299));
+    tokio::task::yield_now().await;
+    assert!(!handle.is_finished());
+
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(
+        handle.await.unwrap(),
+        (utc(0, 5, 0), Some(utc(0, 10, 0)), 0)
+    );
+}
+
+#[tokio::test]
+async fn test_timer_misfire() {
+    use super::clock::Clock;
+
+    let clock = MockClock::new(utc(0, 0, 0));
+    let schedule = TimerSchedule::new(&cron_expr("0 * * * * *"), clock.now()).unwrap();
+    let mut timer = Timer::new(Arc::new(clock.clone()), schedule);
+
+    // late within the grace, the tick still fires
+    clock.set(utc(0, 1, 20));
+    let tick = timer.tick().await.unwrap();
+    assert_eq!((tick.time, tick.misfired), (utc(0, 1, 0), 0));
+
+    // the host is suspended for 10 minutes, the missed ticks are skipped
+    clock.set(utc(0, 11, 10));
+    let tick = timer.tick().await.unwrap();
+    assert_eq!((tick.time, tick.misfired), (utc(0, 11, 0), 9));
+    assert_eq!(tick.next_time, Some(utc(0, 12, 0)));
+}
+
+#[tokio::test]
+async fn test_simulate_timer() {
+    let expr = TimerExpr {
+        interval: Some(3600),
+        ..cron_expr("")
+    };
+    let start = utc(0, 0, 30);
+    let ticks = simulate(
+        TimerSchedule::new(&expr, start).unwrap(),
+        start,
+        Duration::from_secs(86400),
+        1000,
+    )
+    .await;
+    assert_eq!(ticks.len(), 24);
+    assert_eq!(ticks[0], utc(1, 0, 30));
+
+    let ticks = simulate(
+        TimerSchedule::new(&cron_expr("0 0 */6 * * *"), start).unwrap(),
+        start,
+        Duration::from_secs(86400),
+        2,
+    )
+    .await;
+    assert_eq!(ticks, vec![utc(6, 0, 0), utc(12, 0, 0)]);
+
+    assert!(TimerSchedule::new(&cron_expr("invalid"), start).is_err());
+}
@@ -1,6 +1,7 @@
 use std::{collections::HashMap, fmt, process::Output};
 
 use anyhow::anyhow;
+use chrono::{DateTime, Datelike, Local, NaiveTime, Utc, Weekday};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Copy)]
@@ -98,6 +99,8 @@ pub enum ScheduleStatus {
     Unsupervised,
     Scheduling,
     Unscheduled,
+    /// restarted too often, the agent stopped supervising it
+    Flapping,
 }
 
 impl fmt::Display for ScheduleStatus {
@@ -108,6 +111,7 @@ impl fmt::Display for ScheduleStatus {
             ScheduleStatus::Unscheduled => write!(f, "unscheduled"),
             ScheduleStatus::Supervising => write!(f, "supervising"),
             ScheduleStatus::Unsupervised => write!(f, "unsupervised"),
+            ScheduleStatus::Flapping => write!(f, "flapping"),
         }
     }
 }
@@ -128,6 +132,28 @@ pub struct BaseJob {
     pub max_parallel: Option<u32>,
     #[serde(default)]
     pub is_workflow: bool,
+    #[serde(default)]
+    pub container: Option<ContainerOption>,
+    #[serde(default)]
+    pub http_check: Option<HttpCheck>,
+    /// environment variables of the process
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+    /// how the scripts of a bundle job are run, sequentially without stopping if none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bundle_option: Option<BundleOption>,
+    /// health check, restart backoff and flapping detection of a supervised job
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub supervisor_option: Option<SupervisorOption>,
+    /// only one of the instances runs each timer tick, granted by the run lock of comet
+    #[serde(default)]
+    pub singleton: bool,
+    /// what happens when the job runs longer than timeout, killed at once if none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_action: Option<TimeoutAction>,
+    /// the stdout and stderr kept in the exec history, unlimited if none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_limit: Option<OutputLimit>,
 }
 
 impl BaseJob {
@@ -147,22 +173,260 @@ impl BaseJob {
             max_retry: self.max_retry,
             max_parallel: self.max_parallel,
             is_workflow: self.is_workflow,
+            container: self.container.clone(),
+            http_check: self.http_check.clone(),
+            env: self.env.clone(),
+            bundle_option: self.bundle_option.clone(),
+            supervisor_option: self.supervisor_option.clone(),
+            singleton: self.singleton,
+            timeout_action: self.timeout_action.clone(),
+            output_limit: self.output_limit.clone(),
         }
     }
 }
 
+pub const TIMEOUT_SIGTERM: &str = "sigterm";
+pub const TIMEOUT_SIGKILL: &str = "sigkill";
+pub const TIMEOUT_CLEANUP: &str = "cleanup";
+pub const TIMEOUT_CLEANUP_FAILED: &str = "cleanup_failed";
+pub const TIMEOUT_NOTIFY: &str = "notify";
+
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct TimeoutAction {
+    /// seconds the process group is given to exit after SIGTERM before it is killed,
+    /// killed at once if 0. the process is always killed at once on windows
+    #[serde(default)]
+    pub grace_period: u64,
+    /// run by sh -c, or powershell -Command on windows, in the work dir after the job is stopped
+    #[serde(default)]
+    pub cleanup_script: Option<String>,
+    /// the completed callback of the job is sent for the timed out run
+    #[serde(default)]
+    pub notify: bool,
+}
+
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct OutputLimit {
+    /// max bytes of stdout, and of stderr, sent back by the agent, unlimited if 0
+    pub max_bytes: u64,
+    /// bytes kept from the end of the output, the rest of max_bytes is kept from the start
+    #[serde(default)]
+    pub tail_bytes: u64,
+    /// the full output of a truncated run is PUT to this url, eg: a presigned url of a bucket
+    #[serde(default)]
+    pub upload_url: Option<String>,
+}
+
 #[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct BundleScript {
     pub eid: String,
     pub cmd_name: String,
     pub args: Vec<String>,
     pub code: String,
+    #[serde(default)]
+    pub container: Option<ContainerOption>,
+}
+
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum BundleMode {
+    /// one by one in the declared order
+    #[default]
+    Sequential,
+    Parallel,
+}
+
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct BundleOption {
+    #[serde(default)]
+    pub mode: BundleMode,
+    /// max scripts running at the same time in parallel mode, unlimited if none
+    #[serde(default)]
+    pub max_parallel: Option<usize>,
+    /// keep running the rest of the scripts after one exits with non-zero code
+    #[serde(default)]
+    pub continue_on_error: bool,
+}
+
+impl BundleOption {
+    pub fn parallelism(&self, total: usize) -> usize {
+        match self.mode {
+            BundleMode::Sequential => 1,
+            BundleMode::Parallel => self
+                .max_parallel
+                .filter(|v| *v > 0)
+                .unwrap_or(total)
+                .clamp(1, total.max(1)),
+        }
+    }
+}
+
+/// kind: tcp, http, script
+///
+/// target is the address for tcp, eg: 127.0.0.1:8080, the url for http and the shell code for script
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct HealthCheck {
+    pub kind: String,
+    pub target: String,
+    /// seconds between two probes
+    pub interval: u64,
+    /// seconds a probe may take
+    pub timeout: u64,
+    /// consecutive failed probes before the job is killed and restarted
+    pub failure_threshold: u32,
+}
+
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct SupervisorOption {
+    #[serde(default)]
+    pub health_check: Option<HealthCheck>,
+    /// max seconds of the restart delay, which doubles on every restart within the flapping window
+    /// starting from restart_interval, the delay is fixed if none
+    #[serde(default)]
+    pub max_backoff: Option<u64>,
+    /// stop restarting after so many restarts within flapping_window minutes, never stop if none
+    #[serde(default)]
+    pub flapping_threshold: Option<u32>,
+    #[serde(default)]
+    pub flapping_window: u64,
+}
+
+/// output of a script of the bundle, seq is the order the script was started in
+pub struct BundleScriptOutput {
+    pub eid: String,
+    pub seq: usize,
+    pub output: Output,
+}
+
+/// run the script inside a container instead of on the host
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct ContainerOption {
+    /// docker or podman, detect automatically if empty
+    pub runtime: Option<String>,
+    pub image: String,
+    /// cpu limit, eg: "0.5"
+    pub cpus: Option<String>,
+    /// memory limit, eg: "512m"
+    pub memory: Option<String>,
+    pub network: Option<String>,
+    #[serde(default)]
+    pub extra_args: Vec<String>,
+}
+
+/// request an endpoint instead of running a script, used by http job
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct HttpCheck {
+    pub url: String,
+    pub method: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    pub body: Option<String>,
+    /// expected status code list, any 2xx is accepted if empty
+    #[serde(default)]
+    pub expected_status: Vec<u16>,
+    /// max latency in milliseconds
+    pub max_latency: Option<u64>,
+    #[serde(default)]
+    pub assertions: Vec<HttpAssertion>,
+}
+
+/// kind: body_contains, body_not_contains, header_equals, json_equals
+///
+/// target is the header name for header_equals and the json pointer for json_equals, eg: /data/status
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct HttpAssertion {
+    pub kind: String,
+    #[serde(default)]
+    pub target: String,
+    pub value: String,
+}
+
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct HttpCheckOutput {
+    pub status: Option<u16>,
+    /// latency in milliseconds
+    pub latency: u64,
+    pub body: String,
+    pub failures: Vec<String>,
+}
+
+impl HttpCheckOutput {
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// a period in which timer executions and daemon restarts are suppressed
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct BlackoutWindow {
+    pub name: String,
+    /// once or recurring
+    pub window_type: String,
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+    /// days from sunday, 0-6, every day if empty
+    #[serde(default)]
+    pub weekdays: Vec<u8>,
+    /// HH:MM, the window crosses midnight if start_clock is greater than end_clock
+    #[serde(default)]
+    pub start_clock: String,
+    #[serde(default)]
+    pub end_clock: String,
+    /// utc or local
+    #[serde(default)]
+    pub timezone: String,
+}
+
+impl BlackoutWindow {
+    pub fn contains(&self, now: DateTime<Utc>) -> bool {
+        match self.window_type.as_str() {
+            "once" => match (self.start_time, self.end_time) {
+                (Some(start), Some(end)) => start <= now && now < end,
+                _ => false,
+            },
+            "recurring" => {
+                let (Ok(start), Ok(end)) = (
+                    NaiveTime::parse_from_str(&self.start_clock, "%H:%M"),
+                    NaiveTime::parse_from_str(&self.end_clock, "%H:%M"),
+                ) else {
+                    return false;
+                };
+                let (weekday, time) = match self.timezone.as_str() {
+                    "utc" => (now.weekday(), now.time()),
+                    _ => {
+                        let now = now.with_timezone(&Local);
+                        (now.weekday(), now.time())
+                    }
+                };
+
+                if start <= end {
+                    self.match_weekday(weekday) && start <= time && time < end
+                } else {
+                    (self.match_weekday(weekday) && time >= start)
+                        || (self.match_weekday(weekday.pred()) && time < end)
+                }
+            }
+            _ => false,
+        }
+    }
+
+    fn match_weekday(&self, weekday: Weekday) -> bool {
+        self.weekdays.is_empty()
+            || self
+                .weekdays
+                .contains(&(weekday.num_days_from_sunday() as u8))
+    }
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
 pub struct UploadFile {
     pub filename: String,
     pub data: Option<Vec<u8>>,
+    /// the agent pulls the artifact from the comet by the hash if data is none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(default)]
+    pub size: u64,
 }
 
 #[derive(Debug, Serialize, PartialEq, Deserialize, Default, Clone)]
@@ -202,7 +466,8 @@ impl fmt::Display for ScheduleType {
 
 pub enum BundleOutput {
     Output(Output),
-    Bundle(HashMap<String, Output>),
+    Bundle(Vec<BundleScriptOutput>),
+    Http(HttpCheckOutput),
 }
 
 impl BundleOutput {
@@ -210,6 +475,10 @@ impl BundleOutput {
         match self {
             BundleOutput::Output(v) => Some(v.status.to_string()),
             BundleOutput::Bundle(_) => None,
+            BundleOutput::Http(v) => Some(match v.status {
+                Some(status) => format!("http status: {status}, latency: {}ms", v.latency),
+                None => format!("request failed, latency: {}ms", v.latency),
+            }),
         }
     }
 
@@ -224,6 +493,7 @@ impl BundleOutput {
                 }
             }
             BundleOutput::Bundle(_) => None,
+            BundleOutput::Http(v) => Some(if v.is_success() { 0 } else { 1 }),
         }
     }
 
@@ -231,6 +501,7 @@ impl BundleOutput {
         match self {
             BundleOutput::Output(v) => Some(String::from_utf8_lossy(&v.stdout).to_string()),
             BundleOutput::Bundle(_) => None,
+            BundleOutput::Http(v) => Some(v.body.clone()),
         }
     }
 
@@ -238,6 +509,7 @@ impl BundleOutput {
         match self {
             BundleOutput::Output(v) => Some(String::from_utf8_lossy(&v.stderr).to_string()),
             BundleOutput::Bundle(_) => None,
+            BundleOutput::Http(v) => Some(v.failures.join("\n")),
         }
     }
 }
@@ -282,3 +554,40 @@ impl AssignUserOption {
         }
     }
 }
+
+#[test]
+fn test_blackout_window() {
+    use chrono::TimeZone;
+
+    let w = BlackoutWindow {
+        window_type: "recurring".to_string(),
+        weekdays: vec![0],
+        start_clock: "23:00".to_string(),
+        end_clock: "02:00".to_string(),
+        timezone: "utc".to_string(),
+        ..Default::default()
+    };
+    // 2025-06-01 is sunday
+    assert!(w.contains(Utc.with_ymd_and_hms(2025, 6, 1, 23, 30, 0).unwrap()));
+    assert!(w.contains(Utc.with_ymd_and_hms(2025, 6, 2, 1, 0, 0).unwrap()));
+    assert!(!w.contains(Utc.with_ymd_and_hms(2025, 6, 2, 2, 0, 0).unwrap()));
+    assert!(!w.contains(Utc.with_ymd_and_hms(2025, 6, 1, 1, 0, 0).unwrap()));
+}
+
+#[test]
+fn test_bundle_option_parallelism() {
+    let opt = BundleOption::default();
+    assert_eq!(opt.parallelism(5), 1);
+    let opt = BundleOption {
+        mode: BundleMode::Parallel,
+        max_parallel: Some(2),
+        ..Default::default()
+    };
+    assert_eq!(opt.parallelism(5), 2);
+    assert_eq!(opt.parallelism(1), 1);
+    let opt = BundleOption {
+        mode: BundleMode::Parallel,
+        ..Default::default()
+    };
+    assert_eq!(opt.parallelism(5), 5);
+}
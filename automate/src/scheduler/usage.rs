@@ -0,0 +1,49 @@
+use std::collections::HashSet;
+
+use chrono::Utc;
+use sysinfo::{Pid, ProcessesToUpdate, System};
+
+use crate::bridge::msg::ResourceUsage;
+
+/// cpu and memory of the process and its descendants, none if the process has exited
+pub fn sample(sys: &mut System, pid: u32) -> Option<ResourceUsage> {
+    sys.refresh_processes(ProcessesToUpdate::All, true);
+    let root = Pid::from_u32(pid);
+    sys.process(root)?;
+
+    let mut tree = HashSet::from([root]);
+    // parents may be listed after their children, repeat until no process is added
+    loop {
+        let n = tree.len();
+        for (pid, process) in sys.processes() {
+            if process.parent().is_some_and(|v| tree.contains(&v)) {
+                tree.insert(*pid);
+            }
+        }
+        if tree.len() == n {
+            break;
+        }
+    }
+
+    let mut usage = ResourceUsage {
+        sample_time: Utc::now(),
+        ..Default::default()
+    };
+    for pid in tree.iter() {
+        if let Some(process) = sys.process(*pid) {
+            usage.cpu_usage += process.cpu_usage();
+            usage.rss += process.memory();
+            usage.process_count += 1;
+        }
+    }
+    Some(usage)
+}
+
+#[test]
+fn test_sample_usage() {
+    let mut sys = System::new();
+    let usage = sample(&mut sys, std::process::id()).unwrap();
+    assert!(usage.rss > 0);
+    assert!(usage.process_count >= 1);
+    assert!(sample(&mut sys, u32::MAX).is_none());
+}
@@ -0,0 +1,396 @@
+//! fake agents speaking the bridge protocol, used to load test comet and the console
+//! without real instances. the jobs are never executed, a run reports its result after
+//! a random duration and fails at the configured rate
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+use serde_json::{Value, json};
+use tokio::time::sleep;
+use tracing::{debug, error, info};
+
+use crate::{
+    bridge::{
+        Bridge,
+        client::WsClient,
+        msg::{
+            DispatchAckParams, DispatchAckStatus, DispatchJobParams, HeartbeatParams,
+            InstanceFacts, MsgReqKind, UpdateJobParams,
+        },
+    },
+    get_endpoint,
+    scheduler::{
+        ledger::DispatchLedger,
+        types::{JobAction, RunStatus, ScheduleType},
+    },
+};
+
+pub const SIMULATOR_VERSION: &str = "simulator";
+
+#[derive(Debug, Clone)]
+pub struct SimulatorOption {
+    pub namespace: String,
+    pub comet_addr: Vec<String>,
+    pub comet_secret: String,
+    pub agents: u32,
+    /// ip of the first agent, the others take the following ips
+    pub ip_start: Ipv4Addr,
+    /// agents connected per second, 0 to connect all of them at once
+    pub connect_rate: u32,
+    pub heartbeat_interval: Duration,
+    /// the requests of comet are answered after a random delay in the range
+    pub min_latency: Duration,
+    pub max_latency: Duration,
+    pub min_run_duration: Duration,
+    pub max_run_duration: Duration,
+    /// percent of the dispatches rejected
+    pub reject_rate: u8,
+    /// percent of the runs exiting with code 1
+    pub failure_rate: u8,
+    /// percent chance every heartbeat interval that the connection is dropped and reconnected
+    pub disconnect_rate: u8,
+}
+
+#[derive(Debug, Default)]
+pub struct SimulatorStats {
+    pub connected: AtomicU64,
+    pub reconnects: AtomicU64,
+    pub heartbeats: AtomicU64,
+    pub heartbeat_errors: AtomicU64,
+    pub dispatches: AtomicU64,
+    pub rejected: AtomicU64,
+    pub runs: AtomicU64,
+    pub failed_runs: AtomicU64,
+}
+
+impl SimulatorStats {
+    pub fn summary(&self) -> String {
+        format!(
+            "connected: {}, reconnects: {}, heartbeats: {} ({} failed), dispatches: {} ({} rejected), runs: {} ({} failed)",
+            self.connected.load(Ordering::Relaxed),
+            self.reconnects.load(Ordering::Relaxed),
+            self.heartbeats.load(Ordering::Relaxed),
+            self.heartbeat_errors.load(Ordering::Relaxed),
+            self.dispatches.load(Ordering::Relaxed),
+            self.rejected.load(Ordering::Relaxed),
+            self.runs.load(Ordering::Relaxed),
+            self.failed_runs.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// ip of the nth agent
+pub fn agent_ip(start: Ipv4Addr, index: u32) -> Ipv4Addr {
+    Ipv4Addr::from(u32::from(start).wrapping_add(index))
+}
+
+/// locally administered mac address of the nth agent, never taken by a real interface
+pub fn agent_mac_addr(index: u32) -> String {
+    let [a, b, c, d] = index.to_be_bytes();
+    format!("02:00:{a:02x}:{b:02x}:{c:02x}:{d:02x}")
+}
+
+/// true at the rate in percent
+pub fn hit(rate: u8) -> bool {
+    rate > 0 && rand::random_range(0..100) < rate
+}
+
+fn random_duration(min: Duration, max: Duration) -> Duration {
+    if max <= min {
+        return min;
+    }
+    Duration::from_millis(rand::random_range(
+        min.as_millis() as u64..=max.as_millis() as u64,
+    ))
+}
+
+pub struct Simulator {
+    opts: Arc<SimulatorOption>,
+    bridge: Bridge,
+    stats: Arc<SimulatorStats>,
+}
+
+impl Simulator {
+    pub fn new(opts: SimulatorOption) -> Self {
+        Self {
+            opts: Arc::new(opts),
+            bridge: Bridge::new(),
+            stats: Arc::new(SimulatorStats::default()),
+        }
+    }
+
+    pub fn stats(&self) -> Arc<SimulatorStats> {
+        self.stats.clone()
+    }
+
+    /// connect the agents, they keep running and reconnecting until the process exits
+    pub async fn run(&self) -> Result<()> {
+        if self.opts.comet_addr.is_empty() {
+            anyhow::bail!("comet_addr is empty");
+        }
+        for index in 0..self.opts.agents {
+            let agent = SimAgent {
+                index,
+                ip: IpAddr::V4(agent_ip(self.opts.ip_start, index)),
+                mac_addr: agent_mac_addr(index),
+                opts: self.opts.clone(),
+                bridge: self.bridge.clone(),
+                stats: self.stats.clone(),
+                ledger: DispatchLedger::new(),
+            };
+            tokio::spawn(agent.run());
+            if self.opts.connect_rate > 0 {
+                sleep(Duration::from_secs(1) / self.opts.connect_rate).await;
+            }
+        }
+        info!("started {} simulated agents", self.opts.agents);
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+struct SimAgent {
+    index: u32,
+    ip: IpAddr,
+    mac_addr: String,
+    opts: Arc<SimulatorOption>,
+    bridge: Bridge,
+    stats: Arc<SimulatorStats>,
+    ledger: DispatchLedger,
+}
+
+impl SimAgent {
+    fn key(&self) -> String {
+        get_endpoint(self.ip.to_string(), self.mac_addr.clone())
+    }
+
+    async fn run(mut self) {
+        tokio::spawn(self.clone().heartbeat());
+
+        // spread the agents over the comets
+        let mut comet_addr = self.opts.comet_addr.clone();
+        let n = self.index as usize % comet_addr.len();
+        comet_addr.rotate_left(n);
+        let mut is_initialized = false;
+        loop {
+            let mut client = WsClient::new(Some(self.bridge.clone()));
+            client
+                .set_namespace(self.opts.namespace.clone())
+                .set_local_ip(self.ip)
+                .set_comet_secret(self.opts.comet_secret.clone())
+                .set_mac_address(self.mac_addr.clone())
+                .set_initialized(is_initialized);
+
+            let ws_addr = format!("{}/evt/{}", comet_addr[0], self.opts.namespace);
+            match client.connect(&ws_addr, &self.opts.comet_secret).await {
+                Ok(_) => {
+                    is_initialized = true;
+                    self.bridge.append_client(self.key(), client.sender()).await;
+                    self.stats.connected.fetch_add(1, Ordering::Relaxed);
+
+                    let agent = self.clone();
+                    tokio::select! {
+                        _ = client.recv(move |msg| async move { agent.handle(msg).await }) => {},
+                        _ = self.churn() => debug!("simulated disconnect of {}", self.key()),
+                    }
+                    client.drop().await;
+                    self.stats.connected.fetch_sub(1, Ordering::Relaxed);
+                }
+                Err(e) => error!("failed connect {} to {ws_addr} - {e}", self.key()),
+            }
+
+            self.stats.reconnects.fetch_add(1, Ordering::Relaxed);
+            sleep(Duration::from_secs(1)).await;
+            comet_addr.rotate_left(1);
+        }
+    }
+
+    /// returns when the connection should be dropped, never if the disconnect rate is 0
+    async fn churn(&self) {
+        loop {
+            sleep(self.opts.heartbeat_interval).await;
+            if hit(self.opts.disconnect_rate) {
+                return;
+            }
+        }
+    }
+
+    async fn heartbeat(self) {
+        loop {
+            let facts = InstanceFacts {
+                os: "linux".to_string(),
+                arch: std::env::consts::ARCH.to_string(),
+                cpu_count: 1,
+                agent_version: SIMULATOR_VERSION.to_string(),
+                ..Default::default()
+            };
+            match self
+                .send(MsgReqKind::HeartbeatRequest(HeartbeatParams {
+                    namespace: self.opts.namespace.clone(),
+                    mac_addr: self.mac_addr.clone(),
+                    source_ip: self.ip.to_string(),
+                    facts: Some(facts),
+                    agent_config: None,
+                }))
+                .await
+            {
+                Ok(_) => self.stats.heartbeats.fetch_add(1, Ordering::Relaxed),
+                Err(_) => self.stats.heartbeat_errors.fetch_add(1, Ordering::Relaxed),
+            };
+            sleep(self.opts.heartbeat_interval).await;
+        }
+    }
+
+    async fn send(&self, data: MsgReqKind) -> Result<Value> {
+        self.bridge.send_msg(&self.key(), data).await
+    }
+
+    /// answered like Scheduler::handle of the real agent
+    async fn handle(self, msg: MsgReqKind) -> Value {
+        sleep(random_duration(
+            self.opts.min_latency,
+            self.opts.max_latency,
+        ))
+        .await;
+        let ret = match msg {
+            MsgReqKind::DispatchJobRequest(v) => self.dispatch(v).await,
+            MsgReqKind::ReconcileDispatchRequest(v) => Ok(json!(self.ledger.reconcile(&v).await)),
+            MsgReqKind::PruneScheduleRequest(_) => Ok(json!([])),
+            _ => Err(anyhow!(
+                "the request is not supported by the simulated agent"
+            )),
+        };
+
+        match ret {
+            Ok(v) => json!({
+                "code": 20000,
+                "msg": "success",
+                "data": v,
+            }),
+            Err(e) => json!({
+                "code": 50000,
+                "msg": e.to_string(),
+            }),
+        }
+    }
+
+    /// the timers and supervisors are accepted but never run
+    async fn dispatch(&self, params: DispatchJobParams) -> Result<Value> {
+        self.stats.dispatches.fetch_add(1, Ordering::Relaxed);
+        let has_ack = !params.dispatch_id.is_empty();
+        if has_ack && let Some(ack) = self.ledger.accept(&params).await {
+            return Ok(json!({"duplicate": true, "ack": ack}));
+        }
+
+        if hit(self.opts.reject_rate) {
+            self.stats.rejected.fetch_add(1, Ordering::Relaxed);
+            let reason = "simulated dispatch failure".to_string();
+            if has_ack {
+                let nack = self.ledger.reject(&params, reason.clone()).await;
+                self.send_ack(nack);
+            }
+            anyhow::bail!(reason);
+        }
+        if has_ack {
+            self.send_ack(DispatchAckParams::new(&params, DispatchAckStatus::Ack));
+        }
+
+        if params.action != JobAction::Exec {
+            return Ok(json!(null));
+        }
+        if params.is_sync {
+            return self.simulate_run(params).await;
+        }
+        let agent = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = agent.simulate_run(params).await {
+                error!("failed simulate run of {} - {e}", agent.key());
+            }
+        });
+        Ok(json!(null))
+    }
+
+    fn send_ack(&self, ack: DispatchAckParams) {
+        let agent = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = agent.send(MsgReqKind::DispatchAckRequest(ack)).await {
+                error!("failed to send dispatch ack - {e}");
+            }
+        });
+    }
+
+    async fn simulate_run(&self, params: DispatchJobParams) -> Result<Value> {
+        self.stats.runs.fetch_add(1, Ordering::Relaxed);
+        let update = UpdateJobParams {
+            base_job: params.base_job.to_pure_job(),
+            schedule_id: params.schedule_id.clone(),
+            schedule_type: Some(ScheduleType::Once),
+            fields: params.fields.clone(),
+            instance_id: params.instance_id.clone().unwrap_or_default(),
+            bind_ip: self.ip.to_string(),
+            bind_namespace: self.opts.namespace.clone(),
+            created_user: params.created_user.clone(),
+            run_id: params.run_id.clone(),
+            request_id: params.request_id.clone(),
+            start_time: Some(Utc::now()),
+            ..Default::default()
+        };
+        self.send(MsgReqKind::UpdateJobRequest(UpdateJobParams {
+            run_status: Some(RunStatus::Running),
+            ..update.clone()
+        }))
+        .await?;
+
+        sleep(random_duration(
+            self.opts.min_run_duration,
+            self.opts.max_run_duration,
+        ))
+        .await;
+
+        let (exit_code, stdout) = if hit(self.opts.failure_rate) {
+            self.stats.failed_runs.fetch_add(1, Ordering::Relaxed);
+            (1, "simulated failure")
+        } else {
+            (0, "simulated success")
+        };
+        self.send(MsgReqKind::UpdateJobRequest(UpdateJobParams {
+            run_status: Some(RunStatus::Stop),
+            exit_code: Some(exit_code),
+            exit_status: Some(format!("exit status: {exit_code}")),
+            stdout: Some(stdout.to_string()),
+            stderr: Some(String::new()),
+            end_time: Some(Utc::now()),
+            ..update
+        }))
+        .await?;
+
+        Ok(json!({
+            "stdout": stdout,
+            "exit_code": exit_code,
+            "stderr": "",
+        }))
+    }
+}
+
+#[test]
+fn test_simulated_agent_identity() {
+    let start: Ipv4Addr = "10.0.0.250".parse().unwrap();
+    assert_eq!(agent_ip(start, 0).to_string(), "10.0.0.250");
+    assert_eq!(agent_ip(start, 10).to_string(), "10.0.1.4");
+    assert_eq!(agent_mac_addr(0), "02:00:00:00:00:00");
+    assert_eq!(agent_mac_addr(5000), "02:00:00:00:13:88");
+
+    assert!(!hit(0));
+    assert!(hit(100));
+    assert_eq!(
+        random_duration(Duration::from_millis(5), Duration::ZERO),
+        Duration::from_millis(5)
+    );
+}
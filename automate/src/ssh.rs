@@ -42,17 +42,44 @@ pub struct Session {
     session: client::Handle<Client>,
 }
 
+/// ssh private key in openssh or pem format
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct PrivateKey {
+    pub key: String,
+    pub passphrase: Option<String>,
+}
+
 pub struct ConnectParams<A: ToSocketAddrs, U: Into<String>, P: Into<String>> {
     pub user: U,
     pub password: P,
+    /// used instead of the password if set
+    pub private_key: Option<PrivateKey>,
     pub addrs: A,
 }
 
+async fn authenticate(
+    session: &mut client::Handle<Client>,
+    user: impl Into<String>,
+    password: impl Into<String>,
+    private_key: Option<PrivateKey>,
+) -> Result<bool> {
+    Ok(match private_key {
+        Some(v) => {
+            let key_pair = decode_secret_key(&v.key, v.passphrase.as_deref())?;
+            session
+                .authenticate_publickey(user, Arc::new(key_pair))
+                .await?
+        }
+        None => session.authenticate_password(user, password).await?,
+    })
+}
+
 impl Session {
     pub async fn connect<A: ToSocketAddrs, U: Into<String>, P: Into<String>>(
         ConnectParams {
             user,
             password,
+            private_key,
             addrs,
         }: ConnectParams<A, U, P>,
     ) -> Result<Self> {
@@ -68,7 +95,7 @@ impl Session {
         let mut session =
             timeout(Duration::from_secs(1), client::connect(config, addrs, sh)).await??;
 
-        let auth_res = session.authenticate_password(user, password).await?;
+        let auth_res = authenticate(&mut session, user, password, private_key).await?;
 
         if !auth_res {
             anyhow::bail!("Authentication failed");
@@ -319,11 +346,13 @@ pub async fn read_dir(
     port: u16,
     user: &str,
     password: &str,
+    private_key: Option<PrivateKey>,
     dir: Option<&str>,
 ) -> Result<DirDetail> {
     let ssh_session = Session::connect(ConnectParams {
         user,
         password,
+        private_key,
         addrs: ("127.0.0.1", port),
     })
     .await?;
@@ -383,6 +412,7 @@ pub async fn upload(
     port: u16,
     user: &str,
     password: &str,
+    private_key: Option<PrivateKey>,
     filepath: &str,
     data: Vec<u8>,
 ) -> Result<()> {
@@ -394,6 +424,7 @@ pub async fn upload(
     let ssh_session = Session::connect(ConnectParams {
         user,
         password,
+        private_key,
         addrs: ("127.0.0.1", port),
     })
     .await?;
@@ -416,12 +447,14 @@ pub async fn remove(
     port: u16,
     user: &str,
     password: &str,
+    private_key: Option<PrivateKey>,
     remove_type: &str,
     filepath: &str,
 ) -> Result<()> {
     let ssh_session = Session::connect(ConnectParams {
         user,
         password,
+        private_key,
         addrs: ("127.0.0.1", port),
     })
     .await?;
@@ -442,11 +475,13 @@ pub async fn download(
     port: u16,
     user: &str,
     password: &str,
+    private_key: Option<PrivateKey>,
     filepath: &str,
 ) -> Result<Vec<u8>> {
     let ssh_session = Session::connect(ConnectParams {
         user,
         password,
+        private_key,
         addrs: ("127.0.0.1", port),
     })
     .await?;
@@ -0,0 +1,189 @@
+use std::{fs, net::IpAddr, path::Path, sync::Arc};
+
+use anyhow::{Context, Result};
+use poem::listener::{RustlsCertificate, RustlsConfig};
+use rcgen::{
+    BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+    KeyUsagePurpose, SanType,
+};
+use reqwest::{Certificate, ClientBuilder, Identity};
+use serde::{Deserialize, Serialize};
+use tokio_tungstenite::Connector;
+
+/// pem files used for mutual tls between agent, comet and console
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TlsOptions {
+    /// ca certificate used to verify the peer
+    pub ca_file: String,
+    pub cert_file: String,
+    pub key_file: String,
+}
+
+impl TlsOptions {
+    /// tls is enabled only when all of the files are set
+    pub fn build(
+        ca_file: Option<String>,
+        cert_file: Option<String>,
+        key_file: Option<String>,
+    ) -> Result<Option<Self>> {
+        match (ca_file, cert_file, key_file) {
+            (None, None, None) => Ok(None),
+            (Some(ca_file), Some(cert_file), Some(key_file)) => Ok(Some(Self {
+                ca_file,
+                cert_file,
+                key_file,
+            })),
+            _ => anyhow::bail!("tls ca file, cert file and key file must be set together"),
+        }
+    }
+
+    fn read(file: &str) -> Result<Vec<u8>> {
+        let real_path = shellexpand::full(file)?.to_string();
+        fs::read(&real_path).with_context(|| format!("failed read {real_path}"))
+    }
+
+    /// server config of comet, clients without a certificate issued by the ca are refused
+    pub fn server_config(&self) -> Result<RustlsConfig> {
+        Ok(RustlsConfig::new()
+            .fallback(
+                RustlsCertificate::new()
+                    .cert(Self::read(&self.cert_file)?)
+                    .key(Self::read(&self.key_file)?),
+            )
+            .client_auth_required(Self::read(&self.ca_file)?))
+    }
+
+    /// trust the ca and present the client certificate in https requests
+    pub fn http_client(&self, builder: ClientBuilder) -> Result<ClientBuilder> {
+        let mut identity = Self::read(&self.cert_file)?;
+        identity.extend(Self::read(&self.key_file)?);
+        Ok(builder
+            .use_rustls_tls()
+            .add_root_certificate(Certificate::from_pem(&Self::read(&self.ca_file)?)?)
+            .identity(Identity::from_pem(&identity)?))
+    }
+
+    /// connector of the wss connection to comet
+    pub fn ws_connector(&self) -> Result<Connector> {
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut Self::read(&self.ca_file)?.as_slice()) {
+            roots.add(cert?)?;
+        }
+        let certs = rustls_pemfile::certs(&mut Self::read(&self.cert_file)?.as_slice())
+            .collect::<Result<Vec<_>, _>>()?;
+        let key = rustls_pemfile::private_key(&mut Self::read(&self.key_file)?.as_slice())?
+            .context("no private key found")?;
+
+        // both the ring and the aws-lc providers are linked, so the default one is ambiguous
+        let config = rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_client_auth_cert(certs, key)?;
+        Ok(Connector::Rustls(Arc::new(config)))
+    }
+}
+
+fn write_pem(out_dir: &str, name: &str, cert: &str, key: &str) -> Result<(String, String)> {
+    let out_dir = shellexpand::full(out_dir)?.to_string();
+    fs::create_dir_all(&out_dir)?;
+    let cert_file = Path::new(&out_dir).join(format!("{name}.crt"));
+    let key_file = Path::new(&out_dir).join(format!("{name}.key"));
+
+    fs::write(&cert_file, cert)?;
+    fs::write(&key_file, key)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&key_file, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok((
+        cert_file.to_string_lossy().to_string(),
+        key_file.to_string_lossy().to_string(),
+    ))
+}
+
+/// generate a self-signed ca, save as ca.crt and ca.key in out_dir
+pub fn generate_ca(common_name: &str, days: i64, out_dir: &str) -> Result<(String, String)> {
+    let mut params = CertificateParams::default();
+    params
+        .distinguished_name
+        .push(DnType::CommonName, common_name);
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    params.key_usages = vec![
+        KeyUsagePurpose::KeyCertSign,
+        KeyUsagePurpose::CrlSign,
+        KeyUsagePurpose::DigitalSignature,
+    ];
+    params.not_after = params.not_before + time::Duration::days(days);
+
+    let key = KeyPair::generate()?;
+    let cert = params.self_signed(&key)?;
+    write_pem(out_dir, "ca", &cert.pem(), &key.serialize_pem())
+}
+
+/// issue a certificate signed by the ca, it can be used as both server and client certificate,
+/// so the same file works for comet, agent and console
+pub fn issue_cert(
+    ca_cert_file: &str,
+    ca_key_file: &str,
+    common_name: &str,
+    hosts: Vec<String>,
+    days: i64,
+    out_dir: &str,
+) -> Result<(String, String)> {
+    let ca_key = KeyPair::from_pem(&String::from_utf8(TlsOptions::read(ca_key_file)?)?)?;
+    let ca_cert =
+        CertificateParams::from_ca_cert_pem(&String::from_utf8(TlsOptions::read(ca_cert_file)?)?)?
+            .self_signed(&ca_key)?;
+
+    let mut params = CertificateParams::default();
+    params
+        .distinguished_name
+        .push(DnType::CommonName, common_name);
+    params.subject_alt_names = hosts
+        .iter()
+        .map(|v| match v.parse::<IpAddr>() {
+            Ok(ip) => Ok(SanType::IpAddress(ip)),
+            Err(_) => Ok(SanType::DnsName(v.clone().try_into()?)),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    params.key_usages = vec![
+        KeyUsagePurpose::DigitalSignature,
+        KeyUsagePurpose::KeyEncipherment,
+    ];
+    params.extended_key_usages = vec![
+        ExtendedKeyUsagePurpose::ServerAuth,
+        ExtendedKeyUsagePurpose::ClientAuth,
+    ];
+    params.not_after = params.not_before + time::Duration::days(days);
+
+    let key = KeyPair::generate()?;
+    let cert = params.signed_by(&key, &ca_cert, &ca_key)?;
+    write_pem(out_dir, common_name, &cert.pem(), &key.serialize_pem())
+}
+
+#[test]
+fn test_issue_cert() {
+    let out_dir = std::env::temp_dir().join(format!("jiascheduler-tls-{}", nanoid::nanoid!()));
+    let out_dir = out_dir.to_string_lossy().to_string();
+
+    let (ca_cert, ca_key) = generate_ca("jiascheduler ca", 365, &out_dir).unwrap();
+    let (cert, key) = issue_cert(
+        &ca_cert,
+        &ca_key,
+        "comet",
+        vec!["localhost".to_string(), "127.0.0.1".to_string()],
+        365,
+        &out_dir,
+    )
+    .unwrap();
+
+    let opts = TlsOptions::build(Some(ca_cert), Some(cert), Some(key))
+        .unwrap()
+        .unwrap();
+    assert!(opts.ws_connector().is_ok());
+    assert!(TlsOptions::build(None, Some("a".to_string()), None).is_err());
+    let _ = fs::remove_dir_all(out_dir);
+}
@@ -11,9 +11,15 @@ pub struct Model {
     #[sea_orm(unique)]
     pub name: String,
     pub command: String,
+    pub platform_commands: Option<Json>,
+    pub min_agent_version: String,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub install_script: Option<String>,
     pub platform: String,
     pub info: String,
     pub read_code_from_stdin: i8,
+    pub container_image: String,
+    pub container_options: Option<Json>,
     pub created_user: String,
     pub updated_user: String,
     pub created_time: DateTimeLocal,
@@ -15,9 +15,27 @@ pub struct Model {
     pub instance_group_id: u64,
     pub info: String,
     pub status: i8,
+    pub maintenance: bool,
     pub sys_user: String,
     pub password: String,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub private_key: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub key_passphrase: Option<String>,
     pub ssh_port: u16,
+    pub bastion_instance_id: String,
+    pub os: String,
+    pub os_version: String,
+    pub kernel_version: String,
+    pub arch: String,
+    pub cpu_count: u32,
+    pub mem_total: u64,
+    pub mem_used: u64,
+    pub disk_total: u64,
+    pub disk_used: u64,
+    pub agent_version: String,
+    pub facts_updated_time: Option<DateTimeLocal>,
+    pub agent_config: Option<Json>,
     pub created_time: DateTimeLocal,
     pub updated_time: DateTimeLocal,
 }
@@ -11,6 +11,7 @@ pub struct Model {
     #[sea_orm(unique)]
     pub name: String,
     pub info: String,
+    pub bastion_instance_id: String,
     pub created_user: String,
     pub created_time: DateTimeLocal,
     pub updated_time: DateTimeLocal,
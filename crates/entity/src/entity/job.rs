@@ -27,9 +27,25 @@ pub struct Model {
     pub completed_callback: Option<Json>,
     pub is_public: i8,
     pub display_on_dashboard: bool,
+    pub is_disabled: bool,
+    pub is_singleton: bool,
+    #[serde(default)]
+    pub placement: String,
+    #[serde(default)]
+    pub dedup_window_secs: u32,
+    pub offline_policy: Option<Json>,
+    pub quorum: Option<Json>,
+    pub confirmation: Option<Json>,
     pub created_user: String,
     pub updated_user: String,
     pub args: Option<Json>,
+    pub http_check: Option<Json>,
+    pub output_parsers: Option<Json>,
+    pub exit_code_mapping: Option<Json>,
+    pub bundle_option: Option<Json>,
+    pub supervisor_option: Option<Json>,
+    pub timeout_action: Option<Json>,
+    pub output_limit: Option<Json>,
     pub created_time: DateTimeLocal,
     pub updated_time: DateTimeLocal,
     #[serde(default)]
@@ -37,6 +53,8 @@ pub struct Model {
     pub deleted_at: Option<DateTimeLocal>,
     #[serde(default)]
     pub deleted_by: String,
+    #[serde(default)]
+    pub version: u32,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
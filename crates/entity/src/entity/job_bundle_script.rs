@@ -26,6 +26,8 @@ pub struct Model {
     pub deleted_at: Option<DateTimeLocal>,
     #[serde(default)]
     pub deleted_by: String,
+    #[serde(default)]
+    pub version: u32,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
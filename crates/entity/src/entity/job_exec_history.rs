@@ -13,16 +13,31 @@ pub struct Model {
     pub eid: String,
     pub job_type: String,
     pub bundle_script_result: Option<Json>,
+    pub parsed_result: Option<Json>,
     pub exit_status: String,
     pub exit_code: i32,
+    #[serde(default)]
+    pub result_status: String,
     #[sea_orm(column_type = "Text")]
     pub output: String,
     pub start_time: Option<DateTimeLocal>,
     pub end_time: Option<DateTimeLocal>,
     pub run_id: String,
+    #[serde(default)]
+    pub parent_run_id: String,
+    #[serde(default)]
+    pub run_kind: String,
     pub created_time: DateTimeLocal,
     pub updated_time: DateTimeLocal,
     pub created_user: String,
+    pub rerun_of: u64,
+    pub exec_context: Option<Json>,
+    #[serde(default)]
+    pub timeout_action: String,
+    #[serde(default)]
+    pub output_truncated: bool,
+    #[serde(default)]
+    pub output_url: String,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
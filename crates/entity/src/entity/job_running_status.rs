@@ -17,6 +17,8 @@ pub struct Model {
     pub run_status: String,
     pub exit_status: String,
     pub exit_code: i32,
+    #[serde(default)]
+    pub result_status: String,
     pub dispatch_result: Option<Json>,
     pub start_time: Option<DateTimeLocal>,
     pub end_time: Option<DateTimeLocal>,
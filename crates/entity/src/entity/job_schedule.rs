@@ -17,6 +17,7 @@ pub struct Model {
     pub snapshot_data: Option<Json>,
     pub actual_args: Option<Json>,
     pub instance_ids: Option<Json>,
+    pub instance_overrides: Option<Json>,
     pub timer_expr: Option<Json>,
     pub restart_interval: i32,
     pub action: String,
@@ -21,6 +21,18 @@ pub struct Model {
     pub created_user: String,
     pub updated_user: String,
     pub actual_args: Option<Json>,
+    pub override_reason: String,
+    pub placement: Option<Json>,
+    #[serde(default)]
+    pub quorum_status: String,
+    #[serde(default)]
+    pub quorum_required: u32,
+    #[serde(default)]
+    pub target_total: u32,
+    #[serde(default)]
+    pub success_total: u32,
+    #[serde(default)]
+    pub failure_total: u32,
     pub created_time: DateTimeLocal,
     pub updated_time: DateTimeLocal,
     #[serde(default)]
@@ -2,26 +2,61 @@
 
 pub mod prelude;
 
+pub mod agent_config;
 pub mod agent_release_version;
+pub mod blackout_window;
 pub mod casbin_rule;
+pub mod code_policy_rule;
+pub mod code_policy_waiver;
+pub mod dashboard;
+pub mod dispatch_stat;
+pub mod emergency_token;
 pub mod executor;
+pub mod executor_acl;
 pub mod instance;
+pub mod instance_credential;
+pub mod instance_executor;
 pub mod instance_group;
 pub mod instance_role;
+pub mod instance_status_log;
 pub mod job;
 pub mod job_bundle_script;
+pub mod job_daily_stat;
+pub mod job_duration_anomaly;
+pub mod job_duration_stat;
 pub mod job_exec_history;
+pub mod job_pending_dispatch;
+pub mod job_promotion;
+pub mod job_remediation;
+pub mod job_remediation_log;
 pub mod job_running_status;
 pub mod job_schedule;
 pub mod job_schedule_history;
+pub mod job_share;
+pub mod job_sla;
+pub mod job_sla_breach;
 pub mod job_supervisor;
+pub mod job_supervisor_metric;
 pub mod job_timer;
+pub mod job_trigger;
+pub mod job_trigger_log;
+pub mod job_usage_stat;
+pub mod report;
 pub mod role;
+pub mod saved_filter;
+pub mod server_task;
+pub mod sftp_edit_log;
+pub mod status_page;
 pub mod tag;
 pub mod tag_resource;
 pub mod team;
 pub mod team_member;
+pub mod team_quota;
+pub mod team_upload_file;
 pub mod user;
+pub mod user_group;
+pub mod user_group_member;
+pub mod user_group_team;
 pub mod user_server;
 pub mod workflow;
 pub mod workflow_process;
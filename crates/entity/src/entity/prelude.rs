@@ -1,26 +1,61 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.0
 
+pub use super::agent_config::Entity as AgentConfig;
 pub use super::agent_release_version::Entity as AgentReleaseVersion;
+pub use super::blackout_window::Entity as BlackoutWindow;
 pub use super::casbin_rule::Entity as CasbinRule;
+pub use super::code_policy_rule::Entity as CodePolicyRule;
+pub use super::code_policy_waiver::Entity as CodePolicyWaiver;
+pub use super::dashboard::Entity as Dashboard;
+pub use super::dispatch_stat::Entity as DispatchStat;
+pub use super::emergency_token::Entity as EmergencyToken;
 pub use super::executor::Entity as Executor;
+pub use super::executor_acl::Entity as ExecutorAcl;
 pub use super::instance::Entity as Instance;
+pub use super::instance_credential::Entity as InstanceCredential;
+pub use super::instance_executor::Entity as InstanceExecutor;
 pub use super::instance_group::Entity as InstanceGroup;
 pub use super::instance_role::Entity as InstanceRole;
+pub use super::instance_status_log::Entity as InstanceStatusLog;
 pub use super::job::Entity as Job;
 pub use super::job_bundle_script::Entity as JobBundleScript;
+pub use super::job_daily_stat::Entity as JobDailyStat;
+pub use super::job_duration_anomaly::Entity as JobDurationAnomaly;
+pub use super::job_duration_stat::Entity as JobDurationStat;
 pub use super::job_exec_history::Entity as JobExecHistory;
+pub use super::job_pending_dispatch::Entity as JobPendingDispatch;
+pub use super::job_promotion::Entity as JobPromotion;
+pub use super::job_remediation::Entity as JobRemediation;
+pub use super::job_remediation_log::Entity as JobRemediationLog;
 
 pub use super::job_running_status::Entity as JobRunningStatus;
 pub use super::job_schedule::Entity as JobSchedule;
 pub use super::job_schedule_history::Entity as JobScheduleHistory;
+pub use super::job_share::Entity as JobShare;
+pub use super::job_sla::Entity as JobSla;
+pub use super::job_sla_breach::Entity as JobSlaBreach;
 pub use super::job_supervisor::Entity as JobSupervisor;
+pub use super::job_supervisor_metric::Entity as JobSupervisorMetric;
 pub use super::job_timer::Entity as JobTimer;
+pub use super::job_trigger::Entity as JobTrigger;
+pub use super::job_trigger_log::Entity as JobTriggerLog;
+pub use super::job_usage_stat::Entity as JobUsageStat;
+pub use super::report::Entity as Report;
 pub use super::role::Entity as Role;
+pub use super::saved_filter::Entity as SavedFilter;
+pub use super::server_task::Entity as ServerTask;
+pub use super::sftp_edit_log::Entity as SftpEditLog;
+pub use super::status_page::Entity as StatusPage;
 pub use super::tag::Entity as Tag;
 pub use super::tag_resource::Entity as TagResource;
 pub use super::team::Entity as Team;
 pub use super::team_member::Entity as TeamMember;
+pub use super::team_quota::Entity as TeamQuota;
+pub use super::team_upload_file::Entity as TeamUploadFile;
 pub use super::user::Entity as User;
+pub use super::user_group::Entity as UserGroup;
+pub use super::user_group_member::Entity as UserGroupMember;
+pub use super::user_group_team::Entity as UserGroupTeam;
 pub use super::user_server::Entity as UserServer;
 pub use super::workflow::Entity as Workflow;
 pub use super::workflow_process::Entity as WorkflowProcess;
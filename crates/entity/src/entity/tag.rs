@@ -9,6 +9,7 @@ pub struct Model {
     #[sea_orm(primary_key)]
     pub id: u64,
     pub tag_name: String,
+    pub parent_id: u64,
     pub created_user: String,
     pub created_time: DateTimeLocal,
 }
@@ -14,14 +14,17 @@ pub struct Model {
     pub username: String,
     pub nickname: String,
     pub is_root: bool,
+    pub is_disabled: bool,
     pub role_id: u64,
     pub salt: String,
     pub password: String,
+    pub password_expired: bool,
     pub avatar: String,
     pub email: String,
     pub phone: String,
     pub gender: String,
     pub introduction: String,
+    pub timezone: String,
     pub created_time: DateTimeLocal,
     pub updated_time: DateTimeLocal,
 }
@@ -1,15 +1,87 @@
 use nanoid::nanoid;
-use redis::{AsyncCommands, Client, RedisResult};
-use tokio::time::sleep;
+use redis::{AsyncCommands, Client, RedisResult, Script};
+use tokio::{
+    sync::watch,
+    time::{Instant, sleep},
+};
 
-use std::{pin::Pin, time::Duration};
+use std::{collections::HashMap, pin::Pin, time::Duration};
+
+/// take the leadership if the key is free, or renew it if it is held by us.
+/// returns the fencing token of the term, 0 if another node is the leader,
+/// -1 if a step-down was requested for us
+const ACQUIRE_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[4]) == ARGV[1] then
+    if redis.call('GET', KEYS[1]) == ARGV[1] then
+        redis.call('DEL', KEYS[1], KEYS[3])
+    end
+    redis.call('DEL', KEYS[4])
+    return -1
+end
+local current = redis.call('GET', KEYS[1])
+if current == ARGV[1] then
+    redis.call('PEXPIRE', KEYS[1], ARGV[2])
+    redis.call('PEXPIRE', KEYS[3], ARGV[2])
+    return tonumber(redis.call('HGET', KEYS[3], 'token'))
+end
+if current then
+    return 0
+end
+if not redis.call('SET', KEYS[1], ARGV[1], 'NX', 'PX', ARGV[2]) then
+    return 0
+end
+local token = redis.call('INCR', KEYS[2])
+redis.call('HSET', KEYS[3], 'id', ARGV[1], 'name', ARGV[3], 'token', token, 'since', ARGV[4])
+redis.call('PEXPIRE', KEYS[3], ARGV[2])
+return token
+"#;
+
+/// delete the key only if it is still held by us
+const RELEASE_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    redis.call('DEL', KEYS[1], KEYS[2])
+    return 1
+end
+return 0
+"#;
+
+/// the node currently holding the leadership
+#[derive(Debug, Clone, Default)]
+pub struct LeaderInfo {
+    pub id: String,
+    pub name: String,
+    /// increased on every change of leadership, writers can reject stale leaders with it
+    pub token: u64,
+    /// unix timestamp in milliseconds the term started
+    pub since: i64,
+    /// remaining milliseconds before the leadership expires if not renewed
+    pub ttl_ms: i64,
+}
+
+impl LeaderInfo {
+    fn from_hash(mut v: HashMap<String, String>, ttl_ms: i64) -> Option<Self> {
+        Some(Self {
+            id: v.remove("id")?,
+            name: v.remove("name").unwrap_or_default(),
+            token: v.get("token")?.parse().ok()?,
+            since: v
+                .get("since")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or_default(),
+            ttl_ms,
+        })
+    }
+}
 
 pub struct LeaderElection {
     redis_client: Client,
     key: String,
     id: String,
+    name: String,
     ttl: i64,
     check_interval: Duration,
+    token: u64,
+    shutdown: Option<watch::Receiver<bool>>,
 }
 
 impl LeaderElection {
@@ -18,53 +90,207 @@ impl LeaderElection {
             redis_client: client,
             key: key.to_string(),
             id: format!("{}", nanoid!()),
+            name: String::new(),
             ttl,
-            check_interval: Duration::from_secs((ttl / 2) as u64),
+            check_interval: Duration::from_secs((ttl / 3).max(1) as u64),
+            token: 0,
+            shutdown: None,
         })
     }
 
-    async fn acquire_leadership(&mut self) -> RedisResult<bool> {
+    /// human readable name of the node, eg: the address it listens on
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// stop the election and release the leadership once shutdown is set
+    pub fn with_shutdown(mut self, shutdown: watch::Receiver<bool>) -> Self {
+        self.shutdown = Some(shutdown);
+        self
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// fencing token of the current term, 0 if not the leader
+    pub fn token(&self) -> u64 {
+        self.token
+    }
+
+    fn fencing_key(key: &str) -> String {
+        format!("{key}:fencing")
+    }
+
+    fn info_key(key: &str) -> String {
+        format!("{key}:info")
+    }
+
+    fn step_down_key(key: &str) -> String {
+        format!("{key}:step_down")
+    }
+
+    async fn acquire_leadership(&mut self) -> RedisResult<i64> {
         let mut conn = self.redis_client.get_multiplexed_async_connection().await?;
 
-        let acquired: bool = conn.set_nx(&self.key, &self.id).await?;
-        if acquired {
-            conn.expire::<_, ()>(&self.key, self.ttl).await?;
-            return Ok(true);
-        }
+        Script::new(ACQUIRE_SCRIPT)
+            .key(&self.key)
+            .key(Self::fencing_key(&self.key))
+            .key(Self::info_key(&self.key))
+            .key(Self::step_down_key(&self.key))
+            .arg(&self.id)
+            .arg(self.ttl * 1000)
+            .arg(&self.name)
+            .arg(now_millis())
+            .invoke_async(&mut conn)
+            .await
+    }
 
-        let current_id: Option<String> = conn.get(&self.key).await?;
-        if current_id.as_ref() == Some(&self.id) {
-            conn.expire::<_, ()>(&self.key, self.ttl).await?;
-            return Ok(true);
+    /// give up the leadership if it is held by this node
+    pub async fn release(&mut self) -> RedisResult<bool> {
+        let mut conn = self.redis_client.get_multiplexed_async_connection().await?;
+        self.token = 0;
+        let released: i64 = Script::new(RELEASE_SCRIPT)
+            .key(&self.key)
+            .key(Self::info_key(&self.key))
+            .arg(&self.id)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(released == 1)
+    }
+
+    /// the current leader of the key, None if there is none
+    pub async fn current_leader(client: &Client, key: &str) -> RedisResult<Option<LeaderInfo>> {
+        let mut conn = client.get_multiplexed_async_connection().await?;
+        let ttl_ms: i64 = conn.pttl(key).await?;
+        if ttl_ms < 0 {
+            return Ok(None);
         }
+        let info: HashMap<String, String> = conn.hgetall(Self::info_key(key)).await?;
+        Ok(LeaderInfo::from_hash(info, ttl_ms))
+    }
+
+    /// ask the current leader to step down, it releases the leadership on its next renewal
+    /// and does not run for the next term, so another node can take over.
+    /// returns the leader asked to step down
+    pub async fn request_step_down(
+        client: &Client,
+        key: &str,
+        ttl: i64,
+    ) -> RedisResult<Option<LeaderInfo>> {
+        let Some(leader) = Self::current_leader(client, key).await? else {
+            return Ok(None);
+        };
+        let mut conn = client.get_multiplexed_async_connection().await?;
+        conn.pset_ex::<_, _, ()>(Self::step_down_key(key), &leader.id, (ttl * 1000) as u64)
+            .await?;
+        Ok(Some(leader))
+    }
 
-        Ok(false)
+    async fn wait(&mut self, duration: Duration) -> bool {
+        match self.shutdown {
+            Some(ref mut shutdown) => tokio::select! {
+                _ = sleep(duration) => false,
+                _ = shutdown.wait_for(|v| *v) => true,
+            },
+            None => {
+                sleep(duration).await;
+                false
+            }
+        }
     }
 
+    /// campaign until shutdown, the callback is called whenever the leadership changes.
+    /// the leadership is considered lost once it cannot be renewed before the ttl expires,
+    /// even if redis is unreachable
     pub async fn run_election<F>(&mut self, mut leader_callback: F) -> RedisResult<()>
     where
         F: Sync + Send + FnMut(bool) -> Pin<Box<dyn Future<Output = ()> + Send>>,
     {
         let mut is_leader = false;
+        let mut renewed_at = Instant::now();
+        let ttl = Duration::from_secs(self.ttl.max(0) as u64);
+        let lease = lease_after_renewal(ttl, self.check_interval);
 
         loop {
-            match self.acquire_leadership().await {
-                Ok(acquired) => {
+            let next_check = match self.acquire_leadership().await {
+                Ok(token) => {
+                    let acquired = token > 0;
+                    self.token = token.max(0) as u64;
+                    if acquired {
+                        renewed_at = Instant::now();
+                    }
                     if acquired != is_leader {
                         is_leader = acquired;
                         leader_callback(is_leader).await;
                     }
-                    if is_leader {
-                        sleep(self.check_interval).await;
+
+                    if token < 0 {
+                        // stepped down, sit out a term
+                        ttl
+                    } else if is_leader {
+                        self.check_interval
                     } else {
-                        sleep(Duration::from_secs(1)).await;
+                        Duration::from_secs(1)
                     }
                 }
                 Err(e) => {
                     eprintln!("Leader election error: {:?}", e);
-                    sleep(Duration::from_secs(5)).await;
+                    if is_leader && renewed_at.elapsed() >= lease {
+                        is_leader = false;
+                        self.token = 0;
+                        leader_callback(false).await;
+                    }
+                    Duration::from_secs(1)
                 }
+            };
+
+            if self.wait(next_check).await {
+                break;
             }
         }
+
+        if is_leader {
+            leader_callback(false).await;
+        }
+        self.release().await?;
+        Ok(())
     }
 }
+
+/// how long the leadership is kept after the last renewal when redis is unreachable,
+/// a check interval before the ttl expires, none if the ttl is shorter than the interval
+fn lease_after_renewal(ttl: Duration, check_interval: Duration) -> Duration {
+    ttl.saturating_sub(check_interval)
+}
+
+fn now_millis() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |v| v.as_millis() as i64)
+}
+
+#[test]
+fn test_leader_info_from_hash() {
+    let info = HashMap::from([
+        ("id".to_string(), "abc".to_string()),
+        ("name".to_string(), "10.0.0.1:9090".to_string()),
+        ("token".to_string(), "7".to_string()),
+        ("since".to_string(), "1700000000000".to_string()),
+    ]);
+    let leader = LeaderInfo::from_hash(info, 5000).unwrap();
+    assert_eq!(leader.token, 7);
+    assert_eq!(leader.name, "10.0.0.1:9090");
+
+    assert!(LeaderInfo::from_hash(HashMap::new(), 5000).is_none());
+}
+
+#[test]
+fn test_lease_after_renewal() {
+    let secs = Duration::from_secs;
+    assert_eq!(lease_after_renewal(secs(30), secs(10)), secs(20));
+    assert_eq!(lease_after_renewal(secs(1), secs(1)), Duration::ZERO);
+    // the ttl shorter than the check interval, eg: ttl 0
+    assert_eq!(lease_after_renewal(secs(0), secs(1)), Duration::ZERO);
+}
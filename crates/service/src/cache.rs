@@ -0,0 +1,100 @@
+use std::future::Future;
+
+use anyhow::Result;
+use redis::{AsyncCommands, Client};
+use serde::{Serialize, de::DeserializeOwned};
+use tracing::warn;
+
+/// memberships of the teams, invalidated on the team and member changes
+pub const CACHE_TEAM_MEMBER: &str = "team_member";
+/// instances visible to the users, invalidated on the instance, grant and role changes
+pub const CACHE_USER_SERVER: &str = "user_server";
+/// health of the status pages by token, invalidated on the page changes
+pub const CACHE_STATUS_PAGE: &str = "status_page";
+
+/// lookups cached in redis so that all the consoles see the same invalidation.
+/// every namespace has a version, invalidating a namespace bumps the version and
+/// the entries of the old version expire with their ttl
+#[derive(Clone)]
+pub struct Cache {
+    redis: Client,
+}
+
+fn version_key(namespace: &str) -> String {
+    format!("jiascheduler:cache:{namespace}:version")
+}
+
+fn entry_key(namespace: &str, version: u64, key: &str) -> String {
+    format!("jiascheduler:cache:{namespace}:{version}:{key}")
+}
+
+impl Cache {
+    pub fn new(redis: Client) -> Self {
+        Self { redis }
+    }
+
+    /// the cached value of the key, or the value of the loader which is cached for ttl_secs.
+    /// the loader is used directly if redis is unavailable
+    pub async fn get_or_load<T, F, Fut>(
+        &self,
+        namespace: &str,
+        key: &str,
+        ttl_secs: u64,
+        loader: F,
+    ) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut conn = match self.redis.get_multiplexed_async_connection().await {
+            Ok(v) => v,
+            Err(e) => {
+                warn!("cache is unavailable - {e}");
+                return loader().await;
+            }
+        };
+
+        let version: Option<u64> = conn.get(version_key(namespace)).await.unwrap_or_default();
+        let entry_key = entry_key(namespace, version.unwrap_or_default(), key);
+        let cached: Option<String> = conn.get(&entry_key).await.unwrap_or_default();
+        if let Some(v) = cached.and_then(|v| serde_json::from_str(&v).ok()) {
+            return Ok(v);
+        }
+
+        let val = loader().await?;
+        let ret: redis::RedisResult<()> = conn
+            .set_ex(&entry_key, serde_json::to_string(&val)?, ttl_secs)
+            .await;
+        if let Err(e) = ret {
+            warn!("failed cache {entry_key} - {e}");
+        }
+        Ok(val)
+    }
+
+    /// drop all the entries of the namespace, a failure is logged and
+    /// the entries are stale until they expire
+    pub async fn invalidate(&self, namespace: &str) {
+        let ret: Result<()> = async {
+            let mut conn = self.redis.get_multiplexed_async_connection().await?;
+            let _: u64 = conn.incr(version_key(namespace), 1).await?;
+            Ok(())
+        }
+        .await;
+        if let Err(e) = ret {
+            warn!("failed invalidate cache {namespace} - {e}");
+        }
+    }
+}
+
+#[test]
+fn test_cache_key() {
+    assert_eq!(
+        version_key(CACHE_TEAM_MEMBER),
+        "jiascheduler:cache:team_member:version"
+    );
+    assert_eq!(
+        entry_key(CACHE_USER_SERVER, 3, "u-1:i-1"),
+        "jiascheduler:cache:user_server:3:u-1:i-1"
+    );
+}
@@ -0,0 +1,149 @@
+use std::{
+    collections::VecDeque,
+    str::FromStr,
+    sync::{LazyLock, Mutex},
+    time::Duration,
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use sea_orm::{DatabaseConnection, SqlxMySqlConnector};
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    ConnectOptions, Executor,
+    mysql::{MySqlConnectOptions, MySqlPoolOptions},
+};
+use tracing::warn;
+
+use crate::config::DbPool;
+
+/// slow queries kept for the diagnostics, the oldest are dropped
+pub const SLOW_QUERY_CAPACITY: usize = 100;
+
+/// statements longer than this are truncated in the slow-query log
+const SLOW_QUERY_MAX_LEN: usize = 2000;
+
+static SLOW_QUERIES: LazyLock<Mutex<VecDeque<SlowQuery>>> =
+    LazyLock::new(|| Mutex::new(VecDeque::with_capacity(SLOW_QUERY_CAPACITY)));
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SlowQuery {
+    /// the statement with the parameters inlined
+    pub statement: String,
+    pub elapsed_ms: u64,
+    pub failed: bool,
+    pub time: DateTime<Local>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PoolStatus {
+    pub size: u32,
+    pub idle: u32,
+    pub max_connections: u32,
+}
+
+fn truncate_statement(statement: String) -> String {
+    if statement.chars().count() <= SLOW_QUERY_MAX_LEN {
+        return statement;
+    }
+    statement
+        .chars()
+        .take(SLOW_QUERY_MAX_LEN)
+        .chain("...".chars())
+        .collect()
+}
+
+fn record_slow_query(query: SlowQuery) {
+    let mut queries = SLOW_QUERIES.lock().unwrap();
+    if queries.len() >= SLOW_QUERY_CAPACITY {
+        queries.pop_front();
+    }
+    queries.push_back(query);
+}
+
+/// recent slow queries of this console, the latest first
+pub fn slow_queries() -> Vec<SlowQuery> {
+    SLOW_QUERIES.lock().unwrap().iter().rev().cloned().collect()
+}
+
+pub fn pool_status(db: &DatabaseConnection, opts: &DbPool) -> PoolStatus {
+    let pool = db.get_mysql_connection_pool();
+    PoolStatus {
+        size: pool.size(),
+        idle: pool.num_idle() as u32,
+        max_connections: opts.max_connections,
+    }
+}
+
+/// connect the database with the pool options, the statements slower than
+/// slow_query_ms are logged and kept for the diagnostics
+pub async fn connect(database_url: &str, opts: &DbPool) -> Result<DatabaseConnection> {
+    let connect_opts = MySqlConnectOptions::from_str(database_url)?.disable_statement_logging();
+
+    let statement_timeout_ms = opts.statement_timeout_secs * 1000;
+    let pool = MySqlPoolOptions::new()
+        .max_connections(opts.max_connections)
+        .min_connections(opts.min_connections)
+        .acquire_timeout(Duration::from_secs(opts.acquire_timeout_secs))
+        .idle_timeout(
+            (opts.idle_timeout_secs > 0).then(|| Duration::from_secs(opts.idle_timeout_secs)),
+        )
+        .after_connect(move |conn, _| {
+            Box::pin(async move {
+                if statement_timeout_ms > 0 {
+                    conn.execute(
+                        format!("SET SESSION max_execution_time = {statement_timeout_ms}").as_str(),
+                    )
+                    .await?;
+                }
+                Ok(())
+            })
+        })
+        .connect_with(connect_opts)
+        .await?;
+
+    let mut db = SqlxMySqlConnector::from_sqlx_mysql_pool(pool);
+    if opts.slow_query_ms > 0 {
+        let threshold = Duration::from_millis(opts.slow_query_ms);
+        db.set_metric_callback(move |info| {
+            if info.elapsed < threshold {
+                return;
+            }
+            let query = SlowQuery {
+                statement: truncate_statement(info.statement.to_string()),
+                elapsed_ms: info.elapsed.as_millis() as u64,
+                failed: info.failed,
+                time: Local::now(),
+            };
+            warn!(
+                elapsed_ms = query.elapsed_ms,
+                failed = query.failed,
+                "slow query: {}",
+                query.statement
+            );
+            record_slow_query(query);
+        });
+    }
+    Ok(db)
+}
+
+#[test]
+fn test_slow_query_log() {
+    for i in 0..SLOW_QUERY_CAPACITY + 5 {
+        record_slow_query(SlowQuery {
+            statement: format!("SELECT {i}"),
+            elapsed_ms: 1000,
+            failed: false,
+            time: Local::now(),
+        });
+    }
+    let queries = slow_queries();
+    assert_eq!(queries.len(), SLOW_QUERY_CAPACITY);
+    assert_eq!(
+        queries[0].statement,
+        format!("SELECT {}", SLOW_QUERY_CAPACITY + 4)
+    );
+
+    let statement = truncate_statement("x".repeat(SLOW_QUERY_MAX_LEN + 1));
+    assert_eq!(statement.len(), SLOW_QUERY_MAX_LEN + 3);
+}
@@ -0,0 +1,152 @@
+//! typed events of the console. the producers emit them on the bus, the features reacting
+//! to them (webhooks, stats, live views, audit) subscribe to the bus instead of being
+//! called by the producers
+
+pub mod live;
+mod subscriber;
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use automate::bridge::msg::UpdateJobParams;
+use serde_json::Value;
+use tracing::error;
+
+use crate::{entity::job, logic::job::types::ExecResultStatus, state::AppContext};
+
+pub use subscriber::{
+    AuditLogSubscriber, InstanceStatusLogSubscriber, LiveSubscriber, LogSinkSubscriber,
+    StatsSubscriber, WebhookSubscriber,
+};
+
+#[derive(Clone)]
+pub enum JobEvent {
+    /// the running status reported by the agent is written
+    StatusChanged {
+        params: Arc<UpdateJobParams>,
+        result_status: Option<ExecResultStatus>,
+    },
+    /// the supervised job restarted too often, the agent stopped restarting it
+    Flapping { params: Arc<UpdateJobParams> },
+    /// a run finished and its exec history is written
+    RunCompleted {
+        params: Arc<UpdateJobParams>,
+        result_status: ExecResultStatus,
+        parsed_result: Option<Value>,
+        /// the job snapshot of the schedule
+        job_record: Option<Arc<job::Model>>,
+    },
+}
+
+#[derive(Clone)]
+pub enum InstanceEvent {
+    Online {
+        instance_id: String,
+        ip: String,
+        namespace: String,
+    },
+    Offline {
+        instance_id: String,
+        ip: String,
+        namespace: String,
+    },
+}
+
+/// an externally initiated action on a job, eg: a trigger invocation or a remediation
+#[derive(Clone)]
+pub struct AuditEvent {
+    pub action: &'static str,
+    pub eid: String,
+    /// the remote ip of the trigger, the alert of the remediation
+    pub source: String,
+    pub status: String,
+    pub reason: String,
+    pub schedule_id: String,
+}
+
+#[derive(Clone)]
+pub enum BusEvent {
+    Job(JobEvent),
+    Instance(InstanceEvent),
+    Audit(AuditEvent),
+}
+
+impl BusEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BusEvent::Job(JobEvent::StatusChanged { .. }) => "job.status_changed",
+            BusEvent::Job(JobEvent::Flapping { .. }) => "job.flapping",
+            BusEvent::Job(JobEvent::RunCompleted { .. }) => "job.run_completed",
+            BusEvent::Instance(InstanceEvent::Online { .. }) => "instance.online",
+            BusEvent::Instance(InstanceEvent::Offline { .. }) => "instance.offline",
+            BusEvent::Audit(_) => "audit",
+        }
+    }
+}
+
+impl From<JobEvent> for BusEvent {
+    fn from(value: JobEvent) -> Self {
+        BusEvent::Job(value)
+    }
+}
+
+impl From<InstanceEvent> for BusEvent {
+    fn from(value: InstanceEvent) -> Self {
+        BusEvent::Instance(value)
+    }
+}
+
+impl From<AuditEvent> for BusEvent {
+    fn from(value: AuditEvent) -> Self {
+        BusEvent::Audit(value)
+    }
+}
+
+#[async_trait]
+pub trait Subscriber: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// the events the subscriber is not interested in are ignored
+    async fn handle(&self, ctx: &AppContext, event: &BusEvent) -> Result<()>;
+}
+
+/// in-process bus of the console, the events are handled by the subscribers
+/// of the console emitting them
+#[derive(Clone, Default)]
+pub struct EventBus {
+    subscribers: Arc<Vec<Arc<dyn Subscriber>>>,
+}
+
+impl EventBus {
+    /// the bus with the subscribers of the built-in features
+    pub fn with_default_subscribers() -> Self {
+        Self::default()
+            .subscribe(WebhookSubscriber)
+            .subscribe(StatsSubscriber)
+            .subscribe(LiveSubscriber)
+            .subscribe(AuditLogSubscriber)
+            .subscribe(LogSinkSubscriber)
+            .subscribe(InstanceStatusLogSubscriber)
+    }
+
+    pub fn subscribe(mut self, subscriber: impl Subscriber + 'static) -> Self {
+        Arc::make_mut(&mut self.subscribers).push(Arc::new(subscriber));
+        self
+    }
+
+    /// the subscribers handle the event in the order they subscribed,
+    /// a failed one is logged and does not stop the others
+    pub async fn emit(&self, ctx: &AppContext, event: impl Into<BusEvent>) {
+        let event = event.into();
+        for subscriber in self.subscribers.iter() {
+            if let Err(e) = subscriber.handle(ctx, &event).await {
+                error!(
+                    "{} failed to handle {} - {e}",
+                    subscriber.name(),
+                    event.name()
+                );
+            }
+        }
+    }
+}
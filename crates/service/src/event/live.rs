@@ -0,0 +1,243 @@
+//! changes pushed to the live views of the console, fanned out through redis to the
+//! subscribers connected to any console
+
+use std::time::Duration;
+
+use anyhow::Result;
+use futures::{Stream, StreamExt};
+use redis::Client;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// the events of all the consoles are published on the channel, so that a subscriber
+/// connected to any console receives them
+pub const EVENT_CHANNEL: &str = "jiascheduler:events";
+/// the latest events are also kept in a stream, read by the long polling clients from
+/// the id of the last event they received
+pub const EVENT_LOG: &str = "jiascheduler:events:log";
+pub const EVENT_LOG_LEN: u64 = 1000;
+/// events returned by one read of the log
+const EVENT_READ_COUNT: u64 = 100;
+
+/// the id and the field-value pairs of a log entry
+type StreamEntry = (String, Vec<String>);
+
+/// changes pushed to the live views of the console
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum LiveEvent {
+    /// the running status of the job on the instance changed
+    RunStatus {
+        eid: String,
+        instance_id: String,
+        schedule_id: String,
+        schedule_type: Option<String>,
+        run_status: Option<String>,
+        schedule_status: Option<String>,
+        exit_code: Option<i32>,
+        result_status: Option<String>,
+    },
+    /// a run of the job completed
+    ExecHistory {
+        eid: String,
+        instance_id: String,
+        schedule_id: String,
+        exit_code: i32,
+        result_status: String,
+        start_time: Option<String>,
+        end_time: Option<String>,
+    },
+    /// the instance went online or offline
+    InstanceStatus {
+        instance_id: String,
+        ip: String,
+        namespace: String,
+        online: bool,
+    },
+}
+
+impl LiveEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LiveEvent::RunStatus { .. } => "run_status",
+            LiveEvent::ExecHistory { .. } => "exec_history",
+            LiveEvent::InstanceStatus { .. } => "instance_status",
+        }
+    }
+
+    /// the job of the event, none for the instance events
+    pub fn eid(&self) -> Option<&str> {
+        match self {
+            LiveEvent::RunStatus { eid, .. } | LiveEvent::ExecHistory { eid, .. } => Some(eid),
+            LiveEvent::InstanceStatus { .. } => None,
+        }
+    }
+
+    pub fn instance_id(&self) -> &str {
+        match self {
+            LiveEvent::RunStatus { instance_id, .. }
+            | LiveEvent::ExecHistory { instance_id, .. }
+            | LiveEvent::InstanceStatus { instance_id, .. } => instance_id,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct LiveEvents {
+    redis: Client,
+}
+
+impl LiveEvents {
+    pub fn new(redis: Client) -> Self {
+        Self { redis }
+    }
+
+    /// a failure is logged, the live views catch up on their next refresh
+    pub async fn publish(&self, event: &LiveEvent) {
+        let ret: Result<()> = async {
+            let payload = serde_json::to_string(event)?;
+            let mut conn = self.redis.get_multiplexed_async_connection().await?;
+            let _: () = redis::pipe()
+                .publish(EVENT_CHANNEL, &payload)
+                .ignore()
+                .cmd("XADD")
+                .arg(EVENT_LOG)
+                .arg("MAXLEN")
+                .arg("~")
+                .arg(EVENT_LOG_LEN)
+                .arg("*")
+                .arg("event")
+                .arg(&payload)
+                .ignore()
+                .query_async(&mut conn)
+                .await?;
+            Ok(())
+        }
+        .await;
+        if let Err(e) = ret {
+            warn!("failed publish event - {e}");
+        }
+    }
+
+    /// the events published from now on, the malformed ones are skipped
+    pub async fn subscribe(&self) -> Result<impl Stream<Item = LiveEvent> + use<>> {
+        let mut pubsub = self.redis.get_async_pubsub().await?;
+        pubsub.subscribe(EVENT_CHANNEL).await?;
+        Ok(pubsub.into_on_message().filter_map(|msg| async move {
+            let payload: String = msg.get_payload().ok()?;
+            serde_json::from_str(&payload).ok()
+        }))
+    }
+
+    /// the id of the latest event of the log, the reads from it return the later events
+    pub async fn last_id(&self) -> Result<String> {
+        let mut conn = self.redis.get_multiplexed_async_connection().await?;
+        let ret: Vec<StreamEntry> = redis::cmd("XREVRANGE")
+            .arg(EVENT_LOG)
+            .arg("+")
+            .arg("-")
+            .arg("COUNT")
+            .arg(1)
+            .query_async(&mut conn)
+            .await?;
+        Ok(ret
+            .into_iter()
+            .next()
+            .map_or_else(|| "0-0".to_string(), |v| v.0))
+    }
+
+    /// the events after the id, waits up to block if there is none yet.
+    /// returns the id to read from next time, it is unchanged if no event arrived
+    pub async fn read(&self, after_id: &str, block: Duration) -> Result<(String, Vec<LiveEvent>)> {
+        // a blocking read holds the connection, it is not shared with the other commands
+        let mut conn = self.redis.get_multiplexed_async_connection().await?;
+        let ret: Option<Vec<(String, Vec<StreamEntry>)>> = redis::cmd("XREAD")
+            .arg("COUNT")
+            .arg(EVENT_READ_COUNT)
+            .arg("BLOCK")
+            .arg(block.as_millis() as u64)
+            .arg("STREAMS")
+            .arg(EVENT_LOG)
+            .arg(after_id)
+            .query_async(&mut conn)
+            .await?;
+
+        Ok(read_entries(
+            after_id,
+            ret.unwrap_or_default()
+                .into_iter()
+                .flat_map(|(_, entries)| entries)
+                .collect(),
+        ))
+    }
+}
+
+/// the events of the log entries and the id to read from next time,
+/// the malformed entries are skipped but still move the id forward
+fn read_entries(after_id: &str, entries: Vec<StreamEntry>) -> (String, Vec<LiveEvent>) {
+    let next_id = entries
+        .last()
+        .map_or_else(|| after_id.to_string(), |v| v.0.clone());
+    let events = entries
+        .into_iter()
+        .filter_map(|(_, fields)| parse_entry(&fields))
+        .collect();
+    (next_id, events)
+}
+
+/// the fields of a log entry are: event, the json of the event
+fn parse_entry(fields: &[String]) -> Option<LiveEvent> {
+    match fields {
+        [name, payload] if name == "event" => serde_json::from_str(payload).ok(),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_event_format() {
+    let event = LiveEvent::InstanceStatus {
+        instance_id: "i-1".to_string(),
+        ip: "10.0.0.1".to_string(),
+        namespace: "default".to_string(),
+        online: false,
+    };
+    let v = serde_json::to_value(&event).unwrap();
+    assert_eq!(v["event"], "instance_status");
+    assert_eq!(serde_json::from_value::<LiveEvent>(v).unwrap(), event);
+    assert_eq!(event.eid(), None);
+    assert_eq!(
+        parse_entry(&["event".to_string(), serde_json::to_string(&event).unwrap()]),
+        Some(event)
+    );
+    assert_eq!(parse_entry(&["event".to_string()]), None);
+}
+
+#[test]
+fn test_read_entries() {
+    let event = LiveEvent::ExecHistory {
+        eid: "j-1".to_string(),
+        instance_id: "i-1".to_string(),
+        schedule_id: "s-1".to_string(),
+        exit_code: 0,
+        result_status: "success".to_string(),
+        start_time: None,
+        end_time: None,
+    };
+    assert_eq!(serde_json::to_value(&event).unwrap()["event"], event.name());
+
+    let payload = serde_json::to_string(&event).unwrap();
+    let (next_id, events) = read_entries(
+        "1-0",
+        vec![
+            ("2-0".to_string(), vec!["event".to_string(), payload]),
+            (
+                "3-0".to_string(),
+                vec!["event".to_string(), "{".to_string()],
+            ),
+        ],
+    );
+    assert_eq!(next_id, "3-0");
+    assert_eq!(events, [event]);
+
+    assert_eq!(read_entries("1-0", vec![]), ("1-0".to_string(), vec![]));
+}
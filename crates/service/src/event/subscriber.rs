@@ -0,0 +1,224 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Local;
+use tracing::info;
+
+use super::{AuditEvent, BusEvent, InstanceEvent, JobEvent, Subscriber, live::LiveEvent};
+use crate::{
+    logic::{
+        job::{JobLogic, types::ExecResultStatus},
+        report::ReportLogic,
+    },
+    state::AppContext,
+};
+
+/// the completion callbacks of the jobs
+pub struct WebhookSubscriber;
+
+#[async_trait]
+impl Subscriber for WebhookSubscriber {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    async fn handle(&self, ctx: &AppContext, event: &BusEvent) -> Result<()> {
+        match event {
+            BusEvent::Job(JobEvent::Flapping { params }) => {
+                JobLogic::new(ctx)
+                    .completed_callback(params.as_ref().clone(), ExecResultStatus::Failure, None)
+                    .await
+            }
+            BusEvent::Job(JobEvent::RunCompleted {
+                params,
+                result_status,
+                parsed_result,
+                ..
+            }) => {
+                JobLogic::new(ctx)
+                    .completed_callback(
+                        params.as_ref().clone(),
+                        *result_status,
+                        parsed_result.as_ref(),
+                    )
+                    .await
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// the full output of the finished runs shipped to the log sinks of the namespace
+pub struct LogSinkSubscriber;
+
+#[async_trait]
+impl Subscriber for LogSinkSubscriber {
+    fn name(&self) -> &'static str {
+        "log_sink"
+    }
+
+    async fn handle(&self, ctx: &AppContext, event: &BusEvent) -> Result<()> {
+        let BusEvent::Job(JobEvent::RunCompleted { params, .. }) = event else {
+            return Ok(());
+        };
+        if ctx.conf.log_sinks.is_empty() {
+            return Ok(());
+        }
+        JobLogic::new(ctx).ship_output(params).await
+    }
+}
+
+/// the online and offline changes of the instances, the source of the availability reports
+pub struct InstanceStatusLogSubscriber;
+
+#[async_trait]
+impl Subscriber for InstanceStatusLogSubscriber {
+    fn name(&self) -> &'static str {
+        "instance_status_log"
+    }
+
+    async fn handle(&self, ctx: &AppContext, event: &BusEvent) -> Result<()> {
+        let (instance_id, ip, namespace, online) = match event {
+            BusEvent::Instance(InstanceEvent::Online {
+                instance_id,
+                ip,
+                namespace,
+            }) => (instance_id, ip, namespace, true),
+            BusEvent::Instance(InstanceEvent::Offline {
+                instance_id,
+                ip,
+                namespace,
+            }) => (instance_id, ip, namespace, false),
+            _ => return Ok(()),
+        };
+        ReportLogic::new(ctx)
+            .record_instance_status(instance_id, ip, namespace, online)
+            .await
+    }
+}
+
+/// the run counters of the schedules, eg: the quorum of a multi-instance dispatch
+pub struct StatsSubscriber;
+
+#[async_trait]
+impl Subscriber for StatsSubscriber {
+    fn name(&self) -> &'static str {
+        "stats"
+    }
+
+    async fn handle(&self, ctx: &AppContext, event: &BusEvent) -> Result<()> {
+        let BusEvent::Job(JobEvent::RunCompleted {
+            params,
+            result_status,
+            job_record,
+            ..
+        }) = event
+        else {
+            return Ok(());
+        };
+        JobLogic::new(ctx)
+            .count_quorum_run(
+                &params.schedule_id,
+                *result_status != ExecResultStatus::Failure,
+                job_record.as_deref(),
+            )
+            .await
+    }
+}
+
+/// the websocket, server-sent events and long polling views of the console
+pub struct LiveSubscriber;
+
+#[async_trait]
+impl Subscriber for LiveSubscriber {
+    fn name(&self) -> &'static str {
+        "live"
+    }
+
+    async fn handle(&self, ctx: &AppContext, event: &BusEvent) -> Result<()> {
+        let live_event = match event {
+            BusEvent::Job(JobEvent::StatusChanged {
+                params,
+                result_status,
+            }) => LiveEvent::RunStatus {
+                eid: params.base_job.eid.clone(),
+                instance_id: params.instance_id.clone(),
+                schedule_id: params.schedule_id.clone(),
+                schedule_type: params.schedule_type.as_ref().map(|v| v.to_string()),
+                run_status: params.run_status.as_ref().map(|v| v.to_string()),
+                schedule_status: params.schedule_status.as_ref().map(|v| v.to_string()),
+                exit_code: params.exit_code,
+                result_status: result_status.map(|v| v.as_str().to_string()),
+            },
+            BusEvent::Job(JobEvent::RunCompleted {
+                params,
+                result_status,
+                ..
+            }) => LiveEvent::ExecHistory {
+                eid: params.base_job.eid.clone(),
+                instance_id: params.instance_id.clone(),
+                schedule_id: params.schedule_id.clone(),
+                exit_code: params.exit_code.unwrap_or_default(),
+                result_status: result_status.as_str().to_string(),
+                start_time: params.start_time.map(|v| {
+                    v.with_timezone(&Local)
+                        .format("%Y-%m-%d %H:%M:%S")
+                        .to_string()
+                }),
+                end_time: params.end_time.map(|v| {
+                    v.with_timezone(&Local)
+                        .format("%Y-%m-%d %H:%M:%S")
+                        .to_string()
+                }),
+            },
+            BusEvent::Instance(v) => {
+                let (InstanceEvent::Online {
+                    instance_id,
+                    ip,
+                    namespace,
+                }
+                | InstanceEvent::Offline {
+                    instance_id,
+                    ip,
+                    namespace,
+                }) = v;
+                LiveEvent::InstanceStatus {
+                    instance_id: instance_id.clone(),
+                    ip: ip.clone(),
+                    namespace: namespace.clone(),
+                    online: matches!(v, InstanceEvent::Online { .. }),
+                }
+            }
+            _ => return Ok(()),
+        };
+        ctx.live.publish(&live_event).await;
+        Ok(())
+    }
+}
+
+/// the audit trail of the externally initiated actions, written to the audit log target
+pub struct AuditLogSubscriber;
+
+#[async_trait]
+impl Subscriber for AuditLogSubscriber {
+    fn name(&self) -> &'static str {
+        "audit_log"
+    }
+
+    async fn handle(&self, _ctx: &AppContext, event: &BusEvent) -> Result<()> {
+        if let BusEvent::Audit(AuditEvent {
+            action,
+            eid,
+            source,
+            status,
+            reason,
+            schedule_id,
+        }) = event
+        {
+            info!(
+                target: "audit",
+                "{action} of job {eid} from {source} is {status}, schedule: {schedule_id}, reason: {reason}"
+            );
+        }
+        Ok(())
+    }
+}
@@ -1,9 +1,11 @@
 pub mod logic;
 pub mod state;
-use chrono::Local;
 pub use entity;
 use nanoid::nanoid;
+pub mod cache;
 pub mod config;
+pub mod db;
+pub mod event;
 
 pub struct IdGenerator;
 
@@ -14,6 +16,11 @@ impl IdGenerator {
     const FLOW_JOB_PREFIX: &'static str = "f";
     const SCHEDULE_ID_PREFIX: &'static str = "s";
     const INSTANCE_PREFIX: &'static str = "i";
+    const BOOTSTRAP_TOKEN_PREFIX: &'static str = "k";
+    const SERVER_TASK_PREFIX: &'static str = "q";
+    const DISPATCH_PREFIX: &'static str = "d";
+    const API_TOKEN_PREFIX: &'static str = "a";
+    const DISPATCH_CHALLENGE_PREFIX: &'static str = "c";
 
     pub fn get_job_eid() -> String {
         Self::get_id(Self::JOB_PREFIX)
@@ -38,11 +45,31 @@ impl IdGenerator {
         Self::get_id(Self::INSTANCE_PREFIX)
     }
 
+    pub fn get_bootstrap_token_uid() -> String {
+        Self::get_id(Self::BOOTSTRAP_TOKEN_PREFIX)
+    }
+
+    pub fn get_server_task_uid() -> String {
+        Self::get_id(Self::SERVER_TASK_PREFIX)
+    }
+
+    pub fn get_dispatch_uid() -> String {
+        Self::get_id(Self::DISPATCH_PREFIX)
+    }
+
+    pub fn get_api_token_uid() -> String {
+        Self::get_id(Self::API_TOKEN_PREFIX)
+    }
+
+    pub fn get_dispatch_challenge_uid() -> String {
+        Self::get_id(Self::DISPATCH_CHALLENGE_PREFIX)
+    }
+
     fn get_id(prefix: &str) -> String {
         format!("{prefix}-{}", nanoid!(10)).into()
     }
 
     pub fn get_run_id() -> String {
-        Local::now().format("%Y%m%d%H%M%S").to_string()
+        automate::new_run_id()
     }
 }
@@ -0,0 +1,382 @@
+//! agent config managed by the console
+//!
+//! the config of a namespace is the default of its agents, the config of an instance overrides it.
+//! the merged config is pushed to the agents on save and whenever the config reported in the
+//! heartbeat differs from it, the agents save it locally and prefer it over their flags
+use anyhow::{Result, anyhow};
+use automate::{
+    bridge::msg::{AgentConfig, AgentConfigParams, AgentConfigState},
+    comet::types::AgentConfigRequest,
+};
+use sea_orm::{ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QueryTrait};
+use sea_query::OnConflict;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::{
+    entity::{agent_config, instance, prelude::Instance},
+    state::AppContext,
+};
+
+pub const MIN_HEARTBEAT_INTERVAL: u64 = 10;
+pub const MAX_HEARTBEAT_INTERVAL: u64 = 300;
+
+pub fn check_config(config: &AgentConfig) -> Result<()> {
+    if config
+        .output_dir
+        .as_ref()
+        .is_some_and(|v| v.trim().is_empty())
+    {
+        anyhow::bail!("output_dir cannot be empty");
+    }
+    if let Some(level) = &config.log_level {
+        utils::check_log_level(level).map_err(|e| anyhow!("invalid log_level {level}, {e}"))?;
+    }
+    if config
+        .heartbeat_interval
+        .is_some_and(|v| !(MIN_HEARTBEAT_INTERVAL..=MAX_HEARTBEAT_INTERVAL).contains(&v))
+    {
+        anyhow::bail!(
+            "heartbeat_interval must be between {MIN_HEARTBEAT_INTERVAL} and {MAX_HEARTBEAT_INTERVAL}"
+        );
+    }
+    if let Some(addrs) = &config.comet_addr {
+        if addrs.is_empty() {
+            anyhow::bail!("comet_addr cannot be empty");
+        }
+        if let Some(v) = addrs
+            .iter()
+            .find(|v| !v.starts_with("ws://") && !v.starts_with("wss://"))
+        {
+            anyhow::bail!("invalid comet_addr {v}, expect ws:// or wss://");
+        }
+    }
+    Ok(())
+}
+
+/// how the config of the agent differs from the one managed by the console
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct AgentConfigDrift {
+    /// the settings saved by the agent which differ from the console
+    pub drift_fields: Vec<String>,
+    /// the settings saved but not in effect until the agent restarts
+    pub pending_restart: Vec<String>,
+}
+
+fn diff_fields(a: &AgentConfig, b: &AgentConfig) -> Vec<String> {
+    let mut fields = vec![];
+    if a.output_dir != b.output_dir {
+        fields.push("output_dir".to_string());
+    }
+    if a.log_level != b.log_level {
+        fields.push("log_level".to_string());
+    }
+    if a.heartbeat_interval != b.heartbeat_interval {
+        fields.push("heartbeat_interval".to_string());
+    }
+    if a.comet_addr != b.comet_addr {
+        fields.push("comet_addr".to_string());
+    }
+    fields
+}
+
+/// the drift of the reported state from the desired config, the effective settings
+/// are compared with the saved ones merged over themselves, the unset ones come from the flags
+pub fn get_drift(desired: &AgentConfig, state: &AgentConfigState) -> AgentConfigDrift {
+    AgentConfigDrift {
+        drift_fields: diff_fields(desired, &state.config),
+        pending_restart: diff_fields(&state.config.or(&state.effective), &state.effective),
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct InstanceConfigDrift {
+    pub instance_id: String,
+    pub ip: String,
+    pub namespace: String,
+    pub status: i8,
+    pub desired: AgentConfig,
+    /// none if the agent does not report its config
+    pub reported: Option<AgentConfigState>,
+    pub drift: Option<AgentConfigDrift>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PushResult {
+    pub instance_id: String,
+    pub ip: String,
+    pub err: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct AgentConfigLogic<'a> {
+    ctx: &'a AppContext,
+}
+
+impl<'a> AgentConfigLogic<'a> {
+    pub fn new(ctx: &'a AppContext) -> Self {
+        Self { ctx }
+    }
+
+    pub async fn query_config(
+        &self,
+        namespace: Option<String>,
+    ) -> Result<Vec<agent_config::Model>> {
+        Ok(agent_config::Entity::find()
+            .apply_if(namespace, |q, v| {
+                q.filter(agent_config::Column::Namespace.eq(v))
+            })
+            .order_by_asc(agent_config::Column::Namespace)
+            .order_by_asc(agent_config::Column::InstanceId)
+            .all(&self.ctx.db)
+            .await?)
+    }
+
+    /// save the config of the namespace, or of the instance if instance_id is not empty,
+    /// then push the merged config to the online agents it applies to
+    pub async fn save_config(
+        &self,
+        namespace: String,
+        instance_id: String,
+        config: AgentConfig,
+        user: &str,
+    ) -> Result<Vec<PushResult>> {
+        check_config(&config)?;
+        agent_config::Entity::insert(agent_config::ActiveModel {
+            namespace: Set(namespace.clone()),
+            instance_id: Set(instance_id.clone()),
+            config: Set(serde_json::to_value(&config)?),
+            created_user: Set(user.to_string()),
+            updated_user: Set(user.to_string()),
+            ..Default::default()
+        })
+        .on_conflict(
+            OnConflict::columns([
+                agent_config::Column::Namespace,
+                agent_config::Column::InstanceId,
+            ])
+            .update_columns([
+                agent_config::Column::Config,
+                agent_config::Column::UpdatedUser,
+            ])
+            .to_owned(),
+        )
+        .exec(&self.ctx.db)
+        .await?;
+        self.push_namespace(&namespace, &instance_id).await
+    }
+
+    /// the agents fall back to the namespace config or their flags
+    pub async fn delete_config(&self, id: u64) -> Result<Vec<PushResult>> {
+        let Some(record) = agent_config::Entity::find_by_id(id)
+            .one(&self.ctx.db)
+            .await?
+        else {
+            return Ok(vec![]);
+        };
+        agent_config::Entity::delete_by_id(id)
+            .exec(&self.ctx.db)
+            .await?;
+        self.push_namespace(&record.namespace, &record.instance_id)
+            .await
+    }
+
+    /// the config of the instance merged over the config of its namespace
+    pub async fn get_desired_config(
+        &self,
+        namespace: &str,
+        instance_id: &str,
+    ) -> Result<AgentConfig> {
+        let records = agent_config::Entity::find()
+            .filter(agent_config::Column::Namespace.eq(namespace))
+            .filter(agent_config::Column::InstanceId.is_in(["", instance_id]))
+            .all(&self.ctx.db)
+            .await?;
+        let config_of = |instance_id: &str| -> Result<AgentConfig> {
+            records
+                .iter()
+                .find(|v| v.instance_id == instance_id)
+                .map(|v| serde_json::from_value::<AgentConfig>(v.config.clone()))
+                .transpose()
+                .map(Option::unwrap_or_default)
+                .map_err(Into::into)
+        };
+        Ok(config_of(instance_id)?.or(&config_of("")?))
+    }
+
+    /// the desired and the reported config of the instances with their drift
+    pub async fn query_drift(&self, namespace: Option<String>) -> Result<Vec<InstanceConfigDrift>> {
+        let instances = Instance::find()
+            .apply_if(namespace, |q, v| {
+                q.filter(instance::Column::Namespace.eq(v))
+            })
+            .order_by_asc(instance::Column::Id)
+            .all(&self.ctx.db)
+            .await?;
+
+        let mut list = vec![];
+        for ins in instances {
+            let desired = self
+                .get_desired_config(&ins.namespace, &ins.instance_id)
+                .await?;
+            let reported = ins
+                .agent_config
+                .map(serde_json::from_value::<AgentConfigState>)
+                .transpose()
+                .unwrap_or_default();
+            list.push(InstanceConfigDrift {
+                drift: reported.as_ref().map(|v| get_drift(&desired, v)),
+                instance_id: ins.instance_id,
+                ip: ins.ip,
+                namespace: ins.namespace,
+                status: ins.status,
+                desired,
+                reported,
+            });
+        }
+        Ok(list)
+    }
+
+    /// keep the config reported in the heartbeat, it is pushed again if it drifts.
+    /// the agents not reporting their config do not support it
+    pub async fn report_config(
+        &self,
+        mac_addr: String,
+        ip: String,
+        state: Option<AgentConfigState>,
+    ) -> Result<()> {
+        let Some(state) = state else {
+            return Ok(());
+        };
+        let Some(ins) = Instance::find()
+            .filter(instance::Column::MacAddr.eq(mac_addr))
+            .filter(instance::Column::Ip.eq(ip))
+            .one(&self.ctx.db)
+            .await?
+        else {
+            return Ok(());
+        };
+        self.save_reported(ins.id, &state).await?;
+
+        let desired = self
+            .get_desired_config(&ins.namespace, &ins.instance_id)
+            .await?;
+        if get_drift(&desired, &state).drift_fields.is_empty() {
+            return Ok(());
+        }
+        self.push_config(&ins, desired).await?;
+        Ok(())
+    }
+
+    async fn push_namespace(&self, namespace: &str, instance_id: &str) -> Result<Vec<PushResult>> {
+        let instances = Instance::find()
+            .filter(instance::Column::Namespace.eq(namespace))
+            .filter(instance::Column::Status.eq(1))
+            .apply_if((!instance_id.is_empty()).then_some(instance_id), |q, v| {
+                q.filter(instance::Column::InstanceId.eq(v))
+            })
+            .all(&self.ctx.db)
+            .await?;
+
+        let mut list = vec![];
+        for ins in instances {
+            let ret = async {
+                let desired = self
+                    .get_desired_config(&ins.namespace, &ins.instance_id)
+                    .await?;
+                self.push_config(&ins, desired).await
+            }
+            .await;
+            if let Err(e) = &ret {
+                error!("failed push agent config to {}, {e:?}", ins.ip);
+            }
+            list.push(PushResult {
+                instance_id: ins.instance_id,
+                ip: ins.ip,
+                err: ret.err().map(|e| e.to_string()),
+            });
+        }
+        Ok(list)
+    }
+
+    async fn push_config(&self, ins: &instance::Model, config: AgentConfig) -> Result<()> {
+        let pair = automate::Logic::new(self.ctx.redis())
+            .get_link_pair(ins.ip.clone(), ins.mac_addr.clone())
+            .await?;
+        let ret = self
+            .ctx
+            .http_client
+            .post(pair.1.http_url("/agent/config"))
+            .json(&AgentConfigRequest {
+                agent_ip: ins.ip.clone(),
+                mac_addr: ins.mac_addr.clone(),
+                params: AgentConfigParams { config },
+            })
+            .send()
+            .await?
+            .json::<serde_json::Value>()
+            .await?;
+        if ret["code"] != 20000 {
+            anyhow::bail!("failed push agent config, {}", ret["msg"]);
+        }
+        let state = serde_json::from_value::<AgentConfigState>(ret["data"].clone())?;
+        self.save_reported(ins.id, &state).await
+    }
+
+    async fn save_reported(&self, id: u64, state: &AgentConfigState) -> Result<()> {
+        Instance::update_many()
+            .set(instance::ActiveModel {
+                agent_config: Set(Some(serde_json::to_value(state)?)),
+                ..Default::default()
+            })
+            .filter(instance::Column::Id.eq(id))
+            .exec(&self.ctx.db)
+            .await?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_agent_config_drift() {
+    let config = |log_level: Option<&str>, comet_addr: Option<&str>| AgentConfig {
+        log_level: log_level.map(String::from),
+        comet_addr: comet_addr.map(|v| vec![v.to_string()]),
+        ..Default::default()
+    };
+    assert!(
+        check_config(&config(
+            Some("info,automate=debug"),
+            Some("wss://comet:3000")
+        ))
+        .is_ok()
+    );
+    assert!(check_config(&config(None, Some("http://comet:3000"))).is_err());
+    assert!(
+        check_config(&AgentConfig {
+            heartbeat_interval: Some(5),
+            ..Default::default()
+        })
+        .is_err()
+    );
+
+    let state = AgentConfigState {
+        config: config(Some("debug"), Some("ws://b:3000")),
+        effective: config(Some("debug"), Some("ws://a:3000")),
+    };
+    let drift = get_drift(&config(Some("debug"), Some("ws://b:3000")), &state);
+    assert!(drift.drift_fields.is_empty());
+    assert_eq!(drift.pending_restart, vec!["comet_addr"]);
+
+    let drift = get_drift(&config(Some("info"), None), &state);
+    assert_eq!(drift.drift_fields, vec!["log_level", "comet_addr"]);
+
+    // the unset settings fall back to the flags in effect
+    let state = AgentConfigState {
+        config: config(None, None),
+        effective: config(Some("error"), Some("ws://a:3000")),
+    };
+    assert_eq!(
+        get_drift(&config(None, None), &state),
+        AgentConfigDrift::default()
+    );
+}
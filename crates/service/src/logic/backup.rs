@@ -0,0 +1,321 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use sea_orm::{
+    ActiveModelTrait, ConnectionTrait, EntityName, EntityTrait, IntoActiveModel, TransactionTrait,
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use simple_crypt::{decrypt, encrypt};
+
+use crate::{
+    cache::{CACHE_STATUS_PAGE, CACHE_TEAM_MEMBER, CACHE_USER_SERVER},
+    entity::prelude::*,
+    state::AppContext,
+};
+
+/// written before the encrypted archive to tell it from other files
+const BACKUP_MAGIC: &[u8] = b"JSBACKUP1";
+const BACKUP_FORMAT: u32 = 1;
+/// rows inserted by one statement on import
+const RESTORE_BATCH: usize = 500;
+const MIN_PASSPHRASE_LEN: usize = 8;
+
+/// the configuration tables, the history and runtime tables are left out
+macro_rules! with_backup_tables {
+    ($apply:ident) => {
+        $apply!(
+            AgentConfig,
+            AgentReleaseVersion,
+            BlackoutWindow,
+            CasbinRule,
+            CodePolicyRule,
+            CodePolicyWaiver,
+            Dashboard,
+            Executor,
+            ExecutorAcl,
+            Instance,
+            InstanceCredential,
+            InstanceExecutor,
+            InstanceGroup,
+            InstanceRole,
+            Job,
+            JobBundleScript,
+            JobRemediation,
+            JobSchedule,
+            JobShare,
+            JobSla,
+            JobSupervisor,
+            JobTimer,
+            JobTrigger,
+            Report,
+            Role,
+            SavedFilter,
+            StatusPage,
+            Tag,
+            TagResource,
+            Team,
+            TeamMember,
+            TeamQuota,
+            User,
+            UserGroup,
+            UserGroupMember,
+            UserGroupTeam,
+            UserServer,
+            Workflow,
+            WorkflowTimer,
+            WorkflowVersion
+        )
+    };
+}
+
+/// the history and runtime tables that are not backed up
+#[cfg(test)]
+const SKIPPED_TABLES: [&str; 23] = [
+    "dispatch_stat",
+    "emergency_token",
+    "instance_status_log",
+    "job_daily_stat",
+    "job_duration_anomaly",
+    "job_duration_stat",
+    "job_exec_history",
+    "job_pending_dispatch",
+    "job_promotion",
+    "job_remediation_log",
+    "job_running_status",
+    "job_schedule_history",
+    "job_sla_breach",
+    "job_supervisor_metric",
+    "job_trigger_log",
+    "job_usage_stat",
+    "server_task",
+    "sftp_edit_log",
+    "team_upload_file",
+    "workflow_process",
+    "workflow_process_edge",
+    "workflow_process_node",
+    "workflow_process_node_task",
+];
+
+/// columns encrypted with the private_key, they are kept decrypted in the archive
+const SECRET_COLUMNS: [(&str, &str); 5] = [
+    ("instance", "password"),
+    ("instance", "private_key"),
+    ("instance", "key_passphrase"),
+    ("instance_credential", "secret"),
+    ("instance_credential", "passphrase"),
+];
+
+#[derive(Serialize, Deserialize)]
+struct Archive {
+    format: u32,
+    created_time: DateTime<Local>,
+    /// rows of every table keyed by table name
+    tables: BTreeMap<String, Vec<Value>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TableCount {
+    pub name: String,
+    pub rows: u64,
+}
+
+fn check_passphrase(passphrase: &str) -> Result<()> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        anyhow::bail!("passphrase must have at least {MIN_PASSPHRASE_LEN} characters");
+    }
+    Ok(())
+}
+
+fn seal(archive: &Archive, passphrase: &str) -> Result<Vec<u8>> {
+    check_passphrase(passphrase)?;
+    let data = encrypt(&serde_json::to_vec(archive)?, passphrase.as_bytes())?;
+    Ok([BACKUP_MAGIC, &data].concat())
+}
+
+fn open(data: &[u8], passphrase: &str) -> Result<Archive> {
+    let Some(data) = data.strip_prefix(BACKUP_MAGIC) else {
+        anyhow::bail!("not a jiascheduler backup");
+    };
+    let data = decrypt(data, passphrase.as_bytes())
+        .map_err(|_| anyhow::anyhow!("invalid passphrase or broken backup"))?;
+    let archive: Archive = serde_json::from_slice(&data)?;
+    if archive.format != BACKUP_FORMAT {
+        anyhow::bail!("unsupported backup format {}", archive.format);
+    }
+    Ok(archive)
+}
+
+fn map_secrets(
+    tables: &mut BTreeMap<String, Vec<Value>>,
+    f: impl Fn(String) -> Result<String>,
+) -> Result<()> {
+    for (table, column) in SECRET_COLUMNS {
+        for row in tables.get_mut(table).into_iter().flatten() {
+            if let Some(v) = row[column].as_str().filter(|v| !v.is_empty()) {
+                row[column] = Value::String(f(v.to_string())?);
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn dump_table<E>(db: &impl ConnectionTrait) -> Result<Vec<Value>>
+where
+    E: EntityTrait,
+    E::Model: Serialize,
+{
+    let rows = E::find().all(db).await?;
+    Ok(rows
+        .into_iter()
+        .map(serde_json::to_value)
+        .collect::<Result<_, _>>()?)
+}
+
+/// replace the rows of the table, the ids are kept so that the references stay valid
+async fn restore_table<E>(db: &impl ConnectionTrait, rows: &[Value]) -> Result<()>
+where
+    E: EntityTrait,
+    E::Model: DeserializeOwned + IntoActiveModel<E::ActiveModel>,
+    E::ActiveModel: ActiveModelTrait<Entity = E> + Send,
+{
+    E::delete_many().exec(db).await?;
+    for chunk in rows.chunks(RESTORE_BATCH) {
+        let models = chunk
+            .iter()
+            .map(|v| Ok(serde_json::from_value::<E::Model>(v.clone())?.into_active_model()))
+            .collect::<Result<Vec<_>>>()?;
+        E::insert_many(models).exec_without_returning(db).await?;
+    }
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct BackupLogic<'a> {
+    ctx: &'a AppContext,
+}
+
+impl<'a> BackupLogic<'a> {
+    pub fn new(ctx: &'a AppContext) -> Self {
+        Self { ctx }
+    }
+
+    /// the configuration encrypted with the passphrase. the ssh passwords and credentials
+    /// of the instances are kept decrypted in the archive, so that it can be restored with another private_key
+    pub async fn export(&self, passphrase: &str) -> Result<Vec<u8>> {
+        check_passphrase(passphrase)?;
+        let db = &self.ctx.db;
+        let mut tables = BTreeMap::new();
+        macro_rules! dump {
+            ($($entity:ident),*) => {
+                $(
+                    tables.insert(
+                        $entity.table_name().to_string(),
+                        dump_table::<$entity>(db).await?,
+                    );
+                )*
+            };
+        }
+        with_backup_tables!(dump);
+
+        map_secrets(&mut tables, |v| self.ctx.decrypt(v))?;
+
+        seal(
+            &Archive {
+                format: BACKUP_FORMAT,
+                created_time: Local::now(),
+                tables,
+            },
+            passphrase,
+        )
+    }
+
+    /// replace the configuration with the backup in one transaction,
+    /// nothing is written if dry_run
+    pub async fn import(
+        &self,
+        data: &[u8],
+        passphrase: &str,
+        dry_run: bool,
+    ) -> Result<Vec<TableCount>> {
+        let mut archive = open(data, passphrase)?;
+        let counts = archive
+            .tables
+            .iter()
+            .map(|(name, rows)| TableCount {
+                name: name.to_string(),
+                rows: rows.len() as u64,
+            })
+            .collect();
+        if dry_run {
+            return Ok(counts);
+        }
+
+        map_secrets(&mut archive.tables, |v| self.ctx.encrypt(v))?;
+
+        let txn = self.ctx.db.begin().await?;
+        macro_rules! restore {
+            ($($entity:ident),*) => {
+                $(
+                    if let Some(rows) = archive.tables.get($entity.table_name()) {
+                        restore_table::<$entity>(&txn, rows).await?;
+                    }
+                )*
+            };
+        }
+        with_backup_tables!(restore);
+        txn.commit().await?;
+
+        self.ctx.load_policy().await?;
+        self.ctx.cache.invalidate(CACHE_TEAM_MEMBER).await;
+        self.ctx.cache.invalidate(CACHE_USER_SERVER).await;
+        self.ctx.cache.invalidate(CACHE_STATUS_PAGE).await;
+        Ok(counts)
+    }
+}
+
+#[test]
+fn test_backup_archive() {
+    let archive = Archive {
+        format: BACKUP_FORMAT,
+        created_time: Local::now(),
+        tables: BTreeMap::from([(
+            "tag".to_string(),
+            vec![serde_json::json!({"id": 1, "tag_name": "prod"})],
+        )]),
+    };
+    assert!(seal(&archive, "short").is_err());
+
+    let data = seal(&archive, "correct horse").unwrap();
+    let opened = open(&data, "correct horse").unwrap();
+    assert_eq!(opened.tables["tag"][0]["tag_name"], "prod");
+    assert!(open(&data, "wrong horse").is_err());
+    assert!(open(b"plain text", "correct horse").is_err());
+}
+
+#[test]
+fn test_backup_tables() {
+    let mut tables = vec![];
+    macro_rules! names {
+        ($($entity:ident),*) => {
+            $(tables.push($entity.table_name());)*
+        };
+    }
+    with_backup_tables!(names);
+
+    let prelude = include_str!("../../../entity/src/entity/prelude.rs");
+    for line in prelude.lines() {
+        let Some(name) = line
+            .strip_prefix("pub use super::")
+            .and_then(|v| v.split_once("::"))
+            .map(|v| v.0)
+        else {
+            continue;
+        };
+        assert!(
+            tables.contains(&name) != SKIPPED_TABLES.contains(&name),
+            "{name} must be either backed up or skipped"
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use anyhow::Result;
+use automate::scheduler::types::BlackoutWindow as AgentBlackoutWindow;
+use chrono::{Local, Utc};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder,
+    QueryTrait,
+};
+
+use crate::{
+    entity::{blackout_window, prelude::*},
+    state::AppContext,
+};
+
+#[derive(Clone)]
+pub struct BlackoutLogic<'a> {
+    ctx: &'a AppContext,
+}
+
+impl<'a> BlackoutLogic<'a> {
+    pub fn new(ctx: &'a AppContext) -> Self {
+        Self { ctx }
+    }
+
+    pub fn to_agent_window(record: &blackout_window::Model) -> Result<AgentBlackoutWindow> {
+        let weekdays: Vec<u8> = match record.weekdays.clone() {
+            Some(v) => serde_json::from_value(v)?,
+            None => vec![],
+        };
+        Ok(AgentBlackoutWindow {
+            name: record.name.clone(),
+            window_type: record.window_type.clone(),
+            start_time: record.start_time.map(|v| v.with_timezone(&Utc)),
+            end_time: record.end_time.map(|v| v.with_timezone(&Utc)),
+            weekdays,
+            start_clock: record.start_clock.clone(),
+            end_clock: record.end_clock.clone(),
+            timezone: record.timezone.clone(),
+        })
+    }
+
+    pub async fn save_window(
+        &self,
+        active_model: blackout_window::ActiveModel,
+    ) -> Result<blackout_window::ActiveModel> {
+        let model = active_model.save(&self.ctx.db).await?;
+        Ok(model)
+    }
+
+    pub async fn get_window(&self, id: u64) -> Result<Option<blackout_window::Model>> {
+        Ok(BlackoutWindow::find_by_id(id).one(&self.ctx.db).await?)
+    }
+
+    pub async fn query_window(
+        &self,
+        team_id: Option<u64>,
+        name: Option<String>,
+        page: u64,
+        page_size: u64,
+    ) -> Result<(Vec<blackout_window::Model>, u64)> {
+        let select = BlackoutWindow::find()
+            .apply_if(team_id, |q, v| {
+                q.filter(blackout_window::Column::TeamId.is_in([0, v]))
+            })
+            .apply_if(name, |q, v| {
+                q.filter(blackout_window::Column::Name.contains(v))
+            });
+
+        let total = select.clone().count(&self.ctx.db).await?;
+        let list = select
+            .order_by_desc(blackout_window::Column::Id)
+            .paginate(&self.ctx.db, page_size)
+            .fetch_page(page)
+            .await?;
+        Ok((list, total))
+    }
+
+    pub async fn delete_window(&self, id: u64) -> Result<u64> {
+        let ret = BlackoutWindow::delete_by_id(id).exec(&self.ctx.db).await?;
+        Ok(ret.rows_affected)
+    }
+
+    /// get the enabled global windows and the windows of the team
+    pub async fn get_effective_windows(
+        &self,
+        team_id: Option<u64>,
+    ) -> Result<Vec<AgentBlackoutWindow>> {
+        let now = Local::now();
+        let list = BlackoutWindow::find()
+            .filter(blackout_window::Column::IsEnabled.eq(true))
+            .filter(blackout_window::Column::TeamId.is_in([0, team_id.unwrap_or_default()]))
+            .filter(
+                Condition::any()
+                    .add(blackout_window::Column::WindowType.eq("recurring"))
+                    .add(blackout_window::Column::EndTime.gt(now)),
+            )
+            .all(&self.ctx.db)
+            .await?;
+
+        list.iter().map(Self::to_agent_window).collect()
+    }
+
+    /// manual dispatch inside a blackout window is refused unless a reason is given
+    pub async fn check_manual_dispatch(
+        &self,
+        team_id: Option<u64>,
+        override_reason: Option<&str>,
+    ) -> Result<()> {
+        let windows = self.get_effective_windows(team_id).await?;
+        let Some(w) = windows.iter().find(|v| v.contains(Utc::now())) else {
+            return Ok(());
+        };
+
+        if override_reason.is_some_and(|v| !v.trim().is_empty()) {
+            return Ok(());
+        }
+
+        anyhow::bail!(
+            "dispatch is blocked by blackout window {}, please provide an override reason",
+            w.name
+        )
+    }
+}
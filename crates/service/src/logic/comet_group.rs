@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use sea_orm::{ColumnTrait, EntityTrait, QuerySelect};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::CometGroup,
+    entity::{instance, prelude::*},
+    state::AppContext,
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct NamespaceLoad {
+    pub namespace: String,
+    pub instance_count: u64,
+    pub online_count: u64,
+    /// assigned by the namespaces of the group config
+    pub pinned: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct GroupAssignment {
+    pub name: String,
+    pub comet_addrs: Vec<String>,
+    pub namespaces: Vec<NamespaceLoad>,
+    pub instance_count: u64,
+    pub online_count: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NamespaceMove {
+    pub namespace: String,
+    pub from: Option<String>,
+    pub to: String,
+}
+
+/// assign the namespaces to the groups by instance count. the pinned namespaces keep their group,
+/// the assigned ones are kept unless rebalance, the others go to the least loaded group
+fn plan_assignments(
+    groups: &[CometGroup],
+    loads: &[(String, u64)],
+    current: &HashMap<String, String>,
+    rebalance: bool,
+) -> HashMap<String, String> {
+    let mut group_loads = groups
+        .iter()
+        .map(|v| (v.name.as_str(), 0u64))
+        .collect::<Vec<_>>();
+    let mut planned = HashMap::new();
+    let mut add = |namespace: &str, group: &str, n: u64| {
+        if let Some(v) = group_loads.iter_mut().find(|v| v.0 == group) {
+            v.1 += n;
+        }
+        planned.insert(namespace.to_string(), group.to_string());
+    };
+
+    let pinned = groups
+        .iter()
+        .flat_map(|g| g.namespaces.iter().map(|ns| (ns.as_str(), g.name.as_str())))
+        .collect::<HashMap<_, _>>();
+    let load_of = |namespace: &str| loads.iter().find(|v| v.0 == namespace).map_or(0, |v| v.1);
+
+    for (namespace, group) in &pinned {
+        add(namespace, group, load_of(namespace));
+    }
+
+    let mut unassigned = vec![];
+    for (namespace, n) in loads {
+        if pinned.contains_key(namespace.as_str()) {
+            continue;
+        }
+        match current.get(namespace) {
+            Some(group) if !rebalance && groups.iter().any(|v| &v.name == group) => {
+                add(namespace, group, *n)
+            }
+            _ => unassigned.push((namespace, *n)),
+        }
+    }
+
+    unassigned.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    for (namespace, n) in unassigned {
+        let current = current.get(namespace).map(String::as_str);
+        let Some(v) = group_loads
+            .iter_mut()
+            .min_by_key(|v| (v.1, Some(v.0) != current))
+        else {
+            break;
+        };
+        v.1 += n;
+        planned.insert(namespace.to_string(), v.0.to_string());
+    }
+    planned
+}
+
+fn diff_assignments(
+    current: &HashMap<String, String>,
+    planned: &HashMap<String, String>,
+) -> Vec<NamespaceMove> {
+    let mut moves = planned
+        .iter()
+        .filter(|(namespace, group)| current.get(*namespace) != Some(*group))
+        .map(|(namespace, group)| NamespaceMove {
+            namespace: namespace.to_string(),
+            from: current.get(namespace).cloned(),
+            to: group.to_string(),
+        })
+        .collect::<Vec<_>>();
+    moves.sort_by(|a, b| a.namespace.cmp(&b.namespace));
+    moves
+}
+
+#[derive(Clone)]
+pub struct CometGroupLogic<'a> {
+    ctx: &'a AppContext,
+}
+
+impl<'a> CometGroupLogic<'a> {
+    pub fn new(ctx: &'a AppContext) -> Self {
+        Self { ctx }
+    }
+
+    /// instance and online count of every namespace
+    async fn namespace_loads(&self) -> Result<Vec<NamespaceLoad>> {
+        let rows: Vec<(String, i8, i64)> = Instance::find()
+            .select_only()
+            .column(instance::Column::Namespace)
+            .column(instance::Column::Status)
+            .column_as(instance::Column::Id.count(), "total")
+            .group_by(instance::Column::Namespace)
+            .group_by(instance::Column::Status)
+            .into_tuple()
+            .all(&self.ctx.db)
+            .await?;
+
+        let mut loads: Vec<NamespaceLoad> = vec![];
+        for (namespace, status, total) in rows {
+            let idx = match loads.iter().position(|v| v.namespace == namespace) {
+                Some(idx) => idx,
+                None => {
+                    loads.push(NamespaceLoad {
+                        namespace,
+                        ..Default::default()
+                    });
+                    loads.len() - 1
+                }
+            };
+            loads[idx].instance_count += total as u64;
+            if status == 1 {
+                loads[idx].online_count += total as u64;
+            }
+        }
+        Ok(loads)
+    }
+
+    async fn apply(&self, rebalance: bool) -> Result<Vec<NamespaceMove>> {
+        let groups = &self.ctx.conf.comet_groups;
+        let logic = automate::Logic::new(self.ctx.redis());
+        let current = logic.get_comet_group_assignments().await?;
+        if groups.is_empty() {
+            if !current.is_empty() {
+                logic.save_comet_group_assignments(&HashMap::new()).await?;
+            }
+            return Ok(vec![]);
+        }
+
+        let loads = self
+            .namespace_loads()
+            .await?
+            .into_iter()
+            .map(|v| (v.namespace, v.instance_count))
+            .collect::<Vec<_>>();
+        let planned = plan_assignments(groups, &loads, &current, rebalance);
+        let moves = diff_assignments(&current, &planned);
+        if !moves.is_empty() || planned.len() != current.len() {
+            logic.save_comet_group_assignments(&planned).await?;
+        }
+        Ok(moves)
+    }
+
+    /// assign the new namespaces and apply the pinned ones of the config,
+    /// the assigned namespaces stay on their group
+    pub async fn assign_namespaces(&self) -> Result<Vec<NamespaceMove>> {
+        self.apply(false).await
+    }
+
+    /// reassign the namespaces which are not pinned to balance the instances across the groups,
+    /// the agents of the moved namespaces reconnect to the new group
+    pub async fn rebalance(&self) -> Result<Vec<NamespaceMove>> {
+        self.apply(true).await
+    }
+
+    pub async fn get_assignments(&self) -> Result<Vec<GroupAssignment>> {
+        let current = automate::Logic::new(self.ctx.redis())
+            .get_comet_group_assignments()
+            .await?;
+        let loads = self.namespace_loads().await?;
+
+        let mut list = self
+            .ctx
+            .conf
+            .comet_groups
+            .iter()
+            .map(|g| GroupAssignment {
+                name: g.name.clone(),
+                comet_addrs: g.comet_addrs.clone(),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        for mut load in loads {
+            let Some(group) = current
+                .get(&load.namespace)
+                .and_then(|name| list.iter_mut().find(|v| &v.name == name))
+            else {
+                continue;
+            };
+            load.pinned = self
+                .ctx
+                .conf
+                .comet_groups
+                .iter()
+                .any(|g| g.name == group.name && g.namespaces.contains(&load.namespace));
+            group.instance_count += load.instance_count;
+            group.online_count += load.online_count;
+            group.namespaces.push(load);
+        }
+        Ok(list)
+    }
+}
+
+#[test]
+fn test_plan_assignments() {
+    let groups = vec![
+        CometGroup {
+            name: "a".to_string(),
+            namespaces: vec!["pinned".to_string()],
+            ..Default::default()
+        },
+        CometGroup {
+            name: "b".to_string(),
+            ..Default::default()
+        },
+    ];
+    let loads = [
+        ("pinned".to_string(), 50),
+        ("big".to_string(), 40),
+        ("mid".to_string(), 30),
+        ("small".to_string(), 5),
+    ]
+    .to_vec();
+
+    let planned = plan_assignments(&groups, &loads, &HashMap::new(), false);
+    assert_eq!(planned["pinned"], "a");
+    assert_eq!(planned["big"], "b");
+    assert_eq!(planned["mid"], "b");
+    assert_eq!(planned["small"], "a");
+
+    // assigned namespaces are kept until rebalance
+    let current = HashMap::from([
+        ("pinned".to_string(), "b".to_string()),
+        ("big".to_string(), "a".to_string()),
+        ("mid".to_string(), "a".to_string()),
+    ]);
+    let planned = plan_assignments(&groups, &loads, &current, false);
+    assert_eq!(planned["pinned"], "a");
+    assert_eq!(planned["big"], "a");
+    assert_eq!(planned["small"], "b");
+    assert_eq!(
+        diff_assignments(&current, &planned),
+        vec![
+            NamespaceMove {
+                namespace: "pinned".to_string(),
+                from: Some("b".to_string()),
+                to: "a".to_string(),
+            },
+            NamespaceMove {
+                namespace: "small".to_string(),
+                from: None,
+                to: "b".to_string(),
+            },
+        ]
+    );
+
+    let planned = plan_assignments(&groups, &loads, &current, true);
+    assert_eq!(planned["big"], "b");
+    assert_eq!(planned["mid"], "b");
+    assert_eq!(planned["small"], "a");
+}
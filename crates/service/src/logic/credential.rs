@@ -0,0 +1,584 @@
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow};
+use automate::JobAction;
+use chrono::{Local, TimeDelta};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter, QueryOrder,
+};
+use tracing::{error, info};
+
+use crate::{
+    IdGenerator,
+    entity::{instance, instance_credential, prelude::*},
+    logic::{
+        executor::{ExecutorLogic, MAX_SYNC_TIMEOUT_SECS, dispatch_sync},
+        ssh::{PrivateKey, SshHop},
+        types::UserServer,
+    },
+    state::AppContext,
+};
+
+pub const CRED_TYPE_PASSWORD: &str = "password";
+pub const CRED_TYPE_PRIVATE_KEY: &str = "private_key";
+
+pub const SCOPE_WEBSSH: &str = "webssh";
+pub const SCOPE_SFTP: &str = "sftp";
+/// the sys_user of the credential runs the jobs which have no work_user
+pub const SCOPE_JOB: &str = "job";
+const SCOPES: [&str; 3] = [SCOPE_WEBSSH, SCOPE_SFTP, SCOPE_JOB];
+
+/// bastions an ssh connection may go through
+const MAX_BASTION_HOPS: usize = 5;
+
+/// eid of the password rotations, their status updates are not recorded as job runs
+pub const ROTATE_EID_PREFIX: &str = "credential-rotate-";
+const ROTATED_PASSWORD_LEN: usize = 24;
+const PASSWORD_ALPHABET: [char; 62] = [
+    'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's',
+    't', 'u', 'v', 'w', 'x', 'y', 'z', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L',
+    'M', 'N', 'O', 'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z', '0', '1', '2', '3', '4',
+    '5', '6', '7', '8', '9',
+];
+
+/// decrypted credential to log in the instance
+#[derive(Clone, Debug, Default)]
+pub struct SshCredential {
+    pub sys_user: String,
+    pub cred_type: String,
+    /// password or private key
+    pub secret: String,
+    pub passphrase: Option<String>,
+}
+
+impl SshCredential {
+    /// empty for the private key credentials
+    pub fn password(&self) -> String {
+        if self.cred_type == CRED_TYPE_PRIVATE_KEY {
+            return String::new();
+        }
+        self.secret.clone()
+    }
+
+    pub fn private_key(&self) -> Option<PrivateKey> {
+        (self.cred_type == CRED_TYPE_PRIVATE_KEY).then(|| PrivateKey {
+            key: self.secret.clone(),
+            passphrase: self.passphrase.clone(),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SaveCredentialParams {
+    pub id: Option<u64>,
+    pub instance_id: String,
+    pub name: String,
+    pub cred_type: String,
+    pub sys_user: String,
+    /// the saved secret is kept if None on update
+    pub secret: Option<String>,
+    pub passphrase: Option<String>,
+    pub scopes: Vec<String>,
+    pub rotate_days: u32,
+}
+
+/// fails if the key cannot be decoded with the passphrase
+pub fn check_private_key(key: &str, passphrase: Option<&str>) -> Result<()> {
+    russh_keys::decode_secret_key(key, passphrase.filter(|v| !v.is_empty()))
+        .map_err(|e| anyhow!("invalid private key, {e}"))?;
+    Ok(())
+}
+
+/// append the bastion to the chain starting at instance_id, fails on a loop or a too long chain
+fn push_bastion(chain: &mut Vec<String>, instance_id: &str, bastion_id: &str) -> Result<()> {
+    if bastion_id == instance_id || chain.iter().any(|v| v == bastion_id) {
+        anyhow::bail!("bastion loop through instance {bastion_id}");
+    }
+    if chain.len() >= MAX_BASTION_HOPS {
+        anyhow::bail!("more than {MAX_BASTION_HOPS} bastions for instance {instance_id}");
+    }
+    chain.push(bastion_id.to_string());
+    Ok(())
+}
+
+fn has_scope(scopes: &str, scope: &str) -> bool {
+    scopes.split(',').any(|v| v.trim() == scope)
+}
+
+/// validated and deduplicated scopes joined with comma
+fn join_scopes(scopes: &[String]) -> Result<String> {
+    let mut list = vec![];
+    for scope in scopes.iter().map(|v| v.trim()).filter(|v| !v.is_empty()) {
+        if !SCOPES.contains(&scope) {
+            anyhow::bail!("invalid credential scope {scope}");
+        }
+        if !list.contains(&scope) {
+            list.push(scope);
+        }
+    }
+    Ok(list.join(","))
+}
+
+/// the user is written into the rotation script, so only the portable names are accepted
+fn is_valid_sys_user(user: &str) -> bool {
+    !user.is_empty()
+        && !user.starts_with('-')
+        && user
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
+
+fn is_rotation_due(record: &instance_credential::Model, now: chrono::DateTime<Local>) -> bool {
+    record.cred_type == CRED_TYPE_PASSWORD
+        && record.rotate_days > 0
+        && record.rotated_time.unwrap_or(record.created_time)
+            + TimeDelta::days(record.rotate_days.into())
+            <= now
+}
+
+#[derive(Clone)]
+pub struct CredentialLogic<'a> {
+    ctx: &'a AppContext,
+}
+
+impl<'a> CredentialLogic<'a> {
+    pub fn new(ctx: &'a AppContext) -> Self {
+        Self { ctx }
+    }
+
+    pub async fn save_credential(
+        &self,
+        params: SaveCredentialParams,
+        updated_user: &str,
+    ) -> Result<u64> {
+        if ![CRED_TYPE_PASSWORD, CRED_TYPE_PRIVATE_KEY].contains(&params.cred_type.as_str()) {
+            anyhow::bail!("invalid credential type {}", params.cred_type);
+        }
+        if !is_valid_sys_user(&params.sys_user) {
+            anyhow::bail!("invalid system user {}", params.sys_user);
+        }
+        if params.rotate_days > 0 && params.cred_type != CRED_TYPE_PASSWORD {
+            anyhow::bail!("only the password credentials can be rotated");
+        }
+        let scopes = join_scopes(&params.scopes)?;
+        if let Some(key) = params
+            .secret
+            .as_deref()
+            .filter(|v| !v.is_empty() && params.cred_type == CRED_TYPE_PRIVATE_KEY)
+        {
+            check_private_key(key, params.passphrase.as_deref())?;
+        }
+        let secret = params
+            .secret
+            .filter(|v| !v.is_empty())
+            .map(|v| self.ctx.encrypt(v))
+            .transpose()?;
+        let passphrase = params
+            .passphrase
+            .filter(|v| !v.is_empty())
+            .map(|v| self.ctx.encrypt(v))
+            .transpose()?;
+
+        let mut active_model = instance_credential::ActiveModel {
+            instance_id: Set(params.instance_id),
+            name: Set(params.name),
+            cred_type: Set(params.cred_type),
+            sys_user: Set(params.sys_user),
+            scopes: Set(scopes),
+            rotate_days: Set(params.rotate_days),
+            updated_user: Set(updated_user.to_string()),
+            ..Default::default()
+        };
+        match params.id {
+            Some(id) => {
+                InstanceCredential::find_by_id(id)
+                    .one(&self.ctx.db)
+                    .await?
+                    .ok_or(anyhow!("cannot found credential {id}"))?;
+                active_model.id = Set(id);
+                if let Some(secret) = secret {
+                    active_model.secret = Set(secret);
+                    active_model.passphrase = Set(passphrase);
+                }
+            }
+            None => {
+                active_model.secret = Set(secret.ok_or(anyhow!("secret is required"))?);
+                active_model.passphrase = Set(passphrase);
+                active_model.created_user = Set(updated_user.to_string());
+            }
+        }
+        Ok(active_model.save(&self.ctx.db).await?.id.unwrap())
+    }
+
+    /// credentials of the instance, the secrets are not returned
+    pub async fn query_credential(
+        &self,
+        instance_id: &str,
+    ) -> Result<Vec<instance_credential::Model>> {
+        let list = InstanceCredential::find()
+            .filter(instance_credential::Column::InstanceId.eq(instance_id))
+            .order_by_asc(instance_credential::Column::Id)
+            .all(&self.ctx.db)
+            .await?;
+        Ok(list
+            .into_iter()
+            .map(|v| instance_credential::Model {
+                secret: String::new(),
+                passphrase: None,
+                ..v
+            })
+            .collect())
+    }
+
+    pub async fn delete_credential(&self, id: u64) -> Result<u64> {
+        let ret = InstanceCredential::delete_by_id(id)
+            .exec(&self.ctx.db)
+            .await?;
+        Ok(ret.rows_affected)
+    }
+
+    fn decrypt_credential(&self, record: instance_credential::Model) -> Result<SshCredential> {
+        Ok(SshCredential {
+            sys_user: record.sys_user,
+            cred_type: record.cred_type,
+            secret: self.ctx.decrypt(record.secret)?,
+            passphrase: record
+                .passphrase
+                .filter(|v| !v.is_empty())
+                .map(|v| self.ctx.decrypt(v))
+                .transpose()?,
+        })
+    }
+
+    /// the latest updated credential of the instance having the scope
+    pub async fn get_scoped_credential(
+        &self,
+        instance_id: &str,
+        scope: &str,
+    ) -> Result<Option<SshCredential>> {
+        let record = InstanceCredential::find()
+            .filter(instance_credential::Column::InstanceId.eq(instance_id))
+            .order_by_desc(instance_credential::Column::UpdatedTime)
+            .all(&self.ctx.db)
+            .await?
+            .into_iter()
+            .find(|v| has_scope(&v.scopes, scope));
+        record.map(|v| self.decrypt_credential(v)).transpose()
+    }
+
+    /// the scoped credential of the instance, or the sys_user and the private key or password
+    /// saved on the instance
+    pub async fn resolve(&self, instance: &UserServer, scope: &str) -> Result<SshCredential> {
+        if let Some(v) = self
+            .get_scoped_credential(&instance.instance_id, scope)
+            .await?
+        {
+            return Ok(v);
+        }
+        let decrypt = |v: &Option<String>| {
+            v.clone()
+                .filter(|v| !v.is_empty())
+                .map(|v| self.ctx.decrypt(v))
+                .transpose()
+        };
+        let sys_user = instance.sys_user.clone().unwrap_or_default();
+        if let Some(key) = decrypt(&instance.private_key)? {
+            return Ok(SshCredential {
+                sys_user,
+                cred_type: CRED_TYPE_PRIVATE_KEY.to_string(),
+                secret: key,
+                passphrase: decrypt(&instance.key_passphrase)?,
+            });
+        }
+        Ok(SshCredential {
+            sys_user,
+            cred_type: CRED_TYPE_PASSWORD.to_string(),
+            secret: decrypt(&instance.password)?.unwrap_or_default(),
+            passphrase: None,
+        })
+    }
+
+    /// bastion of the instance, or the bastion of its group
+    async fn get_bastion_id(&self, instance_id: &str) -> Result<Option<String>> {
+        let Some(record) = Instance::find()
+            .filter(instance::Column::InstanceId.eq(instance_id))
+            .one(&self.ctx.db)
+            .await?
+        else {
+            return Ok(None);
+        };
+        if !record.bastion_instance_id.is_empty() {
+            return Ok(Some(record.bastion_instance_id));
+        }
+        if record.instance_group_id == 0 {
+            return Ok(None);
+        }
+        Ok(InstanceGroup::find_by_id(record.instance_group_id)
+            .one(&self.ctx.db)
+            .await?
+            .map(|v| v.bastion_instance_id)
+            .filter(|v| !v.is_empty() && v != instance_id))
+    }
+
+    /// the bastion has to be another instance
+    pub async fn check_bastion(&self, bastion_id: &str, id: Option<u64>) -> Result<()> {
+        let record = Instance::find()
+            .filter(instance::Column::InstanceId.eq(bastion_id))
+            .one(&self.ctx.db)
+            .await?
+            .ok_or(anyhow!("cannot found bastion instance {bastion_id}"))?;
+        if Some(record.id) == id {
+            anyhow::bail!("an instance cannot be its own bastion");
+        }
+        Ok(())
+    }
+
+    /// the bastions to go through to reach the instance, the outermost first
+    pub async fn get_bastion_chain(&self, instance_id: &str) -> Result<Vec<instance::Model>> {
+        let mut chain = vec![];
+        let mut current = instance_id.to_string();
+        while let Some(bastion_id) = self.get_bastion_id(&current).await? {
+            push_bastion(&mut chain, instance_id, &bastion_id)?;
+            current = bastion_id;
+        }
+
+        let mut list = vec![];
+        for bastion_id in chain.iter().rev() {
+            list.push(
+                Instance::find()
+                    .filter(instance::Column::InstanceId.eq(bastion_id))
+                    .one(&self.ctx.db)
+                    .await?
+                    .ok_or(anyhow!("cannot found bastion instance {bastion_id}"))?,
+            );
+        }
+        Ok(list)
+    }
+
+    /// the ssh servers to connect in order to log in the instance, like ssh -J.
+    /// the bastions are logged in with their own credential of the scope
+    pub async fn resolve_hops(
+        &self,
+        instance: &UserServer,
+        port: u16,
+        scope: &str,
+    ) -> Result<Vec<SshHop>> {
+        let mut hops = vec![];
+        for v in self.get_bastion_chain(&instance.instance_id).await? {
+            let bastion = UserServer {
+                instance_id: v.instance_id,
+                ip: v.ip,
+                sys_user: Some(v.sys_user),
+                password: Some(v.password),
+                private_key: v.private_key,
+                key_passphrase: v.key_passphrase,
+                ..Default::default()
+            };
+            hops.push(SshHop {
+                credential: self.resolve(&bastion, scope).await?,
+                ip: bastion.ip,
+                port: Some(v.ssh_port).filter(|&v| v != 0).unwrap_or(22),
+            });
+        }
+        hops.push(SshHop {
+            ip: instance.ip.clone(),
+            port,
+            credential: self.resolve(instance, scope).await?,
+        });
+        Ok(hops)
+    }
+
+    /// sys_user of the job scoped credential of every instance having one
+    pub async fn get_job_users(&self, instance_ids: &[String]) -> Result<HashMap<String, String>> {
+        let list = InstanceCredential::find()
+            .filter(instance_credential::Column::InstanceId.is_in(instance_ids))
+            .order_by_asc(instance_credential::Column::UpdatedTime)
+            .all(&self.ctx.db)
+            .await?;
+        Ok(list
+            .into_iter()
+            .filter(|v| has_scope(&v.scopes, SCOPE_JOB))
+            .map(|v| (v.instance_id, v.sys_user))
+            .collect())
+    }
+
+    /// set a random password for the sys_user on the instance through the agent,
+    /// the saved one is replaced only if it succeeded
+    pub async fn rotate_credential(&self, id: u64, updated_user: &str) -> Result<()> {
+        let record = InstanceCredential::find_by_id(id)
+            .one(&self.ctx.db)
+            .await?
+            .ok_or(anyhow!("cannot found credential {id}"))?;
+        if record.cred_type != CRED_TYPE_PASSWORD {
+            anyhow::bail!("only the password credentials can be rotated");
+        }
+        if !is_valid_sys_user(&record.sys_user) {
+            anyhow::bail!("invalid system user {}", record.sys_user);
+        }
+        let instance_record = Instance::find()
+            .filter(instance::Column::InstanceId.eq(&record.instance_id))
+            .one(&self.ctx.db)
+            .await?
+            .ok_or(anyhow!("cannot found instance {}", record.instance_id))?;
+        if ExecutorLogic::os_family(&instance_record.os) == Some("windows") {
+            anyhow::bail!("password rotation is not supported on windows");
+        }
+
+        let password = nanoid::nanoid!(ROTATED_PASSWORD_LEN, &PASSWORD_ALPHABET);
+        let params = automate::DispatchJobParams {
+            base_job: automate::BaseJob {
+                eid: format!("{ROTATE_EID_PREFIX}{id}"),
+                cmd_name: "sh".to_string(),
+                // read from stdin to keep the password out of the process list
+                code: format!("echo '{}:{password}' | chpasswd", record.sys_user),
+                read_code_from_stdin: true,
+                timeout: MAX_SYNC_TIMEOUT_SECS,
+                max_retry: Some(1),
+                max_parallel: Some(1),
+                ..Default::default()
+            },
+            run_id: IdGenerator::get_run_id(),
+            instance_id: Some(instance_record.instance_id.clone()),
+            fields: None,
+            restart_interval: None,
+            created_user: updated_user.to_string(),
+            schedule_id: String::new(),
+            timer_expr: None,
+            is_sync: true,
+            action: JobAction::Exec,
+            blackout_windows: vec![],
+            request_id: utils::current_request_id(),
+            dispatch_id: String::new(),
+        };
+        let data = dispatch_sync(
+            &automate::Logic::new(self.ctx.redis()),
+            &self.ctx.http_client,
+            &self.ctx.conf.comet_secret,
+            &instance_record,
+            params,
+        )
+        .await?;
+        if data["exit_code"].as_i64() != Some(0) {
+            anyhow::bail!(
+                "failed change password of {}, {}",
+                record.sys_user,
+                data["stderr"].as_str().unwrap_or_default()
+            );
+        }
+
+        instance_credential::ActiveModel {
+            id: Set(id),
+            secret: Set(self.ctx.encrypt(password)?),
+            rotated_time: Set(Some(Local::now())),
+            updated_user: Set(updated_user.to_string()),
+            ..Default::default()
+        }
+        .update(&self.ctx.db)
+        .await?;
+        Ok(())
+    }
+
+    /// rotate the passwords older than their rotate_days, returns the rotated count
+    pub async fn rotate_due_credentials(&self) -> Result<u64> {
+        let now = Local::now();
+        let list = InstanceCredential::find()
+            .filter(instance_credential::Column::CredType.eq(CRED_TYPE_PASSWORD))
+            .filter(instance_credential::Column::RotateDays.gt(0))
+            .all(&self.ctx.db)
+            .await?;
+
+        let mut rotated = 0;
+        for record in list.into_iter().filter(|v| is_rotation_due(v, now)) {
+            match self.rotate_credential(record.id, "system").await {
+                Ok(_) => {
+                    info!(
+                        "rotated credential {} of {}",
+                        record.name, record.instance_id
+                    );
+                    rotated += 1;
+                }
+                Err(e) => error!(
+                    "failed rotate credential {} of {} - {e:?}",
+                    record.name, record.instance_id
+                ),
+            }
+        }
+        Ok(rotated)
+    }
+}
+
+#[test]
+fn test_credential_scopes() {
+    assert_eq!(
+        join_scopes(&[
+            "sftp".to_string(),
+            " job ".to_string(),
+            "sftp".to_string(),
+            "".to_string()
+        ])
+        .unwrap(),
+        "sftp,job"
+    );
+    assert!(join_scopes(&["ftp".to_string()]).is_err());
+    assert!(has_scope("sftp,job", SCOPE_JOB));
+    assert!(!has_scope("sftp,job", SCOPE_WEBSSH));
+
+    let credential = SshCredential {
+        sys_user: "deploy".to_string(),
+        cred_type: CRED_TYPE_PRIVATE_KEY.to_string(),
+        secret: "key".to_string(),
+        passphrase: Some("pass".to_string()),
+    };
+    assert_eq!(credential.password(), "");
+    assert_eq!(
+        credential.private_key().unwrap().passphrase.unwrap(),
+        "pass"
+    );
+    assert!(check_private_key("not a key", None).is_err());
+
+    let key_pair = russh_keys::key::KeyPair::generate_ed25519().unwrap();
+    let mut pem = Vec::new();
+    russh_keys::encode_pkcs8_pem_encrypted(&key_pair, b"pass", 1, &mut pem).unwrap();
+    let pem = String::from_utf8(pem).unwrap();
+    assert!(check_private_key(&pem, Some("pass")).is_ok());
+    assert!(check_private_key(&pem, Some("wrong")).is_err());
+
+    let mut chain = vec![];
+    push_bastion(&mut chain, "i1", "b1").unwrap();
+    push_bastion(&mut chain, "i1", "b2").unwrap();
+    assert!(push_bastion(&mut chain, "i1", "b1").is_err());
+    assert!(push_bastion(&mut chain, "i1", "i1").is_err());
+    for id in ["b3", "b4", "b5"] {
+        push_bastion(&mut chain, "i1", id).unwrap();
+    }
+    assert!(push_bastion(&mut chain, "i1", "b6").is_err());
+
+    assert!(is_valid_sys_user("deploy.bot"));
+    assert!(!is_valid_sys_user("root' ; reboot '"));
+    assert!(!is_valid_sys_user("-r"));
+
+    let now = Local::now();
+    let record = instance_credential::Model {
+        cred_type: CRED_TYPE_PASSWORD.to_string(),
+        rotate_days: 30,
+        created_time: now - TimeDelta::days(40),
+        rotated_time: Some(now - TimeDelta::days(10)),
+        ..Default::default()
+    };
+    assert!(!is_rotation_due(&record, now));
+    assert!(is_rotation_due(
+        &instance_credential::Model {
+            rotated_time: None,
+            ..record.clone()
+        },
+        now
+    ));
+    assert!(!is_rotation_due(
+        &instance_credential::Model {
+            rotate_days: 0,
+            rotated_time: None,
+            ..record
+        },
+        now
+    ));
+}
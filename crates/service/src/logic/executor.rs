@@ -1,11 +1,93 @@
 use crate::{
-    entity::{self, executor, prelude::*},
+    IdGenerator,
+    entity::{self, executor, executor_acl, instance, instance_executor, job, prelude::*},
+    logic::{
+        job::types::BundleScriptRecord,
+        types::{ContainerOptions, PlatformCommands, UserInfo},
+    },
     state::AppContext,
 };
-use anyhow::Result;
+use anyhow::{Result, anyhow};
+use automate::{JobAction, scheduler::types::ContainerOption};
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder, QueryTrait,
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, PaginatorTrait, QueryFilter,
+    QueryOrder, QueryTrait, sea_query::OnConflict,
 };
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// eid of the install scripts, their status updates are not recorded as job runs
+pub const INSTALL_EID_PREFIX: &str = "executor-install-";
+/// a synchronous dispatch has to end before the comet stops waiting for the agent
+pub const MAX_SYNC_TIMEOUT_SECS: u64 = 80;
+
+pub const INSTALL_STATUS_INSTALLED: &str = "installed";
+pub const INSTALL_STATUS_FAILED: &str = "failed";
+
+pub const ACL_SUBJECT_TEAM: &str = "team";
+pub const ACL_SUBJECT_ROLE: &str = "role";
+
+/// an executor without acl entries can be used by everyone, otherwise only by the
+/// users of the listed roles or in the listed teams
+pub fn acl_allows(acl: &[executor_acl::Model], role_id: u64, team_ids: &[u64]) -> bool {
+    acl.is_empty()
+        || acl.iter().any(|v| match v.subject_type.as_str() {
+            ACL_SUBJECT_ROLE => v.subject_id == role_id,
+            ACL_SUBJECT_TEAM => team_ids.contains(&v.subject_id),
+            _ => false,
+        })
+}
+
+/// status, exit code and output of the install script from the dispatch result
+fn install_result(ret: Result<Value>) -> (&'static str, Option<i32>, String) {
+    match ret {
+        Ok(data) => {
+            let exit_code = data["exit_code"].as_i64().map(|v| v as i32);
+            let output = [&data["stdout"], &data["stderr"]]
+                .into_iter()
+                .filter_map(|v| v.as_str())
+                .collect::<Vec<_>>()
+                .join("");
+            let status = if exit_code == Some(0) {
+                INSTALL_STATUS_INSTALLED
+            } else {
+                INSTALL_STATUS_FAILED
+            };
+            (status, exit_code, output)
+        }
+        Err(e) => (INSTALL_STATUS_FAILED, None, e.to_string()),
+    }
+}
+
+/// dispatch the job with is_sync and wait for its output, eg: {"stdout", "stderr", "exit_code"}
+pub(crate) async fn dispatch_sync(
+    logic: &automate::Logic,
+    http_client: &reqwest::Client,
+    secret: &str,
+    instance_record: &instance::Model,
+    dispatch_params: automate::DispatchJobParams,
+) -> Result<Value> {
+    let pair = logic
+        .get_link_pair(instance_record.ip.clone(), instance_record.mac_addr.clone())
+        .await?;
+    let body = automate::DispatchJobRequest {
+        agent_ip: instance_record.ip.clone(),
+        mac_addr: instance_record.mac_addr.clone(),
+        dispatch_params,
+    };
+    let ret: Value = http_client
+        .post(format!("{}?secret={secret}", pair.1.http_url("/dispatch")))
+        .json(&body)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    if ret["code"] != 20000 {
+        anyhow::bail!("{}", ret["msg"]);
+    }
+    Ok(ret["data"].clone())
+}
 
 pub struct ExecutorList(Vec<entity::executor::Model>);
 
@@ -30,8 +112,11 @@ impl<'a> ExecutorLogic<'a> {
     }
 
     pub fn get_cmd_args(executor_record: &executor::Model) -> (String, Vec<String>) {
-        let binding = executor_record.command.clone();
-        let command_slice: Vec<&str> = binding.split(" ").collect();
+        Self::split_command(&executor_record.command)
+    }
+
+    fn split_command(command: &str) -> (String, Vec<String>) {
+        let command_slice: Vec<&str> = command.split(" ").collect();
 
         let cmd_name = command_slice
             .get(0)
@@ -42,6 +127,131 @@ impl<'a> ExecutorLogic<'a> {
         (cmd_name, cmd_args)
     }
 
+    pub const PLATFORM_CONTAINER: &'static str = "container";
+
+    /// os family of the os name reported by the agent, eg: linux for Ubuntu
+    pub fn os_family(os: &str) -> Option<&'static str> {
+        let os = os.to_lowercase();
+        if os.is_empty() {
+            None
+        } else if os.contains("windows") {
+            Some("windows")
+        } else if os.contains("darwin") || os.contains("mac") {
+            Some("darwin")
+        } else {
+            Some("linux")
+        }
+    }
+
+    /// numeric parts of the version, eg: v1.2.0-beta is [1, 2, 0]
+    pub fn parse_version(version: &str) -> Option<Vec<u64>> {
+        let version = version.trim().trim_start_matches('v');
+        let version = version.split(['-', '+']).next().unwrap_or_default();
+        version.split('.').map(|v| v.parse().ok()).collect()
+    }
+
+    /// the agent version which cannot be parsed does not satisfy any requirement
+    pub fn version_satisfied(version: &str, min_version: &str) -> bool {
+        let (Some(version), Some(min_version)) = (
+            Self::parse_version(version),
+            Self::parse_version(min_version),
+        ) else {
+            return false;
+        };
+        let len = version.len().max(min_version.len());
+        let pad = |v: Vec<u64>| v.into_iter().chain(std::iter::repeat(0)).take(len);
+        pad(version).cmp(pad(min_version)).is_ge()
+    }
+
+    /// command of the executor on the instance, fails if the agent is older than min_agent_version
+    /// or the executor has no command for the os of the instance
+    pub fn get_instance_cmd_args(
+        executor_record: &executor::Model,
+        instance_record: &instance::Model,
+    ) -> Result<(String, Vec<String>)> {
+        let min_version = &executor_record.min_agent_version;
+        if !min_version.is_empty()
+            && !Self::version_satisfied(&instance_record.agent_version, min_version)
+        {
+            anyhow::bail!(
+                "executor {} requires agent {} or later, but the agent version of {} is {}",
+                executor_record.name,
+                min_version,
+                instance_record.ip,
+                Some(instance_record.agent_version.as_str())
+                    .filter(|v| !v.is_empty())
+                    .unwrap_or("unknown"),
+            );
+        }
+
+        let commands: PlatformCommands = match executor_record.platform_commands.clone() {
+            Some(v) => serde_json::from_value(v)?,
+            None => PlatformCommands::default(),
+        };
+        let os_family = Self::os_family(&instance_record.os);
+        let command = match os_family {
+            Some("windows") => commands.windows,
+            Some("darwin") => commands.darwin,
+            Some(_) => commands.linux,
+            None => None,
+        }
+        .filter(|v| !v.trim().is_empty())
+        .unwrap_or_else(|| executor_record.command.clone());
+
+        if command.trim().is_empty() {
+            anyhow::bail!(
+                "executor {} has no command for the {} os of {}",
+                executor_record.name,
+                os_family.unwrap_or("unknown"),
+                instance_record.ip,
+            );
+        }
+        Ok(Self::split_command(&command))
+    }
+
+    /// command of the executor on every instance keyed by instance_id, the error is
+    /// reported as the dispatch result of the instance
+    pub fn get_targets_cmd_args(
+        executor_record: &executor::Model,
+        instances: &[instance::Model],
+    ) -> HashMap<String, Result<(String, Vec<String>), String>> {
+        instances
+            .iter()
+            .map(|v| {
+                (
+                    v.instance_id.clone(),
+                    Self::get_instance_cmd_args(executor_record, v).map_err(|e| e.to_string()),
+                )
+            })
+            .collect()
+    }
+
+    /// returns the container option if the executor runs scripts inside a container
+    pub fn get_container_option(
+        executor_record: &executor::Model,
+    ) -> Result<Option<ContainerOption>> {
+        if executor_record.platform != Self::PLATFORM_CONTAINER {
+            return Ok(None);
+        }
+        if executor_record.container_image.is_empty() {
+            anyhow::bail!("executor {} has no container image", executor_record.name);
+        }
+
+        let opts: ContainerOptions = match executor_record.container_options.clone() {
+            Some(v) => serde_json::from_value(v)?,
+            None => ContainerOptions::default(),
+        };
+
+        Ok(Some(ContainerOption {
+            runtime: opts.runtime,
+            image: executor_record.container_image.clone(),
+            cpus: opts.cpus,
+            memory: opts.memory,
+            network: opts.network,
+            extra_args: opts.extra_args,
+        }))
+    }
+
     pub async fn get_by_id(&self, id: u32) -> Result<Option<executor::Model>> {
         let one = Executor::find_by_id(id).one(&self.ctx.db).await?;
         Ok(one)
@@ -91,6 +301,344 @@ impl<'a> ExecutorLogic<'a> {
 
     pub async fn delete_job(&self, id: u32) -> Result<u64> {
         let ret = Executor::delete_by_id(id).exec(&self.ctx.db).await?;
+        InstanceExecutor::delete_many()
+            .filter(instance_executor::Column::ExecutorId.eq(id))
+            .exec(&self.ctx.db)
+            .await?;
+        ExecutorAcl::delete_many()
+            .filter(executor_acl::Column::ExecutorId.eq(id))
+            .exec(&self.ctx.db)
+            .await?;
         Ok(ret.rows_affected)
     }
+
+    pub async fn get_acl(&self, executor_id: u64) -> Result<Vec<executor_acl::Model>> {
+        Ok(ExecutorAcl::find()
+            .filter(executor_acl::Column::ExecutorId.eq(executor_id))
+            .order_by_asc(executor_acl::Column::Id)
+            .all(&self.ctx.db)
+            .await?)
+    }
+
+    /// replace the teams and roles allowed to use the executor, an empty list opens
+    /// the executor to everyone
+    pub async fn set_acl(
+        &self,
+        executor_id: u64,
+        team_ids: Vec<u64>,
+        role_ids: Vec<u64>,
+        updated_user: String,
+    ) -> Result<u64> {
+        if self.get_by_id(executor_id as u32).await?.is_none() {
+            anyhow::bail!("cannot found executor {executor_id}");
+        }
+        ExecutorAcl::delete_many()
+            .filter(executor_acl::Column::ExecutorId.eq(executor_id))
+            .exec(&self.ctx.db)
+            .await?;
+
+        let mut entries: Vec<(&str, u64)> = team_ids
+            .into_iter()
+            .map(|v| (ACL_SUBJECT_TEAM, v))
+            .chain(role_ids.into_iter().map(|v| (ACL_SUBJECT_ROLE, v)))
+            .collect();
+        entries.sort();
+        entries.dedup();
+        if entries.is_empty() {
+            return Ok(0);
+        }
+
+        let total = entries.len() as u64;
+        ExecutorAcl::insert_many(entries.into_iter().map(|(subject_type, subject_id)| {
+            executor_acl::ActiveModel {
+                executor_id: Set(executor_id),
+                subject_type: Set(subject_type.to_string()),
+                subject_id: Set(subject_id),
+                updated_user: Set(updated_user.clone()),
+                ..Default::default()
+            }
+        }))
+        .exec_without_returning(&self.ctx.db)
+        .await?;
+        Ok(total)
+    }
+
+    /// fails if the user cannot use one of the executors, team_id is the team the job
+    /// belongs to, the users who can manage all jobs are not restricted
+    pub async fn check_executor_permission(
+        &self,
+        user_info: &UserInfo,
+        team_id: Option<u64>,
+        mut executor_ids: Vec<u64>,
+    ) -> Result<()> {
+        executor_ids.retain(|v| *v != 0);
+        executor_ids.sort();
+        executor_ids.dedup();
+        if executor_ids.is_empty() {
+            return Ok(());
+        }
+
+        let acl = ExecutorAcl::find()
+            .filter(executor_acl::Column::ExecutorId.is_in(executor_ids))
+            .all(&self.ctx.db)
+            .await?;
+        if acl.is_empty() || self.ctx.can_manage_job(&user_info.user_id).await? {
+            return Ok(());
+        }
+
+        let mut acl_by_executor: HashMap<u64, Vec<executor_acl::Model>> = HashMap::new();
+        for v in acl {
+            acl_by_executor.entry(v.executor_id).or_default().push(v);
+        }
+        let team_ids: Vec<u64> = team_id.filter(|v| *v != 0).into_iter().collect();
+
+        for (executor_id, acl) in acl_by_executor {
+            if acl_allows(&acl, user_info.role_id, &team_ids) {
+                continue;
+            }
+            let name = self
+                .get_by_id(executor_id as u32)
+                .await?
+                .map_or(executor_id.to_string(), |v| v.name);
+            anyhow::bail!("no permission to use executor {name}");
+        }
+        Ok(())
+    }
+
+    /// the executor of the job and those of its bundle scripts
+    pub fn get_job_executor_ids(job_record: &job::Model) -> Result<Vec<u64>> {
+        let mut executor_ids = vec![job_record.executor_id];
+        if let Some(bundle_script) = job_record.bundle_script.clone() {
+            let scripts: Vec<BundleScriptRecord> = serde_json::from_value(bundle_script)?;
+            executor_ids.extend(scripts.into_iter().map(|v| v.executor_id));
+        }
+        Ok(executor_ids)
+    }
+
+    /// fails if the user cannot use the executors the job runs with
+    pub async fn check_job_executor_permission(
+        &self,
+        user_info: &UserInfo,
+        job_record: &job::Model,
+    ) -> Result<()> {
+        self.check_executor_permission(
+            user_info,
+            Some(job_record.team_id),
+            Self::get_job_executor_ids(job_record)?,
+        )
+        .await
+    }
+
+    /// run the install script of the executor on the instances and record whether
+    /// the executor is available on each of them
+    pub async fn ensure_executor(
+        &self,
+        executor_id: u64,
+        instance_ids: Vec<String>,
+        updated_user: String,
+    ) -> Result<Vec<instance_executor::Model>> {
+        let executor_record = Executor::find_by_id(executor_id)
+            .one(&self.ctx.db)
+            .await?
+            .ok_or(anyhow!("cannot found executor {executor_id}"))?;
+        let Some(install_script) = executor_record
+            .install_script
+            .clone()
+            .filter(|v| !v.trim().is_empty())
+        else {
+            anyhow::bail!("executor {} has no install script", executor_record.name);
+        };
+
+        let endpoints = Instance::find()
+            .filter(instance::Column::InstanceId.is_in(&instance_ids))
+            .all(&self.ctx.db)
+            .await?;
+        if endpoints.is_empty() {
+            anyhow::bail!("cannot found valid instance");
+        }
+
+        let logic = automate::Logic::new(self.ctx.redis());
+        let http_client = self.ctx.http_client.clone();
+        let secret = self.ctx.conf.comet_secret.clone();
+        let eid = format!("{INSTALL_EID_PREFIX}{executor_id}");
+        let ret = utils::async_batch_do(endpoints, move |v| {
+            let logic = logic.clone();
+            let http_client = http_client.clone();
+            let secret = secret.clone();
+            let (cmd_name, args) = match ExecutorLogic::os_family(&v.os) {
+                Some("windows") => ("powershell", vec!["-Command".to_string()]),
+                _ => ("sh", vec!["-c".to_string()]),
+            };
+            let params = automate::DispatchJobParams {
+                base_job: automate::BaseJob {
+                    eid: eid.clone(),
+                    cmd_name: cmd_name.to_string(),
+                    code: install_script.clone(),
+                    args,
+                    timeout: MAX_SYNC_TIMEOUT_SECS,
+                    max_retry: Some(1),
+                    max_parallel: Some(1),
+                    ..Default::default()
+                },
+                run_id: IdGenerator::get_run_id(),
+                instance_id: Some(v.instance_id.clone()),
+                fields: None,
+                restart_interval: None,
+                created_user: updated_user.clone(),
+                schedule_id: String::new(),
+                timer_expr: None,
+                is_sync: true,
+                action: JobAction::Exec,
+                blackout_windows: vec![],
+                request_id: utils::current_request_id(),
+                dispatch_id: String::new(),
+            };
+            let updated_user = updated_user.clone();
+            Box::pin(async move {
+                let ret = dispatch_sync(&logic, &http_client, &secret, &v, params).await;
+
+                let (status, exit_code, output) = install_result(ret);
+                Ok(instance_executor::Model {
+                    instance_id: v.instance_id,
+                    executor_id,
+                    status: status.to_string(),
+                    exit_code,
+                    output: Some(output),
+                    updated_user,
+                    ..Default::default()
+                })
+            })
+        })
+        .await;
+
+        let list = ret.into_iter().collect::<Result<Vec<_>>>()?;
+        InstanceExecutor::insert_many(list.iter().map(|v| instance_executor::ActiveModel {
+            instance_id: Set(v.instance_id.clone()),
+            executor_id: Set(v.executor_id),
+            status: Set(v.status.clone()),
+            exit_code: Set(v.exit_code),
+            output: Set(v.output.clone()),
+            updated_user: Set(v.updated_user.clone()),
+            ..Default::default()
+        }))
+        .on_conflict(
+            OnConflict::columns([
+                instance_executor::Column::InstanceId,
+                instance_executor::Column::ExecutorId,
+            ])
+            .update_columns([
+                instance_executor::Column::Status,
+                instance_executor::Column::ExitCode,
+                instance_executor::Column::Output,
+                instance_executor::Column::UpdatedUser,
+            ])
+            .to_owned(),
+        )
+        .exec_without_returning(&self.ctx.db)
+        .await?;
+        Ok(list)
+    }
+
+    /// availability of the executors on the instance
+    pub async fn get_instance_executors(
+        &self,
+        instance_id: &str,
+    ) -> Result<Vec<instance_executor::Model>> {
+        Ok(InstanceExecutor::find()
+            .filter(instance_executor::Column::InstanceId.eq(instance_id))
+            .order_by_asc(instance_executor::Column::ExecutorId)
+            .all(&self.ctx.db)
+            .await?)
+    }
+}
+
+#[test]
+fn test_acl_allows() {
+    let entry = |subject_type: &str, subject_id: u64| executor_acl::Model {
+        subject_type: subject_type.to_string(),
+        subject_id,
+        ..Default::default()
+    };
+    assert!(acl_allows(&[], 3, &[]));
+
+    let acl = vec![entry(ACL_SUBJECT_TEAM, 7), entry(ACL_SUBJECT_ROLE, 2)];
+    assert!(acl_allows(&acl, 2, &[]));
+    assert!(acl_allows(&acl, 3, &[7]));
+    assert!(!acl_allows(&acl, 3, &[8]));
+    assert!(!acl_allows(&acl, 7, &[2]));
+}
+
+#[test]
+fn test_get_instance_cmd_args() {
+    let executor_record = executor::Model {
+        name: "python".to_string(),
+        command: "python3 -c".to_string(),
+        platform_commands: Some(serde_json::json!({"windows": "python.exe -c"})),
+        min_agent_version: "1.2".to_string(),
+        ..Default::default()
+    };
+    let instance_record = |os: &str, agent_version: &str| instance::Model {
+        ip: "10.0.0.1".to_string(),
+        os: os.to_string(),
+        agent_version: agent_version.to_string(),
+        ..Default::default()
+    };
+
+    let (cmd_name, args) = ExecutorLogic::get_instance_cmd_args(
+        &executor_record,
+        &instance_record("Windows", "v1.2.0"),
+    )
+    .unwrap();
+    assert_eq!(cmd_name, "python.exe");
+    assert_eq!(args, vec!["-c"]);
+
+    let (cmd_name, _) =
+        ExecutorLogic::get_instance_cmd_args(&executor_record, &instance_record("Ubuntu", "1.10"))
+            .unwrap();
+    assert_eq!(cmd_name, "python3");
+
+    for agent_version in ["1.1.9", "", "dev"] {
+        assert!(
+            ExecutorLogic::get_instance_cmd_args(
+                &executor_record,
+                &instance_record("Ubuntu", agent_version)
+            )
+            .is_err()
+        );
+    }
+
+    let executor_record = executor::Model {
+        command: String::new(),
+        min_agent_version: String::new(),
+        ..executor_record
+    };
+    assert!(
+        ExecutorLogic::get_instance_cmd_args(&executor_record, &instance_record("Darwin", ""))
+            .is_err()
+    );
+}
+
+#[test]
+fn test_install_result() {
+    let ret = install_result(Ok(
+        serde_json::json!({"exit_code": 0, "stdout": "ok\n", "stderr": "warn\n"}),
+    ));
+    assert_eq!(
+        ret,
+        (INSTALL_STATUS_INSTALLED, Some(0), "ok\nwarn\n".to_string())
+    );
+
+    let ret = install_result(Ok(
+        serde_json::json!({"exit_code": 127, "stderr": "not found"}),
+    ));
+    assert_eq!(
+        ret,
+        (INSTALL_STATUS_FAILED, Some(127), "not found".to_string())
+    );
+
+    let ret = install_result(Err(anyhow!("agent offline")));
+    assert_eq!(
+        ret,
+        (INSTALL_STATUS_FAILED, None, "agent offline".to_string())
+    );
 }
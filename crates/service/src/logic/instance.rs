@@ -1,6 +1,10 @@
 use std::time::Duration;
 
 use anyhow::Context;
+use automate::bridge::msg::{AgentLogOutput, AgentLogParams, InstanceFacts};
+use automate::comet::types::{
+    AgentConnectionInfo, AgentCredential, AgentLogRequest, BootstrapToken, DisconnectAgentRequest,
+};
 use automate::scheduler::types::SshConnectionOption;
 use chrono::Local;
 
@@ -23,19 +27,72 @@ use tracing::warn;
 use utils::non_empty;
 
 use crate::IdGenerator;
+use crate::cache::CACHE_USER_SERVER;
 use crate::entity::instance_role;
 use crate::entity::tag;
 use crate::entity::tag_resource;
 use crate::entity::user;
 use crate::entity::{self, instance, instance_group, prelude::*, user_server};
+use crate::event::InstanceEvent;
 use crate::state::AppContext;
 use crate::state::AppState;
 use anyhow::Result;
 
 use super::job::types::InstanceStatSummary;
-use super::types;
+use super::tag::tag_scope_expr;
+use super::types::{self, ResourceType};
 use super::user::UserLogic;
 
+/// instances change rarely, the heartbeat status of a cached instance may lag this long
+const USER_SERVER_CACHE_SECS: u64 = 60;
+
+/// filter instances by the facts reported in heartbeat
+#[derive(Debug, Clone, Default)]
+pub struct InstanceFactsFilter {
+    pub os: Option<String>,
+    /// arm64 and amd64 also match aarch64 and x86_64
+    pub arch: Option<String>,
+    pub agent_version: Option<String>,
+    pub min_cpu_count: Option<u32>,
+    /// bytes
+    pub min_mem_total: Option<u64>,
+    /// ids of the tags accessible to a role limited by tags
+    pub tag_scope: Option<Vec<u64>>,
+}
+
+impl InstanceFactsFilter {
+    pub fn condition(&self) -> Condition {
+        Condition::all()
+            .add_option(non_empty!(self.os).map(|v| instance::Column::Os.eq(v)))
+            .add_option(non_empty!(self.arch).map(|v| {
+                let alias = match v.as_str() {
+                    "arm64" => "aarch64",
+                    "aarch64" => "arm64",
+                    "amd64" => "x86_64",
+                    "x86_64" => "amd64",
+                    _ => v.as_str(),
+                };
+                instance::Column::Arch.is_in([v.clone(), alias.to_string()])
+            }))
+            .add_option(
+                non_empty!(self.agent_version).map(|v| instance::Column::AgentVersion.eq(v)),
+            )
+            .add_option(
+                self.min_cpu_count
+                    .map(|v| instance::Column::CpuCount.gte(v)),
+            )
+            .add_option(
+                self.min_mem_total
+                    .map(|v| instance::Column::MemTotal.gte(v)),
+            )
+            .add_option(
+                self.tag_scope
+                    .clone()
+                    .map(|v| tag_scope_expr(instance::Column::Id, &[ResourceType::Instance], v)),
+            )
+    }
+}
+
 #[derive(Debug, FromQueryResult)]
 struct InstanceStatusCount {
     status: bool,
@@ -150,6 +207,29 @@ impl<'a> InstanceLogic<'a> {
                 .exec(&self.ctx.db)
                 .await?;
         }
+        self.ctx.cache.invalidate(CACHE_USER_SERVER).await;
+
+        if let Some(ins) = Instance::find()
+            .filter(instance::Column::Ip.eq(&agent_ip))
+            .filter(instance::Column::MacAddr.eq(&mac_addr))
+            .one(&self.ctx.db)
+            .await?
+        {
+            let event = if status == 1 {
+                InstanceEvent::Online {
+                    instance_id: ins.instance_id,
+                    ip: ins.ip,
+                    namespace: ins.namespace,
+                }
+            } else {
+                InstanceEvent::Offline {
+                    instance_id: ins.instance_id,
+                    ip: ins.ip,
+                    namespace: ins.namespace,
+                }
+            };
+            self.ctx.bus.emit(self.ctx, event).await;
+        }
 
         if status == 0 {
             return Ok(());
@@ -189,18 +269,42 @@ impl<'a> InstanceLogic<'a> {
                 .await
                 .context("failed insert user server")
                 .map_err(|v| warn!("{v:?}"));
+                self.ctx.cache.invalidate(CACHE_USER_SERVER).await;
             }
         }
 
         Ok(())
     }
 
-    pub async fn set_instance_online(&mut self, mac_addr: String, ip: String) -> Result<u64> {
-        let ret = Instance::update_many()
-            .set(instance::ActiveModel {
+    pub async fn set_instance_online(
+        &mut self,
+        mac_addr: String,
+        ip: String,
+        facts: Option<InstanceFacts>,
+    ) -> Result<u64> {
+        let model = match facts {
+            Some(v) => instance::ActiveModel {
                 status: Set(1),
+                os: Set(v.os),
+                os_version: Set(v.os_version),
+                kernel_version: Set(v.kernel_version),
+                arch: Set(v.arch),
+                cpu_count: Set(v.cpu_count),
+                mem_total: Set(v.mem_total),
+                mem_used: Set(v.mem_used),
+                disk_total: Set(v.disk_total),
+                disk_used: Set(v.disk_used),
+                agent_version: Set(v.agent_version),
+                facts_updated_time: Set(Some(Local::now())),
                 ..Default::default()
-            })
+            },
+            None => instance::ActiveModel {
+                status: Set(1),
+                ..Default::default()
+            },
+        };
+        let ret = Instance::update_many()
+            .set(model)
             .filter(instance::Column::MacAddr.eq(mac_addr))
             .filter(instance::Column::Ip.eq(ip))
             .exec(&self.ctx.db)
@@ -214,6 +318,8 @@ impl<'a> InstanceLogic<'a> {
         status: Option<u8>,
         role_id: u64,
         ignore_role_id: Option<u64>,
+        facts: &InstanceFactsFilter,
+        order_by: Option<(instance::Column, Order)>,
         page: u64,
         page_size: u64,
     ) -> Result<(Vec<types::InstanceRecord>, u64)> {
@@ -227,10 +333,22 @@ impl<'a> InstanceLogic<'a> {
                 instance::Column::Namespace,
                 instance::Column::Info,
                 instance::Column::Status,
+                instance::Column::Maintenance,
                 instance::Column::SysUser,
                 instance::Column::SshPort,
                 instance::Column::Password,
                 instance::Column::InstanceGroupId,
+                instance::Column::Os,
+                instance::Column::OsVersion,
+                instance::Column::KernelVersion,
+                instance::Column::Arch,
+                instance::Column::CpuCount,
+                instance::Column::MemTotal,
+                instance::Column::MemUsed,
+                instance::Column::DiskTotal,
+                instance::Column::DiskUsed,
+                instance::Column::AgentVersion,
+                instance::Column::FactsUpdatedTime,
                 instance::Column::CreatedTime,
                 instance::Column::UpdatedTime,
             ])
@@ -258,10 +376,13 @@ impl<'a> InstanceLogic<'a> {
             })
             .apply_if(ignore_role_id, |query, v| {
                 query.filter(instance_role::Column::RoleId.ne(v))
-            });
+            })
+            .filter(facts.condition());
         let total = model.clone().count(&self.ctx.db).await?;
+        let (order_column, order) =
+            order_by.unwrap_or((entity::instance::Column::UpdatedTime, Order::Asc));
         let list = model
-            .order_by_asc(entity::instance::Column::UpdatedTime)
+            .order_by(order_column, order)
             .into_model()
             .paginate(&self.ctx.db, page_size)
             .fetch_page(page)
@@ -274,6 +395,8 @@ impl<'a> InstanceLogic<'a> {
         ip: Option<String>,
         status: Option<u8>,
         ignore_role_id: Option<u64>,
+        facts: &InstanceFactsFilter,
+        order_by: Option<(instance::Column, Order)>,
         page: u64,
         page_size: u64,
     ) -> Result<(Vec<types::InstanceRecord>, u64)> {
@@ -305,12 +428,15 @@ impl<'a> InstanceLogic<'a> {
             })
             .apply_if(status, |query, v| {
                 query.filter(instance::Column::Status.eq(v))
-            });
+            })
+            .filter(facts.condition());
 
         let total = model.clone().count(&self.ctx.db).await?;
 
+        let (order_column, order) =
+            order_by.unwrap_or((entity::instance::Column::UpdatedTime, Order::Asc));
         let list = model
-            .order_by_asc(entity::instance::Column::UpdatedTime)
+            .order_by(order_column, order)
             .into_model()
             .paginate(&self.ctx.db, page_size)
             .fetch_page(page)
@@ -354,10 +480,12 @@ impl<'a> InstanceLogic<'a> {
             anyhow::bail!("no valid instance granted to the user")
         }
 
-        Ok(UserServer::insert_many(models)
+        let last_insert_id = UserServer::insert_many(models)
             .exec(&self.ctx.db)
             .await?
-            .last_insert_id)
+            .last_insert_id;
+        self.ctx.cache.invalidate(CACHE_USER_SERVER).await;
+        Ok(last_insert_id)
     }
 
     pub async fn query_server_by_tag(
@@ -368,6 +496,7 @@ impl<'a> InstanceLogic<'a> {
         ip: Option<Vec<String>>,
         instance_ids: Option<Vec<String>>,
         tag_id: Option<Vec<u64>>,
+        facts: &InstanceFactsFilter,
         page: u64,
         page_size: u64,
     ) -> Result<(Vec<types::UserServer>, u64)> {
@@ -381,6 +510,7 @@ impl<'a> InstanceLogic<'a> {
             .column(instance::Column::Info)
             .column_as(instance_group::Column::Name, "instance_group_name")
             .column(instance::Column::Status)
+            .column(instance::Column::Maintenance)
             .column(instance::Column::CreatedTime)
             .column(instance::Column::UpdatedTime)
             .column_as(tag::Column::Id, "tag_id")
@@ -427,7 +557,8 @@ impl<'a> InstanceLogic<'a> {
             .filter(instance::Column::Id.gt(0))
             .apply_if(non_empty!(tag_id), |query, v| {
                 query.filter(tag::Column::Id.is_in(v))
-            });
+            })
+            .filter(facts.condition());
 
         let total = model.clone().count(&self.ctx.db).await?;
 
@@ -446,6 +577,7 @@ impl<'a> InstanceLogic<'a> {
         instance_group_id: Option<u64>,
         status: Option<u8>,
         ip: Option<Vec<String>>,
+        facts: &InstanceFactsFilter,
         page: u64,
         page_size: u64,
     ) -> Result<(Vec<types::UserServer>, u64)> {
@@ -460,6 +592,7 @@ impl<'a> InstanceLogic<'a> {
             .column(instance::Column::InstanceGroupId)
             .column_as(instance_group::Column::Name, "instance_group_name")
             .column(instance::Column::Status)
+            .column(instance::Column::Maintenance)
             .column(instance::Column::CreatedTime)
             .column(instance::Column::UpdatedTime)
             .join_rev(
@@ -484,7 +617,8 @@ impl<'a> InstanceLogic<'a> {
             })
             .apply_if(instance_group_id, |query, v| {
                 query.filter(instance_group::Column::Id.eq(v))
-            });
+            })
+            .filter(facts.condition());
 
         let total = model.clone().count(&self.ctx.db).await?;
 
@@ -521,6 +655,18 @@ impl<'a> InstanceLogic<'a> {
                 .filter(instance::Column::Status.eq(true))
                 .exec(&self.ctx.db)
                 .await?;
+                self.ctx.cache.invalidate(CACHE_USER_SERVER).await;
+                self.ctx
+                    .bus
+                    .emit(
+                        self.ctx,
+                        InstanceEvent::Offline {
+                            instance_id: ins.instance_id,
+                            ip: ins.ip,
+                            namespace: ins.namespace,
+                        },
+                    )
+                    .await;
             }
         }
 
@@ -634,6 +780,7 @@ impl<'a> InstanceLogic<'a> {
         instance_group_id: Option<u64>,
         status: Option<u8>,
         ip: Option<Vec<String>>,
+        facts: &InstanceFactsFilter,
         page: u64,
         page_size: u64,
     ) -> Result<(Vec<types::UserServer>, u64)> {
@@ -648,6 +795,7 @@ impl<'a> InstanceLogic<'a> {
             .column(instance::Column::InstanceGroupId)
             .column_as(instance_group::Column::Name, "instance_group_name")
             .column(instance::Column::Status)
+            .column(instance::Column::Maintenance)
             .column(instance::Column::CreatedTime)
             .column(instance::Column::UpdatedTime)
             .join_rev(
@@ -689,6 +837,7 @@ impl<'a> InstanceLogic<'a> {
             .apply_if(instance_group_id, |query, v| {
                 query.filter(instance_group::Column::Id.eq(v))
             })
+            .filter(facts.condition())
             .as_query()
             .to_owned();
 
@@ -706,6 +855,7 @@ impl<'a> InstanceLogic<'a> {
                     .column(instance::Column::InstanceGroupId)
                     .column_as(instance_group::Column::Name, "instance_group_name")
                     .column(instance::Column::Status)
+                    .column(instance::Column::Maintenance)
                     .column(instance::Column::CreatedTime)
                     .column(instance::Column::UpdatedTime)
                     .join_rev(
@@ -736,6 +886,7 @@ impl<'a> InstanceLogic<'a> {
                     .apply_if(non_empty!(instance_id), |query, v| {
                         query.filter(instance::Column::InstanceId.is_in(v))
                     })
+                    .filter(facts.condition())
                     .as_query()
                     .clone(),
             )
@@ -780,6 +931,7 @@ impl<'a> InstanceLogic<'a> {
         model: instance::ActiveModel,
     ) -> Result<instance::ActiveModel> {
         let model = model.save(&self.ctx.db).await?;
+        self.ctx.cache.invalidate(CACHE_USER_SERVER).await;
         Ok(model)
     }
 
@@ -788,6 +940,7 @@ impl<'a> InstanceLogic<'a> {
         model: instance_group::ActiveModel,
     ) -> Result<instance_group::ActiveModel> {
         let model = model.save(&self.ctx.db).await?;
+        self.ctx.cache.invalidate(CACHE_USER_SERVER).await;
         Ok(model)
     }
 
@@ -881,6 +1034,7 @@ impl<'a> InstanceLogic<'a> {
             anyhow::bail!("cannot delete in used group {id}")
         }
         let ret = InstanceGroup::delete_by_id(id).exec(&self.ctx.db).await?;
+        self.ctx.cache.invalidate(CACHE_USER_SERVER).await;
         Ok(ret.rows_affected)
     }
 
@@ -899,11 +1053,14 @@ impl<'a> InstanceLogic<'a> {
             .column(instance::Column::Info)
             .column(instance::Column::MacAddr)
             .column(instance::Column::Password)
+            .column(instance::Column::PrivateKey)
+            .column(instance::Column::KeyPassphrase)
             .column(instance::Column::SysUser)
             .column(instance::Column::SshPort)
             .column(instance::Column::InstanceGroupId)
             .column_as(instance_group::Column::Name, "instance_group_name")
             .column(instance::Column::Status)
+            .column(instance::Column::Maintenance)
             .column(instance::Column::CreatedTime)
             .column(instance::Column::UpdatedTime)
             .join_rev(
@@ -945,7 +1102,10 @@ impl<'a> InstanceLogic<'a> {
             .column(instance::Column::SysUser)
             .column(instance::Column::SshPort)
             .column(instance::Column::Password)
+            .column(instance::Column::PrivateKey)
+            .column(instance::Column::KeyPassphrase)
             .column(instance::Column::Status)
+            .column(instance::Column::Maintenance)
             .column(instance::Column::InstanceGroupId)
             .column_as(instance_group::Column::Name, "instance_group_name")
             .column(instance::Column::CreatedTime)
@@ -1001,7 +1161,10 @@ impl<'a> InstanceLogic<'a> {
                 .column(instance::Column::SysUser)
                 .column(instance::Column::SshPort)
                 .column(instance::Column::Password)
+                .column(instance::Column::PrivateKey)
+                .column(instance::Column::KeyPassphrase)
                 .column(instance::Column::Status)
+                .column(instance::Column::Maintenance)
                 .column(instance::Column::InstanceGroupId)
                 .column_as(instance_group::Column::Name, "instance_group_name")
                 .column(instance::Column::CreatedTime)
@@ -1055,6 +1218,8 @@ impl<'a> InstanceLogic<'a> {
         Ok(one)
     }
 
+    /// the instance if the user can see it, the credentials are left out,
+    /// only the visibility is cached, the permission is checked on every call
     pub async fn get_one_user_server_with_permission(
         &self,
         state: AppState,
@@ -1062,15 +1227,51 @@ impl<'a> InstanceLogic<'a> {
         instance_id: String,
     ) -> Result<Option<types::UserServer>> {
         let can_manage_instance = state.can_manage_instance(&user_info.user_id).await?;
-        let instance_record = if can_manage_instance {
-            self.get_one_admin_server(None, None, Some(instance_id))
-                .await
-        } else {
-            self.get_one_user_server(None, None, Some(instance_id), user_info.user_id.to_string())
-                .await
-        };
+        let key = user_server_cache_key(&user_info.user_id, &instance_id, can_manage_instance);
+        self.ctx
+            .cache
+            .get_or_load(CACHE_USER_SERVER, &key, USER_SERVER_CACHE_SECS, || async {
+                let server = if can_manage_instance {
+                    self.get_one_admin_server(None, None, Some(instance_id))
+                        .await?
+                } else {
+                    self.get_one_user_server(
+                        None,
+                        None,
+                        Some(instance_id),
+                        user_info.user_id.to_string(),
+                    )
+                    .await?
+                };
+                Ok(server.map(without_credentials))
+            })
+            .await
+    }
 
-        instance_record
+    /// the instance with its ssh credentials if the user can see it, they are never cached
+    pub async fn get_one_user_server_with_credential(
+        &self,
+        state: AppState,
+        user_info: &types::UserInfo,
+        instance_id: String,
+    ) -> Result<Option<types::UserServer>> {
+        let Some(mut server) = self
+            .get_one_user_server_with_permission(state, user_info, instance_id)
+            .await?
+        else {
+            return Ok(None);
+        };
+        let Some(record) = Instance::find()
+            .filter(instance::Column::InstanceId.eq(&server.instance_id))
+            .one(&self.ctx.db)
+            .await?
+        else {
+            return Ok(None);
+        };
+        server.password = Some(record.password);
+        server.private_key = record.private_key;
+        server.key_passphrase = record.key_passphrase;
+        Ok(Some(server))
     }
 
     pub async fn set_status(
@@ -1088,7 +1289,233 @@ impl<'a> InstanceLogic<'a> {
             .filter(instance::Column::Id.is_in(id))
             .exec(&self.ctx.db)
             .await?;
+        self.ctx.cache.invalidate(CACHE_USER_SERVER).await;
 
         Ok(ret.rows_affected)
     }
+
+    /// drain the instances, the running jobs finish but no new job is dispatched to them
+    pub async fn set_maintenance(&self, id: Vec<u64>, maintenance: bool) -> Result<u64> {
+        let ret = Instance::update_many()
+            .set(instance::ActiveModel {
+                maintenance: Set(maintenance),
+                ..Default::default()
+            })
+            .filter(instance::Column::Id.is_in(id))
+            .exec(&self.ctx.db)
+            .await?;
+        self.ctx.cache.invalidate(CACHE_USER_SERVER).await;
+
+        Ok(ret.rows_affected)
+    }
+
+    /// create a bootstrap token for agent registration, returns the token secret
+    pub async fn create_bootstrap_token(
+        &self,
+        namespace: Option<String>,
+        single_use: bool,
+        expire_secs: Option<u64>,
+        info: String,
+        created_user: String,
+    ) -> Result<String> {
+        let now = Utc::now().timestamp();
+        automate::Logic::new(self.ctx.redis())
+            .create_bootstrap_token(BootstrapToken {
+                id: IdGenerator::get_bootstrap_token_uid(),
+                namespace: namespace.unwrap_or_default(),
+                single_use,
+                expire_time: expire_secs.map(|v| now + v as i64),
+                info,
+                created_user,
+                created_time: now,
+            })
+            .await
+    }
+
+    pub async fn query_bootstrap_token(&self) -> Result<Vec<BootstrapToken>> {
+        let list = automate::Logic::new(self.ctx.redis())
+            .get_bootstrap_tokens()
+            .await?;
+        Ok(list.into_iter().map(|v| v.1).collect())
+    }
+
+    pub async fn revoke_bootstrap_token(&self, id: &str) -> Result<u64> {
+        automate::Logic::new(self.ctx.redis())
+            .revoke_bootstrap_token(id)
+            .await
+    }
+
+    pub async fn query_agent_credential(&self) -> Result<Vec<AgentCredential>> {
+        automate::Logic::new(self.ctx.redis())
+            .get_agent_credentials()
+            .await
+    }
+
+    /// the live connection of the revoked agent is closed, and it is refused when it reconnects
+    pub async fn revoke_agent_credential(
+        &self,
+        id: Option<String>,
+        mac_addr: Option<String>,
+    ) -> Result<u64> {
+        let revoked = automate::Logic::new(self.ctx.redis())
+            .revoke_agent_credential(id.as_deref(), mac_addr.as_deref())
+            .await?;
+        for v in revoked.iter() {
+            // the agent may be offline already
+            if let Err(e) = self
+                .disconnect_agent(v.agent_ip.clone(), v.mac_addr.clone())
+                .await
+            {
+                warn!("failed to disconnect the revoked agent {} - {e}", v.id);
+            }
+        }
+        Ok(revoked.len() as u64)
+    }
+
+    /// the agents connected to the running comets, the errors of the unreachable comets
+    /// are returned along with them
+    pub async fn query_agent_connections(&self) -> Result<(Vec<AgentConnectionInfo>, Vec<String>)> {
+        let comets = automate::Logic::new(self.ctx.redis()).get_comets().await?;
+        let (mut list, mut errors) = (vec![], vec![]);
+        for comet in comets {
+            let ret = async {
+                let ret = self
+                    .ctx
+                    .http_client
+                    .get(comet.http_url("/agent/connections"))
+                    .send()
+                    .await?
+                    .json::<serde_json::Value>()
+                    .await?;
+                if ret["code"] != 20000 {
+                    anyhow::bail!("{}", ret["msg"]);
+                }
+                Ok(serde_json::from_value::<Vec<AgentConnectionInfo>>(
+                    ret["data"].clone(),
+                )?)
+            }
+            .await;
+            match ret {
+                Ok(v) => list.extend(v),
+                Err(e) => errors.push(format!("{} - {e}", comet.comet_addr)),
+            }
+        }
+        Ok((list, errors))
+    }
+
+    /// close the connection of the agent on its comet, the agent reconnects by itself
+    pub async fn disconnect_agent(&self, agent_ip: String, mac_addr: String) -> Result<()> {
+        let pair = automate::Logic::new(self.ctx.redis())
+            .get_link_pair(agent_ip.clone(), mac_addr.clone())
+            .await?;
+        let ret = self
+            .ctx
+            .http_client
+            .post(pair.1.http_url("/agent/disconnect"))
+            .json(&DisconnectAgentRequest { agent_ip, mac_addr })
+            .send()
+            .await?
+            .json::<serde_json::Value>()
+            .await?;
+        if ret["code"] != 20000 {
+            anyhow::bail!("failed disconnect agent, {}", ret["msg"]);
+        }
+        Ok(())
+    }
+
+    /// the recent log lines of the agent relayed by its comet
+    pub async fn get_agent_log(
+        &self,
+        agent_ip: String,
+        mac_addr: String,
+        params: AgentLogParams,
+    ) -> Result<AgentLogOutput> {
+        let pair = automate::Logic::new(self.ctx.redis())
+            .get_link_pair(agent_ip.clone(), mac_addr.clone())
+            .await?;
+        let ret = self
+            .ctx
+            .http_client
+            .post(pair.1.http_url("/agent/log"))
+            .json(&AgentLogRequest {
+                agent_ip,
+                mac_addr,
+                params,
+            })
+            .send()
+            .await?
+            .json::<serde_json::Value>()
+            .await?;
+        if ret["code"] != 20000 {
+            anyhow::bail!("failed get agent log, {}", ret["msg"]);
+        }
+        Ok(serde_json::from_value(ret["data"].clone())?)
+    }
+}
+
+fn user_server_cache_key(user_id: &str, instance_id: &str, can_manage_instance: bool) -> String {
+    if can_manage_instance {
+        format!("admin:{instance_id}")
+    } else {
+        format!("{user_id}:{instance_id}")
+    }
+}
+
+/// the cached records are shared in redis, the secrets stay in the database
+fn without_credentials(mut v: types::UserServer) -> types::UserServer {
+    v.password = None;
+    v.private_key = None;
+    v.key_passphrase = None;
+    v
+}
+
+#[test]
+fn test_user_server_cache_entry() {
+    assert_eq!(user_server_cache_key("u-1", "i-1", true), "admin:i-1");
+    assert_eq!(user_server_cache_key("u-1", "i-1", false), "u-1:i-1");
+
+    let v = without_credentials(types::UserServer {
+        instance_id: "i-1".to_string(),
+        password: Some("secret".to_string()),
+        private_key: Some("key".to_string()),
+        key_passphrase: Some("phrase".to_string()),
+        ..Default::default()
+    });
+    assert_eq!(v.instance_id, "i-1");
+    assert!(v.password.is_none() && v.private_key.is_none() && v.key_passphrase.is_none());
+}
+
+#[test]
+fn test_instance_facts_filter() {
+    let sql = |facts: InstanceFactsFilter| {
+        Instance::find()
+            .filter(facts.condition())
+            .build(DbBackend::MySql)
+            .to_string()
+    };
+
+    let v = sql(InstanceFactsFilter {
+        arch: Some("arm64".to_string()),
+        min_cpu_count: Some(4),
+        ..Default::default()
+    });
+    assert!(
+        v.contains("`instance`.`arch` IN ('arm64', 'aarch64')"),
+        "{v}"
+    );
+    assert!(v.contains("`instance`.`cpu_count` >= 4"), "{v}");
+
+    let v = sql(InstanceFactsFilter {
+        arch: Some("riscv64".to_string()),
+        os: Some("".to_string()),
+        ..Default::default()
+    });
+    // the empty os is not filtered
+    assert!(
+        v.ends_with("WHERE `instance`.`arch` IN ('riscv64', 'riscv64')"),
+        "{v}"
+    );
+
+    let v = sql(InstanceFactsFilter::default());
+    assert!(v.ends_with("WHERE TRUE"), "{v}");
 }
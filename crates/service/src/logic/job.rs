@@ -1,35 +1,84 @@
 use anyhow::{Ok, Result, anyhow};
 
+mod bulk;
 mod bundle_script;
+mod canary;
+pub mod code_policy;
+pub mod cond_expr;
+mod confirmation;
+mod daily_stat;
 mod dashboard;
+mod dedup;
+mod dispatch_stat;
+mod duration_anomaly;
 mod exec_history;
+mod export;
+mod lineage;
+pub mod log_sink;
+mod offline_policy;
+mod placement;
+mod promotion;
+mod quorum;
+mod recycle_bin;
+mod remediation;
 mod schedule;
+mod share;
+mod sla;
+mod status_page;
+mod status_writer;
 mod supervisor;
+mod test_run;
 mod timer;
+mod trigger;
+mod usage_stat;
 
 use automate::scheduler::types::ScheduleType;
 use chrono::Local;
 
 use entity::job_schedule;
 use sea_orm::{
-    ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, EntityTrait, PaginatorTrait,
-    QueryFilter, QueryOrder, QuerySelect, QueryTrait,
+    ActiveValue::{self, Set},
+    ColumnTrait, Condition, ConnectionTrait, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder,
+    QuerySelect, QueryTrait,
 };
-use sea_query::{Expr, Query};
+use sea_query::{Expr, IntoCondition, Query};
 
 use crate::{
     entity::{
         self, executor, instance, job, job_bundle_script, job_exec_history, job_running_status,
-        job_schedule_history, job_supervisor, job_timer, prelude::*, tag_resource, team,
-        team_member,
+        job_schedule_history, job_share, job_supervisor, job_timer, prelude::*, tag_resource, team,
     },
     state::AppContext,
 };
 use sea_orm::JoinType;
 
-use super::types::UserInfo;
+use super::{
+    tag::{JOB_RESOURCE_TYPES, tag_scope_expr},
+    types::{QuotaResource, UserInfo},
+};
 
 pub mod types;
+pub use canary::CanaryOption;
+pub use confirmation::{
+    ChallengeTarget, DispatchChallenge, JobConfirmation, JobPrompt, PROMPT_TYPES, PendingDispatch,
+};
+pub use export::{EXPORT_DIR, ExportFormat, ExportHistoryTask, ExportKind};
+pub use lineage::{LINEAGE_EXEC_LIMIT, RUN_KIND_RERUN, RUN_KIND_RETRY, RunLineage, RunNode};
+pub use offline_policy::{OFFLINE_POLICIES, OfflinePolicy};
+pub use placement::{PLACEMENT_STICKY, PLACEMENTS, PlacementDecision, exclude_maintenance};
+pub use promotion::{
+    PROMOTION_FAILED, PROMOTION_PENDING, PROMOTION_PROMOTED, PROMOTION_REJECTED, PromotionApproval,
+    PromotionLineage, PromotionSnapshot, TagRewrite,
+};
+pub use quorum::{QUORUM_FAILED, QUORUM_PASSED, QUORUM_PENDING, QuorumOption, QuorumVerdict};
+pub use remediation::RemediationSummary;
+pub use sla::{SLA_DURATION, SLA_FAILURE_STREAK, SLA_SUCCESS_RATE, SlaStatus};
+pub use status_page::{StatusPageJob, StatusPageView};
+pub use status_writer::{RunningStatusUpsert, StatusWrite, StatusWriter, StatusWriterTask};
+pub use test_run::{TEST_RUN_EID_PREFIX, TestRunOutput, TestRunParams};
+pub use timer::{SimulatedTick, TIMER_SIMULATION_LIMIT};
+pub use trigger::TriggerAuth;
+pub use usage_stat::parse_usage_month;
 
 enum EnforerResult<T> {
     Val(bool),
@@ -49,12 +98,63 @@ impl<'a> JobLogic<'a> {
     pub fn new(ctx: &'a AppContext) -> Self {
         Self { ctx }
     }
+    /// version is required when updating the job, see save_versioned
     pub async fn save_job(
         &self,
         model: entity::job::ActiveModel,
+        version: Option<u32>,
     ) -> Result<entity::job::ActiveModel> {
-        let model = model.save(&self.ctx.db).await?;
-        Ok(model)
+        self.check_job_quota(&model).await?;
+        super::save_versioned(
+            &self.ctx.db,
+            model,
+            job::Column::Id,
+            job::Column::Version,
+            version,
+        )
+        .await
+    }
+
+    /// a new job, or a job moved to another team, counts against the quota of the team
+    async fn check_job_quota(&self, model: &job::ActiveModel) -> Result<()> {
+        let ActiveValue::Set(team_id) = model.team_id else {
+            return Ok(());
+        };
+        if let ActiveValue::Set(id) | ActiveValue::Unchanged(id) = model.id {
+            let current = Job::find_by_id(id)
+                .select_only()
+                .column(job::Column::TeamId)
+                .into_tuple::<u64>()
+                .one(&self.ctx.db)
+                .await?;
+            if current == Some(team_id) {
+                return Ok(());
+            }
+        }
+        self.ctx
+            .service()
+            .team
+            .check_quota(team_id, QuotaResource::Job, 1)
+            .await
+    }
+
+    /// a new timer or supervisor, or one moved to another job, counts against the quota
+    /// of the team of its job. current_eid is the eid saved before
+    async fn check_job_resource_quota(
+        &self,
+        resource: QuotaResource,
+        eid: &ActiveValue<String>,
+        current_eid: Option<String>,
+    ) -> Result<()> {
+        let ActiveValue::Set(eid) = eid else {
+            return Ok(());
+        };
+        if current_eid.as_ref() == Some(eid) {
+            return Ok(());
+        }
+        let team = self.ctx.service().team;
+        let team_id = team.get_job_team_id(eid).await?;
+        team.check_quota(team_id, resource, 1).await
     }
 
     pub async fn can_write_bundle_script(
@@ -83,15 +183,12 @@ impl<'a> JobLogic<'a> {
         if is_in_team {
             return Ok(Some(job_record.team_id) == team_id);
         }
-        return Ok(TeamMember::find()
-            .apply_if(Some(job_record.team_id), |q, v| {
-                q.filter(team_member::Column::TeamId.eq(v))
-            })
-            .filter(team_member::Column::UserId.eq(&user_info.user_id))
-            .one(&self.ctx.db)
-            .await?
-            .map(|_| true)
-            == Some(true));
+        return self
+            .ctx
+            .service()
+            .team
+            .is_team_member(job_record.team_id, &user_info.user_id)
+            .await;
     }
 
     pub async fn can_write_bundle_script_by_id(
@@ -120,15 +217,29 @@ impl<'a> JobLogic<'a> {
         if is_in_team {
             return Ok(Some(bundle_script_record.team_id) == team_id);
         }
-        return Ok(TeamMember::find()
-            .apply_if(Some(bundle_script_record.team_id), |q, v| {
-                q.filter(team_member::Column::TeamId.eq(v))
-            })
-            .filter(team_member::Column::UserId.eq(&user_info.user_id))
-            .one(&self.ctx.db)
-            .await?
-            .map(|_| true)
-            == Some(true));
+        return self
+            .ctx
+            .service()
+            .team
+            .is_team_member(bundle_script_record.team_id, &user_info.user_id)
+            .await;
+    }
+
+    /// roles limited by tags can only access the jobs carrying the granted tags
+    async fn can_access_job_tags(
+        &self,
+        user_info: &UserInfo,
+        filter: impl IntoCondition,
+    ) -> Result<bool> {
+        let tag = self.ctx.service().tag;
+        let Some(tag_ids) = tag.get_tag_scope(user_info).await? else {
+            return Ok(true);
+        };
+        let Some(job_record) = Job::find().filter(filter).one(&self.ctx.db).await? else {
+            return Ok(false);
+        };
+        tag.is_resource_tagged(&JOB_RESOURCE_TYPES, job_record.id, tag_ids)
+            .await
     }
 
     pub async fn can_write_job(
@@ -137,6 +248,13 @@ impl<'a> JobLogic<'a> {
         team_id: Option<u64>,
         eid: Option<String>,
     ) -> Result<bool> {
+        if let Some(eid) = eid.as_deref()
+            && !self
+                .can_access_job_tags(user_info, job::Column::Eid.eq(eid))
+                .await?
+        {
+            return Ok(false);
+        }
         let (is_in_team, eid) = match self.enfore(user_info, team_id, eid).await? {
             EnforerResult::Val(v) => return Ok(v),
             EnforerResult::NextCheckVal(is_in_team, v) => (is_in_team, v),
@@ -157,15 +275,12 @@ impl<'a> JobLogic<'a> {
         if is_in_team {
             return Ok(Some(job_record.team_id) == team_id);
         }
-        return Ok(TeamMember::find()
-            .apply_if(Some(job_record.team_id), |q, v| {
-                q.filter(team_member::Column::TeamId.eq(v))
-            })
-            .filter(team_member::Column::UserId.eq(&user_info.user_id))
-            .one(&self.ctx.db)
-            .await?
-            .map(|_| true)
-            == Some(true));
+        return self
+            .ctx
+            .service()
+            .team
+            .is_team_member(job_record.team_id, &user_info.user_id)
+            .await;
     }
 
     pub async fn can_write_job_by_id(
@@ -174,6 +289,13 @@ impl<'a> JobLogic<'a> {
         team_id: Option<u64>,
         job_id: Option<u64>,
     ) -> Result<bool> {
+        if let Some(job_id) = job_id
+            && !self
+                .can_access_job_tags(user_info, job::Column::Id.eq(job_id))
+                .await?
+        {
+            return Ok(false);
+        }
         let (is_in_team, job_id) = match self.enfore(user_info, team_id, job_id).await? {
             EnforerResult::Val(v) => return Ok(v),
             EnforerResult::NextCheckVal(is_in_team, v) => (is_in_team, v),
@@ -194,15 +316,12 @@ impl<'a> JobLogic<'a> {
         if is_in_team {
             return Ok(Some(job_record.team_id) == team_id);
         }
-        return Ok(TeamMember::find()
-            .apply_if(Some(job_record.team_id), |q, v| {
-                q.filter(team_member::Column::TeamId.eq(v))
-            })
-            .filter(team_member::Column::UserId.eq(&user_info.user_id))
-            .one(&self.ctx.db)
-            .await?
-            .map(|_| true)
-            == Some(true));
+        return self
+            .ctx
+            .service()
+            .team
+            .is_team_member(job_record.team_id, &user_info.user_id)
+            .await;
     }
 
     pub async fn can_write_schedule_by_pid(
@@ -234,15 +353,15 @@ impl<'a> JobLogic<'a> {
             return Ok(EnforerResult::Val(true));
         }
 
-        let is_in_team = if team_id.is_some() {
-            TeamMember::find()
-                .apply_if(team_id, |q, v| q.filter(team_member::Column::TeamId.eq(v)))
-                .filter(team_member::Column::UserId.eq(&user_info.user_id))
-                .one(&self.ctx.db)
-                .await?
-                .map_or(false, |_| true)
-        } else {
-            false
+        let is_in_team = match team_id {
+            Some(team_id) => {
+                self.ctx
+                    .service()
+                    .team
+                    .is_team_member(team_id, &user_info.user_id)
+                    .await?
+            }
+            None => false,
         };
 
         let Some(ntv) = target else {
@@ -288,15 +407,12 @@ impl<'a> JobLogic<'a> {
         if is_in_team {
             return Ok(Some(job_record.team_id) == team_id);
         }
-        return Ok(TeamMember::find()
-            .apply_if(Some(job_record.team_id), |q, v| {
-                q.filter(team_member::Column::TeamId.eq(v))
-            })
-            .filter(team_member::Column::UserId.eq(&user_info.user_id))
-            .one(&self.ctx.db)
-            .await?
-            .map(|_| true)
-            == Some(true));
+        return self
+            .ctx
+            .service()
+            .team
+            .is_team_member(job_record.team_id, &user_info.user_id)
+            .await;
     }
 
     pub async fn can_write_job_timer_by_id(
@@ -333,15 +449,12 @@ impl<'a> JobLogic<'a> {
         if is_in_team {
             return Ok(Some(job_record.team_id) == team_id);
         }
-        return Ok(TeamMember::find()
-            .apply_if(Some(job_record.team_id), |q, v| {
-                q.filter(team_member::Column::TeamId.eq(v))
-            })
-            .filter(team_member::Column::UserId.eq(&user_info.user_id))
-            .one(&self.ctx.db)
-            .await?
-            .map(|_| true)
-            == Some(true));
+        return self
+            .ctx
+            .service()
+            .team
+            .is_team_member(job_record.team_id, &user_info.user_id)
+            .await;
     }
 
     pub async fn can_write_job_supervisor_by_id(
@@ -379,15 +492,12 @@ impl<'a> JobLogic<'a> {
         if is_in_team {
             return Ok(Some(job_record.team_id) == team_id);
         }
-        return Ok(TeamMember::find()
-            .apply_if(Some(job_record.team_id), |q, v| {
-                q.filter(team_member::Column::TeamId.eq(v))
-            })
-            .filter(team_member::Column::UserId.eq(&user_info.user_id))
-            .one(&self.ctx.db)
-            .await?
-            .map(|_| true)
-            == Some(true));
+        return self
+            .ctx
+            .service()
+            .team
+            .is_team_member(job_record.team_id, &user_info.user_id)
+            .await;
     }
 
     pub async fn can_dispatch_job(
@@ -397,9 +507,12 @@ impl<'a> JobLogic<'a> {
         schedule_user: Option<&str>,
         eid: &str,
     ) -> Result<bool> {
+        // teams the job is shared with can run it, but cannot edit it
         if !self
             .can_write_job(user_info, team_id, Some(eid.to_string()))
             .await?
+            && self.get_share_permission(user_info, team_id, eid).await?
+                != Some(types::SharePermission::Dispatch)
         {
             return Ok(false);
         }
@@ -422,6 +535,7 @@ impl<'a> JobLogic<'a> {
         if ok {
             let v = Job::find()
                 .filter(job::Column::Eid.eq(eid))
+                .filter(job::Column::IsDeleted.eq(false))
                 .one(&self.ctx.db)
                 .await?;
             v.ok_or(anyhow::anyhow!("no permission to write job"))
@@ -433,6 +547,7 @@ impl<'a> JobLogic<'a> {
                 .apply_if(team_id.map_or(Some(username), |_| None), |q, v| {
                     q.filter(job::Column::Eid.eq(eid).and(job::Column::CreatedUser.eq(v)))
                 })
+                .filter(job::Column::IsDeleted.eq(false))
                 .one(&self.ctx.db)
                 .await?
                 .ok_or(anyhow::anyhow!("no permission to write job"))
@@ -449,6 +564,7 @@ impl<'a> JobLogic<'a> {
         default_eid: Option<String>,
         team_id: Option<u64>,
         tag_ids: Option<Vec<u64>>,
+        tag_scope: Option<Vec<u64>>,
         page: u64,
         page_size: u64,
     ) -> Result<(Vec<types::JobRelatedExecutorModel>, u64)> {
@@ -478,7 +594,18 @@ impl<'a> JobLogic<'a> {
             .apply_if(job_type, |query, v| {
                 query.filter(job::Column::JobType.eq(v))
             })
-            .apply_if(team_id, |q, v| q.filter(job::Column::TeamId.eq(v)))
+            .apply_if(team_id, |q, v| {
+                // jobs shared with the team are listed along with its own jobs
+                q.filter(
+                    job::Column::TeamId.eq(v).or(job::Column::Eid.in_subquery(
+                        Query::select()
+                            .column(job_share::Column::Eid)
+                            .and_where(job_share::Column::TeamId.eq(v))
+                            .from(JobShare)
+                            .to_owned(),
+                    )),
+                )
+            })
             .apply_if(name, |q, v| q.filter(job::Column::Name.contains(v)))
             .apply_if(updated_time_range, |query, v| {
                 query.filter(
@@ -486,6 +613,9 @@ impl<'a> JobLogic<'a> {
                         .gt(v.0)
                         .and(job::Column::UpdatedTime.lt(v.1)),
                 )
+            })
+            .apply_if(tag_scope, |q, v| {
+                q.filter(tag_scope_expr(job::Column::Id, &JOB_RESOURCE_TYPES, v))
             });
 
         match tag_ids {
@@ -522,10 +652,19 @@ impl<'a> JobLogic<'a> {
     }
 
     pub async fn delete_job(&self, user_info: &UserInfo, eid: String) -> Result<u64> {
+        Self::soft_delete_job(&self.ctx.db, user_info, &eid).await
+    }
+
+    /// mark the job and its schedules deleted, db may be a transaction
+    async fn soft_delete_job<C: ConnectionTrait>(
+        db: &C,
+        user_info: &UserInfo,
+        eid: &str,
+    ) -> Result<u64> {
         if JobTimer::find()
-            .filter(job_timer::Column::Eid.eq(&eid))
+            .filter(job_timer::Column::Eid.eq(eid))
             .filter(job_timer::Column::IsDeleted.eq(false))
-            .one(&self.ctx.db)
+            .one(db)
             .await?
             .is_some()
         {
@@ -533,51 +672,54 @@ impl<'a> JobLogic<'a> {
         }
 
         if JobSupervisor::find()
-            .filter(job_supervisor::Column::Eid.eq(&eid))
+            .filter(job_supervisor::Column::Eid.eq(eid))
             .filter(job_supervisor::Column::IsDeleted.eq(false))
-            .one(&self.ctx.db)
+            .one(db)
             .await?
             .is_some()
         {
             anyhow::bail!("do not delete jobs linked to supervisors")
         }
 
+        // the schedules share the deletion time of the job, so they can be restored along with it
+        let deleted_at = Local::now();
         let ret = Job::update_many()
             .set(job::ActiveModel {
                 is_deleted: Set(true),
-                deleted_at: Set(Some(Local::now())),
+                deleted_at: Set(Some(deleted_at)),
                 deleted_by: Set(user_info.username.clone()),
                 ..Default::default()
             })
-            .filter(job::Column::Eid.eq(&eid))
-            .exec(&self.ctx.db)
+            .filter(job::Column::Eid.eq(eid))
+            .filter(job::Column::IsDeleted.eq(false))
+            .exec(db)
             .await?;
 
         JobRunningStatus::update_many()
             .set(job_running_status::ActiveModel {
                 is_deleted: Set(true),
-                deleted_at: Set(Some(Local::now())),
+                deleted_at: Set(Some(deleted_at)),
                 deleted_by: Set(user_info.username.clone()),
                 ..Default::default()
             })
-            .filter(job_running_status::Column::Eid.eq(&eid))
-            .exec(&self.ctx.db)
+            .filter(job_running_status::Column::Eid.eq(eid))
+            .exec(db)
             .await?;
 
         JobScheduleHistory::update_many()
             .set(job_schedule_history::ActiveModel {
                 is_deleted: Set(true),
-                deleted_at: Set(Some(Local::now())),
+                deleted_at: Set(Some(deleted_at)),
                 deleted_by: Set(user_info.username.clone()),
                 ..Default::default()
             })
-            .filter(job_schedule_history::Column::Eid.eq(&eid))
-            .exec(&self.ctx.db)
+            .filter(job_schedule_history::Column::Eid.eq(eid))
+            .exec(db)
             .await?;
 
         JobExecHistory::delete_many()
-            .filter(job_exec_history::Column::Eid.eq(&eid))
-            .exec(&self.ctx.db)
+            .filter(job_exec_history::Column::Eid.eq(eid))
+            .exec(db)
             .await?;
 
         Ok(ret.rows_affected)
@@ -593,6 +735,7 @@ impl<'a> JobLogic<'a> {
         job_type: Option<String>,
         updated_time_range: Option<(String, String)>,
         tag_ids: Option<Vec<u64>>,
+        tag_scope: Option<Vec<u64>>,
         page: u64,
         page_size: u64,
     ) -> Result<(Vec<types::RunStatusRelatedScheduleJobModel>, u64)> {
@@ -601,6 +744,7 @@ impl<'a> JobLogic<'a> {
             .column_as(instance::Column::Ip, "bind_ip")
             .column_as(instance::Column::Namespace, "bind_namespace")
             .column_as(instance::Column::Status, "is_online")
+            .column_as(instance::Column::Maintenance, "maintenance")
             .column_as(job_schedule_history::Column::Name, "schedule_name")
             .column_as(job_schedule_history::Column::DispatchData, "dispatch_data")
             .column_as(executor::Column::Name, "executor_name")
@@ -688,6 +832,9 @@ impl<'a> JobLogic<'a> {
             }
             _ => {}
         };
+        if let Some(v) = tag_scope {
+            select = select.filter(tag_scope_expr(job::Column::Id, &JOB_RESOURCE_TYPES, v));
+        }
 
         let total = select.clone().count(&self.ctx.db).await?;
 
@@ -744,6 +891,7 @@ impl<'a> JobLogic<'a> {
     pub async fn get_job_by_eid(&self, eid: &str) -> Result<Option<job::Model>> {
         let model = Job::find()
             .filter(job::Column::Eid.eq(eid))
+            .filter(job::Column::IsDeleted.eq(false))
             .one(&self.ctx.db)
             .await?;
         Ok(model)
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter,
+    TransactionTrait,
+};
+
+use super::{JobLogic, types::BulkItemResult};
+use crate::{
+    entity::{job, prelude::*, tag_resource},
+    logic::{
+        tag::TagLogic,
+        types::{ResourceType, UserInfo},
+    },
+};
+
+/// message of the items applied in a transaction which was rolled back by another item
+const ROLLED_BACK: &str = "rolled back, another item failed";
+
+/// commit the transaction if every item succeeded, otherwise roll it back and
+/// mark the succeeded items as rolled back. returns whether the changes are applied
+async fn finish(txn: DatabaseTransaction, results: &mut [BulkItemResult]) -> Result<bool> {
+    if results.iter().all(|v| v.ok) {
+        txn.commit().await?;
+        return Ok(true);
+    }
+    txn.rollback().await?;
+    mark_rolled_back(results);
+    Ok(false)
+}
+
+fn mark_rolled_back(results: &mut [BulkItemResult]) {
+    for v in results.iter_mut().filter(|v| v.ok) {
+        v.ok = false;
+        v.message = ROLLED_BACK.to_string();
+    }
+}
+
+impl<'a> JobLogic<'a> {
+    /// the jobs which are not deleted, keyed by eid
+    async fn get_bulk_jobs(&self, eids: &[String]) -> Result<HashMap<String, job::Model>> {
+        Ok(Job::find()
+            .filter(job::Column::Eid.is_in(eids))
+            .filter(job::Column::IsDeleted.eq(false))
+            .all(&self.ctx.db)
+            .await?
+            .into_iter()
+            .map(|v| (v.eid.clone(), v))
+            .collect())
+    }
+
+    /// delete the jobs in a transaction, nothing is deleted if any of them fails,
+    /// eg: the user cannot write one of them
+    pub async fn bulk_delete_job(
+        &self,
+        user_info: &UserInfo,
+        team_id: Option<u64>,
+        eids: &[String],
+    ) -> Result<(bool, Vec<BulkItemResult>)> {
+        let txn = self.ctx.db.begin().await?;
+        let mut results = Vec::with_capacity(eids.len());
+        for eid in eids {
+            if !self
+                .can_write_job(user_info, team_id, Some(eid.clone()))
+                .await?
+            {
+                results.push(BulkItemResult::err(eid, "no permission"));
+                continue;
+            }
+            results.push(match Self::soft_delete_job(&txn, user_info, eid).await {
+                Ok(0) => BulkItemResult::err(eid, "cannot found the job"),
+                Ok(_) => BulkItemResult::ok(eid, 0),
+                Err(e) => BulkItemResult::err(eid, e),
+            });
+        }
+        let applied = finish(txn, &mut results).await?;
+        Ok((applied, results))
+    }
+
+    /// enable or disable the jobs in a transaction, the disabled jobs cannot be started
+    pub async fn bulk_set_disabled(
+        &self,
+        user_info: &UserInfo,
+        team_id: Option<u64>,
+        eids: &[String],
+        disabled: bool,
+    ) -> Result<(bool, Vec<BulkItemResult>)> {
+        let jobs = self.get_bulk_jobs(eids).await?;
+        let txn = self.ctx.db.begin().await?;
+        let mut results = Vec::with_capacity(eids.len());
+        for eid in eids {
+            let Some(record) = jobs.get(eid) else {
+                results.push(BulkItemResult::err(eid, "cannot found the job"));
+                continue;
+            };
+            if !self
+                .can_write_job(user_info, team_id, Some(eid.clone()))
+                .await?
+            {
+                results.push(BulkItemResult::err(eid, "no permission"));
+                continue;
+            }
+            let ret = job::ActiveModel {
+                id: Set(record.id),
+                is_disabled: Set(disabled),
+                updated_user: Set(user_info.username.clone()),
+                ..Default::default()
+            }
+            .update(&txn)
+            .await;
+            results.push(match ret {
+                Ok(_) => BulkItemResult::ok(eid, record.id),
+                Err(e) => BulkItemResult::err(eid, e),
+            });
+        }
+        let applied = finish(txn, &mut results).await?;
+        Ok((applied, results))
+    }
+
+    /// bind the tag to the jobs in a transaction, the tag is created if not exists
+    pub async fn bulk_bind_tag(
+        &self,
+        user_info: &UserInfo,
+        team_id: Option<u64>,
+        eids: &[String],
+        tag_name: &str,
+    ) -> Result<(bool, Vec<BulkItemResult>)> {
+        let jobs = self.get_bulk_jobs(eids).await?;
+        let txn = self.ctx.db.begin().await?;
+        let tag_id = TagLogic::ensure_tag(&txn, &user_info.username, tag_name).await?;
+
+        let mut results = Vec::with_capacity(eids.len());
+        for eid in eids {
+            let Some(record) = jobs.get(eid) else {
+                results.push(BulkItemResult::err(eid, "cannot found the job"));
+                continue;
+            };
+            if !self
+                .can_write_job(user_info, team_id, Some(eid.clone()))
+                .await?
+            {
+                results.push(BulkItemResult::err(eid, "no permission"));
+                continue;
+            }
+            let resource_type = if record.job_type == "bundle" {
+                ResourceType::BundleJob
+            } else {
+                ResourceType::Job
+            };
+            let bound = TagResource::find()
+                .filter(tag_resource::Column::TagId.eq(tag_id))
+                .filter(tag_resource::Column::ResourceType.eq(resource_type.to_string()))
+                .filter(tag_resource::Column::ResourceId.eq(record.id))
+                .one(&txn)
+                .await?;
+            if bound.is_some() {
+                results.push(BulkItemResult::ok(eid, tag_id));
+                continue;
+            }
+            let ret = tag_resource::ActiveModel {
+                tag_id: Set(tag_id),
+                resource_type: Set(resource_type.to_string()),
+                resource_id: Set(record.id),
+                created_user: Set(user_info.username.clone()),
+                ..Default::default()
+            }
+            .insert(&txn)
+            .await;
+            results.push(match ret {
+                Ok(_) => BulkItemResult::ok(eid, tag_id),
+                Err(e) => BulkItemResult::err(eid, e),
+            });
+        }
+        let applied = finish(txn, &mut results).await?;
+        Ok((applied, results))
+    }
+}
+
+#[test]
+fn test_mark_rolled_back() {
+    let mut results = vec![
+        BulkItemResult::ok("a", 1),
+        BulkItemResult::err("b", "cannot found the job"),
+    ];
+    mark_rolled_back(&mut results);
+    assert!(results.iter().all(|v| !v.ok));
+    assert_eq!(results[0].message, ROLLED_BACK);
+    assert_eq!(results[1].message, "cannot found the job");
+}
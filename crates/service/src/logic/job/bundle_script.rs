@@ -12,9 +12,7 @@ use sea_orm::Condition;
 use sea_orm::JoinType;
 use sea_orm::QuerySelect;
 use sea_orm::QueryTrait;
-use sea_orm::{
-    ActiveModelTrait, ColumnTrait, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder,
-};
+use sea_orm::{ColumnTrait, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder};
 use sea_query::Expr;
 
 use super::types;
@@ -24,9 +22,16 @@ impl<'a> JobLogic<'a> {
     pub async fn save_job_bundle_script(
         &self,
         active_model: job_bundle_script::ActiveModel,
+        version: Option<u32>,
     ) -> Result<job_bundle_script::ActiveModel> {
-        let active_model = active_model.save(&self.ctx.db).await?;
-        Ok(active_model)
+        crate::logic::save_versioned(
+            &self.ctx.db,
+            active_model,
+            job_bundle_script::Column::Id,
+            job_bundle_script::Column::Version,
+            version,
+        )
+        .await
     }
 
     pub async fn query_bundle_script(
@@ -96,6 +101,7 @@ impl<'a> JobLogic<'a> {
         let has = Job::find()
             .filter(cond)
             .filter(job::Column::JobType.eq("bundle"))
+            .filter(job::Column::IsDeleted.eq(false))
             .one(&self.ctx.db)
             .await?;
         if has.is_some() {
@@ -121,6 +127,7 @@ impl<'a> JobLogic<'a> {
     ) -> Result<Option<job_bundle_script::Model>> {
         let model = JobBundleScript::find()
             .filter(job_bundle_script::Column::Eid.eq(eid))
+            .filter(job_bundle_script::Column::IsDeleted.eq(false))
             .one(&self.ctx.db)
             .await?;
         Ok(model)
@@ -0,0 +1,227 @@
+//! canary dispatch of the exec action
+//!
+//! the first `size` targets run the job synchronously, their outputs are checked against
+//! the success criteria, then the job is dispatched to the rest if the error rate is tolerated,
+//! otherwise the rest are skipped and the schedule fails.
+//! each dispatch result carries the stage it belongs to and the verdict of the stage
+use std::{future::Future, pin::Pin};
+
+use anyhow::Result;
+use automate::bridge::msg::BundleOutputParams;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{
+    cond_expr,
+    types::{DispatchResult, DispatchTarget},
+};
+
+pub const STAGE_CANARY: &str = "canary";
+pub const STAGE_ROLLOUT: &str = "rollout";
+pub const STAGE_SKIPPED: &str = "skipped";
+
+pub type DispatchFuture = Pin<Box<dyn Future<Output = Result<DispatchResult>> + Send>>;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct CanaryOption {
+    /// number of the targets dispatched first
+    pub size: usize,
+    /// exit codes counted as success, [0] if empty
+    #[serde(default)]
+    pub success_exit_codes: Vec<i32>,
+    /// evaluated with the output of each canary target, see cond_expr
+    #[serde(default)]
+    pub cond_expr: String,
+    /// ratio of the failed canary targets tolerated, 0 stops on any failure
+    #[serde(default)]
+    pub max_error_rate: f64,
+}
+
+/// the stage of a dispatch result, passed is only set for the canary targets
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct CanaryStage {
+    pub stage: String,
+    pub passed: Option<bool>,
+    pub reason: String,
+}
+
+impl CanaryOption {
+    pub fn check(&self) -> Result<()> {
+        if self.size == 0 {
+            anyhow::bail!("canary size must be at least 1");
+        }
+        if !(0.0..=1.0).contains(&self.max_error_rate) {
+            anyhow::bail!("canary max_error_rate must be between 0 and 1");
+        }
+        if !self.cond_expr.is_empty() {
+            cond_expr::check(&self.cond_expr)?;
+        }
+        Ok(())
+    }
+
+    /// whether the canary target succeeded, and why not
+    fn verdict(&self, ret: &DispatchResult) -> (bool, String) {
+        if ret.has_err {
+            return (
+                false,
+                format!("dispatch failed, {}", ret.err.clone().unwrap_or_default()),
+            );
+        }
+        let data = &ret.response["data"];
+        let exit_code = data["exit_code"].as_i64().map(|v| v as i32);
+        let is_success = match exit_code {
+            Some(v) if self.success_exit_codes.is_empty() => v == 0,
+            Some(v) => self.success_exit_codes.contains(&v),
+            None => false,
+        };
+        if !is_success {
+            return (false, format!("exit code {exit_code:?} is not a success"));
+        }
+        if self.cond_expr.is_empty() {
+            return (true, String::new());
+        }
+        let output = BundleOutputParams {
+            eid: String::new(),
+            exit_code,
+            exit_status: None,
+            stdout: data["stdout"].as_str().map(String::from),
+            stderr: data["stderr"].as_str().map(String::from),
+            seq: None,
+        };
+        match cond_expr::eval(&self.cond_expr, &output) {
+            Ok(true) => (true, String::new()),
+            Ok(false) => (false, "cond_expr is false".to_string()),
+            Err(e) => (false, format!("cond_expr failed, {e}")),
+        }
+    }
+
+    /// mark the verdict of each canary target, the rollout goes on if the error rate is tolerated
+    pub fn evaluate(&self, results: &mut [DispatchResult]) -> (bool, String) {
+        let mut failed = 0;
+        for ret in results.iter_mut() {
+            let (passed, reason) = self.verdict(ret);
+            if !passed {
+                failed += 1;
+            }
+            ret.canary = Some(CanaryStage {
+                stage: STAGE_CANARY.to_string(),
+                passed: Some(passed),
+                reason,
+            });
+        }
+        let error_rate = failed as f64 / results.len().max(1) as f64;
+        let passed = error_rate <= self.max_error_rate;
+        (
+            passed,
+            format!(
+                "{failed}/{} canary targets failed, error rate {error_rate:.2} {} {:.2}",
+                results.len(),
+                if passed { "<=" } else { ">" },
+                self.max_error_rate
+            ),
+        )
+    }
+}
+
+fn skipped(v: DispatchTarget, reason: &str) -> DispatchResult {
+    DispatchResult {
+        namespace: v.namespace,
+        bind_ip: v.ip,
+        instance_id: v.instance_id,
+        response: json!(null),
+        has_err: true,
+        err: Some(format!("skipped, canary failed, {reason}")),
+        comet_addr: String::new(),
+        canary: Some(CanaryStage {
+            stage: STAGE_SKIPPED.to_string(),
+            passed: None,
+            reason: reason.to_string(),
+        }),
+    }
+}
+
+/// dispatch to the canary targets and wait for them, then to the rest if they passed.
+/// returns the results of all the targets and the reason if the canary failed
+pub(super) async fn dispatch_canary<F>(
+    option: &CanaryOption,
+    mut targets: Vec<DispatchTarget>,
+    is_sync: bool,
+    dispatch: F,
+) -> Result<(Vec<DispatchResult>, Option<String>)>
+where
+    F: 'static + Send + Sync + Clone + Fn((DispatchTarget, bool)) -> DispatchFuture,
+{
+    let rest = targets.split_off(option.size.min(targets.len()));
+    let mut results = utils::async_batch_do(
+        targets.into_iter().map(|v| (v, true)).collect(),
+        dispatch.clone(),
+    )
+    .await
+    .into_iter()
+    .collect::<Result<Vec<_>>>()?;
+
+    let (passed, reason) = option.evaluate(&mut results);
+    if !passed {
+        results.extend(rest.into_iter().map(|v| skipped(v, &reason)));
+        return Ok((results, Some(reason)));
+    }
+
+    let rollout = utils::async_batch_do(rest.into_iter().map(|v| (v, is_sync)).collect(), dispatch)
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>>>()?;
+    results.extend(rollout.into_iter().map(|mut v| {
+        v.canary = Some(CanaryStage {
+            stage: STAGE_ROLLOUT.to_string(),
+            passed: None,
+            reason: reason.clone(),
+        });
+        v
+    }));
+    Ok((results, None))
+}
+
+#[test]
+fn test_evaluate_canary() {
+    let ret = |exit_code: i32, stdout: &str| DispatchResult {
+        response: json!({"code": 20000, "data": {"exit_code": exit_code, "stdout": stdout}}),
+        ..Default::default()
+    };
+    let option = CanaryOption {
+        size: 3,
+        cond_expr: r#"contains(stdout, "ok")"#.to_string(),
+        ..Default::default()
+    };
+    let mut results = vec![ret(0, "ok"), ret(0, "ok")];
+    assert!(option.evaluate(&mut results).0);
+    assert_eq!(results[0].canary.as_ref().unwrap().passed, Some(true));
+
+    let mut results = vec![
+        ret(0, "ok"),
+        ret(1, "ok"),
+        ret(0, "error"),
+        DispatchResult {
+            has_err: true,
+            err: Some("offline".to_string()),
+            ..Default::default()
+        },
+    ];
+    let (passed, reason) = option.evaluate(&mut results);
+    assert!(!passed);
+    assert!(reason.starts_with("3/4"));
+    assert_eq!(
+        results
+            .iter()
+            .map(|v| v.canary.as_ref().unwrap().passed.unwrap())
+            .collect::<Vec<_>>(),
+        vec![true, false, false, false]
+    );
+
+    let option = CanaryOption {
+        success_exit_codes: vec![0, 1],
+        max_error_rate: 0.5,
+        ..option
+    };
+    assert!(option.evaluate(&mut results).0);
+    assert!(CanaryOption::default().check().is_err());
+}
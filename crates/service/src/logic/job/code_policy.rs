@@ -0,0 +1,336 @@
+//! code policies checked when the job is saved
+//!
+//! every line of the job code and of its bundle scripts is matched against the rules of the
+//! team, the global rules and the builtin ones. a rule replaces the builtin or global rule of
+//! the same name, a disabled one turns it off. a warn rule only reports the line, a reject
+//! rule fails the save unless the user waives it with a justification, which is recorded
+use anyhow::Result;
+use regex::Regex;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect,
+};
+use serde::{Deserialize, Serialize};
+
+use super::JobLogic;
+use crate::entity::{code_policy_rule, code_policy_waiver, job, prelude::*};
+
+pub const POLICY_WARN: &str = "warn";
+pub const POLICY_REJECT: &str = "reject";
+pub const POLICY_ACTIONS: [&str; 2] = [POLICY_WARN, POLICY_REJECT];
+
+/// the matched line is cut to this many chars in the violations and the waivers
+const MAX_LINE_CHARS: usize = 200;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct CodePolicyViolation {
+    pub rule_name: String,
+    pub action: String,
+    pub info: String,
+    /// the job name, or the name of the bundle script
+    pub script: String,
+    /// 1-based line number of the first matching line
+    pub line_no: usize,
+    pub line: String,
+    /// the rejection is waived by the user
+    pub waived: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct CodePolicyWaiver {
+    pub rule_name: String,
+    pub justification: String,
+}
+
+fn builtin_rule(name: &str, pattern: &str, action: &str, info: &str) -> code_policy_rule::Model {
+    code_policy_rule::Model {
+        name: name.to_string(),
+        pattern: pattern.to_string(),
+        action: action.to_string(),
+        is_enabled: true,
+        info: info.to_string(),
+        ..Default::default()
+    }
+}
+
+/// the rules every job is checked against unless they are replaced
+pub fn builtin_rules() -> Vec<code_policy_rule::Model> {
+    vec![
+        builtin_rule(
+            "hardcoded-secret",
+            r#"(?i)(password|passwd|pwd|secret|token|api_?key|access_?key)\b\s*[:=]\s*['"]?[^\s'"$]{4,}"#,
+            POLICY_WARN,
+            "credentials should be passed as args or read from the environment",
+        ),
+        builtin_rule(
+            "curl-pipe-shell",
+            r"\b(curl|wget)\b[^|\n]*\|\s*(sudo\s+)?(ba|z|da)?sh\b",
+            POLICY_WARN,
+            "downloaded scripts run without being reviewed",
+        ),
+        builtin_rule(
+            "rm-rf-root",
+            r"\brm\s+(-\S+\s+)*/\*?(\s|;|&|\||$)",
+            POLICY_REJECT,
+            "removes the root filesystem",
+        ),
+    ]
+}
+
+/// fails if the action is unknown or the pattern is not a valid regex
+pub fn check_rule(pattern: &str, action: &str) -> Result<()> {
+    if !POLICY_ACTIONS.contains(&action) {
+        anyhow::bail!("invalid code policy action {action}");
+    }
+    if pattern.trim().is_empty() {
+        anyhow::bail!("code policy pattern is required");
+    }
+    Regex::new(pattern).map_err(|e| anyhow::anyhow!("invalid code policy pattern, {e}"))?;
+    Ok(())
+}
+
+/// the builtin rules replaced by the global rules, then by the rules of the team,
+/// the disabled ones are dropped
+pub fn effective_rules(
+    rules: Vec<code_policy_rule::Model>,
+    team_id: u64,
+) -> Vec<code_policy_rule::Model> {
+    let mut effective = builtin_rules();
+    let (global, team): (Vec<_>, Vec<_>) = rules.into_iter().partition(|v| v.team_id == 0);
+    for rule in global
+        .into_iter()
+        .chain(team.into_iter().filter(|v| v.team_id == team_id))
+    {
+        match effective.iter_mut().find(|v| v.name == rule.name) {
+            Some(v) => *v = rule,
+            None => effective.push(rule),
+        }
+    }
+    effective.retain(|v| v.is_enabled);
+    effective
+}
+
+/// the first line of the code matching each rule
+pub fn scan_code(
+    rules: &[code_policy_rule::Model],
+    script: &str,
+    code: &str,
+) -> Result<Vec<CodePolicyViolation>> {
+    let mut violations = Vec::new();
+    for rule in rules {
+        let re = Regex::new(&rule.pattern)?;
+        let Some((line_no, line)) = code.lines().enumerate().find(|(_, v)| re.is_match(v)) else {
+            continue;
+        };
+        violations.push(CodePolicyViolation {
+            rule_name: rule.name.clone(),
+            action: rule.action.clone(),
+            info: rule.info.clone(),
+            script: script.to_string(),
+            line_no: line_no + 1,
+            line: line.trim().chars().take(MAX_LINE_CHARS).collect(),
+            waived: false,
+        });
+    }
+    Ok(violations)
+}
+
+impl<'a> JobLogic<'a> {
+    /// scan the scripts, (name, code), of a job of the team. returns the violations to warn
+    /// about, fails if a rejection is not waived with a justification
+    pub async fn check_code_policy(
+        &self,
+        team_id: u64,
+        scripts: &[(String, String)],
+        waivers: &[CodePolicyWaiver],
+    ) -> Result<Vec<CodePolicyViolation>> {
+        let rules = CodePolicyRule::find()
+            .filter(code_policy_rule::Column::TeamId.is_in([0, team_id]))
+            .all(&self.ctx.db)
+            .await?;
+        let rules = effective_rules(rules, team_id);
+
+        let mut violations = Vec::new();
+        for (script, code) in scripts {
+            violations.extend(scan_code(&rules, script, code)?);
+        }
+
+        for v in violations.iter_mut().filter(|v| v.action == POLICY_REJECT) {
+            v.waived = waivers
+                .iter()
+                .any(|w| w.rule_name == v.rule_name && !w.justification.trim().is_empty());
+            if !v.waived {
+                anyhow::bail!(
+                    "code policy {} rejects line {} of {}, {}. waive it with a justification to save anyway",
+                    v.rule_name,
+                    v.line_no,
+                    v.script,
+                    v.info
+                );
+            }
+        }
+        Ok(violations)
+    }
+
+    /// record the waived rejections of the saved job
+    pub async fn record_code_policy_waivers(
+        &self,
+        job_id: u64,
+        violations: &[CodePolicyViolation],
+        waivers: &[CodePolicyWaiver],
+        created_user: &str,
+    ) -> Result<u64> {
+        let list: Vec<_> = violations
+            .iter()
+            .filter(|v| v.waived)
+            .filter_map(|v| {
+                waivers
+                    .iter()
+                    .find(|w| w.rule_name == v.rule_name)
+                    .map(|w| (v, w))
+            })
+            .collect();
+        if list.is_empty() {
+            return Ok(0);
+        }
+
+        let Some(eid) = Job::find_by_id(job_id)
+            .select_only()
+            .column(job::Column::Eid)
+            .into_tuple::<String>()
+            .one(&self.ctx.db)
+            .await?
+        else {
+            anyhow::bail!("cannot found job {job_id}");
+        };
+
+        let total = list.len() as u64;
+        code_policy_waiver::Entity::insert_many(list.into_iter().map(|(v, w)| {
+            code_policy_waiver::ActiveModel {
+                eid: Set(eid.clone()),
+                rule_name: Set(v.rule_name.clone()),
+                matched_line: Set(v.line.clone()),
+                justification: Set(w.justification.trim().to_string()),
+                created_user: Set(created_user.to_string()),
+                ..Default::default()
+            }
+        }))
+        .exec_without_returning(&self.ctx.db)
+        .await?;
+        Ok(total)
+    }
+
+    pub async fn get_code_policy_waivers(
+        &self,
+        eid: &str,
+    ) -> Result<Vec<code_policy_waiver::Model>> {
+        Ok(code_policy_waiver::Entity::find()
+            .filter(code_policy_waiver::Column::Eid.eq(eid))
+            .order_by_desc(code_policy_waiver::Column::Id)
+            .all(&self.ctx.db)
+            .await?)
+    }
+
+    /// the rules configured for the team, the global ones if team_id is 0
+    pub async fn get_code_policy_rules(
+        &self,
+        team_id: u64,
+    ) -> Result<Vec<code_policy_rule::Model>> {
+        Ok(CodePolicyRule::find()
+            .filter(code_policy_rule::Column::TeamId.eq(team_id))
+            .order_by_asc(code_policy_rule::Column::Id)
+            .all(&self.ctx.db)
+            .await?)
+    }
+
+    pub async fn get_code_policy_rule(&self, id: u64) -> Result<Option<code_policy_rule::Model>> {
+        Ok(CodePolicyRule::find_by_id(id).one(&self.ctx.db).await?)
+    }
+
+    pub async fn save_code_policy_rule(
+        &self,
+        active_model: code_policy_rule::ActiveModel,
+    ) -> Result<u64> {
+        if let (Set(pattern), Set(action)) = (&active_model.pattern, &active_model.action) {
+            check_rule(pattern, action)?;
+        }
+        let active_model = active_model.save(&self.ctx.db).await?;
+        Ok(active_model.id.as_ref().to_owned())
+    }
+
+    pub async fn delete_code_policy_rule(&self, id: u64) -> Result<u64> {
+        let ret = CodePolicyRule::delete_by_id(id).exec(&self.ctx.db).await?;
+        Ok(ret.rows_affected)
+    }
+}
+
+#[test]
+fn test_scan_code() {
+    let rules = builtin_rules();
+    let scan = |code: &str| {
+        scan_code(&rules, "job", code)
+            .unwrap()
+            .into_iter()
+            .map(|v| (v.rule_name, v.line_no))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        scan("cd /tmp\nrm -rf /\n"),
+        vec![("rm-rf-root".to_string(), 2)]
+    );
+    assert_eq!(scan("rm -rf --no-preserve-root /*").len(), 1);
+    assert!(scan("rm -rf /tmp/cache\nrm -f ./a.log").is_empty());
+
+    assert_eq!(
+        scan("curl -fsSL https://example.com/install.sh | sudo bash"),
+        vec![("curl-pipe-shell".to_string(), 1)]
+    );
+    assert!(scan("curl -o a.tar.gz https://example.com/a.tar.gz").is_empty());
+
+    assert_eq!(
+        scan("set -e\nexport DB_PASSWORD=\"s3cr3t!\""),
+        vec![("hardcoded-secret".to_string(), 2)]
+    );
+    assert_eq!(scan("mysql --password=hunter22").len(), 1);
+    assert!(scan("mysql --password=$DB_PASSWORD").is_empty());
+}
+
+#[test]
+fn test_effective_rules() {
+    let rule = |team_id: u64, name: &str, action: &str, is_enabled: bool| code_policy_rule::Model {
+        team_id,
+        name: name.to_string(),
+        pattern: "sudo".to_string(),
+        action: action.to_string(),
+        is_enabled,
+        ..Default::default()
+    };
+    let rules = vec![
+        rule(0, "curl-pipe-shell", POLICY_REJECT, true),
+        rule(2, "curl-pipe-shell", POLICY_WARN, true),
+        rule(2, "rm-rf-root", POLICY_REJECT, false),
+        rule(3, "no-sudo", POLICY_REJECT, true),
+    ];
+
+    let effective = effective_rules(rules.clone(), 2);
+    let names: Vec<_> = effective
+        .iter()
+        .map(|v| (v.name.as_str(), v.action.as_str()))
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            ("hardcoded-secret", POLICY_WARN),
+            ("curl-pipe-shell", POLICY_WARN)
+        ]
+    );
+
+    let effective = effective_rules(rules, 0);
+    assert_eq!(effective.len(), 3);
+    assert_eq!(effective[1].action, POLICY_REJECT);
+
+    assert!(check_rule("(", POLICY_WARN).is_err());
+    assert!(check_rule("sudo", "block").is_err());
+    assert!(check_rule("sudo", POLICY_REJECT).is_ok());
+}
@@ -0,0 +1,114 @@
+//! evaluation of the cond_expr of bundle scripts
+//!
+//! the output of a script is bound as variables instead of being spliced into the expression:
+//! - `stdout`, `stderr`: the raw output as string
+//! - `exit_code`: the exit code, -1 if the script did not exit normally
+//! - `$v`: the trimmed stdout, as number if it is one, kept for the expressions written for evalexpr
+//!
+//! besides the builtins of expr-lang, `contains(s, sub)`, `regex(s, pattern)` and `json(s)` are available
+//!
+//! limits:
+//! - the expression is at most [`MAX_EXPR_LEN`] bytes
+//! - only the first [`MAX_OUTPUT_LEN`] bytes of stdout and stderr are visible
+//! - compiled regex are capped by [`MAX_REGEX_SIZE`]
+use anyhow::Result;
+use automate::bridge::msg::BundleOutputParams;
+use expr::{Context, Environment, Value};
+
+pub const MAX_EXPR_LEN: usize = 1024;
+pub const MAX_OUTPUT_LEN: usize = 1 << 20;
+pub const MAX_REGEX_SIZE: usize = 1 << 20;
+
+fn environment<'a>() -> Environment<'a> {
+    let mut env = Environment::new();
+    env.add_function("contains", |c| match (c.args.first(), c.args.get(1)) {
+        (Some(Value::String(s)), Some(Value::String(sub))) => Ok(s.contains(sub.as_str()).into()),
+        _ => Err("contains() takes a string and a string to search for"
+            .to_string()
+            .into()),
+    });
+    env.add_function("regex", |c| match (c.args.first(), c.args.get(1)) {
+        (Some(Value::String(s)), Some(Value::String(pattern))) => {
+            let re = regex::RegexBuilder::new(pattern)
+                .size_limit(MAX_REGEX_SIZE)
+                .build()?;
+            Ok(re.is_match(s).into())
+        }
+        _ => Err("regex() takes a string and a pattern".to_string().into()),
+    });
+    env.add_function("json", |c| match c.args.first() {
+        Some(Value::String(s)) => serde_json::from_str::<Value>(s)
+            .map_err(|e| format!("json() cannot parse the string, {e}").into()),
+        _ => Err("json() takes a string".to_string().into()),
+    });
+    env
+}
+
+fn truncate(s: &str) -> &str {
+    if s.len() <= MAX_OUTPUT_LEN {
+        return s;
+    }
+    let mut end = MAX_OUTPUT_LEN;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+/// check the expression can be compiled, called when the bundle script is saved
+pub fn check(cond_expr: &str) -> Result<()> {
+    if cond_expr.len() > MAX_EXPR_LEN {
+        anyhow::bail!("cond_expr cannot be longer than {MAX_EXPR_LEN} bytes");
+    }
+    expr::compile(cond_expr)?;
+    Ok(())
+}
+
+pub fn eval(cond_expr: &str, output: &BundleOutputParams) -> Result<bool> {
+    check(cond_expr)?;
+
+    let stdout = truncate(output.stdout.as_deref().unwrap_or_default());
+    let stderr = truncate(output.stderr.as_deref().unwrap_or_default());
+    let v = stdout.trim();
+
+    let mut ctx = Context::default();
+    ctx.insert("stdout", stdout);
+    ctx.insert("stderr", stderr);
+    ctx.insert("exit_code", output.exit_code.unwrap_or(-1));
+    ctx.insert(
+        "$v",
+        v.parse::<i64>()
+            .map(Value::from)
+            .or_else(|_| v.parse::<f64>().map(Value::from))
+            .unwrap_or(Value::from(v)),
+    );
+
+    environment()
+        .eval(cond_expr, &ctx)?
+        .as_bool()
+        .ok_or(anyhow::anyhow!("cond_expr must return a bool"))
+}
+
+#[test]
+fn test_eval_cond_expr() {
+    let output = BundleOutputParams {
+        eid: "a".to_string(),
+        exit_code: Some(0),
+        exit_status: None,
+        stdout: Some("42\n".to_string()),
+        stderr: None,
+        seq: None,
+    };
+    assert!(eval("$v == 42 && exit_code == 0", &output).unwrap());
+
+    let output = BundleOutputParams {
+        stdout: Some("line 1\"; true || \"\n{\"ok\": true, \"count\": 3}".to_string()),
+        ..output
+    };
+    assert!(eval(r#"contains(stdout, "line 1")"#, &output).unwrap());
+    assert!(eval(r#"regex(stdout, "count\\W+3")"#, &output).unwrap());
+    assert!(!eval(r#"stdout == "true""#, &output).unwrap());
+    assert!(eval(r#"json(split(stdout, "\n")[1]).count == 3"#, &output).unwrap());
+    assert!(eval("stdout", &output).is_err());
+    assert!(check(&"1".repeat(MAX_EXPR_LEN + 1)).is_err());
+}
@@ -0,0 +1,322 @@
+//! interactive confirmation of the manual dispatches of the destructive jobs
+//!
+//! the dispatch of a job requiring confirmation is not run at once, it is kept in a challenge
+//! describing what will run where. the challenge is confirmed by the same user typing the job
+//! name and the values of the prompts, which are merged into the args of the dispatch.
+//! a challenge is confirmed once and expires after CHALLENGE_TTL_SECS
+use anyhow::{Result, anyhow};
+use chrono::{Duration, Local};
+use redis::AsyncCommands;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::JobLogic;
+use crate::{
+    IdGenerator,
+    entity::{instance, job, prelude::*},
+    logic::{task::DispatchJobTask, types::UserInfo},
+};
+
+pub const CHALLENGE_TTL_SECS: u64 = 300;
+
+pub const PROMPT_STRING: &str = "string";
+pub const PROMPT_NUMBER: &str = "number";
+pub const PROMPT_BOOL: &str = "bool";
+pub const PROMPT_CHOICE: &str = "choice";
+pub const PROMPT_TYPES: [&str; 4] = [PROMPT_STRING, PROMPT_NUMBER, PROMPT_BOOL, PROMPT_CHOICE];
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct JobConfirmation {
+    /// the manual dispatch starting an execution must be confirmed
+    #[serde(default)]
+    pub required: bool,
+    /// the values asked on confirmation, passed to the job as args
+    #[serde(default)]
+    pub prompts: Vec<JobPrompt>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct JobPrompt {
+    /// name of the arg the value is passed as
+    pub name: String,
+    #[serde(default)]
+    pub label: String,
+    /// string, number, bool or choice
+    pub prompt_type: String,
+    /// the allowed values of a choice
+    #[serde(default)]
+    pub options: Vec<String>,
+    #[serde(default)]
+    pub required: bool,
+}
+
+impl JobConfirmation {
+    pub fn check(&self) -> Result<()> {
+        if !self.required && !self.prompts.is_empty() {
+            anyhow::bail!("confirmation prompts require the confirmation");
+        }
+        let mut names = Vec::with_capacity(self.prompts.len());
+        for prompt in &self.prompts {
+            if prompt.name.is_empty() {
+                anyhow::bail!("confirmation prompt name is required");
+            }
+            if names.contains(&prompt.name.as_str()) {
+                anyhow::bail!("duplicate confirmation prompt {}", prompt.name);
+            }
+            names.push(prompt.name.as_str());
+            if !PROMPT_TYPES.contains(&prompt.prompt_type.as_str()) {
+                anyhow::bail!(
+                    "invalid type {} of confirmation prompt {}, expected one of {}",
+                    prompt.prompt_type,
+                    prompt.name,
+                    PROMPT_TYPES.join(", ")
+                );
+            }
+            if prompt.prompt_type == PROMPT_CHOICE && prompt.options.is_empty() {
+                anyhow::bail!("confirmation prompt {} has no options", prompt.name);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// check the typed values against the prompts, returns the values as args
+fn validate_values(
+    prompts: &[JobPrompt],
+    values: &Map<String, Value>,
+) -> Result<Map<String, Value>> {
+    if let Some(name) = values
+        .keys()
+        .find(|k| !prompts.iter().any(|v| &v.name == *k))
+    {
+        anyhow::bail!("unknown prompt {name}");
+    }
+
+    let mut args = Map::new();
+    for prompt in prompts {
+        let value = match values.get(&prompt.name) {
+            None | Some(Value::Null) => None,
+            Some(Value::String(v)) if v.is_empty() => None,
+            Some(v) => Some(v),
+        };
+        let Some(value) = value else {
+            if prompt.required {
+                anyhow::bail!("prompt {} is required", prompt.name);
+            }
+            continue;
+        };
+        let valid = match prompt.prompt_type.as_str() {
+            PROMPT_STRING => value.is_string(),
+            PROMPT_NUMBER => value.is_number(),
+            PROMPT_BOOL => value.is_boolean(),
+            PROMPT_CHOICE => value
+                .as_str()
+                .is_some_and(|v| prompt.options.iter().any(|o| o == v)),
+            _ => false,
+        };
+        if !valid {
+            anyhow::bail!(
+                "invalid {} value of prompt {}",
+                prompt.prompt_type,
+                prompt.name
+            );
+        }
+        args.insert(prompt.name.clone(), value.clone());
+    }
+    Ok(args)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ChallengeTarget {
+    pub instance_id: String,
+    pub ip: String,
+    pub namespace: String,
+}
+
+/// what will run where, returned to the user instead of dispatching
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct DispatchChallenge {
+    pub challenge_id: String,
+    pub eid: String,
+    pub job_name: String,
+    pub action: String,
+    pub targets: Vec<ChallengeTarget>,
+    pub prompts: Vec<JobPrompt>,
+    /// the text to type to confirm, the job name
+    pub confirm_text: String,
+    pub expire_time: String,
+}
+
+/// the dispatch kept until the challenge is confirmed
+#[derive(Serialize, Deserialize)]
+pub struct PendingDispatch {
+    pub task: DispatchJobTask,
+    pub background: bool,
+    pub team_id: Option<u64>,
+    pub created_user: String,
+    prompts: Vec<JobPrompt>,
+    confirm_text: String,
+}
+
+impl<'a> JobLogic<'a> {
+    fn challenge_key(challenge_id: &str) -> String {
+        format!("jiascheduler:dispatch:challenge:{challenge_id}")
+    }
+
+    /// the confirmation of the job, none if its dispatch runs at once
+    pub fn get_confirmation(job_record: &job::Model) -> Result<Option<JobConfirmation>> {
+        let Some(v) = job_record.confirmation.clone() else {
+            return Ok(None);
+        };
+        let confirmation: JobConfirmation = serde_json::from_value(v)?;
+        Ok(Some(confirmation).filter(|v| v.required))
+    }
+
+    /// keep the dispatch until it is confirmed by the user
+    pub async fn create_dispatch_challenge(
+        &self,
+        job_record: &job::Model,
+        confirmation: JobConfirmation,
+        task: DispatchJobTask,
+        background: bool,
+        team_id: Option<u64>,
+        user_info: &UserInfo,
+    ) -> Result<DispatchChallenge> {
+        let targets = Instance::find()
+            .filter(instance::Column::InstanceId.is_in(&task.instance_ids))
+            .all(&self.ctx.db)
+            .await?
+            .into_iter()
+            .map(|v| ChallengeTarget {
+                instance_id: v.instance_id,
+                ip: v.ip,
+                namespace: v.namespace,
+            })
+            .collect::<Vec<_>>();
+        if targets.is_empty() {
+            anyhow::bail!("cannot found valid instance");
+        }
+
+        let challenge = DispatchChallenge {
+            challenge_id: IdGenerator::get_dispatch_challenge_uid(),
+            eid: job_record.eid.clone(),
+            job_name: job_record.name.clone(),
+            action: task.action.clone(),
+            targets,
+            prompts: confirmation.prompts.clone(),
+            confirm_text: job_record.name.clone(),
+            expire_time: (Local::now() + Duration::seconds(CHALLENGE_TTL_SECS as i64))
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string(),
+        };
+        let pending = PendingDispatch {
+            task,
+            background,
+            team_id,
+            created_user: user_info.username.clone(),
+            prompts: confirmation.prompts,
+            confirm_text: challenge.confirm_text.clone(),
+        };
+
+        let mut conn = self.ctx.redis().get_multiplexed_async_connection().await?;
+        let _: () = conn
+            .set_ex(
+                Self::challenge_key(&challenge.challenge_id),
+                serde_json::to_string(&pending)?,
+                CHALLENGE_TTL_SECS,
+            )
+            .await?;
+        Ok(challenge)
+    }
+
+    /// take the dispatch of the challenge, the values of the prompts are merged into its args.
+    /// the challenge is consumed even if the confirmation is refused
+    pub async fn confirm_dispatch_challenge(
+        &self,
+        challenge_id: &str,
+        user_info: &UserInfo,
+        confirm_text: &str,
+        values: &Map<String, Value>,
+    ) -> Result<PendingDispatch> {
+        let mut conn = self.ctx.redis().get_multiplexed_async_connection().await?;
+        let data: Option<String> = conn.get_del(Self::challenge_key(challenge_id)).await?;
+        let mut pending: PendingDispatch = serde_json::from_str(&data.ok_or(anyhow!(
+            "the challenge {challenge_id} is expired or confirmed"
+        ))?)?;
+
+        if pending.created_user != user_info.username {
+            anyhow::bail!("the challenge {challenge_id} is not created by you");
+        }
+        if pending.confirm_text != confirm_text {
+            anyhow::bail!("the confirm text does not match, dispatch again to retry");
+        }
+
+        let prompted = validate_values(&pending.prompts, values)?;
+        if !prompted.is_empty() {
+            let mut args = match pending.task.args.take() {
+                Some(Value::Object(v)) => v,
+                _ => Map::new(),
+            };
+            args.extend(prompted);
+            pending.task.args = Some(Value::Object(args));
+        }
+        Ok(pending)
+    }
+}
+
+#[test]
+fn test_validate_values() {
+    use serde_json::json;
+
+    let prompt = |name: &str, prompt_type: &str, required: bool| JobPrompt {
+        name: name.to_string(),
+        prompt_type: prompt_type.to_string(),
+        options: vec!["prod".to_string(), "test".to_string()],
+        required,
+        ..Default::default()
+    };
+    let prompts = vec![
+        prompt("path", PROMPT_STRING, true),
+        prompt("days", PROMPT_NUMBER, false),
+        prompt("force", PROMPT_BOOL, false),
+        prompt("env", PROMPT_CHOICE, true),
+    ];
+    let values = |v: Value| v.as_object().unwrap().to_owned();
+
+    let args = validate_values(
+        &prompts,
+        &values(json!({"path": "/data/tmp", "days": 7, "env": "test"})),
+    )
+    .unwrap();
+    assert_eq!(
+        Value::Object(args),
+        json!({"path": "/data/tmp", "days": 7, "env": "test"})
+    );
+
+    assert!(validate_values(&prompts, &values(json!({"path": "", "env": "test"}))).is_err());
+    assert!(validate_values(&prompts, &values(json!({"path": "/", "env": "dev"}))).is_err());
+    assert!(
+        validate_values(
+            &prompts,
+            &values(json!({"path": "/", "env": "test", "days": "7"}))
+        )
+        .is_err()
+    );
+    assert!(
+        validate_values(
+            &prompts,
+            &values(json!({"path": "/", "env": "test", "user": "root"}))
+        )
+        .is_err()
+    );
+
+    let confirmation = JobConfirmation {
+        required: true,
+        prompts: vec![
+            prompt("env", PROMPT_CHOICE, true),
+            prompt("env", PROMPT_STRING, false),
+        ],
+    };
+    assert!(confirmation.check().is_err());
+}
@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::{DateTime, Local, NaiveDate, TimeDelta, TimeZone};
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, EntityTrait, FromQueryResult, JoinType, QueryFilter, QueryOrder,
+    QuerySelect, QueryTrait, Select,
+};
+use sea_query::{Expr, OnConflict, Order};
+
+use super::{
+    JobLogic,
+    types::{DailyRunTrend, ExecResultStatus, InstanceRunTop, JobRunTop},
+};
+use crate::{
+    entity::{instance, job, job_daily_stat, job_exec_history, prelude::*},
+    logic,
+};
+
+/// days of the trend series, also the longest backfill of the rollup
+pub const DAILY_STAT_DAYS: i64 = 30;
+
+#[derive(Debug, FromQueryResult)]
+struct DailyExecCount {
+    eid: String,
+    instance_id: String,
+    run_total: i64,
+    fail_total: i64,
+    duration_total_ms: i64,
+    duration_max_ms: i64,
+}
+
+/// start of the day in the local time zone
+pub(super) fn day_start(day: NaiveDate) -> DateTime<Local> {
+    let t = day.and_hms_opt(0, 0, 0).expect("invalid time");
+    Local
+        .from_local_datetime(&t)
+        .earliest()
+        .unwrap_or_else(|| Local.from_utc_datetime(&t))
+}
+
+/// one point per day ending at today, in ascending order
+fn fill_daily_trend(
+    records: Vec<DailyRunTrend>,
+    today: NaiveDate,
+    days: i64,
+) -> Vec<DailyRunTrend> {
+    let mut records: HashMap<NaiveDate, DailyRunTrend> =
+        records.into_iter().map(|v| (v.stat_date, v)).collect();
+    (0..days)
+        .rev()
+        .map(|i| today - TimeDelta::days(i))
+        .map(|day| {
+            records.remove(&day).unwrap_or(DailyRunTrend {
+                stat_date: day,
+                run_total: 0,
+                fail_total: 0,
+                avg_duration_ms: 0,
+            })
+        })
+        .collect()
+}
+
+/// milliseconds between the start and the end of the run, in mysql
+pub(super) const DURATION_MS: &str = "timestampdiff(microsecond, `job_exec_history`.`start_time`, `job_exec_history`.`end_time`) div 1000";
+
+impl<'a> JobLogic<'a> {
+    /// roll up the runs into job_daily_stat by the day they ended, yesterday is recomputed
+    /// because the runs may end after the last rollup of the day.
+    /// at most DAILY_STAT_DAYS are backfilled after a long downtime
+    pub async fn rollup_daily_stat(&self) -> Result<u64> {
+        let today = Local::now().date_naive();
+        let earliest = today - TimeDelta::days(DAILY_STAT_DAYS);
+
+        let last = JobDailyStat::find()
+            .order_by_desc(job_daily_stat::Column::StatDate)
+            .one(&self.ctx.db)
+            .await?
+            .map(|v| v.stat_date);
+
+        let mut day = last.map_or(earliest, |v| (v - TimeDelta::days(1)).max(earliest));
+        let mut n = 0;
+        while day <= today {
+            self.rollup_daily_bucket(day).await?;
+            day += TimeDelta::days(1);
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    async fn rollup_daily_bucket(&self, day: NaiveDate) -> Result<()> {
+        let records = JobExecHistory::find()
+            .select_only()
+            .column(job_exec_history::Column::Eid)
+            .column(job_exec_history::Column::InstanceId)
+            .column_as(job_exec_history::Column::Id.count(), "run_total")
+            .expr_as(
+                Expr::cust(format!(
+                    "cast(sum(if(`job_exec_history`.`result_status` = '{}', 1, 0)) as signed)",
+                    ExecResultStatus::Failure.as_str()
+                )),
+                "fail_total",
+            )
+            .expr_as(
+                Expr::cust(format!("cast(sum({DURATION_MS}) as signed)")),
+                "duration_total_ms",
+            )
+            .expr_as(
+                Expr::cust(format!("cast(max({DURATION_MS}) as signed)")),
+                "duration_max_ms",
+            )
+            .filter(job_exec_history::Column::EndTime.gte(day_start(day)))
+            .filter(job_exec_history::Column::EndTime.lt(day_start(day + TimeDelta::days(1))))
+            .filter(job_exec_history::Column::StartTime.is_not_null())
+            .group_by(job_exec_history::Column::Eid)
+            .group_by(job_exec_history::Column::InstanceId)
+            .into_model::<DailyExecCount>()
+            .all(&self.ctx.db)
+            .await?;
+
+        for v in records {
+            JobDailyStat::insert(job_daily_stat::ActiveModel {
+                stat_date: Set(day),
+                eid: Set(v.eid),
+                instance_id: Set(v.instance_id),
+                run_total: Set(v.run_total as u32),
+                fail_total: Set(v.fail_total as u32),
+                duration_total_ms: Set(v.duration_total_ms.max(0) as u64),
+                duration_max_ms: Set(v.duration_max_ms.max(0) as u64),
+                ..Default::default()
+            })
+            .on_conflict(
+                OnConflict::columns([
+                    job_daily_stat::Column::StatDate,
+                    job_daily_stat::Column::Eid,
+                    job_daily_stat::Column::InstanceId,
+                ])
+                .update_columns([
+                    job_daily_stat::Column::RunTotal,
+                    job_daily_stat::Column::FailTotal,
+                    job_daily_stat::Column::DurationTotalMs,
+                    job_daily_stat::Column::DurationMaxMs,
+                ])
+                .to_owned(),
+            )
+            .exec(&self.ctx.db)
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// rollups of the latest days, limited to the jobs visible to the user
+    async fn visible_daily_stat(
+        &self,
+        user_info: &logic::types::UserInfo,
+        days: i64,
+    ) -> Result<Select<JobDailyStat>> {
+        let svc = self.ctx.service();
+        let (search_user, team_ids) = if self.ctx.can_manage_job(&user_info.user_id).await? {
+            (None, None)
+        } else {
+            let team_ids = svc
+                .team
+                .get_my_teams(&user_info.user_id)
+                .await?
+                .into_iter()
+                .map(|v| v.id)
+                .collect::<Vec<u64>>();
+            (Some(user_info.username.clone()), Some(team_ids))
+        };
+
+        let since = Local::now().date_naive() - TimeDelta::days(days.clamp(1, DAILY_STAT_DAYS) - 1);
+        Ok(JobDailyStat::find()
+            .select_only()
+            .join_rev(
+                JoinType::InnerJoin,
+                Job::belongs_to(JobDailyStat)
+                    .from(job::Column::Eid)
+                    .to(job_daily_stat::Column::Eid)
+                    .into(),
+            )
+            .apply_if(search_user, |query, v| {
+                let mut cond = job::Column::CreatedUser.eq(v);
+                if let Some(team_ids) = team_ids {
+                    cond = cond.or(job::Column::TeamId.is_in(team_ids));
+                }
+                query.filter(cond)
+            })
+            .filter(job::Column::IsDeleted.eq(false))
+            .filter(job_daily_stat::Column::StatDate.gte(since)))
+    }
+
+    /// jobs with the most failed runs of the latest days
+    pub async fn get_top_failing_jobs(
+        &self,
+        user_info: &logic::types::UserInfo,
+        days: i64,
+        limit: u64,
+    ) -> Result<Vec<JobRunTop>> {
+        Ok(self
+            .job_run_top(user_info, days)
+            .await?
+            .having(Expr::cust("sum(`job_daily_stat`.`fail_total`) > 0"))
+            .order_by(Expr::cust("fail_total"), Order::Desc)
+            .order_by(Expr::cust("run_total"), Order::Desc)
+            .limit(limit)
+            .into_model()
+            .all(&self.ctx.db)
+            .await?)
+    }
+
+    /// jobs with the longest average duration of the latest days
+    pub async fn get_slowest_jobs(
+        &self,
+        user_info: &logic::types::UserInfo,
+        days: i64,
+        limit: u64,
+    ) -> Result<Vec<JobRunTop>> {
+        Ok(self
+            .job_run_top(user_info, days)
+            .await?
+            .order_by(Expr::cust("avg_duration_ms"), Order::Desc)
+            .limit(limit)
+            .into_model()
+            .all(&self.ctx.db)
+            .await?)
+    }
+
+    async fn job_run_top(
+        &self,
+        user_info: &logic::types::UserInfo,
+        days: i64,
+    ) -> Result<Select<JobDailyStat>> {
+        Ok(self
+            .visible_daily_stat(user_info, days)
+            .await?
+            .column(job_daily_stat::Column::Eid)
+            .expr_as(Expr::cust("max(`job`.`name`)"), "job_name")
+            .expr_as(
+                Expr::cust("cast(sum(`job_daily_stat`.`run_total`) as signed)"),
+                "run_total",
+            )
+            .expr_as(
+                Expr::cust("cast(sum(`job_daily_stat`.`fail_total`) as signed)"),
+                "fail_total",
+            )
+            .expr_as(
+                Expr::cust(
+                    "cast(sum(`job_daily_stat`.`duration_total_ms`) div sum(`job_daily_stat`.`run_total`) as signed)",
+                ),
+                "avg_duration_ms",
+            )
+            .expr_as(
+                Expr::cust("cast(max(`job_daily_stat`.`duration_max_ms`) as signed)"),
+                "max_duration_ms",
+            )
+            .group_by(job_daily_stat::Column::Eid))
+    }
+
+    /// instances running the most jobs of the latest days
+    pub async fn get_busiest_instances(
+        &self,
+        user_info: &logic::types::UserInfo,
+        days: i64,
+        limit: u64,
+    ) -> Result<Vec<InstanceRunTop>> {
+        Ok(self
+            .visible_daily_stat(user_info, days)
+            .await?
+            .column(job_daily_stat::Column::InstanceId)
+            .expr_as(Expr::cust("max(`instance`.`ip`)"), "ip")
+            .expr_as(
+                Expr::cust("cast(sum(`job_daily_stat`.`run_total`) as signed)"),
+                "run_total",
+            )
+            .expr_as(
+                Expr::cust("cast(sum(`job_daily_stat`.`fail_total`) as signed)"),
+                "fail_total",
+            )
+            .join_rev(
+                JoinType::LeftJoin,
+                Instance::belongs_to(JobDailyStat)
+                    .from(instance::Column::InstanceId)
+                    .to(job_daily_stat::Column::InstanceId)
+                    .into(),
+            )
+            .group_by(job_daily_stat::Column::InstanceId)
+            .order_by(Expr::cust("run_total"), Order::Desc)
+            .limit(limit)
+            .into_model()
+            .all(&self.ctx.db)
+            .await?)
+    }
+
+    /// runs of each day of the latest days, the days without runs are filled with zero
+    pub async fn get_daily_run_trend(
+        &self,
+        user_info: &logic::types::UserInfo,
+        days: i64,
+    ) -> Result<Vec<DailyRunTrend>> {
+        let records: Vec<DailyRunTrend> = self
+            .visible_daily_stat(user_info, days)
+            .await?
+            .column(job_daily_stat::Column::StatDate)
+            .expr_as(
+                Expr::cust("cast(sum(`job_daily_stat`.`run_total`) as signed)"),
+                "run_total",
+            )
+            .expr_as(
+                Expr::cust("cast(sum(`job_daily_stat`.`fail_total`) as signed)"),
+                "fail_total",
+            )
+            .expr_as(
+                Expr::cust(
+                    "cast(sum(`job_daily_stat`.`duration_total_ms`) div sum(`job_daily_stat`.`run_total`) as signed)",
+                ),
+                "avg_duration_ms",
+            )
+            .group_by(job_daily_stat::Column::StatDate)
+            .into_model()
+            .all(&self.ctx.db)
+            .await?;
+
+        Ok(fill_daily_trend(
+            records,
+            Local::now().date_naive(),
+            days.clamp(1, DAILY_STAT_DAYS),
+        ))
+    }
+
+    /// remove the rollups of the job, called when the job is purged
+    pub(super) async fn delete_daily_stats(&self, eid: &str) -> Result<u64> {
+        let ret = JobDailyStat::delete_many()
+            .filter(job_daily_stat::Column::Eid.eq(eid))
+            .exec(&self.ctx.db)
+            .await?;
+        Ok(ret.rows_affected)
+    }
+}
+
+#[test]
+fn test_day_start() {
+    let day = NaiveDate::from_ymd_opt(2025, 9, 10).unwrap();
+    let start = day_start(day);
+    assert_eq!(start.date_naive(), day);
+    assert_eq!(start.format("%H:%M:%S").to_string(), "00:00:00");
+    assert_eq!(
+        day_start(day + TimeDelta::days(1)) - start,
+        TimeDelta::days(1)
+    );
+}
+
+#[test]
+fn test_fill_daily_trend() {
+    let today = NaiveDate::from_ymd_opt(2025, 9, 10).unwrap();
+    let trend = fill_daily_trend(
+        vec![DailyRunTrend {
+            stat_date: today - TimeDelta::days(1),
+            run_total: 10,
+            fail_total: 2,
+            avg_duration_ms: 300,
+        }],
+        today,
+        3,
+    );
+    assert_eq!(
+        trend.iter().map(|v| v.stat_date).collect::<Vec<_>>(),
+        vec![
+            today - TimeDelta::days(2),
+            today - TimeDelta::days(1),
+            today
+        ]
+    );
+    assert_eq!(
+        trend.iter().map(|v| v.run_total).collect::<Vec<_>>(),
+        vec![0, 10, 0]
+    );
+}
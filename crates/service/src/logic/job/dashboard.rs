@@ -13,7 +13,7 @@ use sql_builder::{SqlBuilder, bind::Bind};
 
 use super::{
     JobLogic,
-    types::{self, BundleScriptRecord, JobStatSummary, RunResultSummary},
+    types::{self, BundleScriptRecord, ExecResultStatus, JobStatSummary, RunResultSummary},
 };
 
 use crate::{
@@ -73,7 +73,11 @@ impl<'a> JobLogic<'a> {
             .column(job_exec_history::Column::Eid)
             .column_as(job_exec_history::Column::Eid.count(), "total")
             .expr_as(
-                Expr::case(Expr::col(Alias::new("exit_code")).eq(0), true).finally(false),
+                Expr::case(
+                    Expr::col(Alias::new("result_status")).ne(ExecResultStatus::Failure.as_str()),
+                    true,
+                )
+                .finally(false),
                 "is_exec_succ",
             )
             .filter(job_exec_history::Column::ScheduleId.eq(schedule_id))
@@ -210,6 +214,7 @@ impl<'a> JobLogic<'a> {
 
         let mut summary: JobStatSummary = Default::default();
         summary.total = Job::find()
+            .filter(job::Column::IsDeleted.eq(false))
             .apply_if(search_user.clone(), |query, v| {
                 query.filter(job::Column::CreatedUser.eq(v))
             })
@@ -232,6 +237,7 @@ impl<'a> JobLogic<'a> {
                 query.filter(cond)
             })
             .filter(job_running_status::Column::RunStatus.eq(RunStatus::Running.to_string()))
+            .filter(job_running_status::Column::IsDeleted.eq(false))
             .count(&self.ctx.db)
             .await?;
 
@@ -250,7 +256,12 @@ impl<'a> JobLogic<'a> {
                 }
                 query.filter(cond)
             })
-            .filter(job_running_status::Column::ExitCode.eq(0))
+            // warnings mapped from the exit code are not failures
+            .filter(job_running_status::Column::ResultStatus.is_in([
+                ExecResultStatus::Success.as_str(),
+                ExecResultStatus::Warning.as_str(),
+            ]))
+            .filter(job_running_status::Column::IsDeleted.eq(false))
             .count(&self.ctx.db)
             .await?;
         summary.exec_fail_num = JobRunningStatus::find()
@@ -268,7 +279,8 @@ impl<'a> JobLogic<'a> {
                 }
                 query.filter(cond)
             })
-            .filter(job_running_status::Column::ExitCode.ne(0))
+            .filter(job_running_status::Column::ResultStatus.eq(ExecResultStatus::Failure.as_str()))
+            .filter(job_running_status::Column::IsDeleted.eq(false))
             .count(&self.ctx.db)
             .await?;
         Ok(summary)
@@ -306,6 +318,7 @@ impl<'a> JobLogic<'a> {
                 query.filter(job::Column::JobType.eq(v))
             })
             .filter(job::Column::DisplayOnDashboard.eq(true))
+            .filter(job::Column::IsDeleted.eq(false))
             .all(&self.ctx.db)
             .await?;
 
@@ -323,7 +336,7 @@ impl<'a> JobLogic<'a> {
                 .get_latest_schedule(item.eid.clone(), schedule_id, run_id)
                 .await?
             {
-                if schedule_record.job_type == "default" {
+                if schedule_record.job_type != "bundle" {
                     let count_result = self
                         .get_job_exec_count_result(schedule_record.schedule_id, Some(run_id))
                         .await?;
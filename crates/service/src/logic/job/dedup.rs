@@ -0,0 +1,165 @@
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::Result;
+use automate::{JobAction, scheduler::types::ScheduleType};
+use crypto::{digest::Digest, sha2::Sha256};
+use redis::{AsyncCommands, ExistenceCheck, SetExpiry, SetOptions};
+use serde_json::{Value, json};
+
+use super::{JobLogic, types::InstanceOverride};
+
+/// the value of the dedup key until the first dispatch gets its schedule id
+const DEDUP_PENDING: &str = "pending";
+
+/// how long the coalesced dispatch waits for the schedule id of the first one
+const DEDUP_WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEDUP_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// the keys of the objects are sorted so the hash is independent of their order
+fn canonicalize(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut keys = map.keys().collect::<Vec<_>>();
+            keys.sort();
+            let mut sorted = serde_json::Map::new();
+            for k in keys {
+                sorted.insert(k.clone(), canonicalize(&map[k]));
+            }
+            Value::Object(sorted)
+        }
+        Value::Array(list) => Value::Array(list.iter().map(canonicalize).collect()),
+        v => v.clone(),
+    }
+}
+
+/// content hash of the dispatch, the order of the targets does not matter
+pub fn dispatch_hash(
+    eid: &str,
+    schedule_type: &ScheduleType,
+    action: &JobAction,
+    instance_ids: &[String],
+    args: Option<&Value>,
+    overrides: &HashMap<String, InstanceOverride>,
+) -> Result<String> {
+    let mut instance_ids = instance_ids.to_vec();
+    instance_ids.sort();
+    instance_ids.dedup();
+
+    let content = canonicalize(&json!({
+        "eid": eid,
+        "schedule_type": schedule_type,
+        "action": action,
+        "instance_ids": instance_ids,
+        "args": args,
+        "overrides": serde_json::to_value(overrides)?,
+    }));
+
+    let mut hasher = Sha256::new();
+    hasher.input_str(&serde_json::to_string(&content)?);
+    Ok(hasher.result_str())
+}
+
+/// the result of claiming a dispatch in the dedup window
+pub enum DedupClaim {
+    /// the first dispatch in the window, it must be settled with its schedule id
+    Acquired(String),
+    /// coalesced into the earlier dispatch with the schedule id
+    Duplicate(u64),
+}
+
+impl<'a> JobLogic<'a> {
+    fn dispatch_dedup_key(eid: &str, hash: &str) -> String {
+        format!("jiascheduler:dispatch:dedup:{eid}:{hash}")
+    }
+
+    /// claim the dispatch for the window, or wait for the schedule id of the same one
+    /// dispatched earlier in the window
+    pub async fn claim_dispatch_dedup(
+        &self,
+        eid: &str,
+        hash: &str,
+        window_secs: u32,
+    ) -> Result<DedupClaim> {
+        let mut conn = self.ctx.redis().get_multiplexed_async_connection().await?;
+        let key = Self::dispatch_dedup_key(eid, hash);
+
+        let deadline = tokio::time::Instant::now() + DEDUP_WAIT_TIMEOUT;
+        loop {
+            let ok: Option<String> = conn
+                .set_options(
+                    &key,
+                    DEDUP_PENDING,
+                    SetOptions::default()
+                        .conditional_set(ExistenceCheck::NX)
+                        .with_expiration(SetExpiry::EX(window_secs as u64)),
+                )
+                .await?;
+            if ok.is_some() {
+                return Ok(DedupClaim::Acquired(key));
+            }
+
+            // the key may expire or be released by a failed dispatch in the meantime,
+            // then the next round claims it
+            let value: Option<String> = conn.get(&key).await?;
+            if let Some(id) = value.and_then(|v| v.parse::<u64>().ok()) {
+                return Ok(DedupClaim::Duplicate(id));
+            }
+
+            if tokio::time::Instant::now() >= deadline {
+                anyhow::bail!("the same dispatch of job {eid} is still in progress");
+            }
+            tokio::time::sleep(DEDUP_POLL_INTERVAL).await;
+        }
+    }
+
+    /// record the schedule id of the claimed dispatch, or release the claim if it failed
+    pub async fn settle_dispatch_dedup(&self, key: &str, schedule_id: Option<u64>) -> Result<()> {
+        let mut conn = self.ctx.redis().get_multiplexed_async_connection().await?;
+        match schedule_id {
+            Some(id) => {
+                let _: Option<String> = conn
+                    .set_options(
+                        key,
+                        id,
+                        SetOptions::default()
+                            .conditional_set(ExistenceCheck::XX)
+                            .with_expiration(SetExpiry::KEEPTTL),
+                    )
+                    .await?;
+            }
+            None => {
+                let _: () = conn.del(key).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_dispatch_hash() {
+    let hash = |instance_ids: &[&str], args: Value| {
+        let instance_ids = instance_ids
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>();
+        dispatch_hash(
+            "e1",
+            &ScheduleType::Once,
+            &JobAction::Exec,
+            &instance_ids,
+            Some(&args),
+            &HashMap::new(),
+        )
+        .unwrap()
+    };
+
+    assert_eq!(
+        hash(&["a", "b"], json!({"x": 1, "y": {"m": 1, "n": 2}})),
+        hash(&["b", "a", "a"], json!({"y": {"n": 2, "m": 1}, "x": 1}))
+    );
+    assert_ne!(
+        hash(&["a", "b"], json!({"x": 1})),
+        hash(&["a"], json!({"x": 1}))
+    );
+    assert_ne!(hash(&["a"], json!({"x": 1})), hash(&["a"], json!({"x": 2})));
+}
@@ -0,0 +1,271 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::Result;
+use automate::scheduler::types::ScheduleType;
+use chrono::{DateTime, Local, TimeDelta};
+use sea_orm::{ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect};
+use sea_query::OnConflict;
+use serde_json::Value;
+
+use super::{
+    JobLogic,
+    types::{CometDispatchStat, DispatchResult, DispatchStatSummary},
+};
+use crate::entity::{dispatch_stat, job_exec_history, job_schedule_history, prelude::*};
+
+/// length of a rollup bucket
+pub const STAT_INTERVAL_SECS: i64 = 300;
+
+/// upper bound in milliseconds of each latency bucket, slower samples go to the trailing bucket
+pub const LATENCY_BOUNDS: [u64; 12] = [
+    10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000, 30000, 60000,
+];
+
+#[derive(Debug, Default)]
+struct CometBucket {
+    dispatch_total: u32,
+    dispatch_failed: u32,
+    latency: Vec<u64>,
+}
+
+impl CometBucket {
+    fn observe_latency(&mut self, ms: u64) {
+        if self.latency.is_empty() {
+            self.latency = vec![0; LATENCY_BOUNDS.len() + 1];
+        }
+        let idx = LATENCY_BOUNDS
+            .iter()
+            .position(|&v| ms <= v)
+            .unwrap_or(LATENCY_BOUNDS.len());
+        self.latency[idx] += 1;
+    }
+}
+
+fn bucket_start(t: DateTime<Local>) -> DateTime<Local> {
+    let secs = t.timestamp() - t.timestamp().rem_euclid(STAT_INTERVAL_SECS);
+    DateTime::from_timestamp(secs, 0)
+        .expect("invalid timestamp")
+        .with_timezone(&Local)
+}
+
+fn merge_histogram(dst: &mut Vec<u64>, src: &[u64]) {
+    if dst.len() < src.len() {
+        dst.resize(src.len(), 0);
+    }
+    dst.iter_mut().zip(src).for_each(|(a, b)| *a += b);
+}
+
+/// the percentile is reported as the upper bound of the bucket it falls in,
+/// samples beyond the last bound are reported as the last bound
+fn percentile(histogram: &[u64], p: f64) -> Option<u64> {
+    let total: u64 = histogram.iter().sum();
+    if total == 0 {
+        return None;
+    }
+    let rank = ((total as f64 * p).ceil() as u64).max(1);
+    let mut acc = 0;
+    for (i, v) in histogram.iter().enumerate() {
+        acc += v;
+        if acc >= rank {
+            return Some(LATENCY_BOUNDS[i.min(LATENCY_BOUNDS.len() - 1)]);
+        }
+    }
+    LATENCY_BOUNDS.last().copied()
+}
+
+impl<'a> JobLogic<'a> {
+    /// roll up the schedule history into dispatch_stat, the latest buckets are recomputed
+    /// because jobs may start after their bucket was closed.
+    /// at most one day is backfilled after a long downtime
+    pub async fn rollup_dispatch_stat(&self) -> Result<u64> {
+        let now = Local::now();
+        let interval = TimeDelta::seconds(STAT_INTERVAL_SECS);
+        let earliest = now - TimeDelta::days(1);
+
+        let last = DispatchStat::find()
+            .order_by_desc(dispatch_stat::Column::StatTime)
+            .one(&self.ctx.db)
+            .await?
+            .map(|v| v.stat_time);
+
+        let mut start = bucket_start(last.map_or(earliest, |v| (v - interval * 3).max(earliest)));
+        let end = bucket_start(now) + interval;
+
+        let mut n = 0;
+        while start < end {
+            self.rollup_dispatch_bucket(start, start + interval).await?;
+            start += interval;
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    async fn rollup_dispatch_bucket(
+        &self,
+        start: DateTime<Local>,
+        end: DateTime<Local>,
+    ) -> Result<()> {
+        let records: Vec<(String, String, Option<Value>, Option<Value>)> =
+            JobScheduleHistory::find()
+                .select_only()
+                .column(job_schedule_history::Column::ScheduleId)
+                .column(job_schedule_history::Column::ScheduleType)
+                .column(job_schedule_history::Column::DispatchResult)
+                .column(job_schedule_history::Column::DispatchData)
+                .filter(job_schedule_history::Column::CreatedTime.gte(start))
+                .filter(job_schedule_history::Column::CreatedTime.lt(end))
+                .into_tuple()
+                .all(&self.ctx.db)
+                .await?;
+
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        let once = ScheduleType::Once.to_string();
+        let schedule_ids: Vec<String> = records
+            .iter()
+            .filter(|v| v.1 == once)
+            .map(|v| v.0.clone())
+            .collect();
+
+        // the first execution of each instance, reruns are not dispatched by the schedule
+        let mut start_times: HashMap<(String, String), DateTime<Local>> = HashMap::new();
+        if !schedule_ids.is_empty() {
+            let execs: Vec<(String, String, Option<DateTime<Local>>)> = JobExecHistory::find()
+                .select_only()
+                .column(job_exec_history::Column::ScheduleId)
+                .column(job_exec_history::Column::InstanceId)
+                .column(job_exec_history::Column::StartTime)
+                .filter(job_exec_history::Column::ScheduleId.is_in(schedule_ids))
+                .filter(job_exec_history::Column::RerunOf.eq(0))
+                .into_tuple()
+                .all(&self.ctx.db)
+                .await?;
+            for (schedule_id, instance_id, start_time) in execs {
+                let Some(start_time) = start_time else {
+                    continue;
+                };
+                start_times
+                    .entry((schedule_id, instance_id))
+                    .and_modify(|v| *v = (*v).min(start_time))
+                    .or_insert(start_time);
+            }
+        }
+
+        let mut buckets: BTreeMap<String, CometBucket> = BTreeMap::new();
+        for (schedule_id, schedule_type, dispatch_result, dispatch_data) in records {
+            let Some(results) =
+                dispatch_result.and_then(|v| serde_json::from_value::<Vec<DispatchResult>>(v).ok())
+            else {
+                continue;
+            };
+            let dispatch_time: Option<DateTime<Local>> = dispatch_data
+                .and_then(|v| serde_json::from_value(v["dispatch_time"].clone()).ok())
+                .flatten()
+                .filter(|_| schedule_type == once);
+
+            for r in results {
+                let bucket = buckets.entry(r.comet_addr.clone()).or_default();
+                bucket.dispatch_total += 1;
+                if r.has_err {
+                    bucket.dispatch_failed += 1;
+                    continue;
+                }
+                let Some(dispatch_time) = dispatch_time else {
+                    continue;
+                };
+                if let Some(start_time) = start_times.get(&(schedule_id.clone(), r.instance_id)) {
+                    let ms = (*start_time - dispatch_time).num_milliseconds().max(0) as u64;
+                    bucket.observe_latency(ms);
+                }
+            }
+        }
+
+        for (comet_addr, v) in buckets {
+            DispatchStat::insert(dispatch_stat::ActiveModel {
+                stat_time: Set(start),
+                comet_addr: Set(comet_addr),
+                dispatch_total: Set(v.dispatch_total),
+                dispatch_failed: Set(v.dispatch_failed),
+                latency_total: Set(v.latency.iter().sum::<u64>() as u32),
+                latency_buckets: Set(Some(serde_json::to_value(&v.latency)?)),
+                ..Default::default()
+            })
+            .on_conflict(
+                OnConflict::columns([
+                    dispatch_stat::Column::StatTime,
+                    dispatch_stat::Column::CometAddr,
+                ])
+                .update_columns([
+                    dispatch_stat::Column::DispatchTotal,
+                    dispatch_stat::Column::DispatchFailed,
+                    dispatch_stat::Column::LatencyTotal,
+                    dispatch_stat::Column::LatencyBuckets,
+                ])
+                .to_owned(),
+            )
+            .exec(&self.ctx.db)
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// aggregate the rollups of the latest window, the current bucket is included
+    pub async fn get_dispatch_stat(&self, window: TimeDelta) -> Result<DispatchStatSummary> {
+        let records = DispatchStat::find()
+            .filter(dispatch_stat::Column::StatTime.gte(bucket_start(Local::now() - window)))
+            .all(&self.ctx.db)
+            .await?;
+
+        let mut summary = DispatchStatSummary::default();
+        let mut comets: BTreeMap<String, CometDispatchStat> = BTreeMap::new();
+        let mut histogram = Vec::new();
+
+        for v in records {
+            summary.dispatch_total += v.dispatch_total as u64;
+            summary.dispatch_failed += v.dispatch_failed as u64;
+            summary.latency_samples += v.latency_total as u64;
+
+            let comet = comets
+                .entry(v.comet_addr.clone())
+                .or_insert_with(|| CometDispatchStat {
+                    comet_addr: v.comet_addr.clone(),
+                    ..Default::default()
+                });
+            comet.dispatch_total += v.dispatch_total as u64;
+            comet.dispatch_failed += v.dispatch_failed as u64;
+
+            if let Some(buckets) = v
+                .latency_buckets
+                .and_then(|v| serde_json::from_value::<Vec<u64>>(v).ok())
+            {
+                merge_histogram(&mut histogram, &buckets);
+            }
+        }
+
+        summary.success_rate = if summary.dispatch_total == 0 {
+            1.0
+        } else {
+            (summary.dispatch_total - summary.dispatch_failed) as f64
+                / summary.dispatch_total as f64
+        };
+        summary.latency_p50 = percentile(&histogram, 0.5);
+        summary.latency_p95 = percentile(&histogram, 0.95);
+        summary.comets = comets.into_values().collect();
+        Ok(summary)
+    }
+}
+
+#[test]
+fn test_latency_percentile() {
+    let mut bucket = CometBucket::default();
+    assert_eq!(percentile(&bucket.latency, 0.5), None);
+
+    for ms in [5, 8, 20, 40, 40, 90, 200, 400, 900, 120000] {
+        bucket.observe_latency(ms);
+    }
+    assert_eq!(percentile(&bucket.latency, 0.5), Some(50));
+    assert_eq!(percentile(&bucket.latency, 0.95), Some(60000));
+    assert_eq!(percentile(&bucket.latency, 0.1), Some(10));
+}
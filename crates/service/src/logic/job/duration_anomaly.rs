@@ -0,0 +1,279 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use chrono::{DateTime, Local, TimeDelta};
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, EntityTrait, JoinType, QueryFilter, QueryOrder, QuerySelect,
+    QueryTrait,
+};
+use sea_query::OnConflict;
+use serde_json::json;
+use tracing::error;
+
+use super::{JobLogic, types::DurationAnomalyModel};
+use crate::{
+    config::DurationAnomaly,
+    entity::{
+        instance, job, job_duration_anomaly, job_duration_stat, job_exec_history, prelude::*,
+    },
+    logic,
+};
+
+/// durations of the latest runs kept to compute the median
+pub const DURATION_SAMPLES: usize = 30;
+
+pub fn median(samples: &[u64]) -> u64 {
+    if samples.is_empty() {
+        return 0;
+    }
+    let mut sorted = samples.to_vec();
+    sorted.sort_unstable();
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2
+    } else {
+        sorted[mid]
+    }
+}
+
+/// whether the run is much slower than the runs observed before
+pub fn is_duration_anomaly(opts: &DurationAnomaly, samples: &[u64], duration_ms: u64) -> bool {
+    if samples.len() < opts.min_samples.max(1) || duration_ms < opts.min_duration_secs * 1000 {
+        return false;
+    }
+    duration_ms as f64 > median(samples) as f64 * opts.ratio
+}
+
+impl<'a> JobLogic<'a> {
+    /// feed the runs finished since the last rollup into the rolling statistics of
+    /// each (eid, instance), the runs exceeding the ratio of the median are flagged.
+    /// at most one day is backfilled after a long downtime
+    pub async fn rollup_duration_stat(&self, opts: &DurationAnomaly) -> Result<u64> {
+        let now = Local::now();
+        let earliest = now - TimeDelta::days(1);
+        let cursor = JobDurationStat::find()
+            .filter(job_duration_stat::Column::LastEndTime.is_not_null())
+            .order_by_desc(job_duration_stat::Column::LastEndTime)
+            .one(&self.ctx.db)
+            .await?
+            .and_then(|v| v.last_end_time)
+            .map_or(earliest, |v| v.max(earliest));
+
+        let records = JobExecHistory::find()
+            .filter(job_exec_history::Column::EndTime.gt(cursor))
+            .filter(job_exec_history::Column::EndTime.lte(now))
+            .filter(job_exec_history::Column::StartTime.is_not_null())
+            .order_by_asc(job_exec_history::Column::EndTime)
+            .all(&self.ctx.db)
+            .await?;
+
+        let mut groups: BTreeMap<(String, String), Vec<job_exec_history::Model>> = BTreeMap::new();
+        for v in records {
+            groups
+                .entry((v.eid.clone(), v.instance_id.clone()))
+                .or_default()
+                .push(v);
+        }
+
+        let mut anomalies = Vec::new();
+        for ((eid, instance_id), runs) in groups {
+            let stat = JobDurationStat::find()
+                .filter(job_duration_stat::Column::Eid.eq(&eid))
+                .filter(job_duration_stat::Column::InstanceId.eq(&instance_id))
+                .one(&self.ctx.db)
+                .await?;
+            let (mut samples, mut sample_total): (Vec<u64>, u32) = match stat {
+                Some(v) => (
+                    v.samples
+                        .and_then(|v| serde_json::from_value(v).ok())
+                        .unwrap_or_default(),
+                    v.sample_total,
+                ),
+                None => (vec![], 0),
+            };
+
+            let mut last_end_time: Option<DateTime<Local>> = None;
+            for run in runs {
+                let (Some(start_time), Some(end_time)) = (run.start_time, run.end_time) else {
+                    continue;
+                };
+                let duration_ms = (end_time - start_time).num_milliseconds().max(0) as u64;
+
+                if is_duration_anomaly(opts, &samples, duration_ms) {
+                    anomalies.push(job_duration_anomaly::Model {
+                        eid: eid.clone(),
+                        instance_id: instance_id.clone(),
+                        schedule_id: run.schedule_id.clone(),
+                        run_id: run.run_id.clone(),
+                        exec_history_id: run.id,
+                        duration_ms,
+                        median_ms: median(&samples),
+                        ..Default::default()
+                    });
+                }
+
+                samples.push(duration_ms);
+                if samples.len() > DURATION_SAMPLES {
+                    samples.remove(0);
+                }
+                sample_total += 1;
+                last_end_time = Some(end_time);
+            }
+
+            JobDurationStat::insert(job_duration_stat::ActiveModel {
+                eid: Set(eid),
+                instance_id: Set(instance_id),
+                samples: Set(Some(json!(samples))),
+                sample_total: Set(sample_total),
+                median_ms: Set(median(&samples)),
+                last_end_time: Set(last_end_time),
+                ..Default::default()
+            })
+            .on_conflict(
+                OnConflict::columns([
+                    job_duration_stat::Column::Eid,
+                    job_duration_stat::Column::InstanceId,
+                ])
+                .update_columns([
+                    job_duration_stat::Column::Samples,
+                    job_duration_stat::Column::SampleTotal,
+                    job_duration_stat::Column::MedianMs,
+                    job_duration_stat::Column::LastEndTime,
+                ])
+                .to_owned(),
+            )
+            .exec(&self.ctx.db)
+            .await?;
+        }
+
+        for v in anomalies.iter() {
+            JobDurationAnomaly::insert(job_duration_anomaly::ActiveModel {
+                eid: Set(v.eid.clone()),
+                instance_id: Set(v.instance_id.clone()),
+                schedule_id: Set(v.schedule_id.clone()),
+                run_id: Set(v.run_id.clone()),
+                exec_history_id: Set(v.exec_history_id),
+                duration_ms: Set(v.duration_ms),
+                median_ms: Set(v.median_ms),
+                ..Default::default()
+            })
+            .exec(&self.ctx.db)
+            .await?;
+
+            if let Some(url) = opts.webhook_url.as_ref() {
+                // a failed notification does not roll back the statistics
+                let _ = self
+                    .notify_duration_anomaly(url, v)
+                    .await
+                    .map_err(|e| error!("failed notify duration anomaly - {e:?}"));
+            }
+        }
+
+        Ok(anomalies.len() as u64)
+    }
+
+    async fn notify_duration_anomaly(
+        &self,
+        url: &str,
+        anomaly: &job_duration_anomaly::Model,
+    ) -> Result<()> {
+        self.ctx
+            .http_client
+            .post(url)
+            .json(&json!({
+                "event": "duration_anomaly",
+                "eid": anomaly.eid,
+                "instance_id": anomaly.instance_id,
+                "schedule_id": anomaly.schedule_id,
+                "run_id": anomaly.run_id,
+                "duration_ms": anomaly.duration_ms,
+                "median_ms": anomaly.median_ms,
+            }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// anomalies of the jobs visible to the user, the latest first
+    pub async fn get_duration_anomalies(
+        &self,
+        user_info: &logic::types::UserInfo,
+        window: TimeDelta,
+        limit: u64,
+    ) -> Result<Vec<DurationAnomalyModel>> {
+        let svc = self.ctx.service();
+        let (search_user, team_ids) = if self.ctx.can_manage_job(&user_info.user_id).await? {
+            (None, None)
+        } else {
+            let team_ids = svc
+                .team
+                .get_my_teams(&user_info.user_id)
+                .await?
+                .into_iter()
+                .map(|v| v.id)
+                .collect::<Vec<u64>>();
+            (Some(user_info.username.clone()), Some(team_ids))
+        };
+
+        Ok(JobDurationAnomaly::find()
+            .column_as(job::Column::Name, "job_name")
+            .column_as(instance::Column::Ip, "ip")
+            .join_rev(
+                JoinType::LeftJoin,
+                Job::belongs_to(JobDurationAnomaly)
+                    .from(job::Column::Eid)
+                    .to(job_duration_anomaly::Column::Eid)
+                    .into(),
+            )
+            .join_rev(
+                JoinType::LeftJoin,
+                Instance::belongs_to(JobDurationAnomaly)
+                    .from(instance::Column::InstanceId)
+                    .to(job_duration_anomaly::Column::InstanceId)
+                    .into(),
+            )
+            .apply_if(search_user, |query, v| {
+                let mut cond = job::Column::CreatedUser.eq(v);
+                if let Some(team_ids) = team_ids {
+                    cond = cond.or(job::Column::TeamId.is_in(team_ids));
+                }
+                query.filter(cond)
+            })
+            .filter(job_duration_anomaly::Column::CreatedTime.gte(Local::now() - window))
+            .order_by_desc(job_duration_anomaly::Column::Id)
+            .limit(limit)
+            .into_model()
+            .all(&self.ctx.db)
+            .await?)
+    }
+
+    /// remove the statistics of the job, called when the job is purged
+    pub(super) async fn delete_duration_stats(&self, eid: &str) -> Result<u64> {
+        let ret = JobDurationStat::delete_many()
+            .filter(job_duration_stat::Column::Eid.eq(eid))
+            .exec(&self.ctx.db)
+            .await?;
+        JobDurationAnomaly::delete_many()
+            .filter(job_duration_anomaly::Column::Eid.eq(eid))
+            .exec(&self.ctx.db)
+            .await?;
+        Ok(ret.rows_affected)
+    }
+}
+
+#[test]
+fn test_duration_anomaly() {
+    assert_eq!(median(&[]), 0);
+    assert_eq!(median(&[3, 1, 2]), 2);
+    assert_eq!(median(&[4, 1, 3, 2]), 2);
+
+    let opts = DurationAnomaly::default();
+    let samples = [10_000, 12_000, 11_000, 9_000, 10_000];
+    assert!(!is_duration_anomaly(&opts, &samples, 25_000));
+    assert!(is_duration_anomaly(&opts, &samples, 31_000));
+    // too few runs to trust the median
+    assert!(!is_duration_anomaly(&opts, &samples[..4], 31_000));
+    // short runs are never flagged
+    assert!(!is_duration_anomaly(&opts, &[100; 5], 4_000));
+}
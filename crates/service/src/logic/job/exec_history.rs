@@ -10,14 +10,42 @@ use sea_orm::{
     ColumnTrait, Condition, EntityTrait, JoinType, PaginatorTrait, QueryFilter, QueryOrder,
     QuerySelect, QueryTrait,
 };
-use sea_query::Query;
+use sea_query::{Expr, Query};
 
 use super::JobLogic;
 use super::types::ExecHistoryRelatedScheduleModel;
+use crate::logic::tag::{JOB_RESOURCE_TYPES, tag_scope_expr};
 
 impl<'a> JobLogic<'a> {
     pub async fn create_exec_history(&self) {}
 
+    pub async fn get_exec_history(&self, id: u64) -> Result<Option<job_exec_history::Model>> {
+        let ret = JobExecHistory::find_by_id(id).one(&self.ctx.db).await?;
+        Ok(ret)
+    }
+
+    /// the latest run of each of the jobs, the jobs never run are absent
+    pub async fn get_latest_exec_history(
+        &self,
+        eids: Vec<String>,
+    ) -> Result<Vec<job_exec_history::Model>> {
+        if eids.is_empty() {
+            return Ok(vec![]);
+        }
+        let ids: Vec<u64> = JobExecHistory::find()
+            .select_only()
+            .column_as(job_exec_history::Column::Id.max(), "id")
+            .filter(job_exec_history::Column::Eid.is_in(eids))
+            .group_by(job_exec_history::Column::Eid)
+            .into_tuple()
+            .all(&self.ctx.db)
+            .await?;
+        Ok(JobExecHistory::find()
+            .filter(job_exec_history::Column::Id.is_in(ids))
+            .all(&self.ctx.db)
+            .await?)
+    }
+
     pub async fn query_exec_history(
         &self,
         job_type: String,
@@ -33,6 +61,8 @@ impl<'a> JobLogic<'a> {
         bind_ip: Option<String>,
         start_time_range: Option<(String, String)>,
         tag_ids: Option<Vec<u64>>,
+        tag_scope: Option<Vec<u64>>,
+        metric: Option<String>,
         page: u64,
         page_size: u64,
     ) -> Result<(Vec<ExecHistoryRelatedScheduleModel>, u64)> {
@@ -109,7 +139,13 @@ impl<'a> JobLogic<'a> {
                         .and(job_exec_history::Column::EndTime.lt(v.1)),
                 )
             })
-            .apply_if(team_id, |q, v| q.filter(job::Column::TeamId.eq(v)));
+            .apply_if(team_id, |q, v| q.filter(job::Column::TeamId.eq(v)))
+            .apply_if(metric, |q, v| {
+                q.filter(Expr::cust_with_values(
+                    "JSON_CONTAINS_PATH(job_exec_history.parsed_result, 'one', ?)",
+                    vec![format!("$.\"{}\"", v.replace('"', ""))],
+                ))
+            });
 
         match tag_ids {
             Some(v) if v.len() > 0 => {
@@ -127,6 +163,9 @@ impl<'a> JobLogic<'a> {
             }
             _ => {}
         };
+        if let Some(v) = tag_scope {
+            select = select.filter(tag_scope_expr(job::Column::Id, &JOB_RESOURCE_TYPES, v));
+        }
 
         let total = select.clone().count(&self.ctx.db).await?;
 
@@ -0,0 +1,393 @@
+use std::{fs::File, path::Path, sync::Arc};
+
+use anyhow::Result;
+use arrow::{
+    array::{ArrayRef, Int64Array, StringArray, TimestampMillisecondArray},
+    datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit},
+    record_batch::RecordBatch,
+};
+use chrono::{DateTime, Local};
+use parquet::arrow::ArrowWriter;
+use sea_orm::{
+    ColumnTrait, EntityTrait, JoinType, QueryFilter, QueryOrder, QuerySelect, QueryTrait,
+};
+use serde::{Deserialize, Serialize};
+
+use super::JobLogic;
+use crate::entity::{job, job_exec_history, job_schedule_history, prelude::*};
+
+/// exported files are written here and served by the task id
+pub const EXPORT_DIR: &str = "/tmp/jiascheduler/export";
+
+/// rows fetched and written at a time
+const EXPORT_BATCH: u64 = 5000;
+
+/// exports are cut at this many rows, narrow the time range to export more
+pub const MAX_EXPORT_ROWS: u64 = 1_000_000;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportKind {
+    ExecHistory,
+    ScheduleHistory,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Csv,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+/// filters of the export, the scope of the user is resolved when the task is queued
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ExportHistoryTask {
+    pub kind: ExportKind,
+    pub format: ExportFormat,
+    pub eid: Option<String>,
+    pub team_id: Option<u64>,
+    /// only the records created by the user, unset for the job managers
+    pub created_user: Option<String>,
+    /// range of created_time, eg: ("2025-09-01 00:00:00", "2025-09-08 00:00:00")
+    pub time_range: Option<(String, String)>,
+}
+
+#[derive(Clone, Copy)]
+enum ColumnKind {
+    Int,
+    Str,
+    Time,
+}
+
+enum Cell {
+    Int(Option<i64>),
+    Str(String),
+    Time(Option<DateTime<Local>>),
+}
+
+const EXEC_HISTORY_COLUMNS: [(&str, ColumnKind); 15] = [
+    ("id", ColumnKind::Int),
+    ("eid", ColumnKind::Str),
+    ("job_type", ColumnKind::Str),
+    ("schedule_id", ColumnKind::Str),
+    ("run_id", ColumnKind::Str),
+    ("instance_id", ColumnKind::Str),
+    ("exit_status", ColumnKind::Str),
+    ("exit_code", ColumnKind::Int),
+    ("result_status", ColumnKind::Str),
+    ("start_time", ColumnKind::Time),
+    ("end_time", ColumnKind::Time),
+    ("duration_ms", ColumnKind::Int),
+    ("rerun_of", ColumnKind::Int),
+    ("created_user", ColumnKind::Str),
+    ("created_time", ColumnKind::Time),
+];
+
+const SCHEDULE_HISTORY_COLUMNS: [(&str, ColumnKind); 10] = [
+    ("id", ColumnKind::Int),
+    ("schedule_id", ColumnKind::Str),
+    ("name", ColumnKind::Str),
+    ("eid", ColumnKind::Str),
+    ("job_type", ColumnKind::Str),
+    ("schedule_type", ColumnKind::Str),
+    ("action", ColumnKind::Str),
+    ("override_reason", ColumnKind::Str),
+    ("created_user", ColumnKind::Str),
+    ("created_time", ColumnKind::Time),
+];
+
+fn exec_history_row(v: job_exec_history::Model) -> Vec<Cell> {
+    let duration_ms = match (v.start_time, v.end_time) {
+        (Some(start), Some(end)) => Some((end - start).num_milliseconds()),
+        _ => None,
+    };
+    vec![
+        Cell::Int(Some(v.id as i64)),
+        Cell::Str(v.eid),
+        Cell::Str(v.job_type),
+        Cell::Str(v.schedule_id),
+        Cell::Str(v.run_id),
+        Cell::Str(v.instance_id),
+        Cell::Str(v.exit_status),
+        Cell::Int(Some(v.exit_code as i64)),
+        Cell::Str(v.result_status),
+        Cell::Time(v.start_time),
+        Cell::Time(v.end_time),
+        Cell::Int(duration_ms),
+        Cell::Int(Some(v.rerun_of as i64)),
+        Cell::Str(v.created_user),
+        Cell::Time(Some(v.created_time)),
+    ]
+}
+
+fn schedule_history_row(v: job_schedule_history::Model) -> Vec<Cell> {
+    vec![
+        Cell::Int(Some(v.id as i64)),
+        Cell::Str(v.schedule_id),
+        Cell::Str(v.name),
+        Cell::Str(v.eid),
+        Cell::Str(v.job_type),
+        Cell::Str(v.schedule_type),
+        Cell::Str(v.action),
+        Cell::Str(v.override_reason),
+        Cell::Str(v.created_user),
+        Cell::Time(Some(v.created_time)),
+    ]
+}
+
+fn column_array(kind: ColumnKind, rows: &[Vec<Cell>], i: usize) -> ArrayRef {
+    match kind {
+        ColumnKind::Int => Arc::new(Int64Array::from_iter(rows.iter().map(|row| {
+            match &row[i] {
+                Cell::Int(v) => *v,
+                _ => None,
+            }
+        }))),
+        ColumnKind::Str => Arc::new(StringArray::from_iter(rows.iter().map(|row| {
+            match &row[i] {
+                Cell::Str(v) => Some(v.as_str()),
+                _ => None,
+            }
+        }))),
+        ColumnKind::Time => Arc::new(
+            TimestampMillisecondArray::from_iter(rows.iter().map(|row| match &row[i] {
+                Cell::Time(v) => v.map(|v| v.timestamp_millis()),
+                _ => None,
+            }))
+            .with_timezone("UTC"),
+        ),
+    }
+}
+
+enum ExportWriter {
+    Csv(csv::Writer<File>),
+    Parquet(ArrowWriter<File>, SchemaRef),
+}
+
+impl ExportWriter {
+    fn new(path: &Path, format: ExportFormat, columns: &[(&str, ColumnKind)]) -> Result<Self> {
+        let file = File::create(path)?;
+        Ok(match format {
+            ExportFormat::Csv => {
+                let mut w = csv::Writer::from_writer(file);
+                w.write_record(columns.iter().map(|v| v.0))?;
+                ExportWriter::Csv(w)
+            }
+            ExportFormat::Parquet => {
+                let schema: SchemaRef = Arc::new(Schema::new(
+                    columns
+                        .iter()
+                        .map(|(name, kind)| {
+                            let data_type = match kind {
+                                ColumnKind::Int => DataType::Int64,
+                                ColumnKind::Str => DataType::Utf8,
+                                ColumnKind::Time => {
+                                    DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into()))
+                                }
+                            };
+                            Field::new(*name, data_type, true)
+                        })
+                        .collect::<Vec<_>>(),
+                ));
+                ExportWriter::Parquet(ArrowWriter::try_new(file, schema.clone(), None)?, schema)
+            }
+        })
+    }
+
+    fn write(&mut self, columns: &[(&str, ColumnKind)], rows: &[Vec<Cell>]) -> Result<()> {
+        match self {
+            ExportWriter::Csv(w) => {
+                for row in rows {
+                    w.write_record(row.iter().map(|v| match v {
+                        Cell::Int(v) => v.map(|v| v.to_string()).unwrap_or_default(),
+                        Cell::Str(v) => v.clone(),
+                        Cell::Time(v) => v.map(|v| v.to_rfc3339()).unwrap_or_default(),
+                    }))?;
+                }
+            }
+            ExportWriter::Parquet(w, schema) => {
+                let arrays = columns
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (_, kind))| column_array(*kind, rows, i))
+                    .collect();
+                w.write(&RecordBatch::try_new(schema.clone(), arrays)?)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        match self {
+            ExportWriter::Csv(mut w) => w.flush()?,
+            ExportWriter::Parquet(w, _) => {
+                w.close()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> JobLogic<'a> {
+    /// write the history matched by the task into EXPORT_DIR, returns the file name and the rows
+    pub async fn export_history(
+        &self,
+        task_id: &str,
+        task: &ExportHistoryTask,
+    ) -> Result<(String, u64)> {
+        tokio::fs::create_dir_all(EXPORT_DIR).await?;
+        let file_name = format!("{task_id}.{}", task.format.extension());
+        let path = Path::new(EXPORT_DIR).join(&file_name);
+
+        let columns: &[(&str, ColumnKind)] = match task.kind {
+            ExportKind::ExecHistory => &EXEC_HISTORY_COLUMNS,
+            ExportKind::ScheduleHistory => &SCHEDULE_HISTORY_COLUMNS,
+        };
+        let mut writer = ExportWriter::new(&path, task.format, columns)?;
+
+        let mut last_id = 0;
+        let mut total = 0;
+        while total < MAX_EXPORT_ROWS {
+            let (rows, next_id) = match task.kind {
+                ExportKind::ExecHistory => self.fetch_exec_history_rows(task, last_id).await?,
+                ExportKind::ScheduleHistory => {
+                    self.fetch_schedule_history_rows(task, last_id).await?
+                }
+            };
+            let Some(next_id) = next_id else {
+                break;
+            };
+            total += rows.len() as u64;
+            writer.write(columns, &rows)?;
+            last_id = next_id;
+        }
+        writer.finish()?;
+
+        Ok((file_name, total))
+    }
+
+    async fn fetch_exec_history_rows(
+        &self,
+        task: &ExportHistoryTask,
+        last_id: u64,
+    ) -> Result<(Vec<Vec<Cell>>, Option<u64>)> {
+        let records = JobExecHistory::find()
+            .join_rev(
+                JoinType::LeftJoin,
+                Job::belongs_to(JobExecHistory)
+                    .from(job::Column::Eid)
+                    .to(job_exec_history::Column::Eid)
+                    .into(),
+            )
+            .filter(job_exec_history::Column::Id.gt(last_id))
+            .apply_if(task.eid.clone(), |q, v| {
+                q.filter(job_exec_history::Column::Eid.eq(v))
+            })
+            .apply_if(task.team_id, |q, v| q.filter(job::Column::TeamId.eq(v)))
+            .apply_if(task.created_user.clone(), |q, v| {
+                q.filter(job_exec_history::Column::CreatedUser.eq(v))
+            })
+            .apply_if(task.time_range.clone(), |q, v| {
+                q.filter(
+                    job_exec_history::Column::CreatedTime
+                        .gte(v.0)
+                        .and(job_exec_history::Column::CreatedTime.lt(v.1)),
+                )
+            })
+            .order_by_asc(job_exec_history::Column::Id)
+            .limit(EXPORT_BATCH)
+            .all(&self.ctx.db)
+            .await?;
+
+        let next_id = records.last().map(|v| v.id);
+        Ok((records.into_iter().map(exec_history_row).collect(), next_id))
+    }
+
+    async fn fetch_schedule_history_rows(
+        &self,
+        task: &ExportHistoryTask,
+        last_id: u64,
+    ) -> Result<(Vec<Vec<Cell>>, Option<u64>)> {
+        let records = JobScheduleHistory::find()
+            .join_rev(
+                JoinType::LeftJoin,
+                Job::belongs_to(JobScheduleHistory)
+                    .from(job::Column::Eid)
+                    .to(job_schedule_history::Column::Eid)
+                    .into(),
+            )
+            .filter(job_schedule_history::Column::Id.gt(last_id))
+            .filter(job_schedule_history::Column::IsDeleted.eq(false))
+            .apply_if(task.eid.clone(), |q, v| {
+                q.filter(job_schedule_history::Column::Eid.eq(v))
+            })
+            .apply_if(task.team_id, |q, v| q.filter(job::Column::TeamId.eq(v)))
+            .apply_if(task.created_user.clone(), |q, v| {
+                q.filter(job_schedule_history::Column::CreatedUser.eq(v))
+            })
+            .apply_if(task.time_range.clone(), |q, v| {
+                q.filter(
+                    job_schedule_history::Column::CreatedTime
+                        .gte(v.0)
+                        .and(job_schedule_history::Column::CreatedTime.lt(v.1)),
+                )
+            })
+            .order_by_asc(job_schedule_history::Column::Id)
+            .limit(EXPORT_BATCH)
+            .all(&self.ctx.db)
+            .await?;
+
+        let next_id = records.last().map(|v| v.id);
+        Ok((
+            records.into_iter().map(schedule_history_row).collect(),
+            next_id,
+        ))
+    }
+}
+
+#[test]
+fn test_export_writer() {
+    let dir = std::env::temp_dir().join(format!("jiascheduler-export-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let rows = vec![
+        exec_history_row(job_exec_history::Model {
+            id: 1,
+            eid: "j-1".to_string(),
+            exit_code: 2,
+            created_time: Local::now(),
+            ..Default::default()
+        }),
+        exec_history_row(job_exec_history::Model {
+            id: 2,
+            eid: "j-1".to_string(),
+            created_time: Local::now(),
+            ..Default::default()
+        }),
+    ];
+
+    let csv_path = dir.join("exec.csv");
+    let mut w = ExportWriter::new(&csv_path, ExportFormat::Csv, &EXEC_HISTORY_COLUMNS).unwrap();
+    w.write(&EXEC_HISTORY_COLUMNS, &rows).unwrap();
+    w.finish().unwrap();
+    let content = std::fs::read_to_string(&csv_path).unwrap();
+    assert_eq!(content.lines().count(), 3);
+    assert!(content.starts_with("id,eid,job_type,"));
+
+    let parquet_path = dir.join("exec.parquet");
+    let mut w =
+        ExportWriter::new(&parquet_path, ExportFormat::Parquet, &EXEC_HISTORY_COLUMNS).unwrap();
+    w.write(&EXEC_HISTORY_COLUMNS, &rows).unwrap();
+    w.finish().unwrap();
+    assert!(std::fs::read(&parquet_path).unwrap().starts_with(b"PAR1"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
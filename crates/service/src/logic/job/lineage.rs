@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow};
+use automate::{DispatchJobParams, JobAction};
+use sea_orm::{ColumnTrait, Condition, EntityTrait, QueryFilter, QueryOrder, QuerySelect};
+use serde_json::json;
+
+use super::JobLogic;
+use crate::{
+    IdGenerator,
+    entity::{job_exec_history, job_schedule_history, prelude::*},
+};
+
+pub const RUN_KIND_RETRY: &str = "retry";
+pub const RUN_KIND_RERUN: &str = "rerun";
+
+/// at most so many exec records of a schedule are loaded into its lineage
+pub const LINEAGE_EXEC_LIMIT: u64 = 1000;
+
+/// give the dispatch a new run id linked to the run it retries or reruns,
+/// only the exec runs are linked, the timer ticks get their own run ids
+pub(super) fn link_run(params: &mut DispatchJobParams, parent_run_id: String, run_kind: &str) {
+    params.run_id = IdGenerator::get_run_id();
+    if params.action != JobAction::Exec || parent_run_id.is_empty() {
+        return;
+    }
+    let fields = params.fields.get_or_insert_with(|| json!({}));
+    fields["parent_run_id"] = json!(parent_run_id);
+    fields["run_kind"] = json!(run_kind);
+}
+
+#[derive(Debug, Default)]
+pub struct RunNode {
+    pub run_id: String,
+    pub parent_run_id: String,
+    /// retry or rerun, empty for the root runs
+    pub run_kind: String,
+    /// the exec records of the instances, empty if the run never reported one
+    pub execs: Vec<job_exec_history::Model>,
+    pub children: Vec<RunNode>,
+}
+
+pub struct RunLineage {
+    pub schedule: job_schedule_history::Model,
+    pub runs: Vec<RunNode>,
+    /// the schedule has more exec records than the limit, the latest ones are left out
+    pub truncated: bool,
+}
+
+/// group the exec records by run and link the retries and reruns to their parent runs,
+/// the reruns before the lineage was recorded are linked by rerun_of
+pub fn build_run_tree(execs: Vec<job_exec_history::Model>) -> Vec<RunNode> {
+    let exec_runs: HashMap<u64, String> = execs.iter().map(|v| (v.id, v.run_id.clone())).collect();
+
+    let mut order = vec![];
+    let mut nodes: HashMap<String, RunNode> = HashMap::new();
+    for exec in execs {
+        let (parent_run_id, run_kind) = if !exec.parent_run_id.is_empty() {
+            (exec.parent_run_id.clone(), exec.run_kind.clone())
+        } else if let Some(run_id) = exec_runs.get(&exec.rerun_of) {
+            (run_id.clone(), RUN_KIND_RERUN.to_string())
+        } else {
+            (String::new(), String::new())
+        };
+
+        let node = nodes.entry(exec.run_id.clone()).or_insert_with(|| {
+            order.push(exec.run_id.clone());
+            RunNode {
+                run_id: exec.run_id.clone(),
+                ..Default::default()
+            }
+        });
+        if node.parent_run_id.is_empty() && parent_run_id != node.run_id {
+            node.parent_run_id = parent_run_id;
+            node.run_kind = run_kind;
+        }
+        node.execs.push(exec);
+    }
+
+    // the retried run may have never reported an exec record, eg: its instance was offline
+    let mut runs = vec![];
+    for run_id in order {
+        let parent_run_id = nodes[&run_id].parent_run_id.clone();
+        if !parent_run_id.is_empty() && !nodes.contains_key(&parent_run_id) {
+            runs.push(parent_run_id.clone());
+            nodes.insert(
+                parent_run_id.clone(),
+                RunNode {
+                    run_id: parent_run_id,
+                    ..Default::default()
+                },
+            );
+        }
+        runs.push(run_id);
+    }
+
+    let mut children: HashMap<String, Vec<String>> = HashMap::new();
+    for run_id in &runs {
+        let node = &nodes[run_id];
+        if !node.parent_run_id.is_empty() {
+            children
+                .entry(node.parent_run_id.clone())
+                .or_default()
+                .push(run_id.clone());
+        }
+    }
+
+    fn attach(
+        run_id: &str,
+        nodes: &mut HashMap<String, RunNode>,
+        children: &HashMap<String, Vec<String>>,
+    ) -> Option<RunNode> {
+        // removed once attached, so a cycle of the parents cannot recurse forever
+        let mut node = nodes.remove(run_id)?;
+        for child in children.get(run_id).into_iter().flatten() {
+            if let Some(v) = attach(child, nodes, children) {
+                node.children.push(v);
+            }
+        }
+        Some(node)
+    }
+
+    let roots = runs
+        .iter()
+        .filter(|v| nodes[*v].parent_run_id.is_empty())
+        .cloned()
+        .collect::<Vec<_>>();
+    let mut tree = vec![];
+    // the runs left over are in a cycle of the parents, they are listed as roots
+    for run_id in roots.iter().chain(runs.iter()) {
+        if let Some(v) = attach(run_id, &mut nodes, &children) {
+            tree.push(v);
+        }
+    }
+    tree
+}
+
+impl<'a> JobLogic<'a> {
+    /// the lineage of the schedule, or of the schedule the run belongs to:
+    /// schedule → runs → retries → reruns
+    pub async fn get_run_lineage(
+        &self,
+        schedule_id: Option<String>,
+        run_id: Option<String>,
+    ) -> Result<RunLineage> {
+        let schedule_id = match (schedule_id, run_id) {
+            (Some(schedule_id), _) => schedule_id,
+            (None, Some(run_id)) => {
+                JobExecHistory::find()
+                    .filter(
+                        Condition::any()
+                            .add(job_exec_history::Column::RunId.eq(&run_id))
+                            .add(job_exec_history::Column::ParentRunId.eq(&run_id)),
+                    )
+                    .one(&self.ctx.db)
+                    .await?
+                    .ok_or(anyhow!("cannot found run {run_id}"))?
+                    .schedule_id
+            }
+            (None, None) => anyhow::bail!("schedule_id or run_id is required"),
+        };
+
+        let schedule = self
+            .get_schedule_history(&schedule_id)
+            .await?
+            .ok_or(anyhow!(
+                "cannot found job schedule by schedule_id: {schedule_id}"
+            ))?;
+
+        let mut execs = JobExecHistory::find()
+            .filter(job_exec_history::Column::ScheduleId.eq(&schedule_id))
+            .order_by_asc(job_exec_history::Column::Id)
+            .limit(LINEAGE_EXEC_LIMIT + 1)
+            .all(&self.ctx.db)
+            .await?;
+        let truncated = execs.len() as u64 > LINEAGE_EXEC_LIMIT;
+        execs.truncate(LINEAGE_EXEC_LIMIT as usize);
+
+        Ok(RunLineage {
+            schedule,
+            runs: build_run_tree(execs),
+            truncated,
+        })
+    }
+}
+
+#[test]
+fn test_build_run_tree() {
+    let exec = |id: u64, run_id: &str, parent_run_id: &str, run_kind: &str, rerun_of: u64| {
+        job_exec_history::Model {
+            id,
+            run_id: run_id.to_string(),
+            parent_run_id: parent_run_id.to_string(),
+            run_kind: run_kind.to_string(),
+            rerun_of,
+            ..Default::default()
+        }
+    };
+
+    let tree = build_run_tree(vec![
+        exec(1, "r1", "", "", 0),
+        exec(2, "r1", "", "", 0),
+        exec(3, "r2", "r1", RUN_KIND_RETRY, 0),
+        // a rerun recorded before the lineage, linked by rerun_of
+        exec(4, "r3", "", "", 3),
+        // the retried run never reported an exec record
+        exec(5, "r5", "r4", RUN_KIND_RETRY, 0),
+        exec(6, "r6", "r6", RUN_KIND_RERUN, 0),
+    ]);
+
+    let summary = |v: &RunNode| (v.run_id.clone(), v.run_kind.clone(), v.execs.len());
+    assert_eq!(
+        tree.iter().map(summary).collect::<Vec<_>>(),
+        vec![
+            ("r1".to_string(), "".to_string(), 2),
+            ("r4".to_string(), "".to_string(), 0),
+            ("r6".to_string(), "".to_string(), 1),
+        ]
+    );
+
+    let retry = &tree[0].children[0];
+    assert_eq!(
+        summary(retry),
+        ("r2".to_string(), RUN_KIND_RETRY.to_string(), 1)
+    );
+    assert_eq!(
+        summary(&retry.children[0]),
+        ("r3".to_string(), RUN_KIND_RERUN.to_string(), 1)
+    );
+    assert_eq!(tree[1].children[0].run_id, "r5");
+}
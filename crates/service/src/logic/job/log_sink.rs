@@ -0,0 +1,214 @@
+//! the full output of the finished runs is shipped to the log sinks of the namespace, the
+//! exec history only keeps the output trimmed to trim_bytes of the sinks
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use automate::bridge::msg::UpdateJobParams;
+use chrono::Utc;
+use serde_json::{Value, json};
+use tracing::error;
+
+use super::JobLogic;
+use crate::config::{LOG_SINK_ELASTICSEARCH, LOG_SINK_LOKI, LOG_SINK_S3, LogSink};
+
+/// the smallest trim_bytes of the sinks the runs of the namespace are shipped to,
+/// none if they are not shipped or kept whole
+pub fn trim_bytes(sinks: &[LogSink], namespace: &str) -> Option<usize> {
+    sinks
+        .iter()
+        .filter(|v| v.matches(namespace) && v.trim_bytes > 0)
+        .map(|v| v.trim_bytes)
+        .min()
+}
+
+fn floor_char_boundary(s: &str, mut index: usize) -> usize {
+    while !s.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+/// the head and the tail of the output within max bytes, joined by a marker
+pub fn trim_output(output: &str, max: usize) -> Option<String> {
+    if output.len() <= max {
+        return None;
+    }
+    let head = floor_char_boundary(output, max / 2);
+    let mut tail = output.len() - (max - max / 2);
+    while !output.is_char_boundary(tail) {
+        tail += 1;
+    }
+    Some(format!(
+        "{}\n... {} bytes shipped to the log sinks ...\n{}",
+        &output[..head],
+        tail - head,
+        &output[tail..]
+    ))
+}
+
+fn labels(params: &UpdateJobParams) -> BTreeMap<&'static str, String> {
+    BTreeMap::from([
+        ("eid", params.base_job.eid.clone()),
+        ("instance_id", params.instance_id.clone()),
+        ("schedule_id", params.schedule_id.clone()),
+        ("namespace", params.bind_namespace.clone()),
+    ])
+}
+
+/// one stream for stdout and one for stderr, each line is an entry
+pub fn loki_payload(params: &UpdateJobParams) -> Value {
+    let ts = params.end_time.unwrap_or_else(Utc::now);
+    let ts = ts.timestamp_nanos_opt().unwrap_or_default();
+    let streams: Vec<Value> = [("stdout", &params.stdout), ("stderr", &params.stderr)]
+        .into_iter()
+        .filter_map(|(stream, output)| {
+            let output = output.as_deref().filter(|v| !v.is_empty())?;
+            let mut labels = labels(params);
+            labels.insert("stream", stream.to_string());
+            // the lines of the same run keep their order with distinct timestamps
+            let values: Vec<Value> = output
+                .lines()
+                .enumerate()
+                .map(|(i, line)| json!([(ts + i as i64).to_string(), line]))
+                .collect();
+            Some(json!({"stream": labels, "values": values}))
+        })
+        .collect();
+    json!({ "streams": streams })
+}
+
+pub fn elasticsearch_document(params: &UpdateJobParams) -> Value {
+    let mut doc = json!(labels(params));
+    doc["@timestamp"] = json!(params.end_time.unwrap_or_else(Utc::now).to_rfc3339());
+    doc["run_id"] = json!(params.run_id);
+    doc["exit_code"] = json!(params.exit_code);
+    doc["stdout"] = json!(params.stdout.as_deref().unwrap_or_default());
+    doc["stderr"] = json!(params.stderr.as_deref().unwrap_or_default());
+    doc
+}
+
+/// eg: {url}/{namespace}/{eid}/{schedule_id}/{instance_id}-{run_id}.log
+pub fn s3_object_url(url: &str, params: &UpdateJobParams) -> String {
+    format!(
+        "{}/{}/{}/{}/{}-{}.log",
+        url.trim_end_matches('/'),
+        if params.bind_namespace.is_empty() {
+            "default"
+        } else {
+            &params.bind_namespace
+        },
+        params.base_job.eid,
+        params.schedule_id,
+        params.instance_id,
+        params.run_id
+    )
+}
+
+impl<'a> JobLogic<'a> {
+    /// ship the output of the finished run to the sinks of its namespace,
+    /// a failed sink is logged and does not stop the others
+    pub async fn ship_output(&self, params: &UpdateJobParams) -> Result<()> {
+        for sink in self
+            .ctx
+            .conf
+            .log_sinks
+            .iter()
+            .filter(|v| v.matches(&params.bind_namespace))
+        {
+            if let Err(e) = self.ship_output_to(sink, params).await {
+                error!(
+                    "failed to ship output of {} to log sink {} - {e}",
+                    params.base_job.eid, sink.name
+                );
+            }
+        }
+        Ok(())
+    }
+
+    async fn ship_output_to(&self, sink: &LogSink, params: &UpdateJobParams) -> Result<()> {
+        let client = &self.ctx.http_client;
+        let url = sink.url.trim_end_matches('/');
+        let req = match sink.kind.as_str() {
+            LOG_SINK_LOKI => client
+                .post(format!("{url}/loki/api/v1/push"))
+                .json(&loki_payload(params)),
+            LOG_SINK_ELASTICSEARCH => client
+                .post(format!("{url}/_doc"))
+                .json(&elasticsearch_document(params)),
+            LOG_SINK_S3 => {
+                let output = params.stdout.clone().unwrap_or_default();
+                let output = params
+                    .stderr
+                    .as_ref()
+                    .map_or(output.clone(), |v| format!("{v}\n{output}"));
+                client.put(s3_object_url(url, params)).body(output)
+            }
+            v => anyhow::bail!("unsupported log sink {v}"),
+        };
+        req.headers(Self::callback_headers(Some(sink.headers.clone())))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_trim_output() {
+    let sinks = vec![
+        LogSink {
+            namespaces: vec!["prod".to_string()],
+            trim_bytes: 8,
+            ..Default::default()
+        },
+        LogSink {
+            trim_bytes: 16,
+            ..Default::default()
+        },
+    ];
+    assert_eq!(trim_bytes(&sinks, "prod"), Some(8));
+    assert_eq!(trim_bytes(&sinks, "dev"), Some(16));
+    assert_eq!(trim_bytes(&[], "dev"), None);
+
+    assert_eq!(trim_output("hello", 8), None);
+    assert_eq!(
+        trim_output("0123456789abcdef", 8).unwrap(),
+        "0123\n... 8 bytes shipped to the log sinks ...\ncdef"
+    );
+    // the multibyte chars are not split
+    assert_eq!(
+        trim_output("ééééé", 5).unwrap(),
+        "é\n... 6 bytes shipped to the log sinks ...\né"
+    );
+}
+
+#[test]
+fn test_log_sink_payload() {
+    let params = UpdateJobParams {
+        base_job: automate::BaseJob {
+            eid: "e1".to_string(),
+            ..Default::default()
+        },
+        instance_id: "i1".to_string(),
+        schedule_id: "s1".to_string(),
+        run_id: "r1".to_string(),
+        stdout: Some("a\nb\n".to_string()),
+        end_time: Some(chrono::DateTime::from_timestamp(1, 0).unwrap()),
+        ..Default::default()
+    };
+
+    let payload = loki_payload(&params);
+    assert_eq!(payload["streams"].as_array().unwrap().len(), 1);
+    assert_eq!(payload["streams"][0]["stream"]["stream"], "stdout");
+    assert_eq!(payload["streams"][0]["stream"]["eid"], "e1");
+    assert_eq!(
+        payload["streams"][0]["values"],
+        json!([["1000000000", "a"], ["1000000001", "b"]])
+    );
+
+    assert_eq!(elasticsearch_document(&params)["schedule_id"], "s1");
+    assert_eq!(
+        s3_object_url("http://minio:9000/logs/", &params),
+        "http://minio:9000/logs/default/e1/s1/i1-r1.log"
+    );
+}
@@ -0,0 +1,337 @@
+//! dispatch of the exec action to the offline instances
+//!
+//! the dispatch to an offline instance fails by default. with the wait policy it is deferred
+//! until the instance is online again, with the substitute policy it goes to another online
+//! instance of the same group as soon as there is one. both give up after ttl_secs.
+//! the deferred dispatches are kept in job_pending_dispatch and retried by the leader
+use std::collections::HashSet;
+
+use anyhow::{Result, anyhow};
+use chrono::{Local, TimeDelta};
+use sea_orm::{ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter, QueryOrder};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{
+    JobLogic,
+    lineage::{RUN_KIND_RETRY, link_run},
+    types::{DispatchData, DispatchResult},
+};
+use crate::entity::{instance, job, job_pending_dispatch, job_schedule_history, prelude::*};
+
+pub const OFFLINE_FAIL: &str = "fail";
+pub const OFFLINE_WAIT: &str = "wait";
+pub const OFFLINE_SUBSTITUTE: &str = "substitute";
+
+pub const OFFLINE_POLICIES: [&str; 3] = [OFFLINE_FAIL, OFFLINE_WAIT, OFFLINE_SUBSTITUTE];
+
+/// longest time a dispatch waits for an online instance
+pub const OFFLINE_MAX_TTL_SECS: u64 = 7 * 24 * 3600;
+
+pub const PENDING_WAITING: &str = "waiting";
+pub const PENDING_DISPATCHED: &str = "dispatched";
+pub const PENDING_EXPIRED: &str = "expired";
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct OfflinePolicy {
+    /// fail, wait or substitute
+    pub policy: String,
+    /// seconds to wait for an online instance, unused by fail
+    #[serde(default)]
+    pub ttl_secs: u64,
+}
+
+impl OfflinePolicy {
+    pub fn check(&self) -> Result<()> {
+        if !OFFLINE_POLICIES.contains(&self.policy.as_str()) {
+            anyhow::bail!("invalid offline policy {}", self.policy);
+        }
+        if self.is_deferred() && !(1..=OFFLINE_MAX_TTL_SECS).contains(&self.ttl_secs) {
+            anyhow::bail!("offline policy ttl_secs must be between 1 and {OFFLINE_MAX_TTL_SECS}");
+        }
+        Ok(())
+    }
+
+    fn is_deferred(&self) -> bool {
+        self.policy == OFFLINE_WAIT || self.policy == OFFLINE_SUBSTITUTE
+    }
+}
+
+/// an online instance of the group of the offline one which is not a target of the dispatch
+fn pick_substitute<'b>(
+    offline: &instance::Model,
+    candidates: &'b [instance::Model],
+    targets: &HashSet<String>,
+) -> Option<&'b instance::Model> {
+    if offline.instance_group_id == 0 {
+        return None;
+    }
+    candidates.iter().find(|v| {
+        v.instance_group_id == offline.instance_group_id
+            && v.status == 1
+            && !v.maintenance
+            && !targets.contains(&v.instance_id)
+    })
+}
+
+fn truncate_reason(reason: &str) -> String {
+    reason.chars().take(500).collect()
+}
+
+impl<'a> JobLogic<'a> {
+    /// defer the failed dispatches to the offline instances by the offline policy of the job,
+    /// the deferred ones are no longer counted as failed
+    pub(super) async fn defer_offline_dispatch(
+        &self,
+        job_record: &job::Model,
+        schedule_id: &str,
+        offline_ids: &HashSet<String>,
+        results: &mut [DispatchResult],
+        created_user: &str,
+    ) -> Result<()> {
+        let Some(policy) = job_record
+            .offline_policy
+            .clone()
+            .map(serde_json::from_value::<OfflinePolicy>)
+            .transpose()?
+            .filter(|v| v.is_deferred())
+        else {
+            return Ok(());
+        };
+        let expire_time = Local::now() + TimeDelta::seconds(policy.ttl_secs as i64);
+
+        for ret in results
+            .iter_mut()
+            .filter(|v| v.has_err && offline_ids.contains(&v.instance_id))
+        {
+            JobPendingDispatch::insert(job_pending_dispatch::ActiveModel {
+                schedule_id: Set(schedule_id.to_string()),
+                eid: Set(job_record.eid.clone()),
+                instance_id: Set(ret.instance_id.clone()),
+                policy: Set(policy.policy.clone()),
+                status: Set(PENDING_WAITING.to_string()),
+                reason: Set(truncate_reason(&ret.err.clone().unwrap_or_default())),
+                expire_time: Set(expire_time),
+                created_user: Set(created_user.to_string()),
+                ..Default::default()
+            })
+            .exec(&self.ctx.db)
+            .await?;
+
+            ret.has_err = false;
+            ret.err = None;
+            ret.response = json!({"deferred": {
+                "policy": policy.policy,
+                "expire_time": expire_time,
+            }});
+        }
+        Ok(())
+    }
+
+    /// retry the deferred dispatches as the instances come online,
+    /// returns the number of the ones dispatched
+    pub async fn retry_offline_dispatch(&self) -> Result<u64> {
+        let pending = JobPendingDispatch::find()
+            .filter(job_pending_dispatch::Column::Status.eq(PENDING_WAITING))
+            .order_by_asc(job_pending_dispatch::Column::Id)
+            .all(&self.ctx.db)
+            .await?;
+
+        let mut n = 0;
+        for v in pending {
+            if v.expire_time <= Local::now() {
+                let reason = format!(
+                    "no online instance for {} within the ttl of the {} policy",
+                    v.instance_id, v.policy
+                );
+                self.replace_deferred_result(&v, |ret| {
+                    ret.response = json!(null);
+                    ret.has_err = true;
+                    ret.err = Some(reason.clone());
+                })
+                .await?;
+                self.update_pending_dispatch(v.id, PENDING_EXPIRED, String::new(), &reason)
+                    .await?;
+                continue;
+            }
+
+            match self.redispatch_pending(&v).await {
+                Ok(Some(instance_id)) => {
+                    let reason = if instance_id == v.instance_id {
+                        "the instance is online".to_string()
+                    } else {
+                        format!("substituted by {instance_id}")
+                    };
+                    self.update_pending_dispatch(v.id, PENDING_DISPATCHED, instance_id, &reason)
+                        .await?;
+                    n += 1;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    self.update_pending_dispatch(
+                        v.id,
+                        PENDING_WAITING,
+                        String::new(),
+                        &e.to_string(),
+                    )
+                    .await?
+                }
+            }
+        }
+        Ok(n)
+    }
+
+    /// dispatch to the original instance if it is online, otherwise to a substitute
+    /// if the policy allows it. none if there is no instance to dispatch to yet
+    async fn redispatch_pending(
+        &self,
+        pending: &job_pending_dispatch::Model,
+    ) -> Result<Option<String>> {
+        let Some(history) = self.get_schedule_history(&pending.schedule_id).await? else {
+            return Ok(None);
+        };
+        let mut dispatch_data: DispatchData = history
+            .dispatch_data
+            .ok_or(anyhow!("cannot found job dispatch data"))?
+            .try_into()?;
+
+        let offline = Instance::find()
+            .filter(instance::Column::InstanceId.eq(&pending.instance_id))
+            .one(&self.ctx.db)
+            .await?
+            .ok_or(anyhow!("cannot found instance {}", pending.instance_id))?;
+
+        let endpoint = if offline.status == 1 && !offline.maintenance {
+            offline
+        } else if pending.policy == OFFLINE_SUBSTITUTE && offline.instance_group_id > 0 {
+            let candidates = Instance::find()
+                .filter(instance::Column::InstanceGroupId.eq(offline.instance_group_id))
+                .filter(instance::Column::Status.eq(1))
+                .filter(instance::Column::Maintenance.eq(false))
+                .order_by_asc(instance::Column::Id)
+                .all(&self.ctx.db)
+                .await?;
+            let targets = dispatch_data
+                .target
+                .iter()
+                .map(|v| v.instance_id.clone())
+                .collect::<HashSet<_>>();
+            match pick_substitute(&offline, &candidates, &targets) {
+                Some(v) => v.clone(),
+                None => return Ok(None),
+            }
+        } else {
+            return Ok(None);
+        };
+
+        let mut fields = dispatch_data.params.fields.take().unwrap_or(json!({}));
+        fields["deferred_from"] = json!(pending.instance_id);
+
+        // the substitute runs the share of the offline instance, eg: its overrides
+        let mut dispatch_params = dispatch_data.params_for(&pending.instance_id);
+        dispatch_params.instance_id = Some(endpoint.instance_id.clone());
+        dispatch_params.fields = Some(fields);
+        let parent_run_id = dispatch_params.run_id.clone();
+        link_run(&mut dispatch_params, parent_run_id, RUN_KIND_RETRY);
+
+        let ret = self
+            .dispatch_to_endpoint(&self.ctx.conf.comet_secret, &endpoint, dispatch_params)
+            .await;
+        if ret.has_err {
+            anyhow::bail!(
+                "failed dispatch to {}, {}",
+                endpoint.instance_id,
+                ret.err.unwrap_or_default()
+            );
+        }
+        self.replace_deferred_result(pending, |v| *v = ret).await?;
+        Ok(Some(endpoint.instance_id))
+    }
+
+    /// the deferred result of the instance in the schedule history is updated to the final one
+    async fn replace_deferred_result(
+        &self,
+        pending: &job_pending_dispatch::Model,
+        update: impl FnOnce(&mut DispatchResult),
+    ) -> Result<()> {
+        let Some(history) = self.get_schedule_history(&pending.schedule_id).await? else {
+            return Ok(());
+        };
+        let mut results: Vec<DispatchResult> = history
+            .dispatch_result
+            .map(serde_json::from_value)
+            .transpose()?
+            .unwrap_or_default();
+        let Some(ret) = results
+            .iter_mut()
+            .find(|v| v.instance_id == pending.instance_id && !v.response["deferred"].is_null())
+        else {
+            return Ok(());
+        };
+        update(ret);
+
+        JobScheduleHistory::update_many()
+            .set(job_schedule_history::ActiveModel {
+                dispatch_result: Set(Some(serde_json::to_value(&results)?)),
+                ..Default::default()
+            })
+            .filter(job_schedule_history::Column::Id.eq(history.id))
+            .exec(&self.ctx.db)
+            .await?;
+        Ok(())
+    }
+
+    async fn update_pending_dispatch(
+        &self,
+        id: u64,
+        status: &str,
+        dispatched_instance_id: String,
+        reason: &str,
+    ) -> Result<()> {
+        JobPendingDispatch::update_many()
+            .set(job_pending_dispatch::ActiveModel {
+                status: Set(status.to_string()),
+                dispatched_instance_id: Set(dispatched_instance_id),
+                reason: Set(truncate_reason(reason)),
+                ..Default::default()
+            })
+            .filter(job_pending_dispatch::Column::Id.eq(id))
+            .exec(&self.ctx.db)
+            .await?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_offline_policy() {
+    let policy = |policy: &str, ttl_secs| OfflinePolicy {
+        policy: policy.to_string(),
+        ttl_secs,
+    };
+    assert!(policy(OFFLINE_FAIL, 0).check().is_ok());
+    assert!(policy(OFFLINE_WAIT, 600).check().is_ok());
+    assert!(policy(OFFLINE_WAIT, 0).check().is_err());
+    assert!(
+        policy(OFFLINE_SUBSTITUTE, OFFLINE_MAX_TTL_SECS + 1)
+            .check()
+            .is_err()
+    );
+    assert!(policy("retry", 600).check().is_err());
+
+    let ins = |id: &str, group: u64, status: i8| instance::Model {
+        instance_id: id.to_string(),
+        instance_group_id: group,
+        status,
+        ..Default::default()
+    };
+    let offline = ins("a", 1, 0);
+    let mut candidates = vec![ins("b", 1, 1), ins("c", 2, 1), ins("d", 1, 1)];
+    let targets = HashSet::from(["a".to_string(), "b".to_string()]);
+    let pick = |candidates: &[instance::Model]| {
+        pick_substitute(&offline, candidates, &targets).map(|v| v.instance_id.clone())
+    };
+    assert_eq!(pick(&candidates), Some("d".to_string()));
+    candidates[2].maintenance = true;
+    assert_eq!(pick(&candidates), None);
+    assert!(pick_substitute(&ins("a", 0, 0), &[ins("e", 0, 1)], &targets).is_none());
+}
@@ -0,0 +1,171 @@
+use anyhow::{Result, anyhow};
+use automate::JobAction;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect};
+use serde::{Deserialize, Serialize};
+
+use super::JobLogic;
+use crate::entity::{instance, job_exec_history, prelude::*};
+
+/// the job runs on one of the selected instances, preferring the one that ran it last
+pub const PLACEMENT_STICKY: &str = "sticky";
+
+pub const PLACEMENTS: [&str; 2] = ["", PLACEMENT_STICKY];
+
+/// the instance chosen by the placement, recorded in the schedule history
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct PlacementDecision {
+    pub strategy: String,
+    pub instance_id: String,
+    /// the instance that ran the job last, none if the job has never run
+    pub previous_instance_id: Option<String>,
+    /// last: the previous instance is online, failover: the previous instance is offline
+    /// or no longer selected, first: the job has never run
+    pub reason: String,
+}
+
+/// the previous instance if it is online, otherwise the first online one in the selected order
+fn pick_sticky(
+    instance_ids: &[String],
+    endpoints: &[instance::Model],
+    previous: Option<String>,
+) -> Result<PlacementDecision> {
+    let is_online = |id: &str| {
+        endpoints
+            .iter()
+            .any(|v| v.instance_id == id && v.status == 1)
+    };
+    let (instance_id, reason) = match previous.as_deref() {
+        Some(id) if instance_ids.iter().any(|v| v == id) && is_online(id) => (id, "last"),
+        _ => (
+            instance_ids
+                .iter()
+                .find(|v| is_online(v))
+                .ok_or(anyhow!("all the selected instances are offline"))?
+                .as_str(),
+            if previous.is_some() {
+                "failover"
+            } else {
+                "first"
+            },
+        ),
+    };
+    Ok(PlacementDecision {
+        strategy: PLACEMENT_STICKY.to_string(),
+        instance_id: instance_id.to_string(),
+        previous_instance_id: previous,
+        reason: reason.to_string(),
+    })
+}
+
+/// the actions placed on the instances, the others have to reach every instance
+fn starts_run(action: &JobAction) -> bool {
+    matches!(
+        action,
+        JobAction::Exec
+            | JobAction::StartTimer
+            | JobAction::StartSupervising
+            | JobAction::RestartSupervising
+    )
+}
+
+/// drop the instances in maintenance, they finish the running jobs but receive no new one
+pub fn exclude_maintenance(endpoints: Vec<instance::Model>) -> Result<Vec<instance::Model>> {
+    let total = endpoints.len();
+    let endpoints = endpoints
+        .into_iter()
+        .filter(|v| !v.maintenance)
+        .collect::<Vec<_>>();
+    if endpoints.is_empty() && total > 0 {
+        anyhow::bail!("all the selected instances are in maintenance");
+    }
+    Ok(endpoints)
+}
+
+impl<'a> JobLogic<'a> {
+    /// the instance that ran the job last
+    pub async fn get_last_run_instance(&self, eid: &str) -> Result<Option<String>> {
+        let ret = JobExecHistory::find()
+            .select_only()
+            .column(job_exec_history::Column::InstanceId)
+            .filter(job_exec_history::Column::Eid.eq(eid))
+            .filter(job_exec_history::Column::InstanceId.ne(""))
+            .order_by_desc(job_exec_history::Column::Id)
+            .into_tuple::<String>()
+            .one(&self.ctx.db)
+            .await?;
+        Ok(ret)
+    }
+
+    /// narrow the endpoints to the instance chosen by the placement of the job,
+    /// only the actions starting a run are placed and skip the instances in maintenance,
+    /// the others reach all the endpoints so that a draining instance can still be stopped
+    pub async fn place_endpoints(
+        &self,
+        placement: &str,
+        eid: &str,
+        action: JobAction,
+        instance_ids: &[String],
+        endpoints: Vec<instance::Model>,
+    ) -> Result<(Vec<instance::Model>, Option<PlacementDecision>)> {
+        if !starts_run(&action) {
+            return Ok((endpoints, None));
+        }
+        let endpoints = exclude_maintenance(endpoints)?;
+        if placement != PLACEMENT_STICKY {
+            return Ok((endpoints, None));
+        }
+        let previous = self.get_last_run_instance(eid).await?;
+        let decision = pick_sticky(instance_ids, &endpoints, previous)?;
+        let endpoints = endpoints
+            .into_iter()
+            .filter(|v| v.instance_id == decision.instance_id)
+            .collect();
+        Ok((endpoints, Some(decision)))
+    }
+}
+
+#[test]
+fn test_pick_sticky() {
+    let endpoints = [("a", 1), ("b", 0), ("c", 1)]
+        .map(|(id, status)| instance::Model {
+            instance_id: id.to_string(),
+            status,
+            ..Default::default()
+        })
+        .to_vec();
+    let ids = ["b", "c", "a"].map(String::from).to_vec();
+
+    let pick = |previous: Option<&str>| {
+        let v = pick_sticky(&ids, &endpoints, previous.map(String::from)).unwrap();
+        format!("{}:{}", v.instance_id, v.reason)
+    };
+    assert_eq!(pick(Some("a")), "a:last");
+    assert_eq!(pick(Some("b")), "c:failover");
+    assert_eq!(pick(Some("d")), "c:failover");
+    assert_eq!(pick(None), "c:first");
+    assert!(pick_sticky(&ids[..1], &endpoints, None).is_err());
+}
+
+#[test]
+fn test_exclude_maintenance() {
+    let endpoints = [("a", true), ("b", false), ("c", false)]
+        .map(|(id, maintenance)| instance::Model {
+            instance_id: id.to_string(),
+            maintenance,
+            ..Default::default()
+        })
+        .to_vec();
+    let ids =
+        |list: Vec<instance::Model>| list.into_iter().map(|v| v.instance_id).collect::<Vec<_>>();
+    assert_eq!(
+        ids(exclude_maintenance(endpoints.clone()).unwrap()),
+        ["b", "c"]
+    );
+    assert!(exclude_maintenance(endpoints[..1].to_vec()).is_err());
+    assert!(exclude_maintenance(vec![]).unwrap().is_empty());
+
+    assert!(starts_run(&JobAction::Exec));
+    assert!(starts_run(&JobAction::StartTimer));
+    assert!(!starts_run(&JobAction::Kill));
+    assert!(!starts_run(&JobAction::StopSupervising));
+}
@@ -0,0 +1,548 @@
+//! promotion of a job revision from an environment to another, eg: from the staging team to
+//! the production team.
+//!
+//! the job is copied at the requested version into the snapshot of the promotion, so the
+//! edits of the source made after the request are not promoted. the promotion is applied
+//! once approved by promotion_approvals members of the target team other than the requester,
+//! the source namespace in the name, the args and the tags of the job is rewritten to the
+//! target namespace. promoting the same job to the same team again updates the job promoted
+//! before, the promotions of a job are its lineage
+use anyhow::Result;
+use chrono::Local;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::NotSet, ActiveValue::Set, ColumnTrait, Condition, EntityTrait,
+    PaginatorTrait, QueryFilter, QueryOrder, QueryTrait,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{JobLogic, types::JobFormalArg};
+use crate::{
+    IdGenerator,
+    entity::{job, job_promotion, prelude::*, tag_resource},
+    event::AuditEvent,
+    logic::{
+        tag::TAG_PATH_SEPARATOR,
+        types::{ResourceType, UserInfo},
+    },
+};
+
+pub const PROMOTION_PENDING: &str = "pending";
+pub const PROMOTION_PROMOTED: &str = "promoted";
+pub const PROMOTION_REJECTED: &str = "rejected";
+pub const PROMOTION_FAILED: &str = "failed";
+
+/// upstream promotions followed by the lineage, guards against a cycle of promotions
+const MAX_LINEAGE_DEPTH: usize = 20;
+
+/// the tags under from are moved under to, eg: env/staging to env/production
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct TagRewrite {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct PromotionApproval {
+    pub username: String,
+    pub approved_time: String,
+}
+
+/// the promoted revision of the job
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PromotionSnapshot {
+    pub job: job::Model,
+    pub tags: Vec<String>,
+}
+
+/// the promotions the job came from, the nearest first, and those it was promoted by
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PromotionLineage {
+    pub upstream: Vec<job_promotion::Model>,
+    pub downstream: Vec<job_promotion::Model>,
+}
+
+/// replace the occurrences of from delimited by non-alphanumeric chars,
+/// eg: backup-staging is backup-production but backupstaging is unchanged
+pub fn rewrite_namespace(value: &str, from: &str, to: &str) -> String {
+    if from.is_empty() || from == to {
+        return value.to_string();
+    }
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric());
+    let mut ret = String::with_capacity(value.len());
+    let mut last = 0;
+    for (i, _) in value.match_indices(from) {
+        let end = i + from.len();
+        if is_word(value[..i].chars().last()) || is_word(value[end..].chars().next()) {
+            continue;
+        }
+        ret.push_str(&value[last..i]);
+        ret.push_str(to);
+        last = end;
+    }
+    ret.push_str(&value[last..]);
+    ret
+}
+
+/// the first rewrite whose from is the tag or a tag above it, otherwise the namespace rewrite
+pub fn rewrite_tag(path: &str, rewrites: &[TagRewrite], from: &str, to: &str) -> String {
+    for v in rewrites {
+        if let Some(rest) = path.strip_prefix(v.from.as_str())
+            && (rest.is_empty() || rest.starts_with(TAG_PATH_SEPARATOR))
+        {
+            return format!("{}{rest}", v.to);
+        }
+    }
+    path.split(TAG_PATH_SEPARATOR)
+        .map(|v| rewrite_namespace(v, from, to))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+impl<'a> JobLogic<'a> {
+    /// keep the job at the version until the promotion is approved
+    pub async fn request_promotion(
+        &self,
+        user_info: &UserInfo,
+        eid: &str,
+        version: u32,
+        target_team_id: u64,
+        source_namespace: String,
+        target_namespace: String,
+        tag_rewrites: Vec<TagRewrite>,
+    ) -> Result<job_promotion::Model> {
+        let Some(job_record) = self.get_job_by_eid(eid).await?.filter(|v| !v.is_deleted) else {
+            anyhow::bail!("cannot found job {eid}");
+        };
+        if job_record.version != version {
+            anyhow::bail!(
+                "the job was changed, the current version is {}",
+                job_record.version
+            );
+        }
+        if job_record.team_id == target_team_id {
+            anyhow::bail!("the job already belongs to the team");
+        }
+        if Team::find_by_id(target_team_id)
+            .one(&self.ctx.db)
+            .await?
+            .is_none()
+        {
+            anyhow::bail!("cannot found team {target_team_id}");
+        }
+        let pending = JobPromotion::find()
+            .filter(job_promotion::Column::SourceEid.eq(eid))
+            .filter(job_promotion::Column::TargetTeamId.eq(target_team_id))
+            .filter(job_promotion::Column::Status.eq(PROMOTION_PENDING))
+            .count(&self.ctx.db)
+            .await?;
+        if pending > 0 {
+            anyhow::bail!("the job has a pending promotion to the team");
+        }
+
+        let tags = self
+            .ctx
+            .service()
+            .tag
+            .get_all_tag_bind_by_resource_ids(
+                vec![job_record.id],
+                Self::tag_resource_type(&job_record),
+            )
+            .await?
+            .into_iter()
+            .map(|v| v.tag_name)
+            .collect();
+        let snapshot = PromotionSnapshot {
+            job: job_record.clone(),
+            tags,
+        };
+
+        let record = job_promotion::ActiveModel {
+            source_eid: Set(job_record.eid.clone()),
+            source_team_id: Set(job_record.team_id),
+            source_version: Set(version),
+            source_namespace: Set(source_namespace),
+            target_team_id: Set(target_team_id),
+            target_namespace: Set(target_namespace),
+            tag_rewrites: Set(Some(serde_json::to_value(tag_rewrites)?)),
+            snapshot: Set(Some(serde_json::to_value(snapshot)?)),
+            required_approvals: Set(self.ctx.conf.promotion_approvals()),
+            approvals: Set(Some(serde_json::json!([]))),
+            status: Set(PROMOTION_PENDING.to_string()),
+            created_user: Set(user_info.username.clone()),
+            updated_user: Set(user_info.username.clone()),
+            ..Default::default()
+        }
+        .insert(&self.ctx.db)
+        .await?;
+        self.emit_promotion_audit(&record, &user_info.username)
+            .await;
+
+        if record.required_approvals == 0 {
+            return self.apply_promotion(record, &user_info.username).await;
+        }
+        Ok(record)
+    }
+
+    pub async fn get_promotion(&self, id: u64) -> Result<Option<job_promotion::Model>> {
+        Ok(JobPromotion::find_by_id(id).one(&self.ctx.db).await?)
+    }
+
+    /// the promotion is applied by the last required approval
+    pub async fn approve_promotion(
+        &self,
+        user_info: &UserInfo,
+        record: job_promotion::Model,
+    ) -> Result<job_promotion::Model> {
+        if record.status != PROMOTION_PENDING {
+            anyhow::bail!("the promotion is {}", record.status);
+        }
+        if record.created_user == user_info.username {
+            anyhow::bail!("the promotion cannot be approved by its requester");
+        }
+        let mut approvals: Vec<PromotionApproval> = record
+            .approvals
+            .clone()
+            .map(serde_json::from_value)
+            .transpose()?
+            .unwrap_or_default();
+        if approvals.iter().any(|v| v.username == user_info.username) {
+            anyhow::bail!(
+                "the promotion was already approved by {}",
+                user_info.username
+            );
+        }
+        approvals.push(PromotionApproval {
+            username: user_info.username.clone(),
+            approved_time: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        });
+
+        let record = job_promotion::ActiveModel {
+            id: Set(record.id),
+            approvals: Set(Some(serde_json::to_value(&approvals)?)),
+            updated_user: Set(user_info.username.clone()),
+            ..Default::default()
+        }
+        .update(&self.ctx.db)
+        .await?;
+
+        if approvals.len() as u32 >= record.required_approvals {
+            return self.apply_promotion(record, &user_info.username).await;
+        }
+        Ok(record)
+    }
+
+    pub async fn reject_promotion(
+        &self,
+        user_info: &UserInfo,
+        record: job_promotion::Model,
+        reason: String,
+    ) -> Result<job_promotion::Model> {
+        if record.status != PROMOTION_PENDING {
+            anyhow::bail!("the promotion is {}", record.status);
+        }
+        let record = job_promotion::ActiveModel {
+            id: Set(record.id),
+            status: Set(PROMOTION_REJECTED.to_string()),
+            reason: Set(reason.chars().take(500).collect()),
+            updated_user: Set(user_info.username.clone()),
+            ..Default::default()
+        }
+        .update(&self.ctx.db)
+        .await?;
+        self.emit_promotion_audit(&record, &user_info.username)
+            .await;
+        Ok(record)
+    }
+
+    fn tag_resource_type(job_record: &job::Model) -> ResourceType {
+        match job_record.job_type.as_str() {
+            "bundle" => ResourceType::BundleJob,
+            _ => ResourceType::Job,
+        }
+    }
+
+    /// copy the snapshot into the target team, the promotion is failed if the copy failed
+    async fn apply_promotion(
+        &self,
+        record: job_promotion::Model,
+        username: &str,
+    ) -> Result<job_promotion::Model> {
+        let (status, target_eid, reason) = match self.copy_promoted_job(&record, username).await {
+            Ok(eid) => (PROMOTION_PROMOTED, eid, String::new()),
+            Err(e) => (
+                PROMOTION_FAILED,
+                String::new(),
+                e.to_string().chars().take(500).collect(),
+            ),
+        };
+        let record = job_promotion::ActiveModel {
+            id: Set(record.id),
+            status: Set(status.to_string()),
+            target_eid: Set(target_eid),
+            reason: Set(reason),
+            updated_user: Set(username.to_string()),
+            ..Default::default()
+        }
+        .update(&self.ctx.db)
+        .await?;
+        self.emit_promotion_audit(&record, username).await;
+
+        if record.status == PROMOTION_FAILED {
+            anyhow::bail!("failed to promote job, {}", record.reason);
+        }
+        Ok(record)
+    }
+
+    /// returns the eid of the promoted job
+    async fn copy_promoted_job(
+        &self,
+        record: &job_promotion::Model,
+        username: &str,
+    ) -> Result<String> {
+        let Some(snapshot) = record.snapshot.clone() else {
+            anyhow::bail!("the promotion has no snapshot");
+        };
+        let PromotionSnapshot { job: source, tags } = serde_json::from_value(snapshot)?;
+        let tag_rewrites: Vec<TagRewrite> = record
+            .tag_rewrites
+            .clone()
+            .map(serde_json::from_value)
+            .transpose()?
+            .unwrap_or_default();
+        let (from, to) = (
+            record.source_namespace.as_str(),
+            record.target_namespace.as_str(),
+        );
+
+        let args = source
+            .args
+            .clone()
+            .map(serde_json::from_value::<Vec<JobFormalArg>>)
+            .transpose()?
+            .map(|args| {
+                args.into_iter()
+                    .map(|v| JobFormalArg {
+                        val: rewrite_namespace(&v.val, from, to),
+                        ..v
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .map(serde_json::to_value)
+            .transpose()?;
+
+        // the job promoted to the team before is updated
+        let previous = match JobPromotion::find()
+            .filter(job_promotion::Column::SourceEid.eq(&record.source_eid))
+            .filter(job_promotion::Column::TargetTeamId.eq(record.target_team_id))
+            .filter(job_promotion::Column::Status.eq(PROMOTION_PROMOTED))
+            .order_by_desc(job_promotion::Column::Id)
+            .one(&self.ctx.db)
+            .await?
+        {
+            Some(v) => self
+                .get_job_by_eid(&v.target_eid)
+                .await?
+                .filter(|v| !v.is_deleted && v.team_id == record.target_team_id),
+            None => None,
+        };
+
+        let resource_type = Self::tag_resource_type(&source);
+        let (id, eid, created_user, version) = match previous {
+            Some(ref v) => (Set(v.id), v.eid.clone(), NotSet, Some(v.version)),
+            None => (
+                NotSet,
+                IdGenerator::get_job_eid(),
+                Set(username.to_string()),
+                None,
+            ),
+        };
+        let saved = self
+            .save_job(
+                job::ActiveModel {
+                    id,
+                    eid: Set(eid.clone()),
+                    team_id: Set(record.target_team_id),
+                    executor_id: Set(source.executor_id),
+                    job_type: Set(source.job_type.clone()),
+                    name: Set(rewrite_namespace(&source.name, from, to)),
+                    code: Set(source.code),
+                    info: Set(source.info),
+                    bundle_script: Set(source.bundle_script),
+                    upload_file: Set(source.upload_file),
+                    work_dir: Set(source.work_dir),
+                    work_user: Set(source.work_user),
+                    timeout: Set(source.timeout),
+                    max_retry: Set(source.max_retry),
+                    max_parallel: Set(source.max_parallel),
+                    completed_callback: Set(source.completed_callback),
+                    is_public: Set(source.is_public),
+                    display_on_dashboard: Set(source.display_on_dashboard),
+                    is_singleton: Set(source.is_singleton),
+                    placement: Set(source.placement),
+                    dedup_window_secs: Set(source.dedup_window_secs),
+                    offline_policy: Set(source.offline_policy),
+                    quorum: Set(source.quorum),
+                    confirmation: Set(source.confirmation),
+                    args: Set(args),
+                    http_check: Set(source.http_check),
+                    output_parsers: Set(source.output_parsers),
+                    exit_code_mapping: Set(source.exit_code_mapping),
+                    bundle_option: Set(source.bundle_option),
+                    supervisor_option: Set(source.supervisor_option),
+                    timeout_action: Set(source.timeout_action),
+                    output_limit: Set(source.output_limit),
+                    created_user,
+                    updated_user: Set(username.to_string()),
+                    ..Default::default()
+                },
+                version,
+            )
+            .await?;
+
+        let job_id = saved.id.as_ref().to_owned();
+        TagResource::delete_many()
+            .filter(tag_resource::Column::ResourceType.eq(resource_type.to_string()))
+            .filter(tag_resource::Column::ResourceId.eq(job_id))
+            .exec(&self.ctx.db)
+            .await?;
+        let user_info = UserInfo {
+            username: username.to_string(),
+            ..Default::default()
+        };
+        let tag = self.ctx.service().tag;
+        for path in tags {
+            tag.bind_tag(
+                &user_info,
+                &rewrite_tag(&path, &tag_rewrites, from, to),
+                resource_type.clone(),
+                job_id,
+            )
+            .await?;
+        }
+        Ok(eid)
+    }
+
+    async fn emit_promotion_audit(&self, record: &job_promotion::Model, username: &str) {
+        self.ctx
+            .bus
+            .emit(
+                self.ctx,
+                AuditEvent {
+                    action: "promotion",
+                    eid: record.source_eid.clone(),
+                    source: format!(
+                        "{username}, version {} to team {} {}",
+                        record.source_version, record.target_team_id, record.target_eid
+                    ),
+                    status: record.status.clone(),
+                    reason: record.reason.clone(),
+                    schedule_id: String::new(),
+                },
+            )
+            .await;
+    }
+
+    /// the promotions from or to the team, all the teams if team_id is none
+    pub async fn query_promotion(
+        &self,
+        team_id: Option<u64>,
+        created_user: Option<String>,
+        eid: Option<String>,
+        status: Option<String>,
+        page: u64,
+        page_size: u64,
+    ) -> Result<(Vec<job_promotion::Model>, u64)> {
+        let select = JobPromotion::find()
+            .apply_if(team_id, |q, v| {
+                q.filter(
+                    Condition::any()
+                        .add(job_promotion::Column::SourceTeamId.eq(v))
+                        .add(job_promotion::Column::TargetTeamId.eq(v)),
+                )
+            })
+            .apply_if(eid, |q, v| {
+                q.filter(
+                    Condition::any()
+                        .add(job_promotion::Column::SourceEid.eq(v.clone()))
+                        .add(job_promotion::Column::TargetEid.eq(v)),
+                )
+            })
+            .apply_if(created_user, |q, v| {
+                q.filter(job_promotion::Column::CreatedUser.eq(v))
+            })
+            .apply_if(status, |q, v| q.filter(job_promotion::Column::Status.eq(v)));
+
+        let total = select.clone().count(&self.ctx.db).await?;
+        let list = select
+            .order_by_desc(job_promotion::Column::Id)
+            .paginate(&self.ctx.db, page_size)
+            .fetch_page(page)
+            .await?;
+        Ok((list, total))
+    }
+
+    pub async fn get_promotion_lineage(&self, eid: &str) -> Result<PromotionLineage> {
+        let mut upstream: Vec<job_promotion::Model> = vec![];
+        let mut current = eid.to_string();
+        while upstream.len() < MAX_LINEAGE_DEPTH {
+            let Some(v) = JobPromotion::find()
+                .filter(job_promotion::Column::TargetEid.eq(&current))
+                .filter(job_promotion::Column::Status.eq(PROMOTION_PROMOTED))
+                .order_by_desc(job_promotion::Column::Id)
+                .one(&self.ctx.db)
+                .await?
+            else {
+                break;
+            };
+            if upstream.iter().any(|u| u.source_eid == v.source_eid) {
+                break;
+            }
+            current = v.source_eid.clone();
+            upstream.push(v);
+        }
+
+        let downstream = JobPromotion::find()
+            .filter(job_promotion::Column::SourceEid.eq(eid))
+            .order_by_desc(job_promotion::Column::Id)
+            .all(&self.ctx.db)
+            .await?;
+        Ok(PromotionLineage {
+            upstream,
+            downstream,
+        })
+    }
+}
+
+#[test]
+fn test_rewrite_namespace() {
+    assert_eq!(
+        rewrite_namespace("backup-staging", "staging", "production"),
+        "backup-production"
+    );
+    assert_eq!(
+        rewrite_namespace("staging.db.staging", "staging", "prod"),
+        "prod.db.prod"
+    );
+    assert_eq!(
+        rewrite_namespace("backupstaging staging2", "staging", "prod"),
+        "backupstaging staging2"
+    );
+    assert_eq!(rewrite_namespace("staging", "", "prod"), "staging");
+
+    let rewrites = vec![TagRewrite {
+        from: "team/qa".to_string(),
+        to: "team/ops".to_string(),
+    }];
+    assert_eq!(
+        rewrite_tag("team/qa/nightly", &rewrites, "staging", "prod"),
+        "team/ops/nightly"
+    );
+    assert_eq!(
+        rewrite_tag("team/qa2", &rewrites, "staging", "prod"),
+        "team/qa2"
+    );
+    assert_eq!(
+        rewrite_tag("env/staging", &rewrites, "staging", "prod"),
+        "env/prod"
+    );
+}
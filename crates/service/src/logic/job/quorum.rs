@@ -0,0 +1,220 @@
+//! quorum success criteria of the exec schedules
+//!
+//! without quorum a schedule fails if any target fails to dispatch. with quorum the failed
+//! targets are tolerated, the schedule passes once enough targets succeed and fails once
+//! too many failed to reach the quorum. the verdict is kept in the schedule history and
+//! reported to the completed callback when it is decided
+use anyhow::Result;
+use sea_orm::{ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter};
+use sea_query::Expr;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::debug;
+
+use super::JobLogic;
+use crate::{
+    entity::{job, job_schedule_history, prelude::*},
+    logic::types::{CompletedCallbackOpts, CompletedCallbackTriggerType},
+};
+
+pub const QUORUM_PENDING: &str = "pending";
+pub const QUORUM_PASSED: &str = "passed";
+pub const QUORUM_FAILED: &str = "failed";
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct QuorumOption {
+    /// number of the targets required to succeed
+    #[serde(default)]
+    pub min_success: u32,
+    /// ratio of the targets required to succeed, the larger of the two is required
+    #[serde(default)]
+    pub min_success_rate: f64,
+}
+
+impl QuorumOption {
+    pub fn check(&self) -> Result<()> {
+        if !(0.0..=1.0).contains(&self.min_success_rate) {
+            anyhow::bail!("quorum min_success_rate must be between 0 and 1");
+        }
+        if self.min_success == 0 && self.min_success_rate == 0.0 {
+            anyhow::bail!("quorum requires min_success or min_success_rate");
+        }
+        Ok(())
+    }
+
+    /// number of the targets required to succeed out of total
+    pub fn required(&self, total: u32) -> u32 {
+        let by_rate = (self.min_success_rate * total as f64).ceil() as u32;
+        self.min_success.max(by_rate)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct QuorumVerdict {
+    /// pending, passed or failed
+    pub status: String,
+    pub required: u32,
+    pub total: u32,
+    pub success: u32,
+    pub failure: u32,
+    pub reason: String,
+}
+
+impl QuorumVerdict {
+    /// passed once enough targets succeeded, failed once the rest cannot reach the quorum
+    pub fn new(required: u32, total: u32, success: u32, failure: u32) -> Self {
+        let unfinished = total.saturating_sub(success + failure);
+        let status = if success >= required {
+            QUORUM_PASSED
+        } else if success + unfinished < required {
+            QUORUM_FAILED
+        } else {
+            QUORUM_PENDING
+        };
+        Self {
+            status: status.to_string(),
+            required,
+            total,
+            success,
+            failure,
+            reason: format!(
+                "{success}/{total} targets succeeded, {failure} failed, {required} required"
+            ),
+        }
+    }
+
+    /// the verdict kept in the schedule history, none without quorum
+    pub fn from_history(
+        status: String,
+        required: u32,
+        total: u32,
+        success: u32,
+        failure: u32,
+    ) -> Option<Self> {
+        if status.is_empty() {
+            return None;
+        }
+        // the runs are still counted after the verdict is decided
+        Some(Self {
+            status,
+            ..Self::new(required, total, success, failure)
+        })
+    }
+}
+
+impl<'a> JobLogic<'a> {
+    /// count the finished run of a target, the verdict is decided by the first run reaching it
+    pub(crate) async fn count_quorum_run(
+        &self,
+        schedule_id: &str,
+        is_success: bool,
+        job_record: Option<&job::Model>,
+    ) -> Result<()> {
+        let column = if is_success {
+            job_schedule_history::Column::SuccessTotal
+        } else {
+            job_schedule_history::Column::FailureTotal
+        };
+        let ret = JobScheduleHistory::update_many()
+            .col_expr(column, Expr::col(column).add(1))
+            .filter(job_schedule_history::Column::ScheduleId.eq(schedule_id))
+            .filter(job_schedule_history::Column::QuorumStatus.ne(""))
+            .exec(&self.ctx.db)
+            .await?;
+        if ret.rows_affected == 0 {
+            return Ok(());
+        }
+
+        let Some(history) = self.get_schedule_history(schedule_id).await? else {
+            return Ok(());
+        };
+        if history.quorum_status != QUORUM_PENDING {
+            return Ok(());
+        }
+        let verdict = QuorumVerdict::new(
+            history.quorum_required,
+            history.target_total,
+            history.success_total,
+            history.failure_total,
+        );
+        if verdict.status == QUORUM_PENDING {
+            return Ok(());
+        }
+        let ret = JobScheduleHistory::update_many()
+            .set(job_schedule_history::ActiveModel {
+                quorum_status: Set(verdict.status.clone()),
+                ..Default::default()
+            })
+            .filter(job_schedule_history::Column::Id.eq(history.id))
+            .filter(job_schedule_history::Column::QuorumStatus.eq(QUORUM_PENDING))
+            .exec(&self.ctx.db)
+            .await?;
+        if ret.rows_affected == 1
+            && let Some(job_record) = job_record
+        {
+            self.quorum_callback(schedule_id, job_record, &verdict)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// report the verdict of the schedule to the completed callback of the job
+    pub(super) async fn quorum_callback(
+        &self,
+        schedule_id: &str,
+        job_record: &job::Model,
+        verdict: &QuorumVerdict,
+    ) -> Result<()> {
+        let Some(v) = job_record.completed_callback.clone() else {
+            return Ok(());
+        };
+        let completed_callback = serde_json::from_value::<CompletedCallbackOpts>(v)?;
+        if !completed_callback.enable {
+            return Ok(());
+        }
+        let is_failed = verdict.status == QUORUM_FAILED;
+        if matches!(
+            completed_callback.trigger_on,
+            CompletedCallbackTriggerType::Error
+        ) && !is_failed
+        {
+            return Ok(());
+        }
+
+        let body = json!({
+            "schedule_id": schedule_id,
+            "base_job": job_record,
+            "quorum": verdict,
+            "result_status": if is_failed { "failure" } else { "success" },
+        });
+        let response = self
+            .ctx
+            .http_client
+            .post(&completed_callback.url)
+            .headers(Self::callback_headers(completed_callback.header))
+            .json(&body)
+            .send()
+            .await?;
+        debug!("quorum callback response: {:?}", response.text().await);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_quorum_verdict() {
+    let option = QuorumOption {
+        min_success: 2,
+        min_success_rate: 0.5,
+    };
+    assert_eq!(option.required(3), 2);
+    assert_eq!(option.required(10), 5);
+    assert!(QuorumOption::default().check().is_err());
+
+    let status = |success, failure| QuorumVerdict::new(3, 5, success, failure).status;
+    assert_eq!(status(0, 0), QUORUM_PENDING);
+    assert_eq!(status(3, 1), QUORUM_PASSED);
+    assert_eq!(status(2, 2), QUORUM_PENDING);
+    assert_eq!(status(2, 3), QUORUM_FAILED);
+    // more required than the targets never passes
+    assert_eq!(QuorumVerdict::new(4, 3, 0, 0).status, QUORUM_FAILED);
+}
@@ -0,0 +1,374 @@
+use anyhow::Result;
+use chrono::{Local, TimeDelta};
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, EntityTrait, JoinType, PaginatorTrait, QueryFilter, QueryOrder,
+    QuerySelect, QueryTrait,
+};
+
+use super::{
+    JobLogic,
+    types::{DeletedResourceModel, RecycleResource},
+};
+use crate::{
+    entity::{
+        job, job_running_status, job_schedule_history, job_supervisor, job_timer, prelude::*,
+        tag_resource, team,
+    },
+    logic::types::{QuotaResource, ResourceType},
+};
+
+impl<'a> JobLogic<'a> {
+    /// soft-deleted resources, the latest deleted first
+    pub async fn query_recycle_bin(
+        &self,
+        resource: RecycleResource,
+        team_id: Option<u64>,
+        deleted_by: Option<String>,
+        page: u64,
+        page_size: u64,
+    ) -> Result<(Vec<DeletedResourceModel>, u64)> {
+        let select = match resource {
+            RecycleResource::Job => Job::find()
+                .select_only()
+                .columns([
+                    job::Column::Id,
+                    job::Column::Eid,
+                    job::Column::Name,
+                    job::Column::TeamId,
+                    job::Column::CreatedUser,
+                    job::Column::DeletedBy,
+                    job::Column::DeletedAt,
+                ])
+                .column_as(job::Column::Name, "job_name")
+                .column_as(team::Column::Name, "team_name")
+                .join_rev(
+                    JoinType::LeftJoin,
+                    Team::belongs_to(Job)
+                        .from(team::Column::Id)
+                        .to(job::Column::TeamId)
+                        .into(),
+                )
+                .filter(job::Column::IsDeleted.eq(true))
+                .apply_if(team_id, |q, v| q.filter(job::Column::TeamId.eq(v)))
+                .apply_if(deleted_by, |q, v| q.filter(job::Column::DeletedBy.eq(v)))
+                .order_by_desc(job::Column::DeletedAt)
+                .into_model::<DeletedResourceModel>(),
+            RecycleResource::Timer => JobTimer::find()
+                .select_only()
+                .columns([
+                    job_timer::Column::Id,
+                    job_timer::Column::Eid,
+                    job_timer::Column::Name,
+                    job_timer::Column::CreatedUser,
+                    job_timer::Column::DeletedBy,
+                    job_timer::Column::DeletedAt,
+                ])
+                .column_as(job::Column::Name, "job_name")
+                .column_as(job::Column::TeamId, "team_id")
+                .column_as(team::Column::Name, "team_name")
+                .join_rev(
+                    JoinType::LeftJoin,
+                    Job::belongs_to(JobTimer)
+                        .from(job::Column::Eid)
+                        .to(job_timer::Column::Eid)
+                        .into(),
+                )
+                .join_rev(
+                    JoinType::LeftJoin,
+                    Team::belongs_to(Job)
+                        .from(team::Column::Id)
+                        .to(job::Column::TeamId)
+                        .into(),
+                )
+                .filter(job_timer::Column::IsDeleted.eq(true))
+                .apply_if(team_id, |q, v| q.filter(job::Column::TeamId.eq(v)))
+                .apply_if(deleted_by, |q, v| {
+                    q.filter(job_timer::Column::DeletedBy.eq(v))
+                })
+                .order_by_desc(job_timer::Column::DeletedAt)
+                .into_model::<DeletedResourceModel>(),
+            RecycleResource::Supervisor => JobSupervisor::find()
+                .select_only()
+                .columns([
+                    job_supervisor::Column::Id,
+                    job_supervisor::Column::Eid,
+                    job_supervisor::Column::Name,
+                    job_supervisor::Column::CreatedUser,
+                    job_supervisor::Column::DeletedBy,
+                    job_supervisor::Column::DeletedAt,
+                ])
+                .column_as(job::Column::Name, "job_name")
+                .column_as(job::Column::TeamId, "team_id")
+                .column_as(team::Column::Name, "team_name")
+                .join_rev(
+                    JoinType::LeftJoin,
+                    Job::belongs_to(JobSupervisor)
+                        .from(job::Column::Eid)
+                        .to(job_supervisor::Column::Eid)
+                        .into(),
+                )
+                .join_rev(
+                    JoinType::LeftJoin,
+                    Team::belongs_to(Job)
+                        .from(team::Column::Id)
+                        .to(job::Column::TeamId)
+                        .into(),
+                )
+                .filter(job_supervisor::Column::IsDeleted.eq(true))
+                .apply_if(team_id, |q, v| q.filter(job::Column::TeamId.eq(v)))
+                .apply_if(deleted_by, |q, v| {
+                    q.filter(job_supervisor::Column::DeletedBy.eq(v))
+                })
+                .order_by_desc(job_supervisor::Column::DeletedAt)
+                .into_model::<DeletedResourceModel>(),
+        };
+
+        let paginator = select.paginate(&self.ctx.db, page_size);
+        let total = paginator.num_items().await?;
+        let list = paginator.fetch_page(page).await?;
+        Ok((list, total))
+    }
+
+    async fn is_job_deleted(&self, eid: &str) -> Result<bool> {
+        Ok(Job::find()
+            .filter(job::Column::Eid.eq(eid))
+            .filter(job::Column::IsDeleted.eq(false))
+            .one(&self.ctx.db)
+            .await?
+            .is_none())
+    }
+
+    /// restore the resource, the schedules deleted along with the job are restored too.
+    /// timers and supervisors can be restored only if their job exists
+    pub async fn restore_deleted(&self, resource: RecycleResource, id: u64) -> Result<u64> {
+        let ret = match resource {
+            RecycleResource::Job => {
+                let Some(record) = Job::find_by_id(id)
+                    .filter(job::Column::IsDeleted.eq(true))
+                    .one(&self.ctx.db)
+                    .await?
+                else {
+                    anyhow::bail!("cannot found the deleted job");
+                };
+                self.ctx
+                    .service()
+                    .team
+                    .check_quota(record.team_id, QuotaResource::Job, 1)
+                    .await?;
+
+                let deleted_at = record.deleted_at.unwrap_or_else(Local::now);
+                JobRunningStatus::update_many()
+                    .set(job_running_status::ActiveModel {
+                        is_deleted: Set(false),
+                        deleted_at: Set(None),
+                        deleted_by: Set(String::new()),
+                        ..Default::default()
+                    })
+                    .filter(job_running_status::Column::Eid.eq(&record.eid))
+                    .filter(job_running_status::Column::IsDeleted.eq(true))
+                    .filter(job_running_status::Column::DeletedAt.gte(deleted_at))
+                    .exec(&self.ctx.db)
+                    .await?;
+                JobScheduleHistory::update_many()
+                    .set(job_schedule_history::ActiveModel {
+                        is_deleted: Set(false),
+                        deleted_at: Set(None),
+                        deleted_by: Set(String::new()),
+                        ..Default::default()
+                    })
+                    .filter(job_schedule_history::Column::Eid.eq(&record.eid))
+                    .filter(job_schedule_history::Column::IsDeleted.eq(true))
+                    .filter(job_schedule_history::Column::DeletedAt.gte(deleted_at))
+                    .exec(&self.ctx.db)
+                    .await?;
+
+                Job::update_many()
+                    .set(job::ActiveModel {
+                        is_deleted: Set(false),
+                        deleted_at: Set(None),
+                        deleted_by: Set(String::new()),
+                        ..Default::default()
+                    })
+                    .filter(job::Column::Id.eq(id))
+                    .filter(job::Column::IsDeleted.eq(true))
+                    .exec(&self.ctx.db)
+                    .await?
+            }
+            RecycleResource::Timer => {
+                let Some(record) = JobTimer::find_by_id(id)
+                    .filter(job_timer::Column::IsDeleted.eq(true))
+                    .one(&self.ctx.db)
+                    .await?
+                else {
+                    anyhow::bail!("cannot found the deleted timer");
+                };
+                if self.is_job_deleted(&record.eid).await? {
+                    anyhow::bail!("the job of the timer is deleted, restore the job first");
+                }
+                self.check_job_resource_quota(QuotaResource::Timer, &Set(record.eid.clone()), None)
+                    .await?;
+
+                JobTimer::update_many()
+                    .set(job_timer::ActiveModel {
+                        is_deleted: Set(false),
+                        deleted_at: Set(None),
+                        deleted_by: Set(String::new()),
+                        ..Default::default()
+                    })
+                    .filter(job_timer::Column::Id.eq(id))
+                    .filter(job_timer::Column::IsDeleted.eq(true))
+                    .exec(&self.ctx.db)
+                    .await?
+            }
+            RecycleResource::Supervisor => {
+                let Some(record) = JobSupervisor::find_by_id(id)
+                    .filter(job_supervisor::Column::IsDeleted.eq(true))
+                    .one(&self.ctx.db)
+                    .await?
+                else {
+                    anyhow::bail!("cannot found the deleted supervisor");
+                };
+                if self.is_job_deleted(&record.eid).await? {
+                    anyhow::bail!("the job of the supervisor is deleted, restore the job first");
+                }
+                self.check_job_resource_quota(
+                    QuotaResource::Daemon,
+                    &Set(record.eid.clone()),
+                    None,
+                )
+                .await?;
+
+                JobSupervisor::update_many()
+                    .set(job_supervisor::ActiveModel {
+                        is_deleted: Set(false),
+                        deleted_at: Set(None),
+                        deleted_by: Set(String::new()),
+                        ..Default::default()
+                    })
+                    .filter(job_supervisor::Column::Id.eq(id))
+                    .filter(job_supervisor::Column::IsDeleted.eq(true))
+                    .exec(&self.ctx.db)
+                    .await?
+            }
+        };
+        Ok(ret.rows_affected)
+    }
+
+    /// permanently delete the soft-deleted resource, purging a job also purges
+    /// its deleted timers, supervisors, schedules and tags
+    pub async fn purge_deleted(&self, resource: RecycleResource, id: u64) -> Result<u64> {
+        let ret = match resource {
+            RecycleResource::Job => {
+                let Some(record) = Job::find_by_id(id)
+                    .filter(job::Column::IsDeleted.eq(true))
+                    .one(&self.ctx.db)
+                    .await?
+                else {
+                    anyhow::bail!("cannot found the deleted job");
+                };
+
+                JobTimer::delete_many()
+                    .filter(job_timer::Column::Eid.eq(&record.eid))
+                    .filter(job_timer::Column::IsDeleted.eq(true))
+                    .exec(&self.ctx.db)
+                    .await?;
+                JobSupervisor::delete_many()
+                    .filter(job_supervisor::Column::Eid.eq(&record.eid))
+                    .filter(job_supervisor::Column::IsDeleted.eq(true))
+                    .exec(&self.ctx.db)
+                    .await?;
+                JobRunningStatus::delete_many()
+                    .filter(job_running_status::Column::Eid.eq(&record.eid))
+                    .filter(job_running_status::Column::IsDeleted.eq(true))
+                    .exec(&self.ctx.db)
+                    .await?;
+                JobScheduleHistory::delete_many()
+                    .filter(job_schedule_history::Column::Eid.eq(&record.eid))
+                    .filter(job_schedule_history::Column::IsDeleted.eq(true))
+                    .exec(&self.ctx.db)
+                    .await?;
+                self.delete_job_share(&record.eid).await?;
+                self.delete_job_triggers(&record.eid).await?;
+                self.delete_job_remediations(&record.eid).await?;
+                self.delete_duration_stats(&record.eid).await?;
+                self.delete_job_sla(&record.eid).await?;
+                self.delete_daily_stats(&record.eid).await?;
+                TagResource::delete_many()
+                    .filter(tag_resource::Column::ResourceType.is_in([
+                        ResourceType::Job.to_string(),
+                        ResourceType::BundleJob.to_string(),
+                    ]))
+                    .filter(tag_resource::Column::ResourceId.eq(id))
+                    .exec(&self.ctx.db)
+                    .await?;
+
+                Job::delete_many()
+                    .filter(job::Column::Id.eq(id))
+                    .filter(job::Column::IsDeleted.eq(true))
+                    .exec(&self.ctx.db)
+                    .await?
+            }
+            RecycleResource::Timer => {
+                JobTimer::delete_many()
+                    .filter(job_timer::Column::Id.eq(id))
+                    .filter(job_timer::Column::IsDeleted.eq(true))
+                    .exec(&self.ctx.db)
+                    .await?
+            }
+            RecycleResource::Supervisor => {
+                JobSupervisor::delete_many()
+                    .filter(job_supervisor::Column::Id.eq(id))
+                    .filter(job_supervisor::Column::IsDeleted.eq(true))
+                    .exec(&self.ctx.db)
+                    .await?
+            }
+        };
+        Ok(ret.rows_affected)
+    }
+
+    /// purge the resources deleted more than days ago
+    pub async fn purge_expired_deleted(&self, days: u64) -> Result<u64> {
+        let deadline = Local::now() - TimeDelta::days(days as i64);
+        let mut n = 0;
+
+        let timer_ids: Vec<u64> = JobTimer::find()
+            .select_only()
+            .column(job_timer::Column::Id)
+            .filter(job_timer::Column::IsDeleted.eq(true))
+            .filter(job_timer::Column::DeletedAt.lt(deadline))
+            .limit(500)
+            .into_tuple()
+            .all(&self.ctx.db)
+            .await?;
+        for id in timer_ids {
+            n += self.purge_deleted(RecycleResource::Timer, id).await?;
+        }
+
+        let supervisor_ids: Vec<u64> = JobSupervisor::find()
+            .select_only()
+            .column(job_supervisor::Column::Id)
+            .filter(job_supervisor::Column::IsDeleted.eq(true))
+            .filter(job_supervisor::Column::DeletedAt.lt(deadline))
+            .limit(500)
+            .into_tuple()
+            .all(&self.ctx.db)
+            .await?;
+        for id in supervisor_ids {
+            n += self.purge_deleted(RecycleResource::Supervisor, id).await?;
+        }
+
+        let job_ids: Vec<u64> = Job::find()
+            .select_only()
+            .column(job::Column::Id)
+            .filter(job::Column::IsDeleted.eq(true))
+            .filter(job::Column::DeletedAt.lt(deadline))
+            .limit(500)
+            .into_tuple()
+            .all(&self.ctx.db)
+            .await?;
+        for id in job_ids {
+            n += self.purge_deleted(RecycleResource::Job, id).await?;
+        }
+        Ok(n)
+    }
+}
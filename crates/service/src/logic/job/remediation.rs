@@ -0,0 +1,380 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use automate::{JobAction, scheduler::types::ScheduleType};
+use chrono::Local;
+use redis::{AsyncCommands, ExistenceCheck, SetExpiry, SetOptions};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, EntityTrait, PaginatorTrait,
+    QueryFilter, QueryOrder,
+};
+use serde_json::{Value, json};
+
+use super::{
+    JobLogic,
+    types::{Alert, AlertmanagerPayload, RemediationStatus},
+};
+use crate::{
+    entity::{instance, job_remediation, job_remediation_log, prelude::*},
+    event::AuditEvent,
+    logic::{blackout::BlackoutLogic, types::UserInfo},
+};
+
+/// summary of the alerts received by the remediation
+#[derive(Debug, Default)]
+pub struct RemediationSummary {
+    pub dispatched: u64,
+    pub skipped: u64,
+    pub failed: u64,
+}
+
+/// whether the alert has all the labels of the remediation
+pub fn match_alert_labels(match_labels: Option<&Value>, alert: &Alert) -> bool {
+    let Some(labels) = match_labels.and_then(Value::as_object) else {
+        return true;
+    };
+    labels.iter().all(|(k, v)| {
+        alert
+            .labels
+            .get(k)
+            .is_some_and(|actual| v.as_str() == Some(actual.as_str()))
+    })
+}
+
+impl<'a> JobLogic<'a> {
+    pub async fn save_job_remediation(&self, model: job_remediation::ActiveModel) -> Result<u64> {
+        let model = model.save(&self.ctx.db).await?;
+        Ok(model.id.as_ref().to_owned())
+    }
+
+    pub async fn query_job_remediation(&self, eid: &str) -> Result<Vec<job_remediation::Model>> {
+        Ok(JobRemediation::find()
+            .filter(job_remediation::Column::Eid.eq(eid))
+            .order_by_asc(job_remediation::Column::Id)
+            .all(&self.ctx.db)
+            .await?)
+    }
+
+    pub async fn get_job_remediation(&self, id: u64) -> Result<Option<job_remediation::Model>> {
+        Ok(JobRemediation::find_by_id(id).one(&self.ctx.db).await?)
+    }
+
+    pub async fn delete_job_remediation(&self, id: u64) -> Result<u64> {
+        let ret = JobRemediation::delete_by_id(id).exec(&self.ctx.db).await?;
+        JobRemediationLog::delete_many()
+            .filter(job_remediation_log::Column::RemediationId.eq(id))
+            .exec(&self.ctx.db)
+            .await?;
+        Ok(ret.rows_affected)
+    }
+
+    /// remove the remediations of the job, called when the job is purged
+    pub(super) async fn delete_job_remediations(&self, eid: &str) -> Result<u64> {
+        let mut n = 0;
+        for v in self.query_job_remediation(eid).await? {
+            n += self.delete_job_remediation(v.id).await?;
+        }
+        Ok(n)
+    }
+
+    pub async fn query_job_remediation_log(
+        &self,
+        remediation_id: u64,
+        page: u64,
+        page_size: u64,
+    ) -> Result<(Vec<job_remediation_log::Model>, u64)> {
+        let select = JobRemediationLog::find()
+            .filter(job_remediation_log::Column::RemediationId.eq(remediation_id))
+            .order_by_desc(job_remediation_log::Column::Id);
+        let total = select.clone().count(&self.ctx.db).await?;
+        let list = select
+            .paginate(&self.ctx.db, page_size)
+            .fetch_page(page)
+            .await?;
+        Ok((list, total))
+    }
+
+    async fn save_job_remediation_log(
+        &self,
+        remediation: &job_remediation::Model,
+        alert: &Alert,
+        instance_ids: &[String],
+        status: RemediationStatus,
+        reason: String,
+        schedule_id: String,
+    ) -> Result<()> {
+        job_remediation_log::ActiveModel {
+            remediation_id: Set(remediation.id),
+            eid: Set(remediation.eid.clone()),
+            alertname: Set(alert.alertname().to_string()),
+            fingerprint: Set(alert.fingerprint.clone()),
+            instance_ids: Set(Some(json!(instance_ids))),
+            status: Set(status.as_str().to_string()),
+            reason: Set(reason.chars().take(500).collect()),
+            schedule_id: Set(schedule_id.clone()),
+            ..Default::default()
+        }
+        .insert(&self.ctx.db)
+        .await?;
+        self.ctx
+            .bus
+            .emit(
+                self.ctx,
+                AuditEvent {
+                    action: "remediation",
+                    eid: remediation.eid.clone(),
+                    source: alert.alertname().to_string(),
+                    status: status.as_str().to_string(),
+                    reason,
+                    schedule_id,
+                },
+            )
+            .await;
+        Ok(())
+    }
+
+    /// instances of the alert, resolved by the host of the target label,
+    /// the bound instances are used if the alert has no such label
+    async fn resolve_remediation_target(
+        &self,
+        remediation: &job_remediation::Model,
+        alert: &Alert,
+    ) -> Result<Vec<String>> {
+        if let Some(host) = alert.label_host(&remediation.target_label) {
+            return Ok(Instance::find()
+                .filter(
+                    Condition::any()
+                        .add(instance::Column::Ip.eq(host))
+                        .add(instance::Column::InstanceId.eq(host)),
+                )
+                .all(&self.ctx.db)
+                .await?
+                .into_iter()
+                .map(|v| v.instance_id)
+                .collect());
+        }
+
+        Ok(match remediation.instance_ids.clone() {
+            Some(v) => serde_json::from_value(v)?,
+            None => vec![],
+        })
+    }
+
+    fn remediation_hourly_key(remediation: &job_remediation::Model) -> String {
+        format!(
+            "jiascheduler:remediation:runs:{}:{}",
+            remediation.id,
+            Local::now().timestamp() / 3600
+        )
+    }
+
+    /// safeguards of the auto-runs, returns the instances out of the cooldown,
+    /// or the reason why the run is skipped
+    async fn acquire_remediation_run(
+        &self,
+        remediation: &job_remediation::Model,
+        instance_ids: Vec<String>,
+    ) -> Result<std::result::Result<Vec<String>, String>> {
+        let mut conn = self.ctx.redis().get_multiplexed_async_connection().await?;
+        let hourly_key = Self::remediation_hourly_key(remediation);
+
+        if remediation.max_runs_per_hour > 0 {
+            let runs: Option<u32> = conn.get(&hourly_key).await?;
+            if runs.unwrap_or_default() >= remediation.max_runs_per_hour {
+                return Ok(Err("max auto-runs per hour exceeded".to_string()));
+            }
+        }
+
+        let mut acquired = Vec::new();
+        for instance_id in instance_ids {
+            if remediation.cooldown_secs == 0 {
+                acquired.push(instance_id);
+                continue;
+            }
+            let key = format!(
+                "jiascheduler:remediation:cooldown:{}:{instance_id}",
+                remediation.id
+            );
+            let ok: Option<String> = conn
+                .set_options(
+                    &key,
+                    1,
+                    SetOptions::default()
+                        .conditional_set(ExistenceCheck::NX)
+                        .with_expiration(SetExpiry::EX(remediation.cooldown_secs as u64)),
+                )
+                .await?;
+            if ok.is_some() {
+                acquired.push(instance_id);
+            }
+        }
+        if acquired.is_empty() {
+            return Ok(Err("cooling down".to_string()));
+        }
+
+        let _: u32 = conn.incr(&hourly_key, 1).await?;
+        let _: () = conn.expire(&hourly_key, 3600).await?;
+        Ok(Ok(acquired))
+    }
+
+    /// dispatch the remediation jobs of the firing alerts, the resolved alerts are ignored,
+    /// every matched alert is audited
+    pub async fn receive_alerts(
+        &self,
+        comet_secret: String,
+        user_info: &UserInfo,
+        payload: AlertmanagerPayload,
+    ) -> Result<RemediationSummary> {
+        let mut summary = RemediationSummary::default();
+
+        for alert in payload.alerts.iter().filter(|v| v.is_firing()) {
+            let remediations = JobRemediation::find()
+                .filter(job_remediation::Column::Alertname.eq(alert.alertname()))
+                .filter(job_remediation::Column::IsEnabled.eq(true))
+                .all(&self.ctx.db)
+                .await?;
+
+            for remediation in remediations
+                .iter()
+                .filter(|v| match_alert_labels(v.match_labels.as_ref(), alert))
+            {
+                let (status, reason, instance_ids, schedule_id) = match self
+                    .remediate(comet_secret.clone(), user_info, remediation, alert)
+                    .await
+                {
+                    Ok((Ok(schedule_id), instance_ids)) => {
+                        summary.dispatched += 1;
+                        (
+                            RemediationStatus::Dispatched,
+                            String::new(),
+                            instance_ids,
+                            schedule_id,
+                        )
+                    }
+                    Ok((Err(reason), instance_ids)) => {
+                        summary.skipped += 1;
+                        (
+                            RemediationStatus::Skipped,
+                            reason,
+                            instance_ids,
+                            String::new(),
+                        )
+                    }
+                    Err(e) => {
+                        summary.failed += 1;
+                        (
+                            RemediationStatus::Failed,
+                            e.to_string(),
+                            vec![],
+                            String::new(),
+                        )
+                    }
+                };
+
+                self.save_job_remediation_log(
+                    remediation,
+                    alert,
+                    &instance_ids,
+                    status,
+                    reason,
+                    schedule_id,
+                )
+                .await?;
+            }
+        }
+
+        Ok(summary)
+    }
+
+    async fn remediate(
+        &self,
+        comet_secret: String,
+        user_info: &UserInfo,
+        remediation: &job_remediation::Model,
+        alert: &Alert,
+    ) -> Result<(std::result::Result<String, String>, Vec<String>)> {
+        if !self
+            .can_dispatch_job(user_info, None, None, &remediation.eid)
+            .await?
+        {
+            anyhow::bail!("no permission to dispatch job {}", remediation.eid);
+        }
+
+        let instance_ids = self.resolve_remediation_target(remediation, alert).await?;
+        if instance_ids.is_empty() {
+            return Ok((Err("no instance matched the alert".to_string()), vec![]));
+        }
+
+        let instance_ids = match self
+            .acquire_remediation_run(remediation, instance_ids)
+            .await?
+        {
+            Ok(v) => v,
+            Err(reason) => return Ok((Err(reason), vec![])),
+        };
+
+        let Some(job_record) = self.get_job_by_eid(&remediation.eid).await? else {
+            anyhow::bail!("cannot found job {}", remediation.eid);
+        };
+        BlackoutLogic::new(self.ctx)
+            .check_manual_dispatch(Some(job_record.team_id).filter(|v| *v > 0), None)
+            .await?;
+
+        let history_id = self
+            .dispatch_job(
+                comet_secret,
+                instance_ids.clone(),
+                remediation.eid.clone(),
+                false,
+                format!(
+                    "{}-{}",
+                    remediation.name,
+                    Local::now().format("%Y%m%d%H%M%S")
+                ),
+                ScheduleType::Once,
+                JobAction::Exec,
+                None,
+                None,
+                Some(alert.to_args()),
+                HashMap::new(),
+                user_info.username.clone(),
+                None,
+            )
+            .await?;
+
+        let schedule_id = JobScheduleHistory::find_by_id(history_id)
+            .one(&self.ctx.db)
+            .await?
+            .map(|v| v.schedule_id)
+            .unwrap_or_default();
+        Ok((Ok(schedule_id), instance_ids))
+    }
+}
+
+#[test]
+fn test_match_alert_labels() {
+    let alert = Alert {
+        status: "firing".to_string(),
+        labels: HashMap::from([
+            ("alertname".to_string(), "DiskFull".to_string()),
+            ("severity".to_string(), "critical".to_string()),
+            ("instance".to_string(), "10.0.0.1:9100".to_string()),
+        ]),
+        ..Default::default()
+    };
+
+    assert!(match_alert_labels(None, &alert));
+    assert!(match_alert_labels(
+        Some(&json!({"severity": "critical"})),
+        &alert
+    ));
+    assert!(!match_alert_labels(
+        Some(&json!({"severity": "warning"})),
+        &alert
+    ));
+    assert!(!match_alert_labels(Some(&json!({"job": "node"})), &alert));
+
+    assert_eq!(alert.alertname(), "DiskFull");
+    assert_eq!(alert.label_host("instance"), Some("10.0.0.1"));
+    assert_eq!(alert.label_host("job"), None);
+}
@@ -1,16 +1,27 @@
-use std::{num::NonZeroU64, str::FromStr, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    num::NonZeroU64,
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
 
 use anyhow::{Result, anyhow};
 
 use automate::{
-    JobAction,
-    bridge::msg::{BundleOutputParams, TimerExpr, UpdateJobParams},
-    scheduler::types::{BundleScript, RunStatus, ScheduleStatus, ScheduleType, UploadFile},
+    JobAction, LinkPair,
+    bridge::msg::{
+        BundleOutputParams, PruneScheduleParams, ReconcileDispatchParams, TimerExpr,
+        UpdateJobParams,
+    },
+    scheduler::types::{
+        BundleOption, BundleScript, HttpCheck, OutputLimit, RunStatus, ScheduleStatus,
+        ScheduleType, SupervisorOption, TIMEOUT_NOTIFY, TimeoutAction, UploadFile,
+    },
 };
 
-use chrono::Local;
+use chrono::{Local, TimeDelta};
 use entity::job_schedule;
-use evalexpr::eval_boolean;
 
 use handlebars::Handlebars;
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
@@ -19,7 +30,7 @@ use sea_orm::{
     QueryFilter, QueryOrder, QuerySelect, QueryTrait, Set,
 };
 
-use sea_query::{OnConflict, Query};
+use sea_query::Query;
 
 use serde_json::{Value, json};
 use tokio::fs;
@@ -31,9 +42,13 @@ use crate::{
         self, executor, instance, job, job_exec_history, job_running_status, job_schedule_history,
         prelude::*, tag_resource, team,
     },
+    event::JobEvent,
     logic::{
+        blackout::BlackoutLogic,
+        credential::CredentialLogic,
         executor::ExecutorLogic,
         job::types::DispatchResult,
+        tag::{JOB_RESOURCE_TYPES, tag_scope_expr},
         types::{CompletedCallbackOpts, CompletedCallbackTriggerType, CustomTimerExpr, UserInfo},
     },
 };
@@ -41,8 +56,17 @@ use crate::{
 use utils::file_name;
 
 use super::{
-    JobLogic,
-    types::{self, BundleScriptRecord, BundleScriptResult, DispatchData, DispatchTarget},
+    CanaryOption, JobLogic, QUORUM_FAILED, QuorumOption, QuorumVerdict, RunningStatusUpsert,
+    StatusWrite,
+    canary::{self, DispatchFuture},
+    cond_expr,
+    dedup::{self, DedupClaim},
+    lineage::{RUN_KIND_RERUN, RUN_KIND_RETRY, link_run},
+    log_sink,
+    types::{
+        self, BundleScriptRecord, BundleScriptResult, DispatchData, DispatchTarget,
+        ExecResultStatus, InstanceOverride, OutputParser, parse_output,
+    },
 };
 
 impl<'a> JobLogic<'a> {
@@ -53,16 +77,12 @@ impl<'a> JobLogic<'a> {
         record: Vec<BundleScriptRecord>,
         output: Vec<BundleOutputParams>,
     ) -> Vec<BundleScriptResult> {
-        record
+        let mut ret: Vec<BundleScriptResult> = record
             .iter()
             .map(|v| {
                 for val in output.iter() {
                     if v.eid == val.eid {
-                        let (result, eval_err) = match eval_boolean(&format!(
-                            "$v={}; {}",
-                            val.stdout.clone().unwrap_or_default().clone(),
-                            v.cond_expr.clone(),
-                        )) {
+                        let (result, eval_err) = match cond_expr::eval(&v.cond_expr, val) {
                             Ok(v) => (v, None),
                             Err(e) => (false, Some(e.to_string())),
                         };
@@ -77,6 +97,7 @@ impl<'a> JobLogic<'a> {
                             stderr: val.stderr.clone(),
                             eval_err,
                             result,
+                            seq: val.seq,
                         };
                     }
                 }
@@ -87,10 +108,42 @@ impl<'a> JobLogic<'a> {
                     ..Default::default()
                 };
             })
-            .collect()
+            .collect();
+        // in the order the scripts were run, the skipped ones are put last
+        ret.sort_by_key(|v| v.seq.unwrap_or(usize::MAX));
+        ret
     }
 
-    pub async fn completed_callback(&self, params: UpdateJobParams) -> Result<()> {
+    /// the headers of the completed callback, the invalid ones are skipped
+    pub(crate) fn callback_headers(kv: Option<HashMap<String, String>>) -> HeaderMap {
+        let mut header = HeaderMap::new();
+        kv.unwrap_or_default().into_iter().for_each(|(k, v)| {
+            let key = match HeaderName::from_str(&k) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!("failed to parse header key: {}", e);
+                    return;
+                }
+            };
+
+            let value = match HeaderValue::from_str(&v) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!("failed to parse header value: {}", e);
+                    return;
+                }
+            };
+            header.insert(key, value);
+        });
+        header
+    }
+
+    pub async fn completed_callback(
+        &self,
+        params: UpdateJobParams,
+        result_status: ExecResultStatus,
+        parsed_result: Option<&Value>,
+    ) -> Result<()> {
         let (completed_callback, job_record) = match JobScheduleHistory::find()
             .filter(job_schedule_history::Column::ScheduleId.eq(&params.schedule_id))
             .one(&self.ctx.db)
@@ -117,41 +170,33 @@ impl<'a> JobLogic<'a> {
             return Ok(());
         }
 
-        if params.run_status != Some(RunStatus::Stop) {
+        // a flapping supervisor is reported as a failure to raise an alert
+        if params.run_status != Some(RunStatus::Stop)
+            && params.schedule_status != Some(ScheduleStatus::Flapping)
+        {
             return Ok(());
         }
 
         let http_client = self.ctx.http_client.clone();
         let api_url = format!("{}", completed_callback.url);
 
-        if match completed_callback.trigger_on {
-            CompletedCallbackTriggerType::All => true,
-            CompletedCallbackTriggerType::Error => params.exit_code != Some(0),
-        } {
-            let mut header = HeaderMap::new();
-
-            if let Some(kv) = completed_callback.header {
-                kv.into_iter().for_each(|(k, v)| {
-                    let key = match HeaderName::from_str(&k) {
-                        Ok(v) => v,
-                        Err(e) => {
-                            error!("failed to parse header key: {}", e);
-                            return;
-                        }
-                    };
+        // the timed out run is notified whatever the callback is triggered on
+        let notify_timeout = params
+            .timeout_action
+            .as_deref()
+            .is_some_and(|v| v.split(',').any(|v| v == TIMEOUT_NOTIFY));
 
-                    let value = match HeaderValue::from_str(&v) {
-                        Ok(v) => v,
-                        Err(e) => {
-                            error!("failed to parse header value: {}", e);
-                            return;
-                        }
-                    };
-                    header.insert(key, value);
-                });
+        if notify_timeout
+            || match completed_callback.trigger_on {
+                CompletedCallbackTriggerType::All => true,
+                CompletedCallbackTriggerType::Error => result_status == ExecResultStatus::Failure,
             }
+        {
+            let header = Self::callback_headers(completed_callback.header);
             let mut body = serde_json::to_value(&params)?;
             body["base_job"] = json!(job_record);
+            body["parsed_result"] = json!(parsed_result);
+            body["result_status"] = json!(result_status.as_str());
 
             let response = http_client
                 .post(api_url)
@@ -165,152 +210,218 @@ impl<'a> JobLogic<'a> {
         Ok(())
     }
 
-    pub async fn update_job_status(&self, params: UpdateJobParams) -> Result<u64> {
-        let mut update_values = vec![
-            (
-                job_running_status::Column::ScheduleId,
-                params.schedule_id.clone().into(),
-            ),
-            (
-                job_running_status::Column::UpdatedUser,
-                params.created_user.clone().into(),
-            ),
-            (job_running_status::Column::IsDeleted, false.into()),
-        ];
+    /// the running status and the exec history are written by the status writer,
+    /// they may be visible after flush_interval_ms
+    pub async fn update_job_status(&self, params: UpdateJobParams) -> Result<()> {
+        if params.resource_usage.is_some() {
+            self.save_supervisor_metric(&params).await?;
+            return Ok(());
+        }
 
-        params.start_time.clone().inspect(|v| {
-            update_values.push((job_running_status::Column::StartTime, (*v).into()));
-            update_values.push((job_running_status::Column::EndTime, params.end_time.into()));
-        });
+        let job_record = match params.run_status {
+            Some(RunStatus::Stop) => match self.get_schedule_history(&params.schedule_id).await? {
+                Some(job_schedule_history::Model {
+                    snapshot_data: Some(v),
+                    ..
+                }) => Some(serde_json::from_value::<job::Model>(v)?),
+                _ => None,
+            },
+            _ => None,
+        };
 
-        // params.prev_time.clone().inspect(|v| {
-        //     update_values.push((job_running_status::Column::PrevTime, (*v).into()));
-        //     update_values.push((
-        //         job_running_status::Column::NextTime,
-        //         params.next_time.into(),
-        //     ));
-        // });
-
-        params.schedule_status.clone().inspect(|v| {
-            if *v == ScheduleStatus::Unscheduled {
-                update_values.push((
-                    job_running_status::Column::NextTime,
-                    params.next_time.into(),
-                ));
+        let result_status = match params.exit_code {
+            Some(exit_code) => {
+                let mapping: HashMap<i32, ExecResultStatus> = match job_record
+                    .as_ref()
+                    .and_then(|v| v.exit_code_mapping.clone())
+                {
+                    Some(v) => serde_json::from_value(v)?,
+                    None => HashMap::new(),
+                };
+                Some(ExecResultStatus::classify(&mapping, exit_code))
             }
-        });
+            None => None,
+        };
+
+        let job_type = if params.base_job.bundle_script.is_some() {
+            "bundle"
+        } else if params.base_job.http_check.is_some() {
+            "http"
+        } else {
+            "default"
+        };
 
-        if let Some(run_status) = params.run_status.clone() {
-            update_values.push((
-                job_running_status::Column::RunStatus,
-                run_status.to_string().into(),
-            ))
+        let mut model = job_running_status::ActiveModel {
+            schedule_type: params
+                .schedule_type
+                .clone()
+                .map_or(NotSet, |v| Set(v.to_string())),
+            eid: Set(params.base_job.eid.clone()),
+            instance_id: Set(params.instance_id.clone()),
+            schedule_id: Set(params.schedule_id.clone()),
+            start_time: Set(params.start_time.map(|v| v.with_timezone(&Local))),
+            job_type: Set(job_type.to_string()),
+            prev_time: Set(params.prev_time.map(|v| v.with_timezone(&Local))),
+            updated_user: Set(params.created_user.clone()),
+            is_deleted: Set(false),
+            ..Default::default()
+        };
+        // the existing row is updated with the values of the model
+        let mut update_columns = vec![
+            job_running_status::Column::ScheduleId,
+            job_running_status::Column::UpdatedUser,
+            job_running_status::Column::IsDeleted,
+        ];
+
+        if params.start_time.is_some() {
+            model.end_time = Set(params.end_time.map(|v| v.with_timezone(&Local)));
+            update_columns.extend([
+                job_running_status::Column::StartTime,
+                job_running_status::Column::EndTime,
+            ]);
+        }
+
+        if params.next_time.is_some() || params.schedule_status == Some(ScheduleStatus::Unscheduled)
+        {
+            model.next_time = Set(params.next_time.map(|v| v.with_timezone(&Local)));
+            update_columns.push(job_running_status::Column::NextTime);
+        }
+
+        if let Some(ref run_status) = params.run_status {
+            model.run_status = Set(run_status.to_string());
+            update_columns.push(job_running_status::Column::RunStatus);
         }
 
         if let Some(ref exit_status) = params.exit_status {
-            update_values.push((job_running_status::Column::ExitStatus, exit_status.into()))
+            model.exit_status = Set(exit_status.to_string());
+            update_columns.push(job_running_status::Column::ExitStatus);
         }
 
         if let Some(exit_code) = params.exit_code {
-            update_values.push((job_running_status::Column::ExitCode, exit_code.into()))
+            model.exit_code = Set(exit_code);
+            update_columns.push(job_running_status::Column::ExitCode);
         }
 
-        if let Some(schedule_status) = params.schedule_status.clone() {
-            update_values.push((
-                job_running_status::Column::ScheduleStatus,
-                schedule_status.to_string().into(),
-            ))
+        if let Some(result_status) = result_status {
+            model.result_status = Set(result_status.as_str().to_string());
+            update_columns.push(job_running_status::Column::ResultStatus);
         }
-        if let Some(prev_time) = params.prev_time {
-            update_values.push((job_running_status::Column::PrevTime, prev_time.into()))
+
+        if let Some(ref schedule_status) = params.schedule_status {
+            model.schedule_status = Set(schedule_status.to_string());
+            update_columns.push(job_running_status::Column::ScheduleStatus);
         }
 
-        if let Some(next_time) = params.next_time {
-            update_values.push((job_running_status::Column::NextTime, next_time.into()))
+        if params.prev_time.is_some() {
+            update_columns.push(job_running_status::Column::PrevTime);
         }
 
-        let schedule_type = params
-            .schedule_type
-            .clone()
-            .map_or_else(|| NotSet, |v| Set(v.to_string()));
-        let run_status = params
-            .run_status
-            .clone()
-            .map_or_else(|| NotSet, |v| Set(v.to_string()));
-        let schedule_status = params
-            .schedule_status
-            .clone()
-            .map_or_else(|| NotSet, |v| Set(v.to_string()));
+        self.write_status(StatusWrite::RunningStatus(RunningStatusUpsert {
+            model,
+            update_columns,
+        }))
+        .await?;
 
-        let job_type = if params.base_job.bundle_script.is_some() {
-            "bundle"
-        } else {
-            "default"
-        };
+        let event_params = Arc::new(params.clone());
+        self.ctx
+            .bus
+            .emit(
+                self.ctx,
+                JobEvent::StatusChanged {
+                    params: event_params.clone(),
+                    result_status,
+                },
+            )
+            .await;
 
-        let active_model = JobRunningStatus::insert(job_running_status::ActiveModel {
-            schedule_type,
-            eid: Set(params.base_job.eid.clone()),
-            instance_id: Set(params.instance_id.clone()),
-            schedule_id: Set(params.schedule_id.clone()),
-            schedule_status,
-            run_status,
-            start_time: Set(params.start_time.map(|v| v.with_timezone(&Local))),
-            job_type: Set(job_type.to_string()),
-            prev_time: Set(params.prev_time.map(|v| v.with_timezone(&Local))),
-            updated_user: Set(params.created_user.clone()),
-            ..Default::default()
-        })
-        .on_conflict(
-            OnConflict::columns([
-                job_running_status::Column::Eid,
-                job_running_status::Column::ScheduleType,
-                job_running_status::Column::InstanceId,
-            ])
-            .values(update_values)
-            .to_owned(),
-        );
-
-        let ret = active_model.exec(&self.ctx.db).await?;
+        if params.schedule_status == Some(ScheduleStatus::Flapping) {
+            error!(
+                "supervised job {} on {} is flapping, the agent stopped restarting it",
+                params.base_job.eid, params.bind_ip
+            );
+            self.ctx
+                .bus
+                .emit(
+                    self.ctx,
+                    JobEvent::Flapping {
+                        params: event_params.clone(),
+                    },
+                )
+                .await;
+        }
 
         match params.run_status {
             Some(RunStatus::Stop) => {
-                if let Err(e) = self.completed_callback(params.clone()).await {
-                    error!("failed to send callback request: {}", e);
+                // the update may be replayed from the spool of the agent after it was received,
+                // the first one may still be buffered by the status writer
+                if self
+                    .ctx
+                    .status_writer
+                    .as_ref()
+                    .is_some_and(|v| v.is_buffered(&params.run_id, &params.instance_id))
+                {
+                    debug!("skip buffered exec history of {}", params.schedule_id);
+                    return Ok(());
                 }
-                let (bundle_script_result, job_type) = if params.bundle_output.is_some() {
-                    let schedule_record = self
-                        .get_schedule_history(&params.schedule_id)
+                if let Some(start_time) = params.start_time
+                    && let Some(v) = JobExecHistory::find()
+                        .filter(job_exec_history::Column::ScheduleId.eq(&params.schedule_id))
+                        .filter(job_exec_history::Column::InstanceId.eq(&params.instance_id))
+                        // start_time is stored without the fractional seconds
+                        .filter(job_exec_history::Column::StartTime.between(
+                            (start_time - TimeDelta::seconds(1)).with_timezone(&Local),
+                            (start_time + TimeDelta::seconds(1)).with_timezone(&Local),
+                        ))
+                        .one(&self.ctx.db)
                         .await?
-                        .ok_or(anyhow::format_err!(
-                            "cannot get schedule record {}",
-                            params.schedule_id
-                        ))?;
-                    let job_record: job::Model = serde_json::from_value(
-                        schedule_record
-                            .snapshot_data
-                            .ok_or(anyhow::format_err!("cannot get snapshot_data"))?,
-                    )?;
+                {
+                    debug!("skip replayed exec history of {}", v.schedule_id);
+                    return Ok(());
+                }
+
+                let result_status = result_status.unwrap_or(ExecResultStatus::Success);
+                let output_parsers: Vec<OutputParser> =
+                    match job_record.as_ref().and_then(|v| v.output_parsers.clone()) {
+                        Some(v) => serde_json::from_value(v)?,
+                        None => vec![],
+                    };
+                let parsed_result = parse_output(
+                    &output_parsers,
+                    params.stdout.as_deref().unwrap_or_default(),
+                );
+
+                let (bundle_script_result, job_type) = if params.bundle_output.is_some() {
+                    let job_record = job_record.as_ref().ok_or(anyhow::format_err!(
+                        "cannot get snapshot_data of schedule {}",
+                        params.schedule_id
+                    ))?;
 
                     let bundle_script: Vec<BundleScriptRecord> = serde_json::from_value(
                         job_record
                             .bundle_script
+                            .clone()
                             .ok_or(anyhow::format_err!("cannot get bundle_sciprt"))?,
                     )?;
                     let val = serde_json::to_value(
                         &self.eval(bundle_script, params.bundle_output.unwrap()),
                     )?;
-                    (Set(Some(val)), Set("bundle".to_string()))
+                    (Some(val), "bundle")
                 } else {
-                    (NotSet, Set("default".to_string()))
+                    (None, job_type)
                 };
 
                 let output = params.stdout.unwrap_or_default();
                 let output = params
                     .stderr
                     .map_or(output.clone(), |v| format!("{v}\n{output}"));
-
-                let ret = JobExecHistory::insert(entity::job_exec_history::ActiveModel {
+                // the full output is shipped to the log sinks by the subscriber of the bus
+                let trimmed =
+                    log_sink::trim_bytes(&self.ctx.conf.log_sinks, &params.bind_namespace)
+                        .and_then(|v| log_sink::trim_output(&output, v));
+                let output_truncated = params.output_truncated || trimmed.is_some();
+                let output = trimmed.unwrap_or(output);
+
+                let model = entity::job_exec_history::ActiveModel {
                     schedule_id: Set(params.schedule_id),
                     instance_id: Set(params.instance_id),
                     exit_status: Set(params.exit_status.clone().unwrap_or_default()),
@@ -320,17 +431,51 @@ impl<'a> JobLogic<'a> {
                     eid: Set(params.base_job.eid),
                     start_time: Set(params.start_time.map(|v| v.with_timezone(&Local))),
                     end_time: Set(params.end_time.map(|v| v.with_timezone(&Local))),
-                    bundle_script_result,
+                    bundle_script_result: Set(bundle_script_result),
+                    parsed_result: Set(parsed_result.clone()),
+                    result_status: Set(result_status.as_str().to_string()),
                     created_user: Set(params.created_user),
-                    job_type,
+                    job_type: Set(job_type.to_string()),
+                    rerun_of: Set(params
+                        .fields
+                        .as_ref()
+                        .and_then(|v| v["rerun_of"].as_u64())
+                        .unwrap_or_default()),
+                    parent_run_id: Set(params
+                        .fields
+                        .as_ref()
+                        .and_then(|v| v["parent_run_id"].as_str())
+                        .unwrap_or_default()
+                        .to_string()),
+                    run_kind: Set(params
+                        .fields
+                        .as_ref()
+                        .and_then(|v| v["run_kind"].as_str())
+                        .unwrap_or_default()
+                        .to_string()),
+                    // always set, the batched inserts require the same columns
+                    exec_context: Set(params.exec_context.map(serde_json::to_value).transpose()?),
+                    timeout_action: Set(params.timeout_action.clone().unwrap_or_default()),
+                    output_truncated: Set(output_truncated),
+                    output_url: Set(params.output_url.clone().unwrap_or_default()),
                     ..Default::default()
-                })
-                .exec(&self.ctx.db)
-                .await?;
-
-                Ok(ret.last_insert_id)
+                };
+                self.write_status(StatusWrite::ExecHistory(model)).await?;
+                self.ctx
+                    .bus
+                    .emit(
+                        self.ctx,
+                        JobEvent::RunCompleted {
+                            params: event_params,
+                            result_status,
+                            parsed_result,
+                            job_record: job_record.map(Arc::new),
+                        },
+                    )
+                    .await;
+                Ok(())
             }
-            _ => Ok(ret.last_insert_id),
+            _ => Ok(()),
         }
     }
 
@@ -378,7 +523,7 @@ impl<'a> JobLogic<'a> {
         Ok(val)
     }
 
-    fn get_job_actual_args(
+    pub(super) fn get_job_actual_args(
         job_record: &job::Model,
         actual_args: Option<serde_json::Value>,
     ) -> Result<Option<serde_json::Value>> {
@@ -409,6 +554,86 @@ impl<'a> JobLogic<'a> {
         Ok(Some(ret))
     }
 
+    /// the upload file is sent by the hash of the content instead of inline
+    pub async fn get_upload_file(path: &str) -> Result<UploadFile> {
+        let (sha256, size) = automate::artifact::file_sha256(path).await?;
+        Ok(UploadFile {
+            filename: file_name!(path.to_string()),
+            data: None,
+            sha256: Some(sha256),
+            size,
+        })
+    }
+
+    /// push the upload file to the comet unless the comet already has it,
+    /// the agents pull it from the comet by chunks
+    pub async fn push_artifact(
+        http_client: &reqwest::Client,
+        comet: &LinkPair,
+        path: &str,
+        file: &UploadFile,
+    ) -> Result<()> {
+        let Some(sha256) = file.sha256.as_deref() else {
+            return Ok(());
+        };
+        let url = comet.http_url(&format!("/artifact/{sha256}"));
+        let ret: Value = http_client.get(&url).send().await?.json().await?;
+        if ret["code"] == 20000 && ret["data"].as_u64() == Some(file.size) {
+            return Ok(());
+        }
+
+        let data = fs::read(path).await?;
+        let ret: Value = http_client
+            .post(url)
+            .body(data)
+            .send()
+            .await?
+            .json()
+            .await?;
+        if ret["code"] != 20000 {
+            anyhow::bail!(
+                "failed push artifact to {}, {}",
+                comet.comet_addr,
+                ret["msg"]
+            );
+        }
+        Ok(())
+    }
+
+    /// ask comet whether the agent has acked the dispatch, the response of /dispatch
+    /// may be lost after the job was started, so that it must not be dispatched again
+    pub async fn is_dispatch_acked(
+        http_client: &reqwest::Client,
+        api_url: String,
+        body: &automate::DispatchJobRequest,
+    ) -> bool {
+        let req = automate::ReconcileDispatchRequest {
+            agent_ip: body.agent_ip.clone(),
+            mac_addr: body.mac_addr.clone(),
+            params: ReconcileDispatchParams {
+                schedule_id: body.dispatch_params.schedule_id.clone(),
+                dispatch_id: Some(body.dispatch_params.dispatch_id.clone()),
+            },
+        };
+        let ret = match http_client.post(api_url).json(&req).send().await {
+            Ok(v) => v.json::<Value>().await,
+            Err(e) => Err(e),
+        };
+        match ret {
+            Ok(v) if v["code"] == 20000 => v["data"]
+                .as_array()
+                .is_some_and(|list| list.iter().any(|v| v["status"] == "ack")),
+            Ok(v) => {
+                error!("failed to reconcile dispatch, {}", v["msg"]);
+                false
+            }
+            Err(e) => {
+                error!("failed to reconcile dispatch, {e}");
+                false
+            }
+        }
+    }
+
     pub async fn dispatch_job(
         &self,
         secret: String,
@@ -421,7 +646,9 @@ impl<'a> JobLogic<'a> {
         timer_expr: Option<CustomTimerExpr>,
         restart_interval: Option<Duration>,
         actual_args: Option<serde_json::Value>,
+        overrides: HashMap<String, InstanceOverride>,
         created_user: String,
+        canary: Option<CanaryOption>,
     ) -> Result<u64> {
         let job_record = Job::find()
             .filter(job::Column::Eid.eq(eid.clone()))
@@ -430,21 +657,57 @@ impl<'a> JobLogic<'a> {
             .await?
             .ok_or(anyhow!("cannot found job {}", eid))?;
 
-        self.schedule_job(
-            secret,
-            instance_ids,
-            &job_record,
-            is_sync,
-            schedule_name,
-            schedule_type,
-            action,
-            timer_expr,
-            restart_interval,
-            actual_args,
-            created_user,
-            None,
-        )
-        .await
+        // the same dispatch within the dedup window returns the schedule id of the first one
+        let dedup_key = if job_record.dedup_window_secs > 0 {
+            let hash = dedup::dispatch_hash(
+                &eid,
+                &schedule_type,
+                &action,
+                &instance_ids,
+                actual_args.as_ref(),
+                &overrides,
+            )?;
+            match self
+                .claim_dispatch_dedup(&eid, &hash, job_record.dedup_window_secs)
+                .await?
+            {
+                DedupClaim::Acquired(key) => Some(key),
+                DedupClaim::Duplicate(schedule_id) => {
+                    debug!("dispatch of job {eid} coalesced into schedule {schedule_id}");
+                    return Ok(schedule_id);
+                }
+            }
+        } else {
+            None
+        };
+
+        let ret = self
+            .schedule_job(
+                secret,
+                instance_ids,
+                &job_record,
+                is_sync,
+                schedule_name,
+                schedule_type,
+                action,
+                timer_expr,
+                restart_interval,
+                actual_args,
+                overrides,
+                created_user,
+                None,
+                canary,
+            )
+            .await;
+
+        if let Some(key) = dedup_key
+            && let Err(e) = self
+                .settle_dispatch_dedup(&key, ret.as_ref().ok().copied())
+                .await
+        {
+            error!("failed to settle the dispatch dedup of job {eid} - {e}");
+        }
+        ret
     }
 
     pub async fn schedule_job(
@@ -459,10 +722,29 @@ impl<'a> JobLogic<'a> {
         timer_expr: Option<CustomTimerExpr>,
         restart_interval: Option<Duration>,
         actual_args: Option<serde_json::Value>,
+        overrides: HashMap<String, InstanceOverride>,
         created_user: String,
         schedule_pid: Option<NonZeroU64>,
+        canary: Option<CanaryOption>,
     ) -> Result<u64> {
         self.check_schedule_type(action.clone(), schedule_type.clone())?;
+        if let Some(ref canary) = canary {
+            if action != JobAction::Exec {
+                anyhow::bail!("canary dispatch only supports the exec action");
+            }
+            canary.check()?;
+        }
+        if job_record.is_disabled
+            && matches!(
+                action,
+                JobAction::Exec
+                    | JobAction::StartTimer
+                    | JobAction::StartSupervising
+                    | JobAction::RestartSupervising
+            )
+        {
+            anyhow::bail!("job {} is disabled", job_record.name);
+        }
         let schedule_id = IdGenerator::get_schedule_uid();
         let endpoints = Instance::find()
             .filter(instance::Column::InstanceId.is_in(&instance_ids))
@@ -471,6 +753,15 @@ impl<'a> JobLogic<'a> {
         if endpoints.len() == 0 {
             anyhow::bail!("cannot found valid instance");
         }
+        let (endpoints, placement) = self
+            .place_endpoints(
+                &job_record.placement,
+                &job_record.eid,
+                action,
+                &instance_ids,
+                endpoints,
+            )
+            .await?;
 
         let executor_record = Executor::find()
             .filter(executor::Column::Id.eq(job_record.executor_id))
@@ -483,15 +774,11 @@ impl<'a> JobLogic<'a> {
 
         let mut dispatch_result = Vec::new();
 
-        let mut upload_file: Option<UploadFile> = None;
-
-        if job_record.upload_file != "" {
-            let data = fs::read(job_record.upload_file.clone()).await?;
-            upload_file = Some(UploadFile {
-                filename: file_name!(job_record.upload_file.clone()),
-                data: Some(data),
-            });
-        }
+        let upload_path = Some(job_record.upload_file.clone()).filter(|v| !v.is_empty());
+        let upload_file = match upload_path {
+            Some(ref path) => Some(Self::get_upload_file(path).await?),
+            None => None,
+        };
 
         let (bundle_script, job_type): (Option<Vec<BundleScript>>, String) =
             match job_record.clone().bundle_script {
@@ -514,16 +801,50 @@ impl<'a> JobLogic<'a> {
                             cmd_name,
                             code: v.code.clone(),
                             args: cmd_args,
+                            container: ExecutorLogic::get_container_option(&e)?,
                         })
                     }
 
                     (Some(ret), "bundle".to_string())
                 }
-                None => (None, "default".to_string()),
+                None => (None, job_record.job_type.clone()),
             };
 
+        let bundle_option: Option<BundleOption> = job_record
+            .bundle_option
+            .clone()
+            .filter(|_| bundle_script.is_some())
+            .map(|v| serde_json::from_value(v))
+            .transpose()?;
+
+        let supervisor_option: Option<SupervisorOption> = job_record
+            .supervisor_option
+            .clone()
+            .map(|v| serde_json::from_value(v))
+            .transpose()?;
+
+        let timeout_action: Option<TimeoutAction> = job_record
+            .timeout_action
+            .clone()
+            .map(|v| serde_json::from_value(v))
+            .transpose()?;
+
+        let output_limit: Option<OutputLimit> = job_record
+            .output_limit
+            .clone()
+            .map(|v| serde_json::from_value(v))
+            .transpose()?;
+
+        let http_check: Option<HttpCheck> = job_record
+            .http_check
+            .clone()
+            .filter(|_| job_record.job_type == "http")
+            .map(|v| serde_json::from_value(v))
+            .transpose()?;
+
         let job_actual_args = Self::get_job_actual_args(&job_record, actual_args)?;
         let (cmd_name, cmd_args) = ExecutorLogic::get_cmd_args(&executor_record);
+        let target_cmd_args = ExecutorLogic::get_targets_cmd_args(&executor_record, &endpoints);
 
         let dispatch_params = automate::DispatchJobParams {
             base_job: automate::BaseJob {
@@ -540,6 +861,14 @@ impl<'a> JobLogic<'a> {
                 max_parallel: Some(job_record.max_parallel.into()),
                 read_code_from_stdin: false,
                 is_workflow: false,
+                container: ExecutorLogic::get_container_option(&executor_record)?,
+                http_check,
+                env: HashMap::new(),
+                bundle_option,
+                supervisor_option,
+                singleton: job_record.is_singleton,
+                timeout_action,
+                output_limit,
             },
             run_id: IdGenerator::get_run_id(),
             instance_id: None,
@@ -550,16 +879,65 @@ impl<'a> JobLogic<'a> {
             timer_expr: timer_expr.clone().map(|v| TimerExpr {
                 timezone: v.timezone.clone(),
                 expr: v.expr.clone(),
+                interval: v.interval,
+                jitter: v.jitter,
             }),
             is_sync,
             action: action.clone(),
+            blackout_windows: BlackoutLogic::new(self.ctx)
+                .get_effective_windows(Some(job_record.team_id))
+                .await?,
+            request_id: utils::current_request_id(),
+            dispatch_id: String::new(),
         };
 
+        let mut target_jobs = HashMap::new();
+        for (instance_id, v) in overrides.iter().filter(|(_, v)| !v.is_empty()) {
+            let mut base_job = dispatch_params.base_job.clone();
+            if v.args.is_some() {
+                base_job.code = Self::get_job_code(
+                    job_record.code.clone(),
+                    v.merge_args(job_actual_args.as_ref()),
+                )?;
+            }
+            if let Some(work_dir) = v.work_dir.clone().filter(|v| !v.is_empty()) {
+                base_job.work_dir = Some(work_dir);
+            }
+            base_job.env.extend(v.env.clone());
+            target_jobs.insert(instance_id.clone(), base_job);
+        }
+
+        // the jobs without work_user run as the user of the job scoped credential
+        if job_record.work_user.is_empty() {
+            let instance_ids = endpoints
+                .iter()
+                .map(|v| v.instance_id.clone())
+                .collect::<Vec<_>>();
+            for (instance_id, sys_user) in CredentialLogic::new(self.ctx)
+                .get_job_users(&instance_ids)
+                .await?
+            {
+                target_jobs
+                    .entry(instance_id)
+                    .or_insert_with(|| dispatch_params.base_job.clone())
+                    .work_user = Some(sys_user);
+            }
+        }
+
         let mut dispatch_data = DispatchData {
             target: Vec::new(),
             params: dispatch_params.clone(),
+            dispatch_time: Some(Local::now()),
+            target_jobs,
         };
 
+        // the dispatches to the offline instances are deferred by the offline policy of the job
+        let offline_ids = endpoints
+            .iter()
+            .filter(|v| v.status != 1)
+            .map(|v| v.instance_id.clone())
+            .collect::<HashSet<_>>();
+
         endpoints.into_iter().for_each(|v| {
             dispatch_data.target.push(DispatchTarget {
                 ip: v.ip.clone(),
@@ -572,13 +950,35 @@ impl<'a> JobLogic<'a> {
         let logic = automate::Logic::new(self.ctx.redis().clone());
         let http_client = self.ctx.http_client.clone();
 
-        let batch_push_ret = utils::async_batch_do(dispatch_data.target.clone(), move |v| {
-            let mut dispatch_params = dispatch_params.clone();
+        let target_data = dispatch_data.clone();
+        let dispatch_target = move |(v, is_sync): (DispatchTarget, bool)| -> DispatchFuture {
+            let mut dispatch_params = target_data.params_for(&v.instance_id);
+            dispatch_params.is_sync = is_sync;
+            let cmd_args = target_cmd_args.get(&v.instance_id).cloned();
             let logic = logic.clone();
             let http_client = http_client.clone();
+            let upload_path = upload_path.clone();
             let secret = secret.clone();
-            dispatch_params.instance_id = Some(v.instance_id.clone());
             Box::pin(async move {
+                match cmd_args {
+                    Some(Ok((cmd_name, args))) => {
+                        dispatch_params.base_job.cmd_name = cmd_name;
+                        dispatch_params.base_job.args = args;
+                    }
+                    Some(Err(e)) => {
+                        return Ok(DispatchResult {
+                            namespace: v.namespace.clone(),
+                            instance_id: v.instance_id.clone(),
+                            bind_ip: v.ip.clone(),
+                            response: json!(null),
+                            has_err: true,
+                            err: Some(e),
+                            comet_addr: String::new(),
+                            canary: None,
+                        });
+                    }
+                    None => {}
+                }
                 let body = automate::DispatchJobRequest {
                     agent_ip: v.ip.clone(),
                     mac_addr: v.mac_addr.clone(),
@@ -594,24 +994,47 @@ impl<'a> JobLogic<'a> {
                             response: json!(null),
                             has_err: true,
                             err: Some(e.to_string()),
+                            comet_addr: String::new(),
+                            canary: None,
                         });
                     }
                 };
-                let api_url = format!(
-                    "http://{}/dispatch?secret={}",
-                    pair.1.comet_addr,
-                    secret.clone()
-                );
+                if let (Some(path), Some(file)) =
+                    (upload_path, dispatch_params.base_job.upload_file.as_ref())
+                    && let Err(e) =
+                        JobLogic::push_artifact(&http_client, &pair.1, &path, file).await
+                {
+                    return Ok(DispatchResult {
+                        namespace: v.namespace.clone(),
+                        instance_id: v.instance_id.clone(),
+                        bind_ip: v.ip.clone(),
+                        response: json!(null),
+                        has_err: true,
+                        err: Some(e.to_string()),
+                        comet_addr: pair.1.comet_addr.clone(),
+                        canary: None,
+                    });
+                }
+                let api_url = format!("{}?secret={}", pair.1.http_url("/dispatch"), secret);
                 let response = match http_client.post(api_url).json(&body).send().await {
                     Ok(v) => v,
                     Err(e) => {
+                        let reconcile_url = format!(
+                            "{}?secret={}",
+                            pair.1.http_url("/dispatch/reconcile"),
+                            secret
+                        );
+                        let is_acked =
+                            JobLogic::is_dispatch_acked(&http_client, reconcile_url, &body).await;
                         return Ok(DispatchResult {
                             namespace: v.namespace.clone(),
                             bind_ip: v.ip.clone(),
                             instance_id: v.instance_id.clone(),
                             response: json!(null),
-                            has_err: true,
-                            err: Some(e.to_string()),
+                            has_err: !is_acked,
+                            err: Some(e.to_string()).filter(|_| !is_acked),
+                            comet_addr: pair.1.comet_addr.clone(),
+                            canary: None,
                         });
                     }
                 };
@@ -626,6 +1049,8 @@ impl<'a> JobLogic<'a> {
                             response: json!(null),
                             has_err: true,
                             err: Some(e.to_string()),
+                            comet_addr: pair.1.comet_addr.clone(),
+                            canary: None,
                         });
                     }
                 };
@@ -640,6 +1065,8 @@ impl<'a> JobLogic<'a> {
                             instance_id: v.instance_id.clone(),
                             has_err: true,
                             err: Some(e.to_string()),
+                            comet_addr: pair.1.comet_addr.clone(),
+                            canary: None,
                         });
                     }
                 };
@@ -657,25 +1084,82 @@ impl<'a> JobLogic<'a> {
                     instance_id: v.instance_id.clone(),
                     has_err,
                     err,
+                    comet_addr: pair.1.comet_addr.clone(),
+                    canary: None,
                 })
             })
-        })
-        .await;
+        };
+
+        let (mut batch_push_ret, canary_failure) = match canary {
+            Some(ref canary) => {
+                canary::dispatch_canary(
+                    canary,
+                    dispatch_data.target.clone(),
+                    is_sync,
+                    dispatch_target,
+                )
+                .await?
+            }
+            None => (
+                utils::async_batch_do(
+                    dispatch_data
+                        .target
+                        .iter()
+                        .map(|v| (v.clone(), is_sync))
+                        .collect(),
+                    dispatch_target,
+                )
+                .await
+                .into_iter()
+                .collect::<Result<Vec<_>>>()?,
+                None,
+            ),
+        };
+        if canary.is_none() && action == JobAction::Exec {
+            self.defer_offline_dispatch(
+                job_record,
+                &schedule_id,
+                &offline_ids,
+                &mut batch_push_ret,
+                &created_user,
+            )
+            .await?;
+        }
 
         let mut has_err = false;
         batch_push_ret.into_iter().for_each(|v| {
-            let v = v.unwrap();
             if v.has_err {
                 has_err = true;
             }
             dispatch_result.push(v)
         });
 
+        // the targets failed to dispatch count against the quorum
+        let quorum_verdict = match job_record.quorum.clone() {
+            Some(v) if action == JobAction::Exec => {
+                let quorum: QuorumOption = serde_json::from_value(v)?;
+                let total = dispatch_result.len() as u32;
+                Some(QuorumVerdict::new(
+                    quorum.required(total),
+                    total,
+                    0,
+                    dispatch_result.iter().filter(|v| v.has_err).count() as u32,
+                ))
+            }
+            _ => None,
+        };
+
         dispatch_data
             .params
             .base_job
             .upload_file
             .iter_mut()
+            .chain(
+                dispatch_data
+                    .target_jobs
+                    .values_mut()
+                    .flat_map(|v| v.upload_file.iter_mut()),
+            )
             .for_each(|v| v.data = None);
 
         let schedule_pid = if let Some(v) = schedule_pid {
@@ -712,6 +1196,9 @@ impl<'a> JobLogic<'a> {
                 created_user: Set(created_user.clone()),
                 updated_user: Set(created_user.clone()),
                 instance_ids: Set(Some(serde_json::to_value(&instance_ids)?)),
+                instance_overrides: Set(
+                    Some(serde_json::to_value(&overrides)?).filter(|_| !overrides.is_empty())
+                ),
                 schedule_type: Set(schedule_type.to_string()),
                 action: Set(action.to_string()),
                 timer_expr: Set(timer_expr.map(|v| serde_json::to_value(v)).transpose()?),
@@ -735,6 +1222,13 @@ impl<'a> JobLogic<'a> {
             dispatch_data: Set(Some(serde_json::to_value(&dispatch_data)?)),
             snapshot_data: Set(Some(serde_json::to_value(job_record)?)),
             actual_args: Set(Some(serde_json::to_value(&job_actual_args)?)),
+            placement: Set(placement.map(|v| serde_json::to_value(v)).transpose()?),
+            quorum_status: quorum_verdict
+                .as_ref()
+                .map_or(NotSet, |v| Set(v.status.clone())),
+            quorum_required: quorum_verdict.as_ref().map_or(NotSet, |v| Set(v.required)),
+            target_total: Set(dispatch_result.len() as u32),
+            failure_total: quorum_verdict.as_ref().map_or(NotSet, |v| Set(v.failure)),
             created_user: Set(created_user.clone()),
             updated_user: Set(created_user.clone()),
             ..Default::default()
@@ -742,13 +1236,41 @@ impl<'a> JobLogic<'a> {
         .exec(&self.ctx.db)
         .await?;
 
-        if has_err {
-            anyhow::bail!("Partial job scheduling failed");
+        if let Some(reason) = canary_failure {
+            anyhow::bail!("canary dispatch failed, {reason}");
+        }
+        match quorum_verdict {
+            Some(v) if v.status == QUORUM_FAILED => {
+                if let Err(e) = self.quorum_callback(&schedule_id, job_record, &v).await {
+                    error!("failed to send quorum callback request: {e}");
+                }
+                anyhow::bail!("quorum cannot be met, {}", v.reason);
+            }
+            // the failed targets are tolerated by the quorum
+            Some(_) => {}
+            None if has_err => anyhow::bail!("Partial job scheduling failed"),
+            None => {}
         }
 
         Ok(ret.last_insert_id)
     }
 
+    pub async fn set_schedule_override_reason(
+        &self,
+        id: u64,
+        override_reason: String,
+    ) -> Result<u64> {
+        let ret = JobScheduleHistory::update_many()
+            .set(job_schedule_history::ActiveModel {
+                override_reason: Set(override_reason),
+                ..Default::default()
+            })
+            .filter(job_schedule_history::Column::Id.eq(id))
+            .exec(&self.ctx.db)
+            .await?;
+        Ok(ret.rows_affected)
+    }
+
     pub async fn save_schedule(
         &self,
         id: u64,
@@ -757,6 +1279,7 @@ impl<'a> JobLogic<'a> {
         name: String,
         timer_expr: Option<CustomTimerExpr>,
         actual_args: Option<serde_json::Value>,
+        overrides: HashMap<String, InstanceOverride>,
         updated_user: String,
     ) -> Result<u64> {
         let endpoints = Instance::find()
@@ -788,6 +1311,9 @@ impl<'a> JobLogic<'a> {
             actual_args: Set(Some(serde_json::to_value(job_actual_args)?)),
             updated_user: Set(updated_user.clone()),
             instance_ids: Set(Some(serde_json::to_value(instance_ids)?)),
+            instance_overrides: Set(
+                Some(serde_json::to_value(&overrides)?).filter(|_| !overrides.is_empty())
+            ),
             timer_expr: Set(timer_expr.map(|v| serde_json::to_value(v)).transpose()?),
             ..Default::default()
         })
@@ -809,6 +1335,10 @@ impl<'a> JobLogic<'a> {
             .one(&self.ctx.db)
             .await?
             .ok_or(anyhow!("cannot found instance"))?;
+        // the draining instance does not catch up the missed runs
+        if ins.maintenance {
+            return Ok(());
+        }
 
         let runnable: Vec<(serde_json::Value, String)> = JobRunningStatus::find()
             .select_only()
@@ -841,12 +1371,11 @@ impl<'a> JobLogic<'a> {
         let logic = automate::Logic::new(self.ctx.redis().clone());
 
         for (dispatch_data_val, mac_addr) in runnable {
-            let mut dispatch_data: DispatchData = dispatch_data_val.try_into()?;
-            dispatch_data.params.instance_id = Some(ins.instance_id.clone());
+            let dispatch_data: DispatchData = dispatch_data_val.try_into()?;
 
             let body = automate::DispatchJobRequest {
                 agent_ip: bind_ip.clone(),
-                dispatch_params: dispatch_data.params.clone(),
+                dispatch_params: dispatch_data.params_for(&ins.instance_id),
                 mac_addr,
             };
             let pair = match logic
@@ -865,7 +1394,7 @@ impl<'a> JobLogic<'a> {
                 }
             };
 
-            let api_url = format!("http://{}/dispatch", pair.1.comet_addr);
+            let api_url = pair.1.http_url("/dispatch");
 
             let response = match http_client.post(api_url).json(&body).send().await {
                 Ok(v) => v,
@@ -903,6 +1432,90 @@ impl<'a> JobLogic<'a> {
         Ok(())
     }
 
+    /// timers and supervisors which should be running on the instance, it must be read
+    /// before fix_running_status resets the supervisors of the restarted agent
+    pub async fn get_expected_schedules(
+        &self,
+        bind_ip: &str,
+        mac_addr: &str,
+    ) -> Result<PruneScheduleParams> {
+        let ins = Instance::find()
+            .filter(instance::Column::MacAddr.eq(mac_addr))
+            .filter(instance::Column::Ip.eq(bind_ip))
+            .one(&self.ctx.db)
+            .await?
+            .ok_or(anyhow!("cannot found instance"))?;
+
+        let list: Vec<(String, String)> = JobRunningStatus::find()
+            .select_only()
+            .column(job_running_status::Column::Eid)
+            .column(job_running_status::Column::ScheduleType)
+            .filter(job_running_status::Column::InstanceId.eq(ins.instance_id))
+            .filter(
+                Condition::any()
+                    .add(
+                        job_running_status::Column::ScheduleType
+                            .eq(ScheduleType::Timer.to_string())
+                            .and(job_running_status::Column::ScheduleStatus.is_in([
+                                ScheduleStatus::Scheduling.to_string(),
+                                ScheduleStatus::Prepare.to_string(),
+                            ])),
+                    )
+                    .add(
+                        job_running_status::Column::ScheduleType
+                            .eq(ScheduleType::Daemon.to_string())
+                            .and(
+                                job_running_status::Column::ScheduleStatus
+                                    .eq(ScheduleStatus::Supervising.to_string()),
+                            ),
+                    ),
+            )
+            .into_tuple()
+            .all(&self.ctx.db)
+            .await?;
+
+        let mut params = PruneScheduleParams::default();
+        for (eid, schedule_type) in list {
+            if schedule_type == ScheduleType::Daemon.to_string() {
+                params.daemon_eids.push(eid);
+            } else {
+                params.timer_eids.push(eid);
+            }
+        }
+        Ok(params)
+    }
+
+    /// stop the schedules restored by the agent from its local store which are not expected,
+    /// they were stopped while the agent was unreachable
+    pub async fn prune_agent_schedule(
+        &self,
+        bind_ip: String,
+        mac_addr: String,
+        params: PruneScheduleParams,
+    ) -> Result<()> {
+        let pair = automate::Logic::new(self.ctx.redis())
+            .get_link_pair(bind_ip.clone(), mac_addr.clone())
+            .await?;
+        let req = automate::PruneScheduleRequest {
+            agent_ip: bind_ip,
+            mac_addr,
+            params,
+        };
+        let ret = self
+            .ctx
+            .http_client
+            .post(pair.1.http_url("/schedule/prune"))
+            .json(&req)
+            .send()
+            .await?
+            .json::<Value>()
+            .await?;
+        if ret["code"] != 20000 {
+            anyhow::bail!("failed prune agent schedule, {}", ret["msg"]);
+        }
+        Ok(())
+    }
+
     pub async fn redispatch_job(
         &self,
         schedule_id: &str,
@@ -915,19 +1528,20 @@ impl<'a> JobLogic<'a> {
             .ok_or(anyhow!("cannot found job dispatch data"))?
             .try_into()?;
 
-        dispatch_data.params.run_id = IdGenerator::get_run_id();
+        dispatch_data.params.action = action;
+        let parent_run_id = dispatch_data.params.run_id.clone();
+        link_run(&mut dispatch_data.params, parent_run_id, RUN_KIND_RETRY);
 
         let logic = automate::Logic::new(self.ctx.redis().clone());
 
         let http_client = self.ctx.http_client.clone();
 
-        let batch_push_ret = utils::async_batch_do(dispatch_data.target, move |v| {
-            let mut dispatch_params = dispatch_data.params.clone();
+        let batch_push_ret = utils::async_batch_do(dispatch_data.target.clone(), move |v| {
+            let mut dispatch_params = dispatch_data.params_for(&v.instance_id);
             let logic = logic.clone();
             let http_client = http_client.clone();
             let instance_id = v.instance_id.clone();
             dispatch_params.action = action;
-            dispatch_params.instance_id = Some(instance_id.clone());
             dispatch_params.created_user = created_user.clone();
             Box::pin(async move {
                 let body = automate::DispatchJobRequest {
@@ -945,11 +1559,13 @@ impl<'a> JobLogic<'a> {
                             response: json!(null),
                             has_err: true,
                             err: Some(e.to_string()),
+                            comet_addr: String::new(),
+                            canary: None,
                         });
                     }
                 };
 
-                let api_url = format!("http://{}/dispatch", pair.1.comet_addr);
+                let api_url = pair.1.http_url("/dispatch");
 
                 let response = match http_client.post(api_url).json(&body).send().await {
                     Ok(v) => v,
@@ -961,6 +1577,8 @@ impl<'a> JobLogic<'a> {
                             has_err: true,
                             err: Some(e.to_string()),
                             instance_id: v.instance_id.clone(),
+                            comet_addr: pair.1.comet_addr.clone(),
+                            canary: None,
                         });
                     }
                 };
@@ -975,6 +1593,8 @@ impl<'a> JobLogic<'a> {
                             has_err: true,
                             instance_id: v.instance_id.clone(),
                             err: Some(e.to_string()),
+                            comet_addr: pair.1.comet_addr.clone(),
+                            canary: None,
                         });
                     }
                 };
@@ -991,6 +1611,8 @@ impl<'a> JobLogic<'a> {
                     instance_id: v.instance_id.clone(),
                     has_err,
                     err,
+                    comet_addr: pair.1.comet_addr.clone(),
+                    canary: None,
                 })
             })
         })
@@ -1024,6 +1646,101 @@ impl<'a> JobLogic<'a> {
         Ok(batch_push_ret)
     }
 
+    /// re-dispatch the dispatch data of a past run to the same instance or the chosen one,
+    /// the new exec history records the id of the original run in rerun_of
+    /// and its run id in parent_run_id
+    pub async fn rerun_exec(
+        &self,
+        secret: String,
+        exec_record: &job_exec_history::Model,
+        job_schedule_record: job_schedule_history::Model,
+        instance_id: Option<String>,
+        created_user: String,
+    ) -> Result<DispatchResult> {
+        let dispatch_data: DispatchData = job_schedule_record
+            .dispatch_data
+            .ok_or(anyhow!("cannot found job dispatch data"))?
+            .try_into()?;
+
+        let instance_id = instance_id
+            .filter(|v| !v.is_empty())
+            .unwrap_or(exec_record.instance_id.clone());
+        let endpoint = Instance::find()
+            .filter(instance::Column::InstanceId.eq(&instance_id))
+            .one(&self.ctx.db)
+            .await?
+            .ok_or(anyhow!("cannot found instance {instance_id}"))?;
+        if endpoint.maintenance {
+            anyhow::bail!("instance {instance_id} is in maintenance");
+        }
+
+        let dispatch_params = rerun_params(dispatch_data, exec_record, &instance_id, created_user);
+        Ok(self
+            .dispatch_to_endpoint(&secret, &endpoint, dispatch_params)
+            .await)
+    }
+
+    /// dispatch to a single instance through the comet it is connected to,
+    /// the failure is returned in the dispatch result
+    pub(super) async fn dispatch_to_endpoint(
+        &self,
+        secret: &str,
+        endpoint: &instance::Model,
+        dispatch_params: automate::DispatchJobParams,
+    ) -> DispatchResult {
+        let mut ret = DispatchResult {
+            namespace: endpoint.namespace.clone(),
+            instance_id: endpoint.instance_id.clone(),
+            bind_ip: endpoint.ip.clone(),
+            response: json!(null),
+            has_err: true,
+            err: None,
+            comet_addr: String::new(),
+            canary: None,
+        };
+
+        let body = automate::DispatchJobRequest {
+            agent_ip: endpoint.ip.clone(),
+            mac_addr: endpoint.mac_addr.clone(),
+            dispatch_params,
+        };
+
+        let pair = match automate::Logic::new(self.ctx.redis().clone())
+            .get_link_pair(endpoint.ip.clone(), endpoint.mac_addr.clone())
+            .await
+        {
+            Ok(v) => v,
+            Err(e) => {
+                ret.err = Some(e.to_string());
+                return ret;
+            }
+        };
+        ret.comet_addr = pair.1.comet_addr.clone();
+
+        let api_url = format!("{}?secret={}", pair.1.http_url("/dispatch"), secret);
+        let response = match self.ctx.http_client.post(api_url).json(&body).send().await {
+            Ok(v) => v,
+            Err(e) => {
+                ret.err = Some(e.to_string());
+                return ret;
+            }
+        };
+
+        match response.json::<serde_json::Value>().await {
+            Ok(v) => {
+                if v["code"] != 20000 {
+                    ret.err = Some(v["msg"].to_string());
+                } else {
+                    ret.has_err = false;
+                }
+                ret.response = v;
+            }
+            Err(e) => ret.err = Some(e.to_string()),
+        };
+
+        ret
+    }
+
     pub async fn query_schedule(
         &self,
         schedule_type: Option<String>,
@@ -1033,6 +1750,7 @@ impl<'a> JobLogic<'a> {
         team_id: Option<u64>,
         updated_time_range: Option<(String, String)>,
         tag_ids: Option<Vec<u64>>,
+        tag_scope: Option<Vec<u64>>,
         page: u64,
         page_size: u64,
     ) -> Result<(Vec<types::ScheduleJobTeamModel>, u64)> {
@@ -1072,7 +1790,10 @@ impl<'a> JobLogic<'a> {
                         .and(job_schedule::Column::UpdatedTime.lt(v.1)),
                 )
             })
-            .apply_if(team_id, |q, v| q.filter(job::Column::TeamId.eq(v)));
+            .apply_if(team_id, |q, v| q.filter(job::Column::TeamId.eq(v)))
+            .apply_if(tag_scope, |q, v| {
+                q.filter(tag_scope_expr(job::Column::Id, &JOB_RESOURCE_TYPES, v))
+            });
 
         match tag_ids {
             Some(v) if v.len() > 0 => {
@@ -1111,6 +1832,7 @@ impl<'a> JobLogic<'a> {
         team_id: Option<u64>,
         updated_time_range: Option<(String, String)>,
         tag_ids: Option<Vec<u64>>,
+        tag_scope: Option<Vec<u64>>,
         page: u64,
         page_size: u64,
     ) -> Result<(Vec<types::ScheduleHistoryJobTeamModel>, u64)> {
@@ -1150,7 +1872,10 @@ impl<'a> JobLogic<'a> {
                         .and(job_schedule_history::Column::UpdatedTime.lt(v.1)),
                 )
             })
-            .apply_if(team_id, |q, v| q.filter(job::Column::TeamId.eq(v)));
+            .apply_if(team_id, |q, v| q.filter(job::Column::TeamId.eq(v)))
+            .apply_if(tag_scope, |q, v| {
+                q.filter(tag_scope_expr(job::Column::Id, &JOB_RESOURCE_TYPES, v))
+            });
 
         match tag_ids {
             Some(v) if v.len() > 0 => {
@@ -1289,19 +2014,19 @@ impl<'a> JobLogic<'a> {
             anyhow::bail!("Unable to find agent registration information.");
         };
 
-        let api_url = format!("http://{}/dispatch", pair.1.comet_addr);
-        dispatch_data.params.instance_id = Some(ins.instance_id.clone());
+        let api_url = pair.1.http_url("/dispatch");
         dispatch_data.params.created_user = user_info.username.clone();
 
         let mut body = automate::DispatchJobRequest {
             agent_ip: ins.ip.clone(),
             mac_addr: ins.mac_addr.clone(),
-            dispatch_params: dispatch_data.params.clone(),
+            dispatch_params: dispatch_data.params_for(&ins.instance_id),
         };
         body.dispatch_params.action = action.clone();
-        body.dispatch_params.run_id = IdGenerator::get_run_id();
+        let parent_run_id = body.dispatch_params.run_id.clone();
+        link_run(&mut body.dispatch_params, parent_run_id, RUN_KIND_RETRY);
 
-        let resp = match self
+        let ret: Value = match self
             .ctx
             .http_client
             .post(api_url)
@@ -1310,32 +2035,34 @@ impl<'a> JobLogic<'a> {
             .send()
             .await
         {
-            Ok(v) => v,
-            Err(e) => {
-                self.update_run_status(
-                    user_info,
-                    &instance_id,
-                    &eid,
-                    schedule_type.clone(),
-                    action.clone(),
-                )
-                .await?;
-                anyhow::bail!("failed dispatch job, {e}");
-            }
-        };
-
-        let ret: Value = match resp.json::<serde_json::Value>().await {
-            Ok(v) => v,
+            Ok(resp) => match resp.json::<serde_json::Value>().await {
+                Ok(v) => v,
+                Err(e) => {
+                    self.update_run_status(
+                        user_info,
+                        &instance_id,
+                        &eid,
+                        schedule_type.clone(),
+                        action,
+                    )
+                    .await?;
+                    anyhow::bail!("failed dispatch job, {e}");
+                }
+            },
             Err(e) => {
-                self.update_run_status(
-                    user_info,
-                    &instance_id,
-                    &eid,
-                    schedule_type.clone(),
-                    action.clone(),
-                )
-                .await?;
-                anyhow::bail!("failed dispatch job, {e}");
+                let reconcile_url = pair.1.http_url("/dispatch/reconcile");
+                if !Self::is_dispatch_acked(&self.ctx.http_client, reconcile_url, &body).await {
+                    self.update_run_status(
+                        user_info,
+                        &instance_id,
+                        &eid,
+                        schedule_type.clone(),
+                        action,
+                    )
+                    .await?;
+                    anyhow::bail!("failed dispatch job, {e}");
+                }
+                json!({"code": 20000, "msg": "success", "data": null})
             }
         };
 
@@ -1474,3 +2201,80 @@ impl<'a> JobLogic<'a> {
         Ok(ret.rows_affected)
     }
 }
+
+/// an exec of the dispatch of the past run on the instance, linked to the run it reruns
+fn rerun_params(
+    mut dispatch_data: DispatchData,
+    exec_record: &job_exec_history::Model,
+    instance_id: &str,
+    created_user: String,
+) -> automate::DispatchJobParams {
+    let mut fields = dispatch_data.params.fields.take().unwrap_or(json!({}));
+    fields["rerun_of"] = json!(exec_record.id);
+
+    let mut dispatch_params = dispatch_data.params_for(instance_id);
+    dispatch_params.action = JobAction::Exec;
+    dispatch_params.created_user = created_user;
+    dispatch_params.fields = Some(fields);
+    link_run(
+        &mut dispatch_params,
+        exec_record.run_id.clone(),
+        RUN_KIND_RERUN,
+    );
+    dispatch_params
+}
+
+#[test]
+fn test_rerun_params() {
+    let dispatch_data = DispatchData {
+        target: vec![],
+        params: automate::DispatchJobParams {
+            base_job: automate::BaseJob {
+                eid: "e1".to_string(),
+                code: "echo 1".to_string(),
+                ..Default::default()
+            },
+            schedule_id: "s1".to_string(),
+            instance_id: None,
+            run_id: "r1".to_string(),
+            fields: Some(json!({"shard": 1})),
+            timer_expr: None,
+            restart_interval: None,
+            is_sync: false,
+            created_user: "alice".to_string(),
+            action: JobAction::StartTimer,
+            blackout_windows: vec![],
+            request_id: String::new(),
+            dispatch_id: String::new(),
+        },
+        dispatch_time: None,
+        target_jobs: HashMap::from([(
+            "i2".to_string(),
+            automate::BaseJob {
+                eid: "e1".to_string(),
+                code: "echo 2".to_string(),
+                ..Default::default()
+            },
+        )]),
+    };
+    let exec_record = job_exec_history::Model {
+        id: 7,
+        run_id: "r1".to_string(),
+        instance_id: "i1".to_string(),
+        ..Default::default()
+    };
+
+    let params = rerun_params(dispatch_data, &exec_record, "i2", "bob".to_string());
+    assert_eq!(params.action, JobAction::Exec);
+    assert_eq!(params.instance_id.as_deref(), Some("i2"));
+    assert_eq!(params.created_user, "bob");
+    // the job overridden for the instance is rerun
+    assert_eq!(params.base_job.code, "echo 2");
+    assert_ne!(params.run_id, "r1");
+
+    let fields = params.fields.unwrap();
+    assert_eq!(fields["shard"], 1);
+    assert_eq!(fields["rerun_of"], 7);
+    assert_eq!(fields["parent_run_id"], "r1");
+    assert_eq!(fields["run_kind"], RUN_KIND_RERUN);
+}
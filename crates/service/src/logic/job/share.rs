@@ -0,0 +1,122 @@
+use anyhow::Result;
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, EntityTrait, JoinType, QueryFilter, QueryOrder, QuerySelect,
+    sea_query::OnConflict,
+};
+
+use super::{
+    JobLogic,
+    types::{JobShareModel, SharePermission},
+};
+use crate::{
+    entity::{job_share, prelude::*, team},
+    logic::types::UserInfo,
+};
+
+impl<'a> JobLogic<'a> {
+    /// share the job with the team, the permission of an existing grant is replaced
+    pub async fn grant_job_share(
+        &self,
+        user_info: &UserInfo,
+        eid: &str,
+        team_id: u64,
+        permission: SharePermission,
+    ) -> Result<u64> {
+        let Some(job_record) = self.get_job_by_eid(eid).await? else {
+            anyhow::bail!("cannot found job {eid}");
+        };
+        if job_record.team_id == team_id {
+            anyhow::bail!("the job already belongs to the team");
+        }
+        if Team::find_by_id(team_id).one(&self.ctx.db).await?.is_none() {
+            anyhow::bail!("cannot found team {team_id}");
+        }
+
+        let ret = JobShare::insert(job_share::ActiveModel {
+            eid: Set(eid.to_string()),
+            team_id: Set(team_id),
+            permission: Set(permission.as_str().to_string()),
+            created_user: Set(user_info.username.clone()),
+            updated_user: Set(user_info.username.clone()),
+            ..Default::default()
+        })
+        .on_conflict(
+            OnConflict::columns([job_share::Column::Eid, job_share::Column::TeamId])
+                .update_columns([
+                    job_share::Column::Permission,
+                    job_share::Column::UpdatedUser,
+                ])
+                .to_owned(),
+        )
+        .exec(&self.ctx.db)
+        .await?;
+        Ok(ret.last_insert_id)
+    }
+
+    pub async fn revoke_job_share(&self, eid: &str, team_id: u64) -> Result<u64> {
+        let ret = JobShare::delete_many()
+            .filter(job_share::Column::Eid.eq(eid))
+            .filter(job_share::Column::TeamId.eq(team_id))
+            .exec(&self.ctx.db)
+            .await?;
+        Ok(ret.rows_affected)
+    }
+
+    pub async fn query_job_share(&self, eid: &str) -> Result<Vec<JobShareModel>> {
+        Ok(JobShare::find()
+            .column_as(team::Column::Name, "team_name")
+            .join_rev(
+                JoinType::LeftJoin,
+                Team::belongs_to(JobShare)
+                    .from(team::Column::Id)
+                    .to(job_share::Column::TeamId)
+                    .into(),
+            )
+            .filter(job_share::Column::Eid.eq(eid))
+            .order_by_asc(job_share::Column::Id)
+            .into_model()
+            .all(&self.ctx.db)
+            .await?)
+    }
+
+    /// the permission granted to the team the user is acting in, None if the job is not shared with it
+    pub async fn get_share_permission(
+        &self,
+        user_info: &UserInfo,
+        team_id: Option<u64>,
+        eid: &str,
+    ) -> Result<Option<SharePermission>> {
+        let Some(team_id) = team_id else {
+            return Ok(None);
+        };
+
+        if !self
+            .ctx
+            .service()
+            .team
+            .is_team_member(team_id, &user_info.user_id)
+            .await?
+        {
+            return Ok(None);
+        }
+
+        let Some(record) = JobShare::find()
+            .filter(job_share::Column::Eid.eq(eid))
+            .filter(job_share::Column::TeamId.eq(team_id))
+            .one(&self.ctx.db)
+            .await?
+        else {
+            return Ok(None);
+        };
+        Ok(Some(record.permission.as_str().try_into()?))
+    }
+
+    /// remove the grants of the job, called when the job is purged
+    pub(super) async fn delete_job_share(&self, eid: &str) -> Result<u64> {
+        let ret = JobShare::delete_many()
+            .filter(job_share::Column::Eid.eq(eid))
+            .exec(&self.ctx.db)
+            .await?;
+        Ok(ret.rows_affected)
+    }
+}
@@ -0,0 +1,475 @@
+//! service level agreements of the jobs. the evaluator run by the leader marks a breach for
+//! every run longer than max_duration_secs, when the failed runs in a row reach
+//! max_failure_streak, and for every day whose success rate is below min_success_rate.
+//! the breaches are posted to the webhook of the sla config and recorded in the audit log
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::{DateTime, Local, NaiveDate, TimeDelta};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, FromQueryResult, JoinType,
+    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, QueryTrait,
+};
+use sea_query::{Expr, OnConflict};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::error;
+
+use super::{
+    JobLogic,
+    types::{ExecResultStatus, JobSlaModel},
+};
+use crate::{
+    config::Sla,
+    entity::{job, job_daily_stat, job_exec_history, job_sla, job_sla_breach, prelude::*},
+    event::AuditEvent,
+    logic,
+};
+
+pub const SLA_DURATION: &str = "duration";
+pub const SLA_FAILURE_STREAK: &str = "failure_streak";
+pub const SLA_SUCCESS_RATE: &str = "success_rate";
+
+/// a finished run as seen by the evaluator
+pub struct SlaRun {
+    pub run_id: String,
+    pub duration_secs: u64,
+    pub failed: bool,
+}
+
+/// the compliance of a job over the latest days
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlaStatus {
+    pub sla: JobSlaModel,
+    pub today_run_total: u64,
+    /// none if the job has not run today
+    pub today_success_rate: Option<u32>,
+    pub breach_total: u64,
+    pub last_breach_time: Option<DateTime<Local>>,
+    /// no breach in the latest days and the current failure streak is within the limit
+    pub compliant: bool,
+}
+
+#[derive(Debug, FromQueryResult)]
+struct BreachCount {
+    eid: String,
+    breach_total: i64,
+    last_breach_time: Option<DateTime<Local>>,
+}
+
+#[derive(Debug, FromQueryResult)]
+struct RunCount {
+    eid: String,
+    run_total: i64,
+    fail_total: i64,
+}
+
+/// percent of the runs which did not fail, rounded down
+pub fn success_rate(run_total: u64, fail_total: u64) -> u32 {
+    if run_total == 0 {
+        return 100;
+    }
+    (run_total.saturating_sub(fail_total) * 100 / run_total) as u32
+}
+
+/// the breaches of the runs in end time order, starting from the failure streak of the
+/// runs evaluated before. returns the failure streak after the runs
+pub fn evaluate_runs(
+    sla: &job_sla::Model,
+    mut failure_streak: u32,
+    runs: &[SlaRun],
+) -> (u32, Vec<job_sla_breach::Model>) {
+    let mut breaches = vec![];
+    for run in runs {
+        if sla.max_duration_secs > 0 && run.duration_secs > sla.max_duration_secs {
+            breaches.push(job_sla_breach::Model {
+                eid: sla.eid.clone(),
+                sla_type: SLA_DURATION.to_string(),
+                run_id: run.run_id.clone(),
+                value: run.duration_secs,
+                threshold: sla.max_duration_secs,
+                ..Default::default()
+            });
+        }
+
+        failure_streak = if run.failed { failure_streak + 1 } else { 0 };
+        // marked once when the streak reaches the limit, not for every failure after
+        if sla.max_failure_streak > 0 && failure_streak == sla.max_failure_streak {
+            breaches.push(job_sla_breach::Model {
+                eid: sla.eid.clone(),
+                sla_type: SLA_FAILURE_STREAK.to_string(),
+                run_id: run.run_id.clone(),
+                value: failure_streak as u64,
+                threshold: sla.max_failure_streak as u64,
+                ..Default::default()
+            });
+        }
+    }
+    (failure_streak, breaches)
+}
+
+impl<'a> JobLogic<'a> {
+    /// create or replace the sla of the job, the evaluation state is kept
+    pub async fn save_job_sla(&self, model: job_sla::ActiveModel) -> Result<u64> {
+        let ret = JobSla::insert(model)
+            .on_conflict(
+                OnConflict::column(job_sla::Column::Eid)
+                    .update_columns([
+                        job_sla::Column::MaxDurationSecs,
+                        job_sla::Column::MaxFailureStreak,
+                        job_sla::Column::MinSuccessRate,
+                        job_sla::Column::IsEnabled,
+                        job_sla::Column::UpdatedUser,
+                    ])
+                    .to_owned(),
+            )
+            .exec(&self.ctx.db)
+            .await?;
+        Ok(ret.last_insert_id)
+    }
+
+    /// remove the sla and the breaches of the job, also called when the job is purged
+    pub async fn delete_job_sla(&self, eid: &str) -> Result<u64> {
+        let ret = JobSla::delete_many()
+            .filter(job_sla::Column::Eid.eq(eid))
+            .exec(&self.ctx.db)
+            .await?;
+        JobSlaBreach::delete_many()
+            .filter(job_sla_breach::Column::Eid.eq(eid))
+            .exec(&self.ctx.db)
+            .await?;
+        Ok(ret.rows_affected)
+    }
+
+    /// mark the breaches of the runs finished since the last evaluation, and of the success
+    /// rate of yesterday once a day. at most one day is backfilled after a long downtime
+    pub async fn evaluate_sla(&self, opts: &Sla) -> Result<u64> {
+        let now = Local::now();
+        let earliest = now - TimeDelta::days(1);
+        let yesterday = now.date_naive() - TimeDelta::days(1);
+        let list = JobSla::find()
+            .filter(job_sla::Column::IsEnabled.eq(true))
+            .all(&self.ctx.db)
+            .await?;
+
+        let mut total = 0;
+        for sla in list {
+            let cursor = sla.last_end_time.map_or(earliest, |v| v.max(earliest));
+            let records = JobExecHistory::find()
+                .filter(job_exec_history::Column::Eid.eq(&sla.eid))
+                .filter(job_exec_history::Column::EndTime.gt(cursor))
+                .filter(job_exec_history::Column::EndTime.lte(now))
+                .filter(job_exec_history::Column::StartTime.is_not_null())
+                .order_by_asc(job_exec_history::Column::EndTime)
+                .all(&self.ctx.db)
+                .await?;
+            let runs: Vec<SlaRun> = records
+                .iter()
+                .filter_map(|v| {
+                    let (start_time, end_time) = (v.start_time?, v.end_time?);
+                    Some(SlaRun {
+                        run_id: v.run_id.clone(),
+                        duration_secs: (end_time - start_time).num_seconds().max(0) as u64,
+                        failed: v.result_status == ExecResultStatus::Failure.as_str(),
+                    })
+                })
+                .collect();
+            let (failure_streak, mut breaches) = evaluate_runs(&sla, sla.failure_streak, &runs);
+
+            let rate_date = match sla.rate_date {
+                Some(v) if v >= yesterday => Some(v),
+                _ if sla.min_success_rate == 0 => sla.rate_date,
+                _ => {
+                    breaches.extend(self.evaluate_success_rate(&sla, yesterday).await?);
+                    Some(yesterday)
+                }
+            };
+
+            for v in breaches.iter() {
+                self.mark_sla_breach(opts, v).await?;
+            }
+            total += breaches.len() as u64;
+
+            job_sla::ActiveModel {
+                id: Set(sla.id),
+                failure_streak: Set(failure_streak),
+                last_end_time: Set(records
+                    .last()
+                    .and_then(|v| v.end_time)
+                    .or(sla.last_end_time)),
+                rate_date: Set(rate_date),
+                ..Default::default()
+            }
+            .update(&self.ctx.db)
+            .await?;
+        }
+        Ok(total)
+    }
+
+    async fn evaluate_success_rate(
+        &self,
+        sla: &job_sla::Model,
+        date: NaiveDate,
+    ) -> Result<Option<job_sla_breach::Model>> {
+        let Some(since) = date
+            .and_hms_opt(0, 0, 0)
+            .and_then(|v| v.and_local_timezone(Local).single())
+        else {
+            return Ok(None);
+        };
+        let select = JobExecHistory::find()
+            .filter(job_exec_history::Column::Eid.eq(&sla.eid))
+            .filter(job_exec_history::Column::EndTime.gte(since))
+            .filter(job_exec_history::Column::EndTime.lt(since + TimeDelta::days(1)));
+        let run_total = select.clone().count(&self.ctx.db).await?;
+        let fail_total = select
+            .filter(job_exec_history::Column::ResultStatus.eq(ExecResultStatus::Failure.as_str()))
+            .count(&self.ctx.db)
+            .await?;
+
+        let rate = success_rate(run_total, fail_total);
+        if run_total == 0 || rate >= sla.min_success_rate {
+            return Ok(None);
+        }
+        Ok(Some(job_sla_breach::Model {
+            eid: sla.eid.clone(),
+            sla_type: SLA_SUCCESS_RATE.to_string(),
+            stat_date: Some(date),
+            value: rate as u64,
+            threshold: sla.min_success_rate as u64,
+            ..Default::default()
+        }))
+    }
+
+    /// a failed notification does not stop the evaluation
+    async fn mark_sla_breach(&self, opts: &Sla, breach: &job_sla_breach::Model) -> Result<()> {
+        JobSlaBreach::insert(job_sla_breach::ActiveModel {
+            eid: Set(breach.eid.clone()),
+            sla_type: Set(breach.sla_type.clone()),
+            run_id: Set(breach.run_id.clone()),
+            stat_date: Set(breach.stat_date),
+            value: Set(breach.value),
+            threshold: Set(breach.threshold),
+            ..Default::default()
+        })
+        .exec(&self.ctx.db)
+        .await?;
+
+        let reason = format!(
+            "{} {} breaches the limit {}",
+            breach.sla_type, breach.value, breach.threshold
+        );
+        self.ctx
+            .bus
+            .emit(
+                self.ctx,
+                AuditEvent {
+                    action: "sla_breach",
+                    eid: breach.eid.clone(),
+                    source: breach
+                        .stat_date
+                        .map_or(breach.run_id.clone(), |v| v.to_string()),
+                    status: breach.sla_type.clone(),
+                    reason,
+                    schedule_id: String::new(),
+                },
+            )
+            .await;
+
+        if let Some(url) = opts.webhook_url.as_ref() {
+            let _ = self
+                .ctx
+                .http_client
+                .post(url)
+                .json(&json!({
+                    "event": "sla_breach",
+                    "eid": breach.eid,
+                    "sla_type": breach.sla_type,
+                    "run_id": breach.run_id,
+                    "stat_date": breach.stat_date,
+                    "value": breach.value,
+                    "threshold": breach.threshold,
+                }))
+                .send()
+                .await
+                .and_then(|v| v.error_for_status())
+                .map_err(|e| error!("failed notify sla breach - {e:?}"));
+        }
+        Ok(())
+    }
+
+    /// the slas of the jobs visible to the user with their breaches of the latest days
+    pub async fn get_sla_status(
+        &self,
+        user_info: &logic::types::UserInfo,
+        team_id: Option<u64>,
+        days: i64,
+    ) -> Result<Vec<SlaStatus>> {
+        let (search_user, team_ids) = if self.ctx.can_manage_job(&user_info.user_id).await? {
+            (None, None)
+        } else {
+            let team_ids = self
+                .ctx
+                .service()
+                .team
+                .get_my_teams(&user_info.user_id)
+                .await?
+                .into_iter()
+                .map(|v| v.id)
+                .collect::<Vec<u64>>();
+            (Some(user_info.username.clone()), Some(team_ids))
+        };
+
+        let list: Vec<JobSlaModel> = JobSla::find()
+            .column_as(job::Column::Name, "job_name")
+            .column_as(job::Column::TeamId, "team_id")
+            .join_rev(
+                JoinType::InnerJoin,
+                Job::belongs_to(JobSla)
+                    .from(job::Column::Eid)
+                    .to(job_sla::Column::Eid)
+                    .into(),
+            )
+            .apply_if(search_user, |query, v| {
+                let mut cond = job::Column::CreatedUser.eq(v);
+                if let Some(team_ids) = team_ids {
+                    cond = cond.or(job::Column::TeamId.is_in(team_ids));
+                }
+                query.filter(cond)
+            })
+            .apply_if(team_id, |q, v| q.filter(job::Column::TeamId.eq(v)))
+            .filter(job::Column::IsDeleted.eq(false))
+            .order_by_asc(job_sla::Column::Id)
+            .into_model()
+            .all(&self.ctx.db)
+            .await?;
+        let eids: Vec<String> = list.iter().map(|v| v.eid.clone()).collect();
+
+        let breaches: HashMap<String, BreachCount> = JobSlaBreach::find()
+            .select_only()
+            .column(job_sla_breach::Column::Eid)
+            .expr_as(Expr::cust("cast(count(*) as signed)"), "breach_total")
+            .expr_as(
+                Expr::col(job_sla_breach::Column::CreatedTime).max(),
+                "last_breach_time",
+            )
+            .filter(job_sla_breach::Column::Eid.is_in(eids.clone()))
+            .filter(job_sla_breach::Column::CreatedTime.gte(Local::now() - TimeDelta::days(days)))
+            .group_by(job_sla_breach::Column::Eid)
+            .into_model::<BreachCount>()
+            .all(&self.ctx.db)
+            .await?
+            .into_iter()
+            .map(|v| (v.eid.clone(), v))
+            .collect();
+
+        let runs: HashMap<String, RunCount> = JobDailyStat::find()
+            .select_only()
+            .column(job_daily_stat::Column::Eid)
+            .expr_as(
+                Expr::cust("cast(sum(`job_daily_stat`.`run_total`) as signed)"),
+                "run_total",
+            )
+            .expr_as(
+                Expr::cust("cast(sum(`job_daily_stat`.`fail_total`) as signed)"),
+                "fail_total",
+            )
+            .filter(job_daily_stat::Column::Eid.is_in(eids))
+            .filter(job_daily_stat::Column::StatDate.eq(Local::now().date_naive()))
+            .group_by(job_daily_stat::Column::Eid)
+            .into_model::<RunCount>()
+            .all(&self.ctx.db)
+            .await?
+            .into_iter()
+            .map(|v| (v.eid.clone(), v))
+            .collect();
+
+        Ok(list
+            .into_iter()
+            .map(|sla| {
+                let breach = breaches.get(&sla.eid);
+                let run = runs.get(&sla.eid);
+                let breach_total = breach.map_or(0, |v| v.breach_total as u64);
+                let today_run_total = run.map_or(0, |v| v.run_total as u64);
+                let compliant = breach_total == 0
+                    && (sla.max_failure_streak == 0 || sla.failure_streak < sla.max_failure_streak);
+                SlaStatus {
+                    today_run_total,
+                    today_success_rate: run
+                        .filter(|v| v.run_total > 0)
+                        .map(|v| success_rate(v.run_total as u64, v.fail_total as u64)),
+                    breach_total,
+                    last_breach_time: breach.and_then(|v| v.last_breach_time),
+                    compliant,
+                    sla,
+                }
+            })
+            .collect())
+    }
+
+    pub async fn get_job_sla(&self, eid: &str) -> Result<Option<job_sla::Model>> {
+        Ok(JobSla::find()
+            .filter(job_sla::Column::Eid.eq(eid))
+            .one(&self.ctx.db)
+            .await?)
+    }
+
+    /// the breaches of the job, the latest first
+    pub async fn query_sla_breach(
+        &self,
+        eid: &str,
+        page: u64,
+        page_size: u64,
+    ) -> Result<(Vec<job_sla_breach::Model>, u64)> {
+        let select = JobSlaBreach::find().filter(job_sla_breach::Column::Eid.eq(eid));
+        let total = select.clone().count(&self.ctx.db).await?;
+        let list = select
+            .order_by_desc(job_sla_breach::Column::Id)
+            .paginate(&self.ctx.db, page_size)
+            .fetch_page(page)
+            .await?;
+        Ok((list, total))
+    }
+}
+
+#[test]
+fn test_evaluate_sla_runs() {
+    let sla = job_sla::Model {
+        eid: "j1".to_string(),
+        max_duration_secs: 60,
+        max_failure_streak: 2,
+        ..Default::default()
+    };
+    let run = |run_id: &str, duration_secs: u64, failed: bool| SlaRun {
+        run_id: run_id.to_string(),
+        duration_secs,
+        failed,
+    };
+
+    let (streak, breaches) = evaluate_runs(
+        &sla,
+        1,
+        &[
+            run("r1", 61, true),
+            run("r2", 10, true),
+            run("r3", 60, false),
+        ],
+    );
+    assert_eq!(streak, 0);
+    let breaches: Vec<(&str, &str, u64)> = breaches
+        .iter()
+        .map(|v| (v.sla_type.as_str(), v.run_id.as_str(), v.value))
+        .collect();
+    // the streak carried over reaches the limit at r1, r2 is over the limit but not marked again
+    assert_eq!(
+        breaches,
+        vec![(SLA_DURATION, "r1", 61), (SLA_FAILURE_STREAK, "r1", 2)]
+    );
+
+    let (streak, breaches) = evaluate_runs(&sla, 0, &[run("r4", 1, true)]);
+    assert_eq!((streak, breaches.len()), (1, 0));
+
+    assert_eq!(success_rate(0, 0), 100);
+    assert_eq!(success_rate(3, 1), 66);
+    assert_eq!(success_rate(4, 4), 0);
+}
@@ -0,0 +1,358 @@
+//! read-only status pages of the daemon and timer jobs
+//!
+//! a page is served without login at the url of its token, it only shows the name and the
+//! health of the jobs, never the instances, the outputs or the args. the health is cached
+//! for STATUS_PAGE_CACHE_SECS so the page can be polled by the stakeholders
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow};
+use automate::scheduler::types::{RunStatus, ScheduleStatus, ScheduleType};
+use chrono::Local;
+use nanoid::nanoid;
+use sea_orm::{
+    ActiveModelTrait,
+    ActiveValue::{NotSet, Set},
+    ColumnTrait, Condition, EntityTrait, QueryFilter, QueryOrder,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{JobLogic, types::ExecResultStatus};
+use crate::{
+    cache::CACHE_STATUS_PAGE,
+    entity::{instance, job, job_running_status, prelude::*, status_page},
+    logic::types::UserInfo,
+};
+
+pub const STATUS_PAGE_CACHE_SECS: u64 = 30;
+pub const MAX_STATUS_PAGE_JOBS: usize = 50;
+
+pub const STATUS_OPERATIONAL: &str = "operational";
+pub const STATUS_DEGRADED: &str = "degraded";
+pub const STATUS_DOWN: &str = "down";
+/// no instance is supervising or scheduling the job
+pub const STATUS_INACTIVE: &str = "inactive";
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct StatusPageJob {
+    pub name: String,
+    /// daemon or timer
+    pub schedule_type: String,
+    pub status: String,
+    pub instance_total: u64,
+    pub healthy_total: u64,
+    pub last_run_time: Option<String>,
+    pub next_time: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct StatusPageView {
+    pub name: String,
+    /// the worst status of the jobs
+    pub status: String,
+    pub jobs: Vec<StatusPageJob>,
+    pub generated_time: String,
+}
+
+/// a supervised job is healthy while it runs, a scheduled one while its last run did not fail,
+/// the instance must be online. none if the job is not active on the instance
+fn is_healthy(v: &job_running_status::Model, is_online: bool) -> Option<bool> {
+    if v.schedule_type == ScheduleType::Daemon.to_string() {
+        if v.schedule_status == ScheduleStatus::Flapping.to_string() {
+            return Some(false);
+        }
+        if v.schedule_status != ScheduleStatus::Supervising.to_string() {
+            return None;
+        }
+        return Some(is_online && v.run_status == RunStatus::Running.to_string());
+    }
+    if v.schedule_status != ScheduleStatus::Scheduling.to_string() {
+        return None;
+    }
+    Some(is_online && v.result_status != ExecResultStatus::Failure.as_str())
+}
+
+fn job_status(instance_total: u64, healthy_total: u64) -> &'static str {
+    match (instance_total, healthy_total) {
+        (0, _) => STATUS_INACTIVE,
+        (_, 0) => STATUS_DOWN,
+        (t, h) if t == h => STATUS_OPERATIONAL,
+        _ => STATUS_DEGRADED,
+    }
+}
+
+fn page_status<'s>(statuses: impl Iterator<Item = &'s str>) -> &'static str {
+    let mut ret = STATUS_OPERATIONAL;
+    for v in statuses {
+        match v {
+            STATUS_DOWN => return STATUS_DOWN,
+            STATUS_DEGRADED => ret = STATUS_DEGRADED,
+            _ => {}
+        }
+    }
+    ret
+}
+
+impl<'a> JobLogic<'a> {
+    /// the page can be changed by its creator, or the admins of the team it belongs to
+    async fn can_write_status_page(
+        &self,
+        user_info: &UserInfo,
+        record: &status_page::Model,
+    ) -> Result<bool> {
+        if record.created_user == user_info.username {
+            return Ok(true);
+        }
+        if record.team_id == 0 {
+            return Ok(false);
+        }
+        self.ctx
+            .service()
+            .team
+            .can_write_team(Some(record.team_id), user_info.user_id.clone())
+            .await
+    }
+
+    /// create or update the page, the token is generated when it is created.
+    /// the jobs shown must be writable by the user
+    pub async fn save_status_page(
+        &self,
+        user_info: &UserInfo,
+        id: Option<u64>,
+        name: String,
+        team_id: u64,
+        eids: Vec<String>,
+        is_enabled: bool,
+    ) -> Result<u64> {
+        if eids.is_empty() || eids.len() > MAX_STATUS_PAGE_JOBS {
+            anyhow::bail!("a status page shows 1 to {MAX_STATUS_PAGE_JOBS} jobs");
+        }
+        let scope = Some(team_id).filter(|v| *v != 0);
+        if scope.is_some()
+            && !self
+                .ctx
+                .service()
+                .team
+                .can_write_team(scope, user_info.user_id.clone())
+                .await?
+        {
+            anyhow::bail!("only the team admins can publish the status page of the team");
+        }
+        for eid in &eids {
+            if !self
+                .can_write_job(user_info, scope, Some(eid.clone()))
+                .await?
+            {
+                anyhow::bail!("no permission to publish the status of job {eid}");
+            }
+        }
+
+        let (id, token, created_user) = match id {
+            Some(id) => {
+                let record = StatusPage::find_by_id(id)
+                    .one(&self.ctx.db)
+                    .await?
+                    .ok_or(anyhow!("invalid status page id"))?;
+                if !self.can_write_status_page(user_info, &record).await? {
+                    anyhow::bail!("no permission to change the status page");
+                }
+                (Set(id), NotSet, NotSet)
+            }
+            None => (NotSet, Set(nanoid!(32)), Set(user_info.username.clone())),
+        };
+
+        let model = status_page::ActiveModel {
+            id,
+            token,
+            name: Set(name),
+            team_id: Set(team_id),
+            eids: Set(Some(serde_json::to_value(eids)?)),
+            is_enabled: Set(is_enabled),
+            created_user,
+            updated_user: Set(user_info.username.clone()),
+            ..Default::default()
+        }
+        .save(&self.ctx.db)
+        .await?;
+        self.ctx.cache.invalidate(CACHE_STATUS_PAGE).await;
+        Ok(model.id.as_ref().to_owned())
+    }
+
+    /// the pages of the user and the ones of the teams of the user
+    pub async fn query_status_page(&self, user_info: &UserInfo) -> Result<Vec<status_page::Model>> {
+        let team_ids: Vec<u64> = self
+            .ctx
+            .service()
+            .team
+            .get_member_teams(&user_info.user_id)
+            .await?
+            .into_iter()
+            .map(|v| v.team_id)
+            .collect();
+
+        Ok(StatusPage::find()
+            .filter(
+                Condition::any()
+                    .add(status_page::Column::CreatedUser.eq(&user_info.username))
+                    .add(status_page::Column::TeamId.is_in(team_ids)),
+            )
+            .order_by_asc(status_page::Column::Name)
+            .all(&self.ctx.db)
+            .await?)
+    }
+
+    pub async fn delete_status_page(&self, user_info: &UserInfo, id: u64) -> Result<u64> {
+        let Some(record) = StatusPage::find_by_id(id).one(&self.ctx.db).await? else {
+            return Ok(0);
+        };
+        if !self.can_write_status_page(user_info, &record).await? {
+            anyhow::bail!("no permission to delete the status page");
+        }
+        let ret = StatusPage::delete_by_id(id).exec(&self.ctx.db).await?;
+        self.ctx.cache.invalidate(CACHE_STATUS_PAGE).await;
+        Ok(ret.rows_affected)
+    }
+
+    /// the health of the jobs of the page, none if the token is unknown or the page is disabled
+    pub async fn get_public_status(&self, token: &str) -> Result<Option<StatusPageView>> {
+        self.ctx
+            .cache
+            .get_or_load(CACHE_STATUS_PAGE, token, STATUS_PAGE_CACHE_SECS, || {
+                self.load_public_status(token)
+            })
+            .await
+    }
+
+    async fn load_public_status(&self, token: &str) -> Result<Option<StatusPageView>> {
+        let Some(page) = StatusPage::find()
+            .filter(status_page::Column::Token.eq(token))
+            .filter(status_page::Column::IsEnabled.eq(true))
+            .one(&self.ctx.db)
+            .await?
+        else {
+            return Ok(None);
+        };
+        let eids: Vec<String> = match page.eids {
+            Some(v) => serde_json::from_value(v)?,
+            None => vec![],
+        };
+
+        let jobs = Job::find()
+            .filter(job::Column::Eid.is_in(&eids))
+            .filter(job::Column::IsDeleted.eq(false))
+            .all(&self.ctx.db)
+            .await?;
+        let rows = JobRunningStatus::find()
+            .filter(job_running_status::Column::Eid.is_in(&eids))
+            .filter(job_running_status::Column::IsDeleted.eq(false))
+            .filter(job_running_status::Column::ScheduleType.is_in([
+                ScheduleType::Daemon.to_string(),
+                ScheduleType::Timer.to_string(),
+            ]))
+            .all(&self.ctx.db)
+            .await?;
+        let online: HashMap<String, bool> = Instance::find()
+            .filter(
+                instance::Column::InstanceId.is_in(
+                    rows.iter()
+                        .map(|v| v.instance_id.clone())
+                        .collect::<Vec<_>>(),
+                ),
+            )
+            .all(&self.ctx.db)
+            .await?
+            .into_iter()
+            .map(|v| (v.instance_id, v.status == 1))
+            .collect();
+
+        let format_time = |v: Option<chrono::DateTime<Local>>| {
+            v.map(|v| v.format("%Y-%m-%d %H:%M:%S").to_string())
+        };
+        // in the order chosen for the page
+        let list = eids
+            .iter()
+            .filter_map(|eid| jobs.iter().find(|v| &v.eid == eid))
+            .map(|job_record| {
+                let mut view = StatusPageJob {
+                    name: job_record.name.clone(),
+                    ..Default::default()
+                };
+                for v in rows.iter().filter(|v| v.eid == job_record.eid) {
+                    let is_online = online.get(&v.instance_id).copied().unwrap_or_default();
+                    let Some(healthy) = is_healthy(v, is_online) else {
+                        continue;
+                    };
+                    view.schedule_type = v.schedule_type.clone();
+                    view.instance_total += 1;
+                    view.healthy_total += healthy as u64;
+                    view.last_run_time = view.last_run_time.max(format_time(v.start_time));
+                    view.next_time = match (view.next_time.take(), format_time(v.next_time)) {
+                        (Some(a), Some(b)) => Some(a.min(b)),
+                        (a, b) => a.or(b),
+                    };
+                }
+                view.status = job_status(view.instance_total, view.healthy_total).to_string();
+                view
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Some(StatusPageView {
+            name: page.name,
+            status: page_status(list.iter().map(|v| v.status.as_str())).to_string(),
+            jobs: list,
+            generated_time: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        }))
+    }
+}
+
+#[test]
+fn test_status_page_health() {
+    let row = |schedule_type: ScheduleType, schedule_status: ScheduleStatus, run: RunStatus| {
+        job_running_status::Model {
+            schedule_type: schedule_type.to_string(),
+            schedule_status: schedule_status.to_string(),
+            run_status: run.to_string(),
+            ..Default::default()
+        }
+    };
+    let daemon = row(
+        ScheduleType::Daemon,
+        ScheduleStatus::Supervising,
+        RunStatus::Running,
+    );
+    assert_eq!(is_healthy(&daemon, true), Some(true));
+    assert_eq!(is_healthy(&daemon, false), Some(false));
+    let flapping = row(
+        ScheduleType::Daemon,
+        ScheduleStatus::Flapping,
+        RunStatus::Stop,
+    );
+    assert_eq!(is_healthy(&flapping, true), Some(false));
+    let stopped = row(
+        ScheduleType::Daemon,
+        ScheduleStatus::Unsupervised,
+        RunStatus::Stop,
+    );
+    assert_eq!(is_healthy(&stopped, true), None);
+
+    let mut timer = row(
+        ScheduleType::Timer,
+        ScheduleStatus::Scheduling,
+        RunStatus::Stop,
+    );
+    assert_eq!(is_healthy(&timer, true), Some(true));
+    timer.result_status = ExecResultStatus::Failure.as_str().to_string();
+    assert_eq!(is_healthy(&timer, true), Some(false));
+
+    assert_eq!(job_status(0, 0), STATUS_INACTIVE);
+    assert_eq!(job_status(2, 0), STATUS_DOWN);
+    assert_eq!(job_status(2, 1), STATUS_DEGRADED);
+    assert_eq!(job_status(2, 2), STATUS_OPERATIONAL);
+    assert_eq!(
+        page_status([STATUS_OPERATIONAL, STATUS_INACTIVE, STATUS_DEGRADED].into_iter()),
+        STATUS_DEGRADED
+    );
+    assert_eq!(
+        page_status([STATUS_DEGRADED, STATUS_DOWN].into_iter()),
+        STATUS_DOWN
+    );
+}
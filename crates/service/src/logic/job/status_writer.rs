@@ -0,0 +1,404 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Result;
+use sea_orm::{ActiveModelTrait, DatabaseConnection, EntityTrait, IdenStatic, Iterable};
+use sea_query::OnConflict;
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::{MissedTickBehavior, interval, sleep},
+};
+use tracing::{error, warn};
+
+use super::JobLogic;
+use crate::{
+    config::StatusBatch,
+    entity::{job_exec_history, job_running_status, prelude::*},
+};
+
+/// exec history rows of one statement are kept under this many bytes of output,
+/// so the statement stays below max_allowed_packet
+const EXEC_HISTORY_BATCH_BYTES: usize = 4 * 1024 * 1024;
+
+/// attempts of a failed batch before it is written row by row
+const FLUSH_RETRIES: u32 = 3;
+
+/// upsert of the running status, the update_columns are overwritten by the values
+/// of the model when the row exists
+#[derive(Clone, Debug)]
+pub struct RunningStatusUpsert {
+    pub model: job_running_status::ActiveModel,
+    pub update_columns: Vec<job_running_status::Column>,
+}
+
+impl RunningStatusUpsert {
+    /// statements are batched only when they write the same columns
+    fn signature(&self) -> Vec<String> {
+        job_running_status::Column::iter()
+            .filter(|v| self.model.get(*v).is_set())
+            .map(|v| v.as_str().to_string())
+            .chain(["|".to_string()])
+            .chain(self.update_columns.iter().map(|v| v.as_str().to_string()))
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum StatusWrite {
+    RunningStatus(RunningStatusUpsert),
+    ExecHistory(job_exec_history::ActiveModel),
+}
+
+enum Cmd {
+    Write(Box<StatusWrite>),
+    Shutdown(oneshot::Sender<()>),
+}
+
+/// the run id and the instance id of the exec histories queued but not written yet,
+/// an update replayed from the spool of the agent is dropped while the first one is buffered
+#[derive(Clone, Default)]
+struct BufferedRuns(Arc<Mutex<HashSet<(String, String)>>>);
+
+impl BufferedRuns {
+    fn key(v: &StatusWrite) -> Option<(String, String)> {
+        let StatusWrite::ExecHistory(m) = v else {
+            return None;
+        };
+        let run_id = m.run_id.try_as_ref().filter(|v| !v.is_empty())?;
+        Some((run_id.clone(), m.instance_id.try_as_ref()?.clone()))
+    }
+
+    fn contains(&self, run_id: &str, instance_id: &str) -> bool {
+        self.0
+            .lock()
+            .unwrap()
+            .contains(&(run_id.to_string(), instance_id.to_string()))
+    }
+
+    /// false if the same run of the instance is already buffered
+    fn claim(&self, v: &StatusWrite) -> bool {
+        Self::key(v).is_none_or(|k| self.0.lock().unwrap().insert(k))
+    }
+
+    fn release<'b>(&self, writes: impl IntoIterator<Item = &'b StatusWrite>) {
+        let mut runs = self.0.lock().unwrap();
+        for k in writes.into_iter().filter_map(Self::key) {
+            runs.remove(&k);
+        }
+    }
+}
+
+/// buffers the status writes of the agents, they are written when batch_size is reached
+/// or flush_interval_ms elapsed, in the order they were received
+#[derive(Clone)]
+pub struct StatusWriter {
+    tx: mpsc::Sender<Cmd>,
+    buffered: BufferedRuns,
+}
+
+pub struct StatusWriterTask {
+    db: DatabaseConnection,
+    rx: mpsc::Receiver<Cmd>,
+    batch_size: usize,
+    flush_interval: Duration,
+    buffered: BufferedRuns,
+}
+
+impl StatusWriter {
+    pub fn new(db: DatabaseConnection, opts: &StatusBatch) -> (Self, StatusWriterTask) {
+        let (tx, rx) = mpsc::channel(opts.queue_size.max(1));
+        let buffered = BufferedRuns::default();
+        (
+            Self {
+                tx,
+                buffered: buffered.clone(),
+            },
+            StatusWriterTask {
+                db,
+                rx,
+                batch_size: opts.batch_size.max(1),
+                flush_interval: Duration::from_millis(opts.flush_interval_ms.max(1)),
+                buffered,
+            },
+        )
+    }
+
+    /// the exec history of the run of the instance is queued but not written yet
+    pub fn is_buffered(&self, run_id: &str, instance_id: &str) -> bool {
+        self.buffered.contains(run_id, instance_id)
+    }
+
+    /// queue the write, waits while the queue is full, the exec history of a run
+    /// already buffered is dropped. the write is given back if the writer has been shut down
+    pub async fn send(&self, v: StatusWrite) -> std::result::Result<(), StatusWrite> {
+        if !self.buffered.claim(&v) {
+            return Ok(());
+        }
+        self.tx
+            .send(Cmd::Write(Box::new(v)))
+            .await
+            .map_err(|e| match e.0 {
+                Cmd::Write(v) => {
+                    self.buffered.release([v.as_ref()]);
+                    *v
+                }
+                Cmd::Shutdown(_) => unreachable!(),
+            })
+    }
+
+    /// write the queued updates and stop the writer, the later writes are given back by send
+    pub async fn shutdown(&self) {
+        let (ack_tx, ack_rx) = oneshot::channel();
+        if self.tx.send(Cmd::Shutdown(ack_tx)).await.is_ok() {
+            let _ = ack_rx.await;
+        }
+    }
+}
+
+impl StatusWriterTask {
+    pub async fn run(mut self) {
+        let mut pending = Vec::with_capacity(self.batch_size);
+        let mut ticker = interval(self.flush_interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                cmd = self.rx.recv() => match cmd {
+                    Some(Cmd::Write(v)) => {
+                        pending.push(*v);
+                        if pending.len() >= self.batch_size {
+                            self.flush(&mut pending).await;
+                        }
+                    }
+                    Some(Cmd::Shutdown(ack)) => {
+                        self.rx.close();
+                        while let Some(cmd) = self.rx.recv().await {
+                            if let Cmd::Write(v) = cmd {
+                                pending.push(*v);
+                            }
+                        }
+                        self.flush(&mut pending).await;
+                        let _ = ack.send(());
+                        return;
+                    }
+                    None => {
+                        self.flush(&mut pending).await;
+                        return;
+                    }
+                },
+                _ = ticker.tick() => self.flush(&mut pending).await,
+            }
+        }
+    }
+
+    async fn flush(&self, pending: &mut Vec<StatusWrite>) {
+        if pending.is_empty() {
+            return;
+        }
+        let writes = std::mem::take(pending);
+        for batch in split_batches(writes, self.batch_size) {
+            let mut attempt = 0;
+            loop {
+                match write_batch(&self.db, batch.clone()).await {
+                    Ok(()) => break,
+                    Err(e) if attempt + 1 < FLUSH_RETRIES => {
+                        attempt += 1;
+                        warn!("failed write job status batch, retry {attempt} - {e:?}");
+                        sleep(Duration::from_millis(200 * attempt as u64)).await;
+                    }
+                    Err(e) => {
+                        error!("failed write job status batch, write row by row - {e:?}");
+                        for v in batch.iter() {
+                            if let Err(e) = write_batch(&self.db, vec![v.clone()]).await {
+                                error!("failed write job status - {e:?}");
+                            }
+                        }
+                        break;
+                    }
+                }
+            }
+            // released once written, the later replays are found in the database
+            self.buffered.release(&batch);
+        }
+    }
+}
+
+/// split the writes into batches of consecutive writes that fit in one statement
+fn split_batches(writes: Vec<StatusWrite>, batch_size: usize) -> Vec<Vec<StatusWrite>> {
+    let mut batches: Vec<Vec<StatusWrite>> = Vec::new();
+    let mut batch_bytes = 0;
+    for v in writes {
+        let bytes = match &v {
+            StatusWrite::ExecHistory(m) => m.output.try_as_ref().map_or(0, String::len),
+            StatusWrite::RunningStatus(_) => 0,
+        };
+        let joinable = batches.last().is_some_and(|batch| {
+            batch.len() < batch_size
+                && match (&batch[0], &v) {
+                    (StatusWrite::RunningStatus(a), StatusWrite::RunningStatus(b)) => {
+                        a.signature() == b.signature()
+                    }
+                    (StatusWrite::ExecHistory(_), StatusWrite::ExecHistory(_)) => {
+                        batch_bytes + bytes <= EXEC_HISTORY_BATCH_BYTES
+                    }
+                    _ => false,
+                }
+        });
+        if joinable {
+            batch_bytes += bytes;
+            batches.last_mut().unwrap().push(v);
+        } else {
+            batch_bytes = bytes;
+            batches.push(vec![v]);
+        }
+    }
+    batches
+}
+
+/// write the batch with one statement, all the writes must be of the same kind
+pub(super) async fn write_batch(db: &DatabaseConnection, batch: Vec<StatusWrite>) -> Result<()> {
+    let update_columns = match batch.first() {
+        Some(StatusWrite::RunningStatus(v)) => Some(v.update_columns.clone()),
+        Some(StatusWrite::ExecHistory(_)) => None,
+        None => return Ok(()),
+    };
+
+    match update_columns {
+        Some(update_columns) => {
+            let models = batch.into_iter().filter_map(|v| match v {
+                StatusWrite::RunningStatus(v) => Some(v.model),
+                StatusWrite::ExecHistory(_) => None,
+            });
+            JobRunningStatus::insert_many(models)
+                .on_conflict(
+                    OnConflict::columns([
+                        job_running_status::Column::Eid,
+                        job_running_status::Column::ScheduleType,
+                        job_running_status::Column::InstanceId,
+                    ])
+                    .update_columns(update_columns)
+                    .to_owned(),
+                )
+                .exec_without_returning(db)
+                .await?;
+        }
+        None => {
+            // an update replayed from the spool of the agent may be buffered with the first one
+            let mut seen = HashSet::new();
+            let models = batch
+                .into_iter()
+                .filter_map(|v| match v {
+                    StatusWrite::ExecHistory(v) => Some(v),
+                    StatusWrite::RunningStatus(_) => None,
+                })
+                .filter(|v| {
+                    seen.insert((
+                        v.schedule_id.try_as_ref().cloned(),
+                        v.instance_id.try_as_ref().cloned(),
+                        v.start_time.try_as_ref().cloned().flatten(),
+                    ))
+                })
+                .collect::<Vec<_>>();
+            JobExecHistory::insert_many(models)
+                .exec_without_returning(db)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+impl<'a> JobLogic<'a> {
+    /// queue the write to the status writer, it is written immediately
+    /// if there is no writer or the writer has been shut down
+    pub(super) async fn write_status(&self, v: StatusWrite) -> Result<()> {
+        let v = match self.ctx.status_writer.as_ref() {
+            Some(writer) => match writer.send(v).await {
+                Ok(()) => return Ok(()),
+                Err(v) => v,
+            },
+            None => v,
+        };
+        write_batch(&self.ctx.db, vec![v]).await
+    }
+}
+
+#[test]
+fn test_split_batches() {
+    use sea_orm::ActiveValue::Set;
+
+    let upsert = |run_status: Option<&str>| {
+        let mut model = job_running_status::ActiveModel {
+            eid: Set("j-1".to_string()),
+            ..Default::default()
+        };
+        let mut update_columns = vec![job_running_status::Column::ScheduleId];
+        if let Some(v) = run_status {
+            model.run_status = Set(v.to_string());
+            update_columns.push(job_running_status::Column::RunStatus);
+        }
+        StatusWrite::RunningStatus(RunningStatusUpsert {
+            model,
+            update_columns,
+        })
+    };
+    let history = |output: usize| {
+        StatusWrite::ExecHistory(job_exec_history::ActiveModel {
+            output: Set("x".repeat(output)),
+            ..Default::default()
+        })
+    };
+
+    let batches = split_batches(
+        vec![
+            upsert(Some("running")),
+            upsert(Some("stop")),
+            upsert(None),
+            history(10),
+            history(10),
+            upsert(None),
+            upsert(None),
+            upsert(None),
+        ],
+        2,
+    );
+    assert_eq!(
+        batches.iter().map(Vec::len).collect::<Vec<_>>(),
+        [2, 1, 2, 2, 1]
+    );
+
+    let batches = split_batches(
+        vec![history(EXEC_HISTORY_BATCH_BYTES), history(1), history(1)],
+        10,
+    );
+    assert_eq!(batches.iter().map(Vec::len).collect::<Vec<_>>(), [1, 2]);
+}
+
+#[test]
+fn test_buffered_runs() {
+    use sea_orm::ActiveValue::Set;
+
+    let history = |run_id: &str, instance_id: &str| {
+        StatusWrite::ExecHistory(job_exec_history::ActiveModel {
+            run_id: Set(run_id.to_string()),
+            instance_id: Set(instance_id.to_string()),
+            ..Default::default()
+        })
+    };
+
+    let buffered = BufferedRuns::default();
+    assert!(buffered.claim(&history("r1", "i1")));
+    assert!(buffered.claim(&history("r1", "i2")));
+    // the replay of the buffered run
+    assert!(!buffered.claim(&history("r1", "i1")));
+    assert!(buffered.contains("r1", "i1"));
+    // the runs of the agents not sending a run id are not deduplicated here
+    assert!(buffered.claim(&history("", "i1")));
+    assert!(buffered.claim(&history("", "i1")));
+
+    buffered.release(&[history("r1", "i1")]);
+    assert!(!buffered.contains("r1", "i1"));
+    assert!(buffered.claim(&history("r1", "i1")));
+}
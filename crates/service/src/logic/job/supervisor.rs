@@ -1,15 +1,24 @@
 use crate::{
     entity::{
-        executor, instance, job, job_running_status, job_supervisor, prelude::*, tag_resource, team,
+        executor, instance, job, job_running_status, job_supervisor, job_supervisor_metric,
+        prelude::*, tag_resource, team,
+    },
+    logic::{
+        tag::{JOB_RESOURCE_TYPES, tag_scope_expr},
+        types::{QuotaResource, ResourceType, UserInfo},
     },
-    logic::types::{ResourceType, UserInfo},
 };
 use anyhow::Result;
-use automate::scheduler::types::{RunStatus, ScheduleStatus, ScheduleType};
-use chrono::Local;
+use automate::{
+    bridge::msg::UpdateJobParams,
+    scheduler::types::{RunStatus, ScheduleStatus, ScheduleType},
+};
+use chrono::{Local, TimeDelta};
 use sea_orm::{
-    ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, EntityTrait, JoinType,
-    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, QueryTrait,
+    ActiveModelTrait,
+    ActiveValue::{self, Set},
+    ColumnTrait, Condition, EntityTrait, JoinType, PaginatorTrait, QueryFilter, QueryOrder,
+    QuerySelect, QueryTrait,
 };
 use sea_query::Query;
 
@@ -24,6 +33,7 @@ impl<'a> JobLogic<'a> {
         team_id: Option<u64>,
         updated_time_range: Option<(String, String)>,
         tag_ids: Option<Vec<u64>>,
+        tag_scope: Option<Vec<u64>>,
         page: u64,
         page_size: u64,
     ) -> Result<(Vec<JobSupervisorRelatedJobModel>, u64)> {
@@ -93,6 +103,9 @@ impl<'a> JobLogic<'a> {
             }
             _ => {}
         };
+        if let Some(v) = tag_scope {
+            select = select.filter(tag_scope_expr(job::Column::Id, &JOB_RESOURCE_TYPES, v));
+        }
 
         let total = select.clone().count(&self.ctx.db).await?;
 
@@ -110,6 +123,15 @@ impl<'a> JobLogic<'a> {
         &self,
         active_model: job_supervisor::ActiveModel,
     ) -> Result<job_supervisor::ActiveModel> {
+        let current_eid = match active_model.id {
+            ActiveValue::Set(id) | ActiveValue::Unchanged(id) => JobSupervisor::find_by_id(id)
+                .one(&self.ctx.db)
+                .await?
+                .map(|v| v.eid),
+            ActiveValue::NotSet => None,
+        };
+        self.check_job_resource_quota(QuotaResource::Daemon, &active_model.eid, current_eid)
+            .await?;
         Ok(active_model.save(&self.ctx.db).await?)
     }
 
@@ -184,4 +206,56 @@ impl<'a> JobLogic<'a> {
 
         Ok(())
     }
+
+    /// save a resource usage sample reported by the agent of a supervised job
+    pub async fn save_supervisor_metric(&self, params: &UpdateJobParams) -> Result<u64> {
+        let Some(usage) = params.resource_usage.as_ref() else {
+            return Ok(0);
+        };
+        let ret = JobSupervisorMetric::insert(job_supervisor_metric::ActiveModel {
+            eid: Set(params.base_job.eid.clone()),
+            schedule_id: Set(params.schedule_id.clone()),
+            instance_id: Set(params.instance_id.clone()),
+            run_id: Set(params.run_id.clone()),
+            cpu_usage: Set(usage.cpu_usage),
+            rss: Set(usage.rss),
+            process_count: Set(usage.process_count),
+            sample_time: Set(usage.sample_time.with_timezone(&Local)),
+            ..Default::default()
+        })
+        .exec(&self.ctx.db)
+        .await?;
+        Ok(ret.last_insert_id)
+    }
+
+    /// samples of the latest window in time order, all instances are included if instance_id is none
+    pub async fn get_supervisor_metrics(
+        &self,
+        eid: String,
+        instance_id: Option<String>,
+        window: TimeDelta,
+    ) -> Result<Vec<job_supervisor_metric::Model>> {
+        let list = JobSupervisorMetric::find()
+            .filter(job_supervisor_metric::Column::Eid.eq(eid))
+            .apply_if(instance_id, |q, v| {
+                q.filter(job_supervisor_metric::Column::InstanceId.eq(v))
+            })
+            .filter(job_supervisor_metric::Column::SampleTime.gte(Local::now() - window))
+            .order_by_asc(job_supervisor_metric::Column::SampleTime)
+            .all(&self.ctx.db)
+            .await?;
+        Ok(list)
+    }
+
+    /// delete the samples older than days
+    pub async fn purge_supervisor_metric(&self, days: u64) -> Result<u64> {
+        let ret = JobSupervisorMetric::delete_many()
+            .filter(
+                job_supervisor_metric::Column::SampleTime
+                    .lt(Local::now() - TimeDelta::days(days as i64)),
+            )
+            .exec(&self.ctx.db)
+            .await?;
+        Ok(ret.rows_affected)
+    }
 }
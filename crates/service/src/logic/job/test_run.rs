@@ -0,0 +1,171 @@
+use std::time::Instant;
+
+use anyhow::{Result, anyhow};
+use automate::JobAction;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::JobLogic;
+use crate::{
+    IdGenerator,
+    entity::{instance, job, prelude::*},
+    logic::executor::{ExecutorLogic, MAX_SYNC_TIMEOUT_SECS, dispatch_sync},
+};
+
+/// eid of the test runs, their status updates are not recorded as job runs
+pub const TEST_RUN_EID_PREFIX: &str = "test-run-";
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct TestRunParams {
+    /// one of the sandbox instances, the first online one is used if empty
+    pub instance_id: Option<String>,
+    pub executor_id: u64,
+    pub code: String,
+    /// formal args of the job, eg: [{"name": "date", "val": "2025-01-01"}]
+    pub args: Option<Value>,
+    pub actual_args: Option<Value>,
+    pub work_dir: Option<String>,
+    pub work_user: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct TestRunOutput {
+    pub instance_id: String,
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    pub duration_ms: u64,
+}
+
+/// the requested sandbox, or the first online one in the configured order
+fn pick_sandbox<'b>(
+    sandbox_ids: &[String],
+    online: &'b [instance::Model],
+    instance_id: Option<&str>,
+) -> Result<&'b instance::Model> {
+    if sandbox_ids.is_empty() {
+        anyhow::bail!("no sandbox instance is configured for test run");
+    }
+    match instance_id.filter(|v| !v.is_empty()) {
+        Some(id) => {
+            if !sandbox_ids.iter().any(|v| v == id) {
+                anyhow::bail!("instance {id} is not a sandbox instance");
+            }
+            online
+                .iter()
+                .find(|v| v.instance_id == id)
+                .ok_or(anyhow!("sandbox instance {id} is offline"))
+        }
+        None => sandbox_ids
+            .iter()
+            .find_map(|id| online.iter().find(|v| &v.instance_id == id))
+            .ok_or(anyhow!("all sandbox instances are offline")),
+    }
+}
+
+impl<'a> JobLogic<'a> {
+    /// run the code, which may be unsaved, on a sandbox instance and wait for the output.
+    /// nothing is recorded in the schedule and exec history
+    pub async fn test_run(
+        &self,
+        params: TestRunParams,
+        created_user: &str,
+    ) -> Result<TestRunOutput> {
+        let conf = &self.ctx.conf.test_run;
+        let online = Instance::find()
+            .filter(instance::Column::InstanceId.is_in(&conf.instance_ids))
+            .filter(instance::Column::Status.eq(1))
+            .filter(instance::Column::Maintenance.eq(false))
+            .all(&self.ctx.db)
+            .await?;
+        let instance_record =
+            pick_sandbox(&conf.instance_ids, &online, params.instance_id.as_deref())?;
+
+        let executor_record = Executor::find_by_id(params.executor_id)
+            .one(&self.ctx.db)
+            .await?
+            .ok_or(anyhow!("cannot found executor {}", params.executor_id))?;
+        let (cmd_name, args) =
+            ExecutorLogic::get_instance_cmd_args(&executor_record, instance_record)?;
+
+        let job_record = job::Model {
+            args: params.args,
+            ..Default::default()
+        };
+        let actual_args = Self::get_job_actual_args(&job_record, params.actual_args)?;
+
+        let dispatch_params = automate::DispatchJobParams {
+            base_job: automate::BaseJob {
+                eid: format!("{TEST_RUN_EID_PREFIX}{}", IdGenerator::get_run_id()),
+                cmd_name,
+                code: Self::get_job_code(params.code, actual_args)?,
+                args,
+                work_dir: params.work_dir.filter(|v| !v.is_empty()),
+                work_user: params.work_user.filter(|v| !v.is_empty()),
+                timeout: conf.timeout_secs.clamp(1, MAX_SYNC_TIMEOUT_SECS),
+                max_retry: Some(1),
+                max_parallel: Some(1),
+                container: ExecutorLogic::get_container_option(&executor_record)?,
+                ..Default::default()
+            },
+            run_id: IdGenerator::get_run_id(),
+            instance_id: Some(instance_record.instance_id.clone()),
+            fields: None,
+            restart_interval: None,
+            created_user: created_user.to_string(),
+            schedule_id: String::new(),
+            timer_expr: None,
+            is_sync: true,
+            action: JobAction::Exec,
+            blackout_windows: vec![],
+            request_id: utils::current_request_id(),
+            dispatch_id: String::new(),
+        };
+
+        let start = Instant::now();
+        let data = dispatch_sync(
+            &automate::Logic::new(self.ctx.redis()),
+            &self.ctx.http_client,
+            &self.ctx.conf.comet_secret,
+            instance_record,
+            dispatch_params,
+        )
+        .await?;
+
+        Ok(TestRunOutput {
+            instance_id: instance_record.instance_id.clone(),
+            exit_code: data["exit_code"].as_i64().map(|v| v as i32),
+            stdout: data["stdout"].as_str().unwrap_or_default().to_string(),
+            stderr: data["stderr"].as_str().unwrap_or_default().to_string(),
+            duration_ms: start.elapsed().as_millis() as u64,
+        })
+    }
+}
+
+#[test]
+fn test_pick_sandbox() {
+    let sandbox_ids = vec!["s1".to_string(), "s2".to_string()];
+    let online = ["s2", "other"]
+        .map(|v| instance::Model {
+            instance_id: v.to_string(),
+            ..Default::default()
+        })
+        .to_vec();
+
+    assert_eq!(
+        pick_sandbox(&sandbox_ids, &online, None)
+            .unwrap()
+            .instance_id,
+        "s2"
+    );
+    assert_eq!(
+        pick_sandbox(&sandbox_ids, &online, Some("s2"))
+            .unwrap()
+            .instance_id,
+        "s2"
+    );
+    assert!(pick_sandbox(&sandbox_ids, &online, Some("s1")).is_err());
+    assert!(pick_sandbox(&sandbox_ids, &online, Some("other")).is_err());
+    assert!(pick_sandbox(&[], &online, None).is_err());
+}
@@ -1,23 +1,60 @@
+use std::time::Duration;
+
 use anyhow::Result;
-use chrono::Local;
+use automate::{
+    bridge::msg::TimerExpr,
+    scheduler::timer::{self, TimerSchedule},
+};
+use chrono::{DateTime, Local, Utc};
 use sea_orm::{
-    ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, EntityTrait, JoinType,
-    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, QueryTrait,
+    ActiveValue::{self, Set},
+    ColumnTrait, Condition, EntityTrait, JoinType, PaginatorTrait, QueryFilter, QueryOrder,
+    QuerySelect, QueryTrait,
 };
 use sea_query::Query;
 
 use super::{JobLogic, types::JobTimerRelatedJobModel};
 use crate::{
     entity::{executor, job, job_timer, prelude::*, tag_resource, team},
-    logic::types::UserInfo,
+    logic::{
+        blackout::BlackoutLogic,
+        tag::{JOB_RESOURCE_TYPES, tag_scope_expr},
+        types::{CustomTimerExpr, QuotaResource, UserInfo},
+    },
 };
 
+/// ticks returned by a simulation at most
+pub const TIMER_SIMULATION_LIMIT: usize = 1000;
+
+pub struct SimulatedTick {
+    pub time: DateTime<Utc>,
+    /// the tick is skipped by the agent inside the blackout window
+    pub blackout_window: Option<String>,
+}
+
 impl<'a> JobLogic<'a> {
     pub async fn save_job_timer(
         &self,
         active_model: job_timer::ActiveModel,
+        version: Option<u32>,
     ) -> Result<job_timer::ActiveModel> {
-        Ok(active_model.save(&self.ctx.db).await?)
+        let current_eid = match active_model.id {
+            ActiveValue::Set(id) | ActiveValue::Unchanged(id) => JobTimer::find_by_id(id)
+                .one(&self.ctx.db)
+                .await?
+                .map(|v| v.eid),
+            ActiveValue::NotSet => None,
+        };
+        self.check_job_resource_quota(QuotaResource::Timer, &active_model.eid, current_eid)
+            .await?;
+        crate::logic::save_versioned(
+            &self.ctx.db,
+            active_model,
+            job_timer::Column::Id,
+            job_timer::Column::Version,
+            version,
+        )
+        .await
     }
 
     pub async fn query_job_timer(
@@ -28,6 +65,7 @@ impl<'a> JobLogic<'a> {
         job_type: Option<String>,
         updated_time_range: Option<(String, String)>,
         tag_ids: Option<Vec<u64>>,
+        tag_scope: Option<Vec<u64>>,
         page: u64,
         page_size: u64,
     ) -> Result<(Vec<JobTimerRelatedJobModel>, u64)> {
@@ -95,6 +133,9 @@ impl<'a> JobLogic<'a> {
             }
             _ => {}
         };
+        if let Some(v) = tag_scope {
+            select = select.filter(tag_scope_expr(job::Column::Id, &JOB_RESOURCE_TYPES, v));
+        }
 
         let total = select.clone().count(&self.ctx.db).await?;
 
@@ -122,4 +163,45 @@ impl<'a> JobLogic<'a> {
 
         Ok(ret.rows_affected)
     }
+
+    /// the ticks of the timer in the next hours as the agent fires them, without the jitter,
+    /// the ticks inside the blackout windows of the team are marked
+    pub async fn simulate_timer(
+        &self,
+        team_id: Option<u64>,
+        timer_expr: &CustomTimerExpr,
+        hours: u64,
+    ) -> Result<Vec<SimulatedTick>> {
+        let now = Utc::now();
+        let schedule = TimerSchedule::new(
+            &TimerExpr {
+                timezone: timer_expr.timezone.clone(),
+                expr: timer_expr.expr.clone(),
+                interval: timer_expr.interval,
+                jitter: None,
+            },
+            now,
+        )?;
+        let windows = BlackoutLogic::new(self.ctx)
+            .get_effective_windows(team_id)
+            .await?;
+
+        let ticks = timer::simulate(
+            schedule,
+            now,
+            Duration::from_secs(hours * 3600),
+            TIMER_SIMULATION_LIMIT,
+        )
+        .await;
+        Ok(ticks
+            .into_iter()
+            .map(|time| SimulatedTick {
+                blackout_window: windows
+                    .iter()
+                    .find(|v| v.contains(time))
+                    .map(|v| v.name.clone()),
+                time,
+            })
+            .collect())
+    }
 }
@@ -0,0 +1,333 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use automate::{JobAction, scheduler::types::ScheduleType};
+use chrono::Local;
+use crypto::{hmac::Hmac, mac::Mac, sha2::Sha256, util::fixed_time_eq};
+use nanoid::nanoid;
+use redis::AsyncCommands;
+use rustc_serialize::hex::ToHex;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, PaginatorTrait, QueryFilter,
+    QueryOrder,
+};
+use serde_json::Value;
+
+use super::{JobLogic, types::TriggerStatus};
+use crate::{
+    entity::{job_trigger, job_trigger_log, prelude::*},
+    event::AuditEvent,
+    logic::blackout::BlackoutLogic,
+};
+
+/// payloads longer than this are truncated in the audit
+const MAX_LOGGED_PAYLOAD: usize = 4096;
+
+/// credentials sent by the caller, either a signature of the payload or the secret itself
+#[derive(Debug, Default)]
+pub struct TriggerAuth {
+    /// X-Jiascheduler-Signature: sha256=<hex hmac of the body>
+    pub signature: Option<String>,
+    /// the secret as is, for the callers unable to sign, eg: X-Gitlab-Token or Authorization: Bearer
+    pub secret: Option<String>,
+}
+
+impl TriggerAuth {
+    pub fn verify(&self, secret: &str, body: &[u8]) -> bool {
+        if let Some(signature) = &self.signature {
+            let mut mac = Hmac::new(Sha256::new(), secret.as_bytes());
+            mac.input(body);
+            let expected = format!("sha256={}", mac.result().code().to_hex());
+            return fixed_time_eq(expected.as_bytes(), signature.as_bytes());
+        }
+        self.secret
+            .as_ref()
+            .is_some_and(|v| fixed_time_eq(v.as_bytes(), secret.as_bytes()))
+    }
+}
+
+/// build the job args from the payload, mapping is arg name to json pointer, eg: {"branch": "/ref"},
+/// fields missing in the payload are left out
+pub fn map_trigger_args(mapping: Option<&Value>, payload: &Value) -> Option<Value> {
+    let mapping = mapping?.as_object()?;
+    let args: serde_json::Map<String, Value> = mapping
+        .iter()
+        .filter_map(|(name, pointer)| {
+            let v = payload.pointer(pointer.as_str()?)?;
+            Some((name.clone(), v.clone()))
+        })
+        .collect();
+    Some(Value::Object(args))
+}
+
+fn truncate(s: &str) -> &str {
+    if s.len() <= MAX_LOGGED_PAYLOAD {
+        return s;
+    }
+    let mut end = MAX_LOGGED_PAYLOAD;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+impl<'a> JobLogic<'a> {
+    /// token and secret are generated when the trigger is created
+    pub async fn save_job_trigger(&self, mut model: job_trigger::ActiveModel) -> Result<u64> {
+        if model.id.is_not_set() {
+            model.token = Set(nanoid!(32));
+            model.secret = Set(nanoid!(40));
+        }
+        let model = model.save(&self.ctx.db).await?;
+        Ok(model.id.as_ref().to_owned())
+    }
+
+    pub async fn query_job_trigger(&self, eid: &str) -> Result<Vec<job_trigger::Model>> {
+        Ok(JobTrigger::find()
+            .filter(job_trigger::Column::Eid.eq(eid))
+            .order_by_asc(job_trigger::Column::Id)
+            .all(&self.ctx.db)
+            .await?)
+    }
+
+    pub async fn get_job_trigger(&self, id: u64) -> Result<Option<job_trigger::Model>> {
+        Ok(JobTrigger::find_by_id(id).one(&self.ctx.db).await?)
+    }
+
+    pub async fn delete_job_trigger(&self, id: u64) -> Result<u64> {
+        let ret = JobTrigger::delete_by_id(id).exec(&self.ctx.db).await?;
+        JobTriggerLog::delete_many()
+            .filter(job_trigger_log::Column::TriggerId.eq(id))
+            .exec(&self.ctx.db)
+            .await?;
+        Ok(ret.rows_affected)
+    }
+
+    /// remove the triggers of the job, called when the job is purged
+    pub(super) async fn delete_job_triggers(&self, eid: &str) -> Result<u64> {
+        let mut n = 0;
+        for v in self.query_job_trigger(eid).await? {
+            n += self.delete_job_trigger(v.id).await?;
+        }
+        Ok(n)
+    }
+
+    pub async fn query_job_trigger_log(
+        &self,
+        trigger_id: u64,
+        page: u64,
+        page_size: u64,
+    ) -> Result<(Vec<job_trigger_log::Model>, u64)> {
+        let select = JobTriggerLog::find()
+            .filter(job_trigger_log::Column::TriggerId.eq(trigger_id))
+            .order_by_desc(job_trigger_log::Column::Id);
+        let total = select.clone().count(&self.ctx.db).await?;
+        let list = select
+            .paginate(&self.ctx.db, page_size)
+            .fetch_page(page)
+            .await?;
+        Ok((list, total))
+    }
+
+    async fn save_job_trigger_log(
+        &self,
+        trigger: &job_trigger::Model,
+        remote_ip: &str,
+        status: TriggerStatus,
+        reason: String,
+        schedule_id: String,
+        body: &[u8],
+    ) -> Result<()> {
+        job_trigger_log::ActiveModel {
+            trigger_id: Set(trigger.id),
+            eid: Set(trigger.eid.clone()),
+            remote_ip: Set(remote_ip.to_string()),
+            status: Set(status.as_str().to_string()),
+            reason: Set(truncate(&reason).chars().take(500).collect()),
+            schedule_id: Set(schedule_id.clone()),
+            payload: Set(Some(truncate(&String::from_utf8_lossy(body)).to_string())),
+            ..Default::default()
+        }
+        .insert(&self.ctx.db)
+        .await?;
+        self.ctx
+            .bus
+            .emit(
+                self.ctx,
+                AuditEvent {
+                    action: "trigger",
+                    eid: trigger.eid.clone(),
+                    source: remote_ip.to_string(),
+                    status: status.as_str().to_string(),
+                    reason,
+                    schedule_id,
+                },
+            )
+            .await;
+        Ok(())
+    }
+
+    /// count the invocation in the current minute, false if the limit is exceeded
+    async fn acquire_trigger_rate(&self, trigger: &job_trigger::Model) -> Result<bool> {
+        if trigger.rate_limit == 0 {
+            return Ok(true);
+        }
+        let key = format!(
+            "jiascheduler:trigger:rate:{}:{}",
+            trigger.id,
+            Local::now().timestamp() / 60
+        );
+        let mut conn = self.ctx.redis().get_multiplexed_async_connection().await?;
+        let n: u32 = conn.incr(&key, 1).await?;
+        let _: () = conn.expire(&key, 120).await?;
+        Ok(n <= trigger.rate_limit)
+    }
+
+    /// verify the invocation and dispatch the job with the args mapped from the payload,
+    /// every invocation of a known trigger is audited, returns the schedule id
+    pub async fn fire_job_trigger(
+        &self,
+        comet_secret: String,
+        token: &str,
+        auth: &TriggerAuth,
+        body: &[u8],
+        remote_ip: &str,
+    ) -> Result<String> {
+        let Some(trigger) = JobTrigger::find()
+            .filter(job_trigger::Column::Token.eq(token))
+            .filter(job_trigger::Column::IsEnabled.eq(true))
+            .one(&self.ctx.db)
+            .await?
+        else {
+            anyhow::bail!("trigger not found");
+        };
+
+        let (status, reason) = if !auth.verify(&trigger.secret, body) {
+            (TriggerStatus::Rejected, "invalid signature".to_string())
+        } else if !self.acquire_trigger_rate(&trigger).await? {
+            (TriggerStatus::Rejected, "rate limit exceeded".to_string())
+        } else {
+            match self.dispatch_by_trigger(comet_secret, &trigger, body).await {
+                Ok(schedule_id) => {
+                    self.save_job_trigger_log(
+                        &trigger,
+                        remote_ip,
+                        TriggerStatus::Accepted,
+                        String::new(),
+                        schedule_id.clone(),
+                        body,
+                    )
+                    .await?;
+                    return Ok(schedule_id);
+                }
+                Err(e) => (TriggerStatus::Failed, e.to_string()),
+            }
+        };
+
+        self.save_job_trigger_log(
+            &trigger,
+            remote_ip,
+            status,
+            reason.clone(),
+            String::new(),
+            body,
+        )
+        .await?;
+        anyhow::bail!(reason)
+    }
+
+    async fn dispatch_by_trigger(
+        &self,
+        comet_secret: String,
+        trigger: &job_trigger::Model,
+        body: &[u8],
+    ) -> Result<String> {
+        let payload: Value = if body.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_slice(body).map_err(|e| anyhow::anyhow!("invalid payload, {e}"))?
+        };
+        let instance_ids: Vec<String> = match trigger.instance_ids.clone() {
+            Some(v) => serde_json::from_value(v)?,
+            None => vec![],
+        };
+        if instance_ids.is_empty() {
+            anyhow::bail!("no instance is bound to the trigger");
+        }
+
+        let Some(job_record) = self.get_job_by_eid(&trigger.eid).await? else {
+            anyhow::bail!("cannot found job {}", trigger.eid);
+        };
+        BlackoutLogic::new(self.ctx)
+            .check_manual_dispatch(Some(job_record.team_id).filter(|v| *v > 0), None)
+            .await?;
+
+        let args = map_trigger_args(trigger.args_mapping.as_ref(), &payload);
+        let history_id = self
+            .dispatch_job(
+                comet_secret,
+                instance_ids,
+                trigger.eid.clone(),
+                false,
+                format!("{}-{}", trigger.name, Local::now().format("%Y%m%d%H%M%S")),
+                ScheduleType::Once,
+                JobAction::Exec,
+                None,
+                None,
+                args,
+                HashMap::new(),
+                trigger.created_user.clone(),
+                None,
+            )
+            .await?;
+
+        Ok(JobScheduleHistory::find_by_id(history_id)
+            .one(&self.ctx.db)
+            .await?
+            .map(|v| v.schedule_id)
+            .unwrap_or_default())
+    }
+}
+
+#[test]
+fn test_trigger_auth() {
+    let body = br#"{"ref":"main"}"#;
+    let mut mac = Hmac::new(Sha256::new(), b"s3cret");
+    mac.input(body);
+    let signature = format!("sha256={}", mac.result().code().to_hex());
+
+    let auth = TriggerAuth {
+        signature: Some(signature),
+        secret: None,
+    };
+    assert!(auth.verify("s3cret", body));
+    assert!(!auth.verify("other", body));
+    assert!(!auth.verify("s3cret", br#"{"ref":"dev"}"#));
+
+    let auth = TriggerAuth {
+        signature: None,
+        secret: Some("s3cret".to_string()),
+    };
+    assert!(auth.verify("s3cret", body));
+    assert!(!TriggerAuth::default().verify("s3cret", body));
+}
+
+#[test]
+fn test_map_trigger_args() {
+    let payload = serde_json::json!({
+        "ref": "main",
+        "commit": {"id": "abc"},
+        "alerts": [{"labels": {"instance": "10.0.0.1"}}],
+    });
+    let mapping = serde_json::json!({
+        "branch": "/ref",
+        "commit": "/commit/id",
+        "host": "/alerts/0/labels/instance",
+        "missing": "/not/exists",
+    });
+    assert_eq!(
+        map_trigger_args(Some(&mapping), &payload),
+        Some(serde_json::json!({"branch": "main", "commit": "abc", "host": "10.0.0.1"}))
+    );
+    assert_eq!(map_trigger_args(None, &payload), None);
+}
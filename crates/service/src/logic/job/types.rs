@@ -1,8 +1,12 @@
-use automate::DispatchJobParams;
+use std::collections::HashMap;
+
+use automate::{BaseJob, DispatchJobParams};
 use sea_orm::{FromQueryResult, prelude::DateTimeLocal};
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 
+use crate::IdGenerator;
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromQueryResult)]
 pub struct RunStatusRelatedScheduleJobModel {
     pub id: u64,
@@ -24,6 +28,7 @@ pub struct RunStatusRelatedScheduleJobModel {
     pub run_status: String,
     pub exit_status: String,
     pub exit_code: i32,
+    pub result_status: String,
     pub dispatch_data: Option<serde_json::Value>,
     pub dispatch_result: Option<serde_json::Value>,
     pub start_time: Option<DateTimeLocal>,
@@ -33,6 +38,7 @@ pub struct RunStatusRelatedScheduleJobModel {
     pub updated_user: String,
     pub updated_time: DateTimeLocal,
     pub is_online: bool,
+    pub maintenance: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromQueryResult)]
@@ -49,8 +55,10 @@ pub struct ExecHistoryRelatedScheduleModel {
     pub team_id: Option<u64>,
     pub team_name: Option<String>,
     pub bundle_script_result: Option<serde_json::Value>,
+    pub parsed_result: Option<serde_json::Value>,
     pub created_user: String,
     pub exit_code: i64,
+    pub result_status: String,
     pub exit_status: String,
     pub start_time: Option<DateTimeLocal>,
     pub end_time: Option<DateTimeLocal>,
@@ -58,6 +66,14 @@ pub struct ExecHistoryRelatedScheduleModel {
     pub updated_time: DateTimeLocal,
     pub schedule_name: String,
     pub is_online: bool,
+    pub rerun_of: u64,
+    pub run_id: String,
+    pub parent_run_id: String,
+    pub run_kind: String,
+    pub exec_context: Option<serde_json::Value>,
+    pub timeout_action: String,
+    pub output_truncated: bool,
+    pub output_url: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromQueryResult)]
@@ -85,8 +101,23 @@ pub struct JobRelatedExecutorModel {
     pub created_user: String,
     pub updated_user: String,
     pub display_on_dashboard: bool,
+    pub is_disabled: bool,
+    pub is_singleton: bool,
+    pub placement: String,
+    pub dedup_window_secs: u32,
+    pub offline_policy: Option<serde_json::Value>,
+    pub quorum: Option<serde_json::Value>,
+    pub confirmation: Option<serde_json::Value>,
     pub completed_callback: Option<serde_json::Value>,
     pub args: Option<serde_json::Value>,
+    pub http_check: Option<serde_json::Value>,
+    pub output_parsers: Option<serde_json::Value>,
+    pub exit_code_mapping: Option<serde_json::Value>,
+    pub bundle_option: Option<serde_json::Value>,
+    pub supervisor_option: Option<serde_json::Value>,
+    pub timeout_action: Option<serde_json::Value>,
+    pub output_limit: Option<serde_json::Value>,
+    pub version: u32,
     pub created_time: DateTimeLocal,
     pub updated_time: DateTimeLocal,
 }
@@ -105,6 +136,7 @@ pub struct BundleScriptRelatedExecutorModel {
     pub created_user: String,
     pub updated_user: String,
     pub args: Option<serde_json::Value>,
+    pub version: u32,
     pub created_time: DateTimeLocal,
     pub updated_time: DateTimeLocal,
 }
@@ -127,6 +159,7 @@ pub struct JobTimerRelatedJobModel {
     pub info: String,
     pub created_user: String,
     pub updated_user: String,
+    pub version: u32,
     pub created_time: DateTimeLocal,
     pub updated_time: DateTimeLocal,
 }
@@ -143,6 +176,56 @@ pub struct DispatchTarget {
 pub struct DispatchData {
     pub target: Vec<DispatchTarget>,
     pub params: DispatchJobParams,
+    /// time before the job was sent to comet, used to compute the dispatch to start latency
+    #[serde(default)]
+    pub dispatch_time: Option<DateTimeLocal>,
+    /// job of the instances dispatched with overrides, keyed by instance id
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub target_jobs: HashMap<String, BaseJob>,
+}
+
+impl DispatchData {
+    /// dispatch params sent to the instance, each call is a new dispatch with its own dispatch id
+    pub fn params_for(&self, instance_id: &str) -> DispatchJobParams {
+        let mut params = self.params.clone();
+        if let Some(base_job) = self.target_jobs.get(instance_id) {
+            params.base_job = base_job.clone();
+        }
+        params.instance_id = Some(instance_id.to_string());
+        params.dispatch_id = IdGenerator::get_dispatch_uid();
+        params
+    }
+}
+
+/// parameters of a single instance overriding the ones of the dispatch, eg: a shard id
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct InstanceOverride {
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// merged into the actual args of the job
+    #[serde(default)]
+    pub args: Option<Value>,
+    #[serde(default)]
+    pub work_dir: Option<String>,
+}
+
+impl InstanceOverride {
+    pub fn is_empty(&self) -> bool {
+        self.env.is_empty() && self.args.is_none() && self.work_dir.is_none()
+    }
+
+    /// actual args of the job with the args of the instance applied
+    pub fn merge_args(&self, actual_args: Option<&Value>) -> Option<Value> {
+        let Some(Value::Object(args)) = &self.args else {
+            return actual_args.cloned();
+        };
+        let mut ret = actual_args
+            .filter(|v| v.is_object())
+            .cloned()
+            .unwrap_or(json!({}));
+        ret.as_object_mut().unwrap().extend(args.clone());
+        Some(ret)
+    }
 }
 
 impl TryFrom<Value> for DispatchData {
@@ -186,6 +269,9 @@ pub struct BundleScriptResult {
     pub stderr: Option<String>,
     pub eval_err: Option<String>,
     pub result: bool,
+    /// the order the script was started in, none if it was skipped
+    #[serde(default)]
+    pub seq: Option<usize>,
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
@@ -206,6 +292,12 @@ pub struct DispatchResult {
     pub response: serde_json::Value,
     pub has_err: bool,
     pub err: Option<String>,
+    /// comet which the job was dispatched through, empty if the agent is not registered
+    #[serde(default)]
+    pub comet_addr: String,
+    /// stage of the canary dispatch the result belongs to, none without canary
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canary: Option<super::canary::CanaryStage>,
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
@@ -266,11 +358,43 @@ pub struct JobSupervisorRelatedJobModel {
     pub updated_time: DateTimeLocal,
 }
 
+/// outcome of one job of a bulk operation
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct BulkItemResult {
+    pub eid: String,
+    pub ok: bool,
+    /// the reason of the failure
+    pub message: String,
+    /// id of the created record, eg: the schedule of a dispatch
+    pub id: u64,
+}
+
+impl BulkItemResult {
+    pub fn ok(eid: &str, id: u64) -> Self {
+        Self {
+            eid: eid.to_string(),
+            ok: true,
+            id,
+            ..Default::default()
+        }
+    }
+
+    pub fn err(eid: &str, message: impl ToString) -> Self {
+        Self {
+            eid: eid.to_string(),
+            ok: false,
+            message: message.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromQueryResult)]
 pub struct TeamMemberModel {
     pub user_id: String,
     pub username: String,
     pub is_admin: bool,
+    pub group_id: u64,
     pub created_time: DateTimeLocal,
 }
 
@@ -290,6 +414,12 @@ pub struct ScheduleHistoryJobTeamModel {
     pub dispatch_data: Option<serde_json::Value>,
     pub snapshot_data: Option<serde_json::Value>,
     pub actual_args: Option<serde_json::Value>,
+    pub placement: Option<serde_json::Value>,
+    pub quorum_status: String,
+    pub quorum_required: u32,
+    pub target_total: u32,
+    pub success_total: u32,
+    pub failure_total: u32,
     pub created_user: String,
     pub updated_user: String,
     pub created_time: DateTimeLocal,
@@ -306,6 +436,7 @@ pub struct ScheduleJobTeamModel {
     pub team_id: Option<u64>,
     pub team_name: Option<String>,
     pub instance_ids: Option<serde_json::Value>,
+    pub instance_overrides: Option<serde_json::Value>,
     pub dispatch_result: Option<serde_json::Value>,
     pub schedule_type: String,
     pub action: String,
@@ -324,3 +455,495 @@ pub struct JobFormalArg {
     pub val: String,
     pub info: String,
 }
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct CometDispatchStat {
+    pub comet_addr: String,
+    pub dispatch_total: u64,
+    pub dispatch_failed: u64,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct DispatchStatSummary {
+    pub dispatch_total: u64,
+    pub dispatch_failed: u64,
+    /// 1.0 when nothing was dispatched
+    pub success_rate: f64,
+    pub latency_samples: u64,
+    /// dispatch to start latency in milliseconds, rounded up to the histogram bucket bound
+    pub latency_p50: Option<u64>,
+    pub latency_p95: Option<u64>,
+    pub comets: Vec<CometDispatchStat>,
+}
+
+/// resources that can be restored from the recycle bin
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum RecycleResource {
+    Job,
+    Timer,
+    Supervisor,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromQueryResult)]
+pub struct DeletedResourceModel {
+    pub id: u64,
+    pub eid: String,
+    pub name: String,
+    pub job_name: Option<String>,
+    pub team_id: Option<u64>,
+    pub team_name: Option<String>,
+    pub created_user: String,
+    pub deleted_by: String,
+    pub deleted_at: Option<DateTimeLocal>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromQueryResult)]
+pub struct DurationAnomalyModel {
+    pub id: u64,
+    pub eid: String,
+    pub job_name: Option<String>,
+    pub instance_id: String,
+    pub ip: Option<String>,
+    pub schedule_id: String,
+    pub run_id: String,
+    pub exec_history_id: u64,
+    pub duration_ms: u64,
+    pub median_ms: u64,
+    pub created_time: DateTimeLocal,
+}
+
+/// the sla of a job with the name and the team of the job
+#[derive(Debug, Clone, Serialize, Deserialize, FromQueryResult)]
+pub struct JobSlaModel {
+    pub id: u64,
+    pub eid: String,
+    pub job_name: Option<String>,
+    pub team_id: Option<u64>,
+    pub max_duration_secs: u64,
+    pub max_failure_streak: u32,
+    pub min_success_rate: u32,
+    pub is_enabled: bool,
+    pub failure_streak: u32,
+    pub last_end_time: Option<DateTimeLocal>,
+    pub updated_user: String,
+    pub updated_time: DateTimeLocal,
+}
+
+/// runs of a job aggregated by the daily rollup
+#[derive(Debug, Clone, Serialize, Deserialize, FromQueryResult)]
+pub struct JobRunTop {
+    pub eid: String,
+    pub job_name: Option<String>,
+    pub run_total: i64,
+    pub fail_total: i64,
+    pub avg_duration_ms: i64,
+    pub max_duration_ms: i64,
+}
+
+/// runs of an instance aggregated by the daily rollup
+#[derive(Debug, Clone, Serialize, Deserialize, FromQueryResult)]
+pub struct InstanceRunTop {
+    pub instance_id: String,
+    pub ip: Option<String>,
+    pub run_total: i64,
+    pub fail_total: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromQueryResult)]
+pub struct DailyRunTrend {
+    pub stat_date: chrono::NaiveDate,
+    pub run_total: i64,
+    pub fail_total: i64,
+    pub avg_duration_ms: i64,
+}
+
+/// dimension of the usage report, the usage is always attributed to a team
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageGroupBy {
+    #[default]
+    Team,
+    Job,
+    Instance,
+}
+
+/// runtime of the jobs in a month, the eid or instance_id is empty unless grouped by it
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct UsageRecord {
+    pub team_id: u64,
+    pub team_name: String,
+    pub eid: String,
+    pub job_name: String,
+    pub instance_id: String,
+    pub instance_ip: String,
+    pub run_total: u64,
+    pub runtime_secs: f64,
+}
+
+/// result of an inbound trigger invocation
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerStatus {
+    Accepted,
+    Rejected,
+    Failed,
+}
+
+impl TriggerStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TriggerStatus::Accepted => "accepted",
+            TriggerStatus::Rejected => "rejected",
+            TriggerStatus::Failed => "failed",
+        }
+    }
+}
+
+/// webhook payload of the prometheus alertmanager, only the fields used by the remediation
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct AlertmanagerPayload {
+    #[serde(default)]
+    pub receiver: String,
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub alerts: Vec<Alert>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Alert {
+    /// firing or resolved
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    #[serde(default)]
+    pub annotations: HashMap<String, String>,
+    #[serde(default)]
+    pub starts_at: String,
+    #[serde(default)]
+    pub fingerprint: String,
+}
+
+impl Alert {
+    pub fn alertname(&self) -> &str {
+        self.labels
+            .get("alertname")
+            .map(String::as_str)
+            .unwrap_or_default()
+    }
+
+    pub fn is_firing(&self) -> bool {
+        self.status == "firing"
+    }
+
+    /// host of the label, the port of the scrape target is stripped, eg: 10.0.0.1:9100
+    pub fn label_host(&self, name: &str) -> Option<&str> {
+        let v = self.labels.get(name)?.as_str();
+        let host = match v.rsplit_once(':') {
+            Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host,
+            _ => v,
+        };
+        Some(host.trim_start_matches('[').trim_end_matches(']')).filter(|v| !v.is_empty())
+    }
+
+    /// job args of the remediation, eg: {{labels.instance}} or {{annotations.summary}} in the script
+    pub fn to_args(&self) -> Value {
+        json!({
+            "alertname": self.alertname(),
+            "fingerprint": self.fingerprint,
+            "starts_at": self.starts_at,
+            "labels": self.labels,
+            "annotations": self.annotations,
+        })
+    }
+}
+
+/// result of an auto-remediation run
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum RemediationStatus {
+    Dispatched,
+    Skipped,
+    Failed,
+}
+
+impl RemediationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RemediationStatus::Dispatched => "dispatched",
+            RemediationStatus::Skipped => "skipped",
+            RemediationStatus::Failed => "failed",
+        }
+    }
+}
+
+/// rights granted to a team the job is shared with, shared jobs are never editable by the team
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SharePermission {
+    Read,
+    Dispatch,
+}
+
+impl SharePermission {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SharePermission::Read => "read",
+            SharePermission::Dispatch => "dispatch",
+        }
+    }
+}
+
+impl TryFrom<&str> for SharePermission {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "read" => Ok(SharePermission::Read),
+            "dispatch" => Ok(SharePermission::Dispatch),
+            _ => anyhow::bail!("invalid share permission {value}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromQueryResult)]
+pub struct JobShareModel {
+    pub id: u64,
+    pub eid: String,
+    pub team_id: u64,
+    pub team_name: Option<String>,
+    pub permission: String,
+    pub created_user: String,
+    pub created_time: DateTimeLocal,
+}
+
+/// category of a finished run, decided by the exit code mapping of the job
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecResultStatus {
+    Success,
+    Warning,
+    Failure,
+}
+
+impl ExecResultStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExecResultStatus::Success => "success",
+            ExecResultStatus::Warning => "warning",
+            ExecResultStatus::Failure => "failure",
+        }
+    }
+
+    /// exit codes absent from the mapping fall back to 0 as success and others as failure
+    pub fn classify(mapping: &HashMap<i32, ExecResultStatus>, exit_code: i32) -> Self {
+        match mapping.get(&exit_code) {
+            Some(v) => *v,
+            None if exit_code == 0 => ExecResultStatus::Success,
+            None => ExecResultStatus::Failure,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputParserKind {
+    Regex,
+    JsonPath,
+}
+
+/// rule extracting a named metric from the stdout of the job
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+pub struct OutputParser {
+    pub name: String,
+    pub kind: OutputParserKind,
+    /// regex with a `value` group or at least one capture group, or a json path like `$.data.items[0].count`
+    pub expr: String,
+}
+
+impl OutputParser {
+    pub fn check(&self) -> Result<(), anyhow::Error> {
+        if self.name.is_empty() {
+            anyhow::bail!("output parser name cannot be empty");
+        }
+        match self.kind {
+            OutputParserKind::Regex => {
+                regex::Regex::new(&self.expr)?;
+            }
+            OutputParserKind::JsonPath => {
+                parse_json_path(&self.expr)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// extract the metric from the output, the last match wins for regex parser
+    pub fn extract(&self, stdout: &str) -> Option<Value> {
+        match self.kind {
+            OutputParserKind::Regex => {
+                let re = regex::Regex::new(&self.expr).ok()?;
+                let caps = re.captures_iter(stdout).last()?;
+                let m = caps
+                    .name("value")
+                    .or_else(|| caps.get(1))
+                    .or_else(|| caps.get(0))?;
+                let v = m.as_str().trim();
+                Some(
+                    v.parse::<i64>()
+                        .map(Value::from)
+                        .or_else(|_| v.parse::<f64>().map(Value::from))
+                        .unwrap_or(Value::from(v)),
+                )
+            }
+            OutputParserKind::JsonPath => {
+                let path = parse_json_path(&self.expr).ok()?;
+                let doc = serde_json::from_str::<Value>(stdout.trim())
+                    .ok()
+                    .or_else(|| {
+                        stdout
+                            .lines()
+                            .rev()
+                            .find_map(|l| serde_json::from_str::<Value>(l.trim()).ok())
+                    })?;
+                path.iter()
+                    .try_fold(&doc, |v, seg| match seg {
+                        JsonPathSegment::Key(k) => v.get(k),
+                        JsonPathSegment::Index(i) => v.get(i),
+                    })
+                    .cloned()
+            }
+        }
+    }
+}
+
+enum JsonPathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// a subset of json path, only `$`, `.key`, `['key']` and `[n]` are supported
+fn parse_json_path(expr: &str) -> Result<Vec<JsonPathSegment>, anyhow::Error> {
+    let Some(mut rest) = expr.trim().strip_prefix('$') else {
+        anyhow::bail!("json path must start with $");
+    };
+    let mut ret = vec![];
+    while !rest.is_empty() {
+        if let Some(r) = rest.strip_prefix('.') {
+            let end = r.find(['.', '[']).unwrap_or(r.len());
+            if end == 0 {
+                anyhow::bail!("invalid json path {expr}");
+            }
+            ret.push(JsonPathSegment::Key(r[..end].to_string()));
+            rest = &r[end..];
+        } else if let Some(r) = rest.strip_prefix('[') {
+            let Some(end) = r.find(']') else {
+                anyhow::bail!("invalid json path {expr}");
+            };
+            let seg = r[..end].trim();
+            ret.push(match seg.parse::<usize>() {
+                Ok(i) => JsonPathSegment::Index(i),
+                Err(_) => JsonPathSegment::Key(seg.trim_matches(['\'', '"']).to_string()),
+            });
+            rest = &r[end + 1..];
+        } else {
+            anyhow::bail!("invalid json path {expr}");
+        }
+    }
+    Ok(ret)
+}
+
+/// run the parsers against the stdout, None if the job has no parser
+pub fn parse_output(parsers: &[OutputParser], stdout: &str) -> Option<Value> {
+    if parsers.is_empty() {
+        return None;
+    }
+    let ret: serde_json::Map<String, Value> = parsers
+        .iter()
+        .filter_map(|p| p.extract(stdout).map(|v| (p.name.clone(), v)))
+        .collect();
+    Some(Value::Object(ret))
+}
+
+#[test]
+fn test_instance_override_merge_args() {
+    let o = InstanceOverride {
+        args: Some(json!({"shard": 2})),
+        ..Default::default()
+    };
+    let args = o
+        .merge_args(Some(&json!({"shard": 0, "name": "a"})))
+        .unwrap();
+    assert_eq!(args, json!({"shard": 2, "name": "a"}));
+    assert_eq!(o.merge_args(None).unwrap(), json!({"shard": 2}));
+    assert!(InstanceOverride::default().merge_args(None).is_none());
+}
+
+#[test]
+fn test_parse_output() {
+    let parsers = vec![
+        OutputParser {
+            name: "rows".to_string(),
+            kind: OutputParserKind::Regex,
+            expr: r"synced (?<value>\d+) rows".to_string(),
+        },
+        OutputParser {
+            name: "cost".to_string(),
+            kind: OutputParserKind::Regex,
+            expr: r"cost: ([\d.]+)s".to_string(),
+        },
+        OutputParser {
+            name: "first".to_string(),
+            kind: OutputParserKind::JsonPath,
+            expr: "$.data.items[0]['name']".to_string(),
+        },
+        OutputParser {
+            name: "missing".to_string(),
+            kind: OutputParserKind::JsonPath,
+            expr: "$.none".to_string(),
+        },
+    ];
+    parsers.iter().for_each(|p| p.check().unwrap());
+    let stdout =
+        "synced 10 rows\nsynced 42 rows\ncost: 1.5s\n{\"data\":{\"items\":[{\"name\":\"a\"}]}}\n";
+    assert_eq!(
+        parse_output(&parsers, stdout).unwrap(),
+        json!({"rows": 42, "cost": 1.5, "first": "a"})
+    );
+    assert!(parse_output(&[], stdout).is_none());
+    assert!(parse_json_path("data.items").is_err());
+}
+
+#[test]
+fn test_exec_result_status_classify() {
+    let mapping: HashMap<i32, ExecResultStatus> =
+        serde_json::from_value(json!({"1": "success", "2": "warning"})).unwrap();
+    assert_eq!(
+        ExecResultStatus::classify(&mapping, 0),
+        ExecResultStatus::Success
+    );
+    assert_eq!(
+        ExecResultStatus::classify(&mapping, 1),
+        ExecResultStatus::Success
+    );
+    assert_eq!(
+        ExecResultStatus::classify(&mapping, 2),
+        ExecResultStatus::Warning
+    );
+    assert_eq!(
+        ExecResultStatus::classify(&mapping, 127),
+        ExecResultStatus::Failure
+    );
+}
+
+#[test]
+fn test_share_permission() {
+    for v in [SharePermission::Read, SharePermission::Dispatch] {
+        assert_eq!(SharePermission::try_from(v.as_str()).unwrap(), v);
+        assert_eq!(serde_json::to_value(v).unwrap(), json!(v.as_str()));
+    }
+    assert!(SharePermission::try_from("write").is_err());
+}
@@ -0,0 +1,346 @@
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow};
+use chrono::{Local, Months, NaiveDate, TimeDelta};
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, EntityTrait, FromQueryResult, JoinType, QueryFilter, QueryOrder,
+    QuerySelect, QueryTrait,
+};
+use sea_query::{Expr, OnConflict};
+
+use super::{
+    JobLogic,
+    daily_stat::{DURATION_MS, day_start},
+    types::{UsageGroupBy, UsageRecord},
+};
+use crate::{
+    entity::{instance, job, job_exec_history, job_usage_stat, prelude::*, team},
+    logic,
+};
+
+/// longest backfill of the usage rollup, covers the previous month after a long downtime
+pub const USAGE_STAT_BACKFILL_DAYS: i64 = 62;
+
+#[derive(Debug, FromQueryResult)]
+struct DailyUsage {
+    team_id: Option<u64>,
+    eid: String,
+    instance_id: String,
+    run_total: i64,
+    runtime_ms: i64,
+}
+
+#[derive(Debug, FromQueryResult)]
+struct MonthlyUsage {
+    team_id: u64,
+    eid: String,
+    instance_id: String,
+    run_total: i64,
+    runtime_ms: i64,
+}
+
+/// the first day of the month and of the next month, eg: 2025-11
+pub fn parse_usage_month(month: &str) -> Result<(NaiveDate, NaiveDate)> {
+    let start = NaiveDate::parse_from_str(&format!("{month}-01"), "%Y-%m-%d")
+        .map_err(|_| anyhow!("invalid month {month}, expect YYYY-MM"))?;
+    let end = start
+        .checked_add_months(Months::new(1))
+        .ok_or(anyhow!("invalid month {month}"))?;
+    Ok((start, end))
+}
+
+/// sum the usage of the month by the dimension, the heaviest first
+fn group_usage(records: Vec<MonthlyUsage>, group_by: UsageGroupBy) -> Vec<UsageRecord> {
+    let mut groups: HashMap<(u64, String, String), (i64, i64)> = HashMap::new();
+    for v in records {
+        let key = match group_by {
+            UsageGroupBy::Team => (v.team_id, String::new(), String::new()),
+            UsageGroupBy::Job => (v.team_id, v.eid, String::new()),
+            UsageGroupBy::Instance => (v.team_id, String::new(), v.instance_id),
+        };
+        let group = groups.entry(key).or_default();
+        group.0 += v.run_total;
+        group.1 += v.runtime_ms;
+    }
+    let mut list = groups
+        .into_iter()
+        .map(
+            |((team_id, eid, instance_id), (run_total, runtime_ms))| UsageRecord {
+                team_id,
+                eid,
+                instance_id,
+                run_total: run_total.max(0) as u64,
+                runtime_secs: runtime_ms.max(0) as f64 / 1000.0,
+                ..Default::default()
+            },
+        )
+        .collect::<Vec<_>>();
+    list.sort_by(|a, b| {
+        b.runtime_secs.total_cmp(&a.runtime_secs).then_with(|| {
+            (a.team_id, &a.eid, &a.instance_id).cmp(&(b.team_id, &b.eid, &b.instance_id))
+        })
+    });
+    list
+}
+
+impl<'a> JobLogic<'a> {
+    /// roll up the runtime of the runs into job_usage_stat by the day they ended,
+    /// the usage is attributed to the team the job belongs to at the rollup.
+    /// yesterday is recomputed because the runs may end after the last rollup of the day
+    pub async fn rollup_usage_stat(&self) -> Result<u64> {
+        let today = Local::now().date_naive();
+        let earliest = today - TimeDelta::days(USAGE_STAT_BACKFILL_DAYS);
+
+        let last = JobUsageStat::find()
+            .order_by_desc(job_usage_stat::Column::StatDate)
+            .one(&self.ctx.db)
+            .await?
+            .map(|v| v.stat_date);
+
+        let mut day = last.map_or(earliest, |v| (v - TimeDelta::days(1)).max(earliest));
+        let mut n = 0;
+        while day <= today {
+            self.rollup_usage_bucket(day).await?;
+            day += TimeDelta::days(1);
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    async fn rollup_usage_bucket(&self, day: NaiveDate) -> Result<()> {
+        let records = JobExecHistory::find()
+            .select_only()
+            .column_as(job::Column::TeamId, "team_id")
+            .column(job_exec_history::Column::Eid)
+            .column(job_exec_history::Column::InstanceId)
+            .column_as(job_exec_history::Column::Id.count(), "run_total")
+            .expr_as(
+                Expr::cust(format!("cast(sum({DURATION_MS}) as signed)")),
+                "runtime_ms",
+            )
+            .join_rev(
+                JoinType::LeftJoin,
+                Job::belongs_to(JobExecHistory)
+                    .from(job::Column::Eid)
+                    .to(job_exec_history::Column::Eid)
+                    .into(),
+            )
+            .filter(job_exec_history::Column::EndTime.gte(day_start(day)))
+            .filter(job_exec_history::Column::EndTime.lt(day_start(day + TimeDelta::days(1))))
+            .filter(job_exec_history::Column::StartTime.is_not_null())
+            .group_by(job_exec_history::Column::Eid)
+            .group_by(job_exec_history::Column::InstanceId)
+            .group_by(job::Column::TeamId)
+            .into_model::<DailyUsage>()
+            .all(&self.ctx.db)
+            .await?;
+
+        for v in records {
+            JobUsageStat::insert(job_usage_stat::ActiveModel {
+                stat_date: Set(day),
+                team_id: Set(v.team_id.unwrap_or_default()),
+                eid: Set(v.eid),
+                instance_id: Set(v.instance_id),
+                run_total: Set(v.run_total as u32),
+                runtime_ms: Set(v.runtime_ms.max(0) as u64),
+                ..Default::default()
+            })
+            .on_conflict(
+                OnConflict::columns([
+                    job_usage_stat::Column::StatDate,
+                    job_usage_stat::Column::Eid,
+                    job_usage_stat::Column::InstanceId,
+                ])
+                .update_columns([
+                    job_usage_stat::Column::TeamId,
+                    job_usage_stat::Column::RunTotal,
+                    job_usage_stat::Column::RuntimeMs,
+                ])
+                .to_owned(),
+            )
+            .exec(&self.ctx.db)
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// usage of the month grouped by team, job or instance. the job managers see all the teams,
+    /// the others only the teams they are members of
+    pub async fn get_usage_report(
+        &self,
+        user_info: &logic::types::UserInfo,
+        month: &str,
+        group_by: UsageGroupBy,
+        team_id: Option<u64>,
+    ) -> Result<Vec<UsageRecord>> {
+        let (start, end) = parse_usage_month(month)?;
+        let team_ids = if self.ctx.can_manage_job(&user_info.user_id).await? {
+            None
+        } else {
+            Some(
+                self.ctx
+                    .service()
+                    .team
+                    .get_my_teams(&user_info.user_id)
+                    .await?
+                    .into_iter()
+                    .map(|v| v.id)
+                    .collect::<Vec<u64>>(),
+            )
+        };
+
+        let records = JobUsageStat::find()
+            .select_only()
+            .column(job_usage_stat::Column::TeamId)
+            .column(job_usage_stat::Column::Eid)
+            .column(job_usage_stat::Column::InstanceId)
+            .expr_as(
+                Expr::cust("cast(sum(`job_usage_stat`.`run_total`) as signed)"),
+                "run_total",
+            )
+            .expr_as(
+                Expr::cust("cast(sum(`job_usage_stat`.`runtime_ms`) as signed)"),
+                "runtime_ms",
+            )
+            .filter(job_usage_stat::Column::StatDate.gte(start))
+            .filter(job_usage_stat::Column::StatDate.lt(end))
+            .apply_if(team_ids, |query, v| {
+                query.filter(job_usage_stat::Column::TeamId.is_in(v))
+            })
+            .apply_if(team_id, |query, v| {
+                query.filter(job_usage_stat::Column::TeamId.eq(v))
+            })
+            .group_by(job_usage_stat::Column::TeamId)
+            .group_by(job_usage_stat::Column::Eid)
+            .group_by(job_usage_stat::Column::InstanceId)
+            .into_model::<MonthlyUsage>()
+            .all(&self.ctx.db)
+            .await?;
+
+        let mut list = group_usage(records, group_by);
+        self.fill_usage_names(&mut list).await?;
+        Ok(list)
+    }
+
+    /// the names of the teams, jobs and instances, the deleted jobs keep their name
+    async fn fill_usage_names(&self, list: &mut [UsageRecord]) -> Result<()> {
+        let teams: HashMap<u64, String> = Team::find()
+            .filter(team::Column::Id.is_in(list.iter().map(|v| v.team_id)))
+            .all(&self.ctx.db)
+            .await?
+            .into_iter()
+            .map(|v| (v.id, v.name))
+            .collect();
+        let jobs: HashMap<String, String> = Job::find()
+            .select_only()
+            .column(job::Column::Eid)
+            .column(job::Column::Name)
+            .filter(
+                job::Column::Eid.is_in(
+                    list.iter()
+                        .filter(|v| !v.eid.is_empty())
+                        .map(|v| v.eid.as_str()),
+                ),
+            )
+            .into_tuple::<(String, String)>()
+            .all(&self.ctx.db)
+            .await?
+            .into_iter()
+            .collect();
+        let instances: HashMap<String, String> = Instance::find()
+            .select_only()
+            .column(instance::Column::InstanceId)
+            .column(instance::Column::Ip)
+            .filter(
+                instance::Column::InstanceId.is_in(
+                    list.iter()
+                        .filter(|v| !v.instance_id.is_empty())
+                        .map(|v| v.instance_id.as_str()),
+                ),
+            )
+            .into_tuple::<(String, String)>()
+            .all(&self.ctx.db)
+            .await?
+            .into_iter()
+            .collect();
+
+        for v in list.iter_mut() {
+            v.team_name = teams.get(&v.team_id).cloned().unwrap_or_default();
+            v.job_name = jobs.get(&v.eid).cloned().unwrap_or_default();
+            v.instance_ip = instances.get(&v.instance_id).cloned().unwrap_or_default();
+        }
+        Ok(())
+    }
+
+    /// the usage report as csv, for the charge back outside the scheduler
+    pub fn usage_report_csv(list: &[UsageRecord]) -> Result<Vec<u8>> {
+        let mut w = csv::Writer::from_writer(vec![]);
+        w.write_record([
+            "team_id",
+            "team_name",
+            "eid",
+            "job_name",
+            "instance_id",
+            "instance_ip",
+            "run_total",
+            "runtime_secs",
+        ])?;
+        for v in list {
+            w.write_record([
+                v.team_id.to_string(),
+                v.team_name.clone(),
+                v.eid.clone(),
+                v.job_name.clone(),
+                v.instance_id.clone(),
+                v.instance_ip.clone(),
+                v.run_total.to_string(),
+                format!("{:.3}", v.runtime_secs),
+            ])?;
+        }
+        Ok(w.into_inner()?)
+    }
+}
+
+#[test]
+fn test_group_usage() {
+    assert_eq!(
+        parse_usage_month("2025-12").unwrap(),
+        (
+            NaiveDate::from_ymd_opt(2025, 12, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2026, 1, 1).unwrap()
+        )
+    );
+    assert!(parse_usage_month("2025-13").is_err());
+
+    let records = || {
+        [
+            (1, "a", "i1", 2, 3000),
+            (1, "a", "i2", 1, 500),
+            (2, "b", "i1", 4, 8000),
+        ]
+        .map(
+            |(team_id, eid, instance_id, run_total, runtime_ms)| MonthlyUsage {
+                team_id,
+                eid: eid.to_string(),
+                instance_id: instance_id.to_string(),
+                run_total,
+                runtime_ms,
+            },
+        )
+        .into_iter()
+        .collect::<Vec<_>>()
+    };
+    let list = group_usage(records(), UsageGroupBy::Team);
+    assert_eq!(
+        list.iter()
+            .map(|v| (v.team_id, v.run_total, v.runtime_secs))
+            .collect::<Vec<_>>(),
+        vec![(2, 4, 8.0), (1, 3, 3.5)]
+    );
+    let list = group_usage(records(), UsageGroupBy::Instance);
+    assert_eq!(list.len(), 3);
+    assert!(list.iter().all(|v| v.eid.is_empty()));
+
+    let csv = String::from_utf8(JobLogic::usage_report_csv(&list[..1]).unwrap()).unwrap();
+    assert_eq!(csv.lines().nth(1), Some("2,,,,i1,,4,8.000"));
+}
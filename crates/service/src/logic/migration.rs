@@ -5,6 +5,7 @@ use crate::state::AppContext;
 
 use super::types;
 
+pub mod install;
 mod v100;
 
 #[derive(Clone)]
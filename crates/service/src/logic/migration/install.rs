@@ -0,0 +1,257 @@
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::{Context, Result};
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, ConnectOptions, ConnectionTrait, Database, DbConn, EntityTrait,
+    QueryFilter, Statement,
+};
+use serde::Serialize;
+
+use crate::entity::{executor, prelude::Executor};
+
+/// result of a check run before the installation
+#[derive(Clone, Debug, Serialize, Default)]
+pub struct PreflightCheck {
+    pub name: String,
+    pub passed: bool,
+    pub message: String,
+}
+
+impl PreflightCheck {
+    fn new(name: &str, ret: Result<String>) -> Self {
+        match ret {
+            Ok(message) => Self {
+                name: name.to_string(),
+                passed: true,
+                message,
+            },
+            Err(e) => Self {
+                name: name.to_string(),
+                passed: false,
+                message: format!("{e:#}"),
+            },
+        }
+    }
+}
+
+/// executors optionally created by the installation, (name, command, platform, info)
+const SEED_EXECUTORS: [(&str, &str, &str, &str); 4] = [
+    ("sh", "sh -c", "linux", "run posix shell script"),
+    ("python3", "python3 -c", "linux", "run python3 script"),
+    ("node", "node -e", "linux", "run nodejs script"),
+    (
+        "powershell",
+        "powershell -Command",
+        "windows",
+        "run windows powershell script",
+    ),
+];
+
+/// oldest servers supporting the json columns, (mysql, mariadb)
+const MIN_MYSQL_VERSION: (u64, u64, u64) = (5, 7, 8);
+const MIN_MARIADB_VERSION: (u64, u64, u64) = (10, 2, 7);
+
+pub async fn connect_database(database_url: &str) -> Result<DbConn> {
+    let mut opt = ConnectOptions::new(database_url);
+    opt.connect_timeout(Duration::from_secs(5))
+        .sqlx_logging(false);
+    Database::connect(opt)
+        .await
+        .context("failed connect database")
+}
+
+/// check the database and redis before the installation, the failed checks carry the reason
+pub async fn preflight(database_url: &str, redis_url: &str) -> Vec<PreflightCheck> {
+    let mut checks = match connect_database(database_url).await {
+        Ok(conn) => vec![
+            PreflightCheck::new("database_version", check_database_version(&conn).await),
+            PreflightCheck::new("database_charset", check_database_charset(&conn).await),
+            PreflightCheck::new("index_length", check_index_length(&conn).await),
+        ],
+        Err(e) => vec![PreflightCheck::new("database", Err(e))],
+    };
+    checks.push(PreflightCheck::new("redis", check_redis(redis_url).await));
+    checks
+}
+
+async fn query_string(conn: &DbConn, sql: &str) -> Result<String> {
+    conn.query_one(Statement::from_string(
+        conn.get_database_backend(),
+        sql.to_string(),
+    ))
+    .await?
+    .ok_or(anyhow::anyhow!("no result of {sql}"))?
+    .try_get_by_index::<String>(0)
+    .map_err(Into::into)
+}
+
+async fn check_database_version(conn: &DbConn) -> Result<String> {
+    let version = query_string(conn, "SELECT VERSION()").await?;
+    check_version(&version)?;
+    Ok(version)
+}
+
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let mut parts = version
+        .split(|c: char| !c.is_ascii_digit() && c != '.')
+        .next()?
+        .split('.')
+        .map(|v| v.parse::<u64>().ok());
+    Some((
+        parts.next()??,
+        parts.next()??,
+        parts.next().flatten().unwrap_or(0),
+    ))
+}
+
+/// eg: 8.0.36, 5.7.44-log, 10.6.12-MariaDB-1:10.6.12+maria~ubu2004
+fn check_version(version: &str) -> Result<()> {
+    let got = parse_version(version).ok_or(anyhow::anyhow!("unknown version {version}"))?;
+    let (server, min) = if version.to_lowercase().contains("mariadb") {
+        ("MariaDB", MIN_MARIADB_VERSION)
+    } else {
+        ("MySQL", MIN_MYSQL_VERSION)
+    };
+    if got < min {
+        anyhow::bail!(
+            "{server} {version} is not supported, require {}.{}.{} or later",
+            min.0,
+            min.1,
+            min.2
+        );
+    }
+    Ok(())
+}
+
+async fn check_database_charset(conn: &DbConn) -> Result<String> {
+    let charset = query_string(conn, "SELECT @@character_set_database").await?;
+    if charset != "utf8mb4" {
+        anyhow::bail!(
+            "the charset of the database is {charset}, require utf8mb4, eg: ALTER DATABASE `jiascheduler` CHARACTER SET utf8mb4 COLLATE utf8mb4_general_ci"
+        );
+    }
+    Ok(charset)
+}
+
+async fn check_index_length(conn: &DbConn) -> Result<String> {
+    let rows = conn
+        .query_all(Statement::from_string(
+            conn.get_database_backend(),
+            "SHOW VARIABLES WHERE Variable_name IN ('innodb_large_prefix', 'innodb_file_format', 'innodb_default_row_format')".to_string(),
+        ))
+        .await?;
+    let mut vars = HashMap::new();
+    for row in rows {
+        vars.insert(
+            row.try_get_by_index::<String>(0)?,
+            row.try_get_by_index::<String>(1)?,
+        );
+    }
+    check_index_vars(&vars)?;
+    Ok("index keys of utf8mb4 columns can exceed 767 bytes".to_string())
+}
+
+/// the utf8mb4 unique keys exceed 767 bytes, which fails with
+/// "Specified key was too long" unless the large index prefix is enabled
+fn check_index_vars(vars: &HashMap<String, String>) -> Result<()> {
+    let is = |name: &str, values: &[&str]| {
+        vars.get(name)
+            .is_some_and(|v| values.iter().any(|x| v.eq_ignore_ascii_case(x)))
+    };
+    if is("innodb_large_prefix", &["OFF", "0"]) {
+        anyhow::bail!("innodb_large_prefix is off, require SET GLOBAL innodb_large_prefix = ON");
+    }
+    if is("innodb_file_format", &["Antelope"]) {
+        anyhow::bail!(
+            "innodb_file_format is Antelope, require SET GLOBAL innodb_file_format = Barracuda"
+        );
+    }
+    if is("innodb_default_row_format", &["compact", "redundant"]) {
+        anyhow::bail!(
+            "innodb_default_row_format is {}, require SET GLOBAL innodb_default_row_format = DYNAMIC",
+            vars["innodb_default_row_format"]
+        );
+    }
+    Ok(())
+}
+
+async fn check_redis(redis_url: &str) -> Result<String> {
+    let client = redis::Client::open(redis_url).context("invalid redis url")?;
+    let mut conn = tokio::time::timeout(
+        Duration::from_secs(5),
+        client.get_multiplexed_async_connection(),
+    )
+    .await
+    .context("connect redis timeout")?
+    .context("failed connect redis")?;
+    let pong: String = redis::cmd("PING").query_async(&mut conn).await?;
+    Ok(pong)
+}
+
+/// true if no migration was applied, a failed installation can be rolled back then
+pub async fn is_fresh_database(conn: &DbConn) -> Result<bool> {
+    let tables = query_string(
+        conn,
+        "SELECT CAST(COUNT(*) AS CHAR) FROM information_schema.tables WHERE table_schema = DATABASE() AND table_name = 'seaql_migrations'",
+    )
+    .await?;
+    Ok(tables == "0")
+}
+
+/// create the missing seed executors, returns the names of the created ones
+pub async fn seed_executors(conn: &DbConn) -> Result<Vec<String>> {
+    let exists: Vec<String> = Executor::find()
+        .filter(executor::Column::Name.is_in(SEED_EXECUTORS.map(|v| v.0)))
+        .all(conn)
+        .await?
+        .into_iter()
+        .map(|v| v.name)
+        .collect();
+
+    let mut created = vec![];
+    for (name, command, platform, info) in SEED_EXECUTORS {
+        if exists.iter().any(|v| v == name) {
+            continue;
+        }
+        Executor::insert(executor::ActiveModel {
+            name: Set(name.to_string()),
+            command: Set(command.to_string()),
+            platform: Set(platform.to_string()),
+            info: Set(info.to_string()),
+            created_user: Set("system".to_string()),
+            updated_user: Set("system".to_string()),
+            ..Default::default()
+        })
+        .exec(conn)
+        .await?;
+        created.push(name.to_string());
+    }
+    Ok(created)
+}
+
+#[test]
+fn test_preflight_checks() {
+    assert!(check_version("8.0.36").is_ok());
+    assert!(check_version("5.7.44-log").is_ok());
+    assert!(check_version("5.6.51").is_err());
+    assert!(check_version("10.6.12-MariaDB-1:10.6.12+maria~ubu2004").is_ok());
+    assert!(check_version("10.1.48-MariaDB").is_err());
+    assert!(check_version("unknown").is_err());
+
+    let vars = |list: &[(&str, &str)]| -> HashMap<String, String> {
+        list.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    };
+    assert!(check_index_vars(&vars(&[])).is_ok());
+    assert!(check_index_vars(&vars(&[("innodb_default_row_format", "dynamic")])).is_ok());
+    assert!(check_index_vars(&vars(&[("innodb_default_row_format", "COMPACT")])).is_err());
+    assert!(check_index_vars(&vars(&[("innodb_large_prefix", "OFF")])).is_err());
+    assert!(
+        check_index_vars(&vars(&[
+            ("innodb_large_prefix", "ON"),
+            ("innodb_file_format", "Antelope")
+        ]))
+        .is_err()
+    );
+}
@@ -1,12 +1,29 @@
-use sea_orm::ActiveValue::{self, NotSet, Set};
+use std::fmt;
 
+use anyhow::{Result, anyhow};
+use sea_orm::{
+    ActiveModelBehavior, ActiveModelTrait,
+    ActiveValue::{self, NotSet, Set},
+    ColumnTrait, ConnectionTrait, EntityTrait, IdenStatic, IntoActiveModel, Iterable, ModelTrait,
+    QueryFilter,
+    sea_query::Expr,
+};
+
+pub mod agent_config;
+pub mod backup;
+pub mod blackout;
+pub mod comet_group;
+pub mod credential;
 pub mod executor;
 pub mod instance;
 pub mod job;
 pub mod migration;
+pub mod report;
 pub mod role;
+pub mod security;
 pub mod ssh;
 pub mod tag;
+pub mod task;
 pub mod team;
 pub mod types;
 pub mod user;
@@ -23,3 +40,94 @@ where
         NotSet
     }
 }
+
+/// the record was updated by someone else after the client read it
+#[derive(Debug)]
+pub struct VersionConflict {
+    pub current_version: u32,
+    /// fields whose saved value differs from the one being saved
+    pub fields: Vec<String>,
+}
+
+impl fmt::Display for VersionConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "current version is {}, conflicting fields: {}",
+            self.current_version,
+            self.fields.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for VersionConflict {}
+
+/// columns maintained by the server, they are not reported as conflicts
+const UNTRACKED_COLUMNS: [&str; 6] = [
+    "id",
+    "version",
+    "created_user",
+    "updated_user",
+    "created_time",
+    "updated_time",
+];
+
+/// insert the record, or update it only if its version is still expected_version.
+/// the version is increased on every update, a stale update fails with VersionConflict
+pub async fn save_versioned<A, C>(
+    db: &C,
+    mut active_model: A,
+    id_col: <A::Entity as EntityTrait>::Column,
+    version_col: <A::Entity as EntityTrait>::Column,
+    expected_version: Option<u32>,
+) -> Result<A>
+where
+    A: ActiveModelTrait + ActiveModelBehavior + Send,
+    <A::Entity as EntityTrait>::Model: IntoActiveModel<A>,
+    C: ConnectionTrait,
+{
+    let (Set(id) | ActiveValue::Unchanged(id)) = active_model.get(id_col) else {
+        return Ok(active_model.save(db).await?);
+    };
+    let Some(expected_version) = expected_version else {
+        anyhow::bail!("version is required to update the record");
+    };
+
+    let ret = A::Entity::update_many()
+        .set(active_model.clone())
+        .col_expr(version_col, Expr::col(version_col).add(1))
+        .filter(id_col.eq(id.clone()))
+        .filter(version_col.eq(expected_version))
+        .exec(db)
+        .await?;
+
+    if ret.rows_affected == 0 {
+        let current = A::Entity::find()
+            .filter(id_col.eq(id))
+            .one(db)
+            .await?
+            .ok_or(anyhow!("cannot found the record"))?;
+
+        let fields = <A::Entity as EntityTrait>::Column::iter()
+            .filter(|c| !UNTRACKED_COLUMNS.contains(&c.as_str()))
+            .filter(|c| match active_model.get(*c) {
+                Set(v) => v != current.get(*c),
+                _ => false,
+            })
+            .map(|c| c.as_str().to_string())
+            .collect();
+
+        let current_version = match current.get(version_col) {
+            sea_orm::Value::Unsigned(Some(v)) => v,
+            _ => 0,
+        };
+        return Err(VersionConflict {
+            current_version,
+            fields,
+        }
+        .into());
+    }
+
+    active_model.set(version_col, (expected_version + 1).into());
+    Ok(active_model)
+}
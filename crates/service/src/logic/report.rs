@@ -0,0 +1,603 @@
+//! scheduled reports rendered by the leader and posted to the channels of the report.
+//! failure_summary lists the failing jobs of the team from the daily rollups,
+//! instance_availability the share of the period each instance was online, computed from
+//! the status changes recorded by the instance status subscriber of the bus
+mod pdf;
+
+use std::{collections::HashMap, str::FromStr};
+
+use anyhow::Result;
+use chrono::{DateTime, Local, TimeDelta};
+use croner::Cron;
+use handlebars::Handlebars;
+use rustc_serialize::base64::{STANDARD, ToBase64};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, FromQueryResult, JoinType,
+    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, QueryTrait,
+};
+use sea_query::{Expr, Order};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::error;
+
+use crate::{
+    entity::{instance, instance_status_log, job, job_daily_stat, prelude::*, report},
+    state::AppContext,
+};
+
+pub const REPORT_FAILURE_SUMMARY: &str = "failure_summary";
+pub const REPORT_INSTANCE_AVAILABILITY: &str = "instance_availability";
+pub const REPORT_FORMAT_HTML: &str = "html";
+pub const REPORT_FORMAT_PDF: &str = "pdf";
+
+/// rows of the table of a report, the most failing or the least available first
+const MAX_REPORT_ROWS: usize = 100;
+const MAX_CELL_CHARS: usize = 40;
+
+const REPORT_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{{title}}</title>
+<style>
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; }
+th, td { padding: 6px 12px; border-bottom: 1px solid #ddd; text-align: left; }
+</style>
+</head>
+<body>
+<h1>{{title}}</h1>
+<p>{{since}} - {{until}}</p>
+{{#each summary}}<p>{{this}}</p>
+{{/each}}
+<table>
+<tr>{{#each columns}}<th>{{this}}</th>{{/each}}</tr>
+{{#each rows}}<tr>{{#each this}}<td>{{this}}</td>{{/each}}</tr>
+{{/each}}
+</table>
+<p>generated at {{generated_time}}</p>
+</body>
+</html>"#;
+
+/// webhook the rendered report is posted to
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ReportChannel {
+    pub url: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ReportTable {
+    pub title: String,
+    pub since: String,
+    pub until: String,
+    pub summary: Vec<String>,
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    pub generated_time: String,
+}
+
+pub struct RenderedReport {
+    pub content_type: &'static str,
+    pub filename: String,
+    pub content: Vec<u8>,
+}
+
+#[derive(Debug, FromQueryResult)]
+struct JobFailure {
+    eid: String,
+    job_name: String,
+    run_total: i64,
+    fail_total: i64,
+}
+
+/// the first time matching the cron expression after the time
+pub fn next_run_time(timer_expr: &str, after: DateTime<Local>) -> Result<DateTime<Local>> {
+    let cron = Cron::from_str(timer_expr)
+        .map_err(|e| anyhow::anyhow!("invalid timer expr {timer_expr}, {e}"))?;
+    Ok(cron.find_next_occurrence(&after, false)?)
+}
+
+/// share of [since, until) the instance was online, from its status changes in time order
+/// and its status before since
+pub fn online_ratio(
+    initial_online: bool,
+    changes: &[(DateTime<Local>, bool)],
+    since: DateTime<Local>,
+    until: DateTime<Local>,
+) -> f64 {
+    let total = (until - since).num_seconds();
+    if total <= 0 {
+        return 0.0;
+    }
+    let (mut online, mut from, mut online_secs) = (initial_online, since, 0);
+    for (time, status) in changes {
+        let time = (*time).clamp(since, until);
+        if online {
+            online_secs += (time - from).num_seconds();
+        }
+        (online, from) = (*status, time);
+    }
+    if online {
+        online_secs += (until - from).num_seconds();
+    }
+    online_secs as f64 / total as f64
+}
+
+fn cut_cell(cell: &str) -> String {
+    if cell.chars().count() <= MAX_CELL_CHARS {
+        return cell.to_string();
+    }
+    let mut ret: String = cell.chars().take(MAX_CELL_CHARS - 3).collect();
+    ret.push_str("...");
+    ret
+}
+
+pub fn render_html(table: &ReportTable) -> Result<String> {
+    Ok(Handlebars::new().render_template(REPORT_TEMPLATE, table)?)
+}
+
+/// the lines of the report with the columns aligned, as written to the pdf
+pub fn render_text(table: &ReportTable) -> Vec<String> {
+    let rows: Vec<Vec<String>> = std::iter::once(&table.columns)
+        .chain(table.rows.iter())
+        .map(|row| row.iter().map(|v| cut_cell(v)).collect())
+        .collect();
+    let mut widths = vec![0; table.columns.len()];
+    for row in rows.iter() {
+        for (i, cell) in row.iter().enumerate().take(widths.len()) {
+            widths[i] = widths[i].max(pdf::text_width(cell));
+        }
+    }
+    let format_row = |row: &Vec<String>| {
+        row.iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{cell}{}", " ".repeat(width - pdf::text_width(cell))))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    let mut lines = vec![
+        table.title.clone(),
+        format!("{} - {}", table.since, table.until),
+        String::new(),
+    ];
+    lines.extend(table.summary.iter().cloned());
+    lines.push(String::new());
+    for (i, row) in rows.iter().enumerate() {
+        lines.push(format_row(row));
+        if i == 0 {
+            lines.push(
+                "-".repeat(widths.iter().sum::<usize>() + 2 * widths.len().saturating_sub(1)),
+            );
+        }
+    }
+    lines.push(String::new());
+    lines.push(format!("generated at {}", table.generated_time));
+    lines
+}
+
+pub fn render(table: &ReportTable, format: &str, name: &str) -> Result<RenderedReport> {
+    let filename: String = format!("{name}-{}", table.until)
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect();
+    Ok(match format {
+        REPORT_FORMAT_PDF => RenderedReport {
+            content_type: "application/pdf",
+            filename: format!("{filename}.pdf"),
+            content: pdf::render(&render_text(table)),
+        },
+        _ => RenderedReport {
+            content_type: "text/html; charset=utf-8",
+            filename: format!("{filename}.html"),
+            content: render_html(table)?.into_bytes(),
+        },
+    })
+}
+
+#[derive(Clone)]
+pub struct ReportLogic<'a> {
+    ctx: &'a AppContext,
+}
+
+impl<'a> ReportLogic<'a> {
+    pub fn new(ctx: &'a AppContext) -> Self {
+        Self { ctx }
+    }
+
+    /// the next run time is computed from the timer expr if it is set
+    pub async fn save_report(&self, mut active_model: report::ActiveModel) -> Result<u64> {
+        if let Set(ref timer_expr) = active_model.timer_expr {
+            active_model.next_run_time = Set(Some(next_run_time(timer_expr, Local::now())?));
+        }
+        if let Set(Some(ref channels)) = active_model.channels {
+            let channels: Vec<ReportChannel> = serde_json::from_value(channels.clone())?;
+            if let Some(v) = channels.iter().find(|v| url::Url::parse(&v.url).is_err()) {
+                anyhow::bail!("invalid channel url {}", v.url);
+            }
+        }
+        let model = active_model.save(&self.ctx.db).await?;
+        Ok(model.id.as_ref().to_owned())
+    }
+
+    pub async fn get_report(&self, id: u64) -> Result<Option<report::Model>> {
+        Ok(Report::find_by_id(id).one(&self.ctx.db).await?)
+    }
+
+    /// the reports of the team and the global ones, all of them if team_id is none
+    pub async fn query_report(
+        &self,
+        team_id: Option<u64>,
+        name: Option<String>,
+        page: u64,
+        page_size: u64,
+    ) -> Result<(Vec<report::Model>, u64)> {
+        let select = Report::find()
+            .apply_if(team_id, |q, v| {
+                q.filter(report::Column::TeamId.is_in([0, v]))
+            })
+            .apply_if(name, |q, v| q.filter(report::Column::Name.contains(v)));
+
+        let total = select.clone().count(&self.ctx.db).await?;
+        let list = select
+            .order_by_desc(report::Column::Id)
+            .paginate(&self.ctx.db, page_size)
+            .fetch_page(page)
+            .await?;
+        Ok((list, total))
+    }
+
+    pub async fn delete_report(&self, id: u64) -> Result<u64> {
+        let ret = Report::delete_by_id(id).exec(&self.ctx.db).await?;
+        Ok(ret.rows_affected)
+    }
+
+    /// the data of the period_days of the report ending at until
+    pub async fn build_report(
+        &self,
+        record: &report::Model,
+        until: DateTime<Local>,
+    ) -> Result<ReportTable> {
+        let since = until - TimeDelta::days(record.period_days.max(1) as i64);
+        let mut table = match record.report_type.as_str() {
+            REPORT_FAILURE_SUMMARY => self.failure_summary(record.team_id, since, until).await?,
+            REPORT_INSTANCE_AVAILABILITY => self.instance_availability(since, until).await?,
+            v => anyhow::bail!("unsupported report type {v}"),
+        };
+        table.title = record.name.clone();
+        table.since = since.format("%Y-%m-%d %H:%M").to_string();
+        table.until = until.format("%Y-%m-%d %H:%M").to_string();
+        table.generated_time = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        Ok(table)
+    }
+
+    async fn failure_summary(
+        &self,
+        team_id: u64,
+        since: DateTime<Local>,
+        until: DateTime<Local>,
+    ) -> Result<ReportTable> {
+        let list: Vec<JobFailure> = JobDailyStat::find()
+            .select_only()
+            .column(job_daily_stat::Column::Eid)
+            .expr_as(Expr::cust("max(`job`.`name`)"), "job_name")
+            .expr_as(
+                Expr::cust("cast(sum(`job_daily_stat`.`run_total`) as signed)"),
+                "run_total",
+            )
+            .expr_as(
+                Expr::cust("cast(sum(`job_daily_stat`.`fail_total`) as signed)"),
+                "fail_total",
+            )
+            .join_rev(
+                JoinType::InnerJoin,
+                Job::belongs_to(JobDailyStat)
+                    .from(job::Column::Eid)
+                    .to(job_daily_stat::Column::Eid)
+                    .into(),
+            )
+            .apply_if(Some(team_id).filter(|v| *v != 0), |q, v| {
+                q.filter(job::Column::TeamId.eq(v))
+            })
+            .filter(job::Column::IsDeleted.eq(false))
+            .filter(job_daily_stat::Column::StatDate.gte(since.date_naive()))
+            .filter(job_daily_stat::Column::StatDate.lte(until.date_naive()))
+            .group_by(job_daily_stat::Column::Eid)
+            .order_by(Expr::cust("fail_total"), Order::Desc)
+            .into_model()
+            .all(&self.ctx.db)
+            .await?;
+
+        let run_total: i64 = list.iter().map(|v| v.run_total).sum();
+        let fail_total: i64 = list.iter().map(|v| v.fail_total).sum();
+        let failing: Vec<&JobFailure> = list.iter().filter(|v| v.fail_total > 0).collect();
+        let rate = |fail: i64, run: i64| {
+            if run == 0 {
+                "0.0%".to_string()
+            } else {
+                format!("{:.1}%", fail as f64 * 100.0 / run as f64)
+            }
+        };
+
+        Ok(ReportTable {
+            summary: vec![
+                format!(
+                    "{run_total} runs, {fail_total} failed, failure rate {}",
+                    rate(fail_total, run_total)
+                ),
+                format!("{} of {} jobs failed", failing.len(), list.len()),
+            ],
+            columns: ["job", "eid", "runs", "failed", "failure rate"]
+                .map(String::from)
+                .to_vec(),
+            rows: failing
+                .into_iter()
+                .take(MAX_REPORT_ROWS)
+                .map(|v| {
+                    vec![
+                        v.job_name.clone(),
+                        v.eid.clone(),
+                        v.run_total.to_string(),
+                        v.fail_total.to_string(),
+                        rate(v.fail_total, v.run_total),
+                    ]
+                })
+                .collect(),
+            ..Default::default()
+        })
+    }
+
+    /// an instance without status changes in the period kept its current status,
+    /// one whose first change is online was offline before
+    async fn instance_availability(
+        &self,
+        since: DateTime<Local>,
+        until: DateTime<Local>,
+    ) -> Result<ReportTable> {
+        let instances = Instance::find().all(&self.ctx.db).await?;
+        let logs = InstanceStatusLog::find()
+            .filter(instance_status_log::Column::CreatedTime.gte(since))
+            .filter(instance_status_log::Column::CreatedTime.lt(until))
+            .order_by_asc(instance_status_log::Column::Id)
+            .all(&self.ctx.db)
+            .await?;
+        let mut changes: HashMap<&str, Vec<(DateTime<Local>, bool)>> = HashMap::new();
+        for v in logs.iter() {
+            changes
+                .entry(v.instance_id.as_str())
+                .or_default()
+                .push((v.created_time, v.online));
+        }
+
+        let mut list: Vec<(f64, usize, &instance::Model)> = instances
+            .iter()
+            .map(|v| {
+                let changes = changes
+                    .get(v.instance_id.as_str())
+                    .map_or(&[][..], |v| v.as_slice());
+                let initial_online = changes.first().map_or(v.status == 1, |v| !v.1);
+                let offline_total = changes.iter().filter(|v| !v.1).count();
+                (
+                    online_ratio(initial_online, changes, since, until),
+                    offline_total,
+                    v,
+                )
+            })
+            .collect();
+        list.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let average = if list.is_empty() {
+            0.0
+        } else {
+            list.iter().map(|v| v.0).sum::<f64>() / list.len() as f64
+        };
+        Ok(ReportTable {
+            summary: vec![
+                format!(
+                    "{} instances, average availability {:.2}%",
+                    list.len(),
+                    average * 100.0
+                ),
+                format!(
+                    "{} instances were offline in the period",
+                    list.iter().filter(|v| v.0 < 1.0).count()
+                ),
+            ],
+            columns: [
+                "ip",
+                "namespace",
+                "instance id",
+                "availability",
+                "offline times",
+            ]
+            .map(String::from)
+            .to_vec(),
+            rows: list
+                .into_iter()
+                .take(MAX_REPORT_ROWS)
+                .map(|(ratio, offline_total, v)| {
+                    vec![
+                        v.ip.clone(),
+                        v.namespace.clone(),
+                        v.instance_id.clone(),
+                        format!("{:.2}%", ratio * 100.0),
+                        offline_total.to_string(),
+                    ]
+                })
+                .collect(),
+            ..Default::default()
+        })
+    }
+
+    /// render the report ending now and post it to every channel,
+    /// fails if any channel failed
+    pub async fn deliver_report(&self, record: &report::Model) -> Result<()> {
+        let table = self.build_report(record, Local::now()).await?;
+        let rendered = render(&table, &record.format, &record.name)?;
+        let content = match record.format.as_str() {
+            REPORT_FORMAT_PDF => rendered.content.to_base64(STANDARD),
+            _ => String::from_utf8_lossy(&rendered.content).to_string(),
+        };
+        let body = json!({
+            "event": "report",
+            "report_id": record.id,
+            "name": record.name,
+            "report_type": record.report_type,
+            "team_id": record.team_id,
+            "since": table.since,
+            "until": table.until,
+            "summary": table.summary,
+            "format": record.format,
+            "filename": rendered.filename,
+            "content_type": rendered.content_type,
+            // base64 of the pdf, the html as is
+            "content": content,
+        });
+
+        let channels: Vec<ReportChannel> = match record.channels.clone() {
+            Some(v) => serde_json::from_value(v)?,
+            None => vec![],
+        };
+        let mut errors = vec![];
+        for channel in channels {
+            let ret = self
+                .ctx
+                .http_client
+                .post(&channel.url)
+                .headers(crate::logic::job::JobLogic::callback_headers(Some(
+                    channel.headers,
+                )))
+                .json(&body)
+                .send()
+                .await
+                .and_then(|v| v.error_for_status());
+            if let Err(e) = ret {
+                errors.push(format!("{}: {e}", channel.url));
+            }
+        }
+        if !errors.is_empty() {
+            anyhow::bail!("failed to deliver to {}", errors.join("; "));
+        }
+        Ok(())
+    }
+
+    /// deliver the enabled reports whose next run time passed, run by the leader
+    pub async fn run_due_reports(&self) -> Result<u64> {
+        let now = Local::now();
+        let list = Report::find()
+            .filter(report::Column::IsEnabled.eq(true))
+            .filter(report::Column::NextRunTime.lte(now))
+            .all(&self.ctx.db)
+            .await?;
+
+        let total = list.len() as u64;
+        for record in list {
+            let last_error = match self.deliver_report(&record).await {
+                Ok(_) => String::new(),
+                Err(e) => {
+                    error!("failed deliver report {} - {e:?}", record.name);
+                    e.to_string().chars().take(500).collect()
+                }
+            };
+            report::ActiveModel {
+                id: Set(record.id),
+                last_run_time: Set(Some(now)),
+                last_error: Set(last_error),
+                next_run_time: Set(next_run_time(&record.timer_expr, now).ok()),
+                ..Default::default()
+            }
+            .update(&self.ctx.db)
+            .await?;
+        }
+        Ok(total)
+    }
+
+    pub async fn record_instance_status(
+        &self,
+        instance_id: &str,
+        ip: &str,
+        namespace: &str,
+        online: bool,
+    ) -> Result<()> {
+        InstanceStatusLog::insert(instance_status_log::ActiveModel {
+            instance_id: Set(instance_id.to_string()),
+            ip: Set(ip.to_string()),
+            namespace: Set(namespace.to_string()),
+            online: Set(online),
+            ..Default::default()
+        })
+        .exec(&self.ctx.db)
+        .await?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_online_ratio() {
+    use chrono::TimeZone;
+
+    let since = Local.with_ymd_and_hms(2025, 6, 1, 0, 0, 0).unwrap();
+    let until = since + TimeDelta::hours(10);
+    let at = |h: i64| since + TimeDelta::hours(h);
+
+    assert_eq!(online_ratio(true, &[], since, until), 1.0);
+    assert_eq!(online_ratio(false, &[], since, until), 0.0);
+    assert_eq!(
+        online_ratio(true, &[(at(2), false), (at(4), true)], since, until),
+        0.8
+    );
+    assert_eq!(online_ratio(false, &[(at(5), true)], since, until), 0.5);
+    assert_eq!(online_ratio(true, &[], until, since), 0.0);
+}
+
+#[test]
+fn test_render_report() {
+    let table = ReportTable {
+        title: "weekly <failures>".to_string(),
+        since: "2025-06-01 00:00".to_string(),
+        until: "2025-06-08 00:00".to_string(),
+        summary: vec!["10 runs, 2 failed".to_string()],
+        columns: vec!["job".to_string(), "failed".to_string()],
+        rows: vec![
+            vec!["backup".to_string(), "2".to_string()],
+            vec!["x".repeat(50), "1".to_string()],
+            vec!["数据备份".to_string(), "3".to_string()],
+        ],
+        generated_time: "2025-06-08 09:00:00".to_string(),
+    };
+
+    let html = render_html(&table).unwrap();
+    assert!(html.contains("<h1>weekly &lt;failures&gt;</h1>"));
+    assert!(html.contains("<tr><td>backup</td><td>2</td></tr>"));
+
+    let lines = render_text(&table);
+    assert_eq!(lines[5], format!("{:<40}  failed", "job"));
+    assert_eq!(lines[7], format!("{:<40}  2", "backup"));
+    assert_eq!(lines[8], format!("{}...  1", "x".repeat(37)));
+    // the full width chars take two columns
+    assert_eq!(lines[9], format!("数据备份{}  3", " ".repeat(32)));
+
+    let rendered = render(&table, REPORT_FORMAT_PDF, "weekly failures").unwrap();
+    assert_eq!(rendered.filename, "weekly_failures-2025-06-08_00_00.pdf");
+    assert!(rendered.content.starts_with(b"%PDF"));
+    // the name is written as utf-16 instead of being replaced
+    let content = String::from_utf8(rendered.content).unwrap();
+    assert!(content.contains("<6570636E59074EFD"));
+}
+
+#[test]
+fn test_next_run_time() {
+    use chrono::{Datelike, TimeZone, Timelike};
+
+    let after = Local.with_ymd_and_hms(2025, 6, 2, 10, 0, 0).unwrap();
+    let next = next_run_time("0 0 9 * * Mon", after).unwrap();
+    assert_eq!((next.day(), next.hour()), (9, 9));
+    assert!(next_run_time("every day", after).is_err());
+}
@@ -0,0 +1,152 @@
+//! plain text pdf of the reports, one line per row without any external dependency.
+//! the text is written as utf-16 with the predefined STSong-Light cjk font, which the pdf
+//! readers provide, the ascii chars are half width and the others full width
+
+/// a4 in points
+const PAGE_WIDTH: u32 = 595;
+const PAGE_HEIGHT: u32 = 842;
+const MARGIN: u32 = 40;
+const FONT_SIZE: u32 = 9;
+const LINE_HEIGHT: u32 = 11;
+/// the columns of a line, a half width char is 0.5 of the font size
+pub const LINE_COLUMNS: usize = ((PAGE_WIDTH - 2 * MARGIN) * 2 / FONT_SIZE) as usize;
+const PAGE_LINES: usize = ((PAGE_HEIGHT - 2 * MARGIN) / LINE_HEIGHT) as usize;
+
+/// the columns the char takes in the pdf
+pub fn char_width(c: char) -> usize {
+    if c.is_ascii() { 1 } else { 2 }
+}
+
+/// the columns the text takes in the pdf
+pub fn text_width(text: &str) -> usize {
+    text.chars().map(char_width).sum()
+}
+
+/// the utf-16 hex string of the line cut to LINE_COLUMNS, the control chars are dropped
+fn encode_text(line: &str) -> String {
+    let mut ret = String::with_capacity(line.len() * 4 + 2);
+    let mut width = 0;
+    ret.push('<');
+    for c in line.chars().filter(|c| !c.is_control()) {
+        width += char_width(c);
+        if width > LINE_COLUMNS {
+            break;
+        }
+        let mut buf = [0; 2];
+        for unit in c.encode_utf16(&mut buf) {
+            ret.push_str(&format!("{unit:04X}"));
+        }
+    }
+    ret.push('>');
+    ret
+}
+
+fn page_content(lines: &[String]) -> String {
+    let mut content = format!(
+        "BT\n/F1 {FONT_SIZE} Tf\n{LINE_HEIGHT} TL\n{MARGIN} {} Td\n",
+        PAGE_HEIGHT - MARGIN
+    );
+    for line in lines {
+        content.push_str(&format!("{} Tj T*\n", encode_text(line)));
+    }
+    content.push_str("ET\n");
+    content
+}
+
+/// the lines longer than LINE_CHARS are cut, a new page is started every PAGE_LINES lines
+pub fn render(lines: &[String]) -> Vec<u8> {
+    let pages: Vec<&[String]> = if lines.is_empty() {
+        vec![&[]]
+    } else {
+        lines.chunks(PAGE_LINES).collect()
+    };
+
+    // 1 catalog, 2 pages, 3 font, 4 its cid font, 5 its descriptor, then a page and its content for each page
+    let kids: Vec<String> = (0..pages.len())
+        .map(|i| format!("{} 0 R", 6 + 2 * i))
+        .collect();
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            pages.len()
+        ),
+        "<< /Type /Font /Subtype /Type0 /BaseFont /STSong-Light /Encoding /UniGB-UTF16-H /DescendantFonts [4 0 R] >>".to_string(),
+        // the cids 1 to 95 are the ascii chars
+        "<< /Type /Font /Subtype /CIDFontType0 /BaseFont /STSong-Light /CIDSystemInfo << /Registry (Adobe) /Ordering (GB1) /Supplement 4 >> /FontDescriptor 5 0 R /DW 1000 /W [1 95 500] >>".to_string(),
+        "<< /Type /FontDescriptor /FontName /STSong-Light /Flags 6 /FontBBox [-25 -254 1000 880] /ItalicAngle 0 /Ascent 880 /Descent -120 /CapHeight 880 /StemV 93 >>".to_string(),
+    ];
+    for (i, page) in pages.iter().enumerate() {
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {PAGE_WIDTH} {PAGE_HEIGHT}] /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+            7 + 2 * i
+        ));
+        let content = page_content(page);
+        objects.push(format!(
+            "<< /Length {} >>\nstream\n{content}endstream",
+            content.len()
+        ));
+    }
+
+    let mut pdf = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{} 0 obj\n{object}\nendobj\n", i + 1).as_bytes());
+    }
+
+    let xref = pdf.len();
+    pdf.extend_from_slice(
+        format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes(),
+    );
+    for offset in offsets {
+        pdf.extend_from_slice(format!("{offset:010} 00000 n \n").as_bytes());
+    }
+    pdf.extend_from_slice(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+            objects.len() + 1
+        )
+        .as_bytes(),
+    );
+    pdf
+}
+
+#[test]
+fn test_render_pdf() {
+    let lines: Vec<String> = (0..PAGE_LINES + 1)
+        .map(|i| format!("line (#{i})"))
+        .collect();
+    let text = String::from_utf8(render(&lines)).unwrap();
+    assert!(text.starts_with("%PDF-1.4\n"));
+    assert!(text.ends_with("%%EOF\n"));
+    assert!(text.contains("/Count 2"));
+    assert!(text.contains(&format!("{} Tj T*", encode_text("line (#0)"))));
+
+    // every entry of the xref points at its object
+    let xref = text.rfind("xref\n").unwrap();
+    for (i, entry) in text[xref..].lines().skip(3).take(9).enumerate() {
+        let offset: usize = entry[..10].parse().unwrap();
+        assert!(text[offset..].starts_with(&format!("{} 0 obj", i + 1)));
+    }
+
+    assert!(String::from_utf8(render(&[])).unwrap().contains("/Count 1"));
+}
+
+#[test]
+fn test_encode_text() {
+    assert_eq!(encode_text("a(\\)"), "<00610028005C0029>");
+    // the names outside of ascii are kept, the chars outside of the bmp as surrogate pairs
+    assert_eq!(encode_text("张三 é\t😀"), "<5F204E09002000E9D83DDE00>");
+    assert_eq!(text_width("张三 ab"), 7);
+
+    // a full width char is cut as a whole
+    let line = format!("{}张", "a".repeat(LINE_COLUMNS - 1));
+    assert_eq!(
+        encode_text(&line),
+        encode_text(&"a".repeat(LINE_COLUMNS - 1))
+    );
+    let line = "张".repeat(LINE_COLUMNS);
+    assert_eq!(encode_text(&line).len(), 2 + 4 * (LINE_COLUMNS / 2));
+}
@@ -10,40 +10,70 @@ use sea_query::Expr;
 
 use super::types::Permission;
 use crate::{
+    cache::CACHE_USER_SERVER,
     entity::{instance, instance_role, prelude::*, role, user},
     state::AppContext,
 };
 
-const POLICY_ALLOW_MANAGE_ALL_USER: Permission = Permission {
+pub const POLICY_ALLOW_MANAGE_ALL_USER: Permission = Permission {
     name: "Allow manage all user",
     object: "user",
     action: "manage",
 };
 
-const POLICY_ALLOW_MANAGE_ALL_INSTANCE: Permission = Permission {
+pub const POLICY_ALLOW_MANAGE_ALL_INSTANCE: Permission = Permission {
     name: "Allow manage all instance",
     object: "instance",
     action: "manage",
 };
 
-const POLICY_DO_NOT_ALLOW_CHANGE_DATA: Permission = Permission {
+pub const POLICY_DO_NOT_ALLOW_CHANGE_DATA: Permission = Permission {
     name: "Don't allow changes data ",
     object: "change",
     action: "forbid",
 };
 
-const POLICY_ALLOW_CHANGE_ALL_JOB: Permission = Permission {
+pub const POLICY_ALLOW_CHANGE_ALL_JOB: Permission = Permission {
     name: "Allow manage all job",
     object: "job",
     action: "manage",
 };
 
-const POLICY_ALLOW_UPLOAD_FILE: Permission = Permission {
+pub const POLICY_ALLOW_UPLOAD_FILE: Permission = Permission {
     name: "Allow upload file",
     object: "file",
     action: "upload",
 };
 
+pub const POLICY_ALLOW_DISPATCH_JOB: Permission = Permission {
+    name: "Allow dispatch job",
+    object: "job",
+    action: "dispatch",
+};
+
+pub const POLICY_ALLOW_SSH_INSTANCE: Permission = Permission {
+    name: "Allow ssh and sftp to instance",
+    object: "instance",
+    action: "ssh",
+};
+
+/// object prefix of the tag policies, eg: (role_id, tag:env/prod, access)
+pub const TAG_OBJECT_PREFIX: &str = "tag:";
+pub const TAG_ACTION_ACCESS: &str = "access";
+
+/// the policy is (sub, obj, act)
+pub fn is_tag_policy(policy: &[String]) -> bool {
+    policy_tag(policy).is_some()
+}
+
+/// path of the tag granted by the policy
+pub fn policy_tag(policy: &[String]) -> Option<&str> {
+    match policy {
+        [_, obj, act, ..] if act == TAG_ACTION_ACCESS => obj.strip_prefix(TAG_OBJECT_PREFIX),
+        _ => None,
+    }
+}
+
 pub static PERMISSIONS: LazyLock<Vec<Permission>> = LazyLock::new(|| {
     // vec![
     //     Permission {
@@ -68,6 +98,8 @@ pub static PERMISSIONS: LazyLock<Vec<Permission>> = LazyLock::new(|| {
         POLICY_DO_NOT_ALLOW_CHANGE_DATA,
         POLICY_ALLOW_CHANGE_ALL_JOB,
         POLICY_ALLOW_UPLOAD_FILE,
+        POLICY_ALLOW_DISPATCH_JOB,
+        POLICY_ALLOW_SSH_INSTANCE,
     ]
 });
 
@@ -107,6 +139,7 @@ impl<'a> RoleLogic<'a> {
                 )
                 .exec(&self.ctx.db)
                 .await?;
+            self.ctx.cache.invalidate(CACHE_USER_SERVER).await;
         }
         Ok(active_model.id.as_ref().to_owned())
     }
@@ -150,6 +183,7 @@ impl<'a> RoleLogic<'a> {
                 let update_role = update_role.clone();
                 update_role(v.user_id, role_id.to_string()).await?;
             }
+            self.ctx.cache.invalidate(CACHE_USER_SERVER).await;
 
             affected
         } else {
@@ -232,10 +266,12 @@ impl<'a> RoleLogic<'a> {
                 })
                 .collect::<Vec<instance_role::ActiveModel>>();
 
-            return Ok(InstanceRole::insert_many(data)
+            let last_insert_id = InstanceRole::insert_many(data)
                 .exec(&self.ctx.db)
                 .await?
-                .last_insert_id);
+                .last_insert_id;
+            self.ctx.cache.invalidate(CACHE_USER_SERVER).await;
+            return Ok(last_insert_id);
         }
 
         if let Some(instance_group_ids) = instance_group_ids {
@@ -248,10 +284,12 @@ impl<'a> RoleLogic<'a> {
                 })
                 .collect::<Vec<instance_role::ActiveModel>>();
 
-            Ok(InstanceRole::insert_many(data)
+            let last_insert_id = InstanceRole::insert_many(data)
                 .exec(&self.ctx.db)
                 .await?
-                .last_insert_id)
+                .last_insert_id;
+            self.ctx.cache.invalidate(CACHE_USER_SERVER).await;
+            Ok(last_insert_id)
         } else {
             Ok(0)
         }
@@ -263,7 +301,7 @@ impl<'a> RoleLogic<'a> {
         instance_group_ids: Option<Vec<u64>>,
         instance_ids: Option<Vec<String>>,
     ) -> Result<u64> {
-        Ok(InstanceRole::delete_many()
+        let ret = InstanceRole::delete_many()
             .filter(instance_role::Column::RoleId.eq(role_id))
             .apply_if(instance_ids, |query, v| {
                 query.filter(
@@ -280,8 +318,9 @@ impl<'a> RoleLogic<'a> {
                 )
             })
             .exec(&self.ctx.db)
-            .await?
-            .rows_affected)
+            .await?;
+        self.ctx.cache.invalidate(CACHE_USER_SERVER).await;
+        Ok(ret.rows_affected)
     }
 
     pub async fn is_admin(&self, role_id: u64) -> Result<bool> {
@@ -292,3 +331,14 @@ impl<'a> RoleLogic<'a> {
             .unwrap_or(false))
     }
 }
+
+#[test]
+fn test_policy_tag() {
+    let policy = |v: &[&str]| v.iter().map(|v| v.to_string()).collect::<Vec<String>>();
+    assert_eq!(
+        policy_tag(&policy(&["2", "tag:env/prod", "access"])),
+        Some("env/prod")
+    );
+    assert!(!is_tag_policy(&policy(&["2", "job", "manage"])));
+    assert!(!is_tag_policy(&policy(&["2", "tag:env", "manage"])));
+}
@@ -0,0 +1,117 @@
+use std::net::IpAddr;
+
+use anyhow::Result;
+use automate::SecuritySetting;
+use redis::AsyncCommands;
+
+use crate::state::AppContext;
+
+#[derive(Clone)]
+pub struct SecurityLogic<'a> {
+    ctx: &'a AppContext,
+}
+
+impl<'a> SecurityLogic<'a> {
+    pub fn new(ctx: &'a AppContext) -> Self {
+        Self { ctx }
+    }
+
+    /// the failed attempts are counted per client, so that the other clients cannot lock the account
+    fn failed_key(username: &str, ip: Option<IpAddr>) -> String {
+        format!("jiascheduler:login:failed:{username}:{}", Self::ip_key(ip))
+    }
+
+    fn lock_key(username: &str, ip: Option<IpAddr>) -> String {
+        format!("jiascheduler:login:lock:{username}:{}", Self::ip_key(ip))
+    }
+
+    fn ip_key(ip: Option<IpAddr>) -> String {
+        ip.map_or("unknown".to_string(), |v| v.to_string())
+    }
+
+    pub async fn get_setting(&self) -> Result<SecuritySetting> {
+        automate::Logic::new(self.ctx.redis())
+            .get_security_setting()
+            .await
+    }
+
+    pub async fn save_setting(&self, setting: SecuritySetting) -> Result<()> {
+        automate::comet::types::validate_allowlist(&setting.login_allowlist)?;
+        automate::comet::types::validate_allowlist(&setting.dispatch_allowlist)?;
+        automate::comet::types::validate_allowlist(&setting.trusted_proxies)?;
+        automate::Logic::new(self.ctx.redis())
+            .save_security_setting(setting)
+            .await
+    }
+
+    /// refuse the login when the client ip is not allowed or the account is locked for the client,
+    /// returns the client ip, the forwarded ip is only used if the peer is a trusted proxy
+    pub async fn check_login(
+        &self,
+        username: &str,
+        peer: Option<IpAddr>,
+        forwarded: Option<IpAddr>,
+    ) -> Result<Option<IpAddr>> {
+        let setting = self.get_setting().await?;
+        let ip = setting.client_ip(peer, forwarded);
+        if let Some(ip) = ip
+            && !setting.is_login_allowed(ip)
+        {
+            anyhow::bail!("login from {ip} is not allowed");
+        }
+
+        let mut conn = self.ctx.redis().get_multiplexed_async_connection().await?;
+        let ttl: i64 = conn.ttl(Self::lock_key(username, ip)).await?;
+        if ttl > 0 {
+            anyhow::bail!("too many failed login attempts, please retry after {ttl} seconds");
+        }
+        Ok(ip)
+    }
+
+    /// count the failed attempt of the client and lock the account for it once the limit is reached,
+    /// every further failure doubles the lockout duration
+    pub async fn record_login_failed(&self, username: &str, ip: Option<IpAddr>) -> Result<()> {
+        let setting = self.get_setting().await?;
+        let mut conn = self.ctx.redis().get_multiplexed_async_connection().await?;
+        let failed_key = Self::failed_key(username, ip);
+
+        let failed: u32 = conn.incr(&failed_key, 1).await?;
+        let _: () = conn
+            .expire(&failed_key, setting.login_max_lockout_secs.max(3600) as i64)
+            .await?;
+
+        if let Some(secs) = setting.lockout_secs(failed) {
+            let _: () = conn
+                .set_ex(Self::lock_key(username, ip), failed, secs)
+                .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn reset_login_failed(&self, username: &str, ip: Option<IpAddr>) -> Result<()> {
+        let mut conn = self.ctx.redis().get_multiplexed_async_connection().await?;
+        let _: () = conn
+            .del(&[Self::failed_key(username, ip), Self::lock_key(username, ip)])
+            .await?;
+        Ok(())
+    }
+
+    /// unlock the account for all the clients
+    pub async fn unlock_login(&self, username: &str) -> Result<()> {
+        let mut conn = self.ctx.redis().get_multiplexed_async_connection().await?;
+        let mut keys: Vec<String> = vec![];
+        for pattern in [
+            format!("jiascheduler:login:failed:{username}:*"),
+            format!("jiascheduler:login:lock:{username}:*"),
+        ] {
+            let mut iter = conn.scan_match::<_, String>(pattern).await?;
+            while let Some(key) = iter.next_item().await {
+                keys.push(key);
+            }
+        }
+        if !keys.is_empty() {
+            let _: () = conn.del(keys).await?;
+        }
+        Ok(())
+    }
+}
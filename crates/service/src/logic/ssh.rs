@@ -2,25 +2,34 @@ use std::env;
 use std::sync::Arc;
 use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{Context, Result, anyhow};
 
 use async_trait::async_trait;
 use automate::bridge::msg::{
     SftpDownloadParams, SftpReadDirParams, SftpRemoveParams, SftpUploadParams,
 };
+use crypto::{digest::Digest, sha2::Sha256};
 use futures::stream::{SplitSink, SplitStream};
 use futures::{SinkExt, StreamExt};
 use poem::web::websocket::{Message, WebSocketStream};
+use redis::{AsyncCommands, ExistenceCheck, SetExpiry, SetOptions};
 use russh::*;
 use russh_keys::*;
 use russh_sftp::client::SftpSession;
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder, QueryTrait,
+};
 use serde_json::Value;
 
-use crate::state::AppContext;
+use crate::{
+    entity::{prelude::SftpEditLog, sftp_edit_log},
+    logic::credential::SshCredential,
+    state::AppContext,
+};
 
 use serde::{self, Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
-use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
 use tokio::net::ToSocketAddrs;
 use tokio::time::timeout;
 use tracing::info;
@@ -60,19 +69,48 @@ impl client::Handler for Client {
 
 pub struct Session {
     session: client::Handle<Client>,
+    /// sessions of the bastions carrying the connection of session
+    jumps: Vec<client::Handle<Client>>,
 }
 
-pub struct ConnectParams<A: ToSocketAddrs, U: Into<String>, P: Into<String>> {
-    pub user: U,
-    pub password: P,
-    pub addrs: A,
+/// one ssh server of a chain, the servers before it are its bastions
+#[derive(Clone, Debug)]
+pub struct SshHop {
+    pub ip: String,
+    pub port: u16,
+    pub credential: SshCredential,
 }
 
+async fn authenticate(
+    session: &mut client::Handle<Client>,
+    user: impl Into<String>,
+    password: impl Into<String>,
+    private_key: Option<PrivateKey>,
+) -> Result<()> {
+    let auth_res = match private_key {
+        Some(v) => {
+            let key_pair = decode_secret_key(&v.key, v.passphrase.as_deref())?;
+            session
+                .authenticate_publickey(user, Arc::new(key_pair))
+                .await?
+        }
+        None => session.authenticate_password(user, password).await?,
+    };
+
+    if !auth_res {
+        anyhow::bail!("Authentication failed");
+    }
+    Ok(())
+}
+
+pub use automate::ssh::{ConnectParams, PrivateKey};
+
 impl Session {
     pub async fn connect<A: ToSocketAddrs, U: Into<String>, P: Into<String>>(
         ConnectParams {
             user,
             password,
+            private_key,
             addrs,
         }: ConnectParams<A, U, P>,
     ) -> Result<Self> {
@@ -88,13 +126,70 @@ impl Session {
         let mut session =
             timeout(Duration::from_secs(1), client::connect(config, addrs, sh)).await??;
 
-        let auth_res = session.authenticate_password(user, password).await?;
+        authenticate(&mut session, user, password, private_key).await?;
 
-        if !auth_res {
-            anyhow::bail!("Authentication failed");
-        }
+        Ok(Self {
+            session,
+            jumps: vec![],
+        })
+    }
 
-        Ok(Self { session })
+    /// log in with the password or the private key of the credential
+    pub async fn connect_credential<A: ToSocketAddrs>(
+        credential: &SshCredential,
+        addrs: A,
+    ) -> Result<Self> {
+        Self::connect(ConnectParams {
+            user: credential.sys_user.clone(),
+            password: credential.password(),
+            private_key: credential.private_key(),
+            addrs,
+        })
+        .await
+    }
+
+    /// connect to the last hop through the previous ones, like ssh -J
+    pub async fn connect_chain(hops: Vec<SshHop>) -> Result<Self> {
+        let mut hops = hops.into_iter();
+        let Some(first) = hops.next() else {
+            anyhow::bail!("no ssh server to connect");
+        };
+        let mut session =
+            Self::connect_credential(&first.credential, (first.ip.clone(), first.port))
+                .await
+                .with_context(|| format!("failed connect to {}:{}", first.ip, first.port))?;
+
+        for hop in hops {
+            let channel = session
+                .session
+                .channel_open_direct_tcpip(hop.ip.clone(), hop.port.into(), "127.0.0.1", 0)
+                .await
+                .with_context(|| format!("failed open tunnel to {}:{}", hop.ip, hop.port))?;
+
+            let config = client::Config {
+                inactivity_timeout: Some(Duration::from_secs(90)),
+                keepalive_interval: Some(Duration::from_secs(10)),
+                ..Default::default()
+            };
+            let mut next = timeout(
+                Duration::from_secs(5),
+                client::connect_stream(Arc::new(config), channel.into_stream(), Client {}),
+            )
+            .await??;
+            authenticate(
+                &mut next,
+                hop.credential.sys_user.clone(),
+                hop.credential.password(),
+                hop.credential.private_key(),
+            )
+            .await
+            .with_context(|| format!("failed log in to {}:{}", hop.ip, hop.port))?;
+
+            session
+                .jumps
+                .push(std::mem::replace(&mut session.session, next));
+        }
+        Ok(session)
     }
 
     #[allow(dead_code)]
@@ -124,7 +219,10 @@ impl Session {
             anyhow::bail!("Authentication failed");
         }
 
-        Ok(Self { session })
+        Ok(Self {
+            session,
+            jumps: vec![],
+        })
     }
 
     pub async fn call(
@@ -223,12 +321,38 @@ impl Session {
         self.session
             .disconnect(Disconnect::ByApplication, "", "English")
             .await?;
+        for jump in self.jumps.iter().rev() {
+            jump.disconnect(Disconnect::ByApplication, "", "English")
+                .await?;
+        }
         Ok(())
     }
 }
 
+/// larger files have to be downloaded and uploaded
+pub const MAX_EDIT_FILE_SIZE: u64 = 1024 * 1024;
+/// saves of the same file wait for each other up to this
+const EDIT_LOCK_SECS: u64 = 30;
+
+/// text of the file opened in the console, the etag is required to save it
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EditFile {
+    pub content: String,
+    pub etag: String,
+}
+
+/// sha256 of the content, the etag of a file which does not exist is empty
+pub fn content_etag(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input(data);
+    hasher.result_str()
+}
+
+fn edit_lock_key(instance_id: &str, file_path: &str) -> String {
+    format!("jiascheduler:sftp:edit-lock:{instance_id}:{file_path}")
+}
+
 pub struct SshLogic<'a> {
-    #[allow(dead_code)]
     ctx: &'a AppContext,
 }
 
@@ -244,19 +368,19 @@ impl<'a> SshLogic<'a> {
         mac_addr: String,
         port: u16,
         dir: Option<String>,
-        user: String,
-        password: String,
+        credential: SshCredential,
     ) -> Result<Value> {
         let logic = automate::Logic::new(self.ctx.redis().clone());
         let pair = logic.get_link_pair(ip.clone(), mac_addr.clone()).await?;
-        let api_url = format!("http://{}/sftp/tunnel/read-dir", pair.1.comet_addr);
+        let api_url = pair.1.http_url("/sftp/tunnel/read-dir");
 
         let body = automate::SftpReadDirRequest {
             agent_ip: ip.clone(),
             namespace: namespace.clone(),
             params: SftpReadDirParams {
-                user,
-                password,
+                user: credential.sys_user.clone(),
+                password: credential.password(),
+                private_key: credential.private_key(),
                 ip,
                 dir,
                 port,
@@ -286,14 +410,13 @@ impl<'a> SshLogic<'a> {
         ip: String,
         mac_addr: String,
         port: u16,
-        user: String,
-        password: String,
+        credential: SshCredential,
         filepath: String,
         data: Vec<u8>,
     ) -> Result<String> {
         let logic = automate::Logic::new(self.ctx.redis());
         let pair = logic.get_link_pair(ip.clone(), mac_addr.clone()).await?;
-        let api_url = format!("http://{}/sftp/tunnel/upload", pair.1.comet_addr);
+        let api_url = pair.1.http_url("/sftp/tunnel/upload");
 
         let body = automate::SftpUploadRequest {
             agent_ip: ip.clone(),
@@ -302,8 +425,9 @@ impl<'a> SshLogic<'a> {
             params: SftpUploadParams {
                 ip,
                 port,
-                user,
-                password,
+                user: credential.sys_user.clone(),
+                password: credential.password(),
+                private_key: credential.private_key(),
                 filepath,
                 data,
             },
@@ -333,14 +457,13 @@ impl<'a> SshLogic<'a> {
         ip: String,
         mac_addr: String,
         port: u16,
-        user: String,
-        password: String,
+        credential: SshCredential,
         filepath: String,
         remove_type: String,
     ) -> Result<String> {
         let logic = automate::Logic::new(self.ctx.redis().clone());
         let pair = logic.get_link_pair(ip.clone(), mac_addr.clone()).await?;
-        let api_url = format!("http://{}/sftp/tunnel/remove", pair.1.comet_addr);
+        let api_url = pair.1.http_url("/sftp/tunnel/remove");
 
         let body = automate::SftpRemoveRequest {
             agent_ip: ip.clone(),
@@ -349,8 +472,9 @@ impl<'a> SshLogic<'a> {
             params: SftpRemoveParams {
                 ip,
                 port,
-                user,
-                password,
+                user: credential.sys_user.clone(),
+                password: credential.password(),
+                private_key: credential.private_key(),
                 filepath,
                 remove_type,
             },
@@ -379,13 +503,12 @@ impl<'a> SshLogic<'a> {
         ip: String,
         mac_addr: String,
         port: u16,
-        user: String,
-        password: String,
+        credential: SshCredential,
         filepath: String,
     ) -> Result<Vec<u8>> {
         let logic = automate::Logic::new(self.ctx.redis().clone());
         let pair = logic.get_link_pair(ip.clone(), mac_addr.clone()).await?;
-        let api_url = format!("http://{}/sftp/tunnel/download", pair.1.comet_addr);
+        let api_url = pair.1.http_url("/sftp/tunnel/download");
 
         let body = automate::SftpDownloadRequest {
             agent_ip: ip.clone(),
@@ -394,8 +517,9 @@ impl<'a> SshLogic<'a> {
             params: SftpDownloadParams {
                 ip,
                 port,
-                user,
-                password,
+                user: credential.sys_user.clone(),
+                password: credential.password(),
+                private_key: credential.private_key(),
                 filepath,
             },
         };
@@ -417,4 +541,135 @@ impl<'a> SshLogic<'a> {
             Ok(data)
         }
     }
+
+    /// read the text file to edit it, fails if it is too large or not utf-8
+    pub async fn sftp_open_file(&self, sftp: &SftpSession, file_path: &str) -> Result<EditFile> {
+        let size = sftp.metadata(file_path).await?.size.unwrap_or_default();
+        if size > MAX_EDIT_FILE_SIZE {
+            anyhow::bail!(
+                "the file is larger than {MAX_EDIT_FILE_SIZE} bytes, download it instead"
+            );
+        }
+        let data = sftp.read(file_path).await?;
+        let etag = content_etag(&data);
+        let content = String::from_utf8(data).map_err(|_| anyhow!("the file is not utf-8 text"))?;
+        Ok(EditFile { content, etag })
+    }
+
+    /// write the content only if the file still has the etag it was opened with, an empty etag
+    /// creates the file. the saves of the same file are serialized by a redis lock,
+    /// the new etag is returned
+    pub async fn sftp_save_file(
+        &self,
+        sftp: &SftpSession,
+        instance_id: &str,
+        ip: &str,
+        file_path: &str,
+        content: &str,
+        etag: &str,
+        username: &str,
+    ) -> Result<String> {
+        if content.len() as u64 > MAX_EDIT_FILE_SIZE {
+            anyhow::bail!("the content is larger than {MAX_EDIT_FILE_SIZE} bytes");
+        }
+
+        let mut conn = self.ctx.redis().get_multiplexed_async_connection().await?;
+        let lock_key = edit_lock_key(instance_id, file_path);
+        let locked: Option<String> = conn
+            .set_options(
+                &lock_key,
+                username,
+                SetOptions::default()
+                    .conditional_set(ExistenceCheck::NX)
+                    .with_expiration(SetExpiry::EX(EDIT_LOCK_SECS)),
+            )
+            .await?;
+        if locked.is_none() {
+            anyhow::bail!("the file is being saved by someone else, please retry");
+        }
+
+        let ret: Result<String> = async {
+            let current_etag = if sftp.try_exists(file_path).await? {
+                content_etag(&sftp.read(file_path).await?)
+            } else {
+                String::new()
+            };
+            if current_etag != etag {
+                anyhow::bail!("the file has been modified since it was opened, reopen it to edit");
+            }
+
+            let mut file = sftp.create(file_path).await?;
+            file.write_all(content.as_bytes()).await?;
+            file.shutdown().await?;
+            Ok(current_etag)
+        }
+        .await;
+        let _: () = conn.del(&lock_key).await?;
+        let prev_etag = ret?;
+
+        let new_etag = content_etag(content.as_bytes());
+        SftpEditLog::insert(sftp_edit_log::ActiveModel {
+            instance_id: Set(instance_id.to_string()),
+            ip: Set(ip.to_string()),
+            file_path: Set(file_path.to_string()),
+            prev_etag: Set(prev_etag),
+            etag: Set(new_etag.clone()),
+            size: Set(content.len() as u64),
+            created_user: Set(username.to_string()),
+            ..Default::default()
+        })
+        .exec(&self.ctx.db)
+        .await?;
+        Ok(new_etag)
+    }
+
+    pub async fn query_sftp_edit_log(
+        &self,
+        instance_id: Option<String>,
+        page: u64,
+        page_size: u64,
+    ) -> Result<(Vec<sftp_edit_log::Model>, u64)> {
+        let select = SftpEditLog::find().apply_if(instance_id, |q, v| {
+            q.filter(sftp_edit_log::Column::InstanceId.eq(v))
+        });
+        let total = select.clone().count(&self.ctx.db).await?;
+        let list = select
+            .order_by_desc(sftp_edit_log::Column::Id)
+            .paginate(&self.ctx.db, page_size)
+            .fetch_page(page)
+            .await?;
+        Ok((list, total))
+    }
+}
+
+#[test]
+fn test_content_etag() {
+    assert_eq!(
+        content_etag(b"hello"),
+        "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+    );
+    assert_ne!(content_etag(b"hello\n"), content_etag(b"hello"));
+}
+
+#[tokio::test]
+async fn test_connect_chain() {
+    use crate::logic::credential::CRED_TYPE_PASSWORD;
+
+    assert!(Session::connect_chain(vec![]).await.is_err());
+
+    let hop = |ip: &str, port| SshHop {
+        ip: ip.to_string(),
+        port,
+        credential: SshCredential {
+            sys_user: "deploy".to_string(),
+            cred_type: CRED_TYPE_PASSWORD.to_string(),
+            secret: "secret".to_string(),
+            passphrase: None,
+        },
+    };
+    let Err(e) = Session::connect_chain(vec![hop("127.0.0.1", 1), hop("10.0.0.2", 22)]).await
+    else {
+        panic!("connected through a closed port");
+    };
+    assert_eq!(e.to_string(), "failed connect to 127.0.0.1:1");
 }
@@ -6,10 +6,70 @@ use crate::{
 use anyhow::Result;
 use entity::workflow;
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, Condition, EntityTrait, JoinType, QueryFilter, QuerySelect,
-    QueryTrait, Set,
+    ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, EntityTrait, JoinType,
+    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, QueryTrait, Set,
 };
-use sea_query::Query;
+use sea_query::{Query, SimpleExpr};
+
+/// separator of the tag namespaces, eg: env/prod is the tag prod in the namespace env
+pub const TAG_PATH_SEPARATOR: char = '/';
+
+const MAX_TAG_NAME_LEN: usize = 200;
+
+/// trim the segments of the tag path and remove the empty ones, eg: " env//prod " is env/prod
+pub fn normalize_tag_name(name: &str) -> Result<String> {
+    let path = name
+        .split(TAG_PATH_SEPARATOR)
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .collect::<Vec<_>>()
+        .join("/");
+    if path.is_empty() {
+        anyhow::bail!("empty tag name");
+    }
+    if path.len() > MAX_TAG_NAME_LEN {
+        anyhow::bail!("the tag name exceeds {MAX_TAG_NAME_LEN} bytes");
+    }
+    Ok(path)
+}
+
+/// the namespaces from the top level down to the tag itself, eg: env, env/prod
+pub fn tag_path_chain(path: &str) -> Vec<String> {
+    path.match_indices(TAG_PATH_SEPARATOR)
+        .map(|(i, _)| path[..i].to_string())
+        .chain([path.to_string()])
+        .collect()
+}
+
+/// a granted tag also grants the tags under it
+pub fn tag_in_scope(path: &str, granted: &[String]) -> bool {
+    granted.iter().any(|v| {
+        path.strip_prefix(v.as_str())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(TAG_PATH_SEPARATOR))
+    })
+}
+
+/// the jobs are tagged as either of them
+pub const JOB_RESOURCE_TYPES: [ResourceType; 2] = [ResourceType::Job, ResourceType::BundleJob];
+
+/// resources carrying one of the tags, the column is the id of the resource
+pub fn tag_scope_expr(
+    column: impl ColumnTrait,
+    resource_types: &[ResourceType],
+    tag_ids: Vec<u64>,
+) -> SimpleExpr {
+    column.in_subquery(
+        Query::select()
+            .column(tag_resource::Column::ResourceId)
+            .and_where(
+                tag_resource::Column::ResourceType
+                    .is_in(resource_types.iter().map(|v| v.to_string()))
+                    .and(tag_resource::Column::TagId.is_in(tag_ids)),
+            )
+            .from(TagResource)
+            .to_owned(),
+    )
+}
 
 #[derive(Clone)]
 pub struct TagLogic<'a> {
@@ -31,7 +91,8 @@ impl<'a> TagLogic<'a> {
             ResourceType::Job => {
                 let record = Job::find()
                     .filter(job::Column::Id.eq(resource_id))
-                    .filter(job::Column::JobType.eq("default"))
+                    .filter(job::Column::JobType.is_in(["default", "http"]))
+                    .filter(job::Column::IsDeleted.eq(false))
                     .one(&self.ctx.db)
                     .await?;
                 if record.is_none() {
@@ -42,6 +103,7 @@ impl<'a> TagLogic<'a> {
                 let record = Job::find()
                     .filter(job::Column::Id.eq(resource_id))
                     .filter(job::Column::JobType.eq("bundle"))
+                    .filter(job::Column::IsDeleted.eq(false))
                     .one(&self.ctx.db)
                     .await?;
                 if record.is_none() {
@@ -68,24 +130,7 @@ impl<'a> TagLogic<'a> {
             }
         }
 
-        let tag_record = Tag::find()
-            .filter(tag::Column::TagName.eq(tag_name))
-            .one(&self.ctx.db)
-            .await?;
-
-        let tag_id = if tag_record.is_none() {
-            let inserted = tag::ActiveModel {
-                tag_name: Set(tag_name.to_string()),
-                created_user: Set(user_info.username.clone()),
-                ..Default::default()
-            }
-            .save(&self.ctx.db)
-            .await?;
-
-            inserted.id.as_ref().to_owned()
-        } else {
-            tag_record.unwrap().id
-        };
+        let tag_id = Self::ensure_tag(&self.ctx.db, &user_info.username, tag_name).await?;
 
         tag_resource::ActiveModel {
             tag_id: Set(tag_id),
@@ -100,6 +145,102 @@ impl<'a> TagLogic<'a> {
         Ok(tag_id)
     }
 
+    /// get the id of the tag, the tag and its namespaces are created if not exists
+    pub async fn ensure_tag<C: ConnectionTrait>(
+        db: &C,
+        username: &str,
+        tag_name: &str,
+    ) -> Result<u64> {
+        let path = normalize_tag_name(tag_name)?;
+        let mut parent_id = 0;
+        for name in tag_path_chain(&path) {
+            parent_id = match Tag::find()
+                .filter(tag::Column::TagName.eq(name.clone()))
+                .one(db)
+                .await?
+            {
+                Some(v) => v.id,
+                None => {
+                    tag::ActiveModel {
+                        tag_name: Set(name),
+                        parent_id: Set(parent_id),
+                        created_user: Set(username.to_string()),
+                        ..Default::default()
+                    }
+                    .insert(db)
+                    .await?
+                    .id
+                }
+            };
+        }
+        Ok(parent_id)
+    }
+
+    /// the tags directly under the namespace, the top level tags if no namespace
+    pub async fn query_tag(&self, namespace: Option<String>) -> Result<Vec<tag::Model>> {
+        let parent_id = match namespace {
+            Some(v) => {
+                let Some(record) = Tag::find()
+                    .filter(tag::Column::TagName.eq(normalize_tag_name(&v)?))
+                    .one(&self.ctx.db)
+                    .await?
+                else {
+                    return Ok(vec![]);
+                };
+                record.id
+            }
+            None => 0,
+        };
+        let list = Tag::find()
+            .filter(tag::Column::ParentId.eq(parent_id))
+            .order_by_asc(tag::Column::TagName)
+            .all(&self.ctx.db)
+            .await?;
+        Ok(list)
+    }
+
+    /// ids of the tags the user can access, None if the user is not limited by tags.
+    /// a role is limited once any tag is granted to it
+    pub async fn get_tag_scope(&self, user_info: &types::UserInfo) -> Result<Option<Vec<u64>>> {
+        if user_info.is_root {
+            return Ok(None);
+        }
+        let granted = self
+            .ctx
+            .get_tag_policies_for_user(&user_info.user_id)
+            .await?;
+        if granted.is_empty() {
+            return Ok(None);
+        }
+        let ids = Tag::find()
+            .all(&self.ctx.db)
+            .await?
+            .into_iter()
+            .filter(|v| tag_in_scope(&v.tag_name, &granted))
+            .map(|v| v.id)
+            .collect();
+        Ok(Some(ids))
+    }
+
+    /// true if the resource carries one of the tags
+    pub async fn is_resource_tagged(
+        &self,
+        resource_types: &[ResourceType],
+        resource_id: u64,
+        tag_ids: Vec<u64>,
+    ) -> Result<bool> {
+        let total = TagResource::find()
+            .filter(
+                tag_resource::Column::ResourceType
+                    .is_in(resource_types.iter().map(|v| v.to_string())),
+            )
+            .filter(tag_resource::Column::ResourceId.eq(resource_id))
+            .filter(tag_resource::Column::TagId.is_in(tag_ids))
+            .count(&self.ctx.db)
+            .await?;
+        Ok(total > 0)
+    }
+
     pub async fn unbind_tag(
         &self,
         _user_info: &types::UserInfo,
@@ -161,13 +302,11 @@ impl<'a> TagLogic<'a> {
                         .into(),
                 )
                 .filter(job::Column::IsDeleted.eq(false))
-                .filter(
-                    job::Column::JobType.eq(if resource_type == ResourceType::Job {
-                        "default"
-                    } else {
-                        "bundle"
-                    }),
-                )
+                .filter(if resource_type == ResourceType::Job {
+                    job::Column::JobType.is_in(["default", "http"])
+                } else {
+                    job::Column::JobType.eq("bundle")
+                })
                 .apply_if(team_id, |q, v| q.filter(job::Column::TeamId.eq(v)))
                 .apply_if(username, |q, v| q.filter(job::Column::CreatedUser.eq(v))),
 
@@ -246,3 +385,20 @@ impl<'a> TagLogic<'a> {
         Ok(tags)
     }
 }
+
+#[test]
+fn test_tag_path() {
+    assert_eq!(normalize_tag_name(" env//prod / ").unwrap(), "env/prod");
+    assert!(normalize_tag_name(" / ").is_err());
+    assert_eq!(
+        tag_path_chain("env/prod/db"),
+        ["env", "env/prod", "env/prod/db"]
+    );
+    assert_eq!(tag_path_chain("env"), ["env"]);
+
+    let granted = vec!["env/prod".to_string()];
+    assert!(tag_in_scope("env/prod", &granted));
+    assert!(tag_in_scope("env/prod/db", &granted));
+    assert!(!tag_in_scope("env/production", &granted));
+    assert!(!tag_in_scope("env", &granted));
+}
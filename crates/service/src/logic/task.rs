@@ -0,0 +1,317 @@
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::Result;
+use chrono::{Local, TimeDelta};
+use sea_orm::{
+    ActiveValue::Set, ColumnTrait, Condition, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder,
+    QuerySelect, QueryTrait, sea_query::Expr,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use super::{
+    executor::INSTALL_STATUS_INSTALLED,
+    job::{CanaryOption, ExportHistoryTask, types::InstanceOverride},
+    types::CustomTimerExpr,
+};
+use crate::{
+    IdGenerator,
+    entity::{prelude::*, server_task},
+    state::AppContext,
+};
+
+pub const STATUS_PENDING: &str = "pending";
+pub const STATUS_RUNNING: &str = "running";
+pub const STATUS_SUCCEEDED: &str = "succeeded";
+pub const STATUS_FAILED: &str = "failed";
+
+/// a running task is touched at this interval, so a task whose console died can be detected
+pub const TOUCH_INTERVAL_SECS: u64 = 30;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DispatchJobTask {
+    pub instance_ids: Vec<String>,
+    pub eid: String,
+    pub is_sync: bool,
+    pub schedule_name: String,
+    pub schedule_type: String,
+    pub action: String,
+    pub timer_expr: Option<CustomTimerExpr>,
+    pub restart_interval: Option<u64>,
+    pub args: Option<Value>,
+    #[serde(default)]
+    pub overrides: HashMap<String, InstanceOverride>,
+    pub override_reason: Option<String>,
+    #[serde(default)]
+    pub canary: Option<CanaryOption>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EnsureExecutorTask {
+    pub executor_id: u64,
+    pub instance_ids: Vec<String>,
+}
+
+/// long operations executed by the console workers instead of the http request
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "task_type", content = "params", rename_all = "snake_case")]
+pub enum BackgroundTask {
+    DispatchJob(Box<DispatchJobTask>),
+    ExportHistory(ExportHistoryTask),
+    EnsureExecutor(EnsureExecutorTask),
+}
+
+impl BackgroundTask {
+    pub fn task_type(&self) -> &'static str {
+        match self {
+            BackgroundTask::DispatchJob(_) => "dispatch_job",
+            BackgroundTask::ExportHistory(_) => "export_history",
+            BackgroundTask::EnsureExecutor(_) => "ensure_executor",
+        }
+    }
+
+    fn from_record(record: &server_task::Model) -> Result<Self> {
+        Ok(serde_json::from_value(json!({
+            "task_type": record.task_type,
+            "params": record.params,
+        }))?)
+    }
+}
+
+#[derive(Clone)]
+pub struct TaskLogic<'a> {
+    ctx: &'a AppContext,
+}
+
+impl<'a> TaskLogic<'a> {
+    pub fn new(ctx: &'a AppContext) -> Self {
+        Self { ctx }
+    }
+
+    /// queue the task, returns the task id to poll
+    pub async fn enqueue(
+        &self,
+        task: BackgroundTask,
+        team_id: Option<u64>,
+        created_user: String,
+    ) -> Result<String> {
+        let task_id = IdGenerator::get_server_task_uid();
+        let params = serde_json::to_value(&task)?["params"].take();
+        ServerTask::insert(server_task::ActiveModel {
+            task_id: Set(task_id.clone()),
+            task_type: Set(task.task_type().to_string()),
+            team_id: Set(team_id.unwrap_or_default()),
+            params: Set(Some(params)),
+            status: Set(STATUS_PENDING.to_string()),
+            created_user: Set(created_user),
+            ..Default::default()
+        })
+        .exec(&self.ctx.db)
+        .await?;
+        Ok(task_id)
+    }
+
+    pub async fn get_task(&self, task_id: &str) -> Result<Option<server_task::Model>> {
+        Ok(ServerTask::find()
+            .filter(server_task::Column::TaskId.eq(task_id))
+            .one(&self.ctx.db)
+            .await?)
+    }
+
+    pub async fn query_task(
+        &self,
+        created_user: Option<String>,
+        team_id: Option<u64>,
+        status: Option<String>,
+        page: u64,
+        page_size: u64,
+    ) -> Result<(Vec<server_task::Model>, u64)> {
+        let select = ServerTask::find()
+            .apply_if(created_user, |q, v| {
+                q.filter(server_task::Column::CreatedUser.eq(v))
+            })
+            .apply_if(team_id, |q, v| q.filter(server_task::Column::TeamId.eq(v)))
+            .apply_if(status, |q, v| q.filter(server_task::Column::Status.eq(v)));
+
+        let total = select.clone().count(&self.ctx.db).await?;
+        let list = select
+            .order_by_desc(server_task::Column::Id)
+            .paginate(&self.ctx.db, page_size)
+            .fetch_page(page)
+            .await?;
+        Ok((list, total))
+    }
+
+    /// take the oldest pending task, the status is switched with a conditional update
+    /// so a task is claimed by one worker only
+    pub async fn claim_task(&self, worker: &str) -> Result<Option<server_task::Model>> {
+        let pending = ServerTask::find()
+            .filter(server_task::Column::Status.eq(STATUS_PENDING))
+            .order_by_asc(server_task::Column::Id)
+            .limit(10)
+            .all(&self.ctx.db)
+            .await?;
+
+        for record in pending {
+            let ret = ServerTask::update_many()
+                .col_expr(server_task::Column::Status, Expr::value(STATUS_RUNNING))
+                .col_expr(server_task::Column::Worker, Expr::value(worker))
+                .col_expr(server_task::Column::StartedTime, Expr::value(Local::now()))
+                .filter(server_task::Column::Id.eq(record.id))
+                .filter(server_task::Column::Status.eq(STATUS_PENDING))
+                .exec(&self.ctx.db)
+                .await?;
+            if ret.rows_affected == 1 {
+                return Ok(Some(record));
+            }
+        }
+        Ok(None)
+    }
+
+    /// refresh updated_time of the running task
+    pub async fn touch_task(&self, id: u64) -> Result<()> {
+        ServerTask::update_many()
+            .col_expr(server_task::Column::UpdatedTime, Expr::value(Local::now()))
+            .filter(server_task::Column::Id.eq(id))
+            .filter(server_task::Column::Status.eq(STATUS_RUNNING))
+            .exec(&self.ctx.db)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn finish_task(&self, id: u64, ret: Result<Value>) -> Result<()> {
+        let (status, output, err_msg) = match ret {
+            Ok(v) => (STATUS_SUCCEEDED, Some(v), None),
+            Err(e) => (STATUS_FAILED, None, Some(format!("{e:?}"))),
+        };
+        ServerTask::update_many()
+            .col_expr(server_task::Column::Status, Expr::value(status))
+            .col_expr(server_task::Column::Output, Expr::value(output))
+            .col_expr(server_task::Column::ErrMsg, Expr::value(err_msg))
+            .col_expr(server_task::Column::FinishedTime, Expr::value(Local::now()))
+            .filter(server_task::Column::Id.eq(id))
+            .exec(&self.ctx.db)
+            .await?;
+        Ok(())
+    }
+
+    /// fail the running tasks not touched for a while, their console is gone.
+    /// they are not retried because a task may be partially done, eg: dispatched to a part of the endpoints
+    pub async fn fail_lost_task(&self) -> Result<u64> {
+        let deadline = Local::now() - TimeDelta::seconds(TOUCH_INTERVAL_SECS as i64 * 4);
+        let ret = ServerTask::update_many()
+            .col_expr(server_task::Column::Status, Expr::value(STATUS_FAILED))
+            .col_expr(
+                server_task::Column::ErrMsg,
+                Expr::value("the console running the task is lost"),
+            )
+            .col_expr(server_task::Column::FinishedTime, Expr::value(Local::now()))
+            .filter(
+                Condition::all()
+                    .add(server_task::Column::Status.eq(STATUS_RUNNING))
+                    .add(server_task::Column::UpdatedTime.lt(deadline)),
+            )
+            .exec(&self.ctx.db)
+            .await?;
+        Ok(ret.rows_affected)
+    }
+
+    /// execute the claimed task
+    pub async fn run_task(&self, record: &server_task::Model) -> Result<Value> {
+        let svc = self.ctx.service();
+        match BackgroundTask::from_record(record)? {
+            BackgroundTask::DispatchJob(task) => {
+                let schedule_id = svc
+                    .job
+                    .dispatch_job(
+                        self.ctx.conf.comet_secret.clone(),
+                        task.instance_ids,
+                        task.eid,
+                        task.is_sync,
+                        task.schedule_name,
+                        task.schedule_type.as_str().try_into()?,
+                        task.action.as_str().try_into()?,
+                        task.timer_expr,
+                        task.restart_interval.map(Duration::from_secs),
+                        task.args,
+                        task.overrides,
+                        record.created_user.clone(),
+                        task.canary,
+                    )
+                    .await?;
+
+                if let Some(override_reason) = task.override_reason.filter(|v| !v.is_empty()) {
+                    svc.job
+                        .set_schedule_override_reason(schedule_id, override_reason)
+                        .await?;
+                }
+                Ok(json!({ "schedule_id": schedule_id }))
+            }
+            BackgroundTask::ExportHistory(task) => {
+                let (file_name, rows) = svc.job.export_history(&record.task_id, &task).await?;
+                Ok(json!({ "file_name": file_name, "rows": rows }))
+            }
+            BackgroundTask::EnsureExecutor(task) => {
+                let list = svc
+                    .executor
+                    .ensure_executor(
+                        task.executor_id,
+                        task.instance_ids,
+                        record.created_user.clone(),
+                    )
+                    .await?;
+                let installed = list
+                    .iter()
+                    .filter(|v| v.status == INSTALL_STATUS_INSTALLED)
+                    .count();
+                Ok(json!({ "installed": installed, "failed": list.len() - installed }))
+            }
+        }
+    }
+}
+
+#[test]
+fn test_server_task_record() {
+    let task = BackgroundTask::DispatchJob(Box::new(DispatchJobTask {
+        instance_ids: vec!["i-1".to_string()],
+        eid: "j-1".to_string(),
+        is_sync: false,
+        schedule_name: "deploy".to_string(),
+        schedule_type: "once".to_string(),
+        action: "exec".to_string(),
+        timer_expr: None,
+        restart_interval: None,
+        args: None,
+        overrides: HashMap::new(),
+        override_reason: None,
+        canary: None,
+    }));
+    let record = server_task::Model {
+        task_type: task.task_type().to_string(),
+        params: Some(serde_json::to_value(&task).unwrap()["params"].take()),
+        ..Default::default()
+    };
+    let BackgroundTask::DispatchJob(v) = BackgroundTask::from_record(&record).unwrap() else {
+        panic!("unexpected task type");
+    };
+    assert_eq!(v.eid, "j-1");
+}
+
+#[test]
+fn test_ensure_executor_task_record() {
+    let task = BackgroundTask::EnsureExecutor(EnsureExecutorTask {
+        executor_id: 3,
+        instance_ids: vec!["i-1".to_string(), "i-2".to_string()],
+    });
+    assert_eq!(task.task_type(), "ensure_executor");
+    let record = server_task::Model {
+        task_type: task.task_type().to_string(),
+        params: Some(serde_json::to_value(&task).unwrap()["params"].take()),
+        ..Default::default()
+    };
+    let BackgroundTask::EnsureExecutor(v) = BackgroundTask::from_record(&record).unwrap() else {
+        panic!("unexpected task type");
+    };
+    assert_eq!(v.executor_id, 3);
+    assert_eq!(v.instance_ids, ["i-1", "i-2"]);
+}
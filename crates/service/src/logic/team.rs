@@ -1,17 +1,26 @@
+use std::collections::{BTreeMap, HashSet};
+
 use anyhow::{Ok, Result};
 use sea_orm::{
     ActiveModelTrait,
     ActiveValue::{self, NotSet},
     ColumnTrait, EntityTrait, JoinType, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect,
-    QueryTrait, Set,
+    QueryTrait, Set, TransactionTrait,
 };
 use sea_query::Expr;
 
 use crate::{
-    entity::{job, prelude::*, team, team_member, user},
+    cache::CACHE_TEAM_MEMBER,
+    entity::{
+        job, prelude::*, team, team_member, user, user_group, user_group_member, user_group_team,
+    },
     state::AppContext,
 };
 
+mod quota;
+
+const TEAM_MEMBER_CACHE_SECS: u64 = 300;
+
 use super::{
     job::types::TeamMemberModel,
     types::{self, TeamRecord},
@@ -42,6 +51,32 @@ impl<'a> TeamLogic<'a> {
             .await?)
     }
 
+    /// memberships of the user, cached until the members of a team change
+    pub async fn get_member_teams(&self, user_id: &str) -> Result<Vec<team_member::Model>> {
+        self.ctx
+            .cache
+            .get_or_load(
+                CACHE_TEAM_MEMBER,
+                user_id,
+                TEAM_MEMBER_CACHE_SECS,
+                || async {
+                    Ok(TeamMember::find()
+                        .filter(team_member::Column::UserId.eq(user_id))
+                        .all(&self.ctx.db)
+                        .await?)
+                },
+            )
+            .await
+    }
+
+    pub async fn is_team_member(&self, team_id: u64, user_id: &str) -> Result<bool> {
+        Ok(self
+            .get_member_teams(user_id)
+            .await?
+            .iter()
+            .any(|v| v.team_id == team_id))
+    }
+
     pub async fn can_write_team(&self, team_id: Option<u64>, user_id: String) -> Result<bool> {
         let Some(team_id) = team_id else {
             return Ok(true);
@@ -66,11 +101,11 @@ impl<'a> TeamLogic<'a> {
             return Ok(true);
         }
 
-        if let Some(member) = TeamMember::find()
-            .filter(team_member::Column::TeamId.eq(team_id))
-            .filter(team_member::Column::UserId.eq(&user_id))
-            .one(&self.ctx.db)
+        if let Some(member) = self
+            .get_member_teams(&user_id)
             .await?
+            .into_iter()
+            .find(|v| v.team_id == team_id)
         {
             return Ok(member.is_admin);
         }
@@ -102,13 +137,7 @@ impl<'a> TeamLogic<'a> {
             return Ok(true);
         }
 
-        if TeamMember::find()
-            .filter(team_member::Column::TeamId.eq(team_id))
-            .filter(team_member::Column::UserId.eq(&user_id))
-            .one(&self.ctx.db)
-            .await?
-            .is_some()
-        {
+        if self.is_team_member(team_id, &user_id).await? {
             return Ok(true);
         }
         Ok(false)
@@ -148,6 +177,7 @@ impl<'a> TeamLogic<'a> {
         }
         .save(&self.ctx.db)
         .await?;
+        self.ctx.cache.invalidate(CACHE_TEAM_MEMBER).await;
         Ok(team_id)
     }
 
@@ -244,6 +274,11 @@ impl<'a> TeamLogic<'a> {
             .filter(team_member::Column::TeamId.eq(id))
             .exec(&self.ctx.db)
             .await?;
+        UserGroupTeam::delete_many()
+            .filter(user_group_team::Column::TeamId.eq(id))
+            .exec(&self.ctx.db)
+            .await?;
+        self.ctx.cache.invalidate(CACHE_TEAM_MEMBER).await;
 
         Ok(ret.rows_affected)
     }
@@ -278,10 +313,24 @@ impl<'a> TeamLogic<'a> {
             })
             .collect::<Vec<team_member::ActiveModel>>();
 
-        return Ok(TeamMember::insert_many(members)
+        // the members added manually replace the ones joined by the user groups
+        TeamMember::delete_many()
+            .filter(team_member::Column::TeamId.eq(team_id))
+            .filter(team_member::Column::GroupId.ne(0))
+            .filter(
+                team_member::Column::UserId.is_in(
+                    user_list
+                        .iter()
+                        .map(|v| v.user_id.clone())
+                        .collect::<Vec<String>>(),
+                ),
+            )
             .exec(&self.ctx.db)
-            .await?
-            .last_insert_id);
+            .await?;
+
+        let ret = TeamMember::insert_many(members).exec(&self.ctx.db).await?;
+        self.ctx.cache.invalidate(CACHE_TEAM_MEMBER).await;
+        Ok(ret.last_insert_id)
     }
 
     pub async fn remove_member(&self, team_id: u64, user_ids: Option<Vec<String>>) -> Result<u64> {
@@ -308,12 +357,310 @@ impl<'a> TeamLogic<'a> {
             anyhow::bail!("cannot remove team creator");
         }
 
-        Ok(TeamMember::delete_many()
+        // the members joined by the user groups leave with the group
+        let ret = TeamMember::delete_many()
             .filter(team_member::Column::TeamId.eq(team_id))
             .filter(team_member::Column::UserId.is_in(user_ids))
+            .filter(team_member::Column::GroupId.eq(0))
             .exec(&self.ctx.db)
+            .await?;
+        self.ctx.cache.invalidate(CACHE_TEAM_MEMBER).await;
+        Ok(ret.rows_affected)
+    }
+
+    pub async fn save_user_group(&self, active_model: user_group::ActiveModel) -> Result<u64> {
+        let active_model = active_model.save(&self.ctx.db).await?;
+        Ok(active_model.id.as_ref().to_owned())
+    }
+
+    pub async fn get_user_group(&self, group_id: u64) -> Result<Option<user_group::Model>> {
+        Ok(UserGroup::find_by_id(group_id).one(&self.ctx.db).await?)
+    }
+
+    pub async fn query_user_group(
+        &self,
+        name: Option<String>,
+        page: u64,
+        page_size: u64,
+    ) -> Result<(Vec<user_group::Model>, u64)> {
+        let model = UserGroup::find().apply_if(name, |query, v| {
+            query.filter(user_group::Column::Name.contains(v))
+        });
+        let total = model.clone().count(&self.ctx.db).await?;
+        let list = model
+            .order_by_desc(user_group::Column::UpdatedTime)
+            .paginate(&self.ctx.db, page_size)
+            .fetch_page(page)
+            .await?;
+        Ok((list, total))
+    }
+
+    pub async fn delete_user_group(&self, group_id: u64) -> Result<u64> {
+        let txn = self.ctx.db.begin().await?;
+        let ret = UserGroup::delete_by_id(group_id).exec(&txn).await?;
+        UserGroupMember::delete_many()
+            .filter(user_group_member::Column::GroupId.eq(group_id))
+            .exec(&txn)
+            .await?;
+        UserGroupTeam::delete_many()
+            .filter(user_group_team::Column::GroupId.eq(group_id))
+            .exec(&txn)
+            .await?;
+        txn.commit().await?;
+
+        self.sync_group_team_members().await?;
+        Ok(ret.rows_affected)
+    }
+
+    pub async fn get_user_group_members(
+        &self,
+        group_id: u64,
+    ) -> Result<Vec<types::UserGroupMemberRecord>> {
+        Ok(UserGroupMember::find()
+            .select_only()
+            .column(user_group_member::Column::UserId)
+            .column(user_group_member::Column::CreatedTime)
+            .column(user::Column::Username)
+            .join_rev(
+                JoinType::InnerJoin,
+                User::belongs_to(UserGroupMember)
+                    .from(user::Column::UserId)
+                    .to(user_group_member::Column::UserId)
+                    .into(),
+            )
+            .filter(user_group_member::Column::GroupId.eq(group_id))
+            .into_model()
+            .all(&self.ctx.db)
+            .await?)
+    }
+
+    pub async fn get_user_group_teams(
+        &self,
+        group_id: u64,
+    ) -> Result<Vec<types::UserGroupTeamRecord>> {
+        Ok(UserGroupTeam::find()
+            .select_only()
+            .column(user_group_team::Column::TeamId)
+            .column(user_group_team::Column::IsAdmin)
+            .column_as(team::Column::Name, "team_name")
+            .join_rev(
+                JoinType::InnerJoin,
+                Team::belongs_to(UserGroupTeam)
+                    .from(team::Column::Id)
+                    .to(user_group_team::Column::TeamId)
+                    .into(),
+            )
+            .filter(user_group_team::Column::GroupId.eq(group_id))
+            .into_model()
+            .all(&self.ctx.db)
+            .await?)
+    }
+
+    /// replace the members of the user group, returns the number of (added, removed) members
+    pub async fn set_user_group_members(
+        &self,
+        group_id: u64,
+        user_ids: Vec<String>,
+        created_user: &str,
+    ) -> Result<(u64, u64)> {
+        if self.get_user_group(group_id).await?.is_none() {
+            anyhow::bail!("invalid user group");
+        }
+        let user_ids: HashSet<String> = User::find()
+            .select_only()
+            .column(user::Column::UserId)
+            .filter(user::Column::UserId.is_in(user_ids))
+            .into_tuple::<String>()
+            .all(&self.ctx.db)
+            .await?
+            .into_iter()
+            .collect();
+        let exists: HashSet<String> = UserGroupMember::find()
+            .select_only()
+            .column(user_group_member::Column::UserId)
+            .filter(user_group_member::Column::GroupId.eq(group_id))
+            .into_tuple::<String>()
+            .all(&self.ctx.db)
             .await?
-            .rows_affected)
+            .into_iter()
+            .collect();
+
+        let added: Vec<user_group_member::ActiveModel> = user_ids
+            .difference(&exists)
+            .map(|v| user_group_member::ActiveModel {
+                group_id: Set(group_id),
+                user_id: Set(v.to_owned()),
+                created_user: Set(created_user.to_string()),
+                ..Default::default()
+            })
+            .collect();
+        let removed: Vec<String> = exists.difference(&user_ids).cloned().collect();
+        let (added_num, removed_num) = (added.len() as u64, removed.len() as u64);
+
+        let txn = self.ctx.db.begin().await?;
+        if !removed.is_empty() {
+            UserGroupMember::delete_many()
+                .filter(user_group_member::Column::GroupId.eq(group_id))
+                .filter(user_group_member::Column::UserId.is_in(removed))
+                .exec(&txn)
+                .await?;
+        }
+        if !added.is_empty() {
+            UserGroupMember::insert_many(added).exec(&txn).await?;
+        }
+        txn.commit().await?;
+
+        self.sync_group_team_members().await?;
+        Ok((added_num, removed_num))
+    }
+
+    /// replace the members of the group with the users of an external source, eg: ldap.
+    /// the group is created if not exists
+    pub async fn sync_user_group(
+        &self,
+        name: &str,
+        source: &str,
+        usernames: Vec<String>,
+        created_user: &str,
+    ) -> Result<types::SyncUserGroupResult> {
+        let group_id = match UserGroup::find()
+            .filter(user_group::Column::Name.eq(name))
+            .one(&self.ctx.db)
+            .await?
+        {
+            Some(v) => v.id,
+            None => {
+                self.save_user_group(user_group::ActiveModel {
+                    name: Set(name.to_string()),
+                    source: Set(source.to_string()),
+                    created_user: Set(created_user.to_string()),
+                    updated_user: Set(created_user.to_string()),
+                    ..Default::default()
+                })
+                .await?
+            }
+        };
+
+        let users = User::find()
+            .filter(user::Column::Username.is_in(usernames.clone()))
+            .all(&self.ctx.db)
+            .await?;
+        let unknown = usernames
+            .into_iter()
+            .filter(|v| !users.iter().any(|u| &u.username == v))
+            .collect();
+
+        let (added, removed) = self
+            .set_user_group_members(
+                group_id,
+                users.into_iter().map(|v| v.user_id).collect(),
+                created_user,
+            )
+            .await?;
+        Ok(types::SyncUserGroupResult {
+            group_id,
+            added,
+            removed,
+            unknown,
+        })
+    }
+
+    /// replace the teams the members of the group join, (team_id, is_admin)
+    pub async fn set_user_group_teams(
+        &self,
+        group_id: u64,
+        teams: Vec<(u64, bool)>,
+        created_user: &str,
+    ) -> Result<u64> {
+        if self.get_user_group(group_id).await?.is_none() {
+            anyhow::bail!("invalid user group");
+        }
+        let team_ids: Vec<u64> = teams.iter().map(|v| v.0).collect();
+        let team_num = Team::find()
+            .filter(team::Column::Id.is_in(team_ids.clone()))
+            .count(&self.ctx.db)
+            .await?;
+        if team_num != team_ids.iter().collect::<HashSet<_>>().len() as u64 {
+            anyhow::bail!("invalid team");
+        }
+
+        let txn = self.ctx.db.begin().await?;
+        UserGroupTeam::delete_many()
+            .filter(user_group_team::Column::GroupId.eq(group_id))
+            .exec(&txn)
+            .await?;
+        let mappings: BTreeMap<u64, bool> = teams.into_iter().collect();
+        let total = mappings.len() as u64;
+        if !mappings.is_empty() {
+            UserGroupTeam::insert_many(mappings.into_iter().map(|(team_id, is_admin)| {
+                user_group_team::ActiveModel {
+                    group_id: Set(group_id),
+                    team_id: Set(team_id),
+                    is_admin: Set(is_admin),
+                    created_user: Set(created_user.to_string()),
+                    ..Default::default()
+                }
+            }))
+            .exec(&txn)
+            .await?;
+        }
+        txn.commit().await?;
+
+        self.sync_group_team_members().await?;
+        Ok(total)
+    }
+
+    /// reconcile the team members joined by the user groups with the groups and their teams
+    pub async fn sync_group_team_members(&self) -> Result<()> {
+        let txn = self.ctx.db.begin().await?;
+        let group_members = UserGroupMember::find().all(&txn).await?;
+        let group_teams = UserGroupTeam::find().all(&txn).await?;
+        let team_members = TeamMember::find().all(&txn).await?;
+
+        let manual: HashSet<(u64, String)> = team_members
+            .iter()
+            .filter(|v| v.group_id == 0)
+            .map(|v| (v.team_id, v.user_id.clone()))
+            .collect();
+        let mut wanted = plan_group_team_members(&group_members, &group_teams, &manual);
+
+        for v in team_members.into_iter().filter(|v| v.group_id != 0) {
+            match wanted.remove(&(v.team_id, v.user_id.clone())) {
+                Some((is_admin, group_id)) => {
+                    if is_admin != v.is_admin || group_id != v.group_id {
+                        team_member::ActiveModel {
+                            id: Set(v.id),
+                            is_admin: Set(is_admin),
+                            group_id: Set(group_id),
+                            ..Default::default()
+                        }
+                        .update(&txn)
+                        .await?;
+                    }
+                }
+                None => {
+                    TeamMember::delete_by_id(v.id).exec(&txn).await?;
+                }
+            }
+        }
+
+        if !wanted.is_empty() {
+            TeamMember::insert_many(wanted.into_iter().map(
+                |((team_id, user_id), (is_admin, group_id))| team_member::ActiveModel {
+                    team_id: Set(team_id),
+                    user_id: Set(user_id),
+                    is_admin: Set(is_admin),
+                    group_id: Set(group_id),
+                    created_user: Set("system".to_string()),
+                    ..Default::default()
+                },
+            ))
+            .exec(&txn)
+            .await?;
+        }
+        txn.commit().await?;
+        self.ctx.cache.invalidate(CACHE_TEAM_MEMBER).await;
+        Ok(())
     }
 
     pub async fn count_team_member(&self) -> Result<types::TeamMemberCountList> {
@@ -328,3 +675,58 @@ impl<'a> TeamLogic<'a> {
         Ok(types::TeamMemberCountList(list))
     }
 }
+
+/// the team members the user groups require, (team_id, user_id) -> (is_admin, group_id).
+/// the members added manually are skipped, a member of several groups joins by the first one
+/// and is an admin if any of them grants it
+fn plan_group_team_members(
+    group_members: &[user_group_member::Model],
+    group_teams: &[user_group_team::Model],
+    manual: &HashSet<(u64, String)>,
+) -> BTreeMap<(u64, String), (bool, u64)> {
+    let mut wanted: BTreeMap<(u64, String), (bool, u64)> = BTreeMap::new();
+    for mapping in group_teams {
+        for member in group_members
+            .iter()
+            .filter(|v| v.group_id == mapping.group_id)
+        {
+            let key = (mapping.team_id, member.user_id.clone());
+            if manual.contains(&key) {
+                continue;
+            }
+            let entry = wanted
+                .entry(key)
+                .or_insert((mapping.is_admin, mapping.group_id));
+            entry.0 |= mapping.is_admin;
+            entry.1 = entry.1.min(mapping.group_id);
+        }
+    }
+    wanted
+}
+
+#[test]
+fn test_plan_group_team_members() {
+    let member = |group_id: u64, user_id: &str| user_group_member::Model {
+        group_id,
+        user_id: user_id.to_string(),
+        ..Default::default()
+    };
+    let mapping = |group_id: u64, team_id: u64, is_admin: bool| user_group_team::Model {
+        group_id,
+        team_id,
+        is_admin,
+        ..Default::default()
+    };
+    let group_members = vec![member(1, "u1"), member(1, "u2"), member(2, "u2")];
+    let group_teams = vec![
+        mapping(1, 10, false),
+        mapping(2, 10, true),
+        mapping(2, 20, false),
+    ];
+    let manual = HashSet::from([(10, "u1".to_string())]);
+
+    let wanted = plan_group_team_members(&group_members, &group_teams, &manual);
+    assert_eq!(wanted.len(), 2);
+    assert_eq!(wanted[&(10, "u2".to_string())], (true, 1));
+    assert_eq!(wanted[&(20, "u2".to_string())], (false, 2));
+}
@@ -0,0 +1,185 @@
+use anyhow::Result;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::NotSet, ColumnTrait, EntityTrait, JoinType, PaginatorTrait,
+    QueryFilter, QuerySelect, Set,
+};
+use sea_query::Expr;
+
+use super::TeamLogic;
+use crate::{
+    entity::{job, job_supervisor, job_timer, prelude::*, team_quota, team_upload_file},
+    logic::types::{QuotaResource, TeamUsage},
+};
+
+/// fails if adding the amount to the used one exceeds the limit, 0 means unlimited
+fn check_limit(resource: QuotaResource, limit: u64, used: u64, add: u64) -> Result<()> {
+    if limit == 0 || used + add <= limit {
+        return Ok(());
+    }
+    anyhow::bail!("team quota exceeded, {resource} used {used} of {limit}, requested {add} more")
+}
+
+impl<'a> TeamLogic<'a> {
+    /// the quota of the team, none if unlimited
+    pub async fn get_quota(&self, team_id: u64) -> Result<Option<team_quota::Model>> {
+        Ok(TeamQuota::find()
+            .filter(team_quota::Column::TeamId.eq(team_id))
+            .one(&self.ctx.db)
+            .await?)
+    }
+
+    pub async fn save_quota(&self, mut active_model: team_quota::ActiveModel) -> Result<u64> {
+        let team_id = active_model.team_id.clone().unwrap();
+        if self.get_team(team_id).await?.is_none() {
+            anyhow::bail!("invalid team");
+        }
+        active_model.id = self.get_quota(team_id).await?.map_or(NotSet, |v| Set(v.id));
+        let active_model = active_model.save(&self.ctx.db).await?;
+        Ok(active_model.id.as_ref().to_owned())
+    }
+
+    pub async fn get_usage(&self, team_id: u64) -> Result<TeamUsage> {
+        let jobs = Job::find()
+            .filter(job::Column::TeamId.eq(team_id))
+            .filter(job::Column::IsDeleted.eq(false))
+            .count(&self.ctx.db)
+            .await?;
+
+        let timers = JobTimer::find()
+            .join_rev(
+                JoinType::InnerJoin,
+                Job::belongs_to(JobTimer)
+                    .from(job::Column::Eid)
+                    .to(job_timer::Column::Eid)
+                    .into(),
+            )
+            .filter(job::Column::TeamId.eq(team_id))
+            .filter(job::Column::IsDeleted.eq(false))
+            .filter(job_timer::Column::IsDeleted.eq(false))
+            .count(&self.ctx.db)
+            .await?;
+
+        let daemons = JobSupervisor::find()
+            .join_rev(
+                JoinType::InnerJoin,
+                Job::belongs_to(JobSupervisor)
+                    .from(job::Column::Eid)
+                    .to(job_supervisor::Column::Eid)
+                    .into(),
+            )
+            .filter(job::Column::TeamId.eq(team_id))
+            .filter(job::Column::IsDeleted.eq(false))
+            .filter(job_supervisor::Column::IsDeleted.eq(false))
+            .count(&self.ctx.db)
+            .await?;
+
+        let upload_bytes = TeamUploadFile::find()
+            .select_only()
+            // SUM returns a decimal in mysql
+            .column_as(
+                Expr::cust("CAST(COALESCE(SUM(`size`), 0) AS UNSIGNED)"),
+                "total",
+            )
+            .filter(team_upload_file::Column::TeamId.eq(team_id))
+            .into_tuple::<u64>()
+            .one(&self.ctx.db)
+            .await?
+            .unwrap_or_default();
+
+        Ok(TeamUsage {
+            jobs,
+            timers,
+            daemons,
+            upload_bytes,
+        })
+    }
+
+    /// fails if the team cannot hold the given amount more of the resource,
+    /// the resources without a team are unlimited
+    pub async fn check_quota(&self, team_id: u64, resource: QuotaResource, add: u64) -> Result<()> {
+        if team_id == 0 {
+            return Ok(());
+        }
+        let Some(quota) = self.get_quota(team_id).await? else {
+            return Ok(());
+        };
+        let limit = match resource {
+            QuotaResource::Job => quota.max_jobs as u64,
+            QuotaResource::Timer => quota.max_timers as u64,
+            QuotaResource::Daemon => quota.max_daemons as u64,
+            QuotaResource::UploadBytes => quota.max_upload_bytes,
+        };
+        if limit == 0 {
+            return Ok(());
+        }
+        let usage = self.get_usage(team_id).await?;
+        check_limit(resource, limit, usage.get(resource), add)
+    }
+
+    /// the team the job belongs to, 0 if the job has no team
+    pub async fn get_job_team_id(&self, eid: &str) -> Result<u64> {
+        Ok(Job::find()
+            .select_only()
+            .column(job::Column::TeamId)
+            .filter(job::Column::Eid.eq(eid))
+            .into_tuple::<u64>()
+            .one(&self.ctx.db)
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// record the file uploaded on behalf of the team after checking the upload quota,
+    /// a file of the same name replaces the previous one
+    pub async fn save_upload_file(
+        &self,
+        team_id: u64,
+        filename: &str,
+        size: u64,
+        created_user: &str,
+    ) -> Result<()> {
+        let record = TeamUploadFile::find()
+            .filter(team_upload_file::Column::Filename.eq(filename))
+            .one(&self.ctx.db)
+            .await?;
+        let replaced = record
+            .as_ref()
+            .filter(|v| v.team_id == team_id)
+            .map_or(0, |v| v.size);
+        self.check_quota(
+            team_id,
+            QuotaResource::UploadBytes,
+            size.saturating_sub(replaced),
+        )
+        .await?;
+
+        team_upload_file::ActiveModel {
+            id: record.map_or(NotSet, |v| Set(v.id)),
+            team_id: Set(team_id),
+            filename: Set(filename.to_string()),
+            size: Set(size),
+            created_user: Set(created_user.to_string()),
+            ..Default::default()
+        }
+        .save(&self.ctx.db)
+        .await?;
+        Ok(())
+    }
+
+    /// forget the uploaded file, eg: the file is removed from the upload directory
+    pub async fn delete_upload_file(&self, filename: &str) -> Result<u64> {
+        Ok(TeamUploadFile::delete_many()
+            .filter(team_upload_file::Column::Filename.eq(filename))
+            .exec(&self.ctx.db)
+            .await?
+            .rows_affected)
+    }
+}
+
+#[test]
+fn test_check_limit() {
+    assert!(check_limit(QuotaResource::Job, 0, 100, 1).is_ok());
+    assert!(check_limit(QuotaResource::Job, 10, 9, 1).is_ok());
+    assert!(check_limit(QuotaResource::Job, 10, 10, 1).is_err());
+    assert!(check_limit(QuotaResource::UploadBytes, 1024, 1000, 25).is_err());
+    assert!(check_limit(QuotaResource::UploadBytes, 1024, 1000, 0).is_ok());
+}
@@ -1,5 +1,7 @@
 use std::{collections::HashMap, fmt::Display};
 
+use chrono::Local;
+
 use sea_orm::{FromQueryResult, prelude::DateTimeLocal};
 use serde::{Deserialize, Serialize};
 
@@ -19,6 +21,12 @@ pub struct UserInfo {
     pub permissions: Vec<String>,
     pub created_time: String,
     pub updated_time: String,
+    /// IANA name of the zone the times are displayed in, the server zone is used if empty
+    #[serde(default)]
+    pub timezone: String,
+    /// only changing the password is allowed until it is rotated
+    #[serde(default)]
+    pub password_expired: bool,
 }
 
 #[derive(Clone, Serialize, Deserialize, Default, FromQueryResult)]
@@ -28,19 +36,40 @@ pub struct UserRecord {
     pub username: String,
     pub nickname: String,
     pub is_root: bool,
+    pub is_disabled: bool,
     pub role_id: u64,
     pub salt: String,
     pub password: String,
+    pub password_expired: bool,
     pub avatar: String,
     pub email: String,
     pub phone: String,
     pub gender: String,
     pub role: Option<String>,
     pub introduction: String,
+    pub timezone: String,
     pub created_time: DateTimeLocal,
     pub updated_time: DateTimeLocal,
 }
 
+/// token for the clients without a browser session, eg: jiascheduler-ctl,
+/// the requests are authorized as the user who created it
+#[derive(Clone, Serialize, Deserialize, Default)]
+pub struct ApiToken {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    /// unix timestamp, never expires if none
+    pub expire_time: Option<i64>,
+    pub created_time: i64,
+}
+
+impl ApiToken {
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expire_time.is_some_and(|v| v <= now)
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Default, FromQueryResult)]
 pub struct UserServer {
     pub id: u64,
@@ -52,10 +81,13 @@ pub struct UserServer {
     pub sys_user: Option<String>,
     pub ssh_port: Option<u16>,
     pub password: Option<String>,
+    pub private_key: Option<String>,
+    pub key_passphrase: Option<String>,
     pub instance_group_id: Option<u64>,
     pub instance_group_name: Option<String>,
     pub tag_id: Option<u64>,
     pub status: i8,
+    pub maintenance: bool,
     pub created_time: DateTimeLocal,
     pub updated_time: DateTimeLocal,
 }
@@ -82,6 +114,7 @@ pub struct InstanceRecord {
     pub namespace: String,
     pub info: String,
     pub status: i8,
+    pub maintenance: bool,
     pub sys_user: String,
     pub password: String,
     pub role_id: Option<u64>,
@@ -89,10 +122,58 @@ pub struct InstanceRecord {
     pub instance_group: Option<String>,
     pub instance_group_id: u64,
     pub ssh_port: u16,
+    pub os: String,
+    pub os_version: String,
+    pub kernel_version: String,
+    pub arch: String,
+    pub cpu_count: u32,
+    pub mem_total: u64,
+    pub mem_used: u64,
+    pub disk_total: u64,
+    pub disk_used: u64,
+    pub agent_version: String,
+    pub facts_updated_time: Option<DateTimeLocal>,
     pub created_time: DateTimeLocal,
     pub updated_time: DateTimeLocal,
 }
 
+impl InstanceRecord {
+    /// aggregate the online status and the facts reported in heartbeat into
+    /// healthy, degraded, offline or unknown, with the reasons of degradation
+    pub fn health(&self) -> (&'static str, Vec<String>) {
+        if self.status != 1 {
+            return ("offline", vec![]);
+        }
+        let Some(facts_updated_time) = self.facts_updated_time else {
+            return ("unknown", vec![]);
+        };
+
+        let mut reasons = vec![];
+        let elapsed = (Local::now() - facts_updated_time).num_seconds();
+        if elapsed > 180 {
+            reasons.push(format!("no heartbeat for {elapsed}s"));
+        }
+        if self.disk_total > 0 && self.disk_used * 100 / self.disk_total >= 90 {
+            reasons.push(format!(
+                "disk usage {}%",
+                self.disk_used * 100 / self.disk_total
+            ));
+        }
+        if self.mem_total > 0 && self.mem_used * 100 / self.mem_total >= 95 {
+            reasons.push(format!(
+                "memory usage {}%",
+                self.mem_used * 100 / self.mem_total
+            ));
+        }
+
+        if reasons.is_empty() {
+            ("healthy", reasons)
+        } else {
+            ("degraded", reasons)
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Default)]
 pub struct VersionRecord {
     pub name: String,
@@ -138,6 +219,68 @@ pub struct TeamRecord {
     pub updated_time: DateTimeLocal,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QuotaResource {
+    Job,
+    Timer,
+    Daemon,
+    UploadBytes,
+}
+
+impl Display for QuotaResource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QuotaResource::Job => write!(f, "jobs"),
+            QuotaResource::Timer => write!(f, "timers"),
+            QuotaResource::Daemon => write!(f, "daemons"),
+            QuotaResource::UploadBytes => write!(f, "upload bytes"),
+        }
+    }
+}
+
+/// resources held by a team, counted against its quota
+#[derive(Clone, Serialize, Deserialize, Default)]
+pub struct TeamUsage {
+    pub jobs: u64,
+    pub timers: u64,
+    pub daemons: u64,
+    pub upload_bytes: u64,
+}
+
+impl TeamUsage {
+    pub fn get(&self, resource: QuotaResource) -> u64 {
+        match resource {
+            QuotaResource::Job => self.jobs,
+            QuotaResource::Timer => self.timers,
+            QuotaResource::Daemon => self.daemons,
+            QuotaResource::UploadBytes => self.upload_bytes,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Default, FromQueryResult)]
+pub struct UserGroupMemberRecord {
+    pub user_id: String,
+    pub username: String,
+    pub created_time: DateTimeLocal,
+}
+
+#[derive(Clone, Serialize, Deserialize, Default, FromQueryResult)]
+pub struct UserGroupTeamRecord {
+    pub team_id: u64,
+    pub team_name: String,
+    pub is_admin: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize, Default)]
+pub struct SyncUserGroupResult {
+    pub group_id: u64,
+    pub added: u64,
+    pub removed: u64,
+    /// usernames without a user, they are ignored
+    pub unknown: Vec<String>,
+}
+
 #[derive(Clone, Serialize, Deserialize, PartialEq)]
 pub enum ResourceType {
     Job,
@@ -193,4 +336,50 @@ pub enum CompletedCallbackTriggerType {
 pub struct CustomTimerExpr {
     pub timezone: String,
     pub expr: String,
+    /// run every interval seconds instead of the cron expr
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval: Option<u64>,
+    /// percentage of the interval each run is randomly delayed by
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jitter: Option<u8>,
+}
+
+impl CustomTimerExpr {
+    /// validate the timer and return the next execution times
+    pub fn check(&self) -> anyhow::Result<Vec<String>> {
+        match self.interval {
+            Some(interval) => utils::check_interval_timer(&self.timezone, interval, self.jitter),
+            None => utils::check_timer_expr(&self.timezone, &self.expr),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ContainerOptions {
+    pub runtime: Option<String>,
+    pub cpus: Option<String>,
+    pub memory: Option<String>,
+    pub network: Option<String>,
+    #[serde(default)]
+    pub extra_args: Vec<String>,
+}
+
+/// command of the executor for each os, the command of the executor is used if it is empty
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct PlatformCommands {
+    pub linux: Option<String>,
+    pub windows: Option<String>,
+    pub darwin: Option<String>,
+}
+
+#[test]
+fn test_api_token_expired() {
+    let mut token = ApiToken {
+        expire_time: Some(100),
+        ..Default::default()
+    };
+    assert!(!token.is_expired(99));
+    assert!(token.is_expired(100));
+    token.expire_time = None;
+    assert!(!token.is_expired(i64::MAX));
 }
@@ -1,10 +1,17 @@
 use crate::{
-    entity::{self, role, user},
+    IdGenerator,
+    cache::CACHE_USER_SERVER,
+    entity::{self, emergency_token, role, user},
     state::AppContext,
 };
 use anyhow::{Result, anyhow};
+use chrono::{Local, TimeDelta, Utc};
 use crypto::digest::Digest;
 use crypto::md5::Md5;
+use crypto::sha2::Sha256;
+use crypto::util::fixed_time_eq;
+use redis::AsyncCommands;
+use std::{cmp::Reverse, collections::HashMap};
 
 use crate::state::AppState;
 
@@ -16,12 +23,36 @@ use sea_orm::*;
 
 use super::{omit_empty_active_value, types};
 
+mod dashboard;
+mod saved_filter;
+
+pub use dashboard::{DashboardWidget, WIDGET_TYPES};
+
+/// user id of the break-glass account, which has the admin role
+pub const BREAK_GLASS_USER_ID: &str = "break-glass";
+
+/// the role granted in the casbin policy, "0" revokes the role of the disabled users
+fn policy_role(disabled: bool, role_id: u64) -> String {
+    if disabled || role_id == 0 {
+        "0".to_string()
+    } else {
+        role_id.to_string()
+    }
+}
+
+fn hash_token(secret: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input_str(secret);
+    hasher.result_str()
+}
+
 #[derive(Clone)]
 pub struct UserLogic<'a> {
     ctx: &'a AppContext,
 }
 impl<'a> UserLogic<'a> {
     pub const SESS_KEY: &'static str = "USER_SESSION";
+    const API_TOKEN_KEY: &'static str = "jiascheduler:user:api-token";
 
     pub fn new(ctx: &'a AppContext) -> Self {
         Self { ctx }
@@ -57,19 +88,49 @@ impl<'a> UserLogic<'a> {
         Ok(ret)
     }
 
-    pub async fn valid_user(&self, username: &str, password: &str) -> Result<types::UserRecord> {
-        let got_user = self
-            .get_user(Some(username), None)
-            .await?
-            .ok_or(anyhow!("invalid username"))?;
+    /// None if the username or the password is wrong
+    pub async fn valid_user(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<types::UserRecord>> {
+        let Some(got_user) = self.get_user(Some(username), None).await? else {
+            return Ok(None);
+        };
 
         let password = Self::encry_password(password, &got_user.salt);
 
         if got_user.password != password {
-            Err(anyhow!("invalid username or password"))
+            Ok(None)
+        } else if got_user.is_disabled {
+            Err(anyhow!("user {username} is disabled"))
         } else {
-            Ok(got_user)
+            Ok(Some(got_user))
+        }
+    }
+
+    /// the break-glass account of the config if it matches, the user table is not read
+    pub fn valid_break_glass(&self, username: &str, password: &str) -> Option<types::UserRecord> {
+        let account = self.ctx.conf.break_glass.as_ref()?;
+        if account.username.is_empty()
+            || account.password.is_empty()
+            || account.username != username
+            || !fixed_time_eq(account.password.as_bytes(), password.as_bytes())
+        {
+            return None;
         }
+        let now = Local::now();
+        Some(types::UserRecord {
+            user_id: BREAK_GLASS_USER_ID.to_string(),
+            username: account.username.clone(),
+            nickname: account.username.clone(),
+            is_root: true,
+            role_id: 1,
+            role: Some("admin".to_string()),
+            created_time: now,
+            updated_time: now,
+            ..Default::default()
+        })
     }
 
     pub async fn save(db: &DbConn, user: user::Model) -> Result<user::ActiveModel, DbErr> {
@@ -124,7 +185,10 @@ impl<'a> UserLogic<'a> {
         if let ActiveValue::Set(password) = record.password {
             let salt = nanoid!();
             record.password = Set(Self::encry_password(&password, &salt));
-            record.salt = Set(salt)
+            record.salt = Set(salt);
+            if !record.password_expired.is_set() {
+                record.password_expired = Set(false);
+            }
         }
         let user_id = if let ActiveValue::Set(ref v) = record.user_id {
             v.to_owned()
@@ -137,6 +201,7 @@ impl<'a> UserLogic<'a> {
             .await?
             .ok_or(anyhow!("invalid user_id"))?;
 
+        let role_changed = record.role_id.is_set();
         if let ActiveValue::Set(role_id) = record.role_id {
             if user_record.is_root && role_id != user_record.role_id {
                 anyhow::bail!("root user cannot modify")
@@ -158,6 +223,9 @@ impl<'a> UserLogic<'a> {
             .filter(user::Column::UserId.eq(user_id))
             .exec(&self.ctx.db)
             .await?;
+        if role_changed {
+            self.ctx.cache.invalidate(CACHE_USER_SERVER).await;
+        }
 
         Ok(update_result.rows_affected)
     }
@@ -171,12 +239,16 @@ impl<'a> UserLogic<'a> {
             .filter(user::Column::UserId.eq(user_id.into()))
             .exec(&self.ctx.db)
             .await?;
+        self.ctx.cache.invalidate(CACHE_USER_SERVER).await;
 
         Ok(update_result.rows_affected)
     }
 
     pub async fn load_user_role(&self, state: &AppState) -> Result<()> {
-        let user_records = User::find().all(&self.ctx.db).await?;
+        let user_records = User::find()
+            .filter(user::Column::IsDisabled.eq(false))
+            .all(&self.ctx.db)
+            .await?;
         for v in user_records {
             state
                 .set_role_for_user(&v.user_id, v.role_id.to_string().as_str())
@@ -185,7 +257,14 @@ impl<'a> UserLogic<'a> {
         Ok(())
     }
 
-    async fn init_admin_user(db: &DbConn, username: &str, password: &str) -> Result<()> {
+    /// create the bootstrap admin if not exists, the password must be changed on the first login.
+    /// the password of an existing admin is kept unless reset
+    async fn init_admin_user(
+        db: &DbConn,
+        username: &str,
+        password: &str,
+        reset: bool,
+    ) -> Result<()> {
         let salt = nanoid!();
         let record = User::find()
             .filter(user::Column::Username.eq(username))
@@ -196,6 +275,8 @@ impl<'a> UserLogic<'a> {
             username: Set(username.to_string()),
             nickname: Set(username.to_string()),
             password: Set(Self::encry_password(password.to_string(), salt.clone())),
+            password_expired: Set(true),
+            is_disabled: Set(false),
             gender: Set("male".to_string()),
             is_root: Set(true),
             salt: Set(salt),
@@ -208,6 +289,9 @@ impl<'a> UserLogic<'a> {
                 if !v.is_root {
                     anyhow::bail!("Do not change existing regular users to administrators.")
                 }
+                if !reset {
+                    return Ok(());
+                }
                 user_active_model.id = Set(v.id);
             }
             None => {
@@ -249,10 +333,159 @@ impl<'a> UserLogic<'a> {
 
     pub async fn init_admin(db: &DbConn, username: &str, password: &str) -> Result<()> {
         Self::init_admin_role(db, username).await?;
-        Self::init_admin_user(db, username, password).await?;
+        Self::init_admin_user(db, username, password, false).await?;
         Ok(())
     }
 
+    /// reset the password of the admin and enable it, the admin is created if not exists
+    pub async fn reset_admin(db: &DbConn, username: &str, password: &str) -> Result<()> {
+        Self::init_admin_role(db, username).await?;
+        Self::init_admin_user(db, username, password, true).await?;
+        Ok(())
+    }
+
+    /// create another super admin, who must change the password on the first login
+    pub async fn create_admin(
+        &self,
+        username: String,
+        nickname: String,
+        email: String,
+        password: String,
+    ) -> Result<String> {
+        if self.get_user(Some(&username), None).await?.is_some() {
+            anyhow::bail!("user {username} already exists");
+        }
+        let salt = nanoid!();
+        let user_id = nanoid!(10);
+        User::insert(user::ActiveModel {
+            nickname: Set(if nickname.is_empty() {
+                username.clone()
+            } else {
+                nickname
+            }),
+            username: Set(username),
+            user_id: Set(user_id.clone()),
+            password: Set(Self::encry_password(&password, &salt)),
+            password_expired: Set(true),
+            salt: Set(salt),
+            email: Set(email),
+            gender: Set("male".to_string()),
+            is_root: Set(true),
+            role_id: Set(1),
+            ..Default::default()
+        })
+        .exec(&self.ctx.db)
+        .await?;
+        Ok(user_id)
+    }
+
+    /// the enabled root users except the given one
+    async fn count_other_roots(&self, user_id: &str) -> Result<u64> {
+        Ok(User::find()
+            .filter(user::Column::IsRoot.eq(true))
+            .filter(user::Column::IsDisabled.eq(false))
+            .filter(user::Column::UserId.ne(user_id))
+            .count(&self.ctx.db)
+            .await?)
+    }
+
+    /// hand the root flag of the operator over to another user, the operator keeps the admin role
+    pub async fn transfer_ownership(&self, from_user_id: &str, to_user_id: &str) -> Result<()> {
+        if from_user_id == to_user_id {
+            anyhow::bail!("cannot transfer the ownership to yourself");
+        }
+        let from = self
+            .get_user(None, Some(from_user_id))
+            .await?
+            .ok_or(anyhow!("invalid user_id"))?;
+        if !from.is_root {
+            anyhow::bail!("only the root user can transfer the ownership");
+        }
+        let to = self
+            .get_user(None, Some(to_user_id))
+            .await?
+            .ok_or(anyhow!("invalid user_id"))?;
+        if to.is_disabled {
+            anyhow::bail!(
+                "cannot transfer the ownership to the disabled user {}",
+                to.username
+            );
+        }
+
+        let txn = self.ctx.db.begin().await?;
+        User::update_many()
+            .set(user::ActiveModel {
+                is_root: Set(true),
+                role_id: Set(1),
+                ..Default::default()
+            })
+            .filter(user::Column::UserId.eq(to_user_id))
+            .exec(&txn)
+            .await?;
+        User::update_many()
+            .set(user::ActiveModel {
+                is_root: Set(false),
+                ..Default::default()
+            })
+            .filter(user::Column::UserId.eq(from_user_id))
+            .exec(&txn)
+            .await?;
+        txn.commit().await?;
+        self.ctx.cache.invalidate(CACHE_USER_SERVER).await;
+        Ok(())
+    }
+
+    /// disable or enable the user, the last enabled root user cannot be disabled.
+    /// update_role_policy is called with the role to grant, "0" to revoke the role
+    pub async fn set_disabled<F, T>(
+        &self,
+        operator: &types::UserInfo,
+        user_id: &str,
+        disabled: bool,
+        update_role_policy: F,
+    ) -> Result<u64>
+    where
+        F: FnOnce(String, String) -> T,
+        T: Future<Output = Result<()>>,
+    {
+        if operator.user_id == user_id {
+            anyhow::bail!("cannot disable yourself");
+        }
+        let record = self
+            .get_user(None, Some(user_id))
+            .await?
+            .ok_or(anyhow!("invalid user_id"))?;
+        if record.is_root {
+            if !operator.is_root {
+                anyhow::bail!("only the root user can disable or enable a root user");
+            }
+            if disabled && self.count_other_roots(user_id).await? == 0 {
+                anyhow::bail!("cannot disable the last root user");
+            }
+        }
+
+        let update_result = User::update_many()
+            .set(user::ActiveModel {
+                is_disabled: Set(disabled),
+                ..Default::default()
+            })
+            .filter(user::Column::UserId.eq(user_id))
+            .exec(&self.ctx.db)
+            .await?;
+
+        update_role_policy(
+            record.user_id.clone(),
+            policy_role(disabled, record.role_id),
+        )
+        .await?;
+        if disabled {
+            self.revoke_user_api_tokens(&record.user_id).await?;
+        }
+        self.ctx.cache.invalidate(CACHE_USER_SERVER).await;
+
+        Ok(update_result.rows_affected)
+    }
+
     pub async fn count_by_role(&self) -> Result<types::UserRoleCountList> {
         let list: Vec<types::UserRoleCount> = User::find()
             .select_only()
@@ -321,4 +554,165 @@ impl<'a> UserLogic<'a> {
             .await?;
         Ok((list, total))
     }
+
+    /// create an api token of the user, returns the token secret
+    pub async fn create_api_token(
+        &self,
+        user_id: &str,
+        name: String,
+        expire_secs: Option<u64>,
+    ) -> Result<String> {
+        let now = Utc::now().timestamp();
+        let token = types::ApiToken {
+            id: IdGenerator::get_api_token_uid(),
+            user_id: user_id.to_string(),
+            name,
+            expire_time: expire_secs.map(|v| now + v as i64),
+            created_time: now,
+        };
+        let secret = nanoid!(40);
+        let mut conn = self.ctx.redis().get_multiplexed_async_connection().await?;
+        let _: () = conn
+            .hset(Self::API_TOKEN_KEY, &secret, serde_json::to_string(&token)?)
+            .await?;
+        Ok(secret)
+    }
+
+    /// list the api tokens of all users, expired tokens are removed
+    async fn get_api_tokens(&self) -> Result<Vec<(String, types::ApiToken)>> {
+        let mut conn = self.ctx.redis().get_multiplexed_async_connection().await?;
+        let all: HashMap<String, String> = conn.hgetall(Self::API_TOKEN_KEY).await?;
+        let now = Utc::now().timestamp();
+
+        let mut list = Vec::new();
+        for (secret, v) in all {
+            match serde_json::from_str::<types::ApiToken>(&v) {
+                Ok(token) if !token.is_expired(now) => list.push((secret, token)),
+                _ => {
+                    let _: () = conn.hdel(Self::API_TOKEN_KEY, &secret).await?;
+                }
+            }
+        }
+        list.sort_by_key(|v| Reverse(v.1.created_time));
+        Ok(list)
+    }
+
+    pub async fn query_api_token(&self, user_id: &str) -> Result<Vec<types::ApiToken>> {
+        Ok(self
+            .get_api_tokens()
+            .await?
+            .into_iter()
+            .filter(|v| v.1.user_id == user_id)
+            .map(|v| v.1)
+            .collect())
+    }
+
+    pub async fn revoke_api_token(&self, user_id: &str, id: &str) -> Result<u64> {
+        let mut conn = self.ctx.redis().get_multiplexed_async_connection().await?;
+        let mut affected = 0;
+        for (secret, token) in self.get_api_tokens().await? {
+            if token.id == id && token.user_id == user_id {
+                let n: u64 = conn.hdel(Self::API_TOKEN_KEY, &secret).await?;
+                affected += n;
+            }
+        }
+        Ok(affected)
+    }
+
+    async fn revoke_user_api_tokens(&self, user_id: &str) -> Result<u64> {
+        let mut conn = self.ctx.redis().get_multiplexed_async_connection().await?;
+        let mut affected = 0;
+        for (secret, token) in self.get_api_tokens().await? {
+            if token.user_id == user_id {
+                let n: u64 = conn.hdel(Self::API_TOKEN_KEY, &secret).await?;
+                affected += n;
+            }
+        }
+        Ok(affected)
+    }
+
+    /// create a token of the root user in the database for the offline recovery,
+    /// it is accepted when redis is unavailable. returns the token secret
+    pub async fn create_emergency_token(
+        db: &DbConn,
+        username: &str,
+        name: String,
+        expire_secs: u64,
+    ) -> Result<String> {
+        let record = User::find()
+            .filter(user::Column::Username.eq(username))
+            .one(db)
+            .await?
+            .ok_or(anyhow!("cannot found user {username}"))?;
+        if !record.is_root {
+            anyhow::bail!("emergency tokens can only be created for root users");
+        }
+
+        let secret = nanoid!(40);
+        emergency_token::ActiveModel {
+            user_id: Set(record.user_id),
+            name: Set(name),
+            token_hash: Set(hash_token(&secret)),
+            expire_time: Set(Local::now() + TimeDelta::seconds(expire_secs as i64)),
+            created_user: Set("recovery".to_string()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+        Ok(secret)
+    }
+
+    /// remove the emergency tokens of all users, returns the number of removed tokens
+    pub async fn revoke_emergency_tokens(db: &DbConn) -> Result<u64> {
+        Ok(EmergencyToken::delete_many().exec(db).await?.rows_affected)
+    }
+
+    /// the user the emergency token belongs to, none if the token is unknown or expired
+    pub async fn get_user_by_emergency_token(
+        &self,
+        secret: &str,
+    ) -> Result<Option<types::UserRecord>> {
+        let Some(token) = EmergencyToken::find()
+            .filter(emergency_token::Column::TokenHash.eq(hash_token(secret)))
+            .filter(emergency_token::Column::ExpireTime.gt(Local::now()))
+            .one(&self.ctx.db)
+            .await?
+        else {
+            return Ok(None);
+        };
+        Ok(self
+            .get_user(None, Some(&token.user_id))
+            .await?
+            .filter(|v| !v.is_disabled))
+    }
+
+    /// the user the api token belongs to, none if the token is unknown or expired
+    pub async fn get_user_by_api_token(&self, secret: &str) -> Result<Option<types::UserRecord>> {
+        let mut conn = self.ctx.redis().get_multiplexed_async_connection().await?;
+        let v: Option<String> = conn.hget(Self::API_TOKEN_KEY, secret).await?;
+        let Some(token) = v.and_then(|v| serde_json::from_str::<types::ApiToken>(&v).ok()) else {
+            return Ok(None);
+        };
+        if token.is_expired(Utc::now().timestamp()) {
+            return Ok(None);
+        }
+        Ok(self
+            .get_user(None, Some(&token.user_id))
+            .await?
+            .filter(|v| !v.is_disabled))
+    }
+}
+
+#[test]
+fn test_hash_token() {
+    assert_eq!(hash_token("secret"), hash_token("secret"));
+    assert_ne!(hash_token("secret"), hash_token("secret2"));
+    assert_eq!(hash_token("secret").len(), 64);
+}
+
+#[test]
+fn test_policy_role() {
+    assert_eq!(policy_role(false, 3), "3");
+    assert_eq!(policy_role(true, 3), "0");
+    assert_eq!(policy_role(false, 0), "0");
 }
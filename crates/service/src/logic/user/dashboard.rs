@@ -0,0 +1,304 @@
+use anyhow::{Result, anyhow};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::NotSet, ColumnTrait, Condition, EntityTrait, QueryFilter,
+    QueryOrder, QueryTrait, Set,
+};
+use sea_query::Expr;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::UserLogic;
+use crate::{
+    entity::{dashboard, prelude::*},
+    logic::types::UserInfo,
+};
+
+pub const WIDGET_JOB_STATS: &str = "job_stats";
+pub const WIDGET_INSTANCE_STATUS: &str = "instance_status";
+pub const WIDGET_RECENT_FAILURES: &str = "recent_failures";
+pub const WIDGET_SAVED_SEARCH: &str = "saved_search";
+pub const WIDGET_TYPES: [&str; 4] = [
+    WIDGET_JOB_STATS,
+    WIDGET_INSTANCE_STATUS,
+    WIDGET_RECENT_FAILURES,
+    WIDGET_SAVED_SEARCH,
+];
+pub const MAX_WIDGETS: usize = 50;
+
+/// a widget of the dashboard, the data is loaded by the console from the endpoint of its type
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct DashboardWidget {
+    /// job_stats, instance_status, recent_failures or saved_search
+    pub widget_type: String,
+    #[serde(default)]
+    pub title: String,
+    /// job_stats: {"job_type": "default"}, recent_failures: {"days": 1, "limit": 10},
+    /// saved_search: {"filter_id": 1}
+    #[serde(default)]
+    pub params: Option<Value>,
+    /// position and size in the grid of the dashboard
+    #[serde(default)]
+    pub x: u32,
+    #[serde(default)]
+    pub y: u32,
+    #[serde(default)]
+    pub w: u32,
+    #[serde(default)]
+    pub h: u32,
+}
+
+impl DashboardWidget {
+    pub fn check(&self) -> Result<()> {
+        if !WIDGET_TYPES.contains(&self.widget_type.as_str()) {
+            anyhow::bail!(
+                "invalid widget type {}, expected one of {}",
+                self.widget_type,
+                WIDGET_TYPES.join(", ")
+            );
+        }
+        let params = self.params.clone().unwrap_or_default();
+        match self.widget_type.as_str() {
+            WIDGET_RECENT_FAILURES => {
+                if params["limit"].as_u64().is_some_and(|v| v > 100) {
+                    anyhow::bail!("the limit of the recent failures widget must be at most 100");
+                }
+                if params["days"].as_u64().is_some_and(|v| v == 0 || v > 30) {
+                    anyhow::bail!(
+                        "the days of the recent failures widget must be between 1 and 30"
+                    );
+                }
+            }
+            WIDGET_SAVED_SEARCH if self.filter_id().is_none() => {
+                anyhow::bail!("the saved search widget requires the filter_id");
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// the saved filter shown by the saved search widget
+    pub fn filter_id(&self) -> Option<u64> {
+        self.params.as_ref().and_then(|v| v["filter_id"].as_u64())
+    }
+}
+
+impl<'a> UserLogic<'a> {
+    /// the dashboard can be changed by its creator, or the admins of the team it is shared with
+    async fn can_write_dashboard(
+        &self,
+        user_info: &UserInfo,
+        record: &dashboard::Model,
+    ) -> Result<bool> {
+        if record.created_user == user_info.username {
+            return Ok(true);
+        }
+        if record.team_id == 0 {
+            return Ok(false);
+        }
+        self.ctx
+            .service()
+            .team
+            .can_write_team(Some(record.team_id), user_info.user_id.clone())
+            .await
+    }
+
+    /// create or update the dashboard, it is shared with the members of the team if team_id
+    /// is not 0. the default one is the home view of the team, or of the creator if private
+    pub async fn save_dashboard(
+        &self,
+        user_info: &UserInfo,
+        id: Option<u64>,
+        name: String,
+        widgets: Vec<DashboardWidget>,
+        team_id: u64,
+        is_default: bool,
+    ) -> Result<u64> {
+        if widgets.len() > MAX_WIDGETS {
+            anyhow::bail!("a dashboard has at most {MAX_WIDGETS} widgets");
+        }
+        for widget in &widgets {
+            widget.check()?;
+        }
+
+        let team = self.ctx.service().team;
+        if team_id != 0
+            && !team
+                .can_read_team(Some(team_id), user_info.user_id.clone())
+                .await?
+        {
+            anyhow::bail!("cannot share the dashboard with a team you are not a member of");
+        }
+        if team_id != 0
+            && is_default
+            && !team
+                .can_write_team(Some(team_id), user_info.user_id.clone())
+                .await?
+        {
+            anyhow::bail!("only the team admins can set the default dashboard of the team");
+        }
+
+        // the saved searches must be visible to the user
+        let filter_ids: Vec<u64> = self
+            .query_filter(user_info, None)
+            .await?
+            .into_iter()
+            .map(|v| v.id)
+            .collect();
+        if let Some(id) = widgets
+            .iter()
+            .filter_map(|v| v.filter_id())
+            .find(|v| !filter_ids.contains(v))
+        {
+            anyhow::bail!("invalid saved filter {id}");
+        }
+
+        let created_user = match id {
+            Some(id) => {
+                let record = Dashboard::find_by_id(id)
+                    .one(&self.ctx.db)
+                    .await?
+                    .ok_or(anyhow!("invalid dashboard id"))?;
+                if !self.can_write_dashboard(user_info, &record).await? {
+                    anyhow::bail!("no permission to change the dashboard");
+                }
+                record.created_user
+            }
+            None => user_info.username.clone(),
+        };
+
+        if is_default {
+            // only one home view of each team or user
+            Dashboard::update_many()
+                .col_expr(dashboard::Column::IsDefault, Expr::value(false))
+                .filter(dashboard::Column::TeamId.eq(team_id))
+                .apply_if((team_id == 0).then_some(&created_user), |q, v| {
+                    q.filter(dashboard::Column::CreatedUser.eq(v))
+                })
+                .apply_if(id, |q, v| q.filter(dashboard::Column::Id.ne(v)))
+                .exec(&self.ctx.db)
+                .await?;
+        }
+
+        let active_model = dashboard::ActiveModel {
+            id: id.map_or(NotSet, |v| Set(v)),
+            name: Set(name),
+            widgets: Set(Some(serde_json::to_value(widgets)?)),
+            team_id: Set(team_id),
+            is_default: Set(is_default),
+            created_user: id.map_or(Set(created_user), |_| NotSet),
+            updated_user: Set(user_info.username.clone()),
+            ..Default::default()
+        }
+        .save(&self.ctx.db)
+        .await?;
+        Ok(active_model.id.as_ref().to_owned())
+    }
+
+    /// the dashboards of the user and the ones shared with the teams of the user
+    pub async fn query_dashboard(&self, user_info: &UserInfo) -> Result<Vec<dashboard::Model>> {
+        let team_ids: Vec<u64> = self
+            .ctx
+            .service()
+            .team
+            .get_member_teams(&user_info.user_id)
+            .await?
+            .into_iter()
+            .map(|v| v.team_id)
+            .collect();
+
+        Ok(Dashboard::find()
+            .filter(
+                Condition::any()
+                    .add(
+                        Condition::all()
+                            .add(dashboard::Column::CreatedUser.eq(&user_info.username))
+                            .add(dashboard::Column::TeamId.eq(0)),
+                    )
+                    .add(dashboard::Column::TeamId.is_in(team_ids)),
+            )
+            .order_by_asc(dashboard::Column::Name)
+            .all(&self.ctx.db)
+            .await?)
+    }
+
+    /// the home view of the user, the private default one is preferred over the one of the team
+    pub async fn get_home_dashboard(
+        &self,
+        user_info: &UserInfo,
+        team_id: Option<u64>,
+    ) -> Result<Option<dashboard::Model>> {
+        let private = Dashboard::find()
+            .filter(dashboard::Column::CreatedUser.eq(&user_info.username))
+            .filter(dashboard::Column::TeamId.eq(0))
+            .filter(dashboard::Column::IsDefault.eq(true))
+            .one(&self.ctx.db)
+            .await?;
+        if private.is_some() {
+            return Ok(private);
+        }
+
+        let Some(team_id) = team_id.filter(|v| *v != 0) else {
+            return Ok(None);
+        };
+        if !self
+            .ctx
+            .service()
+            .team
+            .can_read_team(Some(team_id), user_info.user_id.clone())
+            .await?
+        {
+            return Ok(None);
+        }
+        Ok(Dashboard::find()
+            .filter(dashboard::Column::TeamId.eq(team_id))
+            .filter(dashboard::Column::IsDefault.eq(true))
+            .one(&self.ctx.db)
+            .await?)
+    }
+
+    pub async fn delete_dashboard(&self, user_info: &UserInfo, id: u64) -> Result<u64> {
+        let Some(record) = Dashboard::find_by_id(id).one(&self.ctx.db).await? else {
+            return Ok(0);
+        };
+        if !self.can_write_dashboard(user_info, &record).await? {
+            anyhow::bail!("no permission to delete the dashboard");
+        }
+        Ok(Dashboard::delete_by_id(id)
+            .exec(&self.ctx.db)
+            .await?
+            .rows_affected)
+    }
+}
+
+#[test]
+fn test_dashboard_widget_check() {
+    use serde_json::json;
+
+    let widget = |widget_type: &str, params: Value| DashboardWidget {
+        widget_type: widget_type.to_string(),
+        params: Some(params),
+        ..Default::default()
+    };
+    assert!(
+        widget(WIDGET_JOB_STATS, json!({"job_type": "default"}))
+            .check()
+            .is_ok()
+    );
+    assert!(
+        widget(WIDGET_SAVED_SEARCH, json!({"filter_id": 1}))
+            .check()
+            .is_ok()
+    );
+    assert!(widget(WIDGET_SAVED_SEARCH, json!({})).check().is_err());
+    assert!(
+        widget(WIDGET_RECENT_FAILURES, json!({"limit": 101}))
+            .check()
+            .is_err()
+    );
+    assert!(
+        widget(WIDGET_RECENT_FAILURES, json!({"days": 0}))
+            .check()
+            .is_err()
+    );
+    assert!(widget("chart", json!({})).check().is_err());
+}
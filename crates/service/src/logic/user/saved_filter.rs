@@ -0,0 +1,146 @@
+use anyhow::{Result, anyhow};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::NotSet, ColumnTrait, Condition, EntityTrait, QueryFilter,
+    QueryOrder, QueryTrait, Select, Set,
+};
+
+use super::UserLogic;
+use crate::{
+    entity::{prelude::*, saved_filter},
+    logic::types::UserInfo,
+};
+
+/// the filters created by the user or shared with the teams
+fn visible_filters(
+    username: &str,
+    team_ids: Vec<u64>,
+    resource: Option<String>,
+) -> Select<SavedFilter> {
+    SavedFilter::find()
+        .filter(
+            Condition::any()
+                .add(saved_filter::Column::CreatedUser.eq(username))
+                .add(saved_filter::Column::TeamId.is_in(team_ids)),
+        )
+        .apply_if(resource, |query, v| {
+            query.filter(saved_filter::Column::Resource.eq(v))
+        })
+        .order_by_asc(saved_filter::Column::Name)
+}
+
+impl<'a> UserLogic<'a> {
+    /// the filter can be changed by its creator, or the admins of the team it is shared with
+    async fn can_write_filter(
+        &self,
+        user_info: &UserInfo,
+        record: &saved_filter::Model,
+    ) -> Result<bool> {
+        if record.created_user == user_info.username {
+            return Ok(true);
+        }
+        if record.team_id == 0 {
+            return Ok(false);
+        }
+        self.ctx
+            .service()
+            .team
+            .can_write_team(Some(record.team_id), user_info.user_id.clone())
+            .await
+    }
+
+    /// create or update the filter, it is shared with the members of the team if team_id is not 0
+    pub async fn save_filter(
+        &self,
+        user_info: &UserInfo,
+        id: Option<u64>,
+        name: String,
+        resource: String,
+        params: Option<serde_json::Value>,
+        team_id: u64,
+    ) -> Result<u64> {
+        if team_id != 0
+            && !self
+                .ctx
+                .service()
+                .team
+                .can_read_team(Some(team_id), user_info.user_id.clone())
+                .await?
+        {
+            anyhow::bail!("cannot share the filter with a team you are not a member of");
+        }
+        if let Some(id) = id {
+            let record = SavedFilter::find_by_id(id)
+                .one(&self.ctx.db)
+                .await?
+                .ok_or(anyhow!("invalid filter id"))?;
+            if !self.can_write_filter(user_info, &record).await? {
+                anyhow::bail!("no permission to change the filter");
+            }
+        }
+
+        let active_model = saved_filter::ActiveModel {
+            id: id.map_or(NotSet, |v| Set(v)),
+            name: Set(name),
+            resource: Set(resource),
+            params: Set(params),
+            team_id: Set(team_id),
+            created_user: id.map_or(Set(user_info.username.clone()), |_| NotSet),
+            updated_user: Set(user_info.username.clone()),
+            ..Default::default()
+        }
+        .save(&self.ctx.db)
+        .await?;
+        Ok(active_model.id.as_ref().to_owned())
+    }
+
+    /// the filters of the user and the ones shared with the teams of the user
+    pub async fn query_filter(
+        &self,
+        user_info: &UserInfo,
+        resource: Option<String>,
+    ) -> Result<Vec<saved_filter::Model>> {
+        let team_ids: Vec<u64> = self
+            .ctx
+            .service()
+            .team
+            .get_member_teams(&user_info.user_id)
+            .await?
+            .into_iter()
+            .map(|v| v.team_id)
+            .collect();
+
+        Ok(visible_filters(&user_info.username, team_ids, resource)
+            .all(&self.ctx.db)
+            .await?)
+    }
+
+    pub async fn delete_filter(&self, user_info: &UserInfo, id: u64) -> Result<u64> {
+        let Some(record) = SavedFilter::find_by_id(id).one(&self.ctx.db).await? else {
+            return Ok(0);
+        };
+        if !self.can_write_filter(user_info, &record).await? {
+            anyhow::bail!("no permission to delete the filter");
+        }
+        Ok(SavedFilter::delete_by_id(id)
+            .exec(&self.ctx.db)
+            .await?
+            .rows_affected)
+    }
+}
+
+#[test]
+fn test_visible_filters() {
+    let sql = visible_filters("alice", vec![1, 2], Some("exec-list".to_string()))
+        .build(sea_orm::DbBackend::MySql)
+        .to_string();
+    assert!(
+        sql.contains(
+            "(`saved_filter`.`created_user` = 'alice' OR `saved_filter`.`team_id` IN (1, 2))"
+        ),
+        "{sql}"
+    );
+    assert!(
+        sql.contains("`saved_filter`.`resource` = 'exec-list'"),
+        "{sql}"
+    );
+}
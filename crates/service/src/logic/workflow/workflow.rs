@@ -2,17 +2,14 @@ use core::matches;
 use std::pin::Pin;
 
 use crate::logic::executor::ExecutorLogic;
-use crate::logic::job::JobLogic;
 use crate::logic::job::types::{DispatchData, DispatchResult, DispatchTarget};
+use crate::logic::job::{JobLogic, exclude_maintenance};
 use crate::logic::types::{ResourceType, UserInfo};
 use crate::logic::workflow::types::{
     self, CustomJob, NodeStatus, NodeType, ProcessStatus, StandardJob, Task, TaskType,
     UserVariables, WorkflowNode, WorkflowNodeActualArgs, WorkflowProcessArgs,
 };
-use crate::{
-    entity::{prelude::*, team_member},
-    state::AppContext,
-};
+use crate::{IdGenerator, entity::prelude::*, state::AppContext};
 use anyhow::{Result, anyhow};
 use automate::bridge::msg::UpdateJobParams;
 use automate::scheduler::types::{RunStatus, UploadFile};
@@ -32,9 +29,7 @@ use sea_orm::{
 };
 use sea_query::{Expr, Query};
 use serde_json::json;
-use tokio::fs;
 use tracing::{error, info, warn};
-use utils::file_name;
 
 use super::types::{EdgeConfig, NodeConfig};
 
@@ -268,15 +263,15 @@ impl<'a> WorkflowLogic<'a> {
             return Ok(true);
         }
 
-        let is_team_user = if team_id.is_some() {
-            TeamMember::find()
-                .apply_if(team_id, |q, v| q.filter(team_member::Column::TeamId.eq(v)))
-                .filter(team_member::Column::UserId.eq(&user_info.user_id))
-                .one(&self.ctx.db)
+        let is_team_user = match team_id {
+            Some(team_id) => self
+                .ctx
+                .service()
+                .team
+                .is_team_member(team_id, &user_info.user_id)
                 .await?
-                .map(|_| true)
-        } else {
-            None
+                .then_some(true),
+            None => None,
         };
 
         let Some(workflow_id) = workflow_id else {
@@ -298,15 +293,12 @@ impl<'a> WorkflowLogic<'a> {
         if is_team_user.is_some() {
             return Ok(Some(workflow_record.team_id) == team_id);
         }
-        return Ok(TeamMember::find()
-            .apply_if(Some(workflow_record.team_id), |q, v| {
-                q.filter(team_member::Column::TeamId.eq(v))
-            })
-            .filter(team_member::Column::UserId.eq(&user_info.user_id))
-            .one(&self.ctx.db)
-            .await?
-            .map(|_| true)
-            == Some(true));
+        return self
+            .ctx
+            .service()
+            .team
+            .is_team_member(workflow_record.team_id, &user_info.user_id)
+            .await;
     }
 
     pub fn check_nodes(
@@ -734,20 +726,14 @@ impl<'a> WorkflowLogic<'a> {
                 custom_job.executor_id.clone()
             ))?;
 
-        let upload_file: Option<UploadFile> = if let Some(uploadfile) =
-            custom_job.upload_file.clone()
-            && uploadfile != ""
-        {
-            let data = fs::read(uploadfile.clone()).await?;
-            Some(UploadFile {
-                filename: file_name!(uploadfile),
-                data: Some(data),
-            })
-        } else {
-            None
+        let upload_path = custom_job.upload_file.clone().filter(|v| !v.is_empty());
+        let upload_file: Option<UploadFile> = match upload_path {
+            Some(ref path) => Some(JobLogic::get_upload_file(path).await?),
+            None => None,
         };
 
         let (cmd_name, cmd_args) = ExecutorLogic::get_cmd_args(&executor_record);
+        let container = ExecutorLogic::get_container_option(&executor_record)?;
         let eid = node.current_node.id.clone();
         let schedule_id = node.process_id.clone();
 
@@ -768,6 +754,8 @@ impl<'a> WorkflowLogic<'a> {
         if endpoints.len() == 0 {
             anyhow::bail!("cannot found valid instance");
         }
+        let endpoints = exclude_maintenance(endpoints)?;
+        let target_cmd_args = ExecutorLogic::get_targets_cmd_args(&executor_record, &endpoints);
 
         WorkflowProcessNode::update_many()
             .set(workflow_process_node::ActiveModel {
@@ -792,6 +780,7 @@ impl<'a> WorkflowLogic<'a> {
                 max_parallel: Some(1),
                 read_code_from_stdin: false,
                 is_workflow: true,
+                container,
                 ..Default::default()
             },
             run_id: node.run_id.clone(),
@@ -805,11 +794,16 @@ impl<'a> WorkflowLogic<'a> {
             timer_expr: None,
             is_sync: false,
             action: automate::JobAction::Exec,
+            blackout_windows: vec![],
+            request_id: utils::current_request_id(),
+            dispatch_id: IdGenerator::get_dispatch_uid(),
         };
 
         let mut dispatch_data = DispatchData {
             target: Vec::new(),
             params: dispatch_params.clone(),
+            dispatch_time: Some(Local::now()),
+            target_jobs: Default::default(),
         };
 
         endpoints.into_iter().for_each(|v| {
@@ -827,11 +821,32 @@ impl<'a> WorkflowLogic<'a> {
 
         let batch_push_ret = utils::async_batch_do(dispatch_data.target.clone(), move |v| {
             let mut dispatch_params = dispatch_params.clone();
+            let cmd_args = target_cmd_args.get(&v.instance_id).cloned();
             let logic = logic.clone();
             let http_client = http_client.clone();
             let secret = secret.clone();
+            let upload_path = upload_path.clone();
             dispatch_params.instance_id = Some(v.instance_id.clone());
             Box::pin(async move {
+                match cmd_args {
+                    Some(Ok((cmd_name, args))) => {
+                        dispatch_params.base_job.cmd_name = cmd_name;
+                        dispatch_params.base_job.args = args;
+                    }
+                    Some(Err(e)) => {
+                        return Ok(DispatchResult {
+                            namespace: v.namespace.clone(),
+                            instance_id: v.instance_id.clone(),
+                            bind_ip: v.ip.clone(),
+                            response: json!(null),
+                            has_err: true,
+                            err: Some(e),
+                            comet_addr: String::new(),
+                            canary: None,
+                        });
+                    }
+                    None => {}
+                }
                 let body = automate::DispatchJobRequest {
                     agent_ip: v.ip.clone(),
                     mac_addr: v.mac_addr.clone(),
@@ -847,14 +862,28 @@ impl<'a> WorkflowLogic<'a> {
                             response: json!(null),
                             has_err: true,
                             err: Some(e.to_string()),
+                            comet_addr: String::new(),
+                            canary: None,
                         });
                     }
                 };
-                let api_url = format!(
-                    "http://{}/dispatch?secret={}",
-                    pair.1.comet_addr,
-                    secret.clone()
-                );
+                if let (Some(path), Some(file)) =
+                    (upload_path, dispatch_params.base_job.upload_file.as_ref())
+                    && let Err(e) =
+                        JobLogic::push_artifact(&http_client, &pair.1, &path, file).await
+                {
+                    return Ok(DispatchResult {
+                        namespace: v.namespace.clone(),
+                        instance_id: v.instance_id.clone(),
+                        bind_ip: v.ip.clone(),
+                        response: json!(null),
+                        has_err: true,
+                        err: Some(e.to_string()),
+                        comet_addr: pair.1.comet_addr.clone(),
+                        canary: None,
+                    });
+                }
+                let api_url = format!("{}?secret={}", pair.1.http_url("/dispatch"), secret);
                 let response = match http_client.post(api_url).json(&body).send().await {
                     Ok(v) => v,
                     Err(e) => {
@@ -865,6 +894,8 @@ impl<'a> WorkflowLogic<'a> {
                             response: json!(null),
                             has_err: true,
                             err: Some(e.to_string()),
+                            comet_addr: pair.1.comet_addr.clone(),
+                            canary: None,
                         });
                     }
                 };
@@ -879,6 +910,8 @@ impl<'a> WorkflowLogic<'a> {
                             response: json!(null),
                             has_err: true,
                             err: Some(e.to_string()),
+                            comet_addr: pair.1.comet_addr.clone(),
+                            canary: None,
                         });
                     }
                 };
@@ -893,6 +926,8 @@ impl<'a> WorkflowLogic<'a> {
                             instance_id: v.instance_id.clone(),
                             has_err: true,
                             err: Some(e.to_string()),
+                            comet_addr: pair.1.comet_addr.clone(),
+                            canary: None,
                         });
                     }
                 };
@@ -910,6 +945,8 @@ impl<'a> WorkflowLogic<'a> {
                     instance_id: v.instance_id.clone(),
                     has_err,
                     err,
+                    comet_addr: pair.1.comet_addr.clone(),
+                    canary: None,
                 })
             })
         })
@@ -958,17 +995,14 @@ impl<'a> WorkflowLogic<'a> {
                 job_record.executor_id.clone()
             ))?;
 
-        let mut upload_file: Option<UploadFile> = None;
-
-        if job_record.upload_file != "" {
-            let data = fs::read(job_record.upload_file.clone()).await?;
-            upload_file = Some(UploadFile {
-                filename: file_name!(job_record.upload_file.clone()),
-                data: Some(data),
-            });
-        }
+        let upload_path = Some(job_record.upload_file.clone()).filter(|v| !v.is_empty());
+        let upload_file: Option<UploadFile> = match upload_path {
+            Some(ref path) => Some(JobLogic::get_upload_file(path).await?),
+            None => None,
+        };
 
         let (cmd_name, cmd_args) = ExecutorLogic::get_cmd_args(&executor_record);
+        let container = ExecutorLogic::get_container_option(&executor_record)?;
         let eid = node.current_node.id.clone();
         let schedule_id = node.process_id.clone();
 
@@ -989,6 +1023,8 @@ impl<'a> WorkflowLogic<'a> {
         if endpoints.len() == 0 {
             anyhow::bail!("cannot found valid instance");
         }
+        let endpoints = exclude_maintenance(endpoints)?;
+        let target_cmd_args = ExecutorLogic::get_targets_cmd_args(&executor_record, &endpoints);
 
         WorkflowProcessNode::update_many()
             .set(workflow_process_node::ActiveModel {
@@ -1015,6 +1051,7 @@ impl<'a> WorkflowLogic<'a> {
                 max_parallel: Some(job_record.max_parallel.into()),
                 read_code_from_stdin: false,
                 is_workflow: true,
+                container,
                 ..Default::default()
             },
             run_id: node.run_id.clone(),
@@ -1028,11 +1065,16 @@ impl<'a> WorkflowLogic<'a> {
             timer_expr: None,
             is_sync: false,
             action: automate::JobAction::Exec,
+            blackout_windows: vec![],
+            request_id: utils::current_request_id(),
+            dispatch_id: IdGenerator::get_dispatch_uid(),
         };
 
         let mut dispatch_data = DispatchData {
             target: Vec::new(),
             params: dispatch_params.clone(),
+            dispatch_time: Some(Local::now()),
+            target_jobs: Default::default(),
         };
 
         endpoints.into_iter().for_each(|v| {
@@ -1050,11 +1092,32 @@ impl<'a> WorkflowLogic<'a> {
 
         let batch_push_ret = utils::async_batch_do(dispatch_data.target.clone(), move |v| {
             let mut dispatch_params = dispatch_params.clone();
+            let cmd_args = target_cmd_args.get(&v.instance_id).cloned();
             let logic = logic.clone();
             let http_client = http_client.clone();
             let secret = secret.clone();
+            let upload_path = upload_path.clone();
             dispatch_params.instance_id = Some(v.instance_id.clone());
             Box::pin(async move {
+                match cmd_args {
+                    Some(Ok((cmd_name, args))) => {
+                        dispatch_params.base_job.cmd_name = cmd_name;
+                        dispatch_params.base_job.args = args;
+                    }
+                    Some(Err(e)) => {
+                        return Ok(DispatchResult {
+                            namespace: v.namespace.clone(),
+                            instance_id: v.instance_id.clone(),
+                            bind_ip: v.ip.clone(),
+                            response: json!(null),
+                            has_err: true,
+                            err: Some(e),
+                            comet_addr: String::new(),
+                            canary: None,
+                        });
+                    }
+                    None => {}
+                }
                 let body = automate::DispatchJobRequest {
                     agent_ip: v.ip.clone(),
                     mac_addr: v.mac_addr.clone(),
@@ -1070,14 +1133,28 @@ impl<'a> WorkflowLogic<'a> {
                             response: json!(null),
                             has_err: true,
                             err: Some(e.to_string()),
+                            comet_addr: String::new(),
+                            canary: None,
                         });
                     }
                 };
-                let api_url = format!(
-                    "http://{}/dispatch?secret={}",
-                    pair.1.comet_addr,
-                    secret.clone()
-                );
+                if let (Some(path), Some(file)) =
+                    (upload_path, dispatch_params.base_job.upload_file.as_ref())
+                    && let Err(e) =
+                        JobLogic::push_artifact(&http_client, &pair.1, &path, file).await
+                {
+                    return Ok(DispatchResult {
+                        namespace: v.namespace.clone(),
+                        instance_id: v.instance_id.clone(),
+                        bind_ip: v.ip.clone(),
+                        response: json!(null),
+                        has_err: true,
+                        err: Some(e.to_string()),
+                        comet_addr: pair.1.comet_addr.clone(),
+                        canary: None,
+                    });
+                }
+                let api_url = format!("{}?secret={}", pair.1.http_url("/dispatch"), secret);
                 let response = match http_client.post(api_url).json(&body).send().await {
                     Ok(v) => v,
                     Err(e) => {
@@ -1088,6 +1165,8 @@ impl<'a> WorkflowLogic<'a> {
                             response: json!(null),
                             has_err: true,
                             err: Some(e.to_string()),
+                            comet_addr: pair.1.comet_addr.clone(),
+                            canary: None,
                         });
                     }
                 };
@@ -1102,6 +1181,8 @@ impl<'a> WorkflowLogic<'a> {
                             response: json!(null),
                             has_err: true,
                             err: Some(e.to_string()),
+                            comet_addr: pair.1.comet_addr.clone(),
+                            canary: None,
                         });
                     }
                 };
@@ -1116,6 +1197,8 @@ impl<'a> WorkflowLogic<'a> {
                             instance_id: v.instance_id.clone(),
                             has_err: true,
                             err: Some(e.to_string()),
+                            comet_addr: pair.1.comet_addr.clone(),
+                            canary: None,
                         });
                     }
                 };
@@ -1133,6 +1216,8 @@ impl<'a> WorkflowLogic<'a> {
                     instance_id: v.instance_id.clone(),
                     has_err,
                     err,
+                    comet_addr: pair.1.comet_addr.clone(),
+                    canary: None,
                 })
             })
         })
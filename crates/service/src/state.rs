@@ -1,12 +1,27 @@
-use crate::config::Conf;
+use crate::cache::Cache;
+use crate::config::{Conf, ReloadReport};
+use crate::event::{EventBus, live::LiveEvents};
+use crate::logic::agent_config::AgentConfigLogic;
+use crate::logic::backup::BackupLogic;
+use crate::logic::blackout::BlackoutLogic;
+use crate::logic::comet_group::CometGroupLogic;
+use crate::logic::credential::CredentialLogic;
+use crate::logic::report::ReportLogic;
 use crate::logic::role;
+use crate::logic::security::SecurityLogic;
 use crate::logic::ssh::SshLogic;
-use crate::logic::tag::TagLogic;
+use crate::logic::tag::{self, TagLogic};
+use crate::logic::task::TaskLogic;
 use crate::logic::team::TeamLogic;
 use crate::logic::types::Permission;
 use crate::logic::{
-    executor::ExecutorLogic, instance::InstanceLogic, job::JobLogic, migration::MigrationLogic,
-    role::RoleLogic, user::UserLogic, workflow::WorkflowLogic,
+    executor::ExecutorLogic,
+    instance::InstanceLogic,
+    job::{JobLogic, StatusWriter},
+    migration::MigrationLogic,
+    role::RoleLogic,
+    user::UserLogic,
+    workflow::WorkflowLogic,
 };
 
 use anyhow::{Ok, Result};
@@ -34,6 +49,14 @@ pub struct Service<'a> {
     pub team: TeamLogic<'a>,
     pub tag: TagLogic<'a>,
     pub workflow: WorkflowLogic<'a>,
+    pub blackout: BlackoutLogic<'a>,
+    pub security: SecurityLogic<'a>,
+    pub task: TaskLogic<'a>,
+    pub comet_group: CometGroupLogic<'a>,
+    pub backup: BackupLogic<'a>,
+    pub credential: CredentialLogic<'a>,
+    pub agent_config: AgentConfigLogic<'a>,
+    pub report: ReportLogic<'a>,
 }
 
 #[derive(Clone)]
@@ -69,6 +92,7 @@ pub struct AppContextBuilder {
     http_client: Option<reqwest::Client>,
     enforcer: Option<Arc<RwLock<Enforcer>>>,
     rate_limiter: Option<RateLimiter>,
+    status_writer: Option<StatusWriter>,
 }
 
 impl AppContextBuilder {
@@ -95,6 +119,12 @@ impl AppContextBuilder {
         self
     }
 
+    /// buffer the job status updates, they are written immediately if unset
+    pub fn status_writer(mut self, writer: StatusWriter) -> Self {
+        self.status_writer = Some(writer);
+        self
+    }
+
     pub fn enforcer(mut self, mut enforcer: Enforcer) -> Self {
         enforcer.enable_auto_build_role_links(true);
         self.enforcer = Some(Arc::new(RwLock::new(enforcer)));
@@ -102,14 +132,27 @@ impl AppContextBuilder {
     }
 
     pub fn build(self) -> Result<AppContext> {
+        let conf = self.conf.ok_or(anyhow::anyhow!("config is required"))?;
         Ok(AppContext {
             db: self
                 .db
                 .ok_or(anyhow::anyhow!("database connection is required"))?,
+            cache: Cache::new(
+                self.redis
+                    .clone()
+                    .ok_or(anyhow::anyhow!("redis client is required"))?,
+            ),
+            live: LiveEvents::new(
+                self.redis
+                    .clone()
+                    .ok_or(anyhow::anyhow!("redis client is required"))?,
+            ),
+            bus: EventBus::with_default_subscribers(),
             redis: self
                 .redis
                 .ok_or(anyhow::anyhow!("redis client is required"))?,
-            conf: self.conf.ok_or(anyhow::anyhow!("config is required"))?,
+            applied_conf: Arc::new(RwLock::new(conf.clone())),
+            conf,
             http_client: self
                 .http_client
                 .ok_or(anyhow::anyhow!("http client is required"))?,
@@ -120,6 +163,7 @@ impl AppContextBuilder {
                 self.rate_limiter
                     .ok_or(anyhow::anyhow!("rate limiter is required"))?,
             )),
+            status_writer: self.status_writer,
         })
     }
 }
@@ -137,6 +181,10 @@ impl RateLimiter {
         }
     }
 
+    pub fn set_interval(&mut self, interval_secs: u64) {
+        self.interval = Duration::from_secs(interval_secs);
+    }
+
     pub fn can_execute(&mut self) -> bool {
         let now = Instant::now();
         if now.duration_since(self.last_executed) >= self.interval {
@@ -153,9 +201,15 @@ pub struct AppContext {
     pub db: DatabaseConnection,
     redis: Client,
     pub conf: Conf,
+    /// config applied by the latest reload
+    applied_conf: Arc<RwLock<Conf>>,
     rate_limiter: Arc<RwLock<RateLimiter>>,
     pub http_client: reqwest::Client,
     pub enforcer: Arc<RwLock<Enforcer>>,
+    pub status_writer: Option<StatusWriter>,
+    pub cache: Cache,
+    pub live: LiveEvents,
+    pub bus: EventBus,
 }
 
 impl AppContext {
@@ -167,6 +221,7 @@ impl AppContext {
             conf: None,
             http_client: None,
             rate_limiter: None,
+            status_writer: None,
         }
     }
 
@@ -182,6 +237,14 @@ impl AppContext {
             team: TeamLogic::new(self),
             tag: TagLogic::new(self),
             workflow: WorkflowLogic::new(self),
+            blackout: BlackoutLogic::new(self),
+            security: SecurityLogic::new(self),
+            task: TaskLogic::new(self),
+            comet_group: CometGroupLogic::new(self),
+            backup: BackupLogic::new(self),
+            credential: CredentialLogic::new(self),
+            agent_config: AgentConfigLogic::new(self),
+            report: ReportLogic::new(self),
         }
     }
 
@@ -189,6 +252,35 @@ impl AppContext {
         self.redis.clone()
     }
 
+    /// apply the reloadable fields of new config, the other changed fields are
+    /// reported and keep the current value until restart
+    pub async fn reload_conf(&self, new: Conf) -> Result<ReloadReport> {
+        let mut applied = self.applied_conf.write().await;
+        let mut report = applied.diff(&new)?;
+
+        if report.applied.iter().any(|v| v == "log_level") {
+            match new.log_level {
+                Some(ref level) => {
+                    utils::set_log_level(level)?;
+                    applied.log_level = new.log_level.clone();
+                }
+                // the command-line level is restored after restart
+                None => {
+                    report.applied.retain(|v| v != "log_level");
+                    report.restart_required.push("log_level".to_string());
+                }
+            }
+        }
+        if report.applied.iter().any(|v| v == "rate_limit_secs") {
+            self.rate_limiter
+                .write()
+                .await
+                .set_interval(new.rate_limit_secs());
+            applied.rate_limit_secs = new.rate_limit_secs;
+        }
+        Ok(report)
+    }
+
     pub async fn can_execute(&mut self) -> bool {
         let mut limiter = self.rate_limiter.write().await;
         limiter.can_execute()
@@ -213,6 +305,7 @@ impl AppContext {
         let acts = e
             .get_implicit_permissions_for_user(user, None)
             .into_iter()
+            .filter(|v| !role::is_tag_policy(v))
             .map(|v| v[1..].join("_").to_string())
             .collect::<Vec<String>>();
 
@@ -224,12 +317,67 @@ impl AppContext {
         let acts = e
             .get_filtered_policy(0, vec![role_id.to_string()])
             .into_iter()
+            .filter(|v| !role::is_tag_policy(v))
             .map(|v| v[1..].join("_").to_string())
             .collect::<Vec<String>>();
 
         Ok(acts)
     }
 
+    /// paths of the tags granted to the user, empty if the user is not limited by tags
+    pub async fn get_tag_policies_for_user(&self, user: &str) -> Result<Vec<String>> {
+        let mut e = self.enforcer.write().await;
+        let tags = e
+            .get_implicit_permissions_for_user(user, None)
+            .into_iter()
+            .filter_map(|v| role::policy_tag(&v).map(|v| v.to_string()))
+            .collect::<Vec<String>>();
+
+        Ok(tags)
+    }
+
+    pub async fn get_tag_policies_for_role(&self, role_id: u64) -> Result<Vec<String>> {
+        let e = self.enforcer.read().await;
+        let tags = e
+            .get_filtered_policy(0, vec![role_id.to_string()])
+            .into_iter()
+            .filter_map(|v| role::policy_tag(&v).map(|v| v.to_string()))
+            .collect::<Vec<String>>();
+
+        Ok(tags)
+    }
+
+    /// remove the policies of the role, either the tag policies or the others
+    async fn remove_role_policies(&self, role_id: u64, tag_policies: bool) -> Result<()> {
+        let mut e = self.enforcer.write().await;
+        let policies = e
+            .get_filtered_policy(0, vec![role_id.to_string()])
+            .into_iter()
+            .filter(|v| role::is_tag_policy(v) == tag_policies)
+            .collect::<Vec<_>>();
+        if !policies.is_empty() {
+            e.remove_policies(policies).await?;
+        }
+        Ok(())
+    }
+
+    /// limit the role to the resources carrying the tags or the tags under them,
+    /// an empty list removes the limit
+    pub async fn set_tag_policies(&self, role_id: u64, tags: Vec<String>) -> Result<()> {
+        self.remove_role_policies(role_id, true).await?;
+        for tag in tags {
+            let tag = tag::normalize_tag_name(&tag)?;
+            self.set_policy(
+                role_id.to_string().as_str(),
+                &format!("{}{tag}", role::TAG_OBJECT_PREFIX),
+                role::TAG_ACTION_ACCESS,
+            )
+            .await?;
+        }
+        self.load_policy().await?;
+        Ok(())
+    }
+
     pub async fn enforce<T: EnforceArgs>(&self, val: T) -> Result<bool> {
         let e = self.enforcer.read().await;
         Ok(e.enforce(val)?)
@@ -278,6 +426,8 @@ impl AppContext {
         self.set_permission_manage_instance("1").await?;
         self.set_permission_manage_user("1").await?;
         self.set_permission_manage_job("1").await?;
+        self.set_permission_dispatch_job("1").await?;
+        self.set_permission_ssh_instance("1").await?;
         self.load_policy().await?;
         Ok(())
     }
@@ -314,10 +464,13 @@ impl AppContext {
     }
 
     pub async fn set_permissions(&self, role_id: u64, keys: Vec<String>) -> Result<()> {
-        self.delete_role(role_id).await?;
+        // the tag policies are kept, they are saved by set_tag_policies
+        self.remove_role_policies(role_id, false).await?;
         for key in keys {
-            let pair = key.splitn(2, "_").collect::<Vec<&str>>();
-            let (object, action) = (pair[0], pair[1]);
+            // both job_dispatch and job:dispatch are accepted
+            let Some((object, action)) = key.split_once(['_', ':']) else {
+                anyhow::bail!("invalid permission key")
+            };
 
             let got = role::PERMISSIONS
                 .iter()
@@ -347,6 +500,14 @@ impl AppContext {
     pub async fn set_permission_forbid_change(&self, role: &str) -> Result<()> {
         self.set_policy(role, "change", "forbid").await
     }
+
+    pub async fn set_permission_dispatch_job(&self, role: &str) -> Result<()> {
+        self.set_policy(role, "job", "dispatch").await
+    }
+
+    pub async fn set_permission_ssh_instance(&self, role: &str) -> Result<()> {
+        self.set_policy(role, "instance", "ssh").await
+    }
 }
 
 #[test]
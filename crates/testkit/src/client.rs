@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+use anyhow::{Result, anyhow, bail};
+use reqwest::header;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// client of the console api authenticated with a bearer token
+#[derive(Clone)]
+pub struct ApiClient {
+    http: reqwest::Client,
+    server: String,
+}
+
+impl ApiClient {
+    const API_PREFIX: &'static str = "/api/v1";
+
+    pub fn new(server: &str, token: &str) -> Result<Self> {
+        let mut headers = header::HeaderMap::new();
+        let mut auth_value = header::HeaderValue::from_str(&format!("Bearer {token}"))?;
+        auth_value.set_sensitive(true);
+        headers.insert(header::AUTHORIZATION, auth_value);
+
+        Ok(Self {
+            http: reqwest::Client::builder()
+                .default_headers(headers)
+                .timeout(Duration::from_secs(30))
+                .build()?,
+            server: server.trim_end_matches('/').to_string(),
+        })
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}{path}", self.server, Self::API_PREFIX)
+    }
+
+    pub async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<T> {
+        let resp = self.http.get(self.url(path)).query(query).send().await?;
+        Self::data(resp).await
+    }
+
+    pub async fn post<T: DeserializeOwned>(&self, path: &str, body: &Value) -> Result<T> {
+        let resp = self.http.post(self.url(path)).json(body).send().await?;
+        Self::data(resp).await
+    }
+
+    /// unwrap the data of the standard response, {"code":20000,"msg":"success","data":...}
+    async fn data<T: DeserializeOwned>(resp: reqwest::Response) -> Result<T> {
+        let status = resp.status();
+        let body: Value = resp
+            .json()
+            .await
+            .map_err(|e| anyhow!("invalid response, status {status} - {e}"))?;
+        match body["code"].as_i64() {
+            Some(20000) => Ok(serde_json::from_value(body["data"].clone())?),
+            _ => bail!("{}", body["msg"].as_str().unwrap_or("unknown error")),
+        }
+    }
+}
@@ -0,0 +1,368 @@
+//! end-to-end test fixtures, [`TestEnv`] boots the console, comet and an agent in the test
+//! process against ephemeral mysql and redis containers started by testcontainers.
+//!
+//! docker is required, the scenario tests are ignored by default:
+//! `cargo test -p testkit -- --ignored`
+
+mod client;
+
+use std::{
+    fs,
+    future::Future,
+    net::TcpListener,
+    path::PathBuf,
+    sync::Once,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, bail};
+use automate::{
+    comet::{self, CometOptions},
+    scheduler::{
+        Scheduler,
+        types::{AssignUserOption, SshConnectionOption},
+    },
+};
+use nanoid::nanoid;
+use openapi::{WebapiOptions, logic::user::UserLogic};
+use serde_json::{Value, json};
+use service::config::Conf;
+use testcontainers_modules::{
+    mysql::Mysql,
+    redis::Redis,
+    testcontainers::{ContainerAsync, runners::AsyncRunner},
+};
+use tokio::{
+    sync::{Mutex, MutexGuard, oneshot},
+    task::JoinHandle,
+    time::{sleep, timeout},
+};
+use tracing::error;
+
+pub use client::ApiClient;
+
+/// the agent keeps its ip, comet address and metrics in globals,
+/// so only one environment runs in a process at a time
+static ENV_LOCK: Mutex<()> = Mutex::const_new(());
+static INIT_TRACING: Once = Once::new();
+
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(120);
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+pub struct TestEnv {
+    pub client: ApiClient,
+    /// eg: http://127.0.0.1:38481
+    pub console_url: String,
+    /// eg: ws://127.0.0.1:38482
+    pub comet_url: String,
+    pub namespace: String,
+    /// instance id of the in-process agent
+    pub instance_id: String,
+    /// bash executor the jobs created by create_job run with
+    pub executor_id: u64,
+    work_dir: PathBuf,
+    tasks: Vec<JoinHandle<()>>,
+    _mysql: ContainerAsync<Mysql>,
+    _redis: ContainerAsync<Redis>,
+    _guard: MutexGuard<'static, ()>,
+}
+
+impl TestEnv {
+    /// start the containers and the services, then wait until the agent is online,
+    /// set TESTKIT_LOG to see the logs of the services, eg: TESTKIT_LOG=info
+    pub async fn start() -> Result<Self> {
+        let guard = ENV_LOCK.lock().await;
+        INIT_TRACING.call_once(|| {
+            let level = std::env::var("TESTKIT_LOG").unwrap_or_else(|_| "error".to_string());
+            if let Err(e) = utils::init_tracing(&level, false) {
+                eprintln!("failed initialize tracing - {e}");
+            }
+        });
+
+        let mysql = Mysql::default()
+            .start()
+            .await
+            .context("start mysql container")?;
+        let redis = Redis::default()
+            .start()
+            .await
+            .context("start redis container")?;
+        let database_url = format!(
+            "mysql://root@{}:{}/test",
+            mysql.get_host().await?,
+            mysql.get_host_port_ipv4(3306).await?
+        );
+        let redis_url = format!(
+            "redis://{}:{}",
+            redis.get_host().await?,
+            redis.get_host_port_ipv4(6379).await?
+        );
+
+        let work_dir = std::env::temp_dir().join(format!("jiascheduler-testkit-{}", nanoid!(10)));
+        fs::create_dir_all(&work_dir)?;
+        let config_file = work_dir.join("console.toml");
+        let console_addr = format!("127.0.0.1:{}", free_port()?);
+        let comet_port = free_port()?;
+        let namespace = "testkit".to_string();
+
+        fs::write(
+            &config_file,
+            format!(
+                r#"debug = false
+bind_addr = "{console_addr}"
+api_url = "http://{console_addr}"
+redis_url = "{redis_url}"
+comet_secret = "{}"
+database_url = "{database_url}"
+
+[encrypt]
+private_key = "{}"
+
+[admin]
+username = "admin"
+password = "{}"
+"#,
+                nanoid!(16),
+                nanoid!(16),
+                nanoid!(16),
+            ),
+        )?;
+
+        let mut tasks = Vec::new();
+        let (console_tx, console_rx) = oneshot::channel::<Conf>();
+        let opts = WebapiOptions {
+            database_url: None,
+            redis_url: None,
+            bind_addr: None,
+            config_file: config_file.to_string_lossy().to_string(),
+            break_glass: false,
+        };
+        tasks.push(tokio::spawn(async move {
+            if let Err(e) = openapi::run(opts, Some(console_tx)).await {
+                error!("console exited - {e:?}");
+            }
+        }));
+        let conf = timeout(STARTUP_TIMEOUT, console_rx)
+            .await
+            .context("console startup timed out")?
+            .context("console exited before startup")?;
+
+        let (comet_tx, comet_rx) = oneshot::channel::<()>();
+        let opts = CometOptions {
+            redis_url: conf.redis_url.clone(),
+            // comet registers its local ip and the port, so listen on all the interfaces
+            bind_addr: format!("0.0.0.0:{comet_port}"),
+            secret: conf.comet_secret.clone(),
+            tls: None,
+            bus_url: conf.bus_url.clone(),
+            group: None,
+            require_agent_credential: false,
+        };
+        tasks.push(tokio::spawn(async move {
+            if let Err(e) = comet::run(opts, Some(comet_tx)).await {
+                error!("comet exited - {e:?}");
+            }
+        }));
+        timeout(STARTUP_TIMEOUT, comet_rx)
+            .await
+            .context("comet startup timed out")?
+            .context("comet exited before startup")?;
+
+        let comet_url = format!("ws://127.0.0.1:{comet_port}");
+        let mut scheduler = Scheduler::new(
+            namespace.clone(),
+            vec![comet_url.clone()],
+            conf.comet_secret.clone(),
+            work_dir.join("log").to_string_lossy().to_string(),
+            SshConnectionOption::build(None, None, None),
+            AssignUserOption::build(None, None),
+        )
+        .with_agent_version(env!("CARGO_PKG_VERSION"));
+        tasks.push(tokio::spawn(async move {
+            if let Err(e) = scheduler.connect_comet().await {
+                error!("failed connect to comet - {e:?}");
+                return;
+            }
+            if let Err(e) = scheduler.run().await {
+                error!("agent exited - {e:?}");
+            }
+        }));
+
+        let db = service::db::connect(&conf.database_url, &conf.db_pool).await?;
+        let token = UserLogic::create_emergency_token(
+            &db,
+            &conf.admin.username,
+            "testkit".to_string(),
+            STARTUP_TIMEOUT.as_secs() * 30,
+        )
+        .await?;
+        let console_url = format!("http://{console_addr}");
+        let client = ApiClient::new(&console_url, &token)?;
+
+        let instance_id = wait_until(STARTUP_TIMEOUT, || async {
+            // the requests fail until the console listens
+            let ret: Value = client
+                .get("/instance/list", &[("status", "1".to_string())])
+                .await
+                .ok()?;
+            ret["list"].as_array()?.iter().find_map(|v| {
+                (v["namespace"] == namespace.as_str())
+                    .then(|| v["instance_id"].as_str().map(str::to_string))
+                    .flatten()
+            })
+        })
+        .await
+        .context("wait for the agent to be online")?;
+
+        let ret: Value = client
+            .post(
+                "/executor/save",
+                &json!({
+                    "name": "testkit-bash",
+                    "command": "bash -c",
+                    "platform": "linux",
+                    "info": "",
+                }),
+            )
+            .await
+            .context("create executor")?;
+        let executor_id = ret["result"].as_u64().context("invalid executor id")?;
+
+        Ok(Self {
+            client,
+            console_url,
+            comet_url,
+            namespace,
+            instance_id,
+            executor_id,
+            work_dir,
+            tasks,
+            _mysql: mysql,
+            _redis: redis,
+            _guard: guard,
+        })
+    }
+
+    /// save a bash job with the code and return its eid
+    pub async fn create_job(&self, name: &str, code: &str) -> Result<String> {
+        let ret: Value = self
+            .client
+            .post(
+                "/job/save",
+                &json!({
+                    "executor_id": self.executor_id,
+                    "name": name,
+                    "code": code,
+                    "args": [],
+                }),
+            )
+            .await
+            .with_context(|| format!("create job {name}"))?;
+        let id = ret["result"].as_u64().context("invalid job id")?;
+
+        let ret: Value = self
+            .client
+            .get("/job/list", &[("name", name.to_string())])
+            .await?;
+        ret["list"]
+            .as_array()
+            .and_then(|v| v.iter().find(|v| v["id"] == id))
+            .and_then(|v| v["eid"].as_str())
+            .map(str::to_string)
+            .with_context(|| format!("cannot found job {name}"))
+    }
+
+    /// dispatch the job to the agent, the fields of extra are merged into the request,
+    /// eg: json!({"timer_expr": {...}})
+    pub async fn dispatch(
+        &self,
+        eid: &str,
+        schedule_type: &str,
+        action: &str,
+        extra: Value,
+    ) -> Result<Value> {
+        let mut req = json!({
+            "schedule_name": format!("testkit-{schedule_type}"),
+            "schedule_type": schedule_type,
+            "endpoints": [{"instance_id": self.instance_id}],
+            "eid": eid,
+            "is_sync": false,
+            "action": action,
+        });
+        if let (Some(req), Value::Object(extra)) = (req.as_object_mut(), extra) {
+            req.extend(extra);
+        }
+        self.client
+            .post("/job/dispatch", &req)
+            .await
+            .with_context(|| format!("dispatch {action} of job {eid}"))
+    }
+
+    /// exec history of the job, the latest first
+    pub async fn exec_history(&self, eid: &str) -> Result<Vec<Value>> {
+        let ret: Value = self
+            .client
+            .get(
+                "/job/exec-list",
+                &[
+                    ("eid", eid.to_string()),
+                    ("job_type", "default".to_string()),
+                    ("page_size", "100".to_string()),
+                ],
+            )
+            .await?;
+        Ok(ret["list"].as_array().cloned().unwrap_or_default())
+    }
+
+    /// run status of the job on the agent, eg: running, stop
+    pub async fn run_status(&self, eid: &str, schedule_type: &str) -> Result<Option<String>> {
+        let ret: Value = self
+            .client
+            .get(
+                "/job/running-status-list",
+                &[
+                    ("schedule_type", schedule_type.to_string()),
+                    ("job_type", "default".to_string()),
+                    ("page_size", "100".to_string()),
+                ],
+            )
+            .await?;
+        Ok(ret["list"].as_array().and_then(|v| {
+            v.iter()
+                .find(|v| v["eid"] == eid && v["instance_id"] == self.instance_id.as_str())
+                .and_then(|v| v["run_status"].as_str())
+                .map(str::to_string)
+        }))
+    }
+}
+
+impl Drop for TestEnv {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+        let _ = fs::remove_dir_all(&self.work_dir);
+    }
+}
+
+/// poll f until it returns some value or the timeout elapses
+pub async fn wait_until<T, F, Fut>(timeout: Duration, mut f: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Option<T>>,
+{
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(v) = f().await {
+            return Ok(v);
+        }
+        if Instant::now() >= deadline {
+            bail!("timed out after {timeout:?}");
+        }
+        sleep(POLL_INTERVAL).await;
+    }
+}
+
+fn free_port() -> Result<u16> {
+    Ok(TcpListener::bind("127.0.0.1:0")?.local_addr()?.port())
+}
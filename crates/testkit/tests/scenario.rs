@@ -0,0 +1,110 @@
+use std::time::Duration;
+
+use serde_json::json;
+use testkit::{TestEnv, wait_until};
+
+const RUN_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[tokio::test]
+#[ignore = "requires docker"]
+async fn test_dispatch_once() {
+    let env = TestEnv::start().await.unwrap();
+    let eid = env
+        .create_job("testkit-once", "echo hello testkit")
+        .await
+        .unwrap();
+
+    env.dispatch(&eid, "once", "exec", json!({})).await.unwrap();
+
+    let record = wait_until(RUN_TIMEOUT, || async {
+        env.exec_history(&eid)
+            .await
+            .ok()?
+            .into_iter()
+            .find(|v| v["end_time"].is_string())
+    })
+    .await
+    .unwrap();
+    assert_eq!(record["exit_code"], 0);
+    assert!(
+        record["output"]
+            .as_str()
+            .unwrap_or_default()
+            .contains("hello testkit")
+    );
+}
+
+#[tokio::test]
+#[ignore = "requires docker"]
+async fn test_timer() {
+    let env = TestEnv::start().await.unwrap();
+    let eid = env.create_job("testkit-timer", "echo tick").await.unwrap();
+
+    env.dispatch(
+        &eid,
+        "timer",
+        "start_timer",
+        json!({
+            "timer_expr": {
+                "timezone": "UTC",
+                "second": "*/2",
+                "minute": "*",
+                "hour": "*",
+                "day_of_month": "*",
+                "month": "*",
+                "year": "*",
+            }
+        }),
+    )
+    .await
+    .unwrap();
+
+    wait_until(RUN_TIMEOUT, || async {
+        let list = env.exec_history(&eid).await.ok()?;
+        (list.iter().filter(|v| v["exit_code"] == 0).count() >= 2).then_some(())
+    })
+    .await
+    .unwrap();
+
+    env.dispatch(&eid, "timer", "stop_timer", json!({}))
+        .await
+        .unwrap();
+    wait_until(RUN_TIMEOUT, || async {
+        let status = env.run_status(&eid, "timer").await.ok()?;
+        (status.as_deref() != Some("running")).then_some(())
+    })
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+#[ignore = "requires docker"]
+async fn test_daemon() {
+    let env = TestEnv::start().await.unwrap();
+    let eid = env.create_job("testkit-daemon", "sleep 600").await.unwrap();
+
+    env.dispatch(
+        &eid,
+        "daemon",
+        "start_supervising",
+        json!({"restart_interval": 1}),
+    )
+    .await
+    .unwrap();
+    wait_until(RUN_TIMEOUT, || async {
+        let status = env.run_status(&eid, "daemon").await.ok()?;
+        (status.as_deref() == Some("running")).then_some(())
+    })
+    .await
+    .unwrap();
+
+    env.dispatch(&eid, "daemon", "stop_supervising", json!({}))
+        .await
+        .unwrap();
+    wait_until(RUN_TIMEOUT, || async {
+        let status = env.run_status(&eid, "daemon").await.ok()?;
+        (status.as_deref() != Some("running")).then_some(())
+    })
+    .await
+    .unwrap();
+}
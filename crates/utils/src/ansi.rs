@@ -0,0 +1,88 @@
+//! removal of the ansi escape sequences and the control chars of the job output,
+//! which corrupt the json viewers
+
+use std::borrow::Cow;
+
+const ESC: char = '\x1b';
+const BEL: char = '\x07';
+
+/// the output without the escape sequences and the control chars other than \n, \r and \t.
+/// the color sequences, eg: \x1b[31m, are kept if keep_color is true
+pub fn sanitize(output: &str, keep_color: bool) -> Cow<'_, str> {
+    if !output.chars().any(is_removed_char) && !output.contains(ESC) {
+        return Cow::Borrowed(output);
+    }
+
+    let mut ret = String::with_capacity(output.len());
+    let mut chars = output.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != ESC {
+            if !is_removed_char(c) {
+                ret.push(c);
+            }
+            continue;
+        }
+
+        match chars.next() {
+            // CSI: parameters and intermediates followed by a final byte in @..~
+            Some('[') => {
+                let mut seq = String::from("\x1b[");
+                for c in chars.by_ref() {
+                    seq.push(c);
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+                if keep_color && seq.ends_with('m') && seq.len() > 2 {
+                    ret.push_str(&seq);
+                }
+            }
+            // OSC, DCS, SOS, PM and APC: terminated by BEL or ESC \
+            Some(']' | 'P' | 'X' | '^' | '_') => {
+                while let Some(c) = chars.next() {
+                    if c == BEL {
+                        break;
+                    }
+                    if c == ESC && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            // two-char sequences, eg: ESC 7, ESC c
+            Some(_) | None => {}
+        }
+    }
+    Cow::Owned(ret)
+}
+
+fn is_removed_char(c: char) -> bool {
+    c != ESC
+        && c != '\n'
+        && c != '\r'
+        && c != '\t'
+        && (c.is_ascii_control() || ('\u{80}'..='\u{9f}').contains(&c))
+}
+
+#[test]
+fn test_sanitize() {
+    assert!(matches!(
+        sanitize("hello\nworld\t!", false),
+        Cow::Borrowed(_)
+    ));
+    assert_eq!(sanitize("\x1b[1;31merror\x1b[0m done", false), "error done");
+    assert_eq!(
+        sanitize("\x1b[1;31merror\x1b[0m done", true),
+        "\x1b[1;31merror\x1b[0m done"
+    );
+    // cursor moves, erases and titles are removed in both modes
+    assert_eq!(sanitize("\x1b[2K\x1b[1Gprogress 50%", true), "progress 50%");
+    assert_eq!(sanitize("\x1b]0;title\x07text", true), "text");
+    assert_eq!(
+        sanitize("\x1b]8;;http://a\x1b\\link\x1b]8;;\x1b\\", false),
+        "link"
+    );
+    assert_eq!(sanitize("a\x08b\x00c\x1b7d\u{9b}e", false), "abcde");
+    // an unterminated sequence at the end is dropped
+    assert_eq!(sanitize("done\x1b[3", true), "done");
+}
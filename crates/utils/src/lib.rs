@@ -1,10 +1,86 @@
-use std::{future::Future, pin::Pin, str::FromStr, sync::Arc};
+use std::{
+    future::Future,
+    pin::Pin,
+    str::FromStr,
+    sync::{Arc, OnceLock},
+};
 
 use anyhow::{Result, anyhow};
 use chrono::{Local, Utc};
 use croner::{Cron, parser::CronParser};
 use tokio::sync::RwLock;
+use tracing_subscriber::{
+    EnvFilter, Registry, fmt, layer::SubscriberExt, reload, util::SubscriberInitExt,
+};
+pub mod ansi;
+pub mod log_buffer;
 pub mod macros;
+pub mod timezone;
+
+static LOG_FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// initialize tracing, the log level can be changed at runtime by set_log_level,
+/// per-module levels are supported, eg: "info,automate=debug,sea_orm=warn"
+pub fn init_tracing(level: &str, json: bool) -> Result<()> {
+    init_tracing_with_buffer(level, json, 0)
+}
+
+/// initialize tracing like init_tracing, the last `buffer_lines` log lines are also kept
+/// in memory and read by log_buffer::get, 0 to disable
+pub fn init_tracing_with_buffer(level: &str, json: bool, buffer_lines: usize) -> Result<()> {
+    let (filter, handle) = reload::Layer::new(EnvFilter::try_new(level)?);
+    let buffer = (buffer_lines > 0).then(|| {
+        log_buffer::init(buffer_lines);
+        fmt::layer()
+            .with_ansi(false)
+            .with_writer(log_buffer::LogBufferWriter::default)
+    });
+    let registry = tracing_subscriber::registry().with(filter).with(buffer);
+    if json {
+        registry
+            .with(fmt::layer().json().with_current_span(true))
+            .try_init()?;
+    } else {
+        registry.with(fmt::layer()).try_init()?;
+    }
+    LOG_FILTER
+        .set(handle)
+        .map_err(|_| anyhow!("tracing already initialized"))
+}
+
+pub fn get_log_level() -> Option<String> {
+    LOG_FILTER
+        .get()
+        .and_then(|v| v.with_current(|f| f.to_string()).ok())
+}
+
+/// run the future with the request id, which can be read by current_request_id
+pub async fn with_request_id<F: Future>(request_id: String, f: F) -> F::Output {
+    REQUEST_ID.scope(request_id, f).await
+}
+
+/// request id of the current task, empty if not called in a request
+pub fn current_request_id() -> String {
+    REQUEST_ID.try_with(|v| v.clone()).unwrap_or_default()
+}
+
+/// whether the level can be set by set_log_level
+pub fn check_log_level(level: &str) -> Result<()> {
+    EnvFilter::try_new(level)?;
+    Ok(())
+}
+
+pub fn set_log_level(level: &str) -> Result<()> {
+    let handle = LOG_FILTER
+        .get()
+        .ok_or(anyhow!("tracing is not initialized by init_tracing"))?;
+    handle.reload(EnvFilter::try_new(level)?)?;
+    Ok(())
+}
 
 pub async fn async_batch_do<I, T, F>(data: Vec<I>, handler: F) -> Vec<Result<T>>
 where
@@ -82,7 +158,8 @@ async fn test_async_queue_do() {
     println!("result:{:?}, len: {}", ret, ret.len(),)
 }
 
-pub fn check_timer_expr(timezone: &str, expr: &str) -> Result<Vec<String>> {
+/// parse the cron expr with seconds, eg: "0 */5 * * * *", or its english form, eg: "every 5 minutes"
+pub fn parse_cron_expr(expr: &str) -> Result<Cron> {
     let parsed_expr = match CronParser::builder()
         .seconds(croner::parser::Seconds::Required)
         .dom_and_dow(true)
@@ -118,10 +195,14 @@ pub fn check_timer_expr(timezone: &str, expr: &str) -> Result<Vec<String>> {
         },
     };
 
-    let parsed_cron = match Cron::from_str(&parsed_expr) {
+    match Cron::from_str(&parsed_expr) {
         Err(e) => anyhow::bail!("failed build cron, {}", e.to_string()),
-        Ok(v) => v,
-    };
+        Ok(v) => Ok(v),
+    }
+}
+
+pub fn check_timer_expr(timezone: &str, expr: &str) -> Result<Vec<String>> {
+    let parsed_cron = parse_cron_expr(expr)?;
 
     let mut now = Local::now();
     let mut next_exec_times: Vec<String> = vec![];
@@ -148,3 +229,49 @@ pub fn check_timer_expr(timezone: &str, expr: &str) -> Result<Vec<String>> {
 
     Ok(next_exec_times)
 }
+
+/// validate the interval timer and return the next execution times,
+/// jitter is the percentage of the interval each run may be delayed by
+pub fn check_interval_timer(
+    timezone: &str,
+    interval: u64,
+    jitter: Option<u8>,
+) -> Result<Vec<String>> {
+    if interval == 0 {
+        anyhow::bail!("interval must be greater than 0");
+    }
+    if jitter.is_some_and(|v| v > 100) {
+        anyhow::bail!("jitter must be a percentage between 0 and 100");
+    }
+
+    let now = Local::now();
+    let next_exec_times = (1..=10)
+        .map(|i| now + chrono::TimeDelta::seconds((interval * i) as i64))
+        .map(|v| match timezone {
+            "local" => v.format("%Y/%m/%d %H:%M:%S").to_string(),
+            _ => v
+                .with_timezone(&Utc)
+                .format("%Y/%m/%d %H:%M:%S")
+                .to_string(),
+        })
+        .collect();
+    Ok(next_exec_times)
+}
+
+#[test]
+fn test_check_interval_timer() {
+    assert_eq!(
+        check_interval_timer("local", 60, Some(10)).unwrap().len(),
+        10
+    );
+    assert!(check_interval_timer("local", 0, None).is_err());
+    assert!(check_interval_timer("utc", 60, Some(120)).is_err());
+}
+
+#[tokio::test]
+async fn test_with_request_id() {
+    assert_eq!(current_request_id(), "");
+    let id = with_request_id("abc-123".to_string(), async { current_request_id() }).await;
+    assert_eq!(id, "abc-123");
+    assert_eq!(current_request_id(), "");
+}
@@ -0,0 +1,91 @@
+use std::{
+    collections::VecDeque,
+    io,
+    sync::{Mutex, OnceLock},
+};
+
+static LOG_BUFFER: OnceLock<LogBuffer> = OnceLock::new();
+
+/// the recent log lines of the process, the oldest are dropped once it is full
+pub struct LogBuffer {
+    capacity: usize,
+    inner: Mutex<(u64, VecDeque<(u64, String)>)>,
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner: Mutex::new((0, VecDeque::with_capacity(capacity))),
+        }
+    }
+
+    pub fn push(&self, line: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        for line in line.lines().filter(|v| !v.is_empty()) {
+            inner.0 += 1;
+            let seq = inner.0;
+            if inner.1.len() == self.capacity {
+                inner.1.pop_front();
+            }
+            inner.1.push_back((seq, line.to_string()));
+        }
+    }
+
+    /// the last `limit` lines after the seq `since`, with their seq, oldest first
+    pub fn read(&self, since: u64, limit: usize) -> Vec<(u64, String)> {
+        let inner = self.inner.lock().unwrap();
+        let lines = inner.1.iter().filter(|v| v.0 > since).collect::<Vec<_>>();
+        lines[lines.len().saturating_sub(limit)..]
+            .iter()
+            .map(|v| (*v).clone())
+            .collect()
+    }
+}
+
+/// set the buffer the writers made by make_writer push to
+pub(crate) fn init(capacity: usize) {
+    LOG_BUFFER.get_or_init(|| LogBuffer::new(capacity));
+}
+
+/// the buffer set by init_tracing_with_buffer, none if the process does not keep the logs
+pub fn get() -> Option<&'static LogBuffer> {
+    LOG_BUFFER.get()
+}
+
+/// writes one formatted event, pushed to the buffer when it is dropped
+#[derive(Default)]
+pub(crate) struct LogBufferWriter(Vec<u8>);
+
+impl io::Write for LogBufferWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for LogBufferWriter {
+    fn drop(&mut self) {
+        if let Some(buffer) = get() {
+            buffer.push(&String::from_utf8_lossy(&self.0));
+        }
+    }
+}
+
+#[test]
+fn test_log_buffer() {
+    let buffer = LogBuffer::new(3);
+    buffer.push("a\nb\n");
+    buffer.push("c\n");
+    buffer.push("d\n");
+    let seqs = |v: Vec<(u64, String)>| v.into_iter().map(|v| v.0).collect::<Vec<_>>();
+    assert_eq!(seqs(buffer.read(0, 10)), vec![2, 3, 4]);
+    assert_eq!(seqs(buffer.read(0, 2)), vec![3, 4]);
+    assert_eq!(seqs(buffer.read(3, 10)), vec![4]);
+    assert!(buffer.read(4, 10).is_empty());
+    assert_eq!(buffer.read(3, 10)[0].1, "d");
+}
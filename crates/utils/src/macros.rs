@@ -1,10 +1,8 @@
-/// convert DateTime<Local> to local time(String)
+/// convert DateTime to the time(String) in the zone of the current user
 #[macro_export]
 macro_rules! local_time {
     ($time:expr) => {
-        $time
-            .with_timezone(&chrono::Local)
-            .naive_local()
+        $crate::timezone::user_time(&$time)
             .format("%Y/%m/%d %H:%M:%S")
             .to_string()
     };
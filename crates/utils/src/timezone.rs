@@ -0,0 +1,52 @@
+use std::future::Future;
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
+
+tokio::task_local! {
+    static TIMEZONE: Option<Tz>;
+}
+
+/// parse the IANA name of a time zone, eg: Asia/Shanghai, empty means the server zone
+pub fn parse_timezone(v: &str) -> Result<Option<Tz>, String> {
+    if v.is_empty() {
+        return Ok(None);
+    }
+    v.parse::<Tz>().map(Some).map_err(|e| e.to_string())
+}
+
+/// run the request with the time zone preferred by the user
+pub async fn with_timezone<F: Future>(timezone: &str, f: F) -> F::Output {
+    TIMEZONE
+        .scope(parse_timezone(timezone).ok().flatten(), f)
+        .await
+}
+
+/// convert the time into the zone of the current request, the server zone is used
+/// outside of a request or when the user has no preference
+pub fn user_time<T: TimeZone>(t: &DateTime<T>) -> NaiveDateTime {
+    match TIMEZONE.try_with(|v| *v).ok().flatten() {
+        Some(tz) => t.with_timezone(&tz).naive_local(),
+        None => t.with_timezone(&Local).naive_local(),
+    }
+}
+
+#[tokio::test]
+async fn test_user_time() {
+    use chrono::Utc;
+
+    let t = Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap();
+    assert_eq!(user_time(&t), t.with_timezone(&Local).naive_local());
+
+    with_timezone("Asia/Shanghai", async {
+        assert_eq!(user_time(&t).to_string(), "2025-01-01 08:00:00");
+    })
+    .await;
+    with_timezone("America/New_York", async {
+        assert_eq!(user_time(&t).to_string(), "2024-12-31 19:00:00");
+    })
+    .await;
+
+    assert!(parse_timezone("").is_ok_and(|v| v.is_none()));
+    assert!(parse_timezone("Mars/Olympus").is_err());
+}
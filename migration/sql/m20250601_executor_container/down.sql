@@ -0,0 +1,3 @@
+ALTER TABLE executor
+DROP COLUMN `container_image`,
+DROP COLUMN `container_options`;
@@ -0,0 +1,4 @@
+ALTER TABLE executor
+ADD COLUMN `container_image` varchar(255) NOT NULL DEFAULT '' COMMENT 'container image, only for container platform',
+ADD COLUMN `container_options` json DEFAULT NULL COMMENT 'container runtime and resource limits',
+MODIFY COLUMN `platform` varchar(10) NOT NULL DEFAULT 'linux' COMMENT 'platform: linux, windows, container';
@@ -0,0 +1,2 @@
+ALTER TABLE job
+ADD COLUMN `http_check` json DEFAULT NULL COMMENT 'http check options, only for http job';
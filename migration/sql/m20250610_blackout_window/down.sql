@@ -0,0 +1,4 @@
+DROP TABLE IF EXISTS `blackout_window`;
+
+ALTER TABLE job_schedule_history
+DROP COLUMN `override_reason`;
@@ -0,0 +1,23 @@
+CREATE TABLE `blackout_window` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `name` varchar(100) NOT NULL DEFAULT '' COMMENT 'window name',
+    `team_id` bigint unsigned NOT NULL DEFAULT '0' COMMENT 'team id, 0 means global',
+    `window_type` varchar(20) NOT NULL DEFAULT 'once' COMMENT 'once or recurring',
+    `start_time` timestamp NULL DEFAULT NULL COMMENT 'start time of once window',
+    `end_time` timestamp NULL DEFAULT NULL COMMENT 'end time of once window',
+    `weekdays` json DEFAULT NULL COMMENT 'days from sunday of recurring window',
+    `start_clock` varchar(5) NOT NULL DEFAULT '' COMMENT 'HH:MM start of recurring window',
+    `end_clock` varchar(5) NOT NULL DEFAULT '' COMMENT 'HH:MM end of recurring window',
+    `timezone` varchar(10) NOT NULL DEFAULT 'local' COMMENT 'utc or local',
+    `is_enabled` BOOLEAN NOT NULL DEFAULT true COMMENT 'is enabled',
+    `info` varchar(500) NOT NULL DEFAULT '' COMMENT 'describe message',
+    `created_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'creator username',
+    `updated_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'updater username',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    `updated_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT 'updated time',
+    PRIMARY KEY (`id`),
+    KEY `idx_team_id` (`team_id`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'blackout window';
+
+ALTER TABLE job_schedule_history
+ADD COLUMN `override_reason` varchar(500) NOT NULL DEFAULT '' COMMENT 'reason of dispatching inside a blackout window';
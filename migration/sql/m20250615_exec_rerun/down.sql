@@ -0,0 +1,3 @@
+ALTER TABLE job_exec_history
+DROP KEY `idx_rerun_of`,
+DROP COLUMN `rerun_of`;
@@ -0,0 +1,3 @@
+ALTER TABLE job_exec_history
+ADD COLUMN `rerun_of` bigint unsigned NOT NULL DEFAULT '0' COMMENT 'exec history id this run is rerun from, 0 means not a rerun',
+ADD KEY `idx_rerun_of` (`rerun_of`);
@@ -0,0 +1,2 @@
+DELETE FROM casbin_rule
+WHERE ptype = 'p' AND ((v1 = 'job' AND v2 = 'dispatch') OR (v1 = 'instance' AND v2 = 'ssh'));
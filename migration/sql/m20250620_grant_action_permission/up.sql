@@ -0,0 +1,12 @@
+INSERT INTO casbin_rule (`ptype`, `v0`, `v1`, `v2`, `v3`, `v4`, `v5`)
+SELECT 'p', CAST(r.id AS CHAR), p.v1, p.v2, '', '', ''
+FROM role r
+CROSS JOIN (
+    SELECT 'job' AS v1, 'dispatch' AS v2
+    UNION ALL
+    SELECT 'instance' AS v1, 'ssh' AS v2
+) p
+WHERE NOT EXISTS (
+    SELECT 1 FROM casbin_rule c
+    WHERE c.ptype = 'p' AND c.v0 = CAST(r.id AS CHAR) AND c.v1 = p.v1 AND c.v2 = p.v2
+);
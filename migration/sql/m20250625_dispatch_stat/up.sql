@@ -0,0 +1,13 @@
+CREATE TABLE `dispatch_stat` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `stat_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'start of the 5 minutes bucket',
+    `comet_addr` varchar(100) NOT NULL DEFAULT '' COMMENT 'comet the jobs were dispatched through',
+    `dispatch_total` int unsigned NOT NULL DEFAULT '0' COMMENT 'dispatched instance count',
+    `dispatch_failed` int unsigned NOT NULL DEFAULT '0' COMMENT 'failed dispatched instance count',
+    `latency_total` int unsigned NOT NULL DEFAULT '0' COMMENT 'count of dispatch to start latency samples',
+    `latency_buckets` json DEFAULT NULL COMMENT 'dispatch to start latency histogram',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    `updated_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT 'updated time',
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_stat_time_comet` (`stat_time`, `comet_addr`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'dispatch stat rollup';
@@ -0,0 +1,14 @@
+ALTER TABLE instance
+DROP KEY `idx_arch`,
+DROP KEY `idx_os`,
+DROP COLUMN `os`,
+DROP COLUMN `os_version`,
+DROP COLUMN `kernel_version`,
+DROP COLUMN `arch`,
+DROP COLUMN `cpu_count`,
+DROP COLUMN `mem_total`,
+DROP COLUMN `mem_used`,
+DROP COLUMN `disk_total`,
+DROP COLUMN `disk_used`,
+DROP COLUMN `agent_version`,
+DROP COLUMN `facts_updated_time`;
@@ -0,0 +1,14 @@
+ALTER TABLE instance
+ADD COLUMN `os` varchar(50) NOT NULL DEFAULT '' COMMENT 'os name',
+ADD COLUMN `os_version` varchar(50) NOT NULL DEFAULT '' COMMENT 'os version',
+ADD COLUMN `kernel_version` varchar(100) NOT NULL DEFAULT '' COMMENT 'kernel version',
+ADD COLUMN `arch` varchar(20) NOT NULL DEFAULT '' COMMENT 'cpu architecture, eg: x86_64, aarch64',
+ADD COLUMN `cpu_count` int unsigned NOT NULL DEFAULT '0' COMMENT 'logical cpu count',
+ADD COLUMN `mem_total` bigint unsigned NOT NULL DEFAULT '0' COMMENT 'total memory in bytes',
+ADD COLUMN `mem_used` bigint unsigned NOT NULL DEFAULT '0' COMMENT 'used memory in bytes',
+ADD COLUMN `disk_total` bigint unsigned NOT NULL DEFAULT '0' COMMENT 'total disk space in bytes',
+ADD COLUMN `disk_used` bigint unsigned NOT NULL DEFAULT '0' COMMENT 'used disk space in bytes',
+ADD COLUMN `agent_version` varchar(20) NOT NULL DEFAULT '' COMMENT 'agent version',
+ADD COLUMN `facts_updated_time` timestamp NULL DEFAULT NULL COMMENT 'last time the facts were reported',
+ADD KEY `idx_arch` (`arch`),
+ADD KEY `idx_os` (`os`);
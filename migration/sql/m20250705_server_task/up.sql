@@ -0,0 +1,21 @@
+DROP TABLE IF EXISTS `server_task`;
+CREATE TABLE `server_task` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `task_id` varchar(50) NOT NULL DEFAULT '' COMMENT 'task id',
+    `task_type` varchar(50) NOT NULL DEFAULT '' COMMENT 'task type, eg: dispatch_job',
+    `team_id` bigint unsigned NOT NULL DEFAULT '0' COMMENT 'team id',
+    `params` json DEFAULT NULL COMMENT 'task params',
+    `status` varchar(20) NOT NULL DEFAULT 'pending' COMMENT 'pending, running, succeeded or failed',
+    `output` json DEFAULT NULL COMMENT 'task result',
+    `err_msg` text COMMENT 'error of the failed task',
+    `worker` varchar(100) NOT NULL DEFAULT '' COMMENT 'console running the task',
+    `started_time` timestamp NULL DEFAULT NULL COMMENT 'started time',
+    `finished_time` timestamp NULL DEFAULT NULL COMMENT 'finished time',
+    `created_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'created user',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    `updated_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT 'updated time',
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_task_id` (`task_id`),
+    KEY `idx_status` (`status`),
+    KEY `idx_created_user` (`created_user`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'server side background task';
@@ -0,0 +1,8 @@
+ALTER TABLE job
+DROP COLUMN `version`;
+
+ALTER TABLE job_bundle_script
+DROP COLUMN `version`;
+
+ALTER TABLE job_timer
+DROP COLUMN `version`;
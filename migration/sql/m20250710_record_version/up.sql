@@ -0,0 +1,8 @@
+ALTER TABLE job
+ADD COLUMN `version` int unsigned NOT NULL DEFAULT '1' COMMENT 'increased on every update, used to detect concurrent edits';
+
+ALTER TABLE job_bundle_script
+ADD COLUMN `version` int unsigned NOT NULL DEFAULT '1' COMMENT 'increased on every update, used to detect concurrent edits';
+
+ALTER TABLE job_timer
+ADD COLUMN `version` int unsigned NOT NULL DEFAULT '1' COMMENT 'increased on every update, used to detect concurrent edits';
@@ -0,0 +1,14 @@
+DROP TABLE IF EXISTS `job_share`;
+CREATE TABLE `job_share` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `eid` varchar(100) NOT NULL DEFAULT '' COMMENT 'job eid',
+    `team_id` bigint unsigned NOT NULL DEFAULT '0' COMMENT 'team the job is shared with',
+    `permission` varchar(20) NOT NULL DEFAULT 'read' COMMENT 'read or dispatch',
+    `created_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'created user',
+    `updated_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'updated user',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    `updated_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT 'updated time',
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_eid_team` (`eid`, `team_id`),
+    KEY `idx_team_id` (`team_id`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'job shared with other teams';
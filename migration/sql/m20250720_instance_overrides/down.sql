@@ -0,0 +1 @@
+ALTER TABLE `job_schedule` DROP COLUMN `instance_overrides`;
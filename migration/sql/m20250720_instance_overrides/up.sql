@@ -0,0 +1,2 @@
+ALTER TABLE `job_schedule`
+ADD COLUMN `instance_overrides` json DEFAULT NULL COMMENT 'env, args and work_dir overridden per instance' AFTER `instance_ids`;
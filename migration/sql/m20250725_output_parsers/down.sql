@@ -0,0 +1,2 @@
+ALTER TABLE `job` DROP COLUMN `output_parsers`;
+ALTER TABLE `job_exec_history` DROP COLUMN `parsed_result`;
@@ -0,0 +1,4 @@
+ALTER TABLE `job`
+ADD COLUMN `output_parsers` json DEFAULT NULL COMMENT 'rules extracting named metrics from stdout' AFTER `http_check`;
+ALTER TABLE `job_exec_history`
+ADD COLUMN `parsed_result` json DEFAULT NULL COMMENT 'metrics extracted by the output parsers of the job' AFTER `bundle_script_result`;
@@ -0,0 +1,3 @@
+ALTER TABLE `job` DROP COLUMN `exit_code_mapping`;
+ALTER TABLE `job_exec_history` DROP COLUMN `result_status`;
+ALTER TABLE `job_running_status` DROP COLUMN `result_status`;
@@ -0,0 +1,8 @@
+ALTER TABLE `job`
+ADD COLUMN `exit_code_mapping` json DEFAULT NULL COMMENT 'exit code to success, warning or failure' AFTER `output_parsers`;
+ALTER TABLE `job_exec_history`
+ADD COLUMN `result_status` varchar(20) NOT NULL DEFAULT '' COMMENT 'success, warning or failure' AFTER `exit_code`;
+ALTER TABLE `job_running_status`
+ADD COLUMN `result_status` varchar(20) NOT NULL DEFAULT '' COMMENT 'success, warning or failure' AFTER `exit_code`;
+UPDATE `job_exec_history` SET `result_status` = IF(`exit_code` = 0, 'success', 'failure');
+UPDATE `job_running_status` SET `result_status` = IF(`exit_code` = 0, 'success', 'failure') WHERE `run_status` = 'stop';
@@ -0,0 +1,2 @@
+ALTER TABLE `job`
+ADD COLUMN `bundle_option` json DEFAULT NULL COMMENT 'sequential or parallel, max parallelism and continue on error of bundle scripts' AFTER `bundle_script`;
@@ -0,0 +1,2 @@
+ALTER TABLE `job`
+ADD COLUMN `supervisor_option` json DEFAULT NULL COMMENT 'health check, restart backoff and flapping detection of the supervised job' AFTER `bundle_option`;
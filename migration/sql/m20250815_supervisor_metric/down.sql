@@ -0,0 +1 @@
+DROP TABLE IF EXISTS `job_supervisor_metric`;
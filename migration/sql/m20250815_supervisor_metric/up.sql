@@ -0,0 +1,15 @@
+CREATE TABLE `job_supervisor_metric` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `eid` varchar(100) NOT NULL DEFAULT '' COMMENT 'job eid',
+    `schedule_id` varchar(40) NOT NULL DEFAULT '' COMMENT 'schedule id',
+    `instance_id` varchar(40) NOT NULL DEFAULT '' COMMENT 'instance id',
+    `run_id` varchar(40) NOT NULL DEFAULT '' COMMENT 'run id of the daemon process',
+    `cpu_usage` float NOT NULL DEFAULT '0' COMMENT 'cpu usage percentage of a single core',
+    `rss` bigint unsigned NOT NULL DEFAULT '0' COMMENT 'resident memory in bytes',
+    `process_count` int unsigned NOT NULL DEFAULT '0' COMMENT 'count of the process and its descendants',
+    `sample_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'sampled time on the agent',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    PRIMARY KEY (`id`),
+    KEY `idx_eid_instance_sample_time` (`eid`, `instance_id`, `sample_time`),
+    KEY `idx_sample_time` (`sample_time`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'resource usage samples of the supervised jobs';
@@ -0,0 +1,2 @@
+ALTER TABLE `user`
+ADD COLUMN `timezone` varchar(64) NOT NULL DEFAULT '' COMMENT 'IANA time zone the times are displayed in, empty for the server zone' AFTER `introduction`;
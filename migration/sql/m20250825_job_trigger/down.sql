@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS `job_trigger_log`;
+DROP TABLE IF EXISTS `job_trigger`;
@@ -0,0 +1,34 @@
+DROP TABLE IF EXISTS `job_trigger`;
+CREATE TABLE `job_trigger` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `token` varchar(64) NOT NULL DEFAULT '' COMMENT 'path token of the trigger url',
+    `secret` varchar(64) NOT NULL DEFAULT '' COMMENT 'secret to sign the payload',
+    `name` varchar(100) NOT NULL DEFAULT '' COMMENT 'trigger name',
+    `eid` varchar(100) NOT NULL DEFAULT '' COMMENT 'job eid',
+    `instance_ids` json DEFAULT NULL COMMENT 'instances the job is dispatched to',
+    `args_mapping` json DEFAULT NULL COMMENT 'job arg name to json pointer of the payload',
+    `rate_limit` int unsigned NOT NULL DEFAULT '0' COMMENT 'max invocations per minute, unlimited if 0',
+    `is_enabled` tinyint(1) NOT NULL DEFAULT '1' COMMENT 'is enabled',
+    `created_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'created user',
+    `updated_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'updated user',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    `updated_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT 'updated time',
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_token` (`token`),
+    KEY `idx_eid` (`eid`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'inbound triggers to dispatch the job from external systems';
+
+DROP TABLE IF EXISTS `job_trigger_log`;
+CREATE TABLE `job_trigger_log` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `trigger_id` bigint unsigned NOT NULL DEFAULT '0' COMMENT 'trigger id',
+    `eid` varchar(100) NOT NULL DEFAULT '' COMMENT 'job eid',
+    `remote_ip` varchar(50) NOT NULL DEFAULT '' COMMENT 'ip of the caller',
+    `status` varchar(20) NOT NULL DEFAULT '' COMMENT 'accepted, rejected or failed',
+    `reason` varchar(500) NOT NULL DEFAULT '' COMMENT 'why the invocation is rejected or failed',
+    `schedule_id` varchar(40) NOT NULL DEFAULT '' COMMENT 'schedule id of the dispatch',
+    `payload` text COMMENT 'payload of the invocation, truncated',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    PRIMARY KEY (`id`),
+    KEY `idx_trigger_id_created_time` (`trigger_id`, `created_time`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'audit of the trigger invocations';
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS `job_remediation_log`;
+DROP TABLE IF EXISTS `job_remediation`;
@@ -0,0 +1,36 @@
+DROP TABLE IF EXISTS `job_remediation`;
+CREATE TABLE `job_remediation` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `name` varchar(100) NOT NULL DEFAULT '' COMMENT 'remediation name',
+    `eid` varchar(100) NOT NULL DEFAULT '' COMMENT 'job eid',
+    `alertname` varchar(200) NOT NULL DEFAULT '' COMMENT 'alertname of the alerts to remediate',
+    `match_labels` json DEFAULT NULL COMMENT 'labels the alert must have, label name to value',
+    `target_label` varchar(100) NOT NULL DEFAULT 'instance' COMMENT 'alert label whose host is the target instance',
+    `instance_ids` json DEFAULT NULL COMMENT 'instances the job is dispatched to if the target label is absent',
+    `cooldown_secs` int unsigned NOT NULL DEFAULT '600' COMMENT 'min interval between the runs on the same instance',
+    `max_runs_per_hour` int unsigned NOT NULL DEFAULT '6' COMMENT 'max auto-runs per hour, unlimited if 0',
+    `is_enabled` tinyint(1) NOT NULL DEFAULT '1' COMMENT 'is enabled',
+    `created_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'created user',
+    `updated_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'updated user',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    `updated_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT 'updated time',
+    PRIMARY KEY (`id`),
+    KEY `idx_alertname` (`alertname`),
+    KEY `idx_eid` (`eid`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'alerts mapped to the remediation jobs';
+
+DROP TABLE IF EXISTS `job_remediation_log`;
+CREATE TABLE `job_remediation_log` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `remediation_id` bigint unsigned NOT NULL DEFAULT '0' COMMENT 'remediation id',
+    `eid` varchar(100) NOT NULL DEFAULT '' COMMENT 'job eid',
+    `alertname` varchar(200) NOT NULL DEFAULT '' COMMENT 'alertname',
+    `fingerprint` varchar(64) NOT NULL DEFAULT '' COMMENT 'fingerprint of the alert',
+    `instance_ids` json DEFAULT NULL COMMENT 'instances the job is dispatched to',
+    `status` varchar(20) NOT NULL DEFAULT '' COMMENT 'dispatched, skipped or failed',
+    `reason` varchar(500) NOT NULL DEFAULT '' COMMENT 'why the run is skipped or failed',
+    `schedule_id` varchar(40) NOT NULL DEFAULT '' COMMENT 'schedule id of the dispatch',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    PRIMARY KEY (`id`),
+    KEY `idx_remediation_id_created_time` (`remediation_id`, `created_time`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'audit of the auto-remediation runs';
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS `job_duration_anomaly`;
+DROP TABLE IF EXISTS `job_duration_stat`;
@@ -0,0 +1,30 @@
+DROP TABLE IF EXISTS `job_duration_stat`;
+CREATE TABLE `job_duration_stat` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `eid` varchar(100) NOT NULL DEFAULT '' COMMENT 'job eid',
+    `instance_id` varchar(40) NOT NULL DEFAULT '' COMMENT 'instance id',
+    `samples` json DEFAULT NULL COMMENT 'durations in milliseconds of the latest runs',
+    `sample_total` int unsigned NOT NULL DEFAULT '0' COMMENT 'runs observed since the stat is created',
+    `median_ms` bigint unsigned NOT NULL DEFAULT '0' COMMENT 'median duration of the samples',
+    `last_end_time` timestamp NULL DEFAULT NULL COMMENT 'end time of the latest observed run',
+    `updated_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT 'updated time',
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_eid_instance_id` (`eid`, `instance_id`),
+    KEY `idx_last_end_time` (`last_end_time`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'rolling duration statistics of the job runs';
+
+DROP TABLE IF EXISTS `job_duration_anomaly`;
+CREATE TABLE `job_duration_anomaly` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `eid` varchar(100) NOT NULL DEFAULT '' COMMENT 'job eid',
+    `instance_id` varchar(40) NOT NULL DEFAULT '' COMMENT 'instance id',
+    `schedule_id` varchar(40) NOT NULL DEFAULT '' COMMENT 'schedule id',
+    `run_id` varchar(40) NOT NULL DEFAULT '' COMMENT 'run id',
+    `exec_history_id` bigint unsigned NOT NULL DEFAULT '0' COMMENT 'id of the execution history',
+    `duration_ms` bigint unsigned NOT NULL DEFAULT '0' COMMENT 'duration of the run',
+    `median_ms` bigint unsigned NOT NULL DEFAULT '0' COMMENT 'median duration before the run',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    PRIMARY KEY (`id`),
+    KEY `idx_created_time` (`created_time`),
+    KEY `idx_eid_created_time` (`eid`, `created_time`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'runs much slower than usual';
@@ -0,0 +1,15 @@
+DROP TABLE IF EXISTS `job_daily_stat`;
+CREATE TABLE `job_daily_stat` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `stat_date` date NOT NULL COMMENT 'day the runs ended',
+    `eid` varchar(100) NOT NULL DEFAULT '' COMMENT 'job eid',
+    `instance_id` varchar(40) NOT NULL DEFAULT '' COMMENT 'instance id',
+    `run_total` int unsigned NOT NULL DEFAULT '0' COMMENT 'runs ended in the day',
+    `fail_total` int unsigned NOT NULL DEFAULT '0' COMMENT 'failed runs',
+    `duration_total_ms` bigint unsigned NOT NULL DEFAULT '0' COMMENT 'sum of the durations',
+    `duration_max_ms` bigint unsigned NOT NULL DEFAULT '0' COMMENT 'longest duration',
+    `updated_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT 'updated time',
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_stat_date_eid_instance_id` (`stat_date`, `eid`, `instance_id`),
+    KEY `idx_eid` (`eid`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'daily rollup of the job runs per instance';
@@ -0,0 +1,3 @@
+ALTER TABLE `executor`
+DROP COLUMN `platform_commands`,
+DROP COLUMN `min_agent_version`;
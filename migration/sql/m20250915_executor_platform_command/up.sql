@@ -0,0 +1,3 @@
+ALTER TABLE `executor`
+ADD COLUMN `platform_commands` json DEFAULT NULL COMMENT 'command per os: linux, windows, darwin, the command is used for the other os' AFTER `command`,
+ADD COLUMN `min_agent_version` varchar(20) NOT NULL DEFAULT '' COMMENT 'minimum version of the agent to run the executor' AFTER `platform_commands`;
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS `instance_executor`;
+ALTER TABLE `executor` DROP COLUMN `install_script`;
@@ -0,0 +1,17 @@
+ALTER TABLE `executor`
+ADD COLUMN `install_script` text COMMENT 'script installing the executor on the instance' AFTER `min_agent_version`;
+
+DROP TABLE IF EXISTS `instance_executor`;
+CREATE TABLE `instance_executor` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `instance_id` varchar(40) NOT NULL DEFAULT '' COMMENT 'instance id',
+    `executor_id` bigint unsigned NOT NULL DEFAULT '0' COMMENT 'executor id',
+    `status` varchar(20) NOT NULL DEFAULT '' COMMENT 'installed, failed',
+    `exit_code` int DEFAULT NULL COMMENT 'exit code of the install script',
+    `output` text COMMENT 'output of the install script',
+    `updated_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'user who ensured the executor',
+    `updated_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT 'updated time',
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_instance_id_executor_id` (`instance_id`, `executor_id`),
+    KEY `idx_executor_id` (`executor_id`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'executor availability per instance';
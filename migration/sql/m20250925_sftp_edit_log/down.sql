@@ -0,0 +1 @@
+DROP TABLE IF EXISTS `sftp_edit_log`;
@@ -0,0 +1,14 @@
+DROP TABLE IF EXISTS `sftp_edit_log`;
+CREATE TABLE `sftp_edit_log` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `instance_id` varchar(40) NOT NULL DEFAULT '' COMMENT 'instance id',
+    `ip` varchar(40) NOT NULL DEFAULT '' COMMENT 'instance ip',
+    `file_path` varchar(1024) NOT NULL DEFAULT '' COMMENT 'path of the edited file',
+    `prev_etag` varchar(64) NOT NULL DEFAULT '' COMMENT 'etag before the save, empty if the file is created',
+    `etag` varchar(64) NOT NULL DEFAULT '' COMMENT 'etag after the save',
+    `size` bigint unsigned NOT NULL DEFAULT '0' COMMENT 'size after the save',
+    `created_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'user who saved the file',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    PRIMARY KEY (`id`),
+    KEY `idx_instance_id` (`instance_id`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'audit of the files edited over sftp';
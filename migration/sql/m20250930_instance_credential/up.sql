@@ -0,0 +1,19 @@
+DROP TABLE IF EXISTS `instance_credential`;
+CREATE TABLE `instance_credential` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `instance_id` varchar(40) NOT NULL DEFAULT '' COMMENT 'instance id',
+    `name` varchar(50) NOT NULL DEFAULT '' COMMENT 'credential name',
+    `cred_type` varchar(20) NOT NULL DEFAULT 'password' COMMENT 'password, private_key',
+    `sys_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'system user',
+    `secret` text NOT NULL COMMENT 'encrypted password or private key',
+    `passphrase` text COMMENT 'encrypted passphrase of the private key',
+    `scopes` varchar(100) NOT NULL DEFAULT '' COMMENT 'comma separated webssh, sftp, job',
+    `rotate_days` int unsigned NOT NULL DEFAULT '0' COMMENT 'rotate the password every n days, 0 to disable',
+    `rotated_time` timestamp NULL DEFAULT NULL COMMENT 'last rotated time',
+    `created_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'created user',
+    `updated_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'updated user',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    `updated_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT 'updated time',
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_instance_id_name` (`instance_id`, `name`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'ssh credentials of the instances';
@@ -0,0 +1,3 @@
+ALTER TABLE `instance`
+DROP COLUMN `private_key`,
+DROP COLUMN `key_passphrase`;
@@ -0,0 +1,3 @@
+ALTER TABLE `instance`
+ADD COLUMN `private_key` text COMMENT 'encrypted ssh private key, used instead of the password if set' AFTER `password`,
+ADD COLUMN `key_passphrase` text COMMENT 'encrypted passphrase of the private key' AFTER `private_key`;
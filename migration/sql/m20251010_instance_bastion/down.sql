@@ -0,0 +1,2 @@
+ALTER TABLE `instance` DROP COLUMN `bastion_instance_id`;
+ALTER TABLE `instance_group` DROP COLUMN `bastion_instance_id`;
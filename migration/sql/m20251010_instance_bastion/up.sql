@@ -0,0 +1,4 @@
+ALTER TABLE `instance`
+ADD COLUMN `bastion_instance_id` varchar(40) NOT NULL DEFAULT '' COMMENT 'ssh connections are tunneled through this instance, the bastion of the group is used if empty' AFTER `ssh_port`;
+ALTER TABLE `instance_group`
+ADD COLUMN `bastion_instance_id` varchar(40) NOT NULL DEFAULT '' COMMENT 'bastion of the instances in the group' AFTER `info`;
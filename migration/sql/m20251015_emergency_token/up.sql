@@ -0,0 +1,11 @@
+CREATE TABLE `emergency_token` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `user_id` varchar(50) NOT NULL DEFAULT '' COMMENT 'the requests are authorized as this user',
+    `name` varchar(50) NOT NULL DEFAULT '' COMMENT 'token name',
+    `token_hash` varchar(64) NOT NULL DEFAULT '' COMMENT 'sha256 of the token secret',
+    `expire_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'expire time',
+    `created_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'created user',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_token_hash` (`token_hash`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'tokens created by the offline recovery tool, accepted without redis';
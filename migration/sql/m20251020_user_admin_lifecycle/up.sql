@@ -0,0 +1,3 @@
+ALTER TABLE `user`
+ADD COLUMN `is_disabled` tinyint(1) NOT NULL DEFAULT 0 COMMENT 'disabled users cannot log in and lose the permissions of their role' AFTER `is_root`,
+ADD COLUMN `password_expired` tinyint(1) NOT NULL DEFAULT 0 COMMENT 'the password must be changed before other requests are allowed' AFTER `password`;
@@ -0,0 +1,4 @@
+ALTER TABLE `team_member` DROP COLUMN `group_id`;
+DROP TABLE IF EXISTS `user_group_team`;
+DROP TABLE IF EXISTS `user_group_member`;
+DROP TABLE IF EXISTS `user_group`;
@@ -0,0 +1,37 @@
+CREATE TABLE `user_group` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `name` varchar(50) NOT NULL DEFAULT '' COMMENT 'group name',
+    `info` varchar(500) NOT NULL DEFAULT '' COMMENT 'description',
+    `source` varchar(50) NOT NULL DEFAULT '' COMMENT 'external source the members are synced from, eg: ldap, empty if managed manually',
+    `created_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'created user',
+    `updated_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'updated user',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    `updated_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT 'updated time',
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_name` (`name`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'user groups whose members join the mapped teams';
+
+CREATE TABLE `user_group_member` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `group_id` bigint unsigned NOT NULL DEFAULT 0 COMMENT 'user group id',
+    `user_id` varchar(10) NOT NULL DEFAULT '' COMMENT 'user id',
+    `created_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'created user',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_group_user` (`group_id`, `user_id`),
+    KEY `idx_user_id` (`user_id`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'user group members';
+
+CREATE TABLE `user_group_team` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `group_id` bigint unsigned NOT NULL DEFAULT 0 COMMENT 'user group id',
+    `team_id` bigint unsigned NOT NULL DEFAULT 0 COMMENT 'team id',
+    `is_admin` tinyint(1) NOT NULL DEFAULT 0 COMMENT 'the members of the group are admins of the team',
+    `created_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'created user',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_group_team` (`group_id`, `team_id`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'teams the members of the user group join';
+
+ALTER TABLE `team_member`
+ADD COLUMN `group_id` bigint unsigned NOT NULL DEFAULT 0 COMMENT 'the member joined by this user group, 0 if added manually' AFTER `is_admin`;
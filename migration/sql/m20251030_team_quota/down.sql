@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS `team_upload_file`;
+DROP TABLE IF EXISTS `team_quota`;
@@ -0,0 +1,26 @@
+CREATE TABLE `team_quota` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `team_id` bigint unsigned NOT NULL DEFAULT 0 COMMENT 'team id',
+    `max_jobs` int unsigned NOT NULL DEFAULT 0 COMMENT 'max jobs, 0 means unlimited',
+    `max_timers` int unsigned NOT NULL DEFAULT 0 COMMENT 'max timers of the jobs, 0 means unlimited',
+    `max_daemons` int unsigned NOT NULL DEFAULT 0 COMMENT 'max supervisors of the jobs, 0 means unlimited',
+    `max_upload_bytes` bigint unsigned NOT NULL DEFAULT 0 COMMENT 'max total size of the uploaded files, 0 means unlimited',
+    `updated_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'updated user',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    `updated_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT 'updated time',
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_team_id` (`team_id`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'resource quotas of the teams, unlimited if a team has none';
+
+CREATE TABLE `team_upload_file` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `team_id` bigint unsigned NOT NULL DEFAULT 0 COMMENT 'team id',
+    `filename` varchar(255) NOT NULL DEFAULT '' COMMENT 'name of the uploaded file',
+    `size` bigint unsigned NOT NULL DEFAULT 0 COMMENT 'file size in bytes',
+    `created_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'created user',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    `updated_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT 'updated time',
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_filename` (`filename`),
+    KEY `idx_team_id` (`team_id`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'files uploaded on behalf of the teams, counted by the upload quota';
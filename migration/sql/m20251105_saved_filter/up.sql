@@ -0,0 +1,14 @@
+CREATE TABLE `saved_filter` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `name` varchar(100) NOT NULL DEFAULT '' COMMENT 'filter name, eg: prod failures last 24h',
+    `resource` varchar(50) NOT NULL DEFAULT '' COMMENT 'list the filter applies to, eg: exec-list',
+    `params` json DEFAULT NULL COMMENT 'query params of the list',
+    `team_id` bigint unsigned NOT NULL DEFAULT 0 COMMENT 'shared with the members of the team, 0 if private',
+    `created_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'created user',
+    `updated_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'updated user',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    `updated_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT 'updated time',
+    PRIMARY KEY (`id`),
+    KEY `idx_created_user` (`created_user`),
+    KEY `idx_team_id` (`team_id`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'named filters of the list endpoints saved by the users';
@@ -0,0 +1,2 @@
+ALTER TABLE `job`
+ADD COLUMN `is_disabled` tinyint(1) NOT NULL DEFAULT 0 COMMENT 'disabled jobs cannot be started, the running schedules can still be stopped' AFTER `display_on_dashboard`;
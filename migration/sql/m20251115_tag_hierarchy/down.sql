@@ -0,0 +1,4 @@
+ALTER TABLE `tag`
+DROP KEY `idx_parent_id`,
+DROP COLUMN `parent_id`,
+MODIFY COLUMN `tag_name` varchar(40) NOT NULL DEFAULT '' COMMENT '调度uuid';
@@ -0,0 +1,4 @@
+ALTER TABLE `tag`
+MODIFY COLUMN `tag_name` varchar(200) NOT NULL DEFAULT '' COMMENT 'path of the tag, the namespaces are separated by /, eg: env/prod',
+ADD COLUMN `parent_id` bigint unsigned NOT NULL DEFAULT 0 COMMENT 'tag of the parent namespace, 0 for the top level' AFTER `tag_name`,
+ADD KEY `idx_parent_id` (`parent_id`);
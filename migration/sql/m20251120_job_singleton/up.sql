@@ -0,0 +1,2 @@
+ALTER TABLE `job`
+ADD COLUMN `is_singleton` tinyint(1) NOT NULL DEFAULT 0 COMMENT 'only one of the instances runs each timer tick of the job' AFTER `is_disabled`;
@@ -0,0 +1,2 @@
+ALTER TABLE `job` DROP COLUMN `placement`;
+ALTER TABLE `job_schedule_history` DROP COLUMN `placement`;
@@ -0,0 +1,4 @@
+ALTER TABLE `job`
+ADD COLUMN `placement` varchar(20) NOT NULL DEFAULT '' COMMENT 'empty for all the selected instances, sticky for the instance that ran the job last' AFTER `is_singleton`;
+ALTER TABLE `job_schedule_history`
+ADD COLUMN `placement` json NULL COMMENT 'the instance chosen by the placement of the job' AFTER `override_reason`;
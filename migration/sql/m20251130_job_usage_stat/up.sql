@@ -0,0 +1,13 @@
+CREATE TABLE `job_usage_stat` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `stat_date` date NOT NULL COMMENT 'day the runs ended',
+    `team_id` bigint unsigned NOT NULL DEFAULT '0' COMMENT 'team of the job when the usage was rolled up, 0 for no team',
+    `eid` varchar(100) NOT NULL DEFAULT '' COMMENT 'job eid',
+    `instance_id` varchar(40) NOT NULL DEFAULT '' COMMENT 'instance id',
+    `run_total` int unsigned NOT NULL DEFAULT '0' COMMENT 'runs ended in the day',
+    `runtime_ms` bigint unsigned NOT NULL DEFAULT '0' COMMENT 'sum of the run durations',
+    `updated_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT 'updated time',
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_stat_date_eid_instance_id` (`stat_date`, `eid`, `instance_id`),
+    KEY `idx_team_id_stat_date` (`team_id`, `stat_date`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'daily runtime of the jobs per team and instance, kept after the job is deleted for charge back';
@@ -0,0 +1,2 @@
+ALTER TABLE `instance`
+ADD COLUMN `maintenance` tinyint(1) NOT NULL DEFAULT 0 COMMENT 'draining, the running jobs finish but no new job is dispatched to the instance' AFTER `status`;
@@ -0,0 +1,2 @@
+DROP TABLE `job_pending_dispatch`;
+ALTER TABLE `job` DROP COLUMN `offline_policy`;
@@ -0,0 +1,20 @@
+ALTER TABLE `job`
+ADD COLUMN `offline_policy` json NULL COMMENT 'what happens to the dispatch to an offline instance, fail if empty' AFTER `placement`;
+
+CREATE TABLE `job_pending_dispatch` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `schedule_id` varchar(40) NOT NULL DEFAULT '' COMMENT 'schedule id',
+    `eid` varchar(100) NOT NULL DEFAULT '' COMMENT 'job eid',
+    `instance_id` varchar(40) NOT NULL DEFAULT '' COMMENT 'the offline instance the job was dispatched to',
+    `policy` varchar(20) NOT NULL DEFAULT '' COMMENT 'wait or substitute',
+    `status` varchar(20) NOT NULL DEFAULT '' COMMENT 'waiting, dispatched or expired',
+    `dispatched_instance_id` varchar(40) NOT NULL DEFAULT '' COMMENT 'the instance the job was dispatched to at last, the substitute or the original one',
+    `reason` varchar(500) NOT NULL DEFAULT '' COMMENT 'the last error or how the dispatch was done',
+    `expire_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'the dispatch fails if the instance is still offline after this time',
+    `created_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'created user',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    `updated_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT 'updated time',
+    PRIMARY KEY (`id`),
+    KEY `idx_status` (`status`),
+    KEY `idx_schedule_id` (`schedule_id`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'dispatches to offline instances retried by the offline policy of the job';
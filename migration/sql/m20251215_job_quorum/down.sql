@@ -0,0 +1,7 @@
+ALTER TABLE `job_schedule_history`
+DROP COLUMN `quorum_status`,
+DROP COLUMN `quorum_required`,
+DROP COLUMN `target_total`,
+DROP COLUMN `success_total`,
+DROP COLUMN `failure_total`;
+ALTER TABLE `job` DROP COLUMN `quorum`;
@@ -0,0 +1,9 @@
+ALTER TABLE `job`
+ADD COLUMN `quorum` json NULL COMMENT 'the exec schedule succeeds if enough targets succeed, all of them if empty' AFTER `offline_policy`;
+
+ALTER TABLE `job_schedule_history`
+ADD COLUMN `quorum_status` varchar(20) NOT NULL DEFAULT '' COMMENT 'pending, passed or failed, empty without quorum' AFTER `placement`,
+ADD COLUMN `quorum_required` int unsigned NOT NULL DEFAULT '0' COMMENT 'targets required to succeed' AFTER `quorum_status`,
+ADD COLUMN `target_total` int unsigned NOT NULL DEFAULT '0' COMMENT 'targets of the dispatch' AFTER `quorum_required`,
+ADD COLUMN `success_total` int unsigned NOT NULL DEFAULT '0' COMMENT 'targets succeeded' AFTER `target_total`,
+ADD COLUMN `failure_total` int unsigned NOT NULL DEFAULT '0' COMMENT 'targets failed to dispatch or to run' AFTER `success_total`;
@@ -0,0 +1,4 @@
+DROP TABLE IF EXISTS `agent_config`;
+
+ALTER TABLE `instance`
+DROP COLUMN `agent_config`;
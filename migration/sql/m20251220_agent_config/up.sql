@@ -0,0 +1,15 @@
+CREATE TABLE `agent_config` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `namespace` varchar(40) NOT NULL DEFAULT '' COMMENT 'namespace of the agents',
+    `instance_id` varchar(40) NOT NULL DEFAULT '' COMMENT 'the instance overriding the config of the namespace, empty for the namespace default',
+    `config` json NOT NULL COMMENT 'output_dir, log_level, heartbeat_interval and comet_addr, the unset ones fall back to the flags of the agent',
+    `created_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'created user',
+    `updated_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'updated user',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    `updated_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT 'updated time',
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_namespace_instance` (`namespace`, `instance_id`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'agent config managed by the console';
+
+ALTER TABLE `instance`
+ADD COLUMN `agent_config` json NULL COMMENT 'the config saved by the agent and the settings in effect, reported in heartbeat' AFTER `facts_updated_time`;
@@ -0,0 +1,2 @@
+ALTER TABLE `job`
+ADD COLUMN `confirmation` json NULL COMMENT 'the manual dispatch must be confirmed with the prompted values, dispatched at once if empty' AFTER `quorum`;
@@ -0,0 +1 @@
+ALTER TABLE `job_exec_history` DROP COLUMN `exec_context`;
@@ -0,0 +1,2 @@
+ALTER TABLE `job_exec_history`
+ADD COLUMN `exec_context` json NULL COMMENT 'agent version, executor versions, os and environment hash the run was started with' AFTER `rerun_of`;
@@ -0,0 +1,14 @@
+CREATE TABLE `dashboard` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `name` varchar(100) NOT NULL DEFAULT '' COMMENT 'dashboard name, eg: prod overview',
+    `widgets` json DEFAULT NULL COMMENT 'widgets of the dashboard with their layout and params',
+    `team_id` bigint unsigned NOT NULL DEFAULT 0 COMMENT 'shared with the members of the team, 0 if private',
+    `is_default` tinyint(1) NOT NULL DEFAULT 0 COMMENT 'the home view of the team, or of the creator if private',
+    `created_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'created user',
+    `updated_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'updated user',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    `updated_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT 'updated time',
+    PRIMARY KEY (`id`),
+    KEY `idx_created_user` (`created_user`),
+    KEY `idx_team_id` (`team_id`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'dashboards of the users and the teams';
@@ -0,0 +1,16 @@
+CREATE TABLE `status_page` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `token` varchar(64) NOT NULL DEFAULT '' COMMENT 'path token of the public status page url',
+    `name` varchar(100) NOT NULL DEFAULT '' COMMENT 'title of the status page',
+    `team_id` bigint unsigned NOT NULL DEFAULT 0 COMMENT 'managed by the admins of the team, 0 if private',
+    `eids` json DEFAULT NULL COMMENT 'the daemon and timer jobs shown on the page',
+    `is_enabled` tinyint(1) NOT NULL DEFAULT '1' COMMENT 'the page is not served if disabled',
+    `created_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'created user',
+    `updated_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'updated user',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    `updated_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT 'updated time',
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_token` (`token`),
+    KEY `idx_created_user` (`created_user`),
+    KEY `idx_team_id` (`team_id`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'read-only status pages of the jobs published for the users without console accounts';
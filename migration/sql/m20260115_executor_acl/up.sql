@@ -0,0 +1,11 @@
+CREATE TABLE `executor_acl` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `executor_id` bigint unsigned NOT NULL DEFAULT 0 COMMENT 'executor id',
+    `subject_type` varchar(10) NOT NULL DEFAULT '' COMMENT 'team or role',
+    `subject_id` bigint unsigned NOT NULL DEFAULT 0 COMMENT 'id of the team or the role allowed to use the executor',
+    `updated_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'updated user',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    `updated_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT 'updated time',
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_executor_subject` (`executor_id`, `subject_type`, `subject_id`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'teams and roles allowed to use the executors, an executor without entries can be used by everyone';
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS `code_policy_waiver`;
+DROP TABLE IF EXISTS `code_policy_rule`;
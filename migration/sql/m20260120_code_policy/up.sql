@@ -0,0 +1,27 @@
+CREATE TABLE `code_policy_rule` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `team_id` bigint unsigned NOT NULL DEFAULT 0 COMMENT 'the rule applies to the jobs of the team, 0 if it applies to all the jobs',
+    `name` varchar(50) NOT NULL DEFAULT '' COMMENT 'rule name, replaces the builtin rule of the same name',
+    `pattern` varchar(500) NOT NULL DEFAULT '' COMMENT 'regex matched against every line of the code',
+    `action` varchar(10) NOT NULL DEFAULT 'warn' COMMENT 'warn or reject',
+    `is_enabled` tinyint(1) NOT NULL DEFAULT '1' COMMENT 'a disabled rule turns off the builtin rule of the same name',
+    `info` varchar(500) NOT NULL DEFAULT '' COMMENT 'why the pattern is forbidden',
+    `created_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'created user',
+    `updated_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'updated user',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    `updated_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT 'updated time',
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_team_name` (`team_id`, `name`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'forbidden patterns the job code is scanned for on save';
+
+CREATE TABLE `code_policy_waiver` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `eid` varchar(64) NOT NULL DEFAULT '' COMMENT 'job eid',
+    `rule_name` varchar(50) NOT NULL DEFAULT '' COMMENT 'name of the waived rule',
+    `matched_line` varchar(500) NOT NULL DEFAULT '' COMMENT 'the first line matching the rule',
+    `justification` varchar(500) NOT NULL DEFAULT '' COMMENT 'why the job is saved despite the rejection',
+    `created_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'created user',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    PRIMARY KEY (`id`),
+    KEY `idx_eid` (`eid`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'rejections of the code policy waived on job save';
@@ -0,0 +1,2 @@
+ALTER TABLE `job` DROP COLUMN `timeout_action`;
+ALTER TABLE `job_exec_history` DROP COLUMN `timeout_action`;
@@ -0,0 +1,4 @@
+ALTER TABLE `job`
+ADD COLUMN `timeout_action` json NULL COMMENT 'grace period before SIGKILL, cleanup script and notification when the job times out' AFTER `supervisor_option`;
+ALTER TABLE `job_exec_history`
+ADD COLUMN `timeout_action` varchar(100) NOT NULL DEFAULT '' COMMENT 'the timeout actions fired in order, eg: sigterm,cleanup,notify' AFTER `exec_context`;
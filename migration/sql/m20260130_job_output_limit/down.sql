@@ -0,0 +1,2 @@
+ALTER TABLE `job` DROP COLUMN `output_limit`;
+ALTER TABLE `job_exec_history` DROP COLUMN `output_truncated`, DROP COLUMN `output_url`;
@@ -0,0 +1,5 @@
+ALTER TABLE `job`
+ADD COLUMN `output_limit` json NULL COMMENT 'max bytes of stdout and stderr kept, head and tail retention and the upload url of the full output' AFTER `timeout_action`;
+ALTER TABLE `job_exec_history`
+ADD COLUMN `output_truncated` tinyint(1) NOT NULL DEFAULT 0 COMMENT 'stdout or stderr was cut to the output limit by the agent' AFTER `timeout_action`,
+ADD COLUMN `output_url` varchar(500) NOT NULL DEFAULT '' COMMENT 'where the full output of the truncated run was uploaded' AFTER `output_truncated`;
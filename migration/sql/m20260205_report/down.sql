@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS `report`;
+DROP TABLE IF EXISTS `instance_status_log`;
@@ -0,0 +1,32 @@
+CREATE TABLE `report` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `name` varchar(100) NOT NULL DEFAULT '' COMMENT 'title of the report',
+    `team_id` bigint unsigned NOT NULL DEFAULT 0 COMMENT 'the jobs of the team are reported, all the jobs if 0',
+    `report_type` varchar(50) NOT NULL DEFAULT '' COMMENT 'failure_summary or instance_availability',
+    `format` varchar(10) NOT NULL DEFAULT 'html' COMMENT 'html or pdf',
+    `timer_expr` varchar(100) NOT NULL DEFAULT '' COMMENT 'cron expression of the delivery, eg: 0 0 9 * * Mon',
+    `period_days` int unsigned NOT NULL DEFAULT 7 COMMENT 'days covered by the report, ending at the delivery',
+    `channels` json DEFAULT NULL COMMENT 'webhooks the rendered report is posted to',
+    `is_enabled` tinyint(1) NOT NULL DEFAULT '1' COMMENT 'the report is not delivered if disabled',
+    `next_run_time` timestamp NULL DEFAULT NULL COMMENT 'next delivery time',
+    `last_run_time` timestamp NULL DEFAULT NULL COMMENT 'last delivery time',
+    `last_error` varchar(500) NOT NULL DEFAULT '' COMMENT 'error of the last delivery, empty if delivered',
+    `created_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'created user',
+    `updated_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'updated user',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    `updated_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT 'updated time',
+    PRIMARY KEY (`id`),
+    KEY `idx_team_id` (`team_id`),
+    KEY `idx_next_run_time` (`next_run_time`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'scheduled reports rendered by the leader and delivered to the channels';
+
+CREATE TABLE `instance_status_log` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `instance_id` varchar(40) NOT NULL DEFAULT '' COMMENT 'instance id',
+    `ip` varchar(50) NOT NULL DEFAULT '' COMMENT 'ip of the instance',
+    `namespace` varchar(50) NOT NULL DEFAULT '' COMMENT 'namespace of the instance',
+    `online` tinyint(1) NOT NULL DEFAULT '0' COMMENT 'the instance went online, or offline',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    PRIMARY KEY (`id`),
+    KEY `idx_instance_id_created_time` (`instance_id`, `created_time`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'online and offline changes of the instances, the availability reports are computed from';
@@ -0,0 +1,24 @@
+CREATE TABLE `job_promotion` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `source_eid` varchar(100) NOT NULL DEFAULT '' COMMENT 'eid of the promoted job',
+    `source_team_id` bigint unsigned NOT NULL DEFAULT 0 COMMENT 'team of the promoted job',
+    `source_version` int unsigned NOT NULL DEFAULT 0 COMMENT 'version of the promoted job',
+    `source_namespace` varchar(50) NOT NULL DEFAULT '' COMMENT 'environment promoted from, eg: staging',
+    `target_team_id` bigint unsigned NOT NULL DEFAULT 0 COMMENT 'team the job is promoted to',
+    `target_namespace` varchar(50) NOT NULL DEFAULT '' COMMENT 'environment promoted to, eg: production',
+    `target_eid` varchar(100) NOT NULL DEFAULT '' COMMENT 'eid of the job in the target team, empty until promoted',
+    `tag_rewrites` json DEFAULT NULL COMMENT 'tag prefixes rewritten on promotion',
+    `snapshot` json DEFAULT NULL COMMENT 'the job and its tags at the source version',
+    `required_approvals` int unsigned NOT NULL DEFAULT 1 COMMENT 'approvals required before the job is promoted',
+    `approvals` json DEFAULT NULL COMMENT 'users who approved the promotion',
+    `status` varchar(20) NOT NULL DEFAULT 'pending' COMMENT 'pending, promoted, rejected or failed',
+    `reason` varchar(500) NOT NULL DEFAULT '' COMMENT 'reason of the rejection or the failure',
+    `created_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'created user',
+    `updated_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'updated user',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    `updated_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT 'updated time',
+    PRIMARY KEY (`id`),
+    KEY `idx_source_eid` (`source_eid`),
+    KEY `idx_target_eid` (`target_eid`),
+    KEY `idx_target_team_id_status` (`target_team_id`, `status`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'promotions of the jobs between environments, the lineage of the promoted jobs';
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS `job_sla`;
+DROP TABLE IF EXISTS `job_sla_breach`;
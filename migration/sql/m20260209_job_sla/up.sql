@@ -0,0 +1,31 @@
+CREATE TABLE `job_sla` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `eid` varchar(100) NOT NULL DEFAULT '' COMMENT 'job eid',
+    `max_duration_secs` bigint unsigned NOT NULL DEFAULT 0 COMMENT 'a run taking longer is a breach, 0 is unlimited',
+    `max_failure_streak` int unsigned NOT NULL DEFAULT 0 COMMENT 'so many failed runs in a row is a breach, 0 is unlimited',
+    `min_success_rate` int unsigned NOT NULL DEFAULT 0 COMMENT 'percent of the runs of a day which must succeed, 0 is unlimited',
+    `is_enabled` tinyint(1) NOT NULL DEFAULT '1' COMMENT 'the sla is not evaluated if disabled',
+    `failure_streak` int unsigned NOT NULL DEFAULT 0 COMMENT 'failed runs in a row so far',
+    `last_end_time` timestamp NULL DEFAULT NULL COMMENT 'end time of the last evaluated run',
+    `rate_date` date DEFAULT NULL COMMENT 'last day whose success rate is evaluated',
+    `created_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'created user',
+    `updated_user` varchar(50) NOT NULL DEFAULT '' COMMENT 'updated user',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    `updated_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT 'updated time',
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `uk_eid` (`eid`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'service level agreements of the jobs';
+
+CREATE TABLE `job_sla_breach` (
+    `id` bigint unsigned NOT NULL AUTO_INCREMENT COMMENT 'id',
+    `eid` varchar(100) NOT NULL DEFAULT '' COMMENT 'job eid',
+    `sla_type` varchar(20) NOT NULL DEFAULT '' COMMENT 'duration, failure_streak or success_rate',
+    `run_id` varchar(100) NOT NULL DEFAULT '' COMMENT 'the run breaching the sla, empty for success_rate',
+    `stat_date` date DEFAULT NULL COMMENT 'the day breaching success_rate',
+    `value` bigint unsigned NOT NULL DEFAULT 0 COMMENT 'duration secs, failed runs in a row or success rate percent',
+    `threshold` bigint unsigned NOT NULL DEFAULT 0 COMMENT 'the limit of the sla when breached',
+    `created_time` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
+    PRIMARY KEY (`id`),
+    KEY `idx_eid_created_time` (`eid`, `created_time`),
+    KEY `idx_created_time` (`created_time`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'breaches of the job slas marked by the evaluator';
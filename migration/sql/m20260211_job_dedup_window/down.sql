@@ -0,0 +1 @@
+ALTER TABLE `job` DROP COLUMN `dedup_window_secs`;
@@ -0,0 +1,2 @@
+ALTER TABLE `job`
+ADD COLUMN `dedup_window_secs` int unsigned NOT NULL DEFAULT 0 COMMENT 'the same dispatches within the window are coalesced into the first one, 0 for disabled' AFTER `placement`;
@@ -0,0 +1,5 @@
+ALTER TABLE `job_exec_history`
+DROP KEY `idx_parent_run_id`,
+DROP KEY `idx_run_id`,
+DROP COLUMN `run_kind`,
+DROP COLUMN `parent_run_id`;
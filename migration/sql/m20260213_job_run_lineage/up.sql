@@ -0,0 +1,5 @@
+ALTER TABLE `job_exec_history`
+ADD COLUMN `parent_run_id` varchar(50) NOT NULL DEFAULT '' COMMENT 'the run retried or rerun by this one, empty for the first run' AFTER `run_id`,
+ADD COLUMN `run_kind` varchar(10) NOT NULL DEFAULT '' COMMENT 'retry, rerun, empty for the scheduled or dispatched run' AFTER `parent_run_id`,
+ADD KEY `idx_run_id` (`run_id`),
+ADD KEY `idx_parent_run_id` (`parent_run_id`);
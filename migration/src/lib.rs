@@ -3,6 +3,61 @@ pub use sea_orm_migration::prelude::*;
 mod m20250412_add_job_soft_deleted;
 mod m20250420_modify_job_index;
 mod m20250513_workflow;
+mod m20250601_executor_container;
+mod m20250605_job_http_check;
+mod m20250610_blackout_window;
+mod m20250615_exec_rerun;
+mod m20250620_grant_action_permission;
+mod m20250625_dispatch_stat;
+mod m20250630_instance_facts;
+mod m20250705_server_task;
+mod m20250710_record_version;
+mod m20250715_job_share;
+mod m20250720_instance_overrides;
+mod m20250725_output_parsers;
+mod m20250730_exit_code_mapping;
+mod m20250805_bundle_option;
+mod m20250810_supervisor_option;
+mod m20250815_supervisor_metric;
+mod m20250820_user_timezone;
+mod m20250825_job_trigger;
+mod m20250830_job_remediation;
+mod m20250905_job_duration_anomaly;
+mod m20250910_job_daily_stat;
+mod m20250915_executor_platform_command;
+mod m20250920_executor_install;
+mod m20250925_sftp_edit_log;
+mod m20250930_instance_credential;
+mod m20251005_instance_private_key;
+mod m20251010_instance_bastion;
+mod m20251015_emergency_token;
+mod m20251020_user_admin_lifecycle;
+mod m20251025_user_group;
+mod m20251030_team_quota;
+mod m20251105_saved_filter;
+mod m20251110_job_disabled;
+mod m20251115_tag_hierarchy;
+mod m20251120_job_singleton;
+mod m20251125_job_placement;
+mod m20251130_job_usage_stat;
+mod m20251205_instance_maintenance;
+mod m20251210_job_offline_policy;
+mod m20251215_job_quorum;
+mod m20251220_agent_config;
+mod m20251225_job_confirmation;
+mod m20251230_exec_context;
+mod m20260105_dashboard;
+mod m20260110_status_page;
+mod m20260115_executor_acl;
+mod m20260120_code_policy;
+mod m20260125_job_timeout_action;
+mod m20260130_job_output_limit;
+mod m20260205_report;
+mod m20260207_job_promotion;
+mod m20260209_job_sla;
+mod m20260211_job_dedup_window;
+mod m20260213_job_run_lineage;
+pub mod runner;
 mod v1_0_0_create_table;
 mod v1_1_0_001_create_table;
 mod v1_1_0_002_create_table;
@@ -18,6 +73,60 @@ impl MigratorTrait for Migrator {
             Box::new(m20250412_add_job_soft_deleted::Migration),
             Box::new(m20250420_modify_job_index::Migration),
             Box::new(m20250513_workflow::Migration),
+            Box::new(m20250601_executor_container::Migration),
+            Box::new(m20250605_job_http_check::Migration),
+            Box::new(m20250610_blackout_window::Migration),
+            Box::new(m20250615_exec_rerun::Migration),
+            Box::new(m20250620_grant_action_permission::Migration),
+            Box::new(m20250625_dispatch_stat::Migration),
+            Box::new(m20250630_instance_facts::Migration),
+            Box::new(m20250705_server_task::Migration),
+            Box::new(m20250710_record_version::Migration),
+            Box::new(m20250715_job_share::Migration),
+            Box::new(m20250720_instance_overrides::Migration),
+            Box::new(m20250725_output_parsers::Migration),
+            Box::new(m20250730_exit_code_mapping::Migration),
+            Box::new(m20250805_bundle_option::Migration),
+            Box::new(m20250810_supervisor_option::Migration),
+            Box::new(m20250815_supervisor_metric::Migration),
+            Box::new(m20250820_user_timezone::Migration),
+            Box::new(m20250825_job_trigger::Migration),
+            Box::new(m20250830_job_remediation::Migration),
+            Box::new(m20250905_job_duration_anomaly::Migration),
+            Box::new(m20250910_job_daily_stat::Migration),
+            Box::new(m20250915_executor_platform_command::Migration),
+            Box::new(m20250920_executor_install::Migration),
+            Box::new(m20250925_sftp_edit_log::Migration),
+            Box::new(m20250930_instance_credential::Migration),
+            Box::new(m20251005_instance_private_key::Migration),
+            Box::new(m20251010_instance_bastion::Migration),
+            Box::new(m20251015_emergency_token::Migration),
+            Box::new(m20251020_user_admin_lifecycle::Migration),
+            Box::new(m20251025_user_group::Migration),
+            Box::new(m20251030_team_quota::Migration),
+            Box::new(m20251105_saved_filter::Migration),
+            Box::new(m20251110_job_disabled::Migration),
+            Box::new(m20251115_tag_hierarchy::Migration),
+            Box::new(m20251120_job_singleton::Migration),
+            Box::new(m20251125_job_placement::Migration),
+            Box::new(m20251130_job_usage_stat::Migration),
+            Box::new(m20251205_instance_maintenance::Migration),
+            Box::new(m20251210_job_offline_policy::Migration),
+            Box::new(m20251215_job_quorum::Migration),
+            Box::new(m20251220_agent_config::Migration),
+            Box::new(m20251225_job_confirmation::Migration),
+            Box::new(m20251230_exec_context::Migration),
+            Box::new(m20260105_dashboard::Migration),
+            Box::new(m20260110_status_page::Migration),
+            Box::new(m20260115_executor_acl::Migration),
+            Box::new(m20260120_code_policy::Migration),
+            Box::new(m20260125_job_timeout_action::Migration),
+            Box::new(m20260130_job_output_limit::Migration),
+            Box::new(m20260205_report::Migration),
+            Box::new(m20260207_job_promotion::Migration),
+            Box::new(m20260209_job_sla::Migration),
+            Box::new(m20260211_job_dedup_window::Migration),
+            Box::new(m20260213_job_run_lineage::Migration),
         ]
     }
 }
@@ -0,0 +1,29 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // casbin_rule is created by the casbin adapter after migration on a fresh install,
+        // existing roles keep the dispatch and ssh abilities they had before
+        if !manager.has_table("casbin_rule").await? {
+            return Ok(());
+        }
+        let db = manager.get_connection();
+        let sql = include_str!("../sql/m20250620_grant_action_permission/up.sql");
+        db.execute_unprepared(sql).await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if !manager.has_table("casbin_rule").await? {
+            return Ok(());
+        }
+        let db = manager.get_connection();
+        let sql = include_str!("../sql/m20250620_grant_action_permission/down.sql");
+        db.execute_unprepared(sql).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,184 @@
+use std::time::Duration;
+
+use sea_orm_migration::{
+    MigratorTrait,
+    sea_orm::{ConnectionTrait, DatabaseConnection, DbErr, Statement, TransactionTrait},
+};
+
+use tracing::warn;
+
+use crate::Migrator;
+
+/// mysql advisory lock held while migrating, the replicas starting together migrate one by one
+const LOCK_NAME: &str = "jiascheduler:migration";
+
+/// migration not applied yet, with the sql it runs
+pub struct PendingMigration {
+    pub name: String,
+    pub sql: &'static str,
+}
+
+pub struct AppliedMigration {
+    pub name: String,
+    /// unix timestamp
+    pub applied_at: i64,
+}
+
+/// up sql of the migrations, printed by the dry-run
+fn up_sql(name: &str) -> Option<&'static str> {
+    macro_rules! sql {
+        ($dir:literal) => {
+            include_str!(concat!("../sql/", $dir, "/up.sql"))
+        };
+    }
+    Some(match name {
+        "v1_0_0_create_table" => sql!("v1_0_0"),
+        "v1_1_0_001_create_table" => sql!("v1_1_0_001"),
+        "v1_1_0_002_create_table" => sql!("v1_1_0_002"),
+        "m20250412_add_job_soft_deleted" => sql!("m20250412_add_job_soft_deleted"),
+        "m20250420_modify_job_index" => sql!("m20250420_modify_job_index"),
+        "m20250513_workflow" => sql!("m20250513_workflow"),
+        "m20250601_executor_container" => sql!("m20250601_executor_container"),
+        "m20250605_job_http_check" => sql!("m20250605_job_http_check"),
+        "m20250610_blackout_window" => sql!("m20250610_blackout_window"),
+        "m20250615_exec_rerun" => sql!("m20250615_exec_rerun"),
+        "m20250620_grant_action_permission" => sql!("m20250620_grant_action_permission"),
+        "m20250625_dispatch_stat" => sql!("m20250625_dispatch_stat"),
+        "m20250630_instance_facts" => sql!("m20250630_instance_facts"),
+        "m20250705_server_task" => sql!("m20250705_server_task"),
+        "m20250710_record_version" => sql!("m20250710_record_version"),
+        "m20250715_job_share" => sql!("m20250715_job_share"),
+        "m20250720_instance_overrides" => sql!("m20250720_instance_overrides"),
+        "m20250725_output_parsers" => sql!("m20250725_output_parsers"),
+        "m20250730_exit_code_mapping" => sql!("m20250730_exit_code_mapping"),
+        "m20250805_bundle_option" => sql!("m20250805_bundle_option"),
+        "m20250810_supervisor_option" => sql!("m20250810_supervisor_option"),
+        "m20250815_supervisor_metric" => sql!("m20250815_supervisor_metric"),
+        "m20250820_user_timezone" => sql!("m20250820_user_timezone"),
+        "m20250825_job_trigger" => sql!("m20250825_job_trigger"),
+        "m20250830_job_remediation" => sql!("m20250830_job_remediation"),
+        "m20250905_job_duration_anomaly" => sql!("m20250905_job_duration_anomaly"),
+        "m20250910_job_daily_stat" => sql!("m20250910_job_daily_stat"),
+        "m20250915_executor_platform_command" => sql!("m20250915_executor_platform_command"),
+        "m20250920_executor_install" => sql!("m20250920_executor_install"),
+        "m20250925_sftp_edit_log" => sql!("m20250925_sftp_edit_log"),
+        "m20250930_instance_credential" => sql!("m20250930_instance_credential"),
+        "m20251005_instance_private_key" => sql!("m20251005_instance_private_key"),
+        "m20251010_instance_bastion" => sql!("m20251010_instance_bastion"),
+        "m20251015_emergency_token" => sql!("m20251015_emergency_token"),
+        "m20251020_user_admin_lifecycle" => sql!("m20251020_user_admin_lifecycle"),
+        "m20251025_user_group" => sql!("m20251025_user_group"),
+        "m20251030_team_quota" => sql!("m20251030_team_quota"),
+        "m20251105_saved_filter" => sql!("m20251105_saved_filter"),
+        "m20251110_job_disabled" => sql!("m20251110_job_disabled"),
+        "m20251115_tag_hierarchy" => sql!("m20251115_tag_hierarchy"),
+        "m20251120_job_singleton" => sql!("m20251120_job_singleton"),
+        "m20251125_job_placement" => sql!("m20251125_job_placement"),
+        "m20251130_job_usage_stat" => sql!("m20251130_job_usage_stat"),
+        "m20251205_instance_maintenance" => sql!("m20251205_instance_maintenance"),
+        "m20251210_job_offline_policy" => sql!("m20251210_job_offline_policy"),
+        "m20251215_job_quorum" => sql!("m20251215_job_quorum"),
+        "m20251220_agent_config" => sql!("m20251220_agent_config"),
+        "m20251225_job_confirmation" => sql!("m20251225_job_confirmation"),
+        "m20251230_exec_context" => sql!("m20251230_exec_context"),
+        "m20260105_dashboard" => sql!("m20260105_dashboard"),
+        "m20260110_status_page" => sql!("m20260110_status_page"),
+        "m20260115_executor_acl" => sql!("m20260115_executor_acl"),
+        "m20260120_code_policy" => sql!("m20260120_code_policy"),
+        "m20260125_job_timeout_action" => sql!("m20260125_job_timeout_action"),
+        "m20260130_job_output_limit" => sql!("m20260130_job_output_limit"),
+        "m20260205_report" => sql!("m20260205_report"),
+        "m20260207_job_promotion" => sql!("m20260207_job_promotion"),
+        "m20260209_job_sla" => sql!("m20260209_job_sla"),
+        "m20260211_job_dedup_window" => sql!("m20260211_job_dedup_window"),
+        "m20260213_job_run_lineage" => sql!("m20260213_job_run_lineage"),
+        _ => return None,
+    })
+}
+
+/// apply the pending migrations holding the migration lock, returns the applied names.
+/// the replicas waiting for the lock find nothing pending once the holder is done
+pub async fn up_locked(db: &DatabaseConnection, wait: Duration) -> Result<Vec<String>, DbErr> {
+    let backend = db.get_database_backend();
+    // GET_LOCK is bound to the session, the transaction pins one connection of the pool
+    let session = db.begin().await?;
+    let locked = session
+        .query_one(Statement::from_sql_and_values(
+            backend,
+            "SELECT GET_LOCK(?, ?)",
+            [LOCK_NAME.into(), wait.as_secs().into()],
+        ))
+        .await?
+        .map(|v| v.try_get_by_index::<Option<i64>>(0))
+        .transpose()?
+        .flatten();
+    if locked != Some(1) {
+        return Err(DbErr::Custom(format!(
+            "timeout waiting for the migration lock {LOCK_NAME}"
+        )));
+    }
+
+    let ret = async {
+        let pending: Vec<String> = Migrator::get_pending_migrations(db)
+            .await?
+            .iter()
+            .map(|v| v.name().to_string())
+            .collect();
+        if !pending.is_empty() {
+            Migrator::up(db, None).await?;
+        }
+        Ok::<_, DbErr>(pending)
+    }
+    .await;
+
+    // the lock is released whatever the result is, a failure to release it is only logged
+    // since the lock goes away with the session anyway
+    if let Err(e) = session
+        .query_one(Statement::from_sql_and_values(
+            backend,
+            "SELECT RELEASE_LOCK(?)",
+            [LOCK_NAME.into()],
+        ))
+        .await
+    {
+        warn!("failed to release the migration lock {LOCK_NAME} - {e}");
+    }
+    if let Err(e) = session.commit().await {
+        warn!("failed to end the migration lock session - {e}");
+    }
+    ret
+}
+
+pub async fn pending(db: &DatabaseConnection) -> Result<Vec<PendingMigration>, DbErr> {
+    Ok(Migrator::get_pending_migrations(db)
+        .await?
+        .iter()
+        .map(|v| PendingMigration {
+            name: v.name().to_string(),
+            sql: up_sql(v.name()).unwrap_or_default(),
+        })
+        .collect())
+}
+
+pub async fn applied(db: &DatabaseConnection) -> Result<Vec<AppliedMigration>, DbErr> {
+    Ok(Migrator::get_migration_models(db)
+        .await?
+        .into_iter()
+        .map(|v| AppliedMigration {
+            name: v.version,
+            applied_at: v.applied_at,
+        })
+        .collect())
+}
+
+#[test]
+fn test_up_sql() {
+    for migration in Migrator::migrations() {
+        assert!(
+            up_sql(migration.name()).is_some(),
+            "missing up sql of {}",
+            migration.name()
+        );
+    }
+    assert!(up_sql("unknown").is_none());
+}
@@ -1,13 +1,21 @@
+pub mod blackout;
+pub mod event;
 pub mod executor;
 pub mod file;
 pub mod instance;
 pub mod job;
 pub mod manage;
 pub mod migration;
+pub mod promotion;
+pub mod report;
 pub mod role;
+pub mod sla;
+pub mod status_page;
 pub mod tag;
+pub mod task;
 pub mod team;
 pub mod terminal;
+pub mod trigger;
 pub mod types;
 pub mod user;
 pub mod workflow;
@@ -33,6 +41,34 @@ pub fn default_option_page_size() -> Option<u64> {
     Some(20)
 }
 
+/// versions of the web api, handlers whose behavior differs between
+/// versions can read it by Data<&ApiVersion>
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ApiVersion {
+    V1,
+    V2,
+}
+
+impl ApiVersion {
+    pub const ALL: [ApiVersion; 2] = [ApiVersion::V1, ApiVersion::V2];
+
+    pub fn prefix(self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "/api/v1",
+            ApiVersion::V2 => "/api/v2",
+        }
+    }
+}
+
+impl Display for ApiVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiVersion::V1 => f.write_str("v1"),
+            ApiVersion::V2 => f.write_str("v2"),
+        }
+    }
+}
+
 #[derive(Tags)]
 pub enum Tag {
     User,
@@ -46,6 +82,11 @@ pub enum Tag {
     Migration,
     Tag,
     Workflow,
+    Blackout,
+    Task,
+    Report,
+    Promotion,
+    Sla,
 }
 
 pub struct OneOfValidator(Vec<String>);
@@ -0,0 +1,302 @@
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use poem::{web::Data, Endpoint, EndpointExt};
+use poem_openapi::{
+    param::{Header, Query},
+    payload::Json,
+    OpenApi,
+};
+use sea_orm::{ActiveValue::NotSet, Set};
+
+use crate::{
+    api_response,
+    entity::blackout_window,
+    error::NoPermission,
+    local_time,
+    logic::{self, role::POLICY_ALLOW_CHANGE_ALL_JOB},
+    middleware,
+    response::std_into_error,
+    return_err, return_ok,
+    state::AppState,
+};
+
+pub mod types {
+    use poem_openapi::Object;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct SaveBlackoutWindowReq {
+        pub id: Option<u64>,
+        #[oai(validator(min_length = 1, max_length = 100))]
+        pub name: String,
+        /// set true to create a global window, otherwise the window belongs to the current team
+        #[oai(default)]
+        pub is_global: bool,
+        #[oai(validator(
+            custom = "crate::api::OneOfValidator::new(vec![\"once\", \"recurring\"])"
+        ))]
+        pub window_type: String,
+        /// required for once window, eg: 2025-06-01 00:00:00
+        pub start_time: Option<String>,
+        pub end_time: Option<String>,
+        /// days from sunday, 0-6, every day if empty
+        #[oai(default)]
+        pub weekdays: Vec<u8>,
+        /// HH:MM, required for recurring window
+        #[oai(default)]
+        pub start_clock: String,
+        #[oai(default)]
+        pub end_clock: String,
+        #[oai(
+            default = "default_timezone",
+            validator(custom = "crate::api::OneOfValidator::new(vec![\"utc\", \"local\"])")
+        )]
+        pub timezone: String,
+        #[oai(default = "default_enabled")]
+        pub is_enabled: bool,
+        #[oai(default)]
+        pub info: String,
+    }
+
+    fn default_timezone() -> String {
+        "local".to_string()
+    }
+
+    fn default_enabled() -> bool {
+        true
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct SaveBlackoutWindowResp {
+        pub result: u64,
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct DeleteBlackoutWindowReq {
+        pub id: u64,
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct DeleteBlackoutWindowResp {
+        pub result: u64,
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct QueryBlackoutWindowResp {
+        pub total: u64,
+        pub list: Vec<BlackoutWindowRecord>,
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct BlackoutWindowRecord {
+        pub id: u64,
+        pub name: String,
+        pub team_id: u64,
+        pub window_type: String,
+        pub start_time: Option<String>,
+        pub end_time: Option<String>,
+        pub weekdays: Vec<u8>,
+        pub start_clock: String,
+        pub end_clock: String,
+        pub timezone: String,
+        pub is_enabled: bool,
+        pub is_active: bool,
+        pub info: String,
+        pub created_user: String,
+        pub updated_user: String,
+        pub created_time: String,
+        pub updated_time: String,
+    }
+}
+
+fn set_middleware(ep: impl Endpoint) -> impl Endpoint {
+    ep.with(middleware::TeamPermissionMiddleware)
+        .with(middleware::PermissionMiddleware::grant(
+            &POLICY_ALLOW_CHANGE_ALL_JOB,
+        ))
+}
+
+fn parse_local_time(v: &str) -> anyhow::Result<DateTime<Local>> {
+    let t = NaiveDateTime::parse_from_str(v, "%Y-%m-%d %H:%M:%S")?;
+    Local
+        .from_local_datetime(&t)
+        .single()
+        .ok_or(anyhow::anyhow!("invalid local time {v}"))
+}
+
+pub struct BlackoutApi;
+
+#[OpenApi(prefix_path = "/blackout", tag = super::Tag::Blackout)]
+impl BlackoutApi {
+    #[oai(path = "/save", method = "post", transform = "set_middleware")]
+    pub async fn save_window(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Json(req): Json<types::SaveBlackoutWindowReq>,
+    ) -> api_response!(types::SaveBlackoutWindowResp) {
+        let svc = state.service();
+        let team_id = if req.is_global { None } else { team_id };
+
+        let can_write = match team_id {
+            Some(_) => {
+                svc.team
+                    .can_write_team(team_id, user_info.user_id.clone())
+                    .await?
+            }
+            None => granted.manage_job(),
+        };
+        if !can_write {
+            return Err(NoPermission().into());
+        }
+
+        let (start_time, end_time) = match req.window_type.as_str() {
+            "once" => {
+                let (Some(start_time), Some(end_time)) = (req.start_time, req.end_time) else {
+                    return_err!("start_time and end_time are required for once window");
+                };
+                let start_time = parse_local_time(&start_time)?;
+                let end_time = parse_local_time(&end_time)?;
+                if start_time >= end_time {
+                    return_err!("end_time must be later than start_time");
+                }
+                (Some(start_time), Some(end_time))
+            }
+            _ => {
+                if chrono::NaiveTime::parse_from_str(&req.start_clock, "%H:%M").is_err()
+                    || chrono::NaiveTime::parse_from_str(&req.end_clock, "%H:%M").is_err()
+                {
+                    return_err!("start_clock and end_clock must be in HH:MM format");
+                }
+                if req.weekdays.iter().any(|v| *v > 6) {
+                    return_err!("weekdays must be in 0-6");
+                }
+                (None, None)
+            }
+        };
+
+        if let Some(id) = req.id {
+            let Some(record) = svc.blackout.get_window(id).await? else {
+                return_err!("cannot found blackout window");
+            };
+            if record.team_id != team_id.unwrap_or_default() {
+                return Err(NoPermission().into());
+            }
+        }
+
+        let ret = svc
+            .blackout
+            .save_window(blackout_window::ActiveModel {
+                id: req.id.filter(|v| *v != 0).map_or(NotSet, |v| Set(v)),
+                name: Set(req.name),
+                team_id: Set(team_id.unwrap_or_default()),
+                window_type: Set(req.window_type),
+                start_time: Set(start_time),
+                end_time: Set(end_time),
+                weekdays: Set(Some(
+                    serde_json::to_value(req.weekdays).map_err(std_into_error)?,
+                )),
+                start_clock: Set(req.start_clock),
+                end_clock: Set(req.end_clock),
+                timezone: Set(req.timezone),
+                is_enabled: Set(req.is_enabled),
+                info: Set(req.info),
+                created_user: req.id.map_or(Set(user_info.username.clone()), |_| NotSet),
+                updated_user: Set(user_info.username.clone()),
+                ..Default::default()
+            })
+            .await?;
+
+        return_ok!(types::SaveBlackoutWindowResp {
+            result: ret.id.as_ref().to_owned()
+        })
+    }
+
+    #[oai(path = "/list", method = "get", transform = "set_middleware")]
+    pub async fn query_window(
+        &self,
+        state: Data<&AppState>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        #[oai(default)] Query(name): Query<Option<String>>,
+        #[oai(default = "super::default_page", validator(maximum(value = "10000")))]
+        Query(page): Query<u64>,
+        #[oai(
+            default = "super::default_page_size",
+            validator(maximum(value = "10000"))
+        )]
+        Query(page_size): Query<u64>,
+    ) -> api_response!(types::QueryBlackoutWindowResp) {
+        let svc = state.service();
+        let ret = svc
+            .blackout
+            .query_window(
+                team_id.or(Some(0)),
+                name.filter(|v| v != ""),
+                page - 1,
+                page_size,
+            )
+            .await?;
+
+        let now = chrono::Utc::now();
+        let mut list = Vec::new();
+        for v in ret.0 {
+            let is_active =
+                v.is_enabled && logic::blackout::BlackoutLogic::to_agent_window(&v)?.contains(now);
+            list.push(types::BlackoutWindowRecord {
+                id: v.id,
+                name: v.name,
+                team_id: v.team_id,
+                window_type: v.window_type,
+                start_time: v.start_time.map(|v| local_time!(v)),
+                end_time: v.end_time.map(|v| local_time!(v)),
+                weekdays: v
+                    .weekdays
+                    .map(|v| serde_json::from_value(v))
+                    .transpose()
+                    .map_err(std_into_error)?
+                    .unwrap_or_default(),
+                start_clock: v.start_clock,
+                end_clock: v.end_clock,
+                timezone: v.timezone,
+                is_enabled: v.is_enabled,
+                is_active,
+                info: v.info,
+                created_user: v.created_user,
+                updated_user: v.updated_user,
+                created_time: local_time!(v.created_time),
+                updated_time: local_time!(v.updated_time),
+            });
+        }
+
+        return_ok!(types::QueryBlackoutWindowResp { total: ret.1, list })
+    }
+
+    #[oai(path = "/delete", method = "post", transform = "set_middleware")]
+    pub async fn delete_window(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
+        Json(req): Json<types::DeleteBlackoutWindowReq>,
+    ) -> api_response!(types::DeleteBlackoutWindowResp) {
+        let svc = state.service();
+        let Some(record) = svc.blackout.get_window(req.id).await? else {
+            return_err!("cannot found blackout window");
+        };
+
+        let can_write = if record.team_id == 0 {
+            granted.manage_job()
+        } else {
+            svc.team
+                .can_write_team(Some(record.team_id), user_info.user_id.clone())
+                .await?
+        };
+        if !can_write {
+            return Err(NoPermission().into());
+        }
+
+        let result = svc.blackout.delete_window(req.id).await?;
+        return_ok!(types::DeleteBlackoutWindowResp { result })
+    }
+}
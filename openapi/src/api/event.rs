@@ -0,0 +1,242 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use futures::{stream, SinkExt, StreamExt};
+use poem::{
+    handler,
+    web::{
+        sse::{Event as SseEvent, SSE},
+        websocket::{Message, WebSocket},
+        Data, Query,
+    },
+    IntoResponse, Result,
+};
+use serde::Deserialize;
+use service::event::live::LiveEvent;
+use tracing::{error, warn};
+
+use super::types;
+use crate::{
+    logic::types::UserInfo,
+    response::{std_into_error, ApiStdResponse},
+    return_ok, AppState,
+};
+
+/// the visibility checked for a job or an instance is reused this long by the connection
+const VISIBILITY_TTL: Duration = Duration::from_secs(60);
+/// the proxies close the idle connections, a comment is sent to keep the stream open
+const SSE_KEEP_ALIVE: Duration = Duration::from_secs(15);
+/// a poll without any event returns after this long
+const POLL_TIMEOUT: Duration = Duration::from_secs(25);
+
+#[derive(Deserialize)]
+pub struct EventQuery {
+    /// only the events of the jobs of the team
+    pub team_id: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct PollQuery {
+    pub team_id: Option<u64>,
+    /// the cursor returned by the previous poll, the first poll starts from the latest event
+    pub cursor: Option<String>,
+}
+
+/// the jobs and the instances the subscriber can see, the same as in the list endpoints
+struct EventFilter {
+    team_id: Option<u64>,
+    jobs: HashMap<String, bool>,
+    instances: HashMap<String, bool>,
+    reset_at: Instant,
+}
+
+impl EventFilter {
+    fn new(team_id: Option<u64>) -> Self {
+        Self {
+            team_id,
+            jobs: HashMap::new(),
+            instances: HashMap::new(),
+            reset_at: Instant::now(),
+        }
+    }
+
+    async fn is_visible(
+        &mut self,
+        state: &AppState,
+        user_info: &UserInfo,
+        event: &LiveEvent,
+    ) -> anyhow::Result<bool> {
+        if self.reset_at.elapsed() > VISIBILITY_TTL {
+            self.jobs.clear();
+            self.instances.clear();
+            self.reset_at = Instant::now();
+        }
+
+        let svc = state.service();
+        if let Some(eid) = event.eid() {
+            if let Some(v) = self.jobs.get(eid) {
+                return Ok(*v);
+            }
+            let v = svc
+                .job
+                .can_write_job(user_info, self.team_id, Some(eid.to_string()))
+                .await?;
+            self.jobs.insert(eid.to_string(), v);
+            return Ok(v);
+        }
+
+        let instance_id = event.instance_id();
+        if let Some(v) = self.instances.get(instance_id) {
+            return Ok(*v);
+        }
+        let v = svc
+            .instance
+            .get_one_user_server_with_permission(state.clone(), user_info, instance_id.to_string())
+            .await?
+            .is_some();
+        self.instances.insert(instance_id.to_string(), v);
+        Ok(v)
+    }
+}
+
+/// live updates of the console: the run status changes, the completed runs and the
+/// instances going online or offline, the ones the user cannot see are filtered out
+#[handler]
+pub async fn events(
+    state: Data<&AppState>,
+    user_info: Data<&UserInfo>,
+    Query(EventQuery { team_id }): Query<EventQuery>,
+    ws: WebSocket,
+) -> impl IntoResponse {
+    let state = state.clone();
+    let user_info = user_info.clone();
+
+    ws.on_upgrade(move |socket| async move {
+        let (mut sink, mut stream) = socket.split();
+
+        let events = match state.live.subscribe().await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("failed subscribe events - {e}");
+                let _ = sink
+                    .send(Message::Text(
+                        serde_json::json!({"event": "error", "msg": e.to_string()}).to_string(),
+                    ))
+                    .await;
+                return;
+            }
+        };
+        let mut events = Box::pin(events);
+        let mut filter = EventFilter::new(team_id);
+
+        loop {
+            tokio::select! {
+                event = events.next() => {
+                    let Some(event) = event else {
+                        break;
+                    };
+                    match filter.is_visible(&state, &user_info, &event).await {
+                        Ok(true) => {}
+                        Ok(false) => continue,
+                        Err(e) => {
+                            warn!("failed check the visibility of the event - {e}");
+                            continue;
+                        }
+                    }
+                    let Ok(text) = serde_json::to_string(&event) else {
+                        continue;
+                    };
+                    if sink.send(Message::Text(text)).await.is_err() {
+                        break;
+                    }
+                }
+                msg = stream.next() => match msg {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    _ => {}
+                },
+            }
+        }
+    })
+}
+
+/// the event stream for the clients behind the proxies blocking the websockets,
+/// the type of the server-sent event is the name of the event
+#[handler]
+pub async fn sse_events(
+    state: Data<&AppState>,
+    user_info: Data<&UserInfo>,
+    Query(EventQuery { team_id }): Query<EventQuery>,
+) -> Result<SSE> {
+    let live = Box::pin(state.live.subscribe().await?);
+    let filter = EventFilter::new(team_id);
+
+    let stream = stream::unfold(
+        (live, filter, state.clone(), user_info.clone()),
+        |(mut live, mut filter, state, user_info)| async move {
+            loop {
+                let event = live.next().await?;
+                match filter.is_visible(&state, &user_info, &event).await {
+                    Ok(true) => {}
+                    Ok(false) => continue,
+                    Err(e) => {
+                        warn!("failed check the visibility of the event - {e}");
+                        continue;
+                    }
+                }
+                let Ok(data) = serde_json::to_string(&event) else {
+                    continue;
+                };
+                return Some((
+                    SseEvent::message(data).event_type(event.name()),
+                    (live, filter, state, user_info),
+                ));
+            }
+        },
+    );
+    Ok(SSE::new(stream).keep_alive(SSE_KEEP_ALIVE))
+}
+
+/// long polling fallback of the event stream, the events after the cursor are returned as
+/// soon as there is one, or none after POLL_TIMEOUT. the returned cursor is passed to the next poll
+#[handler]
+pub async fn poll_events(
+    state: Data<&AppState>,
+    user_info: Data<&UserInfo>,
+    Query(PollQuery { team_id, cursor }): Query<PollQuery>,
+) -> Result<ApiStdResponse<types::PollEventsResp>> {
+    let mut cursor = match cursor.filter(|v| !v.is_empty()) {
+        Some(v) => v,
+        None => state.live.last_id().await?,
+    };
+    let mut filter = EventFilter::new(team_id);
+    let deadline = Instant::now() + POLL_TIMEOUT;
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        // a block of 0 waits forever
+        if remaining.as_millis() == 0 {
+            break;
+        }
+        let (next_cursor, list) = state.live.read(&cursor, remaining).await?;
+        cursor = next_cursor;
+
+        let mut visible = Vec::new();
+        for event in list {
+            if filter.is_visible(&state, &user_info, &event).await? {
+                visible.push(serde_json::to_value(event).map_err(std_into_error)?);
+            }
+        }
+        if !visible.is_empty() {
+            return_ok!(types::PollEventsResp {
+                cursor,
+                events: visible,
+            });
+        }
+    }
+    return_ok!(types::PollEventsResp {
+        cursor,
+        events: vec![],
+    })
+}
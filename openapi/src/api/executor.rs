@@ -1,11 +1,17 @@
-use crate::{entity::executor, local_time, logic, response::ApiStdResponse, return_ok, AppState};
+use crate::{
+    entity::executor,
+    error::NoPermission,
+    local_time, logic,
+    response::{std_into_error, ApiStdResponse},
+    return_err, return_ok, AppState,
+};
 use poem::{session::Session, web::Data, Result};
 use poem_openapi::{param::Query, payload::Json, OpenApi};
 use sea_orm::{ActiveValue::NotSet, Set};
 
 mod types {
     use poem_openapi::Object;
-    use serde::Serialize;
+    use serde::{Deserialize, Serialize};
 
     #[derive(Object, Serialize, Default)]
     pub struct DeleteExecutorReq {
@@ -21,6 +27,56 @@ mod types {
         pub platform: String,
         pub info: String,
         pub read_code_from_stdin: Option<bool>,
+        pub container_image: Option<String>,
+        pub container_options: Option<ContainerOptions>,
+        pub platform_commands: Option<PlatformCommands>,
+        /// eg: 1.2.0, the instances with an older agent cannot run the executor
+        pub min_agent_version: Option<String>,
+        /// run by sh -c, or powershell -Command on windows, to install the executor on the instances
+        pub install_script: Option<String>,
+    }
+
+    /// command for each os, the command of the executor is used if it is empty
+    #[derive(Object, Serialize, Deserialize, Default)]
+    pub struct PlatformCommands {
+        pub linux: Option<String>,
+        pub windows: Option<String>,
+        pub darwin: Option<String>,
+    }
+
+    impl From<PlatformCommands> for crate::logic::types::PlatformCommands {
+        fn from(value: PlatformCommands) -> Self {
+            Self {
+                linux: value.linux,
+                windows: value.windows,
+                darwin: value.darwin,
+            }
+        }
+    }
+
+    #[derive(Object, Serialize, Deserialize, Default)]
+    pub struct ContainerOptions {
+        /// docker or podman, detected by the agent if empty
+        pub runtime: Option<String>,
+        /// cpu limit, eg: 0.5
+        pub cpus: Option<String>,
+        /// memory limit, eg: 512m
+        pub memory: Option<String>,
+        pub network: Option<String>,
+        #[oai(default)]
+        pub extra_args: Vec<String>,
+    }
+
+    impl From<ContainerOptions> for crate::logic::types::ContainerOptions {
+        fn from(value: ContainerOptions) -> Self {
+            Self {
+                runtime: value.runtime,
+                cpus: value.cpus,
+                memory: value.memory,
+                network: value.network,
+                extra_args: value.extra_args,
+            }
+        }
     }
 
     #[derive(Object, Serialize, Default)]
@@ -28,6 +84,21 @@ mod types {
         pub result: u64,
     }
 
+    /// teams and roles allowed to use the executor, everyone can use it if both are empty
+    #[derive(Object, Serialize, Deserialize, Default)]
+    pub struct ExecutorAcl {
+        pub executor_id: u64,
+        #[oai(default)]
+        pub team_ids: Vec<u64>,
+        #[oai(default)]
+        pub role_ids: Vec<u64>,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct SaveExecutorAclResp {
+        pub affected: u64,
+    }
+
     #[derive(Object, Serialize, Default)]
     pub struct QueryExecutorResp {
         pub total: u64,
@@ -41,6 +112,11 @@ mod types {
         pub command: String,
         pub platform: String,
         pub info: String,
+        pub container_image: String,
+        pub container_options: Option<serde_json::Value>,
+        pub platform_commands: Option<serde_json::Value>,
+        pub min_agent_version: String,
+        pub install_script: Option<String>,
         pub created_time: String,
         pub updated_time: String,
     }
@@ -71,6 +147,35 @@ impl ExecutorApi {
     ) -> Result<ApiStdResponse<types::SaveExecutorRes>> {
         let svc = state.service();
 
+        if req.platform == logic::executor::ExecutorLogic::PLATFORM_CONTAINER
+            && req.container_image.as_ref().is_none_or(|v| v.is_empty())
+        {
+            return_err!("container image is required for container platform");
+        }
+
+        let container_options = match req.container_options {
+            Some(v) => Some(
+                serde_json::to_value(logic::types::ContainerOptions::from(v))
+                    .map_err(std_into_error)?,
+            ),
+            None => None,
+        };
+
+        let min_agent_version = req.min_agent_version.unwrap_or_default();
+        if !min_agent_version.is_empty()
+            && logic::executor::ExecutorLogic::parse_version(&min_agent_version).is_none()
+        {
+            return_err!("invalid minimum agent version");
+        }
+
+        let platform_commands = match req.platform_commands {
+            Some(v) => Some(
+                serde_json::to_value(logic::types::PlatformCommands::from(v))
+                    .map_err(std_into_error)?,
+            ),
+            None => None,
+        };
+
         let ret = svc
             .executor
             .save_executor(executor::ActiveModel {
@@ -83,6 +188,11 @@ impl ExecutorApi {
                     true => 1,
                     false => 0,
                 })),
+                container_image: Set(req.container_image.unwrap_or_default()),
+                container_options: Set(container_options),
+                platform_commands: Set(platform_commands),
+                min_agent_version: Set(min_agent_version),
+                install_script: Set(req.install_script.filter(|v| !v.trim().is_empty())),
                 created_user: Set(user_info.username.clone()),
                 updated_user: Set(user_info.username.clone()),
                 ..Default::default()
@@ -131,6 +241,11 @@ impl ExecutorApi {
                 command: v.command,
                 platform: v.platform,
                 info: v.info,
+                container_image: v.container_image,
+                container_options: v.container_options,
+                platform_commands: v.platform_commands,
+                min_agent_version: v.min_agent_version,
+                install_script: v.install_script,
                 created_time: local_time!(v.created_time),
                 updated_time: local_time!(v.updated_time),
             })
@@ -140,4 +255,48 @@ impl ExecutorApi {
             list: list,
         })
     }
+
+    #[oai(path = "/acl", method = "get")]
+    pub async fn get_executor_acl(
+        &self,
+        state: Data<&AppState>,
+        Query(executor_id): Query<u64>,
+    ) -> Result<ApiStdResponse<types::ExecutorAcl>> {
+        let acl = state.service().executor.get_acl(executor_id).await?;
+        let ids = |subject_type: &str| -> Vec<u64> {
+            acl.iter()
+                .filter(|v| v.subject_type == subject_type)
+                .map(|v| v.subject_id)
+                .collect()
+        };
+        return_ok!(types::ExecutorAcl {
+            executor_id,
+            team_ids: ids(logic::executor::ACL_SUBJECT_TEAM),
+            role_ids: ids(logic::executor::ACL_SUBJECT_ROLE),
+        })
+    }
+
+    #[oai(path = "/acl/save", method = "post")]
+    pub async fn save_executor_acl(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::ExecutorAcl>,
+    ) -> Result<ApiStdResponse<types::SaveExecutorAclResp>> {
+        if !state.can_manage_user(&user_info.user_id).await? {
+            return Err(NoPermission().into());
+        }
+
+        let affected = state
+            .service()
+            .executor
+            .set_acl(
+                req.executor_id,
+                req.team_ids,
+                req.role_ids,
+                user_info.username.clone(),
+            )
+            .await?;
+        return_ok!(types::SaveExecutorAclResp { affected })
+    }
 }
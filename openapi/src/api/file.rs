@@ -6,7 +6,7 @@ use std::{
 use anyhow::anyhow;
 
 use chrono::{DateTime, Utc};
-use poem::{session::Session, web::Data, Result};
+use poem::{session::Session, web::Data, Endpoint, EndpointExt, Result};
 use poem_openapi::{
     param::{Path, Query},
     payload::{Attachment, AttachmentType, Json, PlainText},
@@ -18,15 +18,18 @@ use tokio::{
 };
 
 use crate::{
-    error::NoPermission,
     local_time,
     logic::{
         self,
-        ssh::{ConnectParams, Session as SshSession},
+        credential::SCOPE_SFTP,
+        role::{POLICY_ALLOW_CHANGE_ALL_JOB, POLICY_ALLOW_SSH_INSTANCE, POLICY_ALLOW_UPLOAD_FILE},
+        ssh::Session as SshSession,
     },
+    middleware,
     response::{std_into_error, ApiStdResponse},
     return_err, return_ok, AppState,
 };
+use service::logic::{job::EXPORT_DIR, task::STATUS_SUCCEEDED};
 
 pub mod types {
     use poem_openapi::{
@@ -39,6 +42,8 @@ pub mod types {
     #[derive(Debug, Multipart)]
     pub struct UploadPayload {
         pub file: Upload,
+        /// the file counts against the upload quota of the team
+        pub team_id: Option<u64>,
     }
 
     #[derive(Object, Serialize, Default)]
@@ -101,6 +106,46 @@ pub mod types {
     pub struct SftpRemoveFileRes {
         pub result: String,
     }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct SftpOpenFileRes {
+        pub content: String,
+        /// send it back when saving, the save fails if the file was changed meanwhile
+        pub etag: String,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct SftpSaveFilePayload {
+        pub instance_id: String,
+        pub file_path: String,
+        pub content: String,
+        /// etag returned by open, empty to create a new file
+        pub etag: String,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct SftpSaveFileRes {
+        pub etag: String,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct QuerySftpEditLogResp {
+        pub total: u64,
+        pub list: Vec<SftpEditLogRecord>,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct SftpEditLogRecord {
+        pub id: u64,
+        pub instance_id: String,
+        pub ip: String,
+        pub file_path: String,
+        pub prev_etag: String,
+        pub etag: String,
+        pub size: u64,
+        pub created_user: String,
+        pub created_time: String,
+    }
 }
 
 macro_rules! unwrap_or_response {
@@ -112,11 +157,29 @@ macro_rules! unwrap_or_response {
     };
 }
 
+fn upload_middleware(ep: impl Endpoint) -> impl Endpoint {
+    ep.with(middleware::PermissionMiddleware::new(
+        &POLICY_ALLOW_UPLOAD_FILE,
+    ))
+}
+
+fn export_middleware(ep: impl Endpoint) -> impl Endpoint {
+    ep.with(middleware::PermissionMiddleware::grant(
+        &POLICY_ALLOW_CHANGE_ALL_JOB,
+    ))
+}
+
+fn ssh_middleware(ep: impl Endpoint) -> impl Endpoint {
+    ep.with(middleware::PermissionMiddleware::new(
+        &POLICY_ALLOW_SSH_INSTANCE,
+    ))
+}
+
 pub struct FileApi;
 
 #[OpenApi(prefix_path = "/file", tag = super::Tag::File)]
 impl FileApi {
-    #[oai(path = "/upload", method = "post")]
+    #[oai(path = "/upload", method = "post", transform = "upload_middleware")]
     async fn upload(
         &self,
         state: Data<&AppState>,
@@ -124,20 +187,31 @@ impl FileApi {
         upload: types::UploadPayload,
         user_info: Data<&logic::types::UserInfo>,
     ) -> Result<ApiStdResponse<types::UploadFileRes>> {
-        if !state.can_upload_file(&user_info.user_id).await? {
-            return Err(NoPermission().into());
-        }
-        let filename = upload.file.file_name().map(ToString::to_string);
+        let filename = upload
+            .file
+            .file_name()
+            .map_or("upload".to_string(), ToString::to_string);
         let data = upload.file.into_vec().await.map_err(std_into_error)?;
 
+        if let Some(team_id) = upload.team_id.filter(|&v| v != 0) {
+            let svc = state.service();
+            if !svc
+                .team
+                .can_read_team(Some(team_id), user_info.user_id.clone())
+                .await?
+            {
+                return_err!("no permission");
+            }
+            svc.team
+                .save_upload_file(team_id, &filename, data.len() as u64, &user_info.username)
+                .await?;
+        }
+
         create_dir_all("/tmp/jiascheduler")
             .await
             .map_err(std_into_error)?;
 
-        let target_file = format!(
-            "/tmp/jiascheduler/{}",
-            filename.map_or("upload".to_string(), |v| v)
-        );
+        let target_file = format!("/tmp/jiascheduler/{}", filename);
 
         let mut tmp_file = File::create(target_file.clone())
             .await
@@ -149,17 +223,17 @@ impl FileApi {
         })
     }
 
-    #[oai(path = "/get/:filename", method = "get")]
+    #[oai(
+        path = "/get/:filename",
+        method = "get",
+        transform = "upload_middleware"
+    )]
     async fn get(
         &self,
-        state: Data<&AppState>,
-        user_info: Data<&logic::types::UserInfo>,
+        _state: Data<&AppState>,
+        _user_info: Data<&logic::types::UserInfo>,
         Path(filename): Path<String>,
     ) -> types::GetFileResponse {
-        if !unwrap_or_response!(state.can_upload_file(&user_info.user_id).await) {
-            return types::GetFileResponse::NotAllow;
-        }
-
         let buf = PathBuf::from(filename);
         let name = buf.file_name();
 
@@ -179,7 +253,53 @@ impl FileApi {
         types::GetFileResponse::Ok(attachment)
     }
 
-    #[oai(path = "/sftp/download", method = "get")]
+    /// download the file exported by the background task
+    #[oai(
+        path = "/export/:task_id",
+        method = "get",
+        transform = "export_middleware"
+    )]
+    async fn get_export(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
+        Path(task_id): Path<String>,
+    ) -> types::GetFileResponse {
+        let record = match unwrap_or_response!(state.service().task.get_task(&task_id).await) {
+            Some(v) if v.status == STATUS_SUCCEEDED => v,
+            _ => return types::GetFileResponse::NotFound,
+        };
+        if record.created_user != user_info.username && !granted.manage_job() {
+            return types::GetFileResponse::NotAllow;
+        }
+
+        let file_name = record
+            .output
+            .as_ref()
+            .and_then(|v| v["file_name"].as_str())
+            .and_then(|v| std::path::Path::new(v).file_name())
+            .and_then(|v| v.to_str())
+            .map(ToString::to_string);
+        let Some(file_name) = file_name else {
+            return types::GetFileResponse::NotFound;
+        };
+
+        let data = match fs::read(PathBuf::from(EXPORT_DIR).join(&file_name)).await {
+            Ok(v) => v,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return types::GetFileResponse::NotFound
+            }
+            Err(e) => return types::GetFileResponse::InternalError(PlainText(e.to_string())),
+        };
+
+        let attachment = Attachment::new(data)
+            .attachment_type(AttachmentType::Attachment)
+            .filename(file_name);
+        types::GetFileResponse::Ok(attachment)
+    }
+
+    #[oai(path = "/sftp/download", method = "get", transform = "ssh_middleware")]
     async fn download(
         &self,
         state: Data<&AppState>,
@@ -190,23 +310,19 @@ impl FileApi {
         let svc = state.service();
         let instance_record = unwrap_or_response!(
             svc.instance
-                .get_one_user_server_with_permission(state.clone(), &user_info, instance_id)
+                .get_one_user_server_with_credential(state.clone(), &user_info, instance_id)
                 .await
         )
         .map_or(Err(anyhow!("not found")), |v| Ok(v));
         let instance_record = unwrap_or_response!(instance_record);
-        let password =
-            unwrap_or_response!(state.decrypt(instance_record.password.unwrap_or_default()));
-
-        let ssh_session = unwrap_or_response!(
-            SshSession::connect(ConnectParams {
-                user: instance_record.sys_user.unwrap_or_default(),
-                password,
-                addrs: (instance_record.ip, 22),
-            })
-            .await
+        let hops = unwrap_or_response!(
+            svc.credential
+                .resolve_hops(&instance_record, 22, SCOPE_SFTP)
+                .await
         );
 
+        let ssh_session = unwrap_or_response!(SshSession::connect_chain(hops).await);
+
         let sftp_session = unwrap_or_response!(ssh_session.sftp_client().await);
         let data = unwrap_or_response!(sftp_session.read(&file_path).await);
 
@@ -221,7 +337,7 @@ impl FileApi {
         types::GetFileResponse::Ok(attachment)
     }
 
-    #[oai(path = "/sftp/read-dir", method = "get")]
+    #[oai(path = "/sftp/read-dir", method = "get", transform = "ssh_middleware")]
     async fn sftp_read_dir(
         &self,
         state: Data<&AppState>,
@@ -232,16 +348,14 @@ impl FileApi {
         let svc = state.service();
         let instance_record = svc
             .instance
-            .get_one_user_server_with_permission(state.clone(), &user_info, instance_id.clone())
+            .get_one_user_server_with_credential(state.clone(), &user_info, instance_id.clone())
             .await?
             .map_or(Err(anyhow!("not found")), |v| Ok(v))?;
-        let password = state.decrypt(instance_record.password.unwrap_or_default())?;
-        let ssh_session = SshSession::connect(ConnectParams {
-            user: instance_record.sys_user.unwrap_or_default(),
-            password,
-            addrs: (instance_record.ip, 22),
-        })
-        .await?;
+        let hops = svc
+            .credential
+            .resolve_hops(&instance_record, 22, SCOPE_SFTP)
+            .await?;
+        let ssh_session = SshSession::connect_chain(hops).await?;
 
         let sft_session = ssh_session.sftp_client().await?;
 
@@ -295,7 +409,7 @@ impl FileApi {
         return_ok!(resp)
     }
 
-    #[oai(path = "/sftp/upload", method = "post")]
+    #[oai(path = "/sftp/upload", method = "post", transform = "ssh_middleware")]
     async fn sftp_upload(
         &self,
         state: Data<&AppState>,
@@ -305,16 +419,14 @@ impl FileApi {
         let svc = state.service();
         let instance_record = svc
             .instance
-            .get_one_user_server_with_permission(state.clone(), &user_info, req.instance_id)
+            .get_one_user_server_with_credential(state.clone(), &user_info, req.instance_id)
             .await?
             .map_or(Err(anyhow!("not found")), |v| Ok(v))?;
-        let password = state.decrypt(instance_record.password.unwrap_or_default())?;
-        let ssh_session = SshSession::connect(ConnectParams {
-            user: instance_record.sys_user.unwrap_or_default(),
-            password,
-            addrs: (instance_record.ip, 22),
-        })
-        .await?;
+        let hops = svc
+            .credential
+            .resolve_hops(&instance_record, 22, SCOPE_SFTP)
+            .await?;
+        let ssh_session = SshSession::connect_chain(hops).await?;
 
         let dir = std::path::Path::new(&req.file_path)
             .parent()
@@ -344,7 +456,7 @@ impl FileApi {
         })
     }
 
-    #[oai(path = "/sftp/remove", method = "post")]
+    #[oai(path = "/sftp/remove", method = "post", transform = "ssh_middleware")]
     async fn sftp_remove(
         &self,
         state: Data<&AppState>,
@@ -354,16 +466,14 @@ impl FileApi {
         let svc = state.service();
         let instance_record = svc
             .instance
-            .get_one_user_server_with_permission(state.clone(), &user_info, req.instance_id)
+            .get_one_user_server_with_credential(state.clone(), &user_info, req.instance_id)
             .await?
             .map_or(Err(anyhow!("not found")), |v| Ok(v))?;
-        let password = state.decrypt(instance_record.password.unwrap_or_default())?;
-        let ssh_session = SshSession::connect(ConnectParams {
-            user: instance_record.sys_user.unwrap_or_default(),
-            password,
-            addrs: (instance_record.ip, 22),
-        })
-        .await?;
+        let hops = svc
+            .credential
+            .resolve_hops(&instance_record, 22, SCOPE_SFTP)
+            .await?;
+        let ssh_session = SshSession::connect_chain(hops).await?;
 
         let sftp_session = ssh_session.sftp_client().await?;
 
@@ -384,7 +494,118 @@ impl FileApi {
         })
     }
 
-    #[oai(path = "/sftp/tunnel/read-dir", method = "get")]
+    /// open the text file to edit it in the console
+    #[oai(path = "/sftp/open", method = "get", transform = "ssh_middleware")]
+    async fn sftp_open(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Query(instance_id): Query<String>,
+        Query(file_path): Query<String>,
+    ) -> Result<ApiStdResponse<types::SftpOpenFileRes>> {
+        let svc = state.service();
+        let instance_record = svc
+            .instance
+            .get_one_user_server_with_credential(state.clone(), &user_info, instance_id)
+            .await?
+            .ok_or(anyhow!("not found instance"))?;
+        let hops = svc
+            .credential
+            .resolve_hops(&instance_record, 22, SCOPE_SFTP)
+            .await?;
+        let ssh_session = SshSession::connect_chain(hops).await?;
+        let sftp_session = ssh_session.sftp_client().await?;
+
+        let file = svc.ssh.sftp_open_file(&sftp_session, &file_path).await?;
+        return_ok!(types::SftpOpenFileRes {
+            content: file.content,
+            etag: file.etag,
+        })
+    }
+
+    /// save the edited file, fails if it was changed since it was opened
+    #[oai(path = "/sftp/save", method = "post", transform = "ssh_middleware")]
+    async fn sftp_save(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::SftpSaveFilePayload>,
+    ) -> Result<ApiStdResponse<types::SftpSaveFileRes>> {
+        let svc = state.service();
+        let instance_record = svc
+            .instance
+            .get_one_user_server_with_credential(state.clone(), &user_info, req.instance_id)
+            .await?
+            .ok_or(anyhow!("not found instance"))?;
+        let hops = svc
+            .credential
+            .resolve_hops(&instance_record, 22, SCOPE_SFTP)
+            .await?;
+        let ssh_session = SshSession::connect_chain(hops).await?;
+        let sftp_session = ssh_session.sftp_client().await?;
+
+        let etag = svc
+            .ssh
+            .sftp_save_file(
+                &sftp_session,
+                &instance_record.instance_id,
+                &instance_record.ip,
+                &req.file_path,
+                &req.content,
+                &req.etag,
+                &user_info.username,
+            )
+            .await?;
+        return_ok!(types::SftpSaveFileRes { etag })
+    }
+
+    /// audit of the files saved with /sftp/save
+    #[oai(path = "/sftp/edit-log", method = "get", transform = "ssh_middleware")]
+    async fn query_sftp_edit_log(
+        &self,
+        state: Data<&AppState>,
+        _user_info: Data<&logic::types::UserInfo>,
+        Query(instance_id): Query<Option<String>>,
+        #[oai(
+            default = "crate::api::default_page",
+            validator(maximum(value = "10000"))
+        )]
+        Query(page): Query<u64>,
+        #[oai(
+            default = "crate::api::default_page_size",
+            validator(maximum(value = "10000"))
+        )]
+        Query(page_size): Query<u64>,
+    ) -> Result<ApiStdResponse<types::QuerySftpEditLogResp>> {
+        let (list, total) = state
+            .service()
+            .ssh
+            .query_sftp_edit_log(instance_id.filter(|v| !v.is_empty()), page - 1, page_size)
+            .await?;
+        return_ok!(types::QuerySftpEditLogResp {
+            total,
+            list: list
+                .into_iter()
+                .map(|v| types::SftpEditLogRecord {
+                    id: v.id,
+                    instance_id: v.instance_id,
+                    ip: v.ip,
+                    file_path: v.file_path,
+                    prev_etag: v.prev_etag,
+                    etag: v.etag,
+                    size: v.size,
+                    created_user: v.created_user,
+                    created_time: local_time!(v.created_time),
+                })
+                .collect(),
+        })
+    }
+
+    #[oai(
+        path = "/sftp/tunnel/read-dir",
+        method = "get",
+        transform = "ssh_middleware"
+    )]
     async fn sftp_tunnel_read_dir(
         &self,
         state: Data<&AppState>,
@@ -395,23 +616,15 @@ impl FileApi {
         let svc = state.service();
         let instance_record = svc
             .instance
-            .get_one_user_server_with_permission(state.clone(), &user_info, instance_id)
+            .get_one_user_server_with_credential(state.clone(), &user_info, instance_id)
             .await?
             .ok_or(anyhow!("not found instance"))?;
-        let user = instance_record
-            .sys_user
-            .filter(|v| v != "")
-            .ok_or(anyhow!("no system user"))?;
-        let password = instance_record
-            .password
-            .filter(|v| v != "")
-            .ok_or(anyhow!("no password"))?;
+        let credential = svc.credential.resolve(&instance_record, SCOPE_SFTP).await?;
         let port = instance_record
             .ssh_port
             .filter(|&v| v != 0)
             .ok_or(anyhow!("no ssh port"))?;
 
-        let password = state.decrypt(password)?;
         let ret = svc
             .ssh
             .sftp_read_dir(
@@ -420,8 +633,7 @@ impl FileApi {
                 instance_record.mac_addr,
                 port,
                 dir,
-                user,
-                password,
+                credential,
             )
             .await?;
 
@@ -430,7 +642,11 @@ impl FileApi {
         return_ok!(dir_detail);
     }
 
-    #[oai(path = "/sftp/tunnel/upload", method = "post")]
+    #[oai(
+        path = "/sftp/tunnel/upload",
+        method = "post",
+        transform = "ssh_middleware"
+    )]
     async fn sftp_tunnel_upload(
         &self,
         state: Data<&AppState>,
@@ -440,25 +656,16 @@ impl FileApi {
         let svc = state.service();
         let instance_record = svc
             .instance
-            .get_one_user_server_with_permission(state.clone(), &user_info, req.instance_id)
+            .get_one_user_server_with_credential(state.clone(), &user_info, req.instance_id)
             .await?
             .ok_or(anyhow!("not found instance"))?;
 
-        let user = instance_record
-            .sys_user
-            .filter(|v| v != "")
-            .ok_or(anyhow!("no sys user"))?;
-        let password = instance_record
-            .password
-            .filter(|v| v != "")
-            .ok_or(anyhow!("no password"))?;
+        let credential = svc.credential.resolve(&instance_record, SCOPE_SFTP).await?;
         let port = instance_record
             .ssh_port
             .filter(|&v| v != 0)
             .ok_or(anyhow!("no ssh port"))?;
 
-        let password = state.decrypt(password)?;
-
         let data = req.file.into_vec().await.map_err(std_into_error)?;
 
         let ret = svc
@@ -468,8 +675,7 @@ impl FileApi {
                 instance_record.ip,
                 instance_record.mac_addr,
                 port,
-                user,
-                password,
+                credential,
                 req.file_path,
                 data,
             )
@@ -478,7 +684,11 @@ impl FileApi {
         return_ok!(types::SftpUploadFileRes { result: ret })
     }
 
-    #[oai(path = "/sftp/tunnel/remove", method = "post")]
+    #[oai(
+        path = "/sftp/tunnel/remove",
+        method = "post",
+        transform = "ssh_middleware"
+    )]
     async fn sftp_tunnel_remove(
         &self,
         state: Data<&AppState>,
@@ -493,24 +703,15 @@ impl FileApi {
         let svc = state.service();
         let instance_record = svc
             .instance
-            .get_one_user_server_with_permission(state.clone(), &user_info, req.instance_id)
+            .get_one_user_server_with_credential(state.clone(), &user_info, req.instance_id)
             .await?
             .ok_or(anyhow!("not found instance"))?;
-        let user = instance_record
-            .sys_user
-            .filter(|v| v != "")
-            .ok_or(anyhow!("no sys user"))?;
-        let password = instance_record
-            .password
-            .filter(|v| v != "")
-            .ok_or(anyhow!("no password"))?;
+        let credential = svc.credential.resolve(&instance_record, SCOPE_SFTP).await?;
         let port = instance_record
             .ssh_port
             .filter(|&v| v != 0)
             .ok_or(anyhow!("no ssh port"))?;
 
-        let password = state.decrypt(password)?;
-
         let ret = svc
             .ssh
             .sftp_remove(
@@ -518,8 +719,7 @@ impl FileApi {
                 instance_record.ip,
                 instance_record.mac_addr,
                 port,
-                user,
-                password,
+                credential,
                 req.path,
                 req.remove_type,
             )
@@ -528,7 +728,11 @@ impl FileApi {
         return_ok!(types::SftpRemoveFileRes { result: ret })
     }
 
-    #[oai(path = "/sftp/tunnel/download", method = "get")]
+    #[oai(
+        path = "/sftp/tunnel/download",
+        method = "get",
+        transform = "ssh_middleware"
+    )]
     async fn sftp_tunnel_download(
         &self,
         state: Data<&AppState>,
@@ -539,29 +743,20 @@ impl FileApi {
         let svc = state.service();
         let instance_record = unwrap_or_response!(
             svc.instance
-                .get_one_user_server_with_permission(state.clone(), &user_info, instance_id)
+                .get_one_user_server_with_credential(state.clone(), &user_info, instance_id)
                 .await
         );
 
         let instance_record =
             unwrap_or_response!(instance_record.ok_or(anyhow!("not found instance")));
 
-        let user = unwrap_or_response!(instance_record
-            .sys_user
-            .filter(|v| v != "")
-            .ok_or(anyhow!("no sys user")));
-
-        let password = unwrap_or_response!(instance_record
-            .password
-            .filter(|v| v != "")
-            .ok_or(anyhow!("no password")));
+        let credential =
+            unwrap_or_response!(svc.credential.resolve(&instance_record, SCOPE_SFTP).await);
         let port = unwrap_or_response!(instance_record
             .ssh_port
             .filter(|&v| v != 0)
             .ok_or(anyhow!("no ssh port")));
 
-        let password = unwrap_or_response!(state.decrypt(password));
-
         let data = unwrap_or_response!(
             svc.ssh
                 .sftp_download(
@@ -569,8 +764,7 @@ impl FileApi {
                     instance_record.ip,
                     instance_record.mac_addr,
                     port,
-                    user,
-                    password,
+                    credential,
                     file_path.clone()
                 )
                 .await
@@ -3,18 +3,41 @@ use sea_orm::{ActiveValue::NotSet, Set};
 
 use crate::api_response;
 use crate::{
-    entity::instance, error::NoPermission, local_time, logic, response::ApiStdResponse, return_ok,
-    AppState,
+    default_local_time,
+    entity::instance,
+    error::NoPermission,
+    local_time,
+    logic::{self, role::POLICY_ALLOW_MANAGE_ALL_INSTANCE},
+    middleware,
+    response::ApiStdResponse,
+    return_err, return_ok, AppState,
 };
+use automate::bridge::msg::AgentLogParams;
 use entity::instance_group;
-use poem::{session::Session, web::Data, Result};
+use logic::task::{BackgroundTask, EnsureExecutorTask};
+use poem::{session::Session, web::Data, Endpoint, EndpointExt, Result};
 use poem_openapi::param::Query;
 use poem_openapi::payload::Json;
 
+fn manage_middleware(ep: impl Endpoint) -> impl Endpoint {
+    ep.with(middleware::PermissionMiddleware::new(
+        &POLICY_ALLOW_MANAGE_ALL_INSTANCE,
+    ))
+}
+
+fn query_middleware(ep: impl Endpoint) -> impl Endpoint {
+    ep.with(middleware::PermissionMiddleware::grant(
+        &POLICY_ALLOW_MANAGE_ALL_INSTANCE,
+    ))
+}
+
 pub mod types {
-    use poem_openapi::Object;
+    use poem_openapi::{Enum, Object};
+    use sea_orm::Order;
     use serde::{Deserialize, Serialize};
 
+    use crate::entity::instance;
+
     #[derive(Object, Serialize, Default)]
     pub struct QueryInstanceResp {
         pub total: u64,
@@ -31,13 +54,65 @@ pub mod types {
         pub sys_user: String,
         pub info: String,
         pub status: i8,
+        /// draining, no new job is dispatched to the instance
+        pub maintenance: bool,
         pub role_id: u64,
         pub role_name: String,
         pub instance_group_id: u64,
+        pub os: String,
+        pub os_version: String,
+        pub kernel_version: String,
+        pub arch: String,
+        pub cpu_count: u32,
+        pub mem_total: u64,
+        pub mem_used: u64,
+        pub disk_total: u64,
+        pub disk_used: u64,
+        pub agent_version: String,
+        pub facts_updated_time: String,
+        /// healthy, degraded, offline or unknown
+        pub health: String,
+        pub health_reasons: Vec<String>,
         pub created_time: String,
         pub updated_time: String,
     }
 
+    #[derive(Serialize, Enum, Clone, Copy)]
+    pub enum InstanceSortField {
+        #[oai(rename = "updated_time")]
+        UpdatedTime,
+        #[oai(rename = "os")]
+        Os,
+        #[oai(rename = "arch")]
+        Arch,
+        #[oai(rename = "cpu_count")]
+        CpuCount,
+        #[oai(rename = "mem_total")]
+        MemTotal,
+        #[oai(rename = "mem_used")]
+        MemUsed,
+        #[oai(rename = "disk_used")]
+        DiskUsed,
+        #[oai(rename = "agent_version")]
+        AgentVersion,
+    }
+
+    impl InstanceSortField {
+        pub fn order_by(self, desc: bool) -> (instance::Column, Order) {
+            let column = match self {
+                InstanceSortField::UpdatedTime => instance::Column::UpdatedTime,
+                InstanceSortField::Os => instance::Column::Os,
+                InstanceSortField::Arch => instance::Column::Arch,
+                InstanceSortField::CpuCount => instance::Column::CpuCount,
+                InstanceSortField::MemTotal => instance::Column::MemTotal,
+                InstanceSortField::MemUsed => instance::Column::MemUsed,
+                InstanceSortField::DiskUsed => instance::Column::DiskUsed,
+                InstanceSortField::AgentVersion => instance::Column::AgentVersion,
+            };
+            (column, if desc { Order::Desc } else { Order::Asc })
+        }
+    }
+
     #[derive(Object, Serialize, Default)]
     pub struct GrantedUserReq {
         pub user_id: Vec<String>,
@@ -55,6 +130,13 @@ pub mod types {
         pub instance_group_id: Option<u64>,
         pub tag_id: Option<Vec<u64>>,
         pub status: Option<u8>,
+        /// filter by the facts reported by agent, eg: arch=arm64
+        pub os: Option<String>,
+        pub arch: Option<String>,
+        pub agent_version: Option<String>,
+        pub min_cpu_count: Option<u32>,
+        /// bytes
+        pub min_mem_total: Option<u64>,
 
         #[oai(
             default = "crate::api::default_page_size",
@@ -88,6 +170,7 @@ pub mod types {
         pub instance_group_id: u64,
         pub instance_group: String,
         pub status: i8,
+        pub maintenance: bool,
         pub info: String,
         pub tags: Option<Vec<Tag>>,
         pub created_time: String,
@@ -108,7 +191,16 @@ pub mod types {
         pub status: i8,
         pub sys_user: Option<String>,
         pub password: Option<String>,
+        /// ssh private key in openssh or pem format, used instead of the password.
+        /// the saved one is kept if empty
+        pub private_key: Option<String>,
+        pub key_passphrase: Option<String>,
+        /// remove the saved private key to log in with the password
+        #[oai(default)]
+        pub clear_private_key: bool,
         pub ssh_port: Option<u16>,
+        /// ssh connections are tunneled through this instance, empty to use the bastion of the group
+        pub bastion_instance_id: Option<String>,
     }
 
     #[derive(Object, Serialize, Deserialize)]
@@ -122,6 +214,8 @@ pub mod types {
         pub name: String,
         pub info: String,
         pub instance_ids: Option<Vec<u64>>,
+        /// bastion of the instances in the group
+        pub bastion_instance_id: Option<String>,
     }
 
     #[derive(Object, Serialize, Deserialize)]
@@ -140,6 +234,7 @@ pub mod types {
         pub id: u64,
         pub name: String,
         pub info: String,
+        pub bastion_instance_id: String,
         pub created_time: String,
         pub updated_time: String,
         pub created_user: String,
@@ -171,13 +266,353 @@ pub mod types {
     pub struct SaveInstanceStatusResp {
         pub result: u64,
     }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct SetMaintenanceReq {
+        /// the running jobs finish, the new dispatches and the timer catch-up skip the instances
+        pub maintenance: bool,
+        pub instance_ids: Vec<u64>,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct SetMaintenanceResp {
+        pub result: u64,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct CreateBootstrapTokenReq {
+        /// only agents of this namespace can use the token, any namespace if empty
+        pub namespace: Option<String>,
+        #[oai(default)]
+        pub single_use: bool,
+        /// never expires if empty
+        pub expire_secs: Option<u64>,
+        #[oai(default)]
+        pub info: String,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct CreateBootstrapTokenResp {
+        /// pass to the agent with --bootstrap-token, only shown once
+        pub token: String,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct BootstrapTokenRecord {
+        pub id: String,
+        pub namespace: String,
+        pub single_use: bool,
+        pub expire_time: Option<String>,
+        pub info: String,
+        pub created_user: String,
+        pub created_time: String,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct QueryBootstrapTokenResp {
+        pub list: Vec<BootstrapTokenRecord>,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct RevokeBootstrapTokenReq {
+        pub id: String,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct AgentCredentialRecord {
+        pub id: String,
+        pub namespace: String,
+        pub mac_addr: String,
+        pub agent_ip: String,
+        pub token_id: String,
+        pub created_time: String,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct QueryAgentCredentialResp {
+        pub list: Vec<AgentCredentialRecord>,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct RevokeAgentCredentialReq {
+        pub id: Option<String>,
+        /// revoke all credentials of the agent
+        pub mac_addr: Option<String>,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct RevokeResp {
+        pub result: u64,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct EnsureExecutorReq {
+        pub executor_id: u64,
+        #[oai(validator(min_items = 1))]
+        pub instance_ids: Vec<String>,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct EnsureExecutorResp {
+        /// poll the task with /task/detail, the availability is recorded once it ends
+        pub task_id: String,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct QueryInstanceExecutorResp {
+        pub list: Vec<InstanceExecutorRecord>,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct InstanceExecutorRecord {
+        pub executor_id: u64,
+        pub executor_name: String,
+        /// installed or failed
+        pub status: String,
+        pub exit_code: Option<i32>,
+        pub output: Option<String>,
+        pub updated_user: String,
+        pub updated_time: String,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct SaveSshCredentialReq {
+        pub id: Option<u64>,
+        pub instance_id: String,
+        #[oai(validator(min_length = 1, max_length = 50))]
+        pub name: String,
+        /// password or private_key
+        pub cred_type: String,
+        #[oai(validator(min_length = 1, max_length = 50))]
+        pub sys_user: String,
+        /// password or private key, the saved one is kept if empty on update
+        pub secret: Option<String>,
+        /// passphrase of the private key
+        pub passphrase: Option<String>,
+        /// webssh, sftp or job
+        #[oai(default)]
+        pub scopes: Vec<String>,
+        /// rotate the password every n days, 0 to disable
+        #[oai(default)]
+        pub rotate_days: u32,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct SaveSshCredentialResp {
+        pub id: u64,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct QuerySshCredentialResp {
+        pub list: Vec<SshCredentialRecord>,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct SshCredentialRecord {
+        pub id: u64,
+        pub instance_id: String,
+        pub name: String,
+        pub cred_type: String,
+        pub sys_user: String,
+        pub scopes: Vec<String>,
+        pub rotate_days: u32,
+        pub rotated_time: String,
+        pub created_user: String,
+        pub updated_user: String,
+        pub created_time: String,
+        pub updated_time: String,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct SshCredentialIdReq {
+        pub id: u64,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct DeleteSshCredentialResp {
+        pub result: u64,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct RotateSshCredentialResp {
+        pub result: bool,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct QueryAgentConnectionResp {
+        pub list: Vec<AgentConnectionRecord>,
+        /// the comets failed to respond
+        pub errors: Vec<String>,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct AgentConnectionRecord {
+        pub agent_key: String,
+        pub namespace: String,
+        pub agent_ip: String,
+        pub mac_addr: String,
+        pub protocol_version: u32,
+        pub comet_addr: String,
+        pub connected_time: String,
+        pub last_heartbeat_time: Option<String>,
+        pub msgs_received: u64,
+        pub msgs_sent: u64,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct DisconnectAgentReq {
+        pub agent_ip: String,
+        pub mac_addr: String,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct DisconnectAgentResp {
+        pub result: bool,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct AgentLogResp {
+        /// false if the agent does not keep its logs
+        pub enabled: bool,
+        pub lines: Vec<AgentLogLine>,
+        /// pass as since to poll the new lines
+        pub next_seq: u64,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct AgentLogLine {
+        pub seq: u64,
+        pub line: String,
+    }
+
+    pub fn default_agent_log_limit() -> usize {
+        200
+    }
+
+    /// settings of the agent, the unset ones fall back to the flags of the agent
+    #[derive(Object, Serialize, Deserialize, Default)]
+    pub struct AgentConfig {
+        pub output_dir: Option<String>,
+        /// eg: "info,automate=debug"
+        pub log_level: Option<String>,
+        /// seconds between the heartbeats
+        pub heartbeat_interval: Option<u64>,
+        pub comet_addr: Option<Vec<String>>,
+    }
+
+    impl From<automate::bridge::msg::AgentConfig> for AgentConfig {
+        fn from(value: automate::bridge::msg::AgentConfig) -> Self {
+            Self {
+                output_dir: value.output_dir,
+                log_level: value.log_level,
+                heartbeat_interval: value.heartbeat_interval,
+                comet_addr: value.comet_addr,
+            }
+        }
+    }
+
+    impl From<AgentConfig> for automate::bridge::msg::AgentConfig {
+        fn from(value: AgentConfig) -> Self {
+            Self {
+                output_dir: value.output_dir,
+                log_level: value.log_level,
+                heartbeat_interval: value.heartbeat_interval,
+                comet_addr: value.comet_addr,
+            }
+        }
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct QueryAgentConfigResp {
+        pub list: Vec<AgentConfigRecord>,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct AgentConfigRecord {
+        pub id: u64,
+        pub namespace: String,
+        /// empty for the default of the namespace
+        pub instance_id: String,
+        pub config: AgentConfig,
+        pub created_user: String,
+        pub updated_user: String,
+        pub updated_time: String,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct SaveAgentConfigReq {
+        pub namespace: String,
+        /// overrides the config of the namespace for the instance, empty for the namespace
+        #[oai(default)]
+        pub instance_id: String,
+        pub config: AgentConfig,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct DeleteAgentConfigReq {
+        pub id: u64,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct PushAgentConfigResp {
+        /// the online agents the config was pushed to
+        pub pushed: Vec<AgentConfigPushResult>,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct AgentConfigPushResult {
+        pub instance_id: String,
+        pub ip: String,
+        pub err: Option<String>,
+    }
+
+    impl From<crate::logic::agent_config::PushResult> for AgentConfigPushResult {
+        fn from(value: crate::logic::agent_config::PushResult) -> Self {
+            Self {
+                instance_id: value.instance_id,
+                ip: value.ip,
+                err: value.err,
+            }
+        }
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct QueryAgentConfigDriftResp {
+        pub list: Vec<AgentConfigDriftRecord>,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct AgentConfigDriftRecord {
+        pub instance_id: String,
+        pub ip: String,
+        pub namespace: String,
+        pub status: i8,
+        /// the config of the instance merged over the config of the namespace
+        pub desired: AgentConfig,
+        /// false if the agent does not report its config, eg: an older agent
+        pub supported: bool,
+        /// the config saved by the agent
+        pub saved: Option<AgentConfig>,
+        /// the settings in effect on the agent
+        pub effective: Option<AgentConfig>,
+        /// the settings saved by the agent which differ from the desired ones
+        pub drift_fields: Vec<String>,
+        /// the settings saved but not in effect until the agent restarts
+        pub pending_restart: Vec<String>,
+    }
+}
+
+pub(crate) fn format_timestamp(v: i64) -> String {
+    chrono::DateTime::from_timestamp(v, 0)
+        .map(|v| local_time!(v))
+        .unwrap_or_default()
 }
 
 pub struct InstanceApi;
 
 #[OpenApi(prefix_path = "/instance", tag = super::Tag::Instance)]
 impl InstanceApi {
-    #[oai(path = "/list", method = "get")]
+    #[oai(path = "/list", method = "get", transform = "manage_middleware")]
     pub async fn query_instance(
         &self,
         state: Data<&AppState>,
@@ -187,6 +622,13 @@ impl InstanceApi {
         Query(status): Query<Option<u8>>,
         Query(role_id): Query<Option<u64>>,
         Query(ignore_role_id): Query<Option<u64>>,
+        Query(os): Query<Option<String>>,
+        Query(arch): Query<Option<String>>,
+        Query(agent_version): Query<Option<String>>,
+        Query(min_cpu_count): Query<Option<u32>>,
+        Query(min_mem_total): Query<Option<u64>>,
+        Query(sort_by): Query<Option<types::InstanceSortField>>,
+        #[oai(default)] Query(sort_desc): Query<bool>,
         #[oai(
             default = "crate::api::default_page_size",
             validator(maximum(value = "10000"))
@@ -200,9 +642,15 @@ impl InstanceApi {
         user_info: Data<&logic::types::UserInfo>,
     ) -> Result<ApiStdResponse<types::QueryInstanceResp>> {
         let svc = state.service();
-        if !state.can_manage_instance(&user_info.user_id).await? {
-            return Err(NoPermission().into());
-        }
+        let facts = logic::instance::InstanceFactsFilter {
+            os,
+            arch,
+            agent_version,
+            min_cpu_count,
+            min_mem_total,
+            tag_scope: svc.tag.get_tag_scope(&user_info).await?,
+        };
+        let order_by = sort_by.map(|v| v.order_by(sort_desc));
 
         let ret = match role_id {
             Some(role_id) if role_id > 0 && !svc.role.is_admin(role_id).await? => {
@@ -212,6 +660,8 @@ impl InstanceApi {
                         status,
                         role_id,
                         ignore_role_id.filter(|&v| v != 0),
+                        &facts,
+                        order_by,
                         page - 1,
                         page_size,
                     )
@@ -223,6 +673,8 @@ impl InstanceApi {
                         ip.filter(|v| v != ""),
                         status,
                         ignore_role_id.filter(|&v| v != 0),
+                        &facts,
+                        order_by,
                         page - 1,
                         page_size,
                     )
@@ -233,20 +685,37 @@ impl InstanceApi {
         let list: Vec<types::InstanceRecord> = ret
             .0
             .into_iter()
-            .map(|v| types::InstanceRecord {
-                id: v.id,
-                instance_id: v.instance_id,
-                ip: v.ip,
-                role_id: v.role_id.unwrap_or_default(),
-                role_name: v.role_name.unwrap_or_default(),
-                instance_group: v.instance_group.unwrap_or_default(),
-                instance_group_id: v.instance_group_id,
-                namespace: v.namespace,
-                status: v.status,
-                updated_time: local_time!(v.updated_time),
-                sys_user: v.sys_user,
-                info: v.info,
-                created_time: local_time!(v.created_time),
+            .map(|v| {
+                let (health, health_reasons) = v.health();
+                types::InstanceRecord {
+                    id: v.id,
+                    instance_id: v.instance_id,
+                    ip: v.ip,
+                    role_id: v.role_id.unwrap_or_default(),
+                    role_name: v.role_name.unwrap_or_default(),
+                    instance_group: v.instance_group.unwrap_or_default(),
+                    instance_group_id: v.instance_group_id,
+                    namespace: v.namespace,
+                    status: v.status,
+                    maintenance: v.maintenance,
+                    os: v.os,
+                    os_version: v.os_version,
+                    kernel_version: v.kernel_version,
+                    arch: v.arch,
+                    cpu_count: v.cpu_count,
+                    mem_total: v.mem_total,
+                    mem_used: v.mem_used,
+                    disk_total: v.disk_total,
+                    disk_used: v.disk_used,
+                    agent_version: v.agent_version,
+                    facts_updated_time: default_local_time!(v.facts_updated_time),
+                    health: health.to_string(),
+                    health_reasons,
+                    updated_time: local_time!(v.updated_time),
+                    sys_user: v.sys_user,
+                    info: v.info,
+                    created_time: local_time!(v.created_time),
+                }
             })
             .collect();
         return_ok!(types::QueryInstanceResp {
@@ -274,18 +743,31 @@ impl InstanceApi {
         return_ok!(types::GrantedUserResp {})
     }
 
-    #[oai(path = "/user-server-list", method = "post")]
+    #[oai(
+        path = "/user-server-list",
+        method = "post",
+        transform = "query_middleware"
+    )]
     pub async fn user_server(
         &self,
         state: Data<&AppState>,
         _session: &Session,
         user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
         Json(req): Json<types::UserServerReq>,
     ) -> Result<ApiStdResponse<types::QueryUserServerResp>> {
         let svc = state.service();
         let user_id = user_info.user_id.clone();
 
-        let can_manage_instance = state.can_manage_instance(&user_id).await?;
+        let can_manage_instance = granted.manage_instance();
+        let facts = logic::instance::InstanceFactsFilter {
+            os: req.os.clone(),
+            arch: req.arch.clone(),
+            agent_version: req.agent_version.clone(),
+            min_cpu_count: req.min_cpu_count,
+            min_mem_total: req.min_mem_total,
+            tag_scope: svc.tag.get_tag_scope(&user_info).await?,
+        };
 
         let (list, total) = match req.tag_id {
             Some(tag_id) if tag_id.len() > 0 => {
@@ -304,6 +786,7 @@ impl InstanceApi {
                         req.ips.clone(),
                         req.instance_ids.clone(),
                         Some(tag_id),
+                        &facts,
                         req.page - 1,
                         req.page_size,
                     )
@@ -319,6 +802,7 @@ impl InstanceApi {
                         req.instance_group_id.filter(|&v| v != 0),
                         req.status,
                         req.ips.clone(),
+                        &facts,
                         req.page - 1,
                         req.page_size,
                     )
@@ -334,6 +818,7 @@ impl InstanceApi {
                         req.instance_group_id.filter(|&v| v != 0),
                         req.status,
                         req.ips.clone(),
+                        &facts,
                         req.page - 1,
                         req.page_size,
                     )
@@ -354,6 +839,7 @@ impl InstanceApi {
                 instance_group_id: v.instance_group_id.unwrap_or_default(),
                 instance_group: v.instance_group_name.unwrap_or_default(),
                 status: v.status,
+                maintenance: v.maintenance,
                 created_time: local_time!(v.created_time),
                 updated_time: local_time!(v.updated_time),
             })
@@ -362,19 +848,14 @@ impl InstanceApi {
         return_ok!(types::QueryUserServerResp { list, total })
     }
 
-    #[oai(path = "/save", method = "post")]
+    #[oai(path = "/save", method = "post", transform = "manage_middleware")]
     pub async fn save_instance(
         &self,
         state: Data<&AppState>,
         _session: &Session,
-        user_info: Data<&logic::types::UserInfo>,
         Json(req): Json<types::SaveInstanceReq>,
     ) -> Result<ApiStdResponse<types::SaveInstanceResp>> {
         let svc = state.service();
-        if !state.can_manage_instance(&user_info.user_id).await? {
-            return Err(NoPermission().into());
-        }
-
         let password = req
             .password
             .clone()
@@ -383,9 +864,34 @@ impl InstanceApi {
             .transpose()?
             .map_or(NotSet, |v| Set(v));
 
+        let (private_key, key_passphrase) = if req.clear_private_key {
+            (Set(None), Set(None))
+        } else {
+            match req.private_key.clone().filter(|v| v.trim() != "") {
+                Some(key) => {
+                    logic::credential::check_private_key(&key, req.key_passphrase.as_deref())?;
+                    (
+                        Set(Some(state.encrypt(key)?)),
+                        Set(req
+                            .key_passphrase
+                            .clone()
+                            .filter(|v| v != "")
+                            .map(|v| state.encrypt(v))
+                            .transpose()?),
+                    )
+                }
+                None => (NotSet, NotSet),
+            }
+        };
+
+        let id = req.id.filter(|&v| v != 0);
+        if let Some(bastion_id) = req.bastion_instance_id.as_deref().filter(|v| !v.is_empty()) {
+            svc.credential.check_bastion(bastion_id, id).await?;
+        }
+
         svc.instance
             .save_instance(instance::ActiveModel {
-                id: req.id.filter(|&v| v != 0).map_or(NotSet, |v| Set(v)),
+                id: id.map_or(NotSet, |v| Set(v)),
                 ip: Set(req.ip),
                 namespace: Set(req.namespace),
                 instance_group_id: req.instance_group_id.map_or(NotSet, |v| Set(v)),
@@ -396,14 +902,17 @@ impl InstanceApi {
                     .filter(|v| v.trim() != "")
                     .map_or(NotSet, |v| Set(v)),
                 password,
+                private_key,
+                key_passphrase,
                 ssh_port: req.ssh_port.filter(|&v| v != 0).map_or(NotSet, |v| Set(v)),
+                bastion_instance_id: req.bastion_instance_id.map_or(NotSet, |v| Set(v)),
                 ..Default::default()
             })
             .await?;
         return_ok!(types::SaveInstanceResp { result: 0 })
     }
 
-    #[oai(path = "/set_status", method = "post")]
+    #[oai(path = "/set_status", method = "post", transform = "manage_middleware")]
     pub async fn set_instance_status(
         &self,
         state: Data<&AppState>,
@@ -411,9 +920,6 @@ impl InstanceApi {
         user_info: Data<&logic::types::UserInfo>,
         Json(req): Json<types::SaveInstanceStatusReq>,
     ) -> api_response!(types::SaveInstanceStatusResp) {
-        if !state.can_manage_instance(&user_info.user_id).await? {
-            return Err(NoPermission().into());
-        }
         let result = state
             .service()
             .instance
@@ -423,7 +929,27 @@ impl InstanceApi {
         return_ok!(types::SaveInstanceStatusResp { result })
     }
 
-    #[oai(path = "/group/save", method = "post")]
+    #[oai(
+        path = "/set_maintenance",
+        method = "post",
+        transform = "manage_middleware"
+    )]
+    pub async fn set_maintenance(
+        &self,
+        state: Data<&AppState>,
+        _session: &Session,
+        Json(req): Json<types::SetMaintenanceReq>,
+    ) -> api_response!(types::SetMaintenanceResp) {
+        let result = state
+            .service()
+            .instance
+            .set_maintenance(req.instance_ids, req.maintenance)
+            .await?;
+
+        return_ok!(types::SetMaintenanceResp { result })
+    }
+
+    #[oai(path = "/group/save", method = "post", transform = "manage_middleware")]
     pub async fn save_group(
         &self,
         state: Data<&AppState>,
@@ -432,14 +958,15 @@ impl InstanceApi {
         Json(req): Json<types::SaveInstanceGroupReq>,
     ) -> Result<ApiStdResponse<types::SaveInstanceGroupResp>> {
         let svc = state.service();
-        if !state.can_manage_instance(&user_info.user_id).await? {
-            return Err(NoPermission().into());
+        if let Some(bastion_id) = req.bastion_instance_id.as_deref().filter(|v| !v.is_empty()) {
+            svc.credential.check_bastion(bastion_id, None).await?;
         }
         svc.instance
             .save_group(instance_group::ActiveModel {
                 id: req.id.filter(|&v| v != 0).map_or(NotSet, |v| Set(v)),
                 name: Set(req.name),
                 info: Set(req.info),
+                bastion_instance_id: req.bastion_instance_id.map_or(NotSet, |v| Set(v)),
                 created_user: Set(user_info.username.to_string()),
                 ..Default::default()
             })
@@ -447,7 +974,7 @@ impl InstanceApi {
         return_ok!(types::SaveInstanceGroupResp { result: 0 })
     }
 
-    #[oai(path = "/group/list", method = "get")]
+    #[oai(path = "/group/list", method = "get", transform = "manage_middleware")]
     pub async fn query_group(
         &self,
         state: Data<&AppState>,
@@ -467,13 +994,8 @@ impl InstanceApi {
             validator(maximum(value = "10000"))
         )]
         Query(page): Query<u64>,
-        user_info: Data<&logic::types::UserInfo>,
     ) -> api_response!(types::QueryInstanceGroupResp) {
         let svc = state.service();
-        if !state.can_manage_instance(&user_info.user_id).await? {
-            return Err(NoPermission().into());
-        }
-
         let ret = if let Some(role_id) = role_id {
             svc.instance
                 .query_group_by_role_id(
@@ -502,6 +1024,7 @@ impl InstanceApi {
                 id: v.id,
                 name: v.name,
                 info: v.info,
+                bastion_instance_id: v.bastion_instance_id,
                 created_user: v.created_user,
                 updated_time: local_time!(v.updated_time),
                 created_time: local_time!(v.created_time),
@@ -513,49 +1036,71 @@ impl InstanceApi {
         })
     }
 
-    #[oai(path = "/group/delete", method = "post")]
+    #[oai(
+        path = "/group/delete",
+        method = "post",
+        transform = "manage_middleware"
+    )]
     pub async fn delete_group(
         &self,
         state: Data<&AppState>,
         _session: &Session,
-        user_info: Data<&logic::types::UserInfo>,
         Json(req): Json<types::DeleteInstanceGroupReq>,
     ) -> api_response!(types::DeleteInstanceGroupResp) {
         let svc = state.service();
-        if !state.can_manage_instance(&user_info.user_id).await? {
-            return Err(NoPermission().into());
-        }
         let ret = svc.instance.delete_group(req.id).await?;
         return_ok!(types::DeleteInstanceGroupResp { result: ret })
     }
 
-    #[oai(path = "/instance-stats", method = "post")]
+    #[oai(
+        path = "/instance-stats",
+        method = "post",
+        transform = "query_middleware"
+    )]
     pub async fn get_instance_stats(
         &self,
         state: Data<&AppState>,
         user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
     ) -> Result<ApiStdResponse<types::GetInstanceStatsResp>> {
         let svc = state.service();
-        let can_manage_instance = state.can_manage_instance(&user_info.user_id).await?;
-        let (online_num, offline_num) = if can_manage_instance {
+        let (online_num, offline_num) = if granted.manage_instance() {
             (
                 svc.instance
-                    .query_admin_server(None, None, Some(1), None, 0, 1)
+                    .query_admin_server(None, None, Some(1), None, &Default::default(), 0, 1)
                     .await?
                     .1,
                 svc.instance
-                    .query_admin_server(None, None, Some(0), None, 0, 1)
+                    .query_admin_server(None, None, Some(0), None, &Default::default(), 0, 1)
                     .await?
                     .1,
             )
         } else {
             (
                 svc.instance
-                    .query_user_server(user_info.user_id.clone(), None, None, Some(1), None, 0, 1)
+                    .query_user_server(
+                        user_info.user_id.clone(),
+                        None,
+                        None,
+                        Some(1),
+                        None,
+                        &Default::default(),
+                        0,
+                        1,
+                    )
                     .await?
                     .1,
                 svc.instance
-                    .query_user_server(user_info.user_id.clone(), None, None, Some(0), None, 0, 1)
+                    .query_user_server(
+                        user_info.user_id.clone(),
+                        None,
+                        None,
+                        Some(0),
+                        None,
+                        &Default::default(),
+                        0,
+                        1,
+                    )
                     .await?
                     .1,
             )
@@ -565,4 +1110,496 @@ impl InstanceApi {
             instance_offline_num: offline_num,
         });
     }
+
+    #[oai(
+        path = "/bootstrap-token/create",
+        method = "post",
+        transform = "manage_middleware"
+    )]
+    pub async fn create_bootstrap_token(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::CreateBootstrapTokenReq>,
+    ) -> api_response!(types::CreateBootstrapTokenResp) {
+        let token = state
+            .service()
+            .instance
+            .create_bootstrap_token(
+                req.namespace.filter(|v| v != ""),
+                req.single_use,
+                req.expire_secs.filter(|v| *v > 0),
+                req.info,
+                user_info.username.clone(),
+            )
+            .await?;
+        return_ok!(types::CreateBootstrapTokenResp { token })
+    }
+
+    #[oai(
+        path = "/bootstrap-token/list",
+        method = "get",
+        transform = "manage_middleware"
+    )]
+    pub async fn query_bootstrap_token(
+        &self,
+        state: Data<&AppState>,
+    ) -> api_response!(types::QueryBootstrapTokenResp) {
+        let list = state
+            .service()
+            .instance
+            .query_bootstrap_token()
+            .await?
+            .into_iter()
+            .map(|v| types::BootstrapTokenRecord {
+                id: v.id,
+                namespace: v.namespace,
+                single_use: v.single_use,
+                expire_time: v.expire_time.map(format_timestamp),
+                info: v.info,
+                created_user: v.created_user,
+                created_time: format_timestamp(v.created_time),
+            })
+            .collect();
+        return_ok!(types::QueryBootstrapTokenResp { list })
+    }
+
+    #[oai(
+        path = "/bootstrap-token/revoke",
+        method = "post",
+        transform = "manage_middleware"
+    )]
+    pub async fn revoke_bootstrap_token(
+        &self,
+        state: Data<&AppState>,
+        Json(req): Json<types::RevokeBootstrapTokenReq>,
+    ) -> api_response!(types::RevokeResp) {
+        let result = state
+            .service()
+            .instance
+            .revoke_bootstrap_token(&req.id)
+            .await?;
+        return_ok!(types::RevokeResp { result })
+    }
+
+    #[oai(
+        path = "/credential/list",
+        method = "get",
+        transform = "manage_middleware"
+    )]
+    pub async fn query_agent_credential(
+        &self,
+        state: Data<&AppState>,
+    ) -> api_response!(types::QueryAgentCredentialResp) {
+        let list = state
+            .service()
+            .instance
+            .query_agent_credential()
+            .await?
+            .into_iter()
+            .map(|v| types::AgentCredentialRecord {
+                id: v.id,
+                namespace: v.namespace,
+                mac_addr: v.mac_addr,
+                agent_ip: v.agent_ip,
+                token_id: v.token_id,
+                created_time: format_timestamp(v.created_time),
+            })
+            .collect();
+        return_ok!(types::QueryAgentCredentialResp { list })
+    }
+
+    #[oai(
+        path = "/credential/revoke",
+        method = "post",
+        transform = "manage_middleware"
+    )]
+    pub async fn revoke_agent_credential(
+        &self,
+        state: Data<&AppState>,
+        Json(req): Json<types::RevokeAgentCredentialReq>,
+    ) -> api_response!(types::RevokeResp) {
+        let (id, mac_addr) = (req.id.filter(|v| v != ""), req.mac_addr.filter(|v| v != ""));
+        if id.is_none() && mac_addr.is_none() {
+            return_err!("id or mac_addr is required");
+        }
+        let result = state
+            .service()
+            .instance
+            .revoke_agent_credential(id, mac_addr)
+            .await?;
+        return_ok!(types::RevokeResp { result })
+    }
+
+    /// the live connections of the agents on all the comets
+    #[oai(
+        path = "/connection/list",
+        method = "get",
+        transform = "manage_middleware"
+    )]
+    pub async fn query_agent_connection(
+        &self,
+        state: Data<&AppState>,
+    ) -> api_response!(types::QueryAgentConnectionResp) {
+        let (list, errors) = state.service().instance.query_agent_connections().await?;
+        let list = list
+            .into_iter()
+            .map(|v| types::AgentConnectionRecord {
+                agent_key: v.agent_key,
+                namespace: v.namespace,
+                agent_ip: v.agent_ip,
+                mac_addr: v.mac_addr,
+                protocol_version: v.protocol_version,
+                comet_addr: v.comet_addr,
+                connected_time: format_timestamp(v.connected_at),
+                last_heartbeat_time: v.last_heartbeat.map(format_timestamp),
+                msgs_received: v.msgs_received,
+                msgs_sent: v.msgs_sent,
+            })
+            .collect();
+        return_ok!(types::QueryAgentConnectionResp { list, errors })
+    }
+
+    /// force the agent to reconnect, eg: to pick up a rotated credential
+    #[oai(
+        path = "/connection/disconnect",
+        method = "post",
+        transform = "manage_middleware"
+    )]
+    pub async fn disconnect_agent(
+        &self,
+        state: Data<&AppState>,
+        Json(req): Json<types::DisconnectAgentReq>,
+    ) -> api_response!(types::DisconnectAgentResp) {
+        state
+            .service()
+            .instance
+            .disconnect_agent(req.agent_ip, req.mac_addr)
+            .await?;
+        return_ok!(types::DisconnectAgentResp { result: true })
+    }
+
+    /// the recent log lines of the agent itself, poll with since set to the last next_seq
+    /// to follow the log
+    #[oai(
+        path = "/connection/log",
+        method = "get",
+        transform = "manage_middleware"
+    )]
+    pub async fn get_agent_log(
+        &self,
+        state: Data<&AppState>,
+        Query(agent_ip): Query<String>,
+        Query(mac_addr): Query<String>,
+        #[oai(default)] Query(since): Query<u64>,
+        #[oai(
+            default = "types::default_agent_log_limit",
+            validator(maximum(value = "2000"))
+        )]
+        Query(limit): Query<usize>,
+        #[oai(default)] Query(keyword): Query<String>,
+    ) -> api_response!(types::AgentLogResp) {
+        let ret = state
+            .service()
+            .instance
+            .get_agent_log(
+                agent_ip,
+                mac_addr,
+                AgentLogParams {
+                    since,
+                    limit,
+                    keyword,
+                },
+            )
+            .await?;
+        return_ok!(types::AgentLogResp {
+            enabled: ret.enabled,
+            lines: ret
+                .lines
+                .into_iter()
+                .map(|v| types::AgentLogLine {
+                    seq: v.seq,
+                    line: v.line,
+                })
+                .collect(),
+            next_seq: ret.next_seq,
+        })
+    }
+
+    /// the config of the agents by namespace and the overrides of the instances
+    #[oai(
+        path = "/agent-config/list",
+        method = "get",
+        transform = "manage_middleware"
+    )]
+    pub async fn query_agent_config(
+        &self,
+        state: Data<&AppState>,
+        Query(namespace): Query<Option<String>>,
+    ) -> api_response!(types::QueryAgentConfigResp) {
+        let list = state
+            .service()
+            .agent_config
+            .query_config(namespace.filter(|v| v != ""))
+            .await?
+            .into_iter()
+            .map(|v| types::AgentConfigRecord {
+                id: v.id,
+                namespace: v.namespace,
+                instance_id: v.instance_id,
+                config: serde_json::from_value::<automate::bridge::msg::AgentConfig>(v.config)
+                    .unwrap_or_default()
+                    .into(),
+                created_user: v.created_user,
+                updated_user: v.updated_user,
+                updated_time: local_time!(v.updated_time),
+            })
+            .collect();
+        return_ok!(types::QueryAgentConfigResp { list })
+    }
+
+    /// save the config and push it to the online agents, the agents keep it locally
+    /// and prefer it over their flags
+    #[oai(
+        path = "/agent-config/save",
+        method = "post",
+        transform = "manage_middleware"
+    )]
+    pub async fn save_agent_config(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::SaveAgentConfigReq>,
+    ) -> api_response!(types::PushAgentConfigResp) {
+        let config: automate::bridge::msg::AgentConfig = req.config.into();
+        if let Err(e) = logic::agent_config::check_config(&config) {
+            return_err!(e.to_string());
+        }
+        let pushed = state
+            .service()
+            .agent_config
+            .save_config(req.namespace, req.instance_id, config, &user_info.username)
+            .await?;
+        return_ok!(types::PushAgentConfigResp {
+            pushed: pushed.into_iter().map(Into::into).collect(),
+        })
+    }
+
+    /// delete the config, the agents fall back to the namespace config or their flags
+    #[oai(
+        path = "/agent-config/delete",
+        method = "post",
+        transform = "manage_middleware"
+    )]
+    pub async fn delete_agent_config(
+        &self,
+        state: Data<&AppState>,
+        Json(req): Json<types::DeleteAgentConfigReq>,
+    ) -> api_response!(types::PushAgentConfigResp) {
+        let pushed = state.service().agent_config.delete_config(req.id).await?;
+        return_ok!(types::PushAgentConfigResp {
+            pushed: pushed.into_iter().map(Into::into).collect(),
+        })
+    }
+
+    /// the config the agents should have and the one they report in the heartbeat
+    #[oai(
+        path = "/agent-config/drift",
+        method = "get",
+        transform = "manage_middleware"
+    )]
+    pub async fn query_agent_config_drift(
+        &self,
+        state: Data<&AppState>,
+        Query(namespace): Query<Option<String>>,
+    ) -> api_response!(types::QueryAgentConfigDriftResp) {
+        let list = state
+            .service()
+            .agent_config
+            .query_drift(namespace.filter(|v| v != ""))
+            .await?
+            .into_iter()
+            .map(|v| {
+                let drift = v.drift.unwrap_or_default();
+                types::AgentConfigDriftRecord {
+                    instance_id: v.instance_id,
+                    ip: v.ip,
+                    namespace: v.namespace,
+                    status: v.status,
+                    desired: v.desired.into(),
+                    supported: v.reported.is_some(),
+                    saved: v.reported.as_ref().map(|v| v.config.clone().into()),
+                    effective: v.reported.map(|v| v.effective.into()),
+                    drift_fields: drift.drift_fields,
+                    pending_restart: drift.pending_restart,
+                }
+            })
+            .collect();
+        return_ok!(types::QueryAgentConfigDriftResp { list })
+    }
+
+    /// run the install script of the executor on the instances in the background
+    #[oai(
+        path = "/executor/ensure",
+        method = "post",
+        transform = "manage_middleware"
+    )]
+    pub async fn ensure_executor(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::EnsureExecutorReq>,
+    ) -> api_response!(types::EnsureExecutorResp) {
+        let task_id = state
+            .service()
+            .task
+            .enqueue(
+                BackgroundTask::EnsureExecutor(EnsureExecutorTask {
+                    executor_id: req.executor_id,
+                    instance_ids: req.instance_ids,
+                }),
+                None,
+                user_info.username.clone(),
+            )
+            .await?;
+        return_ok!(types::EnsureExecutorResp { task_id })
+    }
+
+    /// executors ensured on the instance and whether they are available
+    #[oai(path = "/executor/list", method = "get")]
+    pub async fn query_instance_executor(
+        &self,
+        state: Data<&AppState>,
+        _user_info: Data<&logic::types::UserInfo>,
+        Query(instance_id): Query<String>,
+    ) -> api_response!(types::QueryInstanceExecutorResp) {
+        let svc = state.service();
+        let records = svc.executor.get_instance_executors(&instance_id).await?;
+        let executors = svc
+            .executor
+            .get_all_by_executor_id(records.iter().map(|v| v.executor_id).collect())
+            .await?;
+        let list = records
+            .into_iter()
+            .map(|v| types::InstanceExecutorRecord {
+                executor_name: executors
+                    .get_by_id(v.executor_id)
+                    .map(|e| e.name)
+                    .unwrap_or_default(),
+                executor_id: v.executor_id,
+                status: v.status,
+                exit_code: v.exit_code,
+                output: v.output,
+                updated_user: v.updated_user,
+                updated_time: local_time!(v.updated_time),
+            })
+            .collect();
+        return_ok!(types::QueryInstanceExecutorResp { list })
+    }
+
+    /// ssh credentials of the instance, the secrets are never returned
+    #[oai(
+        path = "/ssh-credential/list",
+        method = "get",
+        transform = "manage_middleware"
+    )]
+    pub async fn query_ssh_credential(
+        &self,
+        state: Data<&AppState>,
+        Query(instance_id): Query<String>,
+    ) -> api_response!(types::QuerySshCredentialResp) {
+        let list = state
+            .service()
+            .credential
+            .query_credential(&instance_id)
+            .await?
+            .into_iter()
+            .map(|v| types::SshCredentialRecord {
+                id: v.id,
+                instance_id: v.instance_id,
+                name: v.name,
+                cred_type: v.cred_type,
+                sys_user: v.sys_user,
+                scopes: v
+                    .scopes
+                    .split(',')
+                    .filter(|v| !v.is_empty())
+                    .map(|v| v.to_string())
+                    .collect(),
+                rotate_days: v.rotate_days,
+                rotated_time: default_local_time!(v.rotated_time),
+                created_user: v.created_user,
+                updated_user: v.updated_user,
+                created_time: local_time!(v.created_time),
+                updated_time: local_time!(v.updated_time),
+            })
+            .collect();
+        return_ok!(types::QuerySshCredentialResp { list })
+    }
+
+    #[oai(
+        path = "/ssh-credential/save",
+        method = "post",
+        transform = "manage_middleware"
+    )]
+    pub async fn save_ssh_credential(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::SaveSshCredentialReq>,
+    ) -> api_response!(types::SaveSshCredentialResp) {
+        let id = state
+            .service()
+            .credential
+            .save_credential(
+                logic::credential::SaveCredentialParams {
+                    id: req.id,
+                    instance_id: req.instance_id,
+                    name: req.name,
+                    cred_type: req.cred_type,
+                    sys_user: req.sys_user,
+                    secret: req.secret,
+                    passphrase: req.passphrase,
+                    scopes: req.scopes,
+                    rotate_days: req.rotate_days,
+                },
+                &user_info.username,
+            )
+            .await?;
+        return_ok!(types::SaveSshCredentialResp { id })
+    }
+
+    #[oai(
+        path = "/ssh-credential/delete",
+        method = "post",
+        transform = "manage_middleware"
+    )]
+    pub async fn delete_ssh_credential(
+        &self,
+        state: Data<&AppState>,
+        Json(req): Json<types::SshCredentialIdReq>,
+    ) -> api_response!(types::DeleteSshCredentialResp) {
+        let result = state.service().credential.delete_credential(req.id).await?;
+        return_ok!(types::DeleteSshCredentialResp { result })
+    }
+
+    /// set a new random password for the credential now
+    #[oai(
+        path = "/ssh-credential/rotate",
+        method = "post",
+        transform = "manage_middleware"
+    )]
+    pub async fn rotate_ssh_credential(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::SshCredentialIdReq>,
+    ) -> api_response!(types::RotateSshCredentialResp) {
+        state
+            .service()
+            .credential
+            .rotate_credential(req.id, &user_info.username)
+            .await?;
+        return_ok!(types::RotateSshCredentialResp { result: true })
+    }
 }
@@ -1,34 +1,214 @@
 use std::{
+    collections::HashMap,
     num::{NonZeroI32, NonZeroU64},
     time::Duration,
 };
 
 use crate::{
     api_response, default_local_time,
-    entity::{job, job_bundle_script, job_supervisor},
-    error::NoPermission,
+    entity::{job, job_bundle_script, job_remediation, job_supervisor, job_trigger},
+    error::{conflict_into_error, NoPermission},
     local_time,
-    logic::{self, job::types::BundleScriptRecord},
+    logic::{
+        self,
+        job::types::BundleScriptRecord,
+        role::{POLICY_ALLOW_CHANGE_ALL_JOB, POLICY_ALLOW_DISPATCH_JOB},
+    },
     middleware,
     response::std_into_error,
     return_err, return_ok, AppState,
 };
 
-use service::{logic::types::CustomTimerExpr, IdGenerator};
+use service::{
+    logic::{
+        job::ExportHistoryTask,
+        task::{BackgroundTask, DispatchJobTask},
+        types::CustomTimerExpr,
+    },
+    IdGenerator,
+};
 
-use super::types;
+use super::{file::types::GetFileResponse, types};
 use crate::api::types::CompletedCallbackOpts;
 use automate::{scheduler::types::ScheduleType, JobAction};
 use poem::{session::Session, web::Data, Endpoint, EndpointExt};
 use poem_openapi::{
     param::{Header, Query},
-    payload::Json,
+    payload::{Attachment, AttachmentType, Json, PlainText},
     OpenApi,
 };
 use sea_orm::{ActiveValue::NotSet, Set};
 use serde_json::json;
+
+/// max duration anomalies shown in the dashboard
+const DASHBOARD_ANOMALY_LIMIT: u64 = 50;
+
 fn set_middleware(ep: impl Endpoint) -> impl Endpoint {
     ep.with(middleware::TeamPermissionMiddleware)
+        .with(middleware::PermissionMiddleware::grant(
+            &POLICY_ALLOW_CHANGE_ALL_JOB,
+        ))
+}
+
+fn manage_middleware(ep: impl Endpoint) -> impl Endpoint {
+    ep.with(middleware::TeamPermissionMiddleware)
+        .with(middleware::PermissionMiddleware::new(
+            &POLICY_ALLOW_CHANGE_ALL_JOB,
+        ))
+}
+
+fn dispatch_middleware(ep: impl Endpoint) -> impl Endpoint {
+    ep.with(middleware::TeamPermissionMiddleware)
+        .with(middleware::PermissionMiddleware::new(
+            &POLICY_ALLOW_DISPATCH_JOB,
+        ))
+}
+
+/// actions which start an execution are checked against blackout windows
+fn is_starting_action(action: JobAction) -> bool {
+    matches!(
+        action,
+        JobAction::Exec
+            | JobAction::StartTimer
+            | JobAction::StartSupervising
+            | JobAction::RestartSupervising
+    )
+}
+
+/// the global code policies are managed by the user admins, those of a team by its admins
+async fn can_write_code_policy(
+    state: &AppState,
+    user_info: &logic::types::UserInfo,
+    team_id: u64,
+) -> anyhow::Result<bool> {
+    if team_id == 0 {
+        return state.can_manage_user(&user_info.user_id).await;
+    }
+    state
+        .service()
+        .team
+        .can_write_team(Some(team_id), user_info.user_id.clone())
+        .await
+}
+
+fn code_policy_rule_record(
+    v: crate::entity::code_policy_rule::Model,
+) -> types::CodePolicyRuleRecord {
+    types::CodePolicyRuleRecord {
+        updated_time: (v.id != 0).then(|| local_time!(v.updated_time)),
+        id: v.id,
+        team_id: v.team_id,
+        name: v.name,
+        pattern: v.pattern,
+        action: v.action,
+        is_enabled: v.is_enabled,
+        info: v.info,
+        updated_user: v.updated_user,
+    }
+}
+
+fn run_lineage_node(v: logic::job::RunNode) -> types::RunLineageNode {
+    types::RunLineageNode {
+        run_id: v.run_id,
+        parent_run_id: v.parent_run_id,
+        run_kind: v.run_kind,
+        execs: v
+            .execs
+            .into_iter()
+            .map(|v| types::RunLineageExec {
+                id: v.id,
+                instance_id: v.instance_id,
+                result_status: v.result_status,
+                exit_code: v.exit_code,
+                start_time: default_local_time!(v.start_time),
+                end_time: default_local_time!(v.end_time),
+            })
+            .collect(),
+        children: v.children.into_iter().map(run_lineage_node).collect(),
+    }
+}
+
+/// dispatch the job in the request, or queue it as a background task
+async fn dispatch_task(
+    state: &AppState,
+    task: DispatchJobTask,
+    background: bool,
+    team_id: Option<u64>,
+    username: &str,
+) -> anyhow::Result<types::DispatchJobResp> {
+    let svc = state.service();
+    if background {
+        let task_id = svc
+            .task
+            .enqueue(
+                BackgroundTask::DispatchJob(Box::new(task)),
+                team_id,
+                username.to_string(),
+            )
+            .await?;
+        return Ok(types::DispatchJobResp {
+            result: 0,
+            task_id: Some(task_id),
+            challenge: None,
+        });
+    }
+
+    let ret = svc
+        .job
+        .dispatch_job(
+            state.conf.comet_secret.clone(),
+            task.instance_ids,
+            task.eid,
+            task.is_sync,
+            task.schedule_name,
+            task.schedule_type.as_str().try_into()?,
+            task.action.as_str().try_into()?,
+            task.timer_expr,
+            task.restart_interval.map(Duration::from_secs),
+            task.args,
+            task.overrides,
+            username.to_string(),
+            task.canary,
+        )
+        .await?;
+
+    if let Some(override_reason) = task.override_reason.filter(|v| !v.is_empty()) {
+        svc.job
+            .set_schedule_override_reason(ret, override_reason)
+            .await?;
+    }
+    Ok(types::DispatchJobResp {
+        result: ret,
+        task_id: None,
+        challenge: None,
+    })
+}
+
+/// only the users able to write the resource can restore or purge it
+async fn can_write_deleted(
+    state: &AppState,
+    user_info: &logic::types::UserInfo,
+    team_id: Option<u64>,
+    req: &types::RecycleBinReq,
+) -> anyhow::Result<bool> {
+    let svc = state.service();
+    Ok(match req.resource_type {
+        types::RecycleResourceType::Job => {
+            svc.job
+                .can_write_job_by_id(user_info, team_id, Some(req.id))
+                .await?
+        }
+        types::RecycleResourceType::Timer => {
+            svc.job
+                .can_write_job_timer_by_id(user_info, team_id, Some(req.id))
+                .await?
+        }
+        types::RecycleResourceType::Supervisor => {
+            svc.job
+                .can_write_job_supervisor_by_id(user_info, team_id, Some(req.id))
+                .await?
+        }
+    })
 }
 
 pub struct JobApi;
@@ -59,6 +239,35 @@ impl JobApi {
             return Err(NoPermission().into());
         }
 
+        svc.executor
+            .check_executor_permission(
+                &user_info,
+                team_id,
+                std::iter::once(req.executor_id)
+                    .chain(req.bundle_script.iter().flatten().map(|v| v.executor_id))
+                    .collect(),
+            )
+            .await?;
+
+        let code_policy_waivers: Vec<logic::job::code_policy::CodePolicyWaiver> = req
+            .code_policy_waivers
+            .into_iter()
+            .map(|v| v.into())
+            .collect();
+        let scripts: Vec<(String, String)> =
+            std::iter::once((req.name.clone(), req.code.clone().unwrap_or_default()))
+                .chain(
+                    req.bundle_script
+                        .iter()
+                        .flatten()
+                        .map(|v| (v.name.clone(), v.code.clone())),
+                )
+                .collect();
+        let code_policy_warnings = svc
+            .job
+            .check_code_policy(team_id.unwrap_or_default(), &scripts, &code_policy_waivers)
+            .await?;
+
         let args: Vec<logic::job::types::JobFormalArg> =
             req.args.into_iter().map(|v| v.into()).collect();
 
@@ -75,8 +284,45 @@ impl JobApi {
             NotSet
         };
 
+        let bundle_option = match req.bundle_option {
+            Some(v) if req.bundle_script.is_some() => {
+                let data: automate::scheduler::types::BundleOption = v.into();
+                Set(Some(serde_json::to_value(data).map_err(std_into_error)?))
+            }
+            _ => Set(None),
+        };
+
+        let timeout_action = match req.timeout_action {
+            Some(v) => {
+                let data: automate::scheduler::types::TimeoutAction = v.into();
+                Set(Some(serde_json::to_value(data).map_err(std_into_error)?))
+            }
+            None => Set(None),
+        };
+
+        let output_limit = match req.output_limit {
+            Some(v) => {
+                let data: automate::scheduler::types::OutputLimit = v.into();
+                Set(Some(serde_json::to_value(data).map_err(std_into_error)?))
+            }
+            None => Set(None),
+        };
+
+        let supervisor_option = match req.supervisor_option {
+            Some(v) => {
+                let data: automate::scheduler::types::SupervisorOption = v.into();
+                Set(Some(serde_json::to_value(data).map_err(std_into_error)?))
+            }
+            None => Set(None),
+        };
+
         let (job_type, bundle_script) = match req.bundle_script {
             Some(v) => {
+                for script in v.iter().filter(|v| !v.cond_expr.is_empty()) {
+                    if let Err(e) = logic::job::cond_expr::check(&script.cond_expr) {
+                        return_err!(format!("invalid cond_expr of {}, {e}", script.name));
+                    }
+                }
                 let list: Vec<BundleScriptRecord> = v
                     .iter()
                     .map(|v| BundleScriptRecord {
@@ -94,9 +340,84 @@ impl JobApi {
                     Set(Some(serde_json::to_value(&list).map_err(std_into_error)?)),
                 )
             }
+            None if req.http_check.is_some() => (Set("http".to_string()), NotSet),
             None => (Set("default".to_string()), NotSet),
         };
 
+        let http_check = match req.http_check {
+            Some(v) => {
+                let data: automate::scheduler::types::HttpCheck = v.into();
+                Set(Some(serde_json::to_value(data).map_err(std_into_error)?))
+            }
+            None => Set(None),
+        };
+
+        let output_parsers: Vec<logic::job::types::OutputParser> =
+            req.output_parsers.into_iter().map(|v| v.into()).collect();
+        for v in output_parsers.iter() {
+            if let Err(e) = v.check() {
+                return_err!(format!("invalid output parser {}, {e}", v.name));
+            }
+        }
+        let output_parsers = if output_parsers.is_empty() {
+            Set(None)
+        } else {
+            Set(Some(
+                serde_json::to_value(output_parsers).map_err(std_into_error)?,
+            ))
+        };
+
+        let exit_code_mapping: HashMap<i32, logic::job::types::ExecResultStatus> = req
+            .exit_code_mapping
+            .into_iter()
+            .map(|(k, v)| (k, v.into()))
+            .collect();
+        let exit_code_mapping = if exit_code_mapping.is_empty() {
+            Set(None)
+        } else {
+            Set(Some(
+                serde_json::to_value(exit_code_mapping).map_err(std_into_error)?,
+            ))
+        };
+
+        let placement = req.placement.unwrap_or_default();
+        if !logic::job::PLACEMENTS.contains(&placement.as_str()) {
+            return_err!(format!("invalid placement {placement}"));
+        }
+
+        let offline_policy = match req.offline_policy {
+            Some(v) => {
+                let data: logic::job::OfflinePolicy = v.into();
+                if let Err(e) = data.check() {
+                    return_err!(e.to_string());
+                }
+                Set(Some(serde_json::to_value(data).map_err(std_into_error)?))
+            }
+            None => Set(None),
+        };
+
+        let quorum = match req.quorum {
+            Some(v) => {
+                let data: logic::job::QuorumOption = v.into();
+                if let Err(e) = data.check() {
+                    return_err!(e.to_string());
+                }
+                Set(Some(serde_json::to_value(data).map_err(std_into_error)?))
+            }
+            None => Set(None),
+        };
+
+        let confirmation = match req.confirmation {
+            Some(v) => {
+                let data: logic::job::JobConfirmation = v.into();
+                if let Err(e) = data.check() {
+                    return_err!(e.to_string());
+                }
+                Set(Some(serde_json::to_value(data).map_err(std_into_error)?))
+            }
+            None => Set(None),
+        };
+
         let (eid, id, created_user) = match req.id {
             Some(v) => (NotSet, Set(v), NotSet),
             None => (
@@ -108,37 +429,66 @@ impl JobApi {
 
         let ret = svc
             .job
-            .save_job(job::ActiveModel {
-                id,
-                eid,
-                executor_id: Set(req.executor_id),
-                name: Set(req.name),
-                code: Set(req.code.unwrap_or_default()),
-                info: Set(req.info.unwrap_or_default()),
-                work_dir: Set(req.work_dir.unwrap_or_default()),
-                work_user: Set(req.work_user.unwrap_or_default()),
-                max_retry: Set(req.max_retry.unwrap_or(1)),
-                max_parallel: Set(req.max_parallel.unwrap_or(1)),
-                timeout: Set(req.timeout.unwrap_or(60)),
-                bundle_script,
-                job_type,
-                upload_file: Set(req.upload_file.unwrap_or_default()),
-                is_public: Set(req.is_public.map_or(0, |v| match v {
-                    true => 1,
-                    false => 0,
-                })),
-                display_on_dashboard: Set(req.display_on_dashboard.unwrap_or(false)),
-                created_user,
-                updated_user: Set(user_info.username.clone()),
-                args: args,
-                team_id: team_id.map_or(NotSet, |v| Set(v)),
-                completed_callback,
-                ..Default::default()
-            })
+            .save_job(
+                job::ActiveModel {
+                    id,
+                    eid,
+                    executor_id: Set(req.executor_id),
+                    name: Set(req.name),
+                    code: Set(req.code.unwrap_or_default()),
+                    info: Set(req.info.unwrap_or_default()),
+                    work_dir: Set(req.work_dir.unwrap_or_default()),
+                    work_user: Set(req.work_user.unwrap_or_default()),
+                    max_retry: Set(req.max_retry.unwrap_or(1)),
+                    max_parallel: Set(req.max_parallel.unwrap_or(1)),
+                    timeout: Set(req.timeout.unwrap_or(60)),
+                    bundle_script,
+                    bundle_option,
+                    supervisor_option,
+                    timeout_action,
+                    output_limit,
+                    job_type,
+                    upload_file: Set(req.upload_file.unwrap_or_default()),
+                    is_public: Set(req.is_public.map_or(0, |v| match v {
+                        true => 1,
+                        false => 0,
+                    })),
+                    display_on_dashboard: Set(req.display_on_dashboard.unwrap_or(false)),
+                    is_singleton: Set(req.is_singleton.unwrap_or(false)),
+                    placement: Set(placement),
+                    dedup_window_secs: Set(req.dedup_window_secs.unwrap_or_default()),
+                    offline_policy,
+                    quorum,
+                    confirmation,
+                    created_user,
+                    updated_user: Set(user_info.username.clone()),
+                    args: args,
+                    team_id: team_id.map_or(NotSet, |v| Set(v)),
+                    completed_callback,
+                    http_check,
+                    output_parsers,
+                    exit_code_mapping,
+                    ..Default::default()
+                },
+                req.version,
+            )
+            .await
+            .map_err(conflict_into_error)?;
+
+        let job_id = ret.id.as_ref().to_owned();
+        svc.job
+            .record_code_policy_waivers(
+                job_id,
+                &code_policy_warnings,
+                &code_policy_waivers,
+                &user_info.username,
+            )
             .await?;
 
         return_ok!(types::SaveJobResp {
-            result: ret.id.as_ref().to_owned()
+            result: job_id,
+            version: ret.version.as_ref().to_owned(),
+            code_policy_warnings: code_policy_warnings.into_iter().map(|v| v.into()).collect(),
         });
     }
 
@@ -148,6 +498,7 @@ impl JobApi {
         state: Data<&AppState>,
         _session: &Session,
         user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
 
         #[oai(default = "types::default_page", validator(maximum(value = "10000")))]
         Query(page): Query<u64>,
@@ -176,7 +527,7 @@ impl JobApi {
             .get_validate_team_id_by_job_or_default(&user_info, default_eid.as_deref(), team_id)
             .await?;
 
-        let search_username = if state.can_manage_job(&user_info.user_id).await? {
+        let search_username = if granted.manage_job() {
             search_username
         } else {
             team_id.map_or_else(|| Some(user_info.username.clone()), |_| search_username)
@@ -193,6 +544,7 @@ impl JobApi {
                 default_eid,
                 team_id,
                 tag_ids,
+                svc.tag.get_tag_scope(&user_info).await?,
                 page - 1,
                 page_size,
             )
@@ -224,7 +576,55 @@ impl JobApi {
                 team_id: v.team_id,
                 team_name: v.team_name,
                 display_on_dashboard: v.display_on_dashboard,
+                is_disabled: v.is_disabled,
+                is_singleton: v.is_singleton,
+                placement: v.placement,
+                dedup_window_secs: v.dedup_window_secs,
+                offline_policy: v
+                    .offline_policy
+                    .map(|v| serde_json::from_value::<logic::job::OfflinePolicy>(v))
+                    .transpose()
+                    .unwrap_or_default()
+                    .map(|v| types::OfflinePolicy::from(v)),
+                quorum: v
+                    .quorum
+                    .map(|v| serde_json::from_value::<logic::job::QuorumOption>(v))
+                    .transpose()
+                    .unwrap_or_default()
+                    .map(|v| types::QuorumOption::from(v)),
+                confirmation: v
+                    .confirmation
+                    .map(|v| serde_json::from_value::<logic::job::JobConfirmation>(v))
+                    .transpose()
+                    .unwrap_or_default()
+                    .map(|v| types::JobConfirmation::from(v)),
                 bundle_script: v.bundle_script,
+                bundle_option: v
+                    .bundle_option
+                    .map(|v| serde_json::from_value::<automate::scheduler::types::BundleOption>(v))
+                    .transpose()
+                    .unwrap_or_default()
+                    .map(|v| types::BundleOption::from(v)),
+                supervisor_option: v
+                    .supervisor_option
+                    .map(|v| {
+                        serde_json::from_value::<automate::scheduler::types::SupervisorOption>(v)
+                    })
+                    .transpose()
+                    .unwrap_or_default()
+                    .map(|v| types::SupervisorOption::from(v)),
+                timeout_action: v
+                    .timeout_action
+                    .map(|v| serde_json::from_value::<automate::scheduler::types::TimeoutAction>(v))
+                    .transpose()
+                    .unwrap_or_default()
+                    .map(|v| types::TimeoutAction::from(v)),
+                output_limit: v
+                    .output_limit
+                    .map(|v| serde_json::from_value::<automate::scheduler::types::OutputLimit>(v))
+                    .transpose()
+                    .unwrap_or_default()
+                    .map(|v| types::OutputLimit::from(v)),
                 is_public: v.is_public == 1,
                 job_type: v.job_type,
                 created_user: v.created_user,
@@ -236,6 +636,34 @@ impl JobApi {
                     .transpose()
                     .unwrap_or_default()
                     .map(|v| CompletedCallbackOpts::from(v)),
+                http_check: v
+                    .http_check
+                    .map(|v| serde_json::from_value::<automate::scheduler::types::HttpCheck>(v))
+                    .transpose()
+                    .unwrap_or_default()
+                    .map(|v| types::HttpCheck::from(v)),
+                output_parsers: v
+                    .output_parsers
+                    .map(|v| serde_json::from_value::<Vec<logic::job::types::OutputParser>>(v))
+                    .transpose()
+                    .unwrap_or_default()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(types::OutputParser::from)
+                    .collect(),
+                exit_code_mapping: v
+                    .exit_code_mapping
+                    .map(|v| {
+                        serde_json::from_value::<HashMap<i32, logic::job::types::ExecResultStatus>>(
+                            v,
+                        )
+                    })
+                    .transpose()
+                    .unwrap_or_default()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(k, v)| (k, v.into()))
+                    .collect(),
                 tags: Some(
                     tag_records
                         .iter()
@@ -257,6 +685,7 @@ impl JobApi {
                 max_retry: v.max_retry,
                 max_parallel: v.max_parallel,
                 upload_file: v.upload_file,
+                version: v.version,
                 created_time: local_time!(v.created_time),
                 updated_time: local_time!(v.updated_time),
             })
@@ -288,7 +717,147 @@ impl JobApi {
         return_ok!(types::DeleteJobResp { result })
     }
 
-    #[oai(path = "/dispatch", method = "post", transform = "set_middleware")]
+    /// delete the jobs in a transaction, nothing is deleted if any of them fails
+    #[oai(path = "/bulk/delete", method = "post", transform = "set_middleware")]
+    pub async fn bulk_delete_job(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Json(req): Json<types::BulkJobReq>,
+    ) -> api_response!(types::BulkJobResp) {
+        let (applied, results) = state
+            .service()
+            .job
+            .bulk_delete_job(&user_info, team_id, &req.eids)
+            .await?;
+        return_ok!(types::BulkJobResp {
+            applied,
+            results: results.into_iter().map(Into::into).collect(),
+        })
+    }
+
+    #[oai(path = "/bulk/bind-tag", method = "post", transform = "set_middleware")]
+    pub async fn bulk_bind_tag(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Json(req): Json<types::BulkBindTagReq>,
+    ) -> api_response!(types::BulkJobResp) {
+        let (applied, results) = state
+            .service()
+            .job
+            .bulk_bind_tag(&user_info, team_id, &req.eids, &req.tag_name)
+            .await?;
+        return_ok!(types::BulkJobResp {
+            applied,
+            results: results.into_iter().map(Into::into).collect(),
+        })
+    }
+
+    #[oai(
+        path = "/bulk/set-disabled",
+        method = "post",
+        transform = "set_middleware"
+    )]
+    pub async fn bulk_set_disabled(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Json(req): Json<types::BulkSetDisabledReq>,
+    ) -> api_response!(types::BulkJobResp) {
+        let (applied, results) = state
+            .service()
+            .job
+            .bulk_set_disabled(&user_info, team_id, &req.eids, req.disabled)
+            .await?;
+        return_ok!(types::BulkJobResp {
+            applied,
+            results: results.into_iter().map(Into::into).collect(),
+        })
+    }
+
+    /// execute every job once on the same endpoints, the dispatches are independent
+    #[oai(
+        path = "/bulk/dispatch",
+        method = "post",
+        transform = "dispatch_middleware"
+    )]
+    pub async fn bulk_dispatch(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Json(req): Json<types::BulkDispatchReq>,
+    ) -> api_response!(types::BulkJobResp) {
+        let svc = state.service();
+        svc.blackout
+            .check_manual_dispatch(team_id, req.override_reason.as_deref())
+            .await?;
+
+        let overrides = types::endpoint_overrides(&req.endpoints);
+        let instance_ids: Vec<String> = req.endpoints.into_iter().map(|v| v.instance_id).collect();
+        let mut results = Vec::with_capacity(req.eids.len());
+        for eid in req.eids {
+            if !svc
+                .job
+                .can_dispatch_job(&user_info, team_id, None, &eid)
+                .await?
+            {
+                results.push(types::BulkItemResult {
+                    eid,
+                    message: "no permission".to_string(),
+                    ..Default::default()
+                });
+                continue;
+            }
+            let ret = svc
+                .job
+                .dispatch_job(
+                    state.conf.comet_secret.clone(),
+                    instance_ids.clone(),
+                    eid.clone(),
+                    false,
+                    req.schedule_name.clone().unwrap_or_default(),
+                    ScheduleType::Once,
+                    JobAction::Exec,
+                    None,
+                    None,
+                    req.args.clone(),
+                    overrides.clone(),
+                    user_info.username.clone(),
+                    None,
+                )
+                .await;
+            results.push(match ret {
+                Ok(id) => {
+                    if let Some(reason) = req.override_reason.clone().filter(|v| !v.is_empty()) {
+                        svc.job.set_schedule_override_reason(id, reason).await?;
+                    }
+                    types::BulkItemResult {
+                        eid,
+                        ok: true,
+                        id,
+                        ..Default::default()
+                    }
+                }
+                Err(e) => types::BulkItemResult {
+                    eid,
+                    message: e.to_string(),
+                    ..Default::default()
+                },
+            });
+        }
+
+        return_ok!(types::BulkJobResp {
+            applied: results.iter().any(|v| v.ok),
+            results,
+        })
+    }
+
+    #[oai(path = "/dispatch", method = "post", transform = "dispatch_middleware")]
     pub async fn dispatch(
         &self,
         state: Data<&AppState>,
@@ -298,8 +867,7 @@ impl JobApi {
     ) -> api_response!(types::DispatchJobResp) {
         let svc = state.service();
         let action = req.action.as_str().try_into()?;
-        let schedule_type = req.schedule_type.as_str().try_into()?;
-        let secret = state.conf.comet_secret.clone();
+        ScheduleType::try_from(req.schedule_type.as_str())?;
 
         if !svc
             .job
@@ -309,26 +877,143 @@ impl JobApi {
             return Err(NoPermission().into());
         }
 
-        let ret = svc
+        if is_starting_action(action) {
+            svc.blackout
+                .check_manual_dispatch(team_id, req.override_reason.as_deref())
+                .await?;
+        }
+
+        let task = DispatchJobTask {
+            overrides: types::endpoint_overrides(&req.endpoints),
+            instance_ids: req.endpoints.into_iter().map(|v| v.instance_id).collect(),
+            eid: req.eid,
+            is_sync: req.is_sync,
+            schedule_name: req.schedule_name,
+            schedule_type: req.schedule_type,
+            action: req.action,
+            timer_expr: req.timer_expr.map(|v| v.into()),
+            restart_interval: req.restart_interval,
+            args: req.args,
+            override_reason: req.override_reason,
+            canary: req.canary.map(|v| v.into()),
+        };
+
+        if is_starting_action(action) {
+            let job_record = svc
+                .job
+                .get_job_by_eid(&task.eid)
+                .await?
+                .ok_or(anyhow::anyhow!("cannot found job {}", task.eid))?;
+            svc.executor
+                .check_job_executor_permission(&user_info, &job_record)
+                .await?;
+            if let Some(confirmation) = logic::job::JobLogic::get_confirmation(&job_record)? {
+                let challenge = svc
+                    .job
+                    .create_dispatch_challenge(
+                        &job_record,
+                        confirmation,
+                        task,
+                        req.background,
+                        team_id,
+                        &user_info,
+                    )
+                    .await?;
+                return_ok!(types::DispatchJobResp {
+                    challenge: Some(challenge.into()),
+                    ..Default::default()
+                });
+            }
+        }
+
+        let ret = dispatch_task(&state, task, req.background, team_id, &user_info.username).await?;
+        return_ok!(ret)
+    }
+
+    /// dispatch the job kept by the challenge with the typed values,
+    /// the challenge can only be confirmed once by the user who created it
+    #[oai(
+        path = "/dispatch/confirm",
+        method = "post",
+        transform = "dispatch_middleware"
+    )]
+    pub async fn confirm_dispatch(
+        &self,
+        state: Data<&AppState>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Json(req): Json<types::ConfirmDispatchReq>,
+        user_info: Data<&logic::types::UserInfo>,
+    ) -> api_response!(types::DispatchJobResp) {
+        let svc = state.service();
+        let pending = svc
             .job
-            .dispatch_job(
-                secret,
-                req.endpoints.into_iter().map(|v| v.instance_id).collect(),
-                req.eid,
-                req.is_sync,
-                req.schedule_name,
-                schedule_type,
-                action,
-                req.timer_expr.map(|v| v.into()),
-                req.restart_interval.map(|v| Duration::from_secs(v)),
-                req.args,
-                user_info.username.clone(),
+            .confirm_dispatch_challenge(
+                &req.challenge_id,
+                &user_info,
+                &req.confirm_text,
+                &req.values.into_iter().collect(),
             )
             .await?;
-        return_ok!(types::DispatchJobResp { result: ret })
+        if pending.team_id != team_id {
+            return_err!("the challenge is created in another team");
+        }
+
+        if !svc
+            .job
+            .can_dispatch_job(&user_info, team_id, None, &pending.task.eid)
+            .await?
+        {
+            return Err(NoPermission().into());
+        }
+
+        svc.blackout
+            .check_manual_dispatch(team_id, pending.task.override_reason.as_deref())
+            .await?;
+
+        let job_record = svc
+            .job
+            .get_job_by_eid(&pending.task.eid)
+            .await?
+            .ok_or(anyhow::anyhow!("cannot found job {}", pending.task.eid))?;
+        svc.executor
+            .check_job_executor_permission(&user_info, &job_record)
+            .await?;
+
+        let ret = dispatch_task(
+            &state,
+            pending.task,
+            pending.background,
+            team_id,
+            &user_info.username,
+        )
+        .await?;
+        return_ok!(ret)
+    }
+
+    /// run the job code, which may be unsaved, on a sandbox instance and return the output,
+    /// nothing is recorded in the history
+    #[oai(path = "/test-run", method = "post", transform = "dispatch_middleware")]
+    pub async fn test_run(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::TestRunJobReq>,
+    ) -> api_response!(types::TestRunJobResp) {
+        let svc = state.service();
+        svc.executor
+            .check_executor_permission(&user_info, None, vec![req.executor_id])
+            .await?;
+        let output = svc.job.test_run(req.into(), &user_info.username).await?;
+        return_ok!(types::TestRunJobResp {
+            instance_id: output.instance_id,
+            exit_code: output.exit_code,
+            stdout: output.stdout,
+            stderr: output.stderr,
+            duration_ms: output.duration_ms,
+        })
     }
 
-    #[oai(path = "/schedule", method = "post", transform = "set_middleware")]
+    #[oai(path = "/schedule", method = "post", transform = "dispatch_middleware")]
     pub async fn schedule(
         &self,
         state: Data<&AppState>,
@@ -373,12 +1058,27 @@ impl JobApi {
             return Err(NoPermission().into());
         }
 
+        if is_starting_action(action) {
+            svc.blackout
+                .check_manual_dispatch(team_id, req.override_reason.as_deref())
+                .await?;
+            svc.executor
+                .check_job_executor_permission(&user_info, &job_record)
+                .await?;
+        }
+
         let instances: Vec<String> = serde_json::from_value(
             schedule_record
                 .instance_ids
                 .ok_or(anyhow::format_err!("instances is required"))?,
         )
         .map_err(std_into_error)?;
+        let overrides = schedule_record
+            .instance_overrides
+            .map(serde_json::from_value)
+            .transpose()
+            .map_err(std_into_error)?
+            .unwrap_or_default();
 
         let ret = svc
             .job
@@ -394,14 +1094,26 @@ impl JobApi {
                 NonZeroI32::new(schedule_record.restart_interval)
                     .map(|v| Duration::from_secs(v.get() as u64)),
                 schedule_record.actual_args,
+                overrides,
                 user_info.username.clone(),
                 NonZeroU64::new(schedule_record.id),
+                None,
             )
             .await?;
+
+        if let Some(override_reason) = req.override_reason.filter(|v| !v.is_empty()) {
+            svc.job
+                .set_schedule_override_reason(ret, override_reason)
+                .await?;
+        }
         return_ok!(types::ScheduleJobResp { result: ret })
     }
 
-    #[oai(path = "/redispatch", method = "post", transform = "set_middleware")]
+    #[oai(
+        path = "/redispatch",
+        method = "post",
+        transform = "dispatch_middleware"
+    )]
     pub async fn redispatch(
         &self,
         state: Data<&AppState>,
@@ -436,6 +1148,17 @@ impl JobApi {
             );
         }
 
+        if is_starting_action(action) {
+            svc.blackout
+                .check_manual_dispatch(team_id, req.override_reason.as_deref())
+                .await?;
+            if let Some(override_reason) = req.override_reason.clone().filter(|v| !v.is_empty()) {
+                svc.job
+                    .set_schedule_override_reason(schedule_record.id, override_reason)
+                    .await?;
+            }
+        }
+
         let ret = svc
             .job
             .redispatch_job(
@@ -463,6 +1186,110 @@ impl JobApi {
         return_ok!(ret)
     }
 
+    #[oai(
+        path = "/exec-rerun",
+        method = "post",
+        transform = "dispatch_middleware"
+    )]
+    pub async fn exec_rerun(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Json(req): Json<types::ExecRerunReq>,
+    ) -> api_response!(types::ExecRerunResp) {
+        let svc = state.service();
+        let secret = state.conf.comet_secret.clone();
+
+        let Some(exec_record) = svc.job.get_exec_history(req.exec_history_id).await? else {
+            return_err!("cannot found exec history");
+        };
+
+        let schedule_record = svc
+            .job
+            .get_schedule_history(&exec_record.schedule_id)
+            .await?
+            .ok_or(anyhow::anyhow!(
+                "cannot found job schedule by schedule_id: {}",
+                exec_record.schedule_id
+            ))?;
+
+        if !svc
+            .job
+            .can_dispatch_job(&user_info, team_id, None, &exec_record.eid)
+            .await?
+        {
+            return Err(NoPermission().into());
+        }
+
+        svc.blackout
+            .check_manual_dispatch(team_id, req.override_reason.as_deref())
+            .await?;
+
+        let ret = svc
+            .job
+            .rerun_exec(
+                secret,
+                &exec_record,
+                schedule_record,
+                req.instance_id,
+                user_info.username.clone(),
+            )
+            .await?;
+
+        return_ok!(types::DispatchJobResult {
+            namespace: ret.namespace,
+            ip: ret.bind_ip,
+            response: ret.response,
+            has_err: ret.has_err,
+            call_err: ret.err,
+        })
+    }
+
+    /// the runs of the schedule with their retries and reruns,
+    /// looked up by the schedule or by any of its runs
+    #[oai(path = "/run-lineage", method = "get", transform = "set_middleware")]
+    pub async fn get_run_lineage(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        #[oai(default)] Query(schedule_id): Query<Option<String>>,
+        #[oai(default)] Query(run_id): Query<Option<String>>,
+    ) -> api_response!(types::GetRunLineageResp) {
+        let svc = state.service();
+        let schedule_id = schedule_id.filter(|v| v != "");
+        let run_id = run_id.filter(|v| v != "");
+        if schedule_id.is_none() && run_id.is_none() {
+            return_err!("schedule_id or run_id is required");
+        }
+
+        let ret = svc.job.get_run_lineage(schedule_id, run_id).await?;
+
+        if !granted.manage_job()
+            && ret.schedule.created_user != user_info.username
+            && !svc
+                .job
+                .can_dispatch_job(&user_info, team_id, None, &ret.schedule.eid)
+                .await?
+        {
+            return Err(NoPermission().into());
+        }
+
+        return_ok!(types::GetRunLineageResp {
+            schedule_id: ret.schedule.schedule_id,
+            schedule_name: ret.schedule.name,
+            schedule_type: ret.schedule.schedule_type,
+            eid: ret.schedule.eid,
+            action: ret.schedule.action,
+            created_user: ret.schedule.created_user,
+            created_time: local_time!(ret.schedule.created_time),
+            truncated: ret.truncated,
+            runs: ret.runs.into_iter().map(run_lineage_node).collect(),
+        })
+    }
+
     #[oai(
         path = "/running-status-list",
         method = "get",
@@ -473,6 +1300,7 @@ impl JobApi {
         state: Data<&AppState>,
         _session: &Session,
         user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
 
         #[oai(default)] Query(bind_ip): Query<Option<String>>,
         #[oai(default)] Query(schedule_name): Query<Option<String>>,
@@ -481,7 +1309,9 @@ impl JobApi {
             custom = "super::OneOfValidator::new(vec![\"once\",\"timer\",\"flow\",\"daemon\"])"
         ))]
         Query(schedule_type): Query<String>,
-        #[oai(validator(custom = "super::OneOfValidator::new(vec![\"bundle\", \"default\"])"))]
+        #[oai(validator(
+            custom = "super::OneOfValidator::new(vec![\"bundle\", \"default\", \"http\"])"
+        ))]
         Query(job_type): Query<String>,
         #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
         Query(tag_ids): Query<Option<Vec<u64>>>,
@@ -499,7 +1329,7 @@ impl JobApi {
     ) -> api_response!(types::QueryRunResp) {
         let svc = state.service();
         let updated_time_range = updated_time_range.map(|v| (v[0].clone(), v[1].clone()));
-        let search_username = if state.can_manage_job(&user_info.user_id).await? {
+        let search_username = if granted.manage_job() {
             search_username
         } else {
             team_id.map_or_else(|| Some(user_info.username.clone()), |_| search_username)
@@ -515,6 +1345,7 @@ impl JobApi {
                 Some(job_type.clone()),
                 updated_time_range,
                 tag_ids,
+                svc.tag.get_tag_scope(&user_info).await?,
                 page - 1,
                 page_size,
             )
@@ -539,6 +1370,7 @@ impl JobApi {
                 id: v.id,
                 instance_id: v.instance_id,
                 is_online: v.is_online,
+                maintenance: v.maintenance,
                 eid: v.eid,
                 executor_id: v.executor_id,
                 executor_name: v.executor_name,
@@ -579,6 +1411,7 @@ impl JobApi {
                 run_status: v.run_status,
                 exit_status: v.exit_status,
                 exit_code: v.exit_code,
+                result_status: v.result_status,
                 job_type: v.job_type,
                 dispatch_result: v.dispatch_result,
                 start_time: v.start_time.map_or("".to_string(), |t| local_time!(t)),
@@ -603,6 +1436,7 @@ impl JobApi {
         state: Data<&AppState>,
         _session: &Session,
         user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
         Query(search_username): Query<Option<String>>,
         #[oai(validator(
             custom = "super::OneOfValidator::new(vec![\"once\",\"timer\",\"flow\",\"daemon\"])"
@@ -626,7 +1460,7 @@ impl JobApi {
     ) -> api_response!(types::QueryScheduleHistoryResp) {
         let svc = state.service();
         let updated_time_range = updated_time_range.map(|v| (v[0].clone(), v[1].clone()));
-        let search_username = if state.can_manage_job(&user_info.user_id).await? {
+        let search_username = if granted.manage_job() {
             search_username
         } else {
             team_id.map_or_else(|| Some(user_info.username.clone()), |_| search_username)
@@ -641,6 +1475,7 @@ impl JobApi {
                 team_id,
                 updated_time_range,
                 tag_ids,
+                svc.tag.get_tag_scope(&user_info).await?,
                 page - 1,
                 page_size,
             )
@@ -677,6 +1512,15 @@ impl JobApi {
                 dispatch_result: v.dispatch_result,
                 action: v.action,
                 actual_args: v.actual_args,
+                placement: v.placement,
+                quorum: logic::job::QuorumVerdict::from_history(
+                    v.quorum_status,
+                    v.quorum_required,
+                    v.target_total,
+                    v.success_total,
+                    v.failure_total,
+                )
+                .map(|v| v.into()),
                 tags: Some(
                     tag_records
                         .iter()
@@ -736,7 +1580,7 @@ impl JobApi {
         let sched: Option<CustomTimerExpr> = req.timer_expr.map_or(None, |expr| Some(expr.into()));
 
         let next_exec_times = match sched {
-            Some(ref v) => Some(utils::check_timer_expr(&v.timezone, &v.expr)?),
+            Some(ref v) => Some(v.check()?),
             None => None,
         };
 
@@ -752,6 +1596,7 @@ impl JobApi {
                 req.name,
                 sched,
                 req.args,
+                types::endpoint_overrides(&req.endpoints),
                 user_info.username.clone(),
             )
             .await?;
@@ -768,6 +1613,7 @@ impl JobApi {
         state: Data<&AppState>,
         _session: &Session,
         user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
         Query(search_username): Query<Option<String>>,
         #[oai(validator(
             custom = "super::OneOfValidator::new(vec![\"once\",\"timer\",\"flow\",\"daemon\"])"
@@ -791,7 +1637,7 @@ impl JobApi {
     ) -> api_response!(types::QueryScheduleResp) {
         let svc = state.service();
         let updated_time_range = updated_time_range.map(|v| (v[0].clone(), v[1].clone()));
-        let search_username = if state.can_manage_job(&user_info.user_id).await? {
+        let search_username = if granted.manage_job() {
             search_username
         } else {
             team_id.map_or_else(|| Some(user_info.username.clone()), |_| search_username)
@@ -806,6 +1652,7 @@ impl JobApi {
                 team_id,
                 updated_time_range,
                 tag_ids,
+                svc.tag.get_tag_scope(&user_info).await?,
                 page - 1,
                 page_size,
             )
@@ -844,6 +1691,7 @@ impl JobApi {
                 instance_ids: v
                     .instance_ids
                     .map_or(vec![], |v| serde_json::from_value(v).unwrap_or(vec![])),
+                instance_overrides: v.instance_overrides,
                 tags: Some(
                     tag_records
                         .iter()
@@ -875,11 +1723,14 @@ impl JobApi {
         state: Data<&AppState>,
         _session: &Session,
         user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
         #[oai(default)] Query(bind_namespace): Query<Option<String>>,
         #[oai(default)] Query(bind_ip): Query<Option<String>>,
         #[oai(default)] Query(instance_id): Query<Option<String>>,
         Query(search_username): Query<Option<String>>,
-        #[oai(validator(custom = "super::OneOfValidator::new(vec![\"bundle\", \"default\"])"))]
+        #[oai(validator(
+            custom = "super::OneOfValidator::new(vec![\"bundle\", \"default\", \"http\"])"
+        ))]
         Query(job_type): Query<String>,
         #[oai(default)] Query(schedule_name): Query<Option<String>>,
         #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
@@ -891,6 +1742,14 @@ impl JobApi {
         #[oai(default)] Query(schedule_pid): Query<Option<u64>>,
         #[oai(default)] Query(schedule_id): Query<Option<String>>,
         #[oai(default)] Query(eid): Query<Option<String>>,
+        /// only the runs that extracted the metric
+        #[oai(default)]
+        Query(metric): Query<Option<String>>,
+        /// plain by default, color keeps the ansi colors, raw returns the output unchanged
+        #[oai(validator(
+            custom = "super::OneOfValidator::new(vec![\"plain\",\"color\",\"raw\"])"
+        ))]
+        Query(output_mode): Query<Option<String>>,
 
         /// Search based on time range
         #[oai(validator(max_items = 2, min_items = 2))]
@@ -907,7 +1766,7 @@ impl JobApi {
         let start_time_range = start_time_range.map(|v| (v[0].clone(), v[1].clone()));
         let svc = state.service();
 
-        let search_username = if state.can_manage_job(&user_info.user_id).await? {
+        let search_username = if granted.manage_job() {
             search_username
         } else {
             team_id.map_or_else(|| Some(user_info.username.clone()), |_| search_username)
@@ -928,6 +1787,8 @@ impl JobApi {
                 bind_ip,
                 start_time_range,
                 tag_ids,
+                svc.tag.get_tag_scope(&user_info).await?,
+                metric.filter(|v| v != ""),
                 page - 1,
                 page_size,
             )
@@ -956,13 +1817,15 @@ impl JobApi {
                 is_online: v.is_online,
                 exit_status: v.exit_status,
                 exit_code: v.exit_code,
+                result_status: v.result_status,
                 job_name: v.job_name,
-                output: v.output,
+                output: types::sanitize_output(v.output, output_mode.as_deref()),
                 job_type: v.job_type,
                 team_id: v.team_id,
                 team_name: v.team_name,
                 created_user: v.created_user,
                 bundle_script_result: v.bundle_script_result,
+                parsed_result: v.parsed_result,
                 start_time: Some(default_local_time!(v.start_time)),
                 end_time: Some(default_local_time!(v.end_time)),
                 tags: Some(
@@ -983,6 +1846,14 @@ impl JobApi {
                 created_time: local_time!(v.created_time),
                 updated_time: local_time!(v.updated_time),
                 schedule_name: v.schedule_name,
+                rerun_of: v.rerun_of,
+                run_id: v.run_id,
+                parent_run_id: v.parent_run_id,
+                run_kind: v.run_kind,
+                exec_context: v.exec_context,
+                timeout_action: v.timeout_action,
+                output_truncated: v.output_truncated,
+                output_url: v.output_url,
             })
             .collect();
         return_ok!(types::QueryExecResp {
@@ -1141,7 +2012,7 @@ impl JobApi {
         return_ok!(types::DeleteExecHistoryResp { result })
     }
 
-    #[oai(path = "/action", method = "post", transform = "set_middleware")]
+    #[oai(path = "/action", method = "post", transform = "dispatch_middleware")]
     pub async fn action(
         &self,
         state: Data<&AppState>,
@@ -1193,6 +2064,10 @@ impl JobApi {
             return_err!("no permission to delete this job");
         }
 
+        svc.executor
+            .check_executor_permission(&user_info, team_id, vec![req.executor_id])
+            .await?;
+
         let (eid, id, created_user) = match req.id {
             Some(v) => (NotSet, Set(v), NotSet),
             None => (
@@ -1204,23 +2079,28 @@ impl JobApi {
 
         let ret = svc
             .job
-            .save_job_bundle_script(job_bundle_script::ActiveModel {
-                id,
-                eid,
-                executor_id: Set(req.executor_id),
-                team_id: team_id.map_or(NotSet, |v| Set(v)),
-                name: Set(req.name),
-                code: Set(req.code),
-                info: Set(req.info),
-                created_user,
-                updated_user: Set(user_info.username.clone()),
-                args: Set(args),
-                ..Default::default()
-            })
-            .await?;
+            .save_job_bundle_script(
+                job_bundle_script::ActiveModel {
+                    id,
+                    eid,
+                    executor_id: Set(req.executor_id),
+                    team_id: team_id.map_or(NotSet, |v| Set(v)),
+                    name: Set(req.name),
+                    code: Set(req.code),
+                    info: Set(req.info),
+                    created_user,
+                    updated_user: Set(user_info.username.clone()),
+                    args: Set(args),
+                    ..Default::default()
+                },
+                req.version,
+            )
+            .await
+            .map_err(conflict_into_error)?;
 
         return_ok!(types::SaveJobBundleScriptResp {
-            result: ret.id.as_ref().to_owned()
+            result: ret.id.as_ref().to_owned(),
+            version: ret.version.as_ref().to_owned(),
         });
     }
 
@@ -1259,6 +2139,7 @@ impl JobApi {
         state: Data<&AppState>,
         _session: &Session,
         user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
         Query(search_username): Query<Option<String>>,
         #[oai(default)] Query(name): Query<Option<String>>,
         Query(default_eid): Query<Option<String>>,
@@ -1287,7 +2168,7 @@ impl JobApi {
             )
             .await?;
 
-        let search_username = if state.can_manage_job(&user_info.user_id).await? {
+        let search_username = if granted.manage_job() {
             search_username
         } else {
             team_id.map_or_else(|| Some(user_info.username.clone()), |_| search_username)
@@ -1322,6 +2203,7 @@ impl JobApi {
                 created_user: v.created_user,
                 updated_user: v.updated_user,
                 args: v.args,
+                version: v.version,
                 created_time: local_time!(v.created_time),
                 updated_time: local_time!(v.updated_time),
             })
@@ -1338,6 +2220,7 @@ impl JobApi {
         state: Data<&AppState>,
         _session: &Session,
         user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
         #[oai(default)] Query(name): Query<Option<String>>,
         #[oai(default)] Query(job_type): Query<Option<String>>,
         #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
@@ -1359,7 +2242,7 @@ impl JobApi {
         let updated_time_range = updated_time_range.map(|v| (v[0].clone(), v[1].clone()));
         let svc = state.service();
 
-        let search_username = if state.can_manage_job(&user_info.user_id).await? {
+        let search_username = if granted.manage_job() {
             search_username.as_ref()
         } else {
             team_id.map_or(Some(&user_info.username), |_| search_username.as_ref())
@@ -1374,6 +2257,7 @@ impl JobApi {
                 job_type.clone().filter(|v| v != ""),
                 updated_time_range,
                 tag_ids,
+                svc.tag.get_tag_scope(&user_info).await?,
                 page - 1,
                 page_size,
             )
@@ -1424,6 +2308,7 @@ impl JobApi {
                 executor_platform: v.executor_platform,
                 created_user: v.created_user,
                 updated_user: v.updated_user,
+                version: v.version,
                 created_time: local_time!(v.created_time),
                 updated_time: local_time!(v.updated_time),
             })
@@ -1455,7 +2340,7 @@ impl JobApi {
 
         let sched: logic::types::CustomTimerExpr = req.timer_expr.clone().into();
 
-        let next_exec_times = utils::check_timer_expr(&sched.timezone, &sched.expr)?;
+        let next_exec_times = sched.check()?;
 
         let job_args: Vec<logic::job::types::JobFormalArg> =
             req.job_args.into_iter().map(|v| v.into()).collect();
@@ -1470,24 +2355,29 @@ impl JobApi {
 
         let ret = svc
             .job
-            .save_job_timer(crate::entity::job_timer::ActiveModel {
-                id: req.id.map_or(NotSet, |v| Set(v)),
-                name: Set(req.name),
-                eid: Set(req.eid),
-                timer_expr: Set(Some(
-                    serde_json::to_value(req.timer_expr).map_err(std_into_error)?,
-                )),
-                job_type: Set(req.job_type),
-                info: Set(req.info),
-                job_args,
-                created_user: req.id.map_or(Set(user_info.username.clone()), |_| NotSet),
-                updated_user: Set(user_info.username.clone()),
-                ..Default::default()
-            })
-            .await?;
+            .save_job_timer(
+                crate::entity::job_timer::ActiveModel {
+                    id: req.id.map_or(NotSet, |v| Set(v)),
+                    name: Set(req.name),
+                    eid: Set(req.eid),
+                    timer_expr: Set(Some(
+                        serde_json::to_value(req.timer_expr).map_err(std_into_error)?,
+                    )),
+                    job_type: Set(req.job_type),
+                    info: Set(req.info),
+                    job_args,
+                    created_user: req.id.map_or(Set(user_info.username.clone()), |_| NotSet),
+                    updated_user: Set(user_info.username.clone()),
+                    ..Default::default()
+                },
+                req.version,
+            )
+            .await
+            .map_err(conflict_into_error)?;
 
         return_ok!(types::SaveJobTimerResp {
             result: ret.id.as_ref().to_owned(),
+            version: ret.version.as_ref().to_owned(),
             next_exec_times
         });
     }
@@ -1512,6 +2402,39 @@ impl JobApi {
         return_ok!(types::DeleteJobTimerResp { result });
     }
 
+    /// preview the ticks of the timer in the next hours, eg: the next 24 hours
+    #[oai(
+        path = "/simulate-timer",
+        method = "post",
+        transform = "set_middleware"
+    )]
+    pub async fn simulate_timer(
+        &self,
+        state: Data<&AppState>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Json(req): Json<types::SimulateTimerReq>,
+    ) -> api_response!(types::SimulateTimerResp) {
+        let svc = state.service();
+        let timer_expr: logic::types::CustomTimerExpr = req.timer_expr.into();
+        timer_expr.check()?;
+
+        let ticks = svc
+            .job
+            .simulate_timer(team_id, &timer_expr, req.hours)
+            .await?;
+        return_ok!(types::SimulateTimerResp {
+            total: ticks.len() as u64,
+            truncated: ticks.len() >= logic::job::TIMER_SIMULATION_LIMIT,
+            list: ticks
+                .into_iter()
+                .map(|v| types::SimulatedTick {
+                    time: local_time!(v.time),
+                    blackout_window: v.blackout_window,
+                })
+                .collect(),
+        })
+    }
+
     #[oai(path = "/dashboard", method = "post", transform = "set_middleware")]
     pub async fn get_dashboard(
         &self,
@@ -1553,8 +2476,33 @@ impl JobApi {
             })
             .collect();
 
+        let anomalies = svc
+            .job
+            .get_duration_anomalies(
+                &user_info,
+                chrono::TimeDelta::days(1),
+                DASHBOARD_ANOMALY_LIMIT,
+            )
+            .await?
+            .into_iter()
+            .map(|v| types::DurationAnomalyRecord {
+                id: v.id,
+                eid: v.eid,
+                job_name: v.job_name,
+                instance_id: v.instance_id,
+                ip: v.ip,
+                schedule_id: v.schedule_id,
+                run_id: v.run_id,
+                exec_history_id: v.exec_history_id,
+                duration_ms: v.duration_ms,
+                median_ms: v.median_ms,
+                created_time: local_time!(v.created_time),
+            })
+            .collect();
+
         return_ok!(types::GetDashboardResp {
             rows,
+            anomalies,
             job_num: job_summary.total,
             running_num: job_summary.running_num,
             exec_succ_num: job_summary.exec_succ_num,
@@ -1562,6 +2510,271 @@ impl JobApi {
         });
     }
 
+    /// jobs with the most failed runs of the latest days
+    #[oai(
+        path = "/dashboard/top-failing",
+        method = "get",
+        transform = "set_middleware"
+    )]
+    pub async fn get_top_failing_jobs(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(
+            default = "types::default_dashboard_days",
+            validator(minimum(value = "1"), maximum(value = "30"))
+        )]
+        Query(days): Query<i64>,
+        #[oai(
+            default = "types::default_top_limit",
+            validator(maximum(value = "100"))
+        )]
+        Query(limit): Query<u64>,
+    ) -> api_response!(types::GetJobRunTopResp) {
+        let list = state
+            .service()
+            .job
+            .get_top_failing_jobs(&user_info, days, limit)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        return_ok!(types::GetJobRunTopResp { list })
+    }
+
+    /// jobs with the longest average duration of the latest days
+    #[oai(
+        path = "/dashboard/slowest",
+        method = "get",
+        transform = "set_middleware"
+    )]
+    pub async fn get_slowest_jobs(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(
+            default = "types::default_dashboard_days",
+            validator(minimum(value = "1"), maximum(value = "30"))
+        )]
+        Query(days): Query<i64>,
+        #[oai(
+            default = "types::default_top_limit",
+            validator(maximum(value = "100"))
+        )]
+        Query(limit): Query<u64>,
+    ) -> api_response!(types::GetJobRunTopResp) {
+        let list = state
+            .service()
+            .job
+            .get_slowest_jobs(&user_info, days, limit)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        return_ok!(types::GetJobRunTopResp { list })
+    }
+
+    /// instances running the most jobs of the latest days
+    #[oai(
+        path = "/dashboard/busiest-instances",
+        method = "get",
+        transform = "set_middleware"
+    )]
+    pub async fn get_busiest_instances(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(
+            default = "types::default_dashboard_days",
+            validator(minimum(value = "1"), maximum(value = "30"))
+        )]
+        Query(days): Query<i64>,
+        #[oai(
+            default = "types::default_top_limit",
+            validator(maximum(value = "100"))
+        )]
+        Query(limit): Query<u64>,
+    ) -> api_response!(types::GetInstanceRunTopResp) {
+        let list = state
+            .service()
+            .job
+            .get_busiest_instances(&user_info, days, limit)
+            .await?
+            .into_iter()
+            .map(|v| types::InstanceRunTopRecord {
+                instance_id: v.instance_id,
+                ip: v.ip,
+                run_total: v.run_total,
+                fail_total: v.fail_total,
+            })
+            .collect();
+        return_ok!(types::GetInstanceRunTopResp { list })
+    }
+
+    /// runs of each day, one point per day ending at today
+    #[oai(
+        path = "/dashboard/trend",
+        method = "get",
+        transform = "set_middleware"
+    )]
+    pub async fn get_daily_run_trend(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(
+            default = "types::default_trend_days",
+            validator(minimum(value = "1"), maximum(value = "30"))
+        )]
+        Query(days): Query<i64>,
+    ) -> api_response!(types::GetDailyRunTrendResp) {
+        let list = state
+            .service()
+            .job
+            .get_daily_run_trend(&user_info, days)
+            .await?
+            .into_iter()
+            .map(|v| types::DailyRunTrendRecord {
+                stat_date: v.stat_date.format("%Y-%m-%d").to_string(),
+                run_total: v.run_total,
+                fail_total: v.fail_total,
+                avg_duration_ms: v.avg_duration_ms,
+            })
+            .collect();
+        return_ok!(types::GetDailyRunTrendResp { list })
+    }
+
+    /// runtime of the month grouped by team, job or instance for the charge back,
+    /// the users other than the job managers only see their teams
+    #[oai(path = "/usage/report", method = "get", transform = "set_middleware")]
+    pub async fn get_usage_report(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Query(month): Query<String>,
+        #[oai(default)] Query(group_by): Query<types::UsageGroupBy>,
+        Query(team_id): Query<Option<u64>>,
+    ) -> api_response!(types::GetUsageReportResp) {
+        let list = state
+            .service()
+            .job
+            .get_usage_report(&user_info, &month, group_by.into(), team_id)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        return_ok!(types::GetUsageReportResp { month, list })
+    }
+
+    /// the usage report as csv
+    #[oai(
+        path = "/usage/report/export",
+        method = "get",
+        transform = "set_middleware"
+    )]
+    pub async fn export_usage_report(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Query(month): Query<String>,
+        #[oai(default)] Query(group_by): Query<types::UsageGroupBy>,
+        Query(team_id): Query<Option<u64>>,
+    ) -> GetFileResponse {
+        let data = match state
+            .service()
+            .job
+            .get_usage_report(&user_info, &month, group_by.into(), team_id)
+            .await
+            .and_then(|v| logic::job::JobLogic::usage_report_csv(&v))
+        {
+            Ok(v) => v,
+            Err(e) => return GetFileResponse::InternalError(PlainText(e.to_string())),
+        };
+        GetFileResponse::Ok(
+            Attachment::new(data)
+                .attachment_type(AttachmentType::Attachment)
+                .filename(format!("jiascheduler-usage-{month}.csv")),
+        )
+    }
+
+    #[oai(
+        path = "/dispatch-stats",
+        method = "get",
+        transform = "manage_middleware"
+    )]
+    pub async fn get_dispatch_stats(
+        &self,
+        state: Data<&AppState>,
+        #[oai(default)] Query(window): Query<types::DispatchStatWindow>,
+    ) -> api_response!(types::GetDispatchStatResp) {
+        let stat = state
+            .service()
+            .job
+            .get_dispatch_stat(window.duration())
+            .await?;
+
+        return_ok!(types::GetDispatchStatResp {
+            dispatch_total: stat.dispatch_total,
+            dispatch_failed: stat.dispatch_failed,
+            success_rate: stat.success_rate,
+            latency_samples: stat.latency_samples,
+            latency_p50: stat.latency_p50,
+            latency_p95: stat.latency_p95,
+            comets: stat
+                .comets
+                .into_iter()
+                .map(|v| types::CometDispatchStat {
+                    comet_addr: v.comet_addr,
+                    dispatch_total: v.dispatch_total,
+                    dispatch_failed: v.dispatch_failed,
+                })
+                .collect(),
+        });
+    }
+
+    #[oai(
+        path = "/supervisor-metrics",
+        method = "get",
+        transform = "set_middleware"
+    )]
+    pub async fn get_supervisor_metrics(
+        &self,
+        state: Data<&AppState>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        user_info: Data<&logic::types::UserInfo>,
+        Query(eid): Query<String>,
+        Query(instance_id): Query<Option<String>>,
+        #[oai(default)] Query(window): Query<types::DispatchStatWindow>,
+    ) -> api_response!(types::GetSupervisorMetricsResp) {
+        let svc = state.service();
+        if !svc
+            .job
+            .can_dispatch_job(&user_info, team_id, None, &eid)
+            .await?
+        {
+            return Err(NoPermission().into());
+        }
+
+        let list = svc
+            .job
+            .get_supervisor_metrics(eid, instance_id, window.duration())
+            .await?;
+
+        return_ok!(types::GetSupervisorMetricsResp {
+            list: list
+                .into_iter()
+                .map(|v| types::SupervisorMetricRecord {
+                    schedule_id: v.schedule_id,
+                    instance_id: v.instance_id,
+                    run_id: v.run_id,
+                    cpu_usage: v.cpu_usage,
+                    rss: v.rss,
+                    process_count: v.process_count,
+                    sample_time: local_time!(v.sample_time),
+                })
+                .collect(),
+        });
+    }
+
     #[oai(
         path = "/supervisor-list",
         method = "get",
@@ -1572,6 +2785,7 @@ impl JobApi {
         state: Data<&AppState>,
         _session: &Session,
         user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
         #[oai(default)] Query(name): Query<Option<String>>,
         Query(eid): Query<Option<String>>,
         /// Search based on time range
@@ -1590,7 +2804,7 @@ impl JobApi {
     ) -> api_response!(types::QueryJobSupervisorResp) {
         let updated_time_range = updated_time_range.map(|v| (v[0].clone(), v[1].clone()));
         let svc = state.service();
-        let search_username = if state.can_manage_job(&user_info.user_id).await? {
+        let search_username = if granted.manage_job() {
             search_username.as_ref()
         } else {
             team_id.map_or_else(|| Some(&user_info.username), |_| search_username.as_ref())
@@ -1604,6 +2818,7 @@ impl JobApi {
                 team_id,
                 updated_time_range,
                 tag_ids,
+                svc.tag.get_tag_scope(&user_info).await?,
                 page - 1,
                 page_size,
             )
@@ -1744,4 +2959,844 @@ impl JobApi {
         let result = svc.job.delete_job_supervisor(&user_info, req.id).await?;
         return_ok!(types::DeleteJobSupervisorResp { result });
     }
+
+    /// soft-deleted jobs, timers and supervisors, job managers see all of them,
+    /// others see the ones of the team or deleted by themselves
+    #[oai(path = "/recycle-bin", method = "get", transform = "set_middleware")]
+    pub async fn query_recycle_bin(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        #[oai(default)] Query(resource_type): Query<types::RecycleResourceType>,
+        #[oai(default = "super::default_page", validator(maximum(value = "10000")))]
+        Query(page): Query<u64>,
+        #[oai(
+            default = "super::default_page_size",
+            validator(maximum(value = "10000"))
+        )]
+        Query(page_size): Query<u64>,
+    ) -> api_response!(types::QueryRecycleBinResp) {
+        let deleted_by = if team_id.is_some() || granted.manage_job() {
+            None
+        } else {
+            Some(user_info.username.clone())
+        };
+
+        let (list, total) = state
+            .service()
+            .job
+            .query_recycle_bin(
+                resource_type.into(),
+                team_id,
+                deleted_by,
+                page - 1,
+                page_size,
+            )
+            .await?;
+
+        let days = state.conf.recycle_bin_days();
+        let list = list
+            .into_iter()
+            .map(|v| types::DeletedResourceRecord {
+                id: v.id,
+                eid: v.eid,
+                name: v.name,
+                job_name: v.job_name,
+                team_id: v.team_id,
+                team_name: v.team_name,
+                created_user: v.created_user,
+                deleted_by: v.deleted_by,
+                deleted_at: v.deleted_at.map(|v| local_time!(v)),
+                purge_at: v
+                    .deleted_at
+                    .filter(|_| days > 0)
+                    .map(|v| local_time!(v + chrono::TimeDelta::days(days as i64))),
+            })
+            .collect();
+
+        return_ok!(types::QueryRecycleBinResp { total, list })
+    }
+
+    #[oai(
+        path = "/recycle-bin/restore",
+        method = "post",
+        transform = "set_middleware"
+    )]
+    pub async fn restore_deleted(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Json(req): Json<types::RecycleBinReq>,
+    ) -> api_response!(types::RecycleBinResp) {
+        if !can_write_deleted(&state, &user_info, team_id, &req).await? {
+            return Err(NoPermission().into());
+        }
+
+        let result = state
+            .service()
+            .job
+            .restore_deleted(req.resource_type.into(), req.id)
+            .await?;
+        return_ok!(types::RecycleBinResp { result })
+    }
+
+    /// permanently delete the resource from the recycle bin
+    #[oai(
+        path = "/recycle-bin/purge",
+        method = "post",
+        transform = "set_middleware"
+    )]
+    pub async fn purge_deleted(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Json(req): Json<types::RecycleBinReq>,
+    ) -> api_response!(types::RecycleBinResp) {
+        if !can_write_deleted(&state, &user_info, team_id, &req).await? {
+            return Err(NoPermission().into());
+        }
+
+        let result = state
+            .service()
+            .job
+            .purge_deleted(req.resource_type.into(), req.id)
+            .await?;
+        return_ok!(types::RecycleBinResp { result })
+    }
+
+    /// share the job with another team, the team can view or run the job but cannot edit it
+    #[oai(path = "/share", method = "post", transform = "set_middleware")]
+    pub async fn share_job(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Json(req): Json<types::ShareJobReq>,
+    ) -> api_response!(types::ShareJobResp) {
+        let svc = state.service();
+        if !svc
+            .job
+            .can_write_job(&user_info, team_id, Some(req.eid.clone()))
+            .await?
+        {
+            return Err(NoPermission().into());
+        }
+
+        let result = svc
+            .job
+            .grant_job_share(&user_info, &req.eid, req.team_id, req.permission.into())
+            .await?;
+        return_ok!(types::ShareJobResp { result })
+    }
+
+    #[oai(path = "/unshare", method = "post", transform = "set_middleware")]
+    pub async fn unshare_job(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Json(req): Json<types::UnshareJobReq>,
+    ) -> api_response!(types::UnshareJobResp) {
+        let svc = state.service();
+        if !svc
+            .job
+            .can_write_job(&user_info, team_id, Some(req.eid.clone()))
+            .await?
+        {
+            return Err(NoPermission().into());
+        }
+
+        let result = svc.job.revoke_job_share(&req.eid, req.team_id).await?;
+        return_ok!(types::UnshareJobResp { result })
+    }
+
+    /// teams the job is shared with
+    #[oai(path = "/share-list", method = "get", transform = "set_middleware")]
+    pub async fn query_job_share(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Query(eid): Query<String>,
+    ) -> api_response!(types::QueryJobShareResp) {
+        let svc = state.service();
+        if !svc
+            .job
+            .can_write_job(&user_info, team_id, Some(eid.clone()))
+            .await?
+        {
+            return Err(NoPermission().into());
+        }
+
+        let list = svc
+            .job
+            .query_job_share(&eid)
+            .await?
+            .into_iter()
+            .map(|v| types::JobShareRecord {
+                id: v.id,
+                eid: v.eid,
+                team_id: v.team_id,
+                team_name: v.team_name,
+                permission: v.permission,
+                created_user: v.created_user,
+                created_time: local_time!(v.created_time),
+            })
+            .collect();
+        return_ok!(types::QueryJobShareResp { list })
+    }
+
+    /// create or update an inbound trigger, the job is dispatched when the trigger url is called
+    #[oai(path = "/trigger/save", method = "post", transform = "set_middleware")]
+    pub async fn save_job_trigger(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Json(req): Json<types::SaveJobTriggerReq>,
+    ) -> api_response!(types::SaveJobTriggerResp) {
+        let svc = state.service();
+        if !svc
+            .job
+            .can_dispatch_job(&user_info, team_id, None, &req.eid)
+            .await?
+        {
+            return Err(NoPermission().into());
+        }
+
+        if req.instance_ids.is_empty() {
+            return_err!("no instance is bound to the trigger");
+        }
+
+        let (id, created_user) = match req.id {
+            Some(id) => {
+                let Some(v) = svc.job.get_job_trigger(id).await? else {
+                    return_err!("cannot found the trigger");
+                };
+                if v.eid != req.eid {
+                    return Err(NoPermission().into());
+                }
+                (Set(id), NotSet)
+            }
+            None => (NotSet, Set(user_info.username.clone())),
+        };
+
+        let result = svc
+            .job
+            .save_job_trigger(job_trigger::ActiveModel {
+                id,
+                name: Set(req.name),
+                eid: Set(req.eid),
+                instance_ids: Set(Some(json!(req.instance_ids))),
+                args_mapping: Set(req.args_mapping),
+                rate_limit: Set(req.rate_limit),
+                is_enabled: Set(req.is_enabled),
+                created_user,
+                updated_user: Set(user_info.username.clone()),
+                ..Default::default()
+            })
+            .await?;
+        return_ok!(types::SaveJobTriggerResp { result })
+    }
+
+    #[oai(path = "/trigger/list", method = "get", transform = "set_middleware")]
+    pub async fn query_job_trigger(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Query(eid): Query<String>,
+    ) -> api_response!(types::QueryJobTriggerResp) {
+        let svc = state.service();
+        if !svc
+            .job
+            .can_dispatch_job(&user_info, team_id, None, &eid)
+            .await?
+        {
+            return Err(NoPermission().into());
+        }
+
+        let list = svc
+            .job
+            .query_job_trigger(&eid)
+            .await?
+            .into_iter()
+            .map(|v| types::JobTriggerRecord {
+                id: v.id,
+                name: v.name,
+                eid: v.eid,
+                token: v.token,
+                secret: v.secret,
+                instance_ids: v.instance_ids,
+                args_mapping: v.args_mapping,
+                rate_limit: v.rate_limit,
+                is_enabled: v.is_enabled,
+                created_user: v.created_user,
+                updated_user: v.updated_user,
+                created_time: local_time!(v.created_time),
+                updated_time: local_time!(v.updated_time),
+            })
+            .collect();
+        return_ok!(types::QueryJobTriggerResp { list })
+    }
+
+    #[oai(
+        path = "/trigger/delete",
+        method = "post",
+        transform = "set_middleware"
+    )]
+    pub async fn delete_job_trigger(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Json(req): Json<types::DeleteJobTriggerReq>,
+    ) -> api_response!(types::DeleteJobTriggerResp) {
+        let svc = state.service();
+        let Some(trigger) = svc.job.get_job_trigger(req.id).await? else {
+            return_err!("cannot found the trigger");
+        };
+        if !svc
+            .job
+            .can_dispatch_job(&user_info, team_id, None, &trigger.eid)
+            .await?
+        {
+            return Err(NoPermission().into());
+        }
+
+        let result = svc.job.delete_job_trigger(req.id).await?;
+        return_ok!(types::DeleteJobTriggerResp { result })
+    }
+
+    /// audit of the invocations of the trigger
+    #[oai(
+        path = "/trigger/log-list",
+        method = "get",
+        transform = "set_middleware"
+    )]
+    pub async fn query_job_trigger_log(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Query(trigger_id): Query<u64>,
+        #[oai(default = "types::default_page", validator(maximum(value = "10000")))]
+        Query(page): Query<u64>,
+        #[oai(
+            default = "types::default_page_size",
+            validator(maximum(value = "10000"))
+        )]
+        Query(page_size): Query<u64>,
+    ) -> api_response!(types::QueryJobTriggerLogResp) {
+        let svc = state.service();
+        let Some(trigger) = svc.job.get_job_trigger(trigger_id).await? else {
+            return_err!("cannot found the trigger");
+        };
+        if !svc
+            .job
+            .can_dispatch_job(&user_info, team_id, None, &trigger.eid)
+            .await?
+        {
+            return Err(NoPermission().into());
+        }
+
+        let (list, total) = svc
+            .job
+            .query_job_trigger_log(trigger_id, page - 1, page_size)
+            .await?;
+        let list = list
+            .into_iter()
+            .map(|v| types::JobTriggerLogRecord {
+                id: v.id,
+                trigger_id: v.trigger_id,
+                remote_ip: v.remote_ip,
+                status: v.status,
+                reason: v.reason,
+                schedule_id: v.schedule_id,
+                payload: v.payload,
+                created_time: local_time!(v.created_time),
+            })
+            .collect();
+        return_ok!(types::QueryJobTriggerLogResp { total, list })
+    }
+
+    /// map the alerts to a remediation job, the job is dispatched when a matched alert fires
+    #[oai(
+        path = "/remediation/save",
+        method = "post",
+        transform = "set_middleware"
+    )]
+    pub async fn save_job_remediation(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Json(req): Json<types::SaveJobRemediationReq>,
+    ) -> api_response!(types::SaveJobRemediationResp) {
+        let svc = state.service();
+        if !svc
+            .job
+            .can_dispatch_job(&user_info, team_id, None, &req.eid)
+            .await?
+        {
+            return Err(NoPermission().into());
+        }
+
+        if req.match_labels.as_ref().is_some_and(|v| {
+            !v.as_object()
+                .is_some_and(|labels| labels.values().all(serde_json::Value::is_string))
+        }) {
+            return_err!("match_labels must be an object of label name to value");
+        }
+
+        let (id, created_user) = match req.id {
+            Some(id) => {
+                let Some(v) = svc.job.get_job_remediation(id).await? else {
+                    return_err!("cannot found the remediation");
+                };
+                if v.eid != req.eid {
+                    return Err(NoPermission().into());
+                }
+                (Set(id), NotSet)
+            }
+            None => (NotSet, Set(user_info.username.clone())),
+        };
+
+        let result = svc
+            .job
+            .save_job_remediation(job_remediation::ActiveModel {
+                id,
+                name: Set(req.name),
+                eid: Set(req.eid),
+                alertname: Set(req.alertname),
+                match_labels: Set(req.match_labels),
+                target_label: Set(req.target_label),
+                instance_ids: Set(Some(json!(req.instance_ids))),
+                cooldown_secs: Set(req.cooldown_secs),
+                max_runs_per_hour: Set(req.max_runs_per_hour),
+                is_enabled: Set(req.is_enabled),
+                created_user,
+                updated_user: Set(user_info.username.clone()),
+                ..Default::default()
+            })
+            .await?;
+        return_ok!(types::SaveJobRemediationResp { result })
+    }
+
+    #[oai(
+        path = "/remediation/list",
+        method = "get",
+        transform = "set_middleware"
+    )]
+    pub async fn query_job_remediation(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Query(eid): Query<String>,
+    ) -> api_response!(types::QueryJobRemediationResp) {
+        let svc = state.service();
+        if !svc
+            .job
+            .can_dispatch_job(&user_info, team_id, None, &eid)
+            .await?
+        {
+            return Err(NoPermission().into());
+        }
+
+        let list = svc
+            .job
+            .query_job_remediation(&eid)
+            .await?
+            .into_iter()
+            .map(|v| types::JobRemediationRecord {
+                id: v.id,
+                name: v.name,
+                eid: v.eid,
+                alertname: v.alertname,
+                match_labels: v.match_labels,
+                target_label: v.target_label,
+                instance_ids: v.instance_ids,
+                cooldown_secs: v.cooldown_secs,
+                max_runs_per_hour: v.max_runs_per_hour,
+                is_enabled: v.is_enabled,
+                created_user: v.created_user,
+                updated_user: v.updated_user,
+                created_time: local_time!(v.created_time),
+                updated_time: local_time!(v.updated_time),
+            })
+            .collect();
+        return_ok!(types::QueryJobRemediationResp { list })
+    }
+
+    #[oai(
+        path = "/remediation/delete",
+        method = "post",
+        transform = "set_middleware"
+    )]
+    pub async fn delete_job_remediation(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Json(req): Json<types::DeleteJobRemediationReq>,
+    ) -> api_response!(types::DeleteJobRemediationResp) {
+        let svc = state.service();
+        let Some(remediation) = svc.job.get_job_remediation(req.id).await? else {
+            return_err!("cannot found the remediation");
+        };
+        if !svc
+            .job
+            .can_dispatch_job(&user_info, team_id, None, &remediation.eid)
+            .await?
+        {
+            return Err(NoPermission().into());
+        }
+
+        let result = svc.job.delete_job_remediation(req.id).await?;
+        return_ok!(types::DeleteJobRemediationResp { result })
+    }
+
+    /// audit of the auto-remediation runs
+    #[oai(
+        path = "/remediation/log-list",
+        method = "get",
+        transform = "set_middleware"
+    )]
+    pub async fn query_job_remediation_log(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Query(remediation_id): Query<u64>,
+        #[oai(default = "types::default_page", validator(maximum(value = "10000")))]
+        Query(page): Query<u64>,
+        #[oai(
+            default = "types::default_page_size",
+            validator(maximum(value = "10000"))
+        )]
+        Query(page_size): Query<u64>,
+    ) -> api_response!(types::QueryJobRemediationLogResp) {
+        let svc = state.service();
+        let Some(remediation) = svc.job.get_job_remediation(remediation_id).await? else {
+            return_err!("cannot found the remediation");
+        };
+        if !svc
+            .job
+            .can_dispatch_job(&user_info, team_id, None, &remediation.eid)
+            .await?
+        {
+            return Err(NoPermission().into());
+        }
+
+        let (list, total) = svc
+            .job
+            .query_job_remediation_log(remediation_id, page - 1, page_size)
+            .await?;
+        let list = list
+            .into_iter()
+            .map(|v| types::JobRemediationLogRecord {
+                id: v.id,
+                remediation_id: v.remediation_id,
+                alertname: v.alertname,
+                fingerprint: v.fingerprint,
+                instance_ids: v.instance_ids,
+                status: v.status,
+                reason: v.reason,
+                schedule_id: v.schedule_id,
+                created_time: local_time!(v.created_time),
+            })
+            .collect();
+        return_ok!(types::QueryJobRemediationLogResp { total, list })
+    }
+
+    /// webhook receiver of the prometheus alertmanager, configure it in the webhook_configs
+    /// with an api token as the bearer credentials, eg:
+    /// url: http://jiascheduler:9090/api/v1/job/remediation/alertmanager
+    #[oai(
+        path = "/remediation/alertmanager",
+        method = "post",
+        transform = "dispatch_middleware"
+    )]
+    pub async fn receive_alerts(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<serde_json::Value>,
+    ) -> api_response!(types::ReceiveAlertsResp) {
+        let payload = serde_json::from_value(req).map_err(std_into_error)?;
+        let summary = state
+            .service()
+            .job
+            .receive_alerts(state.conf.comet_secret.clone(), &user_info, payload)
+            .await?;
+        return_ok!(types::ReceiveAlertsResp {
+            dispatched: summary.dispatched,
+            skipped: summary.skipped,
+            failed: summary.failed,
+        })
+    }
+
+    /// export the exec or schedule history into a file in the background,
+    /// the records visible in the history lists are exported
+    #[oai(path = "/export", method = "post", transform = "set_middleware")]
+    pub async fn export_history(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Json(req): Json<types::ExportHistoryReq>,
+    ) -> api_response!(types::ExportHistoryResp) {
+        let svc = state.service();
+        let created_user = if granted.manage_job() {
+            None
+        } else {
+            team_id.map_or_else(|| Some(user_info.username.clone()), |_| None)
+        };
+
+        let task_id = svc
+            .task
+            .enqueue(
+                BackgroundTask::ExportHistory(ExportHistoryTask {
+                    kind: req.kind.into(),
+                    format: req.format.into(),
+                    eid: req.eid.filter(|v| !v.is_empty()),
+                    team_id,
+                    created_user,
+                    time_range: req.time_range.map(|v| (v[0].clone(), v[1].clone())),
+                }),
+                team_id,
+                user_info.username.clone(),
+            )
+            .await?;
+        return_ok!(types::ExportHistoryResp { task_id })
+    }
+
+    /// create or update a read-only status page of the daemon and timer jobs,
+    /// it is served without login at the url of its token
+    #[oai(
+        path = "/status-page/save",
+        method = "post",
+        transform = "set_middleware"
+    )]
+    pub async fn save_status_page(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::SaveStatusPageReq>,
+    ) -> api_response!(types::SaveStatusPageResp) {
+        let result = state
+            .service()
+            .job
+            .save_status_page(
+                &user_info,
+                req.id,
+                req.name,
+                req.team_id,
+                req.eids,
+                req.is_enabled,
+            )
+            .await?;
+        return_ok!(types::SaveStatusPageResp { result })
+    }
+
+    #[oai(
+        path = "/status-page/list",
+        method = "get",
+        transform = "set_middleware"
+    )]
+    pub async fn query_status_page(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+    ) -> api_response!(types::QueryStatusPageResp) {
+        let list = state
+            .service()
+            .job
+            .query_status_page(&user_info)
+            .await?
+            .into_iter()
+            .map(|v| types::StatusPageRecord {
+                id: v.id,
+                token: v.token,
+                name: v.name,
+                team_id: v.team_id,
+                eids: v.eids,
+                is_enabled: v.is_enabled,
+                created_user: v.created_user,
+                updated_user: v.updated_user,
+                created_time: local_time!(v.created_time),
+                updated_time: local_time!(v.updated_time),
+            })
+            .collect();
+        return_ok!(types::QueryStatusPageResp { list })
+    }
+
+    #[oai(
+        path = "/status-page/delete",
+        method = "post",
+        transform = "set_middleware"
+    )]
+    pub async fn delete_status_page(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::DeleteStatusPageReq>,
+    ) -> api_response!(types::DeleteStatusPageResp) {
+        let result = state
+            .service()
+            .job
+            .delete_status_page(&user_info, req.id)
+            .await?;
+        return_ok!(types::DeleteStatusPageResp { result })
+    }
+
+    /// the code policies of the team, the global ones if team_id is 0, and the rules
+    /// the jobs of the team are checked against
+    #[oai(
+        path = "/code-policy/list",
+        method = "get",
+        transform = "set_middleware"
+    )]
+    pub async fn query_code_policy(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(default)] Query(team_id): Query<u64>,
+    ) -> api_response!(types::QueryCodePolicyRuleResp) {
+        let svc = state.service();
+        if team_id != 0
+            && !svc
+                .team
+                .can_read_team(Some(team_id), user_info.user_id.clone())
+                .await?
+        {
+            return Err(NoPermission().into());
+        }
+
+        let list = svc.job.get_code_policy_rules(team_id).await?;
+        let mut rules = list.clone();
+        if team_id != 0 {
+            rules.extend(svc.job.get_code_policy_rules(0).await?);
+        }
+        let effective = logic::job::code_policy::effective_rules(rules, team_id);
+
+        return_ok!(types::QueryCodePolicyRuleResp {
+            list: list.into_iter().map(code_policy_rule_record).collect(),
+            effective: effective.into_iter().map(code_policy_rule_record).collect(),
+        })
+    }
+
+    #[oai(
+        path = "/code-policy/save",
+        method = "post",
+        transform = "set_middleware"
+    )]
+    pub async fn save_code_policy(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::SaveCodePolicyRuleReq>,
+    ) -> api_response!(types::SaveCodePolicyRuleResp) {
+        let svc = state.service();
+        if let Some(id) = req.id {
+            let Some(record) = svc.job.get_code_policy_rule(id).await? else {
+                return_err!("cannot found the code policy");
+            };
+            if record.team_id != req.team_id {
+                return_err!("the code policy cannot be moved to another team");
+            }
+        }
+        if !can_write_code_policy(&state, &user_info, req.team_id).await? {
+            return Err(NoPermission().into());
+        }
+
+        let (id, created_user) = match req.id {
+            Some(v) => (Set(v), NotSet),
+            None => (NotSet, Set(user_info.username.clone())),
+        };
+        let result = svc
+            .job
+            .save_code_policy_rule(crate::entity::code_policy_rule::ActiveModel {
+                id,
+                team_id: Set(req.team_id),
+                name: Set(req.name),
+                pattern: Set(req.pattern),
+                action: Set(req.action),
+                is_enabled: Set(req.is_enabled.unwrap_or(true)),
+                info: Set(req.info),
+                created_user,
+                updated_user: Set(user_info.username.clone()),
+                ..Default::default()
+            })
+            .await?;
+        return_ok!(types::SaveCodePolicyRuleResp { result })
+    }
+
+    #[oai(
+        path = "/code-policy/delete",
+        method = "post",
+        transform = "set_middleware"
+    )]
+    pub async fn delete_code_policy(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::DeleteCodePolicyRuleReq>,
+    ) -> api_response!(types::DeleteCodePolicyRuleResp) {
+        let svc = state.service();
+        let Some(record) = svc.job.get_code_policy_rule(req.id).await? else {
+            return_err!("cannot found the code policy");
+        };
+        if !can_write_code_policy(&state, &user_info, record.team_id).await? {
+            return Err(NoPermission().into());
+        }
+        let result = svc.job.delete_code_policy_rule(req.id).await?;
+        return_ok!(types::DeleteCodePolicyRuleResp { result })
+    }
+
+    /// the rejections of the code policies waived when the job was saved
+    #[oai(
+        path = "/code-policy/waivers",
+        method = "get",
+        transform = "set_middleware"
+    )]
+    pub async fn query_code_policy_waiver(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Query(eid): Query<String>,
+    ) -> api_response!(types::QueryCodePolicyWaiverResp) {
+        let svc = state.service();
+        if !svc
+            .job
+            .can_write_job(&user_info, team_id, Some(eid.clone()))
+            .await?
+        {
+            return Err(NoPermission().into());
+        }
+
+        let list = svc
+            .job
+            .get_code_policy_waivers(&eid)
+            .await?
+            .into_iter()
+            .map(|v| types::CodePolicyWaiverRecord {
+                id: v.id,
+                eid: v.eid,
+                rule_name: v.rule_name,
+                matched_line: v.matched_line,
+                justification: v.justification,
+                created_user: v.created_user,
+                created_time: local_time!(v.created_time),
+            })
+            .collect();
+        return_ok!(types::QueryCodePolicyWaiverResp { list })
+    }
 }
@@ -1,22 +1,29 @@
 use crate::{
     entity::user,
     error::NoPermission,
-    local_time,
+    job, local_time,
     logic::{self, role::PERMISSIONS, user::UserLogic},
-    response::ApiStdResponse,
-    return_err, return_ok, AppState,
+    response::{std_into_error, ApiStdResponse},
+    return_err, return_ok, AppState, WebapiOptions,
 };
 
+use super::file::types::GetFileResponse;
+
 use anyhow::anyhow;
+use leader_election::LeaderElection;
 use poem::{session::Session, web::Data, Result};
-use poem_openapi::{param::Query, payload::Json, OpenApi};
+use poem_openapi::{
+    param::Query,
+    payload::{Attachment, AttachmentType, Json, PlainText},
+    OpenApi,
+};
 use sea_orm::{ActiveValue::NotSet, Set};
 use types::PermissionRecord;
 pub struct ManageApi;
 
 pub mod types {
 
-    use poem_openapi::Object;
+    use poem_openapi::{types::multipart::Upload, Multipart, Object};
     use serde::{Deserialize, Serialize};
 
     #[derive(Object, Serialize, Deserialize)]
@@ -47,6 +54,43 @@ pub mod types {
     pub struct AdminUpdateInfoResp {
         pub affected: u64,
     }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct CreateAdminReq {
+        pub username: String,
+        /// must be changed on the first login
+        pub password: String,
+        pub nickname: Option<String>,
+        pub email: Option<String>,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct CreateAdminResp {
+        pub user_id: String,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct TransferOwnershipReq {
+        /// the user becomes root, the current root user keeps the admin role
+        pub user_id: String,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct TransferOwnershipResp {
+        pub result: bool,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct DisableUserReq {
+        pub user_id: String,
+        /// false to enable the user again
+        pub disabled: bool,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct DisableUserResp {
+        pub affected: u64,
+    }
     #[derive(Object, Serialize, Default)]
     pub struct AllPermissionResp {
         pub list: Vec<PermissionRecord>,
@@ -59,6 +103,197 @@ pub mod types {
         pub action: String,
         pub key: String,
     }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct SecuritySetting {
+        /// ip or cidr allowed to login the console, any address if empty
+        #[oai(default)]
+        pub login_allowlist: Vec<String>,
+        /// ip or cidr allowed to call the comet /dispatch endpoint, any address if empty
+        #[oai(default)]
+        pub dispatch_allowlist: Vec<String>,
+        /// failed attempts allowed before the account is locked, 0 disables the lockout
+        pub login_max_attempts: u32,
+        /// first lockout duration in seconds, doubled on every further failure
+        pub login_lockout_secs: u64,
+        pub login_max_lockout_secs: u64,
+        /// ip or cidr of the reverse proxies whose forwarding headers are trusted
+        #[oai(default)]
+        pub trusted_proxies: Vec<String>,
+    }
+
+    impl From<automate::SecuritySetting> for SecuritySetting {
+        fn from(value: automate::SecuritySetting) -> Self {
+            Self {
+                login_allowlist: value.login_allowlist,
+                dispatch_allowlist: value.dispatch_allowlist,
+                login_max_attempts: value.login_max_attempts,
+                login_lockout_secs: value.login_lockout_secs,
+                login_max_lockout_secs: value.login_max_lockout_secs,
+                trusted_proxies: value.trusted_proxies,
+            }
+        }
+    }
+
+    impl Into<automate::SecuritySetting> for SecuritySetting {
+        fn into(self) -> automate::SecuritySetting {
+            automate::SecuritySetting {
+                login_allowlist: self.login_allowlist,
+                dispatch_allowlist: self.dispatch_allowlist,
+                login_max_attempts: self.login_max_attempts,
+                login_lockout_secs: self.login_lockout_secs,
+                login_max_lockout_secs: self.login_max_lockout_secs,
+                trusted_proxies: self.trusted_proxies,
+            }
+        }
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct SaveSecuritySettingResp {
+        pub result: bool,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct LogLevel {
+        /// per-module levels, eg: "info,automate=debug,sea_orm=warn"
+        pub level: String,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct ReloadConfResp {
+        /// changed fields applied at runtime
+        pub applied: Vec<String>,
+        /// changed fields ignored until restart
+        pub restart_required: Vec<String>,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct UnlockLoginReq {
+        pub username: String,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct LeaderRecord {
+        pub node_id: String,
+        /// ip and bind address of the console
+        pub name: String,
+        /// increased on every failover
+        pub fencing_token: u64,
+        pub since: String,
+        /// milliseconds before the leadership expires if not renewed
+        pub ttl_ms: i64,
+    }
+
+    impl From<leader_election::LeaderInfo> for LeaderRecord {
+        fn from(value: leader_election::LeaderInfo) -> Self {
+            Self {
+                node_id: value.id,
+                name: value.name,
+                fencing_token: value.token,
+                since: chrono::DateTime::from_timestamp_millis(value.since)
+                    .map(|v| crate::local_time!(v))
+                    .unwrap_or_default(),
+                ttl_ms: value.ttl_ms,
+            }
+        }
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct GetLeaderResp {
+        /// none during failover
+        pub leader: Option<LeaderRecord>,
+        /// id of the console serving the request
+        pub node_id: String,
+        pub is_leader: bool,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct StepDownResp {
+        /// the leader asked to step down, it hands over on its next renewal
+        pub leader: Option<LeaderRecord>,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct SlowQueryRecord {
+        /// the statement with the parameters inlined
+        pub statement: String,
+        pub elapsed_ms: u64,
+        pub failed: bool,
+        pub time: String,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct DbDiagnosticsResp {
+        /// connections opened by the pool of the console serving the request
+        pub pool_size: u32,
+        pub pool_idle: u32,
+        pub max_connections: u32,
+        pub slow_query_ms: u64,
+        /// the latest first
+        pub slow_queries: Vec<SlowQueryRecord>,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct CometNamespace {
+        pub namespace: String,
+        pub instance_count: u64,
+        pub online_count: u64,
+        /// assigned by the namespaces of the group config
+        pub pinned: bool,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct CometGroupRecord {
+        pub name: String,
+        pub comet_addrs: Vec<String>,
+        pub namespaces: Vec<CometNamespace>,
+        pub instance_count: u64,
+        pub online_count: u64,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct CometGroupResp {
+        pub list: Vec<CometGroupRecord>,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct NamespaceMove {
+        pub namespace: String,
+        pub from: Option<String>,
+        pub to: String,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct RebalanceCometGroupResp {
+        /// the agents of the moved namespaces reconnect to the new group within 30 seconds
+        pub moves: Vec<NamespaceMove>,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct ExportBackupReq {
+        /// at least 8 characters, it is required again to import the backup
+        pub passphrase: String,
+    }
+
+    #[derive(Debug, Multipart)]
+    pub struct ImportBackupReq {
+        pub file: Upload,
+        pub passphrase: String,
+        /// only check the backup and count the rows
+        pub dry_run: Option<bool>,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct BackupTableCount {
+        pub name: String,
+        pub rows: u64,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct ImportBackupResp {
+        pub dry_run: bool,
+        pub tables: Vec<BackupTableCount>,
+    }
 }
 
 #[OpenApi(prefix_path = "/admin", tag = super::Tag::Admin)]
@@ -152,6 +387,8 @@ impl ManageApi {
                         gender: record.gender,
                         permissions,
                         role: record.role.unwrap_or_default(),
+                        timezone: record.timezone,
+                        password_expired: record.password_expired,
                     },
                 );
             }
@@ -161,6 +398,107 @@ impl ManageApi {
         return_ok!(types::AdminUpdateInfoResp { affected });
     }
 
+    #[oai(path = "/user/create-admin", method = "post")]
+    pub async fn create_admin(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::CreateAdminReq>,
+    ) -> Result<ApiStdResponse<types::CreateAdminResp>> {
+        if !user_info.is_root {
+            return Err(NoPermission().into());
+        }
+        if req.username.is_empty() || req.password.is_empty() {
+            return_err!("username and password are required");
+        }
+
+        let user_id = state
+            .service()
+            .user
+            .create_admin(
+                req.username,
+                req.nickname.unwrap_or_default(),
+                req.email.unwrap_or_default(),
+                req.password,
+            )
+            .await?;
+        state.set_role_for_user(&user_id, "1").await?;
+        state.load_policy().await?;
+
+        return_ok!(types::CreateAdminResp { user_id })
+    }
+
+    #[oai(path = "/user/transfer-ownership", method = "post")]
+    pub async fn transfer_ownership(
+        &self,
+        sess: &Session,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::TransferOwnershipReq>,
+    ) -> Result<ApiStdResponse<types::TransferOwnershipResp>> {
+        if !user_info.is_root {
+            return Err(NoPermission().into());
+        }
+
+        state
+            .service()
+            .user
+            .transfer_ownership(&user_info.user_id, &req.user_id)
+            .await?;
+        state.set_role_for_user(&req.user_id, "1").await?;
+        state.load_policy().await?;
+
+        if sess
+            .get::<logic::types::UserInfo>(UserLogic::SESS_KEY)
+            .is_some()
+        {
+            sess.set(
+                UserLogic::SESS_KEY,
+                logic::types::UserInfo {
+                    is_root: false,
+                    ..(*user_info).clone()
+                },
+            );
+        }
+
+        return_ok!(types::TransferOwnershipResp { result: true })
+    }
+
+    #[oai(path = "/user/set-disabled", method = "post")]
+    pub async fn set_user_disabled(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::DisableUserReq>,
+    ) -> Result<ApiStdResponse<types::DisableUserResp>> {
+        let ok = state.can_manage_user(&user_info.user_id).await?;
+        if !ok {
+            return Err(NoPermission().into());
+        }
+
+        let state_clone = state.clone();
+        let affected = state
+            .service()
+            .user
+            .set_disabled(
+                &user_info,
+                &req.user_id,
+                req.disabled,
+                |user_id: String, role: String| async move {
+                    if role == "0" {
+                        state_clone.delete_role_for_user(&user_id).await?;
+                    } else {
+                        state_clone.set_role_for_user(&user_id, &role).await?;
+                    }
+                    Ok(())
+                },
+            )
+            .await?;
+        state.load_policy().await?;
+
+        return_ok!(types::DisableUserResp { affected })
+    }
+
     #[oai(path = "/instance/user-server-list", method = "get")]
     pub async fn admin_user_server(
         &self,
@@ -223,6 +561,7 @@ impl ManageApi {
                         ip.filter(|v| v != "").map_or(None, |v| Some(vec![v])),
                         None,
                         Some(tag_id),
+                        &Default::default(),
                         page - 1,
                         page_size,
                     )
@@ -240,6 +579,7 @@ impl ManageApi {
                         instance_group_id.filter(|&v| v != 0),
                         status,
                         ip.filter(|v| v != "").map_or(None, |v| Some(vec![v])),
+                        &Default::default(),
                         page - 1,
                         page_size,
                     )
@@ -257,6 +597,7 @@ impl ManageApi {
                         instance_group_id.filter(|&v| v != 0),
                         status,
                         ip.filter(|v| v != "").map_or(None, |v| Some(vec![v])),
+                        &Default::default(),
                         page - 1,
                         page_size,
                     )
@@ -277,6 +618,7 @@ impl ManageApi {
                 instance_group_id: v.instance_group_id.unwrap_or_default(),
                 instance_group: v.instance_group_name.unwrap_or_default(),
                 status: v.status,
+                maintenance: v.maintenance,
                 created_time: local_time!(v.created_time),
                 updated_time: local_time!(v.updated_time),
             })
@@ -311,4 +653,301 @@ impl ManageApi {
             list: permission_record
         });
     }
+
+    #[oai(path = "/security/setting", method = "get")]
+    pub async fn get_security_setting(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+    ) -> Result<ApiStdResponse<types::SecuritySetting>> {
+        let ok = state.can_manage_user(&user_info.user_id).await?;
+        if !ok {
+            return Err(NoPermission().into());
+        }
+
+        let setting = state.service().security.get_setting().await?;
+        return_ok!(setting.into())
+    }
+
+    #[oai(path = "/security/save-setting", method = "post")]
+    pub async fn save_security_setting(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::SecuritySetting>,
+    ) -> Result<ApiStdResponse<types::SaveSecuritySettingResp>> {
+        let ok = state.can_manage_user(&user_info.user_id).await?;
+        if !ok {
+            return Err(NoPermission().into());
+        }
+
+        state.service().security.save_setting(req.into()).await?;
+        return_ok!(types::SaveSecuritySettingResp { result: true })
+    }
+
+    #[oai(path = "/security/unlock-login", method = "post")]
+    pub async fn unlock_login(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::UnlockLoginReq>,
+    ) -> Result<ApiStdResponse<types::SaveSecuritySettingResp>> {
+        let ok = state.can_manage_user(&user_info.user_id).await?;
+        if !ok {
+            return Err(NoPermission().into());
+        }
+
+        state.service().security.unlock_login(&req.username).await?;
+        return_ok!(types::SaveSecuritySettingResp { result: true })
+    }
+
+    #[oai(path = "/reload", method = "post")]
+    pub async fn reload_conf(
+        &self,
+        state: Data<&AppState>,
+        opts: Data<&WebapiOptions>,
+        user_info: Data<&logic::types::UserInfo>,
+    ) -> Result<ApiStdResponse<types::ReloadConfResp>> {
+        let ok = state.can_manage_user(&user_info.user_id).await?;
+        if !ok {
+            return Err(NoPermission().into());
+        }
+
+        let report = crate::reload_conf(&state, &opts).await?;
+        return_ok!(types::ReloadConfResp {
+            applied: report.applied,
+            restart_required: report.restart_required,
+        })
+    }
+
+    #[oai(path = "/log/level", method = "get")]
+    pub async fn get_log_level(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+    ) -> Result<ApiStdResponse<types::LogLevel>> {
+        let ok = state.can_manage_user(&user_info.user_id).await?;
+        if !ok {
+            return Err(NoPermission().into());
+        }
+
+        let Some(level) = utils::get_log_level() else {
+            return_err!("log level is not adjustable");
+        };
+        return_ok!(types::LogLevel { level })
+    }
+
+    #[oai(path = "/log/set-level", method = "post")]
+    pub async fn set_log_level(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::LogLevel>,
+    ) -> Result<ApiStdResponse<types::LogLevel>> {
+        let ok = state.can_manage_user(&user_info.user_id).await?;
+        if !ok {
+            return Err(NoPermission().into());
+        }
+
+        utils::set_log_level(&req.level)?;
+        return_ok!(types::LogLevel { level: req.level })
+    }
+
+    #[oai(path = "/leader", method = "get")]
+    pub async fn get_leader(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+    ) -> Result<ApiStdResponse<types::GetLeaderResp>> {
+        let ok = state.can_manage_user(&user_info.user_id).await?;
+        if !ok {
+            return Err(NoPermission().into());
+        }
+
+        let leader = LeaderElection::current_leader(&state.redis(), job::LEADER_ELECTION_KEY)
+            .await
+            .map_err(|e| anyhow!("failed get leader - {e}"))?;
+        let node_id = job::node_id().to_string();
+        return_ok!(types::GetLeaderResp {
+            is_leader: leader.as_ref().is_some_and(|v| v.id == node_id),
+            leader: leader.map(Into::into),
+            node_id,
+        })
+    }
+
+    #[oai(path = "/leader/step-down", method = "post")]
+    pub async fn step_down_leader(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+    ) -> Result<ApiStdResponse<types::StepDownResp>> {
+        let ok = state.can_manage_user(&user_info.user_id).await?;
+        if !ok {
+            return Err(NoPermission().into());
+        }
+
+        let leader = LeaderElection::request_step_down(
+            &state.redis(),
+            job::LEADER_ELECTION_KEY,
+            job::LEADER_ELECTION_TTL,
+        )
+        .await
+        .map_err(|e| anyhow!("failed step down leader - {e}"))?;
+        return_ok!(types::StepDownResp {
+            leader: leader.map(Into::into),
+        })
+    }
+
+    /// pool status and recent slow queries of the console serving the request
+    #[oai(path = "/diagnostics/db", method = "get")]
+    pub async fn db_diagnostics(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+    ) -> Result<ApiStdResponse<types::DbDiagnosticsResp>> {
+        let ok = state.can_manage_user(&user_info.user_id).await?;
+        if !ok {
+            return Err(NoPermission().into());
+        }
+
+        let status = service::db::pool_status(&state.db, &state.conf.db_pool);
+        return_ok!(types::DbDiagnosticsResp {
+            pool_size: status.size,
+            pool_idle: status.idle,
+            max_connections: status.max_connections,
+            slow_query_ms: state.conf.db_pool.slow_query_ms,
+            slow_queries: service::db::slow_queries()
+                .into_iter()
+                .map(|v| types::SlowQueryRecord {
+                    statement: v.statement,
+                    elapsed_ms: v.elapsed_ms,
+                    failed: v.failed,
+                    time: local_time!(v.time),
+                })
+                .collect(),
+        })
+    }
+
+    /// comet groups of the config with the assigned namespaces
+    #[oai(path = "/comet-group/list", method = "get")]
+    pub async fn query_comet_group(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+    ) -> Result<ApiStdResponse<types::CometGroupResp>> {
+        let ok = state.can_manage_user(&user_info.user_id).await?;
+        if !ok {
+            return Err(NoPermission().into());
+        }
+
+        let list = state.service().comet_group.get_assignments().await?;
+        return_ok!(types::CometGroupResp {
+            list: list
+                .into_iter()
+                .map(|v| types::CometGroupRecord {
+                    name: v.name,
+                    comet_addrs: v.comet_addrs,
+                    namespaces: v
+                        .namespaces
+                        .into_iter()
+                        .map(|n| types::CometNamespace {
+                            namespace: n.namespace,
+                            instance_count: n.instance_count,
+                            online_count: n.online_count,
+                            pinned: n.pinned,
+                        })
+                        .collect(),
+                    instance_count: v.instance_count,
+                    online_count: v.online_count,
+                })
+                .collect(),
+        })
+    }
+
+    /// reassign the namespaces which are not pinned to balance the instances across the groups
+    #[oai(path = "/comet-group/rebalance", method = "post")]
+    pub async fn rebalance_comet_group(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+    ) -> Result<ApiStdResponse<types::RebalanceCometGroupResp>> {
+        let ok = state.can_manage_user(&user_info.user_id).await?;
+        if !ok {
+            return Err(NoPermission().into());
+        }
+
+        let moves = state.service().comet_group.rebalance().await?;
+        return_ok!(types::RebalanceCometGroupResp {
+            moves: moves
+                .into_iter()
+                .map(|v| types::NamespaceMove {
+                    namespace: v.namespace,
+                    from: v.from,
+                    to: v.to,
+                })
+                .collect(),
+        })
+    }
+
+    /// export users, roles, teams, jobs, executors and instances as an encrypted backup,
+    /// the history is not included
+    #[oai(path = "/backup/export", method = "post")]
+    pub async fn export_backup(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::ExportBackupReq>,
+    ) -> GetFileResponse {
+        match state.can_manage_user(&user_info.user_id).await {
+            Ok(true) => {}
+            Ok(false) => return GetFileResponse::NotAllow,
+            Err(e) => return GetFileResponse::InternalError(PlainText(e.to_string())),
+        }
+
+        let data = match state.service().backup.export(&req.passphrase).await {
+            Ok(v) => v,
+            Err(e) => return GetFileResponse::InternalError(PlainText(e.to_string())),
+        };
+        let filename = format!(
+            "jiascheduler-backup-{}.bin",
+            chrono::Local::now().format("%Y%m%d%H%M%S")
+        );
+        GetFileResponse::Ok(
+            Attachment::new(data)
+                .attachment_type(AttachmentType::Attachment)
+                .filename(filename),
+        )
+    }
+
+    /// replace the configuration with the backup, it is meant to restore a fresh install
+    #[oai(path = "/backup/import", method = "post")]
+    pub async fn import_backup(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        req: types::ImportBackupReq,
+    ) -> Result<ApiStdResponse<types::ImportBackupResp>> {
+        let ok = state.can_manage_user(&user_info.user_id).await?;
+        if !ok {
+            return Err(NoPermission().into());
+        }
+
+        let dry_run = req.dry_run.unwrap_or_default();
+        let data = req.file.into_vec().await.map_err(std_into_error)?;
+        let tables = state
+            .service()
+            .backup
+            .import(&data, &req.passphrase, dry_run)
+            .await?;
+        return_ok!(types::ImportBackupResp {
+            dry_run,
+            tables: tables
+                .into_iter()
+                .map(|v| types::BackupTableCount {
+                    name: v.name,
+                    rows: v.rows,
+                })
+                .collect(),
+        })
+    }
 }
@@ -1,20 +1,22 @@
 use anyhow::Context;
-use migration::MigratorTrait;
+use migration::{runner, MigratorTrait};
 use nanoid::nanoid;
 use poem::{session::Session, web::Data, Result};
 use poem_openapi::{param::Query, payload::Json, OpenApi};
-use redis::Client;
-use sea_orm::{ConnectOptions, Database};
+use sea_orm::DbConn;
 use tokio::sync::mpsc::Sender;
+use tracing::{error, info};
 use url::Url;
 
 use crate::{
+    api::instance::format_timestamp,
     api_response,
-    logic::{self, user::UserLogic},
+    error::NoPermission,
+    logic::{self, migration::install, user::UserLogic},
     response::{std_into_error, ApiStdResponse},
     return_err, return_ok, AppState, InstallState,
 };
-use service::config::Conf;
+use service::config::{Conf, CONFIG_VERSION};
 
 mod types {
     use poem_openapi::Object;
@@ -51,6 +53,42 @@ mod types {
     #[derive(Object, Serialize, Default)]
     pub struct InstallResp {
         pub result: i32,
+        /// names of the executors created by seed_executors
+        pub seeded_executors: Vec<String>,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct MigrationStatusResp {
+        pub applied: Vec<AppliedMigration>,
+        /// names of the migrations applied by the next start or upgrade
+        pub pending: Vec<String>,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct AppliedMigration {
+        pub name: String,
+        pub applied_time: String,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct PreflightReq {
+        pub database_url: String,
+        pub redis_url: String,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct PreflightCheck {
+        /// database, database_version, database_charset, index_length or redis
+        pub name: String,
+        pub passed: bool,
+        /// the found value, or the reason and the fix of the failed check
+        pub message: String,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct PreflightResp {
+        pub passed: bool,
+        pub list: Vec<PreflightCheck>,
     }
 
     fn default_up() -> String {
@@ -69,6 +107,9 @@ mod types {
         pub comet_secret: String,
         #[oai(default = "default_up")]
         pub migration_type: String,
+        /// create the common executors, eg: sh, python3, node, powershell
+        #[oai(default)]
+        pub seed_executors: bool,
     }
 
     #[derive(Object, Serialize, Default)]
@@ -175,6 +216,59 @@ impl MigrationApi {
         })
     }
 
+    /// applied and pending schema migrations of the database
+    #[oai(path = "/status", method = "get")]
+    pub async fn migration_status(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+    ) -> Result<ApiStdResponse<types::MigrationStatusResp>> {
+        if !state.can_manage_user(&user_info.user_id).await? {
+            return Err(NoPermission().into());
+        }
+        let applied = runner::applied(&state.db)
+            .await
+            .map_err(std_into_error)?
+            .into_iter()
+            .map(|v| types::AppliedMigration {
+                name: v.name,
+                applied_time: format_timestamp(v.applied_at),
+            })
+            .collect();
+        let pending = runner::pending(&state.db)
+            .await
+            .map_err(std_into_error)?
+            .into_iter()
+            .map(|v| v.name)
+            .collect();
+        return_ok!(types::MigrationStatusResp { applied, pending })
+    }
+
+    #[oai(path = "/install/preflight", method = "post")]
+    pub async fn install_preflight(
+        &self,
+        install_state: Data<&InstallState>,
+        Json(req): Json<types::PreflightReq>,
+    ) -> Result<ApiStdResponse<types::PreflightResp>> {
+        if install_state.is_installed {
+            return_err!("already installed");
+        }
+        let list: Vec<types::PreflightCheck> =
+            install::preflight(&req.database_url, &req.redis_url)
+                .await
+                .into_iter()
+                .map(|v| types::PreflightCheck {
+                    name: v.name,
+                    passed: v.passed,
+                    message: v.message,
+                })
+                .collect();
+        return_ok!(types::PreflightResp {
+            passed: list.iter().all(|v| v.passed),
+            list,
+        })
+    }
+
     #[oai(path = "/install", method = "post")]
     pub async fn install(
         &self,
@@ -182,31 +276,76 @@ impl MigrationApi {
         Json(req): Json<types::InstallReq>,
         tx: Data<&Sender<()>>,
     ) -> Result<ApiStdResponse<types::InstallResp>> {
-        // 1. connect database
-        let database_url = Url::parse(&req.database_url).context("failed parse database url")?;
+        if install_state.is_installed {
+            return_err!("already installed");
+        }
+        Url::parse(&req.database_url).context("failed parse database url")?;
+        Url::parse(&req.redis_url).context("failed parse redis url")?;
 
-        let opt = ConnectOptions::new(database_url);
-        let conn = Database::connect(opt)
+        // 1. check database and redis
+        let failed: Vec<String> = install::preflight(&req.database_url, &req.redis_url)
             .await
-            .context("failed connect database")?;
+            .into_iter()
+            .filter(|v| !v.passed)
+            .map(|v| format!("{}: {}", v.name, v.message))
+            .collect();
+        if !failed.is_empty() {
+            return_err!(format!("preflight check failed, {}", failed.join("; ")));
+        }
 
-        // 2. connect redis
-        let redis_url = Url::parse(&req.redis_url).context("failed parse redis url")?;
-        Client::open(redis_url).context("failed connect to redis")?;
+        let conn = install::connect_database(&req.database_url).await?;
+        // only the tables created by this installation are dropped on failure
+        let rollback = req.migration_type == "up" && install::is_fresh_database(&conn).await?;
 
+        match Self::do_install(&conn, &install_state, req).await {
+            Ok(seeded_executors) => {
+                tx.send(()).await.context("failed send install signal")?;
+                return_ok!(types::InstallResp {
+                    result: 0,
+                    seeded_executors,
+                })
+            }
+            Err(e) => {
+                if let Err(e) = Self::rollback(&conn, &install_state, rollback).await {
+                    error!("failed rollback installation - {e:?}");
+                }
+                Err(e.into())
+            }
+        }
+    }
+}
+
+impl MigrationApi {
+    /// returns the names of the seeded executors
+    async fn do_install(
+        conn: &DbConn,
+        install_state: &InstallState,
+        req: types::InstallReq,
+    ) -> anyhow::Result<Vec<String>> {
+        // 2. migrate database
         if req.migration_type == "up" {
-            migration::Migrator::up(&conn, None)
+            migration::Migrator::up(conn, None)
                 .await
                 .context("failed migrate database")?;
         }
 
-        // 2. create admin user
-        let _ = UserLogic::init_admin(&conn, &req.username, &req.password)
+        // 3. create admin user
+        UserLogic::init_admin(conn, &req.username, &req.password)
             .await
             .context("failed create admin user")?;
 
-        // 3. generate config file
+        // 4. seed executors
+        let seeded = if req.seed_executors {
+            install::seed_executors(conn)
+                .await
+                .context("failed seed executors")?
+        } else {
+            vec![]
+        };
+
+        // 5. generate config file
         let mut conf = Conf::default();
+        conf.config_version = CONFIG_VERSION;
         conf.database_url = req.database_url;
         conf.redis_url = req.redis_url;
         conf.bind_addr = req.bind_addr;
@@ -216,8 +355,27 @@ impl MigrationApi {
         conf.encrypt.private_key = nanoid!();
         conf.sync2file(install_state.config_file.clone())
             .context("failed save config file")?;
+        Ok(seeded)
+    }
 
-        tx.send(()).await.context("failed send install signal")?;
-        return_ok!(types::InstallResp { result: 0 })
+    /// remove the partially written config file, and revert the migrations if drop_tables
+    async fn rollback(
+        conn: &DbConn,
+        install_state: &InstallState,
+        drop_tables: bool,
+    ) -> anyhow::Result<()> {
+        if let Some(ref config_file) = install_state.config_file {
+            let real_path = shellexpand::full(config_file)?.to_string();
+            if std::path::Path::new(&real_path).exists() {
+                std::fs::remove_file(&real_path)?;
+            }
+        }
+        if drop_tables {
+            info!("rollback the migrations of the failed installation");
+            migration::Migrator::reset(conn)
+                .await
+                .context("failed revert migrations")?;
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,351 @@
+use poem::{web::Data, Endpoint, EndpointExt};
+use poem_openapi::{
+    param::{Header, Query},
+    payload::Json,
+    OpenApi,
+};
+
+use crate::{
+    api_response,
+    entity::job_promotion,
+    error::NoPermission,
+    local_time,
+    logic::{
+        self,
+        job::{PromotionApproval, PromotionSnapshot, TagRewrite},
+        role::POLICY_ALLOW_CHANGE_ALL_JOB,
+    },
+    middleware,
+    response::std_into_error,
+    return_err, return_ok,
+    state::AppState,
+};
+
+pub mod types {
+    use poem_openapi::Object;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Object, Deserialize, Serialize, Clone)]
+    pub struct TagRewrite {
+        #[oai(validator(min_length = 1, max_length = 200))]
+        pub from: String,
+        #[oai(validator(min_length = 1, max_length = 200))]
+        pub to: String,
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct RequestPromotionReq {
+        pub eid: String,
+        /// the version of the job to promote, the promotion fails if the job was changed since
+        pub version: u32,
+        pub target_team_id: u64,
+        /// rewritten to target_namespace in the name, the args and the tags of the job,
+        /// eg: staging
+        #[oai(validator(max_length = 50))]
+        pub source_namespace: String,
+        /// eg: production
+        #[oai(validator(max_length = 50))]
+        pub target_namespace: String,
+        /// applied to the tags before the namespace rewrite
+        #[oai(default)]
+        pub tag_rewrites: Vec<TagRewrite>,
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct ApprovePromotionReq {
+        pub id: u64,
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct RejectPromotionReq {
+        pub id: u64,
+        #[oai(default, validator(max_length = 500))]
+        pub reason: String,
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct QueryPromotionResp {
+        pub total: u64,
+        pub list: Vec<PromotionRecord>,
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct PromotionApproval {
+        pub username: String,
+        pub approved_time: String,
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct PromotionRecord {
+        pub id: u64,
+        pub job_name: String,
+        pub source_eid: String,
+        pub source_team_id: u64,
+        pub source_version: u32,
+        pub source_namespace: String,
+        pub target_team_id: u64,
+        pub target_namespace: String,
+        pub target_eid: String,
+        pub tag_rewrites: Vec<TagRewrite>,
+        pub tags: Vec<String>,
+        pub required_approvals: u32,
+        pub approvals: Vec<PromotionApproval>,
+        /// pending, promoted, rejected or failed
+        pub status: String,
+        pub reason: String,
+        pub created_user: String,
+        pub updated_user: String,
+        pub created_time: String,
+        pub updated_time: String,
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct PromotionLineageResp {
+        /// the promotions the job came from, the nearest first
+        pub upstream: Vec<PromotionRecord>,
+        /// the promotions of the job, the latest first
+        pub downstream: Vec<PromotionRecord>,
+    }
+}
+
+fn set_middleware(ep: impl Endpoint) -> impl Endpoint {
+    ep.with(middleware::TeamPermissionMiddleware)
+        .with(middleware::PermissionMiddleware::grant(
+            &POLICY_ALLOW_CHANGE_ALL_JOB,
+        ))
+}
+
+fn promotion_record(v: job_promotion::Model) -> poem::Result<types::PromotionRecord> {
+    let snapshot: PromotionSnapshot = v
+        .snapshot
+        .map(serde_json::from_value)
+        .transpose()
+        .map_err(std_into_error)?
+        .unwrap_or_default();
+    let tag_rewrites: Vec<TagRewrite> = v
+        .tag_rewrites
+        .map(serde_json::from_value)
+        .transpose()
+        .map_err(std_into_error)?
+        .unwrap_or_default();
+    let approvals: Vec<PromotionApproval> = v
+        .approvals
+        .map(serde_json::from_value)
+        .transpose()
+        .map_err(std_into_error)?
+        .unwrap_or_default();
+
+    Ok(types::PromotionRecord {
+        id: v.id,
+        job_name: snapshot.job.name,
+        source_eid: v.source_eid,
+        source_team_id: v.source_team_id,
+        source_version: v.source_version,
+        source_namespace: v.source_namespace,
+        target_team_id: v.target_team_id,
+        target_namespace: v.target_namespace,
+        target_eid: v.target_eid,
+        tag_rewrites: tag_rewrites
+            .into_iter()
+            .map(|v| types::TagRewrite {
+                from: v.from,
+                to: v.to,
+            })
+            .collect(),
+        tags: snapshot.tags,
+        required_approvals: v.required_approvals,
+        approvals: approvals
+            .into_iter()
+            .map(|v| types::PromotionApproval {
+                username: v.username,
+                approved_time: v.approved_time,
+            })
+            .collect(),
+        status: v.status,
+        reason: v.reason,
+        created_user: v.created_user,
+        updated_user: v.updated_user,
+        created_time: local_time!(v.created_time),
+        updated_time: local_time!(v.updated_time),
+    })
+}
+
+/// the promotions are reviewed by the target team
+async fn can_review_promotion(
+    state: &AppState,
+    user_info: &logic::types::UserInfo,
+    granted: &middleware::Granted,
+    record: &job_promotion::Model,
+) -> anyhow::Result<bool> {
+    if granted.manage_job() {
+        return Ok(true);
+    }
+    state
+        .service()
+        .team
+        .can_write_team(Some(record.target_team_id), user_info.user_id.clone())
+        .await
+}
+
+pub struct PromotionApi;
+
+#[OpenApi(prefix_path = "/promotion", tag = super::Tag::Promotion)]
+impl PromotionApi {
+    /// keep the job at the version until the target team approves its promotion
+    #[oai(path = "/request", method = "post", transform = "set_middleware")]
+    pub async fn request_promotion(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Json(req): Json<types::RequestPromotionReq>,
+    ) -> api_response!(types::PromotionRecord) {
+        let svc = state.service();
+        if !svc
+            .job
+            .can_write_job(&user_info, team_id, Some(req.eid.clone()))
+            .await?
+        {
+            return Err(NoPermission().into());
+        }
+
+        let record = svc
+            .job
+            .request_promotion(
+                &user_info,
+                &req.eid,
+                req.version,
+                req.target_team_id,
+                req.source_namespace,
+                req.target_namespace,
+                req.tag_rewrites
+                    .into_iter()
+                    .map(|v| TagRewrite {
+                        from: v.from,
+                        to: v.to,
+                    })
+                    .collect(),
+            )
+            .await?;
+        return_ok!(promotion_record(record)?)
+    }
+
+    /// the job is promoted by the last required approval
+    #[oai(path = "/approve", method = "post", transform = "set_middleware")]
+    pub async fn approve_promotion(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
+        Json(req): Json<types::ApprovePromotionReq>,
+    ) -> api_response!(types::PromotionRecord) {
+        let svc = state.service();
+        let Some(record) = svc.job.get_promotion(req.id).await? else {
+            return_err!("cannot found promotion");
+        };
+        if !can_review_promotion(&state, &user_info, &granted, &record).await? {
+            return Err(NoPermission().into());
+        }
+
+        let record = svc.job.approve_promotion(&user_info, record).await?;
+        return_ok!(promotion_record(record)?)
+    }
+
+    #[oai(path = "/reject", method = "post", transform = "set_middleware")]
+    pub async fn reject_promotion(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
+        Json(req): Json<types::RejectPromotionReq>,
+    ) -> api_response!(types::PromotionRecord) {
+        let svc = state.service();
+        let Some(record) = svc.job.get_promotion(req.id).await? else {
+            return_err!("cannot found promotion");
+        };
+        if !can_review_promotion(&state, &user_info, &granted, &record).await? {
+            return Err(NoPermission().into());
+        }
+
+        let record = svc
+            .job
+            .reject_promotion(&user_info, record, req.reason)
+            .await?;
+        return_ok!(promotion_record(record)?)
+    }
+
+    /// the promotions from or to the current team, those requested by the user without team
+    #[oai(path = "/list", method = "get", transform = "set_middleware")]
+    pub async fn query_promotion(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        #[oai(default)] Query(eid): Query<Option<String>>,
+        #[oai(default)] Query(status): Query<Option<String>>,
+        #[oai(default = "super::default_page", validator(maximum(value = "10000")))]
+        Query(page): Query<u64>,
+        #[oai(
+            default = "super::default_page_size",
+            validator(maximum(value = "10000"))
+        )]
+        Query(page_size): Query<u64>,
+    ) -> api_response!(types::QueryPromotionResp) {
+        let svc = state.service();
+        let created_user = match team_id {
+            None if !granted.manage_job() => Some(user_info.username.clone()),
+            _ => None,
+        };
+        let ret = svc
+            .job
+            .query_promotion(
+                team_id,
+                created_user,
+                eid.filter(|v| v != ""),
+                status.filter(|v| v != ""),
+                page - 1,
+                page_size,
+            )
+            .await?;
+
+        let mut list = Vec::new();
+        for v in ret.0 {
+            list.push(promotion_record(v)?);
+        }
+        return_ok!(types::QueryPromotionResp { total: ret.1, list })
+    }
+
+    /// where the job was promoted from and to, for the audits
+    #[oai(path = "/lineage", method = "get", transform = "set_middleware")]
+    pub async fn get_promotion_lineage(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Query(eid): Query<String>,
+    ) -> api_response!(types::PromotionLineageResp) {
+        let svc = state.service();
+        if !svc
+            .job
+            .can_write_job(&user_info, team_id, Some(eid.clone()))
+            .await?
+        {
+            return Err(NoPermission().into());
+        }
+
+        let lineage = svc.job.get_promotion_lineage(&eid).await?;
+        let mut upstream = Vec::new();
+        for v in lineage.upstream {
+            upstream.push(promotion_record(v)?);
+        }
+        let mut downstream = Vec::new();
+        for v in lineage.downstream {
+            downstream.push(promotion_record(v)?);
+        }
+        return_ok!(types::PromotionLineageResp {
+            upstream,
+            downstream
+        })
+    }
+}
@@ -0,0 +1,363 @@
+use poem::{web::Data, Endpoint, EndpointExt};
+use poem_openapi::{
+    param::{Header, Query},
+    payload::Json,
+    OpenApi,
+};
+use sea_orm::{ActiveValue::NotSet, Set};
+
+use crate::{
+    api_response,
+    entity::report,
+    error::NoPermission,
+    local_time,
+    logic::{
+        self,
+        report::{ReportChannel, REPORT_INSTANCE_AVAILABILITY},
+        role::POLICY_ALLOW_CHANGE_ALL_JOB,
+    },
+    middleware,
+    response::std_into_error,
+    return_err, return_ok,
+    state::AppState,
+};
+
+pub mod types {
+    use std::collections::HashMap;
+
+    use poem_openapi::Object;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Object, Deserialize, Serialize, Clone)]
+    pub struct ReportChannel {
+        #[oai(validator(min_length = 1, max_length = 500))]
+        pub url: String,
+        #[oai(default)]
+        pub headers: HashMap<String, String>,
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct SaveReportReq {
+        pub id: Option<u64>,
+        #[oai(validator(min_length = 1, max_length = 100))]
+        pub name: String,
+        /// failure_summary of the current team, or instance_availability of all the instances,
+        /// which is global and needs the permission to manage the jobs
+        #[oai(validator(
+            custom = "crate::api::OneOfValidator::new(vec![\"failure_summary\", \"instance_availability\"])"
+        ))]
+        pub report_type: String,
+        #[oai(
+            default = "default_format",
+            validator(custom = "crate::api::OneOfValidator::new(vec![\"html\", \"pdf\"])")
+        )]
+        pub format: String,
+        /// cron expression, eg: 0 0 9 * * Mon
+        #[oai(validator(min_length = 1, max_length = 100))]
+        pub timer_expr: String,
+        /// days covered by the report, ending at its run time
+        #[oai(
+            default = "default_period_days",
+            validator(minimum(value = "1"), maximum(value = "90"))
+        )]
+        pub period_days: u32,
+        #[oai(validator(min_items = 1, max_items = 10))]
+        pub channels: Vec<ReportChannel>,
+        #[oai(default = "default_enabled")]
+        pub is_enabled: bool,
+    }
+
+    fn default_format() -> String {
+        "html".to_string()
+    }
+
+    fn default_period_days() -> u32 {
+        7
+    }
+
+    fn default_enabled() -> bool {
+        true
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct SaveReportResp {
+        pub result: u64,
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct ReportIdReq {
+        pub id: u64,
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct DeleteReportResp {
+        pub result: u64,
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct SendReportResp {
+        pub result: bool,
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct QueryReportResp {
+        pub total: u64,
+        pub list: Vec<ReportRecord>,
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct ReportRecord {
+        pub id: u64,
+        pub name: String,
+        pub team_id: u64,
+        pub report_type: String,
+        pub format: String,
+        pub timer_expr: String,
+        pub period_days: u32,
+        pub channels: Vec<ReportChannel>,
+        pub is_enabled: bool,
+        pub next_run_time: Option<String>,
+        pub last_run_time: Option<String>,
+        pub last_error: String,
+        pub created_user: String,
+        pub updated_user: String,
+        pub created_time: String,
+        pub updated_time: String,
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct PreviewReportResp {
+        pub title: String,
+        pub since: String,
+        pub until: String,
+        pub summary: Vec<String>,
+        pub columns: Vec<String>,
+        pub rows: Vec<Vec<String>>,
+        /// the report as delivered in html format
+        pub html: String,
+    }
+}
+
+fn set_middleware(ep: impl Endpoint) -> impl Endpoint {
+    ep.with(middleware::TeamPermissionMiddleware)
+        .with(middleware::PermissionMiddleware::grant(
+            &POLICY_ALLOW_CHANGE_ALL_JOB,
+        ))
+}
+
+/// the global reports, eg: instance_availability, need the permission to manage the jobs
+async fn can_write_report(
+    state: &AppState,
+    user_info: &logic::types::UserInfo,
+    granted: &middleware::Granted,
+    team_id: u64,
+) -> anyhow::Result<bool> {
+    if team_id == 0 {
+        return Ok(granted.manage_job());
+    }
+    state
+        .service()
+        .team
+        .can_write_team(Some(team_id), user_info.user_id.clone())
+        .await
+}
+
+pub struct ReportApi;
+
+#[OpenApi(prefix_path = "/report", tag = super::Tag::Report)]
+impl ReportApi {
+    #[oai(path = "/save", method = "post", transform = "set_middleware")]
+    pub async fn save_report(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Json(req): Json<types::SaveReportReq>,
+    ) -> api_response!(types::SaveReportResp) {
+        let svc = state.service();
+        let team_id = match req.report_type.as_str() {
+            REPORT_INSTANCE_AVAILABILITY => 0,
+            _ => team_id.unwrap_or_default(),
+        };
+        if !can_write_report(&state, &user_info, &granted, team_id).await? {
+            return Err(NoPermission().into());
+        }
+
+        if let Some(id) = req.id {
+            let Some(record) = svc.report.get_report(id).await? else {
+                return_err!("cannot found report");
+            };
+            if record.team_id != team_id {
+                return Err(NoPermission().into());
+            }
+        }
+
+        let channels: Vec<ReportChannel> = req
+            .channels
+            .into_iter()
+            .map(|v| ReportChannel {
+                url: v.url,
+                headers: v.headers,
+            })
+            .collect();
+
+        let result = svc
+            .report
+            .save_report(report::ActiveModel {
+                id: req.id.filter(|v| *v != 0).map_or(NotSet, |v| Set(v)),
+                name: Set(req.name),
+                team_id: Set(team_id),
+                report_type: Set(req.report_type),
+                format: Set(req.format),
+                timer_expr: Set(req.timer_expr),
+                period_days: Set(req.period_days),
+                channels: Set(Some(
+                    serde_json::to_value(channels).map_err(std_into_error)?,
+                )),
+                is_enabled: Set(req.is_enabled),
+                created_user: req.id.map_or(Set(user_info.username.clone()), |_| NotSet),
+                updated_user: Set(user_info.username.clone()),
+                ..Default::default()
+            })
+            .await?;
+
+        return_ok!(types::SaveReportResp { result })
+    }
+
+    #[oai(path = "/list", method = "get", transform = "set_middleware")]
+    pub async fn query_report(
+        &self,
+        state: Data<&AppState>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        #[oai(default)] Query(name): Query<Option<String>>,
+        #[oai(default = "super::default_page", validator(maximum(value = "10000")))]
+        Query(page): Query<u64>,
+        #[oai(
+            default = "super::default_page_size",
+            validator(maximum(value = "10000"))
+        )]
+        Query(page_size): Query<u64>,
+    ) -> api_response!(types::QueryReportResp) {
+        let svc = state.service();
+        let ret = svc
+            .report
+            .query_report(
+                team_id.or(Some(0)),
+                name.filter(|v| v != ""),
+                page - 1,
+                page_size,
+            )
+            .await?;
+
+        let mut list = Vec::new();
+        for v in ret.0 {
+            let channels: Vec<ReportChannel> = v
+                .channels
+                .map(|v| serde_json::from_value(v))
+                .transpose()
+                .map_err(std_into_error)?
+                .unwrap_or_default();
+            list.push(types::ReportRecord {
+                id: v.id,
+                name: v.name,
+                team_id: v.team_id,
+                report_type: v.report_type,
+                format: v.format,
+                timer_expr: v.timer_expr,
+                period_days: v.period_days,
+                channels: channels
+                    .into_iter()
+                    .map(|v| types::ReportChannel {
+                        url: v.url,
+                        headers: v.headers,
+                    })
+                    .collect(),
+                is_enabled: v.is_enabled,
+                next_run_time: v.next_run_time.map(|v| local_time!(v)),
+                last_run_time: v.last_run_time.map(|v| local_time!(v)),
+                last_error: v.last_error,
+                created_user: v.created_user,
+                updated_user: v.updated_user,
+                created_time: local_time!(v.created_time),
+                updated_time: local_time!(v.updated_time),
+            });
+        }
+
+        return_ok!(types::QueryReportResp { total: ret.1, list })
+    }
+
+    #[oai(path = "/delete", method = "post", transform = "set_middleware")]
+    pub async fn delete_report(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
+        Json(req): Json<types::ReportIdReq>,
+    ) -> api_response!(types::DeleteReportResp) {
+        let svc = state.service();
+        let Some(record) = svc.report.get_report(req.id).await? else {
+            return_err!("cannot found report");
+        };
+        if !can_write_report(&state, &user_info, &granted, record.team_id).await? {
+            return Err(NoPermission().into());
+        }
+
+        let result = svc.report.delete_report(req.id).await?;
+        return_ok!(types::DeleteReportResp { result })
+    }
+
+    /// the report as it would be delivered now, without sending it
+    #[oai(path = "/preview", method = "get", transform = "set_middleware")]
+    pub async fn preview_report(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
+        Query(id): Query<u64>,
+    ) -> api_response!(types::PreviewReportResp) {
+        let svc = state.service();
+        let Some(record) = svc.report.get_report(id).await? else {
+            return_err!("cannot found report");
+        };
+        if !can_write_report(&state, &user_info, &granted, record.team_id).await? {
+            return Err(NoPermission().into());
+        }
+
+        let table = svc
+            .report
+            .build_report(&record, chrono::Local::now())
+            .await?;
+        let html = logic::report::render_html(&table)?;
+        return_ok!(types::PreviewReportResp {
+            title: table.title,
+            since: table.since,
+            until: table.until,
+            summary: table.summary,
+            columns: table.columns,
+            rows: table.rows,
+            html,
+        })
+    }
+
+    /// deliver the report now, the schedule of the report is unchanged
+    #[oai(path = "/send", method = "post", transform = "set_middleware")]
+    pub async fn send_report(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
+        Json(req): Json<types::ReportIdReq>,
+    ) -> api_response!(types::SendReportResp) {
+        let svc = state.service();
+        let Some(record) = svc.report.get_report(req.id).await? else {
+            return_err!("cannot found report");
+        };
+        if !can_write_report(&state, &user_info, &granted, record.team_id).await? {
+            return Err(NoPermission().into());
+        }
+
+        svc.report.deliver_report(&record).await?;
+        return_ok!(types::SendReportResp { result: true })
+    }
+}
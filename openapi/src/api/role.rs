@@ -1,12 +1,23 @@
-use poem::{session::Session, web::Data, Result};
+use poem::{session::Session, web::Data, Endpoint, EndpointExt, Result};
 use poem_openapi::{param::Query, payload::Json, OpenApi};
 use sea_orm::{ActiveValue::NotSet, Set};
 
 use crate::{
-    api_response, entity::role, error::NoPermission, local_time, logic, response::ApiStdResponse,
+    api_response,
+    entity::role,
+    local_time,
+    logic::{self, role::POLICY_ALLOW_MANAGE_ALL_USER},
+    middleware,
+    response::ApiStdResponse,
     return_err, return_ok, AppState,
 };
 
+fn set_middleware(ep: impl Endpoint) -> impl Endpoint {
+    ep.with(middleware::PermissionMiddleware::new(
+        &POLICY_ALLOW_MANAGE_ALL_USER,
+    ))
+}
+
 pub struct RoleApi;
 
 mod types {
@@ -25,6 +36,9 @@ mod types {
         pub info: String,
         pub user_ids: Option<Vec<String>>,
         pub permissions: Option<Vec<String>>,
+        /// limit the role to the jobs, schedules and instances carrying the tags or the
+        /// tags under them, eg: env/prod. an empty list removes the limit
+        pub tags: Option<Vec<String>>,
     }
 
     #[derive(Object, Serialize, Deserialize)]
@@ -45,6 +59,7 @@ mod types {
         pub info: String,
         pub user_total: i64,
         pub permissions: Vec<String>,
+        pub tags: Vec<String>,
         pub created_time: String,
         pub created_user: String,
     }
@@ -87,7 +102,7 @@ mod types {
 
 #[OpenApi(prefix_path = "/role", tag = super::Tag::Role)]
 impl RoleApi {
-    #[oai(path = "/save", method = "post")]
+    #[oai(path = "/save", method = "post", transform = "set_middleware")]
     pub async fn save_role(
         &self,
         user_info: Data<&logic::types::UserInfo>,
@@ -95,11 +110,6 @@ impl RoleApi {
         state: Data<&AppState>,
         Json(req): Json<types::SaveRoleReq>,
     ) -> Result<ApiStdResponse<types::SaveRoleResult>> {
-        let ok = state.can_manage_user(&user_info.user_id).await?;
-        if !ok {
-            return Err(NoPermission().into());
-        }
-
         if req.id == Some(1) {
             return_err!("Dont't allow modify admin role");
         }
@@ -121,22 +131,21 @@ impl RoleApi {
         if let Some(permissions) = req.permissions {
             state.set_permissions(role_id, permissions).await?;
         }
+        if let Some(tags) = req.tags {
+            state.set_tag_policies(role_id, tags).await?;
+        }
 
         return_ok!(types::SaveRoleResult { role_id })
     }
 
-    #[oai(path = "/set-user", method = "post")]
+    #[oai(path = "/set-user", method = "post", transform = "set_middleware")]
     pub async fn set_user(
         &self,
-        user_info: Data<&logic::types::UserInfo>,
+        _user_info: Data<&logic::types::UserInfo>,
         _session: &Session,
         state: Data<&AppState>,
         Json(req): Json<types::SetUserReq>,
     ) -> Result<ApiStdResponse<types::UpdateResult>> {
-        let ok = state.can_manage_user(&user_info.user_id).await?;
-        if !ok {
-            return Err(NoPermission().into());
-        }
         let state_clone = state.clone();
         let affected = state
             .service()
@@ -154,7 +163,7 @@ impl RoleApi {
         return_ok!(types::UpdateResult { affected })
     }
 
-    #[oai(path = "/delete", method = "post")]
+    #[oai(path = "/delete", method = "post", transform = "set_middleware")]
     pub async fn delete_role(
         &self,
         _user_info: Data<&logic::types::UserInfo>,
@@ -166,7 +175,7 @@ impl RoleApi {
         return_ok!(types::UpdateResult { affected })
     }
 
-    #[oai(path = "/list", method = "get")]
+    #[oai(path = "/list", method = "get", transform = "set_middleware")]
     pub async fn query_role(
         &self,
         state: Data<&AppState>,
@@ -185,13 +194,8 @@ impl RoleApi {
             validator(maximum(value = "10000"))
         )]
         Query(page): Query<u64>,
-        user_info: Data<&logic::types::UserInfo>,
+        _user_info: Data<&logic::types::UserInfo>,
     ) -> Result<ApiStdResponse<types::QueryRoleResp>> {
-        let ok = state.can_manage_user(&user_info.user_id).await?;
-        if !ok {
-            return Err(NoPermission().into());
-        }
-
         let svc = state.service();
         let ret = svc
             .role
@@ -210,6 +214,7 @@ impl RoleApi {
         let mut list: Vec<types::RoleRecord> = Vec::new();
         for v in ret.0 {
             let permissions = state.get_permissions_for_role(v.id).await?;
+            let tags = state.get_tag_policies_for_role(v.id).await?;
             list.push(types::RoleRecord {
                 id: v.id,
                 created_time: local_time!(v.created_time),
@@ -217,6 +222,7 @@ impl RoleApi {
                 name: v.name,
                 info: v.info,
                 permissions,
+                tags,
                 created_user: v.created_user,
             });
         }
@@ -224,19 +230,14 @@ impl RoleApi {
         return_ok!(types::QueryRoleResp { total: ret.1, list })
     }
 
-    #[oai(path = "/bind-instance", method = "post")]
+    #[oai(path = "/bind-instance", method = "post", transform = "set_middleware")]
     pub async fn bind_instance(
         &self,
         state: Data<&AppState>,
         _session: &Session,
-        user_info: Data<&logic::types::UserInfo>,
+        _user_info: Data<&logic::types::UserInfo>,
         Json(req): Json<types::BindInsanceReq>,
     ) -> api_response!(types::BindInsanceResp) {
-        let ok = state.can_manage_user(&user_info.user_id).await?;
-        if !ok {
-            return Err(NoPermission().into());
-        }
-
         let svc = state.service();
         let ret = svc
             .role
@@ -249,19 +250,18 @@ impl RoleApi {
         return_ok!(types::BindInsanceResp { result: ret })
     }
 
-    #[oai(path = "/unbind-instance", method = "post")]
+    #[oai(
+        path = "/unbind-instance",
+        method = "post",
+        transform = "set_middleware"
+    )]
     pub async fn unbind_instance(
         &self,
         state: Data<&AppState>,
         _session: &Session,
-        user_info: Data<&logic::types::UserInfo>,
+        _user_info: Data<&logic::types::UserInfo>,
         Json(req): Json<types::UnbindInsanceReq>,
     ) -> api_response!(types::UnbindInsanceResp) {
-        let ok = state.can_manage_user(&user_info.user_id).await?;
-        if !ok {
-            return Err(NoPermission().into());
-        }
-
         let svc = state.service();
         let ret = svc
             .role
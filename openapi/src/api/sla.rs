@@ -0,0 +1,258 @@
+use poem::{web::Data, Endpoint, EndpointExt};
+use poem_openapi::{
+    param::{Header, Query},
+    payload::Json,
+    OpenApi,
+};
+use sea_orm::Set;
+
+use crate::{
+    api_response, entity::job_sla, error::NoPermission, local_time, logic, middleware, return_err,
+    return_ok, state::AppState,
+};
+
+pub mod types {
+    use poem_openapi::Object;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct SaveSlaReq {
+        pub eid: String,
+        /// runs longer than it are breaches, 0 disables the check
+        #[oai(default)]
+        pub max_duration_secs: u64,
+        /// a breach is marked when the failed runs in a row reach it, 0 disables the check
+        #[oai(default)]
+        pub max_failure_streak: u32,
+        /// percent of the runs of a day which must not fail, 0 disables the check
+        #[oai(default, validator(maximum(value = "100")))]
+        pub min_success_rate: u32,
+        #[oai(default = "default_enabled")]
+        pub is_enabled: bool,
+    }
+
+    fn default_enabled() -> bool {
+        true
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct SaveSlaResp {
+        pub result: u64,
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct DeleteSlaReq {
+        pub eid: String,
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct DeleteSlaResp {
+        pub result: u64,
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct SlaStatusResp {
+        pub total: u64,
+        pub compliant_total: u64,
+        pub list: Vec<SlaStatusRecord>,
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct SlaStatusRecord {
+        pub eid: String,
+        pub job_name: String,
+        pub team_id: u64,
+        pub max_duration_secs: u64,
+        pub max_failure_streak: u32,
+        pub min_success_rate: u32,
+        pub is_enabled: bool,
+        pub failure_streak: u32,
+        pub today_run_total: u64,
+        pub today_success_rate: Option<u32>,
+        /// breaches in the latest days of the query
+        pub breach_total: u64,
+        pub last_breach_time: Option<String>,
+        pub compliant: bool,
+        pub updated_user: String,
+        pub updated_time: String,
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct QuerySlaBreachResp {
+        pub total: u64,
+        pub list: Vec<SlaBreachRecord>,
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct SlaBreachRecord {
+        pub id: u64,
+        pub eid: String,
+        /// duration, failure_streak or success_rate
+        pub sla_type: String,
+        /// the run of the duration and failure_streak breaches
+        pub run_id: String,
+        /// the day of the success_rate breaches
+        pub stat_date: Option<String>,
+        pub value: u64,
+        pub threshold: u64,
+        pub created_time: String,
+    }
+}
+
+fn set_middleware(ep: impl Endpoint) -> impl Endpoint {
+    ep.with(middleware::TeamPermissionMiddleware)
+}
+
+pub struct SlaApi;
+
+#[OpenApi(prefix_path = "/sla", tag = super::Tag::Sla)]
+impl SlaApi {
+    /// the breaches are evaluated from the runs finished after the sla is saved
+    #[oai(path = "/save", method = "post", transform = "set_middleware")]
+    pub async fn save_sla(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Json(req): Json<types::SaveSlaReq>,
+    ) -> api_response!(types::SaveSlaResp) {
+        let svc = state.service();
+        if !svc
+            .job
+            .can_write_job(&user_info, team_id, Some(req.eid.clone()))
+            .await?
+        {
+            return Err(NoPermission().into());
+        }
+        if req.max_duration_secs == 0 && req.max_failure_streak == 0 && req.min_success_rate == 0 {
+            return_err!("at least one limit of the sla is required");
+        }
+
+        let result = svc
+            .job
+            .save_job_sla(job_sla::ActiveModel {
+                eid: Set(req.eid),
+                max_duration_secs: Set(req.max_duration_secs),
+                max_failure_streak: Set(req.max_failure_streak),
+                min_success_rate: Set(req.min_success_rate),
+                is_enabled: Set(req.is_enabled),
+                created_user: Set(user_info.username.clone()),
+                updated_user: Set(user_info.username.clone()),
+                ..Default::default()
+            })
+            .await?;
+        return_ok!(types::SaveSlaResp { result })
+    }
+
+    #[oai(path = "/delete", method = "post", transform = "set_middleware")]
+    pub async fn delete_sla(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Json(req): Json<types::DeleteSlaReq>,
+    ) -> api_response!(types::DeleteSlaResp) {
+        let svc = state.service();
+        if !svc
+            .job
+            .can_write_job(&user_info, team_id, Some(req.eid.clone()))
+            .await?
+        {
+            return Err(NoPermission().into());
+        }
+
+        let result = svc.job.delete_job_sla(&req.eid).await?;
+        return_ok!(types::DeleteSlaResp { result })
+    }
+
+    /// the compliance of the jobs visible to the user
+    #[oai(path = "/status", method = "get", transform = "set_middleware")]
+    pub async fn get_sla_status(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        #[oai(
+            default = "default_days",
+            validator(minimum(value = "1"), maximum(value = "90"))
+        )]
+        Query(days): Query<i64>,
+    ) -> api_response!(types::SlaStatusResp) {
+        let svc = state.service();
+        let list: Vec<types::SlaStatusRecord> = svc
+            .job
+            .get_sla_status(&user_info, team_id, days)
+            .await?
+            .into_iter()
+            .map(|v| types::SlaStatusRecord {
+                eid: v.sla.eid,
+                job_name: v.sla.job_name.unwrap_or_default(),
+                team_id: v.sla.team_id.unwrap_or_default(),
+                max_duration_secs: v.sla.max_duration_secs,
+                max_failure_streak: v.sla.max_failure_streak,
+                min_success_rate: v.sla.min_success_rate,
+                is_enabled: v.sla.is_enabled,
+                failure_streak: v.sla.failure_streak,
+                today_run_total: v.today_run_total,
+                today_success_rate: v.today_success_rate,
+                breach_total: v.breach_total,
+                last_breach_time: v.last_breach_time.map(|v| local_time!(v)),
+                compliant: v.compliant,
+                updated_user: v.sla.updated_user,
+                updated_time: local_time!(v.sla.updated_time),
+            })
+            .collect();
+
+        return_ok!(types::SlaStatusResp {
+            total: list.len() as u64,
+            compliant_total: list.iter().filter(|v| v.compliant).count() as u64,
+            list,
+        })
+    }
+
+    #[oai(path = "/breach-list", method = "get", transform = "set_middleware")]
+    pub async fn query_sla_breach(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        Query(eid): Query<String>,
+        #[oai(default = "super::default_page", validator(maximum(value = "10000")))]
+        Query(page): Query<u64>,
+        #[oai(
+            default = "super::default_page_size",
+            validator(maximum(value = "10000"))
+        )]
+        Query(page_size): Query<u64>,
+    ) -> api_response!(types::QuerySlaBreachResp) {
+        let svc = state.service();
+        if !svc
+            .job
+            .can_write_job(&user_info, team_id, Some(eid.clone()))
+            .await?
+        {
+            return Err(NoPermission().into());
+        }
+
+        let ret = svc.job.query_sla_breach(&eid, page - 1, page_size).await?;
+        let list = ret
+            .0
+            .into_iter()
+            .map(|v| types::SlaBreachRecord {
+                id: v.id,
+                eid: v.eid,
+                sla_type: v.sla_type,
+                run_id: v.run_id,
+                stat_date: v.stat_date.map(|v| v.to_string()),
+                value: v.value,
+                threshold: v.threshold,
+                created_time: local_time!(v.created_time),
+            })
+            .collect();
+        return_ok!(types::QuerySlaBreachResp { total: ret.1, list })
+    }
+}
+
+fn default_days() -> i64 {
+    7
+}
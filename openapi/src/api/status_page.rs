@@ -0,0 +1,129 @@
+use poem::{
+    error::NotFoundError,
+    handler,
+    web::{Data, Html, Path},
+    Result,
+};
+use service::logic::job::StatusPageView;
+
+use super::types;
+use crate::{response::ApiStdResponse, return_err, return_ok, AppState};
+
+fn escape_html(v: &str) -> String {
+    let mut ret = String::with_capacity(v.len());
+    for c in v.chars() {
+        match c {
+            '&' => ret.push_str("&amp;"),
+            '<' => ret.push_str("&lt;"),
+            '>' => ret.push_str("&gt;"),
+            '"' => ret.push_str("&quot;"),
+            '\'' => ret.push_str("&#39;"),
+            _ => ret.push(c),
+        }
+    }
+    ret
+}
+
+fn render_html(view: &StatusPageView) -> String {
+    let rows: String = view
+        .jobs
+        .iter()
+        .map(|v| {
+            format!(
+                "<tr><td>{}</td><td>{}</td><td class=\"{}\">{}</td><td>{}/{}</td><td>{}</td><td>{}</td></tr>",
+                escape_html(&v.name),
+                escape_html(&v.schedule_type),
+                escape_html(&v.status),
+                escape_html(&v.status),
+                v.healthy_total,
+                v.instance_total,
+                escape_html(v.last_run_time.as_deref().unwrap_or("-")),
+                escape_html(v.next_time.as_deref().unwrap_or("-")),
+            )
+        })
+        .collect();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta http-equiv="refresh" content="30">
+<title>{name}</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em; }}
+table {{ border-collapse: collapse; }}
+th, td {{ padding: 6px 12px; border-bottom: 1px solid #ddd; text-align: left; }}
+.operational {{ color: #389e0d; }}
+.degraded {{ color: #d48806; }}
+.down {{ color: #cf1322; }}
+.inactive {{ color: #8c8c8c; }}
+</style>
+</head>
+<body>
+<h1>{name}</h1>
+<h2 class="{status}">{status}</h2>
+<table>
+<tr><th>job</th><th>type</th><th>status</th><th>healthy</th><th>last run</th><th>next run</th></tr>
+{rows}
+</table>
+<p>updated at {generated_time}</p>
+</body>
+</html>"#,
+        name = escape_html(&view.name),
+        status = escape_html(&view.status),
+        generated_time = escape_html(&view.generated_time),
+    )
+}
+
+/// the health of the jobs of the status page, served without login
+#[handler]
+pub async fn public_status(
+    Path(token): Path<String>,
+    state: Data<&AppState>,
+) -> Result<ApiStdResponse<types::PublicStatusResp>> {
+    let view = match state.service().job.get_public_status(&token).await {
+        Ok(Some(v)) => v,
+        Ok(None) => return_err!("the status page does not exist"),
+        Err(e) => return_err!(e.to_string()),
+    };
+
+    return_ok!(types::PublicStatusResp {
+        name: view.name,
+        status: view.status,
+        jobs: view
+            .jobs
+            .into_iter()
+            .map(|v| types::StatusPageJob {
+                name: v.name,
+                schedule_type: v.schedule_type,
+                status: v.status,
+                instance_total: v.instance_total,
+                healthy_total: v.healthy_total,
+                last_run_time: v.last_run_time,
+                next_time: v.next_time,
+            })
+            .collect(),
+        generated_time: view.generated_time,
+    })
+}
+
+/// the status page rendered for the browsers, it refreshes itself
+#[handler]
+pub async fn public_status_html(
+    Path(token): Path<String>,
+    state: Data<&AppState>,
+) -> Result<Html<String>> {
+    let Some(view) = state.service().job.get_public_status(&token).await? else {
+        return Err(NotFoundError.into());
+    };
+    Ok(Html(render_html(&view)))
+}
+
+#[test]
+fn test_escape_html() {
+    assert_eq!(
+        escape_html("<script>alert('x')</script> & \"y\""),
+        "&lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt; &amp; &quot;y&quot;"
+    );
+}
@@ -8,7 +8,7 @@ use types::{BindTagResp, UnbindTagResp};
 
 use crate::{
     api_response,
-    logic::{self, types::ResourceType},
+    logic::{self, role::POLICY_ALLOW_CHANGE_ALL_JOB, types::ResourceType},
     middleware, return_ok,
     state::AppState,
 };
@@ -59,6 +59,19 @@ pub mod types {
         pub list: Vec<TagCount>,
     }
 
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct QueryTagResp {
+        pub list: Vec<TagRecord>,
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct TagRecord {
+        pub id: u64,
+        /// path of the tag, eg: env/prod
+        pub tag_name: String,
+        pub parent_id: u64,
+    }
+
     #[derive(Object, Deserialize, Serialize)]
     pub struct TagCount {
         pub tag_id: u64,
@@ -69,6 +82,9 @@ pub mod types {
 
 fn set_middleware(ep: impl Endpoint) -> impl Endpoint {
     ep.with(middleware::TeamPermissionMiddleware)
+        .with(middleware::PermissionMiddleware::grant(
+            &POLICY_ALLOW_CHANGE_ALL_JOB,
+        ))
 }
 
 pub struct TagApi;
@@ -123,6 +139,7 @@ impl TagApi {
     pub async fn count_resource(
         &self,
         user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
         state: Data<&AppState>,
         Query(resource_type): Query<types::ResourceType>,
         #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
@@ -135,12 +152,11 @@ impl TagApi {
             types::ResourceType::Workflow => ResourceType::Workflow,
         };
 
-        let search_username =
-            if state.can_manage_job(&user_info.user_id).await? || team_id.is_some() {
-                None
-            } else {
-                Some(user_info.username.clone())
-            };
+        let search_username = if granted.manage_job() || team_id.is_some() {
+            None
+        } else {
+            Some(user_info.username.clone())
+        };
 
         let ret = svc
             .tag
@@ -160,4 +176,30 @@ impl TagApi {
 
         return_ok!(resp);
     }
+
+    /// the tags directly under the namespace, eg: env lists env/prod and env/dev,
+    /// the top level tags are listed without namespace
+    #[oai(path = "/list", method = "get", transform = "set_middleware")]
+    pub async fn query_tag(
+        &self,
+        state: Data<&AppState>,
+        Query(namespace): Query<Option<String>>,
+    ) -> api_response!(types::QueryTagResp) {
+        let ret = state
+            .service()
+            .tag
+            .query_tag(namespace.filter(|v| v != ""))
+            .await?;
+
+        let list = ret
+            .into_iter()
+            .map(|v| types::TagRecord {
+                id: v.id,
+                tag_name: v.tag_name,
+                parent_id: v.parent_id,
+            })
+            .collect();
+
+        return_ok!(types::QueryTagResp { list });
+    }
 }
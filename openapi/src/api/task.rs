@@ -0,0 +1,136 @@
+use poem::{web::Data, Endpoint, EndpointExt};
+use poem_openapi::{
+    param::{Header, Query},
+    OpenApi,
+};
+
+use crate::{
+    api_response,
+    entity::server_task,
+    error::NoPermission,
+    local_time,
+    logic::{self, role::POLICY_ALLOW_CHANGE_ALL_JOB},
+    middleware, return_err, return_ok,
+    state::AppState,
+};
+
+pub mod types {
+    use poem_openapi::Object;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct TaskRecord {
+        pub task_id: String,
+        pub task_type: String,
+        pub team_id: u64,
+        pub params: Option<serde_json::Value>,
+        /// pending, running, succeeded or failed
+        pub status: String,
+        pub output: Option<serde_json::Value>,
+        pub err_msg: Option<String>,
+        /// console running the task
+        pub worker: String,
+        pub started_time: Option<String>,
+        pub finished_time: Option<String>,
+        pub created_user: String,
+        pub created_time: String,
+        pub updated_time: String,
+    }
+
+    #[derive(Object, Deserialize, Serialize)]
+    pub struct QueryTaskResp {
+        pub total: u64,
+        pub list: Vec<TaskRecord>,
+    }
+}
+
+impl From<server_task::Model> for types::TaskRecord {
+    fn from(v: server_task::Model) -> Self {
+        Self {
+            task_id: v.task_id,
+            task_type: v.task_type,
+            team_id: v.team_id,
+            params: v.params,
+            status: v.status,
+            output: v.output,
+            err_msg: v.err_msg,
+            worker: v.worker,
+            started_time: v.started_time.map(|v| local_time!(v)),
+            finished_time: v.finished_time.map(|v| local_time!(v)),
+            created_user: v.created_user,
+            created_time: local_time!(v.created_time),
+            updated_time: local_time!(v.updated_time),
+        }
+    }
+}
+
+fn query_middleware(ep: impl Endpoint) -> impl Endpoint {
+    ep.with(middleware::PermissionMiddleware::grant(
+        &POLICY_ALLOW_CHANGE_ALL_JOB,
+    ))
+}
+
+pub struct TaskApi;
+
+#[OpenApi(prefix_path = "/task", tag = super::Tag::Task)]
+impl TaskApi {
+    /// poll the status of a background task
+    #[oai(path = "/detail", method = "get", transform = "query_middleware")]
+    pub async fn get_task(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
+        Query(task_id): Query<String>,
+    ) -> api_response!(types::TaskRecord) {
+        let Some(record) = state.service().task.get_task(&task_id).await? else {
+            return_err!("cannot found task");
+        };
+        if record.created_user != user_info.username && !granted.manage_job() {
+            return Err(NoPermission().into());
+        }
+
+        return_ok!(record.into())
+    }
+
+    /// tasks created by the current user, job managers see all of the tasks
+    #[oai(path = "/list", method = "get", transform = "query_middleware")]
+    pub async fn query_task(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
+        #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
+        #[oai(default)] Query(status): Query<Option<String>>,
+        #[oai(default = "super::default_page", validator(maximum(value = "10000")))]
+        Query(page): Query<u64>,
+        #[oai(
+            default = "super::default_page_size",
+            validator(maximum(value = "10000"))
+        )]
+        Query(page_size): Query<u64>,
+    ) -> api_response!(types::QueryTaskResp) {
+        let created_user = if granted.manage_job() {
+            None
+        } else {
+            Some(user_info.username.clone())
+        };
+
+        let (list, total) = state
+            .service()
+            .task
+            .query_task(
+                created_user,
+                team_id,
+                status.filter(|v| v != ""),
+                page - 1,
+                page_size,
+            )
+            .await?;
+
+        return_ok!(types::QueryTaskResp {
+            total,
+            list: list.into_iter().map(Into::into).collect(),
+        })
+    }
+}
@@ -1,14 +1,23 @@
-use poem::{session::Session, web::Data};
+use poem::{session::Session, web::Data, Endpoint, EndpointExt};
 use poem_openapi::{param::Query, payload::Json, OpenApi};
 use sea_orm::{ActiveValue::NotSet, Set};
 
 use crate::{
     api_response,
-    entity::{team, team_member},
-    local_time, logic, return_err, return_ok,
+    entity::{team, team_member, team_quota, user_group},
+    error::NoPermission,
+    local_time,
+    logic::{self, role::POLICY_ALLOW_CHANGE_ALL_JOB},
+    middleware, return_err, return_ok,
     state::AppState,
 };
 
+fn query_middleware(ep: impl Endpoint) -> impl Endpoint {
+    ep.with(middleware::PermissionMiddleware::grant(
+        &POLICY_ALLOW_CHANGE_ALL_JOB,
+    ))
+}
+
 pub struct TeamApi;
 
 mod types {
@@ -47,6 +56,8 @@ mod types {
         pub user_id: String,
         pub username: String,
         pub is_admin: bool,
+        /// the user group the member joined by, 0 if added manually
+        pub group_id: u64,
         pub created_time: String,
     }
 
@@ -87,6 +98,157 @@ mod types {
     pub struct RemoveTeamMemberResp {
         pub affected: u64,
     }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct SaveUserGroupReq {
+        pub id: Option<u64>,
+        pub name: String,
+        pub info: Option<String>,
+        /// external source the members are synced from, eg: ldap
+        pub source: Option<String>,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct SaveUserGroupResp {
+        pub id: u64,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct UserGroupRecord {
+        pub id: u64,
+        pub name: String,
+        pub info: String,
+        pub source: String,
+        pub created_user: String,
+        pub created_time: String,
+        pub updated_time: String,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct QueryUserGroupResp {
+        pub total: u64,
+        pub list: Vec<UserGroupRecord>,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct UserGroupMemberRecord {
+        pub user_id: String,
+        pub username: String,
+        pub created_time: String,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct UserGroupTeam {
+        pub team_id: u64,
+        /// ignored when saving
+        #[oai(default)]
+        pub team_name: String,
+        pub is_admin: bool,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct GetUserGroupDetailResp {
+        pub group: UserGroupRecord,
+        pub members: Vec<UserGroupMemberRecord>,
+        pub teams: Vec<UserGroupTeam>,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct DeleteUserGroupReq {
+        pub id: u64,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct DeleteUserGroupResp {
+        pub affected: u64,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct SetUserGroupMembersReq {
+        pub group_id: u64,
+        /// the members of the group are replaced by these users
+        pub user_ids: Vec<String>,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct SetUserGroupMembersResp {
+        pub added: u64,
+        pub removed: u64,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct SetUserGroupTeamsReq {
+        pub group_id: u64,
+        /// the teams the members join, replaces the previous ones
+        pub teams: Vec<UserGroupTeam>,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct SetUserGroupTeamsResp {
+        pub total: u64,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct SyncUserGroupReq {
+        /// the group is created if not exists
+        #[oai(validator(min_length = 1))]
+        pub name: String,
+        pub source: String,
+        /// the complete member list of the group in the external source
+        pub usernames: Vec<String>,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct SyncUserGroupResp {
+        pub group_id: u64,
+        pub added: u64,
+        pub removed: u64,
+        /// usernames without a user, they are ignored
+        pub unknown: Vec<String>,
+    }
+
+    #[derive(Object, Serialize, Deserialize, Default)]
+    pub struct TeamQuota {
+        pub team_id: u64,
+        /// 0 means unlimited
+        pub max_jobs: u32,
+        pub max_timers: u32,
+        pub max_daemons: u32,
+        pub max_upload_bytes: u64,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct TeamUsage {
+        pub jobs: u64,
+        pub timers: u64,
+        pub daemons: u64,
+        pub upload_bytes: u64,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct GetTeamQuotaResp {
+        pub quota: TeamQuota,
+        pub usage: TeamUsage,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct SaveTeamQuotaResp {
+        pub id: u64,
+    }
+
+    impl From<crate::entity::user_group::Model> for UserGroupRecord {
+        fn from(v: crate::entity::user_group::Model) -> Self {
+            Self {
+                id: v.id,
+                name: v.name,
+                info: v.info,
+                source: v.source,
+                created_user: v.created_user,
+                created_time: crate::local_time!(v.created_time),
+                updated_time: crate::local_time!(v.updated_time),
+            }
+        }
+    }
 }
 
 #[OpenApi(prefix_path = "/team", tag = super::Tag::Team)]
@@ -122,7 +284,7 @@ impl TeamApi {
         return_ok!(types::SaveTeamResult { affected: ret })
     }
 
-    #[oai(path = "/list", method = "get")]
+    #[oai(path = "/list", method = "get", transform = "query_middleware")]
     pub async fn query_team(
         &self,
         state: Data<&AppState>,
@@ -143,9 +305,10 @@ impl TeamApi {
         )]
         Query(page): Query<u64>,
         user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
     ) -> api_response!(types::QueryTeamResp) {
         let svc = state.service();
-        let username = if state.can_manage_job(&user_info.user_id).await? {
+        let username = if granted.manage_job() {
             username
         } else {
             Some(user_info.username.clone())
@@ -211,6 +374,7 @@ impl TeamApi {
                         user_id: v.user_id,
                         username: v.username,
                         is_admin: v.is_admin,
+                        group_id: v.group_id,
                         created_time: local_time!(v.created_time),
                     }
                 })
@@ -277,4 +441,256 @@ impl TeamApi {
             .await?;
         return_ok!(types::RemoveTeamMemberResp { affected })
     }
+
+    /// the quota of the team and its usage, the quota is unlimited if all the max values are 0
+    #[oai(path = "/quota", method = "get")]
+    pub async fn get_team_quota(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Query(team_id): Query<u64>,
+    ) -> api_response!(types::GetTeamQuotaResp) {
+        let svc = state.service();
+        if !svc
+            .team
+            .can_read_team(Some(team_id), user_info.user_id.clone())
+            .await?
+        {
+            return_err!("no permission");
+        }
+
+        let quota = svc
+            .team
+            .get_quota(team_id)
+            .await?
+            .map_or(types::TeamQuota::default(), |v| types::TeamQuota {
+                team_id: v.team_id,
+                max_jobs: v.max_jobs,
+                max_timers: v.max_timers,
+                max_daemons: v.max_daemons,
+                max_upload_bytes: v.max_upload_bytes,
+            });
+        let usage = svc.team.get_usage(team_id).await?;
+
+        return_ok!(types::GetTeamQuotaResp {
+            quota: types::TeamQuota { team_id, ..quota },
+            usage: types::TeamUsage {
+                jobs: usage.jobs,
+                timers: usage.timers,
+                daemons: usage.daemons,
+                upload_bytes: usage.upload_bytes,
+            },
+        })
+    }
+
+    #[oai(path = "/quota/save", method = "post")]
+    pub async fn save_team_quota(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::TeamQuota>,
+    ) -> api_response!(types::SaveTeamQuotaResp) {
+        if !state.can_manage_user(&user_info.user_id).await? {
+            return Err(NoPermission().into());
+        }
+
+        let id = state
+            .service()
+            .team
+            .save_quota(team_quota::ActiveModel {
+                team_id: Set(req.team_id),
+                max_jobs: Set(req.max_jobs),
+                max_timers: Set(req.max_timers),
+                max_daemons: Set(req.max_daemons),
+                max_upload_bytes: Set(req.max_upload_bytes),
+                updated_user: Set(user_info.username.clone()),
+                ..Default::default()
+            })
+            .await?;
+        return_ok!(types::SaveTeamQuotaResp { id })
+    }
+
+    #[oai(path = "/group/save", method = "post")]
+    pub async fn save_user_group(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::SaveUserGroupReq>,
+    ) -> api_response!(types::SaveUserGroupResp) {
+        if !state.can_manage_user(&user_info.user_id).await? {
+            return Err(NoPermission().into());
+        }
+
+        let id = state
+            .service()
+            .team
+            .save_user_group(user_group::ActiveModel {
+                id: req.id.map_or(NotSet, |v| Set(v)),
+                name: Set(req.name),
+                info: req.info.map_or(NotSet, |v| Set(v)),
+                source: req.source.map_or(NotSet, |v| Set(v)),
+                created_user: req.id.map_or(Set(user_info.username.clone()), |_| NotSet),
+                updated_user: Set(user_info.username.clone()),
+                ..Default::default()
+            })
+            .await?;
+        return_ok!(types::SaveUserGroupResp { id })
+    }
+
+    #[oai(path = "/group/list", method = "get")]
+    pub async fn query_user_group(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Query(name): Query<Option<String>>,
+        #[oai(
+            default = "crate::api::default_page_size",
+            validator(maximum(value = "10000"))
+        )]
+        Query(page_size): Query<u64>,
+        #[oai(
+            default = "crate::api::default_page",
+            validator(maximum(value = "10000"))
+        )]
+        Query(page): Query<u64>,
+    ) -> api_response!(types::QueryUserGroupResp) {
+        if !state.can_manage_user(&user_info.user_id).await? {
+            return Err(NoPermission().into());
+        }
+
+        let (list, total) = state
+            .service()
+            .team
+            .query_user_group(name.filter(|v| v != ""), page - 1, page_size)
+            .await?;
+        return_ok!(types::QueryUserGroupResp {
+            total,
+            list: list.into_iter().map(Into::into).collect(),
+        })
+    }
+
+    #[oai(path = "/group/detail", method = "get")]
+    pub async fn get_user_group_detail(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Query(id): Query<u64>,
+    ) -> api_response!(types::GetUserGroupDetailResp) {
+        if !state.can_manage_user(&user_info.user_id).await? {
+            return Err(NoPermission().into());
+        }
+
+        let svc = state.service();
+        let Some(group) = svc.team.get_user_group(id).await? else {
+            return_err!("user group not found");
+        };
+        let members = svc.team.get_user_group_members(id).await?;
+        let teams = svc.team.get_user_group_teams(id).await?;
+
+        return_ok!(types::GetUserGroupDetailResp {
+            group: group.into(),
+            members: members
+                .into_iter()
+                .map(|v| types::UserGroupMemberRecord {
+                    user_id: v.user_id,
+                    username: v.username,
+                    created_time: local_time!(v.created_time),
+                })
+                .collect(),
+            teams: teams
+                .into_iter()
+                .map(|v| types::UserGroupTeam {
+                    team_id: v.team_id,
+                    team_name: v.team_name,
+                    is_admin: v.is_admin,
+                })
+                .collect(),
+        })
+    }
+
+    #[oai(path = "/group/delete", method = "post")]
+    pub async fn delete_user_group(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::DeleteUserGroupReq>,
+    ) -> api_response!(types::DeleteUserGroupResp) {
+        if !state.can_manage_user(&user_info.user_id).await? {
+            return Err(NoPermission().into());
+        }
+
+        let affected = state.service().team.delete_user_group(req.id).await?;
+        return_ok!(types::DeleteUserGroupResp { affected })
+    }
+
+    #[oai(path = "/group/set-members", method = "post")]
+    pub async fn set_user_group_members(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::SetUserGroupMembersReq>,
+    ) -> api_response!(types::SetUserGroupMembersResp) {
+        if !state.can_manage_user(&user_info.user_id).await? {
+            return Err(NoPermission().into());
+        }
+
+        let (added, removed) = state
+            .service()
+            .team
+            .set_user_group_members(req.group_id, req.user_ids, &user_info.username)
+            .await?;
+        return_ok!(types::SetUserGroupMembersResp { added, removed })
+    }
+
+    #[oai(path = "/group/set-teams", method = "post")]
+    pub async fn set_user_group_teams(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::SetUserGroupTeamsReq>,
+    ) -> api_response!(types::SetUserGroupTeamsResp) {
+        if !state.can_manage_user(&user_info.user_id).await? {
+            return Err(NoPermission().into());
+        }
+
+        let total = state
+            .service()
+            .team
+            .set_user_group_teams(
+                req.group_id,
+                req.teams
+                    .into_iter()
+                    .map(|v| (v.team_id, v.is_admin))
+                    .collect(),
+                &user_info.username,
+            )
+            .await?;
+        return_ok!(types::SetUserGroupTeamsResp { total })
+    }
+
+    /// replace the members of the group with the users of an external source,
+    /// eg: called periodically by a script reading the ldap group
+    #[oai(path = "/group/sync", method = "post")]
+    pub async fn sync_user_group(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::SyncUserGroupReq>,
+    ) -> api_response!(types::SyncUserGroupResp) {
+        if !state.can_manage_user(&user_info.user_id).await? {
+            return Err(NoPermission().into());
+        }
+
+        let ret = state
+            .service()
+            .team
+            .sync_user_group(&req.name, &req.source, req.usernames, &user_info.username)
+            .await?;
+        return_ok!(types::SyncUserGroupResp {
+            group_id: ret.group_id,
+            added: ret.added,
+            removed: ret.removed,
+            unknown: ret.unknown,
+        })
+    }
 }
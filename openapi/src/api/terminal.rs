@@ -1,8 +1,8 @@
 use std::sync::Arc;
 
-use crate::logic::ssh::{ConnectParams, Session};
+use crate::logic::{credential::SCOPE_WEBSSH, ssh::Session};
 use crate::state::AppState;
-use crate::{logic, return_err_to_wsconn};
+use crate::{logic, middleware, return_err_to_wsconn};
 
 use automate::Logic;
 use futures::{SinkExt, StreamExt};
@@ -12,7 +12,7 @@ use poem::web::websocket::WebSocket;
 use poem::web::{Data, Path, Query};
 use poem::{handler, FromRequest, IntoResponse, Request};
 use tokio::sync::RwLock;
-use tokio_tungstenite::connect_async;
+use tokio_tungstenite::connect_async_tls_with_config;
 
 use tracing::{debug, error};
 
@@ -52,24 +52,19 @@ pub async fn webssh(
     state: Data<&AppState>,
     _session: &WebSession,
     user_info: Data<&logic::types::UserInfo>,
+    granted: Data<&middleware::Granted>,
     Query(types::WebSshQuery { rows, cols }): Query<types::WebSshQuery>,
     ws: WebSocket,
 ) -> impl IntoResponse {
     let state_clone = state.clone();
     let user_id = user_info.user_id.clone();
+    let can_manage_instance = granted.manage_instance();
 
     ws.on_upgrade(move |socket| async move {
         let (mut sink, mut stream) = socket.split();
 
         let svc = state_clone.service();
 
-        let can_manage_instance = match state_clone.can_manage_instance(&user_id).await {
-            Ok(v) => v,
-            Err(e) => {
-                return_err_to_wsconn!(sink, format!("Notice: failed to valid permissions, {e}"));
-            }
-        };
-
         let instance_record = if can_manage_instance {
             svc.instance
                 .get_one_admin_server(None, None, Some(instance_id))
@@ -90,23 +85,22 @@ pub async fn webssh(
             }
         };
 
-        let password = match state_clone.decrypt(instance_record.password.unwrap_or_default()) {
+        let hops = match svc
+            .credential
+            .resolve_hops(
+                &instance_record,
+                instance_record.ssh_port.unwrap_or(22),
+                SCOPE_WEBSSH,
+            )
+            .await
+        {
             Ok(v) => v,
             Err(e) => {
-                return_err_to_wsconn!(
-                    sink,
-                    format!("Notice: failed decrypt instance password, {e}")
-                );
+                return_err_to_wsconn!(sink, format!("Notice: failed get instance credential, {e}"));
             }
         };
 
-        let mut ssh = match Session::connect(ConnectParams {
-            user: instance_record.sys_user.unwrap_or_default(),
-            password,
-            addrs: (instance_record.ip, instance_record.ssh_port.unwrap_or(22)),
-        })
-        .await
-        {
+        let mut ssh = match Session::connect_chain(hops).await {
             Ok(v) => v,
             Err(e) => {
                 return_err_to_wsconn!(
@@ -138,10 +132,12 @@ pub async fn proxy_webssh(
     state: Data<&AppState>,
     Path(instance_id): Path<String>,
     user_info: Data<&logic::types::UserInfo>,
+    granted: Data<&middleware::Granted>,
     Query(types::WebSshQuery { rows, cols }): Query<types::WebSshQuery>,
 ) -> impl IntoResponse {
     let state_clone = state.clone();
     let user_id = user_info.user_id.clone();
+    let can_manage_instance = granted.manage_instance();
 
     let ws = WebSocket::from_request_without_body(req)
         .await
@@ -155,16 +151,6 @@ pub async fn proxy_webssh(
 
         let svc = state_clone.service();
 
-        let can_manage_instance = match state_clone.can_manage_instance(&user_id).await {
-            Ok(v) => v,
-            Err(e) => {
-                return_err_to_wsconn!(
-                    clientsink,
-                    format!("Notice: failed to valid permissions, {e}")
-                );
-            }
-        };
-
         let instance_record = if can_manage_instance {
             svc.instance
                 .get_one_admin_server(None, None, Some(instance_id))
@@ -198,43 +184,40 @@ pub async fn proxy_webssh(
             }
         };
 
-        let Some(password_raw) = instance_record.password else {
-            return_err_to_wsconn!(
-                clientsink,
-                format!("Notice: please set the instance password first")
-            );
-        };
-
-        let password = match state_clone.decrypt(password_raw) {
+        let credential = match svc.credential.resolve(&instance_record, SCOPE_WEBSSH).await {
             Ok(v) => v,
             Err(e) => {
                 return_err_to_wsconn!(
                     clientsink,
-                    format!("Notice: failed decrypt instance password, {e}")
+                    format!("Notice: failed get instance credential, {e}")
                 );
             }
         };
 
-        let Some(user) = instance_record.sys_user  else {
-            return_err_to_wsconn!(clientsink, "Notice: please set the system user first");
-        };
-
-        let Some(port) =  instance_record.ssh_port else {
+        let Some(port) = instance_record.ssh_port else {
             return_err_to_wsconn!(clientsink, "Notice: please set the ssh port first");
         };
 
-        let uri = format!(
-            "ws://{}/ssh/tunnel?cols={}&rows={}&user={}&password={}&ip={}&port={}&namespace={}&mac_addr={}",
-            pair.1.comet_addr,
-            cols,
-            rows,
-            user,
-            password,
-            instance_record.ip,
-            port,
-            instance_record.namespace,
-            instance_record.mac_addr,
-        );
+        let mut query = vec![
+            ("cols", cols.to_string()),
+            ("rows", rows.to_string()),
+            ("user", credential.sys_user.clone()),
+            ("password", credential.password()),
+            ("ip", instance_record.ip.clone()),
+            ("port", port.to_string()),
+            ("namespace", instance_record.namespace.clone()),
+            ("mac_addr", instance_record.mac_addr.clone()),
+        ];
+        if let Some(v) = credential.private_key() {
+            query.push(("private_key", v.key));
+            query.extend(v.passphrase.map(|v| ("key_passphrase", v)));
+        }
+        let uri = match url::Url::parse_with_params(&pair.1.ws_url("/ssh/tunnel"), &query) {
+            Ok(v) => v.to_string(),
+            Err(e) => {
+                return_err_to_wsconn!(clientsink, format!("Notice: invalid tunnel url, {e}"));
+            }
+        };
 
         let mut ws_request = http::Request::builder()
             .header(
@@ -248,7 +231,14 @@ pub async fn proxy_webssh(
         }
 
         // Start connection to server
-        let (serversocket, _) = match connect_async(ws_request.body(()).unwrap()).await {
+        let (serversocket, _) = match connect_async_tls_with_config(
+            ws_request.body(()).unwrap(),
+            None,
+            false,
+            automate::get_ws_connector(),
+        )
+        .await
+        {
             Ok(v) => v,
             Err(e) => {
                 return_err_to_wsconn!(
@@ -0,0 +1,60 @@
+use poem::{
+    handler,
+    http::HeaderMap,
+    web::{Data, Path, RealIp},
+    Body, Result,
+};
+use service::logic::job::TriggerAuth;
+
+use super::types;
+use crate::{response::ApiStdResponse, return_err, return_ok, AppState};
+
+fn header_value(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_string())
+}
+
+/// credentials of the invocation, a signature of the body is preferred over the secret itself
+fn trigger_auth(headers: &HeaderMap) -> TriggerAuth {
+    TriggerAuth {
+        signature: header_value(headers, "X-Jiascheduler-Signature"),
+        secret: header_value(headers, "X-Gitlab-Token").or_else(|| {
+            header_value(headers, "Authorization")
+                .and_then(|v| v.strip_prefix("Bearer ").map(|v| v.trim().to_string()))
+        }),
+    }
+}
+
+/// inbound trigger of the external systems, eg: CI pipelines or alert managers,
+/// it is authenticated by the secret of the trigger instead of the user session
+#[handler]
+pub async fn trigger(
+    Path(token): Path<String>,
+    RealIp(ip): RealIp,
+    headers: &HeaderMap,
+    state: Data<&AppState>,
+    body: Body,
+) -> Result<ApiStdResponse<types::TriggerJobResp>> {
+    let body = body.into_bytes().await?;
+    let remote_ip = ip.map(|v| v.to_string()).unwrap_or_default();
+
+    let schedule_id = match state
+        .service()
+        .job
+        .fire_job_trigger(
+            state.conf.comet_secret.clone(),
+            &token,
+            &trigger_auth(headers),
+            &body,
+            &remote_ip,
+        )
+        .await
+    {
+        Ok(v) => v,
+        Err(e) => return_err!(e.to_string()),
+    };
+
+    return_ok!(types::TriggerJobResp { schedule_id })
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{borrow::Cow, collections::HashMap};
 
 use automate::scheduler::types;
 use poem_openapi::{Enum, Object};
@@ -12,6 +12,10 @@ use utils::is_valid_json;
 #[derive(Object, Serialize, Default)]
 pub struct SaveJobResp {
     pub result: u64,
+    /// version of the saved job, send it with the next update
+    pub version: u32,
+    /// the lines matching the code policies, including the waived rejections
+    pub code_policy_warnings: Vec<CodePolicyViolation>,
 }
 
 #[derive(Object, Serialize, Default)]
@@ -30,12 +34,389 @@ pub struct SaveJobReq {
     pub code: Option<String>,
     pub info: Option<String>,
     pub bundle_script: Option<Vec<BundleScript>>,
+    /// how the bundle scripts are run, sequentially without stopping if none
+    pub bundle_option: Option<BundleOption>,
+    /// health check, restart backoff and flapping detection when the job is supervised
+    pub supervisor_option: Option<SupervisorOption>,
+    /// what happens when the job runs longer than timeout, killed at once if empty
+    pub timeout_action: Option<TimeoutAction>,
+    /// stdout and stderr kept in the exec history, unlimited if empty
+    pub output_limit: Option<OutputLimit>,
     pub upload_file: Option<String>,
     #[oai(default)]
     pub is_public: Option<bool>,
     pub display_on_dashboard: Option<bool>,
+    /// only one of the instances runs each timer tick, the others skip it
+    pub is_singleton: Option<bool>,
+    /// empty for all the selected instances, sticky for the one that ran the job last,
+    /// which fails over to the next selected instance when it is offline
+    pub placement: Option<String>,
+    /// the dispatches with the same args and targets within the seconds are coalesced
+    /// into the first one, 0 for disabled
+    pub dedup_window_secs: Option<u32>,
+    /// what happens to the dispatch to an offline instance, fail if empty
+    pub offline_policy: Option<OfflinePolicy>,
+    /// the exec schedule succeeds once enough targets succeed, all of them if empty
+    pub quorum: Option<QuorumOption>,
+    /// the manual dispatch must be confirmed with the prompted values, dispatched at once if empty
+    pub confirmation: Option<JobConfirmation>,
     pub args: Vec<JobFormalArg>,
     pub completed_callback: Option<CompletedCallbackOpts>,
+    /// turns the job into a http job which requests the url instead of running code
+    pub http_check: Option<HttpCheck>,
+    /// extract named metrics from stdout into the exec history
+    #[oai(default)]
+    pub output_parsers: Vec<OutputParser>,
+    /// exit codes that are not plain failures, eg: {"1": "success", "2": "warning"}
+    #[oai(default)]
+    pub exit_code_mapping: HashMap<i32, ExecResultStatus>,
+    /// version of the job being edited, required for update
+    pub version: Option<u32>,
+    /// save the job although the code is rejected by these code policies
+    #[oai(default)]
+    pub code_policy_waivers: Vec<CodePolicyWaiver>,
+}
+
+#[derive(Object, Serialize, Deserialize, Default)]
+pub struct HttpCheck {
+    #[oai(validator(min_length = 1))]
+    pub url: String,
+    #[oai(default = "default_http_method")]
+    pub method: String,
+    #[oai(default)]
+    pub headers: HashMap<String, String>,
+    pub body: Option<String>,
+    /// any 2xx is accepted if empty
+    #[oai(default)]
+    pub expected_status: Vec<u16>,
+    /// max latency in milliseconds
+    pub max_latency: Option<u64>,
+    #[oai(default)]
+    pub assertions: Vec<HttpAssertion>,
+}
+
+fn default_http_method() -> String {
+    "GET".to_string()
+}
+
+#[derive(Object, Serialize, Deserialize, Default)]
+pub struct HttpAssertion {
+    #[oai(validator(
+        custom = "crate::api::OneOfValidator::new(vec![\"body_contains\", \"body_not_contains\", \"header_equals\", \"json_equals\"])"
+    ))]
+    pub kind: String,
+    /// header name for header_equals, json pointer for json_equals, eg: /data/status
+    #[oai(default)]
+    pub target: String,
+    pub value: String,
+}
+
+impl From<types::HttpCheck> for HttpCheck {
+    fn from(value: types::HttpCheck) -> Self {
+        Self {
+            url: value.url,
+            method: value.method,
+            headers: value.headers,
+            body: value.body,
+            expected_status: value.expected_status,
+            max_latency: value.max_latency,
+            assertions: value
+                .assertions
+                .into_iter()
+                .map(|v| HttpAssertion {
+                    kind: v.kind,
+                    target: v.target,
+                    value: v.value,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Into<types::HttpCheck> for HttpCheck {
+    fn into(self) -> types::HttpCheck {
+        types::HttpCheck {
+            url: self.url,
+            method: self.method,
+            headers: self.headers,
+            body: self.body,
+            expected_status: self.expected_status,
+            max_latency: self.max_latency,
+            assertions: self
+                .assertions
+                .into_iter()
+                .map(|v| types::HttpAssertion {
+                    kind: v.kind,
+                    target: v.target,
+                    value: v.value,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Enum, Clone, Copy)]
+#[oai(rename_all = "snake_case")]
+pub enum ExecResultStatus {
+    Success,
+    Warning,
+    #[default]
+    Failure,
+}
+
+impl From<logic::job::types::ExecResultStatus> for ExecResultStatus {
+    fn from(v: logic::job::types::ExecResultStatus) -> Self {
+        match v {
+            logic::job::types::ExecResultStatus::Success => Self::Success,
+            logic::job::types::ExecResultStatus::Warning => Self::Warning,
+            logic::job::types::ExecResultStatus::Failure => Self::Failure,
+        }
+    }
+}
+
+impl From<ExecResultStatus> for logic::job::types::ExecResultStatus {
+    fn from(v: ExecResultStatus) -> Self {
+        match v {
+            ExecResultStatus::Success => Self::Success,
+            ExecResultStatus::Warning => Self::Warning,
+            ExecResultStatus::Failure => Self::Failure,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Enum, Clone, Copy)]
+#[oai(rename_all = "snake_case")]
+pub enum OutputParserKind {
+    /// the `value` group, the first group or the whole match of the last match
+    #[default]
+    Regex,
+    /// path like `$.data.items[0].count` into the json printed to stdout
+    JsonPath,
+}
+
+#[derive(Object, Serialize, Deserialize, Default)]
+pub struct OutputParser {
+    #[oai(validator(min_length = 1, max_length = 50))]
+    pub name: String,
+    pub kind: OutputParserKind,
+    #[oai(validator(min_length = 1))]
+    pub expr: String,
+}
+
+impl From<logic::job::types::OutputParser> for OutputParser {
+    fn from(value: logic::job::types::OutputParser) -> Self {
+        Self {
+            name: value.name,
+            kind: match value.kind {
+                logic::job::types::OutputParserKind::Regex => OutputParserKind::Regex,
+                logic::job::types::OutputParserKind::JsonPath => OutputParserKind::JsonPath,
+            },
+            expr: value.expr,
+        }
+    }
+}
+
+impl Into<logic::job::types::OutputParser> for OutputParser {
+    fn into(self) -> logic::job::types::OutputParser {
+        logic::job::types::OutputParser {
+            name: self.name,
+            kind: match self.kind {
+                OutputParserKind::Regex => logic::job::types::OutputParserKind::Regex,
+                OutputParserKind::JsonPath => logic::job::types::OutputParserKind::JsonPath,
+            },
+            expr: self.expr,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Enum, Clone, Copy)]
+#[oai(rename_all = "snake_case")]
+pub enum BundleMode {
+    /// one by one in the declared order
+    #[default]
+    Sequential,
+    Parallel,
+}
+
+#[derive(Object, Serialize, Deserialize, Default)]
+pub struct BundleOption {
+    #[oai(default)]
+    pub mode: BundleMode,
+    /// max scripts running at the same time in parallel mode, unlimited if empty
+    #[oai(validator(minimum(value = "1")))]
+    pub max_parallel: Option<usize>,
+    /// keep running the rest of the scripts after one exits with non-zero code
+    #[oai(default)]
+    pub continue_on_error: bool,
+}
+
+impl From<types::BundleOption> for BundleOption {
+    fn from(value: types::BundleOption) -> Self {
+        Self {
+            mode: match value.mode {
+                types::BundleMode::Sequential => BundleMode::Sequential,
+                types::BundleMode::Parallel => BundleMode::Parallel,
+            },
+            max_parallel: value.max_parallel,
+            continue_on_error: value.continue_on_error,
+        }
+    }
+}
+
+impl Into<types::BundleOption> for BundleOption {
+    fn into(self) -> types::BundleOption {
+        types::BundleOption {
+            mode: match self.mode {
+                BundleMode::Sequential => types::BundleMode::Sequential,
+                BundleMode::Parallel => types::BundleMode::Parallel,
+            },
+            max_parallel: self.max_parallel,
+            continue_on_error: self.continue_on_error,
+        }
+    }
+}
+
+#[derive(Object, Serialize, Deserialize, Default)]
+pub struct HealthCheck {
+    #[oai(validator(
+        custom = "crate::api::OneOfValidator::new(vec![\"tcp\", \"http\", \"script\"])"
+    ))]
+    pub kind: String,
+    /// address for tcp, eg: 127.0.0.1:8080, url for http, shell code for script
+    #[oai(validator(min_length = 1))]
+    pub target: String,
+    /// seconds between two probes
+    #[oai(default = "default_health_check_interval")]
+    pub interval: u64,
+    /// seconds a probe may take
+    #[oai(default = "default_health_check_timeout")]
+    pub timeout: u64,
+    /// consecutive failed probes before the job is restarted
+    #[oai(default = "default_health_check_failure_threshold")]
+    pub failure_threshold: u32,
+}
+
+fn default_health_check_interval() -> u64 {
+    30
+}
+
+fn default_health_check_timeout() -> u64 {
+    5
+}
+
+fn default_health_check_failure_threshold() -> u32 {
+    3
+}
+
+#[derive(Object, Serialize, Deserialize, Default)]
+pub struct TimeoutAction {
+    /// seconds the job is given to exit after SIGTERM before it is killed, killed at once if 0
+    #[oai(default, validator(maximum(value = "3600")))]
+    pub grace_period: u64,
+    /// run by sh -c, or powershell -Command on windows, after the job is stopped
+    pub cleanup_script: Option<String>,
+    /// send the completed callback for the timed out run
+    #[oai(default)]
+    pub notify: bool,
+}
+
+impl From<types::TimeoutAction> for TimeoutAction {
+    fn from(value: types::TimeoutAction) -> Self {
+        Self {
+            grace_period: value.grace_period,
+            cleanup_script: value.cleanup_script,
+            notify: value.notify,
+        }
+    }
+}
+
+impl From<TimeoutAction> for types::TimeoutAction {
+    fn from(value: TimeoutAction) -> Self {
+        Self {
+            grace_period: value.grace_period,
+            cleanup_script: value.cleanup_script.filter(|v| !v.trim().is_empty()),
+            notify: value.notify,
+        }
+    }
+}
+
+#[derive(Object, Serialize, Deserialize, Default)]
+pub struct OutputLimit {
+    /// max bytes of stdout, and of stderr, kept by the agent
+    #[oai(validator(minimum(value = "1024")))]
+    pub max_bytes: u64,
+    /// bytes kept from the end of the output, the rest is kept from the start
+    #[oai(default)]
+    pub tail_bytes: u64,
+    /// the full output of a truncated run is PUT to this url, eg: a presigned url of a bucket
+    pub upload_url: Option<String>,
+}
+
+impl From<types::OutputLimit> for OutputLimit {
+    fn from(value: types::OutputLimit) -> Self {
+        Self {
+            max_bytes: value.max_bytes,
+            tail_bytes: value.tail_bytes,
+            upload_url: value.upload_url,
+        }
+    }
+}
+
+impl From<OutputLimit> for types::OutputLimit {
+    fn from(value: OutputLimit) -> Self {
+        Self {
+            max_bytes: value.max_bytes,
+            tail_bytes: value.tail_bytes.min(value.max_bytes),
+            upload_url: value.upload_url.filter(|v| !v.trim().is_empty()),
+        }
+    }
+}
+
+#[derive(Object, Serialize, Deserialize, Default)]
+pub struct SupervisorOption {
+    pub health_check: Option<HealthCheck>,
+    /// max seconds of the restart delay, which doubles from restart_interval on every restart
+    /// within the flapping window, the delay is fixed if empty
+    pub max_backoff: Option<u64>,
+    /// stop restarting after so many restarts within flapping_window minutes, never stop if empty
+    #[oai(validator(minimum(value = "1")))]
+    pub flapping_threshold: Option<u32>,
+    #[oai(default)]
+    pub flapping_window: u64,
+}
+
+impl From<types::SupervisorOption> for SupervisorOption {
+    fn from(value: types::SupervisorOption) -> Self {
+        Self {
+            health_check: value.health_check.map(|v| HealthCheck {
+                kind: v.kind,
+                target: v.target,
+                interval: v.interval,
+                timeout: v.timeout,
+                failure_threshold: v.failure_threshold,
+            }),
+            max_backoff: value.max_backoff,
+            flapping_threshold: value.flapping_threshold,
+            flapping_window: value.flapping_window,
+        }
+    }
+}
+
+impl Into<types::SupervisorOption> for SupervisorOption {
+    fn into(self) -> types::SupervisorOption {
+        types::SupervisorOption {
+            health_check: self.health_check.map(|v| types::HealthCheck {
+                kind: v.kind,
+                target: v.target,
+                interval: v.interval,
+                timeout: v.timeout,
+                failure_threshold: v.failure_threshold,
+            }),
+            max_backoff: self.max_backoff,
+            flapping_threshold: self.flapping_threshold,
+            flapping_window: self.flapping_window,
+        }
+    }
 }
 
 #[derive(Object, Serialize, Default)]
@@ -155,8 +536,19 @@ pub struct JobRecord {
     pub team_name: Option<String>,
     pub team_id: Option<u64>,
     pub bundle_script: Option<Value>,
+    pub bundle_option: Option<BundleOption>,
+    pub supervisor_option: Option<SupervisorOption>,
+    pub timeout_action: Option<TimeoutAction>,
+    pub output_limit: Option<OutputLimit>,
     pub tags: Option<Vec<JobTag>>,
     pub display_on_dashboard: bool,
+    pub is_disabled: bool,
+    pub is_singleton: bool,
+    pub placement: String,
+    pub dedup_window_secs: u32,
+    pub offline_policy: Option<OfflinePolicy>,
+    pub quorum: Option<QuorumOption>,
+    pub confirmation: Option<JobConfirmation>,
     pub work_dir: String,
     pub work_user: String,
     pub timeout: u64,
@@ -167,6 +559,10 @@ pub struct JobRecord {
     pub upload_file: String,
     pub args: Option<Value>,
     pub completed_callback: Option<CompletedCallbackOpts>,
+    pub http_check: Option<HttpCheck>,
+    pub output_parsers: Vec<OutputParser>,
+    pub exit_code_mapping: HashMap<i32, ExecResultStatus>,
+    pub version: u32,
     pub created_time: String,
     pub updated_time: String,
 }
@@ -186,6 +582,8 @@ pub struct RunRecord {
     pub team_name: Option<String>,
     pub instance_id: String,
     pub is_online: bool,
+    /// the instance is draining, the job keeps running but is not dispatched again
+    pub maintenance: bool,
     pub bind_ip: String,
     pub bind_namespace: String,
     pub schedule_type: String,
@@ -198,6 +596,8 @@ pub struct RunRecord {
     pub run_status: String,
     pub exit_status: String,
     pub exit_code: i32,
+    /// success, warning or failure of the last run, empty before the first run ends
+    pub result_status: String,
     pub dispatch_result: Option<serde_json::Value>,
     pub dispatch_data: Option<serde_json::Value>,
     pub tags: Option<Vec<JobTag>>,
@@ -218,6 +618,33 @@ pub struct QueryRunResp {
 #[derive(Object, Serialize, Deserialize, Default)]
 pub struct Endpoint {
     pub instance_id: String,
+    /// environment variables passed to the job on this instance only
+    #[oai(default)]
+    pub env: HashMap<String, String>,
+    /// merged into the args of the dispatch on this instance, eg: a shard id
+    pub args: Option<serde_json::Value>,
+    /// overrides the work dir of the job on this instance
+    pub work_dir: Option<String>,
+}
+
+/// overrides of the endpoints which set any, keyed by instance id
+pub fn endpoint_overrides(
+    endpoints: &[Endpoint],
+) -> HashMap<String, logic::job::types::InstanceOverride> {
+    endpoints
+        .iter()
+        .map(|v| {
+            (
+                v.instance_id.clone(),
+                logic::job::types::InstanceOverride {
+                    env: v.env.clone(),
+                    args: v.args.clone(),
+                    work_dir: v.work_dir.clone().filter(|v| !v.is_empty()),
+                },
+            )
+        })
+        .filter(|(_, v)| !v.is_empty())
+        .collect()
 }
 
 #[derive(Object, Serialize, Default)]
@@ -231,108 +658,505 @@ pub struct DispatchJobReq {
     pub restart_interval: Option<u64>,
     pub is_sync: bool,
     pub action: String,
+    /// required when dispatching inside a blackout window
+    pub override_reason: Option<String>,
+    /// dispatch in a background task instead of the request, poll the returned task_id
+    #[oai(default)]
+    pub background: bool,
+    /// exec on a few instances first and continue to the rest only if they succeed
+    pub canary: Option<CanaryOption>,
 }
 
-#[derive(Object, Serialize, Default)]
-pub struct DispatchJobResp {
-    pub result: u64,
+#[derive(Object, Serialize, Deserialize, Default)]
+pub struct CanaryOption {
+    /// number of the instances dispatched first
+    pub size: usize,
+    /// exit codes counted as success, [0] if empty
+    #[oai(default)]
+    pub success_exit_codes: Vec<i32>,
+    /// evaluated with the output of each canary instance, eg: contains(stdout, "ok")
+    #[oai(default)]
+    pub cond_expr: String,
+    /// ratio of the failed canary instances tolerated, 0 stops on any failure
+    #[oai(default)]
+    pub max_error_rate: f64,
 }
 
-pub type RedispatchJobResp = Vec<DispatchJobResult>;
+impl From<CanaryOption> for logic::job::CanaryOption {
+    fn from(value: CanaryOption) -> Self {
+        Self {
+            size: value.size,
+            success_exit_codes: value.success_exit_codes,
+            cond_expr: value.cond_expr,
+            max_error_rate: value.max_error_rate,
+        }
+    }
+}
 
-#[derive(Object, Serialize, Default)]
-pub struct DispatchJobResult {
-    pub namespace: String,
-    pub ip: String,
-    pub response: serde_json::Value,
-    pub has_err: bool,
-    pub call_err: Option<String>,
+#[derive(Object, Serialize, Deserialize, Default)]
+pub struct OfflinePolicy {
+    /// fail, wait until the instance is online, or substitute another online instance
+    /// of the same group
+    pub policy: String,
+    /// seconds to wait for an online instance before the dispatch fails
+    #[oai(default)]
+    pub ttl_secs: u64,
 }
 
-#[derive(Object, Serialize, Default)]
-pub struct RedispatchJobReq {
-    pub schedule_id: String,
-    pub action: String,
+impl From<logic::job::OfflinePolicy> for OfflinePolicy {
+    fn from(value: logic::job::OfflinePolicy) -> Self {
+        Self {
+            policy: value.policy,
+            ttl_secs: value.ttl_secs,
+        }
+    }
 }
 
-#[derive(Object, Serialize, Default)]
-pub struct ScheduleJobReq {
-    /// schedule primary id
-    pub schedule_pid: u64,
-    pub action: String,
+impl From<OfflinePolicy> for logic::job::OfflinePolicy {
+    fn from(value: OfflinePolicy) -> Self {
+        Self {
+            policy: value.policy,
+            ttl_secs: value.ttl_secs,
+        }
+    }
 }
 
-#[derive(Object, Serialize, Default)]
-pub struct ScheduleJobResp {
-    pub result: u64,
+#[derive(Object, Serialize, Deserialize, Default)]
+pub struct QuorumOption {
+    /// number of the targets required to succeed
+    #[oai(default)]
+    pub min_success: u32,
+    /// ratio of the targets required to succeed, the larger of the two is required
+    #[oai(default)]
+    pub min_success_rate: f64,
 }
 
-#[derive(Object, Serialize, Default)]
-pub struct DeleteJobReq {
-    pub eid: String,
+impl From<logic::job::QuorumOption> for QuorumOption {
+    fn from(value: logic::job::QuorumOption) -> Self {
+        Self {
+            min_success: value.min_success,
+            min_success_rate: value.min_success_rate,
+        }
+    }
 }
 
-#[derive(Object, Serialize, Default)]
-pub struct DeleteJobResp {
-    pub result: u64,
+impl From<QuorumOption> for logic::job::QuorumOption {
+    fn from(value: QuorumOption) -> Self {
+        Self {
+            min_success: value.min_success,
+            min_success_rate: value.min_success_rate,
+        }
+    }
 }
 
-#[derive(Object, Serialize, Default)]
-#[oai(skip_serializing_if_is_none)]
-pub struct SaveScheduleReq {
-    pub id: u64,
+#[derive(Object, Serialize, Deserialize, Default)]
+pub struct JobConfirmation {
+    /// the manual dispatch starting an execution returns a challenge to confirm
+    #[oai(default)]
+    pub required: bool,
+    /// the values asked on confirmation, passed to the job as args
+    #[oai(default)]
+    pub prompts: Vec<JobPrompt>,
+}
+
+#[derive(Object, Serialize, Deserialize, Default)]
+pub struct JobPrompt {
+    /// name of the arg the value is passed as
     pub name: String,
-    pub endpoints: Vec<Endpoint>,
-    pub eid: String,
-    pub args: Option<serde_json::Value>,
-    pub timer_expr: Option<CustomTimerExpr>,
-    pub restart_interval: Option<u64>,
+    #[oai(default)]
+    pub label: String,
+    /// string, number, bool or choice
+    pub prompt_type: String,
+    /// the allowed values of a choice
+    #[oai(default)]
+    pub options: Vec<String>,
+    #[oai(default)]
+    pub required: bool,
 }
 
-#[derive(Object, Serialize, Default)]
-pub struct SaveScheduleResp {
-    pub result: u64,
-    pub next_exec_times: Option<Vec<String>>,
+impl From<logic::job::JobConfirmation> for JobConfirmation {
+    fn from(value: logic::job::JobConfirmation) -> Self {
+        Self {
+            required: value.required,
+            prompts: value.prompts.into_iter().map(Into::into).collect(),
+        }
+    }
 }
 
-#[derive(Object, Serialize, Default)]
-pub struct ScheduleHistoryRecord {
-    pub id: u64,
-    pub schedule_id: String,
-    pub team_id: Option<u64>,
-    pub team_name: Option<String>,
-    pub name: String,
-    pub eid: String,
-    pub job_type: String,
-    pub dispatch_result: Option<Value>,
-    pub schedule_type: String,
-    pub action: String,
-    pub dispatch_data: Option<Value>,
-    pub snapshot_data: Option<Value>,
-    pub actual_args: Option<Value>,
-    pub tags: Option<Vec<JobTag>>,
-    pub created_user: String,
-    pub updated_user: String,
-    pub created_time: String,
-    pub updated_time: String,
+impl From<JobConfirmation> for logic::job::JobConfirmation {
+    fn from(value: JobConfirmation) -> Self {
+        Self {
+            required: value.required,
+            prompts: value.prompts.into_iter().map(Into::into).collect(),
+        }
+    }
 }
 
-#[derive(Object, Serialize, Default)]
-pub struct QueryScheduleResp {
-    pub total: u64,
-    pub list: Vec<ScheduleRecord>,
+impl From<logic::job::JobPrompt> for JobPrompt {
+    fn from(value: logic::job::JobPrompt) -> Self {
+        Self {
+            name: value.name,
+            label: value.label,
+            prompt_type: value.prompt_type,
+            options: value.options,
+            required: value.required,
+        }
+    }
+}
+
+impl From<JobPrompt> for logic::job::JobPrompt {
+    fn from(value: JobPrompt) -> Self {
+        Self {
+            name: value.name,
+            label: value.label,
+            prompt_type: value.prompt_type,
+            options: value.options,
+            required: value.required,
+        }
+    }
 }
 
 #[derive(Object, Serialize, Default)]
-pub struct ScheduleRecord {
-    pub id: u64,
-    pub team_id: Option<u64>,
+pub struct QuorumVerdict {
+    /// pending, passed or failed
+    pub status: String,
+    pub required: u32,
+    pub total: u32,
+    pub success: u32,
+    pub failure: u32,
+    pub reason: String,
+}
+
+impl From<logic::job::QuorumVerdict> for QuorumVerdict {
+    fn from(value: logic::job::QuorumVerdict) -> Self {
+        Self {
+            status: value.status,
+            required: value.required,
+            total: value.total,
+            success: value.success,
+            failure: value.failure,
+            reason: value.reason,
+        }
+    }
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct DispatchJobResp {
+    /// schedule id, 0 for background dispatch
+    pub result: u64,
+    pub task_id: Option<String>,
+    /// returned instead of dispatching if the job requires confirmation,
+    /// the dispatch runs once the challenge is confirmed
+    pub challenge: Option<DispatchChallenge>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct ChallengeTarget {
+    pub instance_id: String,
+    pub ip: String,
+    pub namespace: String,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct DispatchChallenge {
+    pub challenge_id: String,
+    pub eid: String,
+    pub job_name: String,
+    pub action: String,
+    /// the instances the job will run on
+    pub targets: Vec<ChallengeTarget>,
+    /// the values to fill in on confirmation
+    pub prompts: Vec<JobPrompt>,
+    /// the text to type to confirm
+    pub confirm_text: String,
+    pub expire_time: String,
+}
+
+impl From<logic::job::DispatchChallenge> for DispatchChallenge {
+    fn from(value: logic::job::DispatchChallenge) -> Self {
+        Self {
+            challenge_id: value.challenge_id,
+            eid: value.eid,
+            job_name: value.job_name,
+            action: value.action,
+            targets: value
+                .targets
+                .into_iter()
+                .map(|v| ChallengeTarget {
+                    instance_id: v.instance_id,
+                    ip: v.ip,
+                    namespace: v.namespace,
+                })
+                .collect(),
+            prompts: value.prompts.into_iter().map(Into::into).collect(),
+            confirm_text: value.confirm_text,
+            expire_time: value.expire_time,
+        }
+    }
+}
+
+#[derive(Object, Serialize, Deserialize, Default)]
+pub struct ConfirmDispatchReq {
+    pub challenge_id: String,
+    /// must equal the confirm_text of the challenge
+    pub confirm_text: String,
+    /// the values of the prompts, eg: {"path": "/data/tmp", "force": true}
+    #[oai(default)]
+    pub values: HashMap<String, Value>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct TestRunJobReq {
+    /// one of the sandbox instances, the first online one is used if empty
+    pub instance_id: Option<String>,
+    pub executor_id: u64,
+    pub code: String,
+    /// formal args of the job
+    pub args: Option<serde_json::Value>,
+    pub actual_args: Option<serde_json::Value>,
+    pub work_dir: Option<String>,
+    pub work_user: Option<String>,
+}
+
+impl From<TestRunJobReq> for logic::job::TestRunParams {
+    fn from(value: TestRunJobReq) -> Self {
+        Self {
+            instance_id: value.instance_id,
+            executor_id: value.executor_id,
+            code: value.code,
+            args: value.args,
+            actual_args: value.actual_args,
+            work_dir: value.work_dir,
+            work_user: value.work_user,
+        }
+    }
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct TestRunJobResp {
+    pub instance_id: String,
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    pub duration_ms: u64,
+}
+
+pub type RedispatchJobResp = Vec<DispatchJobResult>;
+
+#[derive(Object, Serialize, Default)]
+pub struct DispatchJobResult {
+    pub namespace: String,
+    pub ip: String,
+    pub response: serde_json::Value,
+    pub has_err: bool,
+    pub call_err: Option<String>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct RedispatchJobReq {
+    pub schedule_id: String,
+    pub action: String,
+    /// required when dispatching inside a blackout window
+    pub override_reason: Option<String>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct ExecRerunReq {
+    /// exec history id of the run to rerun
+    pub exec_history_id: u64,
+    /// rerun on the chosen instance, defaults to the instance of the original run
+    pub instance_id: Option<String>,
+    /// required when dispatching inside a blackout window
+    pub override_reason: Option<String>,
+}
+
+pub type ExecRerunResp = DispatchJobResult;
+
+#[derive(Object, Serialize, Default)]
+pub struct RunLineageExec {
+    /// exec history id
+    pub id: u64,
+    pub instance_id: String,
+    pub result_status: String,
+    pub exit_code: i32,
+    pub start_time: String,
+    pub end_time: String,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct RunLineageNode {
+    pub run_id: String,
+    pub parent_run_id: String,
+    /// retry or rerun, empty for the first run
+    pub run_kind: String,
+    /// the runs of the instances, empty if the run never reported one
+    pub execs: Vec<RunLineageExec>,
+    /// the retries and reruns of the run
+    pub children: Vec<RunLineageNode>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct GetRunLineageResp {
+    pub schedule_id: String,
+    pub schedule_name: String,
+    pub schedule_type: String,
+    pub eid: String,
+    pub action: String,
+    pub created_user: String,
+    pub created_time: String,
+    /// the schedule has more runs than the limit, the latest ones are left out
+    pub truncated: bool,
+    pub runs: Vec<RunLineageNode>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct ScheduleJobReq {
+    /// schedule primary id
+    pub schedule_pid: u64,
+    pub action: String,
+    /// required when dispatching inside a blackout window
+    pub override_reason: Option<String>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct ScheduleJobResp {
+    pub result: u64,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct BulkJobReq {
+    #[oai(validator(min_items = 1, max_items = 500))]
+    pub eids: Vec<String>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct BulkBindTagReq {
+    #[oai(validator(min_items = 1, max_items = 500))]
+    pub eids: Vec<String>,
+    #[oai(validator(min_length = 1))]
+    pub tag_name: String,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct BulkSetDisabledReq {
+    #[oai(validator(min_items = 1, max_items = 500))]
+    pub eids: Vec<String>,
+    /// the disabled jobs cannot be started, false to enable them again
+    pub disabled: bool,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct BulkDispatchReq {
+    #[oai(validator(min_items = 1, max_items = 500))]
+    pub eids: Vec<String>,
+    /// every job is executed once on these endpoints
+    pub endpoints: Vec<Endpoint>,
+    pub schedule_name: Option<String>,
+    pub args: Option<serde_json::Value>,
+    /// required when dispatching inside a blackout window
+    pub override_reason: Option<String>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct BulkItemResult {
+    pub eid: String,
+    pub ok: bool,
+    pub message: String,
+    /// id of the created record, eg: the schedule of a dispatch
+    pub id: u64,
+}
+
+impl From<logic::job::types::BulkItemResult> for BulkItemResult {
+    fn from(value: logic::job::types::BulkItemResult) -> Self {
+        Self {
+            eid: value.eid,
+            ok: value.ok,
+            message: value.message,
+            id: value.id,
+        }
+    }
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct BulkJobResp {
+    /// false if nothing is changed because an item failed, the dispatches are
+    /// not transactional and report every item
+    pub applied: bool,
+    pub results: Vec<BulkItemResult>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct DeleteJobReq {
+    pub eid: String,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct DeleteJobResp {
+    pub result: u64,
+}
+
+#[derive(Object, Serialize, Default)]
+#[oai(skip_serializing_if_is_none)]
+pub struct SaveScheduleReq {
+    pub id: u64,
+    pub name: String,
+    pub endpoints: Vec<Endpoint>,
+    pub eid: String,
+    pub args: Option<serde_json::Value>,
+    pub timer_expr: Option<CustomTimerExpr>,
+    pub restart_interval: Option<u64>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct SaveScheduleResp {
+    pub result: u64,
+    pub next_exec_times: Option<Vec<String>>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct ScheduleHistoryRecord {
+    pub id: u64,
+    pub schedule_id: String,
+    pub team_id: Option<u64>,
+    pub team_name: Option<String>,
+    pub name: String,
+    pub eid: String,
+    pub job_type: String,
+    pub dispatch_result: Option<Value>,
+    pub schedule_type: String,
+    pub action: String,
+    pub dispatch_data: Option<Value>,
+    pub snapshot_data: Option<Value>,
+    pub actual_args: Option<Value>,
+    /// the instance chosen by the placement of the job
+    pub placement: Option<Value>,
+    /// the verdict of the quorum of the job, none without quorum
+    pub quorum: Option<QuorumVerdict>,
+    pub tags: Option<Vec<JobTag>>,
+    pub created_user: String,
+    pub updated_user: String,
+    pub created_time: String,
+    pub updated_time: String,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct QueryScheduleResp {
+    pub total: u64,
+    pub list: Vec<ScheduleRecord>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct ScheduleRecord {
+    pub id: u64,
+    pub team_id: Option<u64>,
     pub team_name: Option<String>,
     pub name: String,
     pub eid: String,
     pub job_type: String,
     pub action: String,
     pub instance_ids: Vec<String>,
+    /// env, args and work_dir overridden per instance id
+    pub instance_overrides: Option<Value>,
     pub dispatch_result: Option<Value>,
     pub schedule_type: String,
     pub snapshot_data: Option<Value>,
@@ -351,6 +1175,24 @@ pub struct QueryScheduleHistoryResp {
     pub list: Vec<ScheduleHistoryRecord>,
 }
 
+pub const OUTPUT_MODE_PLAIN: &str = "plain";
+pub const OUTPUT_MODE_COLOR: &str = "color";
+pub const OUTPUT_MODE_RAW: &str = "raw";
+
+/// plain removes the escape sequences and the control chars, color keeps the color
+/// sequences for the viewers rendering them, raw returns the output as the agent sent it
+pub fn sanitize_output(output: String, output_mode: Option<&str>) -> String {
+    let keep_color = match output_mode.unwrap_or(OUTPUT_MODE_PLAIN) {
+        OUTPUT_MODE_RAW => return output,
+        OUTPUT_MODE_COLOR => true,
+        _ => false,
+    };
+    match utils::ansi::sanitize(&output, keep_color) {
+        Cow::Borrowed(_) => output,
+        Cow::Owned(v) => v,
+    }
+}
+
 #[derive(Object, Serialize, Default)]
 pub struct ExecRecord {
     pub id: u64,
@@ -363,8 +1205,12 @@ pub struct ExecRecord {
     pub team_id: Option<u64>,
     pub team_name: Option<String>,
     pub bundle_script_result: Option<serde_json::Value>,
+    /// metrics extracted by the output parsers of the job
+    pub parsed_result: Option<serde_json::Value>,
     pub exit_status: String,
     pub exit_code: i64,
+    /// success, warning or failure decided by the exit code mapping of the job
+    pub result_status: String,
     pub start_time: Option<String>,
     pub end_time: Option<String>,
     pub tags: Option<Vec<JobTag>>,
@@ -373,6 +1219,22 @@ pub struct ExecRecord {
     pub created_time: String,
     pub updated_time: String,
     pub schedule_name: String,
+    /// exec history id this run is rerun from, 0 means not a rerun
+    pub rerun_of: u64,
+    pub run_id: String,
+    /// the run retried or rerun by this one, empty for the first run
+    pub parent_run_id: String,
+    /// retry or rerun, empty for the first run
+    pub run_kind: String,
+    /// agent version, executor versions, os and environment hash the run was started with,
+    /// none for the runs of the agents not reporting it
+    pub exec_context: Option<serde_json::Value>,
+    /// the timeout actions fired in order, eg: sigterm,cleanup,notify, empty if not timed out
+    pub timeout_action: String,
+    /// stdout or stderr was cut to the output limit of the job
+    pub output_truncated: bool,
+    /// where the full output of the truncated run was uploaded, empty if not uploaded
+    pub output_url: String,
 }
 
 #[derive(Object, Serialize, Default)]
@@ -441,11 +1303,14 @@ pub struct SaveJobBundleScriptReq {
     pub code: String,
     pub info: String,
     pub args: Option<HashMap<String, String>>,
+    /// version of the bundle script being edited, required for update
+    pub version: Option<u32>,
 }
 
 #[derive(Object, Serialize, Default)]
 pub struct SaveJobBundleScriptResp {
     pub result: u64,
+    pub version: u32,
 }
 
 #[derive(Object, Serialize, Default)]
@@ -467,6 +1332,7 @@ pub struct JobBundleScriptRecord {
     pub created_user: String,
     pub updated_user: String,
     pub args: Option<Value>,
+    pub version: u32,
     pub created_time: String,
     pub updated_time: String,
 }
@@ -495,6 +1361,7 @@ pub struct JobTimerRecord {
     pub tags: Option<Vec<JobTag>>,
     pub created_user: String,
     pub updated_user: String,
+    pub version: u32,
     pub created_time: String,
     pub updated_time: String,
 }
@@ -516,6 +1383,8 @@ pub struct SaveJobTimerReq {
     pub timer_expr: TimerExpr,
     pub job_args: Vec<JobFormalArg>,
     pub info: String,
+    /// version of the timer being edited, required for update
+    pub version: Option<u32>,
 }
 
 fn default_time_zone() -> String {
@@ -527,6 +1396,10 @@ pub struct CustomTimerExpr {
     #[oai(default = "default_time_zone")]
     pub timezone: String,
     pub expr: String,
+    /// run every interval seconds instead of the cron expr
+    pub interval: Option<u64>,
+    /// percentage of the interval each run is randomly delayed by, 0 to 100
+    pub jitter: Option<u8>,
 }
 
 impl Into<logic::types::CustomTimerExpr> for CustomTimerExpr {
@@ -534,6 +1407,8 @@ impl Into<logic::types::CustomTimerExpr> for CustomTimerExpr {
         logic::types::CustomTimerExpr {
             timezone: self.timezone,
             expr: self.expr,
+            interval: self.interval,
+            jitter: self.jitter,
         }
     }
 }
@@ -548,6 +1423,10 @@ pub struct TimerExpr {
     pub day_of_month: String,
     pub month: String,
     pub year: String,
+    /// run every interval seconds instead of the cron expr
+    pub interval: Option<u64>,
+    /// percentage of the interval each run is randomly delayed by, 0 to 100
+    pub jitter: Option<u8>,
 }
 
 impl From<String> for TimerExpr {
@@ -563,6 +1442,8 @@ impl From<String> for TimerExpr {
                 day_of_month: vec.get(3).map_or("1".to_string(), |&v| v.to_string()),
                 month: vec.get(4).map_or("1".to_string(), |&v| v.to_string()),
                 year: vec.get(5).map_or("1".to_string(), |&v| v.to_string()),
+                interval: v.interval,
+                jitter: v.jitter,
             }
         } else {
             let vec: Vec<&str> = value.split(" ").collect();
@@ -574,6 +1455,8 @@ impl From<String> for TimerExpr {
                 day_of_month: vec.get(3).map_or("1".to_string(), |&v| v.to_string()),
                 month: vec.get(4).map_or("1".to_string(), |&v| v.to_string()),
                 year: vec.get(5).map_or("1".to_string(), |&v| v.to_string()),
+                interval: None,
+                jitter: None,
             }
         }
     }
@@ -590,6 +1473,8 @@ impl From<logic::types::CustomTimerExpr> for TimerExpr {
             day_of_month: vec.get(3).map_or("1".to_string(), |&v| v.to_string()),
             month: vec.get(4).map_or("1".to_string(), |&v| v.to_string()),
             year: vec.get(5).map_or("1".to_string(), |&v| v.to_string()),
+            interval: value.interval,
+            jitter: value.jitter,
         }
     }
 }
@@ -602,6 +1487,8 @@ impl Into<String> for TimerExpr {
                 "{} {} {} {} {} {}",
                 self.second, self.minute, self.hour, self.day_of_month, self.month, self.year
             ),
+            "interval":self.interval,
+            "jitter":self.jitter,
         });
 
         v.to_string()
@@ -616,6 +1503,8 @@ impl Into<logic::types::CustomTimerExpr> for TimerExpr {
                 "{} {} {} {} {} {}",
                 self.second, self.minute, self.hour, self.day_of_month, self.month, self.year
             ),
+            interval: self.interval,
+            jitter: self.jitter,
         }
     }
 }
@@ -623,9 +1512,40 @@ impl Into<logic::types::CustomTimerExpr> for TimerExpr {
 #[derive(Object, Serialize, Default)]
 pub struct SaveJobTimerResp {
     pub result: u64,
+    pub version: u32,
     pub next_exec_times: Vec<String>,
 }
 
+#[derive(Object, Serialize, Deserialize, Default)]
+pub struct SimulateTimerReq {
+    pub timer_expr: TimerExpr,
+    /// hours simulated from now
+    #[oai(
+        default = "default_simulate_hours",
+        validator(minimum(value = "1"), maximum(value = "168"))
+    )]
+    pub hours: u64,
+}
+
+fn default_simulate_hours() -> u64 {
+    24
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct SimulateTimerResp {
+    pub total: u64,
+    /// more ticks than the limit fall into the hours, only the first ones are listed
+    pub truncated: bool,
+    pub list: Vec<SimulatedTick>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct SimulatedTick {
+    pub time: String,
+    /// the tick is skipped inside the blackout window
+    pub blackout_window: Option<String>,
+}
+
 #[derive(Object, Serialize, Default)]
 pub struct GetDashboardReq {
     // pub eid: String,
@@ -645,61 +1565,200 @@ pub struct GetDashboardResp {
     pub exec_succ_num: u64,
     pub exec_fail_num: u64,
     pub rows: Vec<JobRunResultStats>,
+    /// runs of the last day much slower than the median of the job on the same instance
+    pub anomalies: Vec<DurationAnomalyRecord>,
 }
 
 #[derive(Object, Serialize, Default)]
-pub struct JobRunResultStats {
-    pub name: String,
+pub struct JobRunTopRecord {
     pub eid: String,
-    pub schedule_name: String,
-    pub results: Vec<JobRunSummary>,
+    pub job_name: Option<String>,
+    pub run_total: i64,
+    pub fail_total: i64,
+    pub avg_duration_ms: i64,
+    pub max_duration_ms: i64,
+}
+
+impl From<logic::job::types::JobRunTop> for JobRunTopRecord {
+    fn from(v: logic::job::types::JobRunTop) -> Self {
+        Self {
+            eid: v.eid,
+            job_name: v.job_name,
+            run_total: v.run_total,
+            fail_total: v.fail_total,
+            avg_duration_ms: v.avg_duration_ms,
+            max_duration_ms: v.max_duration_ms,
+        }
+    }
 }
 
 #[derive(Object, Serialize, Default)]
-pub struct JobRunSummary {
-    pub eid: String,
-    pub total: i64,
-    pub name: String,
-    pub info: String,
-    pub last_start_time: String,
-    pub exec_succ_num: i64,
-    pub exec_fail_num: i64,
-    pub check_succ_num: i64,
-    pub check_fail_num: i64,
-    pub eval_fail_num: i64,
+pub struct GetJobRunTopResp {
+    pub list: Vec<JobRunTopRecord>,
 }
 
 #[derive(Object, Serialize, Default)]
-pub struct JobSupervisorRecord {
-    pub id: u64,
-    pub name: String,
-    pub job_name: String,
-    pub eid: String,
-    pub executor_id: u64,
-    pub executor_name: String,
-    pub executor_platform: String,
-    pub team_id: Option<u64>,
-    pub team_name: Option<String>,
-    pub job_args: Option<Value>,
-    pub restart_interval: u64,
-    pub info: String,
-    pub tags: Option<Vec<JobTag>>,
-    pub created_user: String,
-    pub updated_user: String,
-    pub created_time: String,
-    pub updated_time: String,
+pub struct InstanceRunTopRecord {
+    pub instance_id: String,
+    pub ip: Option<String>,
+    pub run_total: i64,
+    pub fail_total: i64,
 }
 
 #[derive(Object, Serialize, Default)]
-pub struct QueryJobSupervisorResp {
-    pub total: u64,
-    pub list: Vec<JobSupervisorRecord>,
+pub struct GetInstanceRunTopResp {
+    pub list: Vec<InstanceRunTopRecord>,
 }
 
 #[derive(Object, Serialize, Default)]
-#[oai(skip_serializing_if_is_none)]
-pub struct SaveJobSupervisorReq {
-    pub id: Option<u64>,
+pub struct DailyRunTrendRecord {
+    /// eg: 2025-09-10
+    pub stat_date: String,
+    pub run_total: i64,
+    pub fail_total: i64,
+    pub avg_duration_ms: i64,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct GetDailyRunTrendResp {
+    pub list: Vec<DailyRunTrendRecord>,
+}
+
+#[derive(Serialize, Default, Enum, Clone, Copy)]
+#[oai(rename_all = "snake_case")]
+pub enum UsageGroupBy {
+    #[default]
+    Team,
+    Job,
+    Instance,
+}
+
+impl From<UsageGroupBy> for logic::job::types::UsageGroupBy {
+    fn from(value: UsageGroupBy) -> Self {
+        match value {
+            UsageGroupBy::Team => Self::Team,
+            UsageGroupBy::Job => Self::Job,
+            UsageGroupBy::Instance => Self::Instance,
+        }
+    }
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct UsageRecord {
+    pub team_id: u64,
+    pub team_name: String,
+    /// empty unless grouped by job
+    pub eid: String,
+    pub job_name: String,
+    /// empty unless grouped by instance
+    pub instance_id: String,
+    pub instance_ip: String,
+    pub run_total: u64,
+    pub runtime_secs: f64,
+}
+
+impl From<logic::job::types::UsageRecord> for UsageRecord {
+    fn from(value: logic::job::types::UsageRecord) -> Self {
+        Self {
+            team_id: value.team_id,
+            team_name: value.team_name,
+            eid: value.eid,
+            job_name: value.job_name,
+            instance_id: value.instance_id,
+            instance_ip: value.instance_ip,
+            run_total: value.run_total,
+            runtime_secs: value.runtime_secs,
+        }
+    }
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct GetUsageReportResp {
+    /// eg: 2025-11
+    pub month: String,
+    pub list: Vec<UsageRecord>,
+}
+
+pub fn default_dashboard_days() -> i64 {
+    7
+}
+
+pub fn default_trend_days() -> i64 {
+    30
+}
+
+pub fn default_top_limit() -> u64 {
+    10
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct DurationAnomalyRecord {
+    pub id: u64,
+    pub eid: String,
+    pub job_name: Option<String>,
+    pub instance_id: String,
+    pub ip: Option<String>,
+    pub schedule_id: String,
+    pub run_id: String,
+    pub exec_history_id: u64,
+    pub duration_ms: u64,
+    pub median_ms: u64,
+    pub created_time: String,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct JobRunResultStats {
+    pub name: String,
+    pub eid: String,
+    pub schedule_name: String,
+    pub results: Vec<JobRunSummary>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct JobRunSummary {
+    pub eid: String,
+    pub total: i64,
+    pub name: String,
+    pub info: String,
+    pub last_start_time: String,
+    pub exec_succ_num: i64,
+    pub exec_fail_num: i64,
+    pub check_succ_num: i64,
+    pub check_fail_num: i64,
+    pub eval_fail_num: i64,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct JobSupervisorRecord {
+    pub id: u64,
+    pub name: String,
+    pub job_name: String,
+    pub eid: String,
+    pub executor_id: u64,
+    pub executor_name: String,
+    pub executor_platform: String,
+    pub team_id: Option<u64>,
+    pub team_name: Option<String>,
+    pub job_args: Option<Value>,
+    pub restart_interval: u64,
+    pub info: String,
+    pub tags: Option<Vec<JobTag>>,
+    pub created_user: String,
+    pub updated_user: String,
+    pub created_time: String,
+    pub updated_time: String,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct QueryJobSupervisorResp {
+    pub total: u64,
+    pub list: Vec<JobSupervisorRecord>,
+}
+
+#[derive(Object, Serialize, Default)]
+#[oai(skip_serializing_if_is_none)]
+pub struct SaveJobSupervisorReq {
+    pub id: Option<u64>,
     pub eid: String,
     pub restart_interval: u64,
     pub job_args: Vec<JobFormalArg>,
@@ -784,3 +1843,608 @@ pub struct DeleteJobTimerReq {
 pub struct DeleteJobTimerResp {
     pub result: u64,
 }
+
+#[derive(Serialize, Default, Enum, Clone, Copy)]
+pub enum DispatchStatWindow {
+    #[default]
+    #[oai(rename = "1h")]
+    Hour,
+    #[oai(rename = "24h")]
+    Day,
+    #[oai(rename = "7d")]
+    Week,
+    #[oai(rename = "30d")]
+    Month,
+}
+
+impl DispatchStatWindow {
+    pub fn duration(self) -> chrono::TimeDelta {
+        match self {
+            DispatchStatWindow::Hour => chrono::TimeDelta::hours(1),
+            DispatchStatWindow::Day => chrono::TimeDelta::days(1),
+            DispatchStatWindow::Week => chrono::TimeDelta::days(7),
+            DispatchStatWindow::Month => chrono::TimeDelta::days(30),
+        }
+    }
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct CometDispatchStat {
+    pub comet_addr: String,
+    pub dispatch_total: u64,
+    pub dispatch_failed: u64,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct GetDispatchStatResp {
+    pub dispatch_total: u64,
+    pub dispatch_failed: u64,
+    pub success_rate: f64,
+    pub latency_samples: u64,
+    /// p50 of the dispatch to start latency in milliseconds
+    pub latency_p50: Option<u64>,
+    /// p95 of the dispatch to start latency in milliseconds
+    pub latency_p95: Option<u64>,
+    pub comets: Vec<CometDispatchStat>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct SupervisorMetricRecord {
+    pub schedule_id: String,
+    pub instance_id: String,
+    pub run_id: String,
+    /// percentage of a single core
+    pub cpu_usage: f32,
+    /// resident memory in bytes
+    pub rss: u64,
+    pub process_count: u32,
+    pub sample_time: String,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct GetSupervisorMetricsResp {
+    pub list: Vec<SupervisorMetricRecord>,
+}
+
+#[derive(Serialize, Default, Enum, Clone, Copy)]
+#[oai(rename_all = "snake_case")]
+pub enum RecycleResourceType {
+    #[default]
+    Job,
+    Timer,
+    Supervisor,
+}
+
+impl From<RecycleResourceType> for logic::job::types::RecycleResource {
+    fn from(v: RecycleResourceType) -> Self {
+        match v {
+            RecycleResourceType::Job => Self::Job,
+            RecycleResourceType::Timer => Self::Timer,
+            RecycleResourceType::Supervisor => Self::Supervisor,
+        }
+    }
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct DeletedResourceRecord {
+    pub id: u64,
+    pub eid: String,
+    pub name: String,
+    pub job_name: Option<String>,
+    pub team_id: Option<u64>,
+    pub team_name: Option<String>,
+    pub created_user: String,
+    pub deleted_by: String,
+    pub deleted_at: Option<String>,
+    /// the time it will be purged automatically
+    pub purge_at: Option<String>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct QueryRecycleBinResp {
+    pub total: u64,
+    pub list: Vec<DeletedResourceRecord>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct RecycleBinReq {
+    pub resource_type: RecycleResourceType,
+    pub id: u64,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct RecycleBinResp {
+    pub result: u64,
+}
+
+#[derive(Serialize, Default, Enum, Clone, Copy)]
+#[oai(rename_all = "snake_case")]
+pub enum JobSharePermission {
+    /// the team can view the job
+    #[default]
+    Read,
+    /// the team can view and run the job
+    Dispatch,
+}
+
+impl From<JobSharePermission> for logic::job::types::SharePermission {
+    fn from(v: JobSharePermission) -> Self {
+        match v {
+            JobSharePermission::Read => Self::Read,
+            JobSharePermission::Dispatch => Self::Dispatch,
+        }
+    }
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct ShareJobReq {
+    pub eid: String,
+    /// the team the job is shared with
+    pub team_id: u64,
+    pub permission: JobSharePermission,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct ShareJobResp {
+    pub result: u64,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct UnshareJobReq {
+    pub eid: String,
+    pub team_id: u64,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct UnshareJobResp {
+    pub result: u64,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct JobShareRecord {
+    pub id: u64,
+    pub eid: String,
+    pub team_id: u64,
+    pub team_name: Option<String>,
+    pub permission: String,
+    pub created_user: String,
+    pub created_time: String,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct QueryJobShareResp {
+    pub list: Vec<JobShareRecord>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct SaveJobTriggerReq {
+    /// create a trigger if absent
+    pub id: Option<u64>,
+    pub eid: String,
+    #[oai(validator(min_length = 1, max_length = 100))]
+    pub name: String,
+    /// instances the job is dispatched to
+    pub instance_ids: Vec<String>,
+    /// job args mapped from the payload, arg name to json pointer, eg: {"branch": "/ref"}
+    pub args_mapping: Option<serde_json::Value>,
+    /// invocations allowed per minute, 0 is unlimited
+    #[oai(default)]
+    pub rate_limit: u32,
+    #[oai(default)]
+    pub is_enabled: bool,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct SaveJobTriggerResp {
+    pub result: u64,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct JobTriggerRecord {
+    pub id: u64,
+    pub name: String,
+    pub eid: String,
+    /// the trigger url is /api/trigger/{token}
+    pub token: String,
+    /// signs the payload by hmac-sha256 in X-Jiascheduler-Signature
+    pub secret: String,
+    pub instance_ids: Option<serde_json::Value>,
+    pub args_mapping: Option<serde_json::Value>,
+    pub rate_limit: u32,
+    pub is_enabled: bool,
+    pub created_user: String,
+    pub updated_user: String,
+    pub created_time: String,
+    pub updated_time: String,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct QueryJobTriggerResp {
+    pub list: Vec<JobTriggerRecord>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct DeleteJobTriggerReq {
+    pub id: u64,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct DeleteJobTriggerResp {
+    pub result: u64,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct JobTriggerLogRecord {
+    pub id: u64,
+    pub trigger_id: u64,
+    pub remote_ip: String,
+    /// accepted, rejected or failed
+    pub status: String,
+    pub reason: String,
+    pub schedule_id: String,
+    pub payload: Option<String>,
+    pub created_time: String,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct QueryJobTriggerLogResp {
+    pub total: u64,
+    pub list: Vec<JobTriggerLogRecord>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct TriggerJobResp {
+    pub schedule_id: String,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct SaveJobRemediationReq {
+    /// create a remediation if absent
+    pub id: Option<u64>,
+    pub eid: String,
+    #[oai(validator(min_length = 1, max_length = 100))]
+    pub name: String,
+    /// alertname of the alerts to remediate
+    #[oai(validator(min_length = 1, max_length = 200))]
+    pub alertname: String,
+    /// labels the alert must have, label name to value, eg: {"severity": "critical"}
+    pub match_labels: Option<serde_json::Value>,
+    /// the job runs on the instance whose ip is the host of the label, eg: instance="10.0.0.1:9100"
+    #[oai(default = "default_target_label")]
+    pub target_label: String,
+    /// instances the job is dispatched to if the alert has no target label
+    #[oai(default)]
+    pub instance_ids: Vec<String>,
+    /// min interval between the runs on the same instance, 0 is no cooldown
+    #[oai(default = "default_cooldown_secs")]
+    pub cooldown_secs: u32,
+    /// max auto-runs per hour, 0 is unlimited
+    #[oai(default = "default_max_runs_per_hour")]
+    pub max_runs_per_hour: u32,
+    #[oai(default)]
+    pub is_enabled: bool,
+}
+
+fn default_target_label() -> String {
+    "instance".to_string()
+}
+
+fn default_cooldown_secs() -> u32 {
+    600
+}
+
+fn default_max_runs_per_hour() -> u32 {
+    6
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct SaveJobRemediationResp {
+    pub result: u64,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct JobRemediationRecord {
+    pub id: u64,
+    pub name: String,
+    pub eid: String,
+    pub alertname: String,
+    pub match_labels: Option<serde_json::Value>,
+    pub target_label: String,
+    pub instance_ids: Option<serde_json::Value>,
+    pub cooldown_secs: u32,
+    pub max_runs_per_hour: u32,
+    pub is_enabled: bool,
+    pub created_user: String,
+    pub updated_user: String,
+    pub created_time: String,
+    pub updated_time: String,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct QueryJobRemediationResp {
+    pub list: Vec<JobRemediationRecord>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct DeleteJobRemediationReq {
+    pub id: u64,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct DeleteJobRemediationResp {
+    pub result: u64,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct JobRemediationLogRecord {
+    pub id: u64,
+    pub remediation_id: u64,
+    pub alertname: String,
+    pub fingerprint: String,
+    pub instance_ids: Option<serde_json::Value>,
+    /// dispatched, skipped or failed
+    pub status: String,
+    pub reason: String,
+    pub schedule_id: String,
+    pub created_time: String,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct QueryJobRemediationLogResp {
+    pub total: u64,
+    pub list: Vec<JobRemediationLogRecord>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct ReceiveAlertsResp {
+    pub dispatched: u64,
+    pub skipped: u64,
+    pub failed: u64,
+}
+
+#[derive(Serialize, Deserialize, Default, Enum, Clone, Copy)]
+#[oai(rename_all = "snake_case")]
+pub enum ExportKind {
+    #[default]
+    ExecHistory,
+    ScheduleHistory,
+}
+
+impl From<ExportKind> for logic::job::ExportKind {
+    fn from(v: ExportKind) -> Self {
+        match v {
+            ExportKind::ExecHistory => Self::ExecHistory,
+            ExportKind::ScheduleHistory => Self::ScheduleHistory,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Enum, Clone, Copy)]
+#[oai(rename_all = "snake_case")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Parquet,
+}
+
+impl From<ExportFormat> for logic::job::ExportFormat {
+    fn from(v: ExportFormat) -> Self {
+        match v {
+            ExportFormat::Csv => Self::Csv,
+            ExportFormat::Parquet => Self::Parquet,
+        }
+    }
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct ExportHistoryReq {
+    pub kind: ExportKind,
+    pub format: ExportFormat,
+    pub eid: Option<String>,
+    /// range of the created time, eg: ["2025-09-01 00:00:00", "2025-09-08 00:00:00"]
+    #[oai(validator(max_items = 2, min_items = 2))]
+    pub time_range: Option<Vec<String>>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct ExportHistoryResp {
+    /// poll the task with /task/detail, download the file with /file/export/:task_id once succeeded
+    pub task_id: String,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct SaveStatusPageReq {
+    /// create a status page if absent
+    pub id: Option<u64>,
+    #[oai(validator(min_length = 1, max_length = 100))]
+    pub name: String,
+    /// the page belongs to the team if not 0, only the team admins can publish it
+    #[oai(default)]
+    pub team_id: u64,
+    /// the daemon and timer jobs shown, in the order of the page
+    #[oai(validator(min_items = 1, max_items = 50))]
+    pub eids: Vec<String>,
+    #[oai(default)]
+    pub is_enabled: bool,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct SaveStatusPageResp {
+    pub result: u64,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct StatusPageRecord {
+    pub id: u64,
+    /// the page is served without login at /status/{token}, the json at /api/status-page/{token}
+    pub token: String,
+    pub name: String,
+    pub team_id: u64,
+    pub eids: Option<serde_json::Value>,
+    pub is_enabled: bool,
+    pub created_user: String,
+    pub updated_user: String,
+    pub created_time: String,
+    pub updated_time: String,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct QueryStatusPageResp {
+    pub list: Vec<StatusPageRecord>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct DeleteStatusPageReq {
+    pub id: u64,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct DeleteStatusPageResp {
+    pub result: u64,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct StatusPageJob {
+    pub name: String,
+    /// daemon or timer
+    pub schedule_type: String,
+    /// operational, degraded, down or inactive
+    pub status: String,
+    pub instance_total: u64,
+    pub healthy_total: u64,
+    pub last_run_time: Option<String>,
+    pub next_time: Option<String>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct PublicStatusResp {
+    pub name: String,
+    /// the worst status of the jobs: operational, degraded or down
+    pub status: String,
+    pub jobs: Vec<StatusPageJob>,
+    pub generated_time: String,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct PollEventsResp {
+    /// pass it to the next poll
+    pub cursor: String,
+    /// the events named by the event field: run_status, exec_history or instance_status
+    pub events: Vec<serde_json::Value>,
+}
+
+#[derive(Object, Serialize, Deserialize, Default)]
+pub struct CodePolicyWaiver {
+    pub rule_name: String,
+    #[oai(validator(min_length = 1, max_length = 500))]
+    pub justification: String,
+}
+
+impl From<CodePolicyWaiver> for logic::job::code_policy::CodePolicyWaiver {
+    fn from(value: CodePolicyWaiver) -> Self {
+        Self {
+            rule_name: value.rule_name,
+            justification: value.justification,
+        }
+    }
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct CodePolicyViolation {
+    pub rule_name: String,
+    /// warn or reject
+    pub action: String,
+    pub info: String,
+    /// the job name, or the name of the bundle script
+    pub script: String,
+    pub line_no: u64,
+    pub line: String,
+    pub waived: bool,
+}
+
+impl From<logic::job::code_policy::CodePolicyViolation> for CodePolicyViolation {
+    fn from(value: logic::job::code_policy::CodePolicyViolation) -> Self {
+        Self {
+            rule_name: value.rule_name,
+            action: value.action,
+            info: value.info,
+            script: value.script,
+            line_no: value.line_no as u64,
+            line: value.line,
+            waived: value.waived,
+        }
+    }
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct SaveCodePolicyRuleReq {
+    pub id: Option<u64>,
+    /// the rule applies to the jobs of the team, to all the jobs if 0
+    #[oai(default)]
+    pub team_id: u64,
+    /// the builtin or global rule of the same name is replaced
+    #[oai(validator(min_length = 1, max_length = 50))]
+    pub name: String,
+    /// regex matched against every line of the code
+    #[oai(validator(min_length = 1, max_length = 500))]
+    pub pattern: String,
+    #[oai(validator(custom = "crate::api::OneOfValidator::new(vec![\"warn\", \"reject\"])"))]
+    pub action: String,
+    /// enabled if empty, a disabled rule turns off the rule of the same name
+    pub is_enabled: Option<bool>,
+    #[oai(default)]
+    pub info: String,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct SaveCodePolicyRuleResp {
+    pub result: u64,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct DeleteCodePolicyRuleReq {
+    pub id: u64,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct DeleteCodePolicyRuleResp {
+    pub result: u64,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct CodePolicyRuleRecord {
+    /// 0 for the builtin rules
+    pub id: u64,
+    pub team_id: u64,
+    pub name: String,
+    pub pattern: String,
+    pub action: String,
+    pub is_enabled: bool,
+    pub info: String,
+    pub updated_user: String,
+    pub updated_time: Option<String>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct QueryCodePolicyRuleResp {
+    /// the rules configured for the team, or the global ones
+    pub list: Vec<CodePolicyRuleRecord>,
+    /// the rules the jobs of the team are checked against
+    pub effective: Vec<CodePolicyRuleRecord>,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct CodePolicyWaiverRecord {
+    pub id: u64,
+    pub eid: String,
+    pub rule_name: String,
+    pub matched_line: String,
+    pub justification: String,
+    pub created_user: String,
+    pub created_time: String,
+}
+
+#[derive(Object, Serialize, Default)]
+pub struct QueryCodePolicyWaiverResp {
+    pub list: Vec<CodePolicyWaiverRecord>,
+}
@@ -1,17 +1,43 @@
 use crate::{
-    entity::user,
-    error::NoPermission,
+    api::instance::format_timestamp,
+    entity::{dashboard, user},
+    error::{InvalidUser, NoPermission},
     local_time,
     logic::{self, user::UserLogic},
     response::ApiStdResponse,
-    return_ok, AppState,
+    return_err, return_ok, AppState,
 };
 
 pub struct UserApi;
 
-use poem::{session::Session, web::Data, Result};
+fn dashboard_record(v: dashboard::Model) -> types::DashboardRecord {
+    types::DashboardRecord {
+        id: v.id,
+        name: v.name,
+        widgets: v
+            .widgets
+            .map(serde_json::from_value::<Vec<logic::user::DashboardWidget>>)
+            .transpose()
+            .unwrap_or_default()
+            .unwrap_or_default()
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+        team_id: v.team_id,
+        is_default: v.is_default,
+        created_user: v.created_user,
+        updated_time: local_time!(v.updated_time),
+    }
+}
+
+use poem::{
+    session::Session,
+    web::{Data, RealIp, RemoteAddr},
+    Result,
+};
 use poem_openapi::{param::Query, payload::Json, OpenApi};
 use sea_orm::{ActiveValue::NotSet, Set};
+use tracing::warn;
 
 pub mod types {
     use poem_openapi::Object;
@@ -44,6 +70,9 @@ pub mod types {
         pub permissions: Vec<String>,
         pub updated_time: String,
         pub created_time: String,
+        pub timezone: String,
+        /// only /user/update-info with a new password is allowed until it is changed
+        pub password_expired: bool,
     }
 
     #[derive(Object, Serialize, Deserialize)]
@@ -73,6 +102,8 @@ pub mod types {
         pub gender: String,
         pub introduction: String,
         pub phone: String,
+        /// IANA time zone the times are displayed in, eg: Asia/Shanghai, empty for the server zone
+        pub timezone: Option<String>,
     }
 
     #[derive(Object, Serialize, Deserialize)]
@@ -80,6 +111,187 @@ pub mod types {
         pub affected: u64,
     }
 
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct CreateApiTokenReq {
+        pub name: String,
+        /// never expires if empty
+        pub expire_secs: Option<u64>,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct CreateApiTokenResp {
+        /// shown only once, send it as "Authorization: Bearer <token>"
+        pub token: String,
+    }
+
+    #[derive(Object, Serialize, Default)]
+    pub struct ApiTokenRecord {
+        pub id: String,
+        pub name: String,
+        pub expire_time: Option<String>,
+        pub created_time: String,
+    }
+
+    #[derive(Object, Serialize)]
+    pub struct QueryApiTokenResp {
+        pub list: Vec<ApiTokenRecord>,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct RevokeApiTokenReq {
+        pub id: String,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct RevokeApiTokenResp {
+        pub result: u64,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct SaveFilterReq {
+        /// update the filter if set
+        pub id: Option<u64>,
+        #[oai(validator(min_length = 1, max_length = 100))]
+        pub name: String,
+        /// the list the filter applies to, eg: exec-list
+        #[oai(validator(min_length = 1, max_length = 50))]
+        pub resource: String,
+        /// query params of the list
+        pub params: Option<serde_json::Value>,
+        /// shared with the members of the team, private if empty
+        pub team_id: Option<u64>,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct SaveFilterResp {
+        pub id: u64,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct FilterRecord {
+        pub id: u64,
+        pub name: String,
+        pub resource: String,
+        pub params: Option<serde_json::Value>,
+        pub team_id: u64,
+        pub created_user: String,
+        pub updated_time: String,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct QueryFilterResp {
+        pub list: Vec<FilterRecord>,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct DeleteFilterReq {
+        pub id: u64,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct DeleteFilterResp {
+        pub result: u64,
+    }
+
+    #[derive(Object, Serialize, Deserialize, Default)]
+    pub struct DashboardWidget {
+        /// job_stats, instance_status, recent_failures or saved_search
+        pub widget_type: String,
+        #[oai(default)]
+        pub title: String,
+        /// job_stats: {"job_type": "default"}, recent_failures: {"days": 1, "limit": 10},
+        /// saved_search: {"filter_id": 1}
+        pub params: Option<serde_json::Value>,
+        /// position and size in the grid of the dashboard
+        #[oai(default)]
+        pub x: u32,
+        #[oai(default)]
+        pub y: u32,
+        #[oai(default)]
+        pub w: u32,
+        #[oai(default)]
+        pub h: u32,
+    }
+
+    impl From<DashboardWidget> for crate::logic::user::DashboardWidget {
+        fn from(value: DashboardWidget) -> Self {
+            Self {
+                widget_type: value.widget_type,
+                title: value.title,
+                params: value.params,
+                x: value.x,
+                y: value.y,
+                w: value.w,
+                h: value.h,
+            }
+        }
+    }
+
+    impl From<crate::logic::user::DashboardWidget> for DashboardWidget {
+        fn from(value: crate::logic::user::DashboardWidget) -> Self {
+            Self {
+                widget_type: value.widget_type,
+                title: value.title,
+                params: value.params,
+                x: value.x,
+                y: value.y,
+                w: value.w,
+                h: value.h,
+            }
+        }
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct SaveDashboardReq {
+        /// update the dashboard if set
+        pub id: Option<u64>,
+        #[oai(validator(min_length = 1, max_length = 100))]
+        pub name: String,
+        pub widgets: Vec<DashboardWidget>,
+        /// shared with the members of the team, private if empty
+        pub team_id: Option<u64>,
+        /// the home view of the team, or of the user if private
+        #[oai(default)]
+        pub is_default: bool,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct SaveDashboardResp {
+        pub id: u64,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct DashboardRecord {
+        pub id: u64,
+        pub name: String,
+        pub widgets: Vec<DashboardWidget>,
+        pub team_id: u64,
+        pub is_default: bool,
+        pub created_user: String,
+        pub updated_time: String,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct QueryDashboardResp {
+        pub list: Vec<DashboardRecord>,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct GetHomeDashboardResp {
+        /// none if neither the user nor the team has a default dashboard
+        pub dashboard: Option<DashboardRecord>,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct DeleteDashboardReq {
+        pub id: u64,
+    }
+
+    #[derive(Object, Serialize, Deserialize)]
+    pub struct DeleteDashboardResp {
+        pub result: u64,
+    }
+
     #[derive(Object, Serialize)]
     pub struct QueryUserResp {
         pub total: u64,
@@ -93,6 +305,8 @@ pub mod types {
         pub nickname: String,
         pub role: String,
         pub role_id: u64,
+        pub is_root: bool,
+        pub is_disabled: bool,
         pub avatar: String,
         pub email: String,
         pub phone: String,
@@ -110,14 +324,60 @@ impl UserApi {
         &self,
         session: &Session,
         state: Data<&AppState>,
+        remote_addr: &RemoteAddr,
+        RealIp(forwarded): RealIp,
         // #[oai(name = "TOKEN")] _token: Header<String>,
         Json(login_req): Json<types::LoginReq>,
     ) -> Result<ApiStdResponse<types::Logined>> {
         let svc = state.service();
-        let login_user = svc
+        let peer = remote_addr.as_socket_addr().map(|v| v.ip());
+        // the break-glass account is used when redis may be broken, so its lockout checks
+        // are skipped if redis cannot be reached
+        let break_glass = svc
             .user
-            .valid_user(&login_req.username, &login_req.password)
-            .await?;
+            .valid_break_glass(&login_req.username, &login_req.password);
+        let ip = match svc
+            .security
+            .check_login(&login_req.username, peer, forwarded)
+            .await
+        {
+            Ok(v) => v,
+            Err(e) if break_glass.is_some() && e.is::<redis::RedisError>() => {
+                warn!("skip the login checks of the break-glass account - {e}");
+                peer
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let login_user = match break_glass {
+            Some(v) => {
+                warn!("break-glass account {} logged in from {ip:?}", v.username);
+                if let Err(e) = svc
+                    .security
+                    .reset_login_failed(&login_req.username, ip)
+                    .await
+                {
+                    warn!("failed to reset the failed logins of the break-glass account - {e}");
+                }
+                v
+            }
+            None => {
+                let Some(v) = svc
+                    .user
+                    .valid_user(&login_req.username, &login_req.password)
+                    .await?
+                else {
+                    svc.security
+                        .record_login_failed(&login_req.username, ip)
+                        .await?;
+                    return Err(InvalidUser().into());
+                };
+                svc.security
+                    .reset_login_failed(&login_req.username, ip)
+                    .await?;
+                v
+            }
+        };
 
         let permissions = state.get_permissions_for_user(&login_user.user_id).await?;
 
@@ -138,6 +398,8 @@ impl UserApi {
                 gender: login_user.gender,
                 permissions,
                 role: login_user.role.unwrap_or_default(),
+                timezone: login_user.timezone,
+                password_expired: login_user.password_expired,
             },
         );
 
@@ -213,6 +475,8 @@ impl UserApi {
             updated_time: user_info.updated_time.clone(),
             created_time: user_info.created_time.clone(),
             gender: user_info.gender.clone(),
+            timezone: user_info.timezone.clone(),
+            password_expired: user_info.password_expired,
         })
     }
 
@@ -227,6 +491,9 @@ impl UserApi {
     ) -> Result<ApiStdResponse<types::UpdateInfoResp>> {
         let svc = state.service();
         let user_id = user_info.user_id.clone();
+        if let Some(Err(e)) = req.timezone.as_deref().map(utils::timezone::parse_timezone) {
+            return_err!(format!("invalid timezone, {e}"));
+        }
 
         let affected = svc
             .user
@@ -240,6 +507,7 @@ impl UserApi {
                     gender: Set(req.gender),
                     password: req.password.filter(|v| v != "").map_or(NotSet, |v| Set(v)),
                     introduction: Set(req.introduction),
+                    timezone: req.timezone.map_or(NotSet, |v| Set(v)),
                     ..Default::default()
                 },
                 |_, _| async { Ok(()) },
@@ -266,6 +534,8 @@ impl UserApi {
                         gender: record.gender,
                         permissions,
                         role: record.role.unwrap_or_default(),
+                        timezone: record.timezone,
+                        password_expired: record.password_expired,
                     },
                 );
             }
@@ -320,6 +590,8 @@ impl UserApi {
                 nickname: v.nickname,
                 role: v.role.unwrap_or_default(),
                 role_id: v.role_id,
+                is_root: v.is_root,
+                is_disabled: v.is_disabled,
                 avatar: v.avatar,
                 email: v.email,
                 phone: v.phone,
@@ -335,4 +607,196 @@ impl UserApi {
             list: list,
         })
     }
+
+    #[oai(path = "/api-token/create", method = "post")]
+    pub async fn create_api_token(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::CreateApiTokenReq>,
+    ) -> Result<ApiStdResponse<types::CreateApiTokenResp>> {
+        let token = state
+            .service()
+            .user
+            .create_api_token(
+                &user_info.user_id,
+                req.name,
+                req.expire_secs.filter(|v| *v > 0),
+            )
+            .await?;
+        return_ok!(types::CreateApiTokenResp { token })
+    }
+
+    #[oai(path = "/api-token/list", method = "get")]
+    pub async fn query_api_token(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+    ) -> Result<ApiStdResponse<types::QueryApiTokenResp>> {
+        let list = state
+            .service()
+            .user
+            .query_api_token(&user_info.user_id)
+            .await?
+            .into_iter()
+            .map(|v| types::ApiTokenRecord {
+                id: v.id,
+                name: v.name,
+                expire_time: v.expire_time.map(format_timestamp),
+                created_time: format_timestamp(v.created_time),
+            })
+            .collect();
+        return_ok!(types::QueryApiTokenResp { list })
+    }
+
+    #[oai(path = "/api-token/revoke", method = "post")]
+    pub async fn revoke_api_token(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::RevokeApiTokenReq>,
+    ) -> Result<ApiStdResponse<types::RevokeApiTokenResp>> {
+        let result = state
+            .service()
+            .user
+            .revoke_api_token(&user_info.user_id, &req.id)
+            .await?;
+        return_ok!(types::RevokeApiTokenResp { result })
+    }
+
+    #[oai(path = "/saved-filter/save", method = "post")]
+    pub async fn save_filter(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::SaveFilterReq>,
+    ) -> Result<ApiStdResponse<types::SaveFilterResp>> {
+        let id = state
+            .service()
+            .user
+            .save_filter(
+                &user_info,
+                req.id,
+                req.name,
+                req.resource,
+                req.params,
+                req.team_id.unwrap_or_default(),
+            )
+            .await?;
+        return_ok!(types::SaveFilterResp { id })
+    }
+
+    /// the filters of the user and the ones shared with the teams of the user
+    #[oai(path = "/saved-filter/list", method = "get")]
+    pub async fn query_filter(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Query(resource): Query<Option<String>>,
+    ) -> Result<ApiStdResponse<types::QueryFilterResp>> {
+        let list = state
+            .service()
+            .user
+            .query_filter(&user_info, resource.filter(|v| v != ""))
+            .await?
+            .into_iter()
+            .map(|v| types::FilterRecord {
+                id: v.id,
+                name: v.name,
+                resource: v.resource,
+                params: v.params,
+                team_id: v.team_id,
+                created_user: v.created_user,
+                updated_time: local_time!(v.updated_time),
+            })
+            .collect();
+        return_ok!(types::QueryFilterResp { list })
+    }
+
+    #[oai(path = "/saved-filter/delete", method = "post")]
+    pub async fn delete_filter(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::DeleteFilterReq>,
+    ) -> Result<ApiStdResponse<types::DeleteFilterResp>> {
+        let result = state
+            .service()
+            .user
+            .delete_filter(&user_info, req.id)
+            .await?;
+        return_ok!(types::DeleteFilterResp { result })
+    }
+
+    #[oai(path = "/dashboard/save", method = "post")]
+    pub async fn save_dashboard(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::SaveDashboardReq>,
+    ) -> Result<ApiStdResponse<types::SaveDashboardResp>> {
+        let id = state
+            .service()
+            .user
+            .save_dashboard(
+                &user_info,
+                req.id,
+                req.name,
+                req.widgets.into_iter().map(Into::into).collect(),
+                req.team_id.unwrap_or_default(),
+                req.is_default,
+            )
+            .await?;
+        return_ok!(types::SaveDashboardResp { id })
+    }
+
+    /// the dashboards of the user and the ones shared with the teams of the user
+    #[oai(path = "/dashboard/list", method = "get")]
+    pub async fn query_dashboard(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+    ) -> Result<ApiStdResponse<types::QueryDashboardResp>> {
+        let list = state
+            .service()
+            .user
+            .query_dashboard(&user_info)
+            .await?
+            .into_iter()
+            .map(dashboard_record)
+            .collect();
+        return_ok!(types::QueryDashboardResp { list })
+    }
+
+    /// the default dashboard of the user, or of the team if the user has none
+    #[oai(path = "/dashboard/home", method = "get")]
+    pub async fn get_home_dashboard(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Query(team_id): Query<Option<u64>>,
+    ) -> Result<ApiStdResponse<types::GetHomeDashboardResp>> {
+        let dashboard = state
+            .service()
+            .user
+            .get_home_dashboard(&user_info, team_id)
+            .await?
+            .map(dashboard_record);
+        return_ok!(types::GetHomeDashboardResp { dashboard })
+    }
+
+    #[oai(path = "/dashboard/delete", method = "post")]
+    pub async fn delete_dashboard(
+        &self,
+        state: Data<&AppState>,
+        user_info: Data<&logic::types::UserInfo>,
+        Json(req): Json<types::DeleteDashboardReq>,
+    ) -> Result<ApiStdResponse<types::DeleteDashboardResp>> {
+        let result = state
+            .service()
+            .user
+            .delete_dashboard(&user_info, req.id)
+            .await?;
+        return_ok!(types::DeleteDashboardResp { result })
+    }
 }
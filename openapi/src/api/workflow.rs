@@ -15,12 +15,33 @@ use service::logic::workflow::{
 use super::types;
 
 use crate::{
-    api::types::UserVariables, api_response, local_time, logic, middleware, return_err, return_ok,
+    api::types::UserVariables,
+    api_response, local_time,
+    logic::{
+        self,
+        role::{POLICY_ALLOW_CHANGE_ALL_JOB, POLICY_ALLOW_DISPATCH_JOB},
+    },
+    middleware, return_err, return_ok,
     state::AppState,
 };
 
 fn set_middleware(ep: impl Endpoint) -> impl Endpoint {
     ep.with(middleware::TeamPermissionMiddleware)
+        .with(middleware::PermissionMiddleware::grant(
+            &POLICY_ALLOW_CHANGE_ALL_JOB,
+        ))
+}
+
+fn query_middleware(ep: impl Endpoint) -> impl Endpoint {
+    ep.with(middleware::PermissionMiddleware::grant(
+        &POLICY_ALLOW_CHANGE_ALL_JOB,
+    ))
+}
+
+fn dispatch_middleware(ep: impl Endpoint) -> impl Endpoint {
+    ep.with(middleware::PermissionMiddleware::new(
+        &POLICY_ALLOW_DISPATCH_JOB,
+    ))
 }
 pub struct WorkflowApi;
 
@@ -132,6 +153,7 @@ impl WorkflowApi {
         &self,
         state: Data<&AppState>,
         user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
         #[oai(default = "types::default_page", validator(maximum(value = "10000")))]
         Query(page): Query<u64>,
         #[oai(
@@ -145,7 +167,7 @@ impl WorkflowApi {
         #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
         #[oai(default)] Query(name): Query<Option<String>>,
     ) -> api_response!(types::QueryWorkflowResp) {
-        let search_username = if state.can_manage_job(&user_info.user_id).await? {
+        let search_username = if granted.manage_job() {
             search_username
         } else {
             team_id.map_or_else(|| Some(user_info.username.clone()), |_| search_username)
@@ -403,7 +425,7 @@ impl WorkflowApi {
                             bind_ip: v.bind_ip,
                             exit_code: v.exit_code,
                             exit_status: v.exit_status,
-                            output: v.output,
+                            output: utils::ansi::sanitize(&v.output, false).into_owned(),
                             restart_num: v.restart_num,
                             dispatch_result: v.dispatch_result,
                             created_user: v.created_user,
@@ -437,11 +459,12 @@ impl WorkflowApi {
         return_ok!(resp)
     }
 
-    #[oai(path = "/process/list", method = "get")]
+    #[oai(path = "/process/list", method = "get", transform = "query_middleware")]
     pub async fn query_process(
         &self,
         state: Data<&AppState>,
         user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
         #[oai(default = "types::default_page", validator(maximum(value = "10000")))]
         Query(page): Query<u64>,
         #[oai(
@@ -456,7 +479,7 @@ impl WorkflowApi {
         Query(tag_ids): Query<Option<Vec<u64>>>,
         #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
     ) -> api_response!(types::QueryWorkflowProcessResp) {
-        let search_username = if state.can_manage_job(&user_info.user_id).await? {
+        let search_username = if granted.manage_job() {
             search_username
         } else {
             team_id.map_or_else(|| Some(user_info.username.clone()), |_| search_username)
@@ -546,7 +569,11 @@ impl WorkflowApi {
         return_ok!(types::QueryWorkflowProcessResp { total: ret.1, list })
     }
 
-    #[oai(path = "/start-process", method = "post")]
+    #[oai(
+        path = "/start-process",
+        method = "post",
+        transform = "dispatch_middleware"
+    )]
     pub async fn start_process(
         &self,
         state: Data<&AppState>,
@@ -680,6 +707,9 @@ impl WorkflowApi {
             return_err!("no permission");
         }
 
+        if req.timer_expr.interval.is_some() {
+            return_err!("interval timers are not supported by workflow timers");
+        }
         let next_exec_times =
             utils::check_timer_expr(&req.timer_expr.timezone, &req.timer_expr.expr)?;
 
@@ -715,11 +745,12 @@ impl WorkflowApi {
         })
     }
 
-    #[oai(path = "/timer/list", method = "get")]
+    #[oai(path = "/timer/list", method = "get", transform = "query_middleware")]
     pub async fn query_timer(
         &self,
         state: Data<&AppState>,
         user_info: Data<&logic::types::UserInfo>,
+        granted: Data<&middleware::Granted>,
         #[oai(default = "types::default_page", validator(maximum(value = "10000")))]
         Query(page): Query<u64>,
         #[oai(
@@ -734,7 +765,7 @@ impl WorkflowApi {
         Query(tag_ids): Query<Option<Vec<u64>>>,
         #[oai(name = "X-Team-Id")] Header(team_id): Header<Option<u64>>,
     ) -> api_response!(types::QueryWorkflowTimerResp) {
-        let search_username = if state.can_manage_job(&user_info.user_id).await? {
+        let search_username = if granted.manage_job() {
             search_username
         } else {
             team_id.map_or_else(|| Some(user_info.username.clone()), |_| search_username)
@@ -833,7 +864,11 @@ impl WorkflowApi {
         return_ok!(types::DeleteTimerResp { result: ret })
     }
 
-    #[oai(path = "/timer/schedule", method = "post")]
+    #[oai(
+        path = "/timer/schedule",
+        method = "post",
+        transform = "dispatch_middleware"
+    )]
     async fn schedule_timer(
         &self,
         state: Data<&AppState>,
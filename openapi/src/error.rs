@@ -1,10 +1,11 @@
 use anyhow::{anyhow, Result};
 use poem::{error::ResponseError, http::StatusCode, Error as PError, IntoResponse};
 use poem_openapi::payload::Json;
+use service::logic::VersionConflict;
 use std::{error::Error as StdError, ops::Deref};
 use thiserror::Error;
 
-use crate::response::StdResponse;
+use crate::{i18n, response::StdResponse};
 
 #[allow(unused)]
 #[derive(Error, Debug)]
@@ -25,7 +26,7 @@ impl From<BizErr> for PError {
 
 impl BizErr {
     pub fn new<S: Into<String>>(msg: S, code: i32) -> Self {
-        let mut e = PError::from_string(msg.into(), StatusCode::OK);
+        let mut e = PError::from_string(i18n::t(msg.into()), StatusCode::OK);
         e.set_data(code);
         BizErr(e)
     }
@@ -80,8 +81,17 @@ define_biz_error!(
     (BizError, 50000, "Internal error");
     (InvalidUser, 50004, "Invalid username or passowrd");
     (NoPermission, 50005, "This operation is not allowed");
+    (Conflict, 50409, "The record has been modified by others, please reload it");
 );
 
+/// report a stale save as Conflict with the conflicting fields, other errors are kept as is
+pub fn conflict_into_error(e: anyhow::Error) -> PError {
+    match e.downcast_ref::<VersionConflict>() {
+        Some(v) => Conflict().with_msg(v.to_string()).into(),
+        None => e.into(),
+    }
+}
+
 impl ResponseError for BizError {
     fn status(&self) -> StatusCode {
         StatusCode::OK
@@ -105,6 +115,8 @@ pub async fn custom_error(e: PError) -> impl IntoResponse {
     if msg.contains("Duplicate entry") {
         msg = "record already exists, please do not add it again".to_string()
     }
+    // errors raised outside of BizErr and return_err, eg: by poem
+    let msg = i18n::t(msg);
 
     let code = e.data::<i32>().unwrap_or(&code).to_owned();
     Json(StdResponse::<bool> {
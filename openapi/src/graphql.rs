@@ -0,0 +1,529 @@
+//! graphql endpoint alongside the rest api, read-only. the nested fields of a page, eg: the
+//! tags and the latest run of the jobs, are batched by the dataloaders into one query each
+use async_graphql::{
+    dataloader::DataLoader, http::GraphiQLSource, ComplexObject, Context, EmptyMutation,
+    EmptySubscription, Json, Object, Result, Schema, SimpleObject,
+};
+use async_graphql_poem::{GraphQLRequest, GraphQLResponse};
+use poem::{
+    handler,
+    http::HeaderMap,
+    web::{Data, Html},
+};
+use serde_json::Value;
+
+use crate::{
+    default_local_time,
+    entity::job_exec_history,
+    local_time,
+    logic::{self, types::UserInfo},
+    middleware::Granted,
+    AppState,
+};
+
+mod loader;
+
+use loader::{LatestRunLoader, TagLoader};
+
+pub type ApiSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+const GRAPHQL_PATH: &str = "/api/graphql";
+const MAX_DEPTH: usize = 8;
+const MAX_COMPLEXITY: usize = 5000;
+
+/// the team of the X-Team-Id header, validated by the team permission middleware
+struct TeamScope(Option<u64>);
+
+pub fn schema() -> ApiSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+#[handler]
+pub async fn graphql(
+    schema: Data<&ApiSchema>,
+    state: Data<&AppState>,
+    user_info: Data<&UserInfo>,
+    granted: Data<&Granted>,
+    headers: &HeaderMap,
+    req: GraphQLRequest,
+) -> GraphQLResponse {
+    let team_id = headers
+        .get("X-Team-Id")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok());
+    let req = req
+        .0
+        .data(state.clone())
+        .data(user_info.clone())
+        .data(granted.clone())
+        .data(TeamScope(team_id))
+        .data(DataLoader::new(
+            TagLoader {
+                state: state.clone(),
+            },
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            LatestRunLoader {
+                state: state.clone(),
+            },
+            tokio::spawn,
+        ));
+    schema.execute(req).await.into()
+}
+
+#[handler]
+pub async fn graphiql() -> Html<String> {
+    Html(GraphiQLSource::build().endpoint(GRAPHQL_PATH).finish())
+}
+
+/// the rest api scope: the job admins see all, the others their own jobs or the ones of the team
+fn created_user(granted: &Granted, user_info: &UserInfo, team_id: Option<u64>) -> Option<String> {
+    if granted.manage_job() {
+        return None;
+    }
+    team_id.map_or_else(|| Some(user_info.username.clone()), |_| None)
+}
+
+#[derive(SimpleObject)]
+pub struct Tag {
+    pub id: u64,
+    pub tag_name: String,
+}
+
+#[derive(SimpleObject)]
+pub struct ExecRun {
+    pub id: u64,
+    pub schedule_id: String,
+    pub job_name: Option<String>,
+    pub instance_id: Option<String>,
+    pub bind_ip: Option<String>,
+    pub exit_status: String,
+    pub exit_code: i64,
+    pub result_status: String,
+    pub output: String,
+    pub created_user: String,
+    pub start_time: String,
+    pub end_time: String,
+}
+
+impl From<job_exec_history::Model> for ExecRun {
+    fn from(v: job_exec_history::Model) -> Self {
+        Self {
+            id: v.id,
+            schedule_id: v.schedule_id,
+            job_name: None,
+            instance_id: Some(v.instance_id),
+            bind_ip: None,
+            exit_status: v.exit_status,
+            exit_code: v.exit_code.into(),
+            result_status: v.result_status,
+            output: utils::ansi::sanitize(&v.output, false).into_owned(),
+            created_user: v.created_user,
+            start_time: default_local_time!(v.start_time),
+            end_time: default_local_time!(v.end_time),
+        }
+    }
+}
+
+async fn load_tags(ctx: &Context<'_>, job_id: u64) -> Result<Vec<Tag>> {
+    let binds = ctx
+        .data::<DataLoader<TagLoader>>()?
+        .load_one(job_id)
+        .await?
+        .unwrap_or_default();
+    Ok(binds
+        .into_iter()
+        .map(|v| Tag {
+            id: v.tag_id,
+            tag_name: v.tag_name,
+        })
+        .collect())
+}
+
+async fn load_latest_run(ctx: &Context<'_>, eid: &str) -> Result<Option<ExecRun>> {
+    Ok(ctx
+        .data::<DataLoader<LatestRunLoader>>()?
+        .load_one(eid.to_string())
+        .await?
+        .map(ExecRun::from))
+}
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct Job {
+    pub id: u64,
+    pub eid: String,
+    pub name: String,
+    pub job_type: String,
+    pub executor_name: String,
+    pub info: String,
+    pub team_id: Option<u64>,
+    pub team_name: Option<String>,
+    pub is_disabled: bool,
+    pub args: Option<Json<Value>>,
+    pub created_user: String,
+    pub updated_user: String,
+    pub created_time: String,
+    pub updated_time: String,
+}
+
+#[ComplexObject]
+impl Job {
+    async fn tags(&self, ctx: &Context<'_>) -> Result<Vec<Tag>> {
+        load_tags(ctx, self.id).await
+    }
+
+    async fn latest_run(&self, ctx: &Context<'_>) -> Result<Option<ExecRun>> {
+        load_latest_run(ctx, &self.eid).await
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct ScheduleRecord {
+    pub id: u64,
+    #[graphql(skip)]
+    pub job_id: u64,
+    pub eid: String,
+    pub name: String,
+    pub job_type: String,
+    pub schedule_type: String,
+    pub action: String,
+    pub timer_expr: Option<Json<Value>>,
+    pub team_id: Option<u64>,
+    pub team_name: Option<String>,
+    pub created_user: String,
+    pub updated_user: String,
+    pub created_time: String,
+    pub updated_time: String,
+}
+
+#[ComplexObject]
+impl ScheduleRecord {
+    async fn tags(&self, ctx: &Context<'_>) -> Result<Vec<Tag>> {
+        load_tags(ctx, self.job_id).await
+    }
+
+    async fn latest_run(&self, ctx: &Context<'_>) -> Result<Option<ExecRun>> {
+        load_latest_run(ctx, &self.eid).await
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct Instance {
+    pub id: u64,
+    pub instance_id: String,
+    pub ip: String,
+    pub namespace: String,
+    pub status: i8,
+    pub maintenance: bool,
+    pub os: String,
+    pub arch: String,
+    pub agent_version: String,
+    /// healthy, degraded, offline or unknown
+    pub health: String,
+    pub updated_time: String,
+}
+
+#[derive(SimpleObject)]
+pub struct Team {
+    pub id: u64,
+    pub name: String,
+    pub info: String,
+    pub is_admin: bool,
+    pub created_user: String,
+    pub created_time: String,
+}
+
+#[derive(SimpleObject)]
+pub struct JobPage {
+    pub total: u64,
+    pub list: Vec<Job>,
+}
+
+#[derive(SimpleObject)]
+pub struct SchedulePage {
+    pub total: u64,
+    pub list: Vec<ScheduleRecord>,
+}
+
+#[derive(SimpleObject)]
+pub struct ExecPage {
+    pub total: u64,
+    pub list: Vec<ExecRun>,
+}
+
+#[derive(SimpleObject)]
+pub struct InstancePage {
+    pub total: u64,
+    pub list: Vec<Instance>,
+}
+
+#[derive(SimpleObject)]
+pub struct TeamPage {
+    pub total: u64,
+    pub list: Vec<Team>,
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    async fn jobs(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 1, validator(minimum = 1, maximum = 10000))] page: u64,
+        #[graphql(default = 20, validator(minimum = 1, maximum = 200))] page_size: u64,
+        name: Option<String>,
+        job_type: Option<String>,
+        tag_ids: Option<Vec<u64>>,
+    ) -> Result<JobPage> {
+        let state = ctx.data::<AppState>()?;
+        let user_info = ctx.data::<UserInfo>()?;
+        let team_id = ctx.data::<TeamScope>()?.0;
+        let svc = state.service();
+
+        let (list, total) = svc
+            .job
+            .query_job(
+                created_user(ctx.data::<Granted>()?, user_info, team_id),
+                job_type.filter(|v| !v.is_empty()),
+                name.filter(|v| !v.is_empty()),
+                None,
+                None,
+                None,
+                team_id,
+                tag_ids,
+                svc.tag.get_tag_scope(user_info).await?,
+                page - 1,
+                page_size,
+            )
+            .await?;
+        let list = list
+            .into_iter()
+            .map(|v| Job {
+                id: v.id,
+                eid: v.eid,
+                name: v.name,
+                job_type: v.job_type,
+                executor_name: v.executor_name,
+                info: v.info,
+                team_id: v.team_id,
+                team_name: v.team_name,
+                is_disabled: v.is_disabled,
+                args: v.args.map(Json),
+                created_user: v.created_user,
+                updated_user: v.updated_user,
+                created_time: local_time!(v.created_time),
+                updated_time: local_time!(v.updated_time),
+            })
+            .collect();
+        Ok(JobPage { total, list })
+    }
+
+    async fn schedules(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 1, validator(minimum = 1, maximum = 10000))] page: u64,
+        #[graphql(default = 20, validator(minimum = 1, maximum = 200))] page_size: u64,
+        #[graphql(desc = "once, timer, flow or daemon")] schedule_type: Option<String>,
+        #[graphql(default_with = "String::from(\"default\")")] job_type: String,
+        name: Option<String>,
+        tag_ids: Option<Vec<u64>>,
+    ) -> Result<SchedulePage> {
+        let state = ctx.data::<AppState>()?;
+        let user_info = ctx.data::<UserInfo>()?;
+        let team_id = ctx.data::<TeamScope>()?.0;
+        let svc = state.service();
+
+        let (list, total) = svc
+            .job
+            .query_schedule(
+                schedule_type.filter(|v| !v.is_empty()),
+                created_user(ctx.data::<Granted>()?, user_info, team_id),
+                job_type,
+                name.filter(|v| !v.is_empty()),
+                team_id,
+                None,
+                tag_ids,
+                svc.tag.get_tag_scope(user_info).await?,
+                page - 1,
+                page_size,
+            )
+            .await?;
+        let list = list
+            .into_iter()
+            .map(|v| ScheduleRecord {
+                id: v.id,
+                job_id: v.job_id,
+                eid: v.eid,
+                name: v.name,
+                job_type: v.job_type,
+                schedule_type: v.schedule_type,
+                action: v.action,
+                timer_expr: v.timer_expr.map(Json),
+                team_id: v.team_id,
+                team_name: v.team_name,
+                created_user: v.created_user,
+                updated_user: v.updated_user,
+                created_time: local_time!(v.created_time),
+                updated_time: local_time!(v.updated_time),
+            })
+            .collect();
+        Ok(SchedulePage { total, list })
+    }
+
+    async fn exec_history(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 1, validator(minimum = 1, maximum = 10000))] page: u64,
+        #[graphql(default = 20, validator(minimum = 1, maximum = 200))] page_size: u64,
+        #[graphql(default_with = "String::from(\"default\")")] job_type: String,
+        eid: Option<String>,
+        schedule_id: Option<String>,
+        instance_id: Option<String>,
+        tag_ids: Option<Vec<u64>>,
+    ) -> Result<ExecPage> {
+        let state = ctx.data::<AppState>()?;
+        let user_info = ctx.data::<UserInfo>()?;
+        let team_id = ctx.data::<TeamScope>()?.0;
+        let svc = state.service();
+
+        let (list, total) = svc
+            .job
+            .query_exec_history(
+                job_type,
+                None,
+                schedule_id.filter(|v| !v.is_empty()),
+                None,
+                team_id,
+                eid.filter(|v| !v.is_empty()),
+                None,
+                created_user(ctx.data::<Granted>()?, user_info, team_id),
+                instance_id.filter(|v| !v.is_empty()),
+                None,
+                None,
+                None,
+                tag_ids,
+                svc.tag.get_tag_scope(user_info).await?,
+                None,
+                page - 1,
+                page_size,
+            )
+            .await?;
+        let list = list
+            .into_iter()
+            .map(|v| ExecRun {
+                id: v.id,
+                schedule_id: v.schedule_id,
+                job_name: Some(v.job_name),
+                instance_id: None,
+                bind_ip: Some(v.ip),
+                exit_status: v.exit_status,
+                exit_code: v.exit_code,
+                result_status: v.result_status,
+                output: utils::ansi::sanitize(&v.output, false).into_owned(),
+                created_user: v.created_user,
+                start_time: default_local_time!(v.start_time),
+                end_time: default_local_time!(v.end_time),
+            })
+            .collect();
+        Ok(ExecPage { total, list })
+    }
+
+    /// the instances are visible to the instance admins, like the instance list of the rest api
+    async fn instances(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 1, validator(minimum = 1, maximum = 10000))] page: u64,
+        #[graphql(default = 20, validator(minimum = 1, maximum = 200))] page_size: u64,
+        ip: Option<String>,
+        status: Option<u8>,
+    ) -> Result<InstancePage> {
+        let state = ctx.data::<AppState>()?;
+        let user_info = ctx.data::<UserInfo>()?;
+        if !ctx.data::<Granted>()?.manage_instance() {
+            return Err("No permission".into());
+        }
+        let svc = state.service();
+
+        let facts = logic::instance::InstanceFactsFilter {
+            tag_scope: svc.tag.get_tag_scope(user_info).await?,
+            ..Default::default()
+        };
+        let (list, total) = svc
+            .instance
+            .query_instance(
+                ip.filter(|v| !v.is_empty()),
+                status,
+                None,
+                &facts,
+                None,
+                page - 1,
+                page_size,
+            )
+            .await?;
+        let list = list
+            .into_iter()
+            .map(|v| Instance {
+                health: v.health().0.to_string(),
+                id: v.id,
+                instance_id: v.instance_id,
+                ip: v.ip,
+                namespace: v.namespace,
+                status: v.status,
+                maintenance: v.maintenance,
+                os: v.os,
+                arch: v.arch,
+                agent_version: v.agent_version,
+                updated_time: local_time!(v.updated_time),
+            })
+            .collect();
+        Ok(InstancePage { total, list })
+    }
+
+    async fn teams(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 1, validator(minimum = 1, maximum = 10000))] page: u64,
+        #[graphql(default = 20, validator(minimum = 1, maximum = 200))] page_size: u64,
+        name: Option<String>,
+    ) -> Result<TeamPage> {
+        let state = ctx.data::<AppState>()?;
+        let user_info = ctx.data::<UserInfo>()?;
+        let username = if ctx.data::<Granted>()?.manage_job() {
+            None
+        } else {
+            Some(user_info.username.clone())
+        };
+
+        let (list, total) = state
+            .service()
+            .team
+            .query_team(
+                name.filter(|v| !v.is_empty()),
+                username,
+                None,
+                None,
+                page - 1,
+                page_size,
+            )
+            .await?;
+        let list = list
+            .into_iter()
+            .map(|v| Team {
+                is_admin: v.is_admin.unwrap_or(v.created_user == user_info.username),
+                id: v.id,
+                name: v.name,
+                info: v.info,
+                created_user: v.created_user,
+                created_time: local_time!(v.created_time),
+            })
+            .collect();
+        Ok(TeamPage { total, list })
+    }
+}
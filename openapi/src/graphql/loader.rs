@@ -0,0 +1,60 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_graphql::dataloader::Loader;
+
+use crate::{
+    entity::job_exec_history,
+    logic::{tag::JOB_RESOURCE_TYPES, types::TagBind},
+    AppState,
+};
+
+/// the tags of the jobs by the job id
+pub struct TagLoader {
+    pub state: AppState,
+}
+
+impl Loader<u64> for TagLoader {
+    type Value = Vec<TagBind>;
+    type Error = Arc<anyhow::Error>;
+
+    async fn load(&self, keys: &[u64]) -> Result<HashMap<u64, Self::Value>, Self::Error> {
+        let mut ret: HashMap<u64, Self::Value> = HashMap::new();
+        // a job is bound to the tags as a job or as a bundle job
+        for resource_type in JOB_RESOURCE_TYPES {
+            let binds = self
+                .state
+                .service()
+                .tag
+                .get_all_tag_bind_by_resource_ids(keys.to_vec(), resource_type)
+                .await
+                .map_err(Arc::new)?;
+            for v in binds {
+                ret.entry(v.resource_id).or_default().push(v);
+            }
+        }
+        Ok(ret)
+    }
+}
+
+/// the latest run of the jobs by the eid
+pub struct LatestRunLoader {
+    pub state: AppState,
+}
+
+impl Loader<String> for LatestRunLoader {
+    type Value = job_exec_history::Model;
+    type Error = Arc<anyhow::Error>;
+
+    async fn load(&self, keys: &[String]) -> Result<HashMap<String, Self::Value>, Self::Error> {
+        Ok(self
+            .state
+            .service()
+            .job
+            .get_latest_exec_history(keys.to_vec())
+            .await
+            .map_err(Arc::new)?
+            .into_iter()
+            .map(|v| (v.eid.clone(), v))
+            .collect())
+    }
+}
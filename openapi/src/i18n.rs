@@ -0,0 +1,103 @@
+use std::{collections::HashMap, future::Future, sync::LazyLock};
+
+/// messages are written in english in the code, the other languages are
+/// translated by the catalogs under locales, keyed by the english message
+static ZH_CN: LazyLock<HashMap<String, String>> = LazyLock::new(|| {
+    serde_json::from_str(include_str!("../locales/zh-CN.json")).expect("invalid zh-CN catalog")
+});
+
+tokio::task_local! {
+    static LANG: Lang;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Lang {
+    #[default]
+    En,
+    ZhCn,
+}
+
+impl Lang {
+    /// pick the first supported language of the Accept-Language header by quality,
+    /// eg: "zh-CN,zh;q=0.9,en;q=0.8"
+    pub fn from_accept_language(v: &str) -> Self {
+        let mut langs: Vec<(&str, f32)> = v
+            .split(',')
+            .filter_map(|item| {
+                let mut parts = item.split(';');
+                let tag = parts.next()?.trim();
+                let q = parts
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .and_then(|q| q.parse().ok())
+                    .unwrap_or(1.0);
+                (!tag.is_empty()).then_some((tag, q))
+            })
+            .collect();
+        langs.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        langs
+            .into_iter()
+            .find_map(|(tag, _)| Self::from_tag(tag))
+            .unwrap_or_default()
+    }
+
+    fn from_tag(tag: &str) -> Option<Self> {
+        let primary = tag.split(['-', '_']).next().unwrap_or_default();
+        match primary.to_ascii_lowercase().as_str() {
+            "zh" => Some(Lang::ZhCn),
+            "en" => Some(Lang::En),
+            _ => None,
+        }
+    }
+
+    fn catalog(self) -> Option<&'static HashMap<String, String>> {
+        match self {
+            Lang::En => None,
+            Lang::ZhCn => Some(&ZH_CN),
+        }
+    }
+
+    pub fn translate(self, msg: &str) -> String {
+        self.catalog()
+            .and_then(|v| v.get(msg))
+            .cloned()
+            .unwrap_or_else(|| msg.to_string())
+    }
+}
+
+/// run the request with the language negotiated from Accept-Language
+pub async fn with_lang<F: Future>(lang: Lang, f: F) -> F::Output {
+    LANG.scope(lang, f).await
+}
+
+/// language of the current request, english outside of a request
+pub fn current_lang() -> Lang {
+    LANG.try_with(|v| *v).unwrap_or_default()
+}
+
+/// translate the message into the language of the current request,
+/// messages missing in the catalog are returned as is
+pub fn t(msg: impl AsRef<str>) -> String {
+    current_lang().translate(msg.as_ref())
+}
+
+#[tokio::test]
+async fn test_translate() {
+    assert_eq!(
+        Lang::from_accept_language("zh-CN,zh;q=0.9,en;q=0.8"),
+        Lang::ZhCn
+    );
+    assert_eq!(Lang::from_accept_language("fr;q=0.9, en;q=0.5"), Lang::En);
+    assert_eq!(
+        Lang::from_accept_language("en;q=0.3, zh-TW;q=0.7"),
+        Lang::ZhCn
+    );
+    assert_eq!(Lang::from_accept_language("*"), Lang::En);
+
+    assert_eq!(t("no permission"), "no permission");
+    with_lang(Lang::ZhCn, async {
+        assert_eq!(t("no permission"), "没有权限");
+        assert_eq!(t("unknown message"), "unknown message");
+    })
+    .await;
+}
@@ -1,4 +1,7 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
 
 use anyhow::{Context, Result};
 use automate::{
@@ -7,18 +10,49 @@ use automate::{
 };
 
 use leader_election::LeaderElection;
-use service::logic::workflow::timer::WorkflowTimerTask;
-use tokio::{sync::RwLock, time::sleep};
-use tracing::{error, info};
+use service::logic::{
+    credential::ROTATE_EID_PREFIX, executor::INSTALL_EID_PREFIX, job::TEST_RUN_EID_PREFIX,
+    task::TOUCH_INTERVAL_SECS, workflow::timer::WorkflowTimerTask,
+};
+use tokio::{
+    sync::{watch, RwLock},
+    task::JoinHandle,
+    time::sleep,
+};
+use tracing::{debug, error, info};
 
 use crate::AppState;
 
+pub const LEADER_ELECTION_KEY: &str = "jiascheduler:leader_election";
+pub const LEADER_ELECTION_TTL: i64 = 10;
+
+/// background tasks a console runs at the same time
+const TASK_WORKERS: usize = 4;
+
+static NODE_ID: OnceLock<String> = OnceLock::new();
+
+/// id of this console in the leader election
+pub fn node_id() -> &'static str {
+    NODE_ID.get().map_or("", |v| v.as_str())
+}
+
+/// readable name of this console
+fn node_name(state: &AppState) -> String {
+    format!("{}({})", automate::get_local_ip(), state.conf.bind_addr)
+}
+
 async fn heartbeat(state: AppState, msg: HeartbeatParams) -> Result<()> {
-    state
-        .service()
-        .instance
-        .set_instance_online(msg.mac_addr, msg.source_ip)
+    let mut svc = state.service();
+    svc.instance
+        .set_instance_online(msg.mac_addr.clone(), msg.source_ip.clone(), msg.facts)
         .await?;
+    if let Err(e) = svc
+        .agent_config
+        .report_config(msg.mac_addr, msg.source_ip, msg.agent_config)
+        .await
+    {
+        error!("failed sync agent config, {e:?}");
+    }
 
     Ok(())
 }
@@ -43,6 +77,10 @@ async fn agent_online(state: AppState, msg: AgentOnlineParams) -> Result<()> {
             "start initialize runnable job on {}:{}",
             msg.agent_ip, msg.namespace,
         );
+        let expected = svc
+            .job
+            .get_expected_schedules(&msg.agent_ip, &msg.mac_addr)
+            .await;
         svc.job
             .fix_running_status(&msg.agent_ip, &msg.mac_addr)
             .await
@@ -62,6 +100,20 @@ async fn agent_online(state: AppState, msg: AgentOnlineParams) -> Result<()> {
                 e.to_string()
             );
         }
+
+        // the schedules restored by the agent are kept if the expected ones are unknown
+        match expected {
+            Ok(v) => {
+                if let Err(e) = svc
+                    .job
+                    .prune_agent_schedule(msg.agent_ip.clone(), msg.mac_addr.clone(), v)
+                    .await
+                {
+                    error!("failed prune agent schedule, {e:?}");
+                }
+            }
+            Err(e) => error!("failed get expected schedules, {e:?}"),
+        }
     }
 
     Ok(())
@@ -96,6 +148,283 @@ pub async fn check_health(state: AppState, is_master: Arc<RwLock<bool>>) {
     }
 }
 
+pub async fn rollup_dispatch_stat(state: AppState, is_master: Arc<RwLock<bool>>) {
+    let svc = state.service();
+    loop {
+        if *is_master.read().await {
+            let _ = svc
+                .job
+                .rollup_dispatch_stat()
+                .await
+                .context("failed rollup dispatch stat")
+                .map_err(|e| error!("{e:?}"));
+
+            sleep(Duration::from_secs(60)).await;
+        } else {
+            sleep(Duration::from_secs(1)).await;
+        }
+    }
+}
+
+pub async fn rollup_duration_stat(state: AppState, is_master: Arc<RwLock<bool>>) {
+    let svc = state.service();
+    loop {
+        if *is_master.read().await {
+            let _ = svc
+                .job
+                .rollup_duration_stat(&state.conf.duration_anomaly)
+                .await
+                .context("failed rollup duration stat")
+                .map_err(|e| error!("{e:?}"));
+
+            sleep(Duration::from_secs(60)).await;
+        } else {
+            sleep(Duration::from_secs(1)).await;
+        }
+    }
+}
+
+pub async fn rollup_daily_stat(state: AppState, is_master: Arc<RwLock<bool>>) {
+    let svc = state.service();
+    loop {
+        if *is_master.read().await {
+            let _ = svc
+                .job
+                .rollup_daily_stat()
+                .await
+                .context("failed rollup daily stat")
+                .map_err(|e| error!("{e:?}"));
+
+            sleep(Duration::from_secs(300)).await;
+        } else {
+            sleep(Duration::from_secs(1)).await;
+        }
+    }
+}
+
+/// runtime per team, job and instance for the usage reports
+pub async fn rollup_usage_stat(state: AppState, is_master: Arc<RwLock<bool>>) {
+    let svc = state.service();
+    loop {
+        if *is_master.read().await {
+            let _ = svc
+                .job
+                .rollup_usage_stat()
+                .await
+                .context("failed rollup usage stat")
+                .map_err(|e| error!("{e:?}"));
+
+            sleep(Duration::from_secs(300)).await;
+        } else {
+            sleep(Duration::from_secs(1)).await;
+        }
+    }
+}
+
+/// retry the dispatches deferred by the offline policy as the instances change their status
+pub async fn retry_offline_dispatch(state: AppState, is_master: Arc<RwLock<bool>>) {
+    let svc = state.service();
+    loop {
+        if *is_master.read().await {
+            let _ = svc
+                .job
+                .retry_offline_dispatch()
+                .await
+                .context("failed retry offline dispatch")
+                .map_err(|e| error!("{e:?}"));
+
+            sleep(Duration::from_secs(5)).await;
+        } else {
+            sleep(Duration::from_secs(1)).await;
+        }
+    }
+}
+
+pub async fn fail_lost_task(state: AppState, is_master: Arc<RwLock<bool>>) {
+    let svc = state.service();
+    loop {
+        if *is_master.read().await {
+            let _ = svc
+                .task
+                .fail_lost_task()
+                .await
+                .context("failed fail lost server task")
+                .map_err(|e| error!("{e:?}"));
+
+            sleep(Duration::from_secs(60)).await;
+        } else {
+            sleep(Duration::from_secs(1)).await;
+        }
+    }
+}
+
+/// permanently delete the resources kept in the recycle bin for longer than recycle_bin_days
+pub async fn purge_recycle_bin(state: AppState, is_master: Arc<RwLock<bool>>) {
+    let svc = state.service();
+    let days = state.conf.recycle_bin_days();
+    loop {
+        if days > 0 && *is_master.read().await {
+            let _ = svc
+                .job
+                .purge_expired_deleted(days)
+                .await
+                .context("failed purge recycle bin")
+                .map_err(|e| error!("{e:?}"));
+
+            sleep(Duration::from_secs(3600)).await;
+        } else {
+            sleep(Duration::from_secs(1)).await;
+        }
+    }
+}
+
+/// resource usage samples of the supervised jobs are kept for this many days
+const SUPERVISOR_METRIC_DAYS: u64 = 7;
+
+pub async fn purge_supervisor_metric(state: AppState, is_master: Arc<RwLock<bool>>) {
+    let svc = state.service();
+    loop {
+        if *is_master.read().await {
+            let _ = svc
+                .job
+                .purge_supervisor_metric(SUPERVISOR_METRIC_DAYS)
+                .await
+                .context("failed purge supervisor metric")
+                .map_err(|e| error!("{e:?}"));
+
+            sleep(Duration::from_secs(3600)).await;
+        } else {
+            sleep(Duration::from_secs(1)).await;
+        }
+    }
+}
+
+/// rotate the instance passwords older than the rotate_days of their credential
+pub async fn rotate_credential(state: AppState, is_master: Arc<RwLock<bool>>) {
+    let svc = state.service();
+    loop {
+        if *is_master.read().await {
+            let _ = svc
+                .credential
+                .rotate_due_credentials()
+                .await
+                .context("failed rotate credential")
+                .map_err(|e| error!("{e:?}"));
+
+            sleep(Duration::from_secs(3600)).await;
+        } else {
+            sleep(Duration::from_secs(1)).await;
+        }
+    }
+}
+
+/// deliver the scheduled reports whose run time passed
+pub async fn run_report(state: AppState, is_master: Arc<RwLock<bool>>) {
+    let svc = state.service();
+    loop {
+        if *is_master.read().await {
+            let _ = svc
+                .report
+                .run_due_reports()
+                .await
+                .context("failed run report")
+                .map_err(|e| error!("{e:?}"));
+
+            sleep(Duration::from_secs(60)).await;
+        } else {
+            sleep(Duration::from_secs(1)).await;
+        }
+    }
+}
+
+/// mark the breaches of the job slas
+pub async fn evaluate_sla(state: AppState, is_master: Arc<RwLock<bool>>) {
+    let svc = state.service();
+    loop {
+        if *is_master.read().await {
+            let _ = svc
+                .job
+                .evaluate_sla(&state.conf.sla)
+                .await
+                .context("failed evaluate sla")
+                .map_err(|e| error!("{e:?}"));
+
+            sleep(Duration::from_secs(60)).await;
+        } else {
+            sleep(Duration::from_secs(1)).await;
+        }
+    }
+}
+
+/// assign the new namespaces to the comet groups, the assigned ones are moved only by rebalance
+pub async fn assign_comet_group(state: AppState, is_master: Arc<RwLock<bool>>) {
+    let svc = state.service();
+    loop {
+        if *is_master.read().await {
+            match svc.comet_group.assign_namespaces().await {
+                Ok(moves) => moves.iter().for_each(|v| {
+                    info!(
+                        "namespace {} is assigned to comet group {}",
+                        v.namespace, v.to
+                    )
+                }),
+                Err(e) => error!("failed assign comet group - {e:?}"),
+            }
+            sleep(Duration::from_secs(60)).await;
+        } else {
+            sleep(Duration::from_secs(1)).await;
+        }
+    }
+}
+
+/// run the queued server tasks until shutdown, a running task is touched periodically
+/// so the leader can tell it from a task whose console died
+pub async fn run_task_worker(state: AppState, mut shutdown: watch::Receiver<bool>) {
+    let svc = state.service();
+    let worker = node_name(&state);
+    while !*shutdown.borrow() {
+        let record = match svc.task.claim_task(&worker).await {
+            Ok(Some(v)) => v,
+            Ok(None) => {
+                tokio::select! {
+                    _ = sleep(Duration::from_secs(1)) => continue,
+                    _ = shutdown.wait_for(|v| *v) => break,
+                }
+            }
+            Err(e) => {
+                error!("failed claim server task - {e:?}");
+                sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+
+        info!("start server task {}", record.task_id);
+        let run = svc.task.run_task(&record);
+        tokio::pin!(run);
+        let mut touch = tokio::time::interval(Duration::from_secs(TOUCH_INTERVAL_SECS));
+        touch.tick().await;
+        let ret = loop {
+            tokio::select! {
+                ret = &mut run => break ret,
+                _ = touch.tick() => {
+                    let _ = svc
+                        .task
+                        .touch_task(record.id)
+                        .await
+                        .map_err(|e| error!("failed touch server task - {e:?}"));
+                }
+            }
+        };
+
+        if let Err(e) = &ret {
+            error!("server task {} failed - {e:?}", record.task_id);
+        }
+        if let Err(e) = svc.task.finish_task(record.id, ret).await {
+            error!("failed finish server task {} - {e:?}", record.task_id);
+        }
+    }
+}
+
 pub async fn schedule_workflow(state: AppState, is_master: Arc<RwLock<bool>>) {
     let workflow_service = state.service().workflow;
 
@@ -145,32 +474,71 @@ pub async fn schedule_workflow(state: AppState, is_master: Arc<RwLock<bool>>) {
     }
 }
 
-pub async fn leader_process(state: AppState) {
+/// the background subsystems run only on the elected console, the leadership is
+/// released on shutdown so another console takes over without waiting for the ttl
+pub async fn leader_process(state: AppState, shutdown: watch::Receiver<bool>) {
     let is_master = Arc::new(RwLock::new(false));
-    let state_clone = state.clone();
     let is_master_clone = is_master.clone();
+
+    let mut election = LeaderElection::new(state.redis(), LEADER_ELECTION_KEY, LEADER_ELECTION_TTL)
+        .expect("failed initialize leader election")
+        .with_name(format!(
+            "{}({})",
+            automate::get_local_ip(),
+            state.conf.bind_addr
+        ))
+        .with_shutdown(shutdown);
+    let _ = NODE_ID.set(election.id().to_string());
+
     tokio::spawn(async move {
-        let mut l = LeaderElection::new(state_clone.redis(), "jiascheduler:leader_election", 10)
-            .expect("failed initialize leader election");
-
-        l.run_election(|ok| {
-            let is_master_clone = is_master_clone.clone();
-            Box::pin(async move {
-                info!("got leader election result {ok}");
-                let mut val = is_master_clone.write().await;
-                *val = ok;
-                ()
+        let ret = election
+            .run_election(|ok| {
+                let is_master_clone = is_master_clone.clone();
+                Box::pin(async move {
+                    info!("got leader election result {ok}");
+                    let mut val = is_master_clone.write().await;
+                    *val = ok;
+                })
             })
-        })
-        .await
-        .expect("failed run leader election");
+            .await;
+        match ret {
+            Ok(_) => info!("released leadership"),
+            Err(e) => error!("failed release leadership - {e}"),
+        }
     });
     tokio::spawn(check_health(state.clone(), is_master.clone()));
     tokio::spawn(schedule_workflow(state.clone(), is_master.clone()));
+    tokio::spawn(rollup_dispatch_stat(state.clone(), is_master.clone()));
+    tokio::spawn(rollup_duration_stat(state.clone(), is_master.clone()));
+    tokio::spawn(rollup_daily_stat(state.clone(), is_master.clone()));
+    tokio::spawn(rollup_usage_stat(state.clone(), is_master.clone()));
+    tokio::spawn(retry_offline_dispatch(state.clone(), is_master.clone()));
+    tokio::spawn(fail_lost_task(state.clone(), is_master.clone()));
+    tokio::spawn(purge_recycle_bin(state.clone(), is_master.clone()));
+    tokio::spawn(purge_supervisor_metric(state.clone(), is_master.clone()));
+    tokio::spawn(assign_comet_group(state.clone(), is_master.clone()));
+    tokio::spawn(rotate_credential(state.clone(), is_master.clone()));
+    tokio::spawn(run_report(state.clone(), is_master.clone()));
+    tokio::spawn(evaluate_sla(state.clone(), is_master.clone()));
 }
 
 pub async fn update_job_status(state: AppState, v: UpdateJobParams) -> Result<()> {
     let svc = state.service();
+    debug!(
+        request_id = v.request_id,
+        run_id = v.run_id,
+        "update job {} status",
+        v.base_job.eid
+    );
+
+    if v.base_job.eid.starts_with(INSTALL_EID_PREFIX)
+        || v.base_job.eid.starts_with(TEST_RUN_EID_PREFIX)
+        || v.base_job.eid.starts_with(ROTATE_EID_PREFIX)
+    {
+        // the output of the synchronous install scripts, test runs and password rotations
+        // is returned to their caller
+        return Ok(());
+    }
 
     if v.base_job.is_workflow {
         svc.workflow.update_node_status(v).await?;
@@ -180,14 +548,21 @@ pub async fn update_job_status(state: AppState, v: UpdateJobParams) -> Result<()
     Ok(())
 }
 
-pub async fn start(state: AppState) -> Result<()> {
-    let bus = Bus::new(state.redis().clone());
+/// start the background tasks, the returned handle completes after the pending
+/// job status updates are flushed once shutdown is set
+pub async fn start(state: AppState, shutdown: watch::Receiver<bool>) -> Result<JoinHandle<()>> {
+    let bus = Bus::from_url(state.conf.bus_url.as_deref(), state.redis().clone())?
+        .with_shutdown(shutdown.clone());
 
-    leader_process(state.clone()).await;
+    leader_process(state.clone(), shutdown.clone()).await;
+
+    for _ in 0..TASK_WORKERS {
+        tokio::spawn(run_task_worker(state.clone(), shutdown.clone()));
+    }
 
     // process job update msg
     let state_clone = state.clone();
-    tokio::spawn(async move {
+    let handle = tokio::spawn(async move {
         loop {
             let ret = bus
                 .recv(|_key, msg| {
@@ -207,12 +582,20 @@ pub async fn start(state: AppState) -> Result<()> {
                     })
                 })
                 .await;
+            if bus.is_shutdown() {
+                info!("flushed pending bus msg");
+                break;
+            }
             if let Err(e) = ret {
                 error!("failed to recv bus msg - {e}");
                 sleep(Duration::from_millis(500)).await;
             }
             info!("restart recv bus msg");
         }
+        if let Some(status_writer) = state_clone.status_writer.as_ref() {
+            status_writer.shutdown().await;
+            info!("flushed pending job status");
+        }
     });
 
     // process workflow node msg
@@ -236,5 +619,5 @@ pub async fn start(state: AppState) -> Result<()> {
         }
     });
 
-    Ok(())
+    Ok(handle)
 }
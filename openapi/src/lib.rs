@@ -3,26 +3,38 @@ pub mod response;
 
 use anyhow::{anyhow, Context, Result};
 use api::{
-    executor::ExecutorApi, file::FileApi, instance::InstanceApi, job::JobApi, manage::ManageApi,
-    migration::MigrationApi, role::RoleApi, tag::TagApi, team::TeamApi, terminal, user::UserApi,
-    workflow::WorkflowApi,
+    blackout::BlackoutApi, event, executor::ExecutorApi, file::FileApi, instance::InstanceApi,
+    job::JobApi, manage::ManageApi, migration::MigrationApi, promotion::PromotionApi,
+    report::ReportApi, role::RoleApi, sla::SlaApi, status_page, tag::TagApi, task::TaskApi,
+    team::TeamApi, terminal, trigger, user::UserApi, workflow::WorkflowApi, ApiVersion,
 };
 use casbin::{CoreApi, DefaultModel, Enforcer};
 
-use ::migration::{Migrator, MigratorTrait};
+use ::migration::{runner, Migrator, MigratorTrait};
 
-use logic::user::UserLogic;
-use middleware::AuthMiddleware;
-use poem::{get, IntoEndpoint};
-use service::config::Conf;
+use logic::{
+    role::{
+        POLICY_ALLOW_CHANGE_ALL_JOB, POLICY_ALLOW_MANAGE_ALL_INSTANCE, POLICY_ALLOW_SSH_INSTANCE,
+    },
+    user::{UserLogic, BREAK_GLASS_USER_ID},
+};
+use middleware::{
+    ApiVersionMiddleware, AuthMiddleware, I18nMiddleware, PermissionMiddleware, RequestIdMiddleware,
+};
+use poem::{get, post, IntoEndpoint};
+use service::config::{Conf, ReloadReport};
+use service::logic::job::StatusWriter;
 
 pub use error::custom_error;
 
 pub use openapi_derive::ApiStdResponse;
 use poem::{
     endpoint::{EmbeddedFileEndpoint, EmbeddedFilesEndpoint},
-    listener::TcpListener,
-    session::{CookieConfig, RedisStorage, ServerSession},
+    listener::{
+        acme::{AutoCert, ChallengeType, LETS_ENCRYPT_PRODUCTION, LETS_ENCRYPT_STAGING},
+        BoxListener, Listener, RustlsCertificate, RustlsConfig, TcpListener,
+    },
+    session::{CookieConfig, MemoryStorage, RedisStorage, ServerSession},
     EndpointExt, Route,
 };
 use poem_openapi::{ContactObject, OpenApiService};
@@ -31,17 +43,22 @@ use redis::{aio::ConnectionManager, Client};
 pub use entity;
 use git_version::git_version;
 use reqwest::header;
-use sea_orm::{ConnectOptions, Database, DatabaseConnection};
+use sea_orm::DatabaseConnection;
 use sea_orm_adapter::SeaOrmAdapter;
 use state::{AppContext, AppState};
 use std::{path::Path, time::Duration};
-use tokio::sync::{mpsc, oneshot::Sender};
-use tracing::info;
+use tokio::{
+    sync::{mpsc, oneshot::Sender, watch},
+    time::timeout,
+};
+use tracing::{error, info, warn};
 use url::Url;
 
 pub mod api;
 
 mod error;
+mod graphql;
+pub mod i18n;
 mod job;
 pub use service::logic;
 pub mod middleware;
@@ -50,16 +67,23 @@ mod migration;
 pub use service::state;
 pub mod utils;
 
+/// in-flight requests are waited up to this duration on shutdown
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+/// replicas wait up to this duration for another replica applying the migrations
+const MIGRATION_LOCK_TIMEOUT: Duration = Duration::from_secs(600);
+
 #[derive(Clone)]
 pub struct WebapiOptions {
     pub database_url: Option<String>,
     pub redis_url: Option<String>,
     pub bind_addr: Option<String>,
     pub config_file: String,
+    /// accept the break_glass account of the config file
+    pub break_glass: bool,
 }
 
 impl WebapiOptions {
-    fn merge_conf(&self, config_path: &str) -> Result<Conf> {
+    pub fn merge_conf(&self, config_path: &str) -> Result<Conf> {
         let real_path = shellexpand::full(config_path)?;
         let mut conf = Conf::parse(real_path.as_ref())?;
 
@@ -75,9 +99,30 @@ impl WebapiOptions {
             .bind_addr
             .iter()
             .map(|v| conf.bind_addr = v.to_string());
+        if !self.break_glass {
+            conf.break_glass = None;
+        }
 
+        let errors = conf.validate();
+        if !errors.is_empty() {
+            anyhow::bail!("invalid config {config_path}\n{}", errors.join("\n"));
+        }
         Ok(conf)
     }
+
+    /// validate the config file with the overrides, the unknown keys are reported as warnings
+    pub fn check_conf(&self) -> Result<()> {
+        let real_path = shellexpand::full(&self.config_file)?;
+        if !Path::new(real_path.as_ref()).exists() {
+            anyhow::bail!("config {real_path} is not found");
+        }
+        for key in Conf::unknown_keys(real_path.as_ref())? {
+            println!("warning: unknown key {key}");
+        }
+        self.merge_conf(&self.config_file)?;
+        println!("config {real_path} is valid");
+        Ok(())
+    }
 }
 
 use rust_embed::RustEmbed;
@@ -156,7 +201,8 @@ async fn install(opts: &WebapiOptions) -> Result<()> {
             Some(opts.config_file.to_string()),
         ))
         .data(AppState::Uninitialized)
-        .catch_all_error(custom_error);
+        .catch_all_error(custom_error)
+        .with(I18nMiddleware);
 
     poem::Server::new(TcpListener::bind(bind_addr))
         .run_with_graceful_shutdown(
@@ -170,9 +216,84 @@ async fn install(opts: &WebapiOptions) -> Result<()> {
     Ok(())
 }
 
+type Apis = (
+    UserApi,
+    TeamApi,
+    JobApi,
+    ExecutorApi,
+    InstanceApi,
+    FileApi,
+    RoleApi,
+    MigrationApi,
+    ManageApi,
+    TagApi,
+    WorkflowApi,
+    BlackoutApi,
+    TaskApi,
+    ReportApi,
+    PromotionApi,
+    SlaApi,
+);
+
+/// the same handlers are mounted under every version, the handlers branch on ApiVersion
+/// where the versions differ
+fn api_service(version: ApiVersion) -> OpenApiService<Apis, ()> {
+    OpenApiService::new(
+        (
+            UserApi,
+            TeamApi,
+            JobApi,
+            ExecutorApi,
+            InstanceApi,
+            FileApi,
+            RoleApi,
+            MigrationApi,
+            ManageApi,
+            TagApi,
+            WorkflowApi,
+            BlackoutApi,
+            TaskApi,
+            ReportApi,
+            PromotionApi,
+            SlaApi,
+        ),
+        "jiascheduler web api",
+        version.to_string(),
+    )
+    .summary("jiascheduler web api")
+    .description(
+        "A high-performance, scalable, dynamically configured job scheduler developed with rust",
+    )
+    .contact(
+        ContactObject::new()
+            .name("iwannay")
+            .url("https://github.com/iwannay")
+            .email("772648576@qq.com"),
+    )
+}
+
+/// apply the pending migrations, the replicas starting together wait for the one migrating
 pub async fn upgrade(conn: &DatabaseConnection) -> Result<()> {
-    if !Migrator::get_pending_migrations(conn).await?.is_empty() {
-        Migrator::up(conn, None).await?;
+    if Migrator::get_pending_migrations(conn).await?.is_empty() {
+        return Ok(());
+    }
+    let applied = runner::up_locked(conn, MIGRATION_LOCK_TIMEOUT).await?;
+    if !applied.is_empty() {
+        info!("applied migrations {applied:?}");
+    }
+    Ok(())
+}
+
+/// print the pending migrations and their sql without applying them
+pub async fn migrate_dry_run(opts: &WebapiOptions) -> Result<()> {
+    let conf = opts.merge_conf(&opts.config_file).context("merge config")?;
+    let conn = service::db::connect(&conf.database_url, &conf.db_pool).await?;
+    let pending = runner::pending(&conn).await?;
+    if pending.is_empty() {
+        println!("no pending migrations");
+    }
+    for v in pending {
+        println!("-- {}\n{}\n", v.name, v.sql.trim());
     }
     Ok(())
 }
@@ -185,13 +306,12 @@ pub async fn run(opts: WebapiOptions, signal: Option<Sender<Conf>>) -> Result<()
     }
 
     let conf = opts.merge_conf(&opts.config_file).context("merge config")?;
-    let mut connect_opts =
-        ConnectOptions::new(Url::parse(&conf.database_url).expect("database url"));
-    connect_opts
-        .sqlx_logging(false) // Disable SQLx log
-        .connect_timeout(Duration::from_secs(5)); // Set connect timeout
-
-    let conn = Database::connect(connect_opts.clone())
+    if let Some(ref level) = conf.log_level {
+        if let Err(e) = ::utils::set_log_level(level) {
+            error!("failed set log level {level} - {e}");
+        }
+    }
+    let conn = service::db::connect(&conf.database_url, &conf.db_pool)
         .await
         .expect("failed connect to database");
 
@@ -216,77 +336,136 @@ pub async fn run(opts: WebapiOptions, signal: Option<Sender<Conf>>) -> Result<()
     auth_value.set_sensitive(true);
     headers.insert(header::AUTHORIZATION, auth_value);
 
-    let ctx = AppContext::builder()
+    let mut http_client = reqwest::Client::builder().default_headers(headers);
+    if let Some(ref tls) = conf.comet_tls {
+        automate::set_tls_options(tls.clone()).context("load comet tls certificates")?;
+        http_client = tls.http_client(http_client)?;
+    }
+
+    let mut builder = AppContext::builder();
+    if conf.status_batch.batch_size > 0 {
+        let (status_writer, status_writer_task) =
+            StatusWriter::new(conn.clone(), &conf.status_batch);
+        tokio::spawn(status_writer_task.run());
+        builder = builder.status_writer(status_writer);
+    }
+    let ctx = builder
         .db(conn)
         .conf(conf.clone())
         .redis(client)
         .enforcer(e)
-        .rate_limit(30)
-        .http_client(
-            reqwest::Client::builder()
-                .default_headers(headers)
-                .build()?,
-        )
+        .rate_limit(conf.rate_limit_secs())
+        .http_client(http_client.build()?)
         .build()?;
     let state = AppState::Inner(ctx);
 
-    let api_service = OpenApiService::new(
-        (
-            UserApi,
-            TeamApi,
-            JobApi,
-            ExecutorApi,
-            InstanceApi,
-            FileApi,
-            RoleApi,
-            MigrationApi,
-            ManageApi,
-            TagApi,
-            WorkflowApi,
-        ),
-        "jiascheduler web api",
-        "1.0",
-    )
-    .summary("jiascheduler web api")
-    .description(
-        "A high-performance, scalable, dynamically configured job scheduler developed with rust",
-    )
-    .server("/api")
-    .contact(
-        ContactObject::new()
-            .name("iwannay")
-            .url("https://github.com/iwannay")
-            .email("772648576@qq.com"),
-    );
-
     state.service().user.load_user_role(&state).await?;
     state.init_admin_permission().await?;
+    if conf.break_glass.is_some() {
+        warn!("break-glass account is enabled");
+        state.set_role_for_user(BREAK_GLASS_USER_ID, "1").await?;
+    }
 
-    job::start(state.clone()).await?;
+    #[cfg(unix)]
+    tokio::spawn(reload_on_sighup(state.clone(), opts.clone()));
 
-    let ui = api_service.rapidoc();
-    let app = Route::new()
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let job_handle = job::start(state.clone(), shutdown_rx).await?;
+
+    let legacy_api = api_service(ApiVersion::V1).server("/api");
+    let ui = legacy_api.rapidoc();
+    let mut app = Route::new()
         .at("/", EmbeddedFileEndpoint::<Dist>::new("index.html"))
         .nest("/", EmbeddedFilesEndpoint::<Dist>::new())
         .at(
             "/terminal/webssh/:instance_id",
-            get(terminal::webssh).with(AuthMiddleware),
+            get(terminal::webssh)
+                .with(PermissionMiddleware::grant(
+                    &POLICY_ALLOW_MANAGE_ALL_INSTANCE,
+                ))
+                .with(PermissionMiddleware::new(&POLICY_ALLOW_SSH_INSTANCE))
+                .with(AuthMiddleware),
         )
         .at(
             "/terminal/tunnel/:instance_id",
-            get(terminal::proxy_webssh).with(AuthMiddleware),
+            get(terminal::proxy_webssh)
+                .with(PermissionMiddleware::grant(
+                    &POLICY_ALLOW_MANAGE_ALL_INSTANCE,
+                ))
+                .with(PermissionMiddleware::new(&POLICY_ALLOW_SSH_INSTANCE))
+                .with(AuthMiddleware),
         )
-        .nest("/api", api_service.with(AuthMiddleware))
-        .nest("/doc", ui)
-        .catch_all_error(custom_error)
-        .with(ServerSession::new(
-            CookieConfig::default()
-                .name("jiaschduler-sid")
-                .max_age(Some(Duration::from_secs(86400)))
-                .secure(false),
+        .at("/api/trigger/:token", post(trigger::trigger))
+        .at("/api/events", get(event::events).with(AuthMiddleware))
+        .at(
+            "/api/events/sse",
+            get(event::sse_events).with(AuthMiddleware),
+        )
+        .at(
+            "/api/events/poll",
+            get(event::poll_events).with(AuthMiddleware),
+        )
+        .at("/api/status-page/:token", get(status_page::public_status))
+        .at("/status/:token", get(status_page::public_status_html))
+        .at(
+            "/api/graphql",
+            get(graphql::graphiql)
+                .post(graphql::graphql)
+                .with(
+                    PermissionMiddleware::grant(&POLICY_ALLOW_CHANGE_ALL_JOB)
+                        .and(&POLICY_ALLOW_MANAGE_ALL_INSTANCE),
+                )
+                .with(middleware::TeamPermissionMiddleware)
+                .with(AuthMiddleware),
+        )
+        // compatibility shim, the unversioned path keeps serving v1 for the old clients
+        .nest(
+            "/api",
+            legacy_api
+                .with(ApiVersionMiddleware::new(ApiVersion::V1))
+                .with(AuthMiddleware),
+        )
+        .nest("/doc", ui);
+
+    for version in ApiVersion::ALL {
+        let service = api_service(version).server(version.prefix());
+        app = app
+            .at(format!("/openapi/{version}.json"), service.spec_endpoint())
+            .at(
+                format!("/openapi/{version}.yaml"),
+                service.spec_endpoint_yaml(),
+            )
+            .nest(
+                version.prefix(),
+                service
+                    .with(ApiVersionMiddleware::new(version))
+                    .with(AuthMiddleware),
+            );
+    }
+
+    let cookie_config = CookieConfig::default()
+        .name("jiaschduler-sid")
+        .max_age(Some(Duration::from_secs(86400)))
+        .secure(conf.tls.is_some());
+    let app = app.catch_all_error(custom_error);
+    // the break-glass account is used when redis may be broken, the sessions are kept in memory
+    // and are lost when the console restarts
+    let app = if conf.break_glass.is_some() {
+        app.with(ServerSession::new(cookie_config, MemoryStorage::new()))
+            .boxed()
+    } else {
+        app.with(ServerSession::new(
+            cookie_config,
             RedisStorage::new(ConnectionManager::new(state.redis()).await.unwrap()),
         ))
+        .boxed()
+    };
+    let app = app
+        .with(RequestIdMiddleware)
+        .with(I18nMiddleware)
         .data(state)
+        .data(opts.clone())
+        .data(graphql::schema())
         .data(InstallState::new(
             true,
             conf.bind_addr.clone(),
@@ -297,7 +476,100 @@ pub async fn run(opts: WebapiOptions, signal: Option<Sender<Conf>>) -> Result<()
         tx.send(conf.clone()).expect("failed send signal");
     }
 
-    Ok(poem::Server::new(TcpListener::bind(conf.bind_addr.clone()))
-        .run(app)
-        .await?)
+    poem::Server::new(listener(&conf)?)
+        .run_with_graceful_shutdown(
+            app,
+            async move {
+                automate::shutdown_signal().await;
+                info!("console is shutting down");
+            },
+            Some(SHUTDOWN_TIMEOUT),
+        )
+        .await?;
+
+    shutdown_tx.send_replace(true);
+    if timeout(SHUTDOWN_TIMEOUT, job_handle).await.is_err() {
+        error!("timeout waiting for pending job status updates");
+    }
+    Ok(())
+}
+
+/// re-read the config file and apply the reloadable fields
+pub async fn reload_conf(state: &AppState, opts: &WebapiOptions) -> Result<ReloadReport> {
+    let conf = opts.merge_conf(&opts.config_file).context("merge config")?;
+    let report = state.reload_conf(conf).await?;
+    info!(
+        "reloaded config, applied: {:?}, restart required: {:?}",
+        report.applied, report.restart_required
+    );
+    Ok(report)
+}
+
+#[cfg(unix)]
+async fn reload_on_sighup(state: AppState, opts: WebapiOptions) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = signal(SignalKind::hangup()).expect("failed install SIGHUP handler");
+    while hangup.recv().await.is_some() {
+        if let Err(e) = reload_conf(&state, &opts).await {
+            error!("failed reload config - {e:?}");
+        }
+    }
+}
+
+fn listener(conf: &Conf) -> Result<BoxListener> {
+    let listener = TcpListener::bind(conf.bind_addr.clone());
+    let Some(ref tls) = conf.tls else {
+        return Ok(listener.boxed());
+    };
+
+    if let Some(ref acme) = tls.acme {
+        let mut auto_cert = AutoCert::builder()
+            .directory_url(if acme.staging {
+                LETS_ENCRYPT_STAGING
+            } else {
+                LETS_ENCRYPT_PRODUCTION
+            })
+            .challenge_type(ChallengeType::TlsAlpn01)
+            .cache_path(shellexpand::full(&acme.cache_dir)?.to_string());
+        for domain in &acme.domains {
+            auto_cert = auto_cert.domain(domain);
+        }
+        return Ok(listener.acme(auto_cert.build()?).boxed());
+    }
+
+    let read = |file: &str| -> Result<Vec<u8>> {
+        let real_path = shellexpand::full(file)?.to_string();
+        std::fs::read(&real_path).with_context(|| format!("failed read {real_path}"))
+    };
+    Ok(listener
+        .rustls(
+            RustlsConfig::new().fallback(
+                RustlsCertificate::new()
+                    .cert(read(&tls.cert_file)?)
+                    .key(read(&tls.key_file)?),
+            ),
+        )
+        .boxed())
+}
+
+#[test]
+fn test_listener() {
+    let mut conf = Conf::default();
+    conf.bind_addr = "127.0.0.1:0".to_string();
+    assert!(listener(&conf).is_ok());
+
+    conf.tls = Some(service::config::Tls {
+        cert_file: "/nonexistent/jiascheduler/cert.pem".to_string(),
+        key_file: "/nonexistent/jiascheduler/key.pem".to_string(),
+        acme: None,
+    });
+    let err = listener(&conf).err().unwrap();
+    assert!(err
+        .to_string()
+        .starts_with("failed read /nonexistent/jiascheduler/cert.pem"));
+
+    let tls: service::config::Tls =
+        serde_json::from_value(serde_json::json!({"acme": {"domains": ["example.com"]}})).unwrap();
+    assert_eq!(tls.acme.unwrap().cache_dir, "~/.jiascheduler/acme");
 }
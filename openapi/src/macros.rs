@@ -11,27 +11,26 @@ macro_rules! return_ok {
         return Ok(poem_openapi::payload::Json(crate::response::StdResponse {
             code: 20000,
             data: Some($data),
-            msg: "success".to_string(),
+            msg: crate::i18n::t("success"),
         }))
     };
 }
 
+/// the message is translated into the language of the request
 #[macro_export]
 macro_rules! return_err {
     ($msg:expr) => {{
-        let mut e = poem::Error::from_string($msg, poem::http::StatusCode::OK);
+        let mut e = poem::Error::from_string($crate::i18n::t($msg), poem::http::StatusCode::OK);
         e.set_data(50001i32);
         return Err(e);
     }};
 }
 
-/// convert DateTime<Local> to local time(String)
+/// convert DateTime to the time(String) in the zone of the current user
 #[macro_export]
 macro_rules! local_time {
     ($time:expr) => {
-        $time
-            .with_timezone(&chrono::Local)
-            .naive_local()
+        utils::timezone::user_time(&$time)
             .format("%Y/%m/%d %H:%M:%S")
             .to_string()
     };
@@ -58,7 +57,7 @@ macro_rules! default_string {
 macro_rules! default_local_time {
     ($time:expr) => {
         $time.clone().map_or("".to_string(), |v| {
-            v.with_timezone(&chrono::Local).naive_local().to_string()
+            utils::timezone::user_time(&v).to_string()
         })
     };
 }
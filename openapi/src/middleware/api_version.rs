@@ -0,0 +1,65 @@
+use poem::{http::HeaderValue, Endpoint, IntoResponse, Middleware, Request, Response, Result};
+
+use crate::api::ApiVersion;
+
+pub const API_VERSION_HEADER: &str = "X-Api-Version";
+
+/// attach the version of the mounted api to the request and the response
+pub struct ApiVersionMiddleware {
+    version: ApiVersion,
+}
+
+impl ApiVersionMiddleware {
+    pub fn new(version: ApiVersion) -> Self {
+        Self { version }
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for ApiVersionMiddleware {
+    type Output = ApiVersionMiddlewareEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        ApiVersionMiddlewareEndpoint {
+            ep,
+            version: self.version,
+        }
+    }
+}
+
+pub struct ApiVersionMiddlewareEndpoint<E> {
+    ep: E,
+    version: ApiVersion,
+}
+
+impl<E> Endpoint for ApiVersionMiddlewareEndpoint<E>
+where
+    E: Endpoint,
+{
+    type Output = Response;
+
+    async fn call(&self, mut req: Request) -> Result<Self::Output> {
+        req.extensions_mut().insert(self.version);
+        let mut resp = self.ep.call(req).await?.into_response();
+        if let Ok(v) = HeaderValue::from_str(&self.version.to_string()) {
+            resp.headers_mut().insert(API_VERSION_HEADER, v);
+        }
+        Ok(resp)
+    }
+}
+
+#[tokio::test]
+async fn test_api_version_middleware() {
+    use poem::{endpoint::make, EndpointExt};
+
+    let ep = make(|req: Request| async move {
+        req.extensions()
+            .get::<ApiVersion>()
+            .map(|v| v.prefix())
+            .unwrap_or_default()
+    })
+    .with(ApiVersionMiddleware::new(ApiVersion::V2));
+
+    let resp = ep.call(Request::default()).await.unwrap();
+    assert_eq!(resp.headers().get(API_VERSION_HEADER).unwrap(), "v2");
+    assert_eq!(resp.into_body().into_string().await.unwrap(), "/api/v2");
+}
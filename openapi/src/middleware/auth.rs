@@ -1,7 +1,71 @@
-use crate::logic::{types, user::UserLogic};
+use crate::{
+    local_time,
+    logic::{types, user::UserLogic},
+    AppState,
+};
 use poem::{
-    session::Session, web::Json, Endpoint, IntoResponse, Middleware, Request, Response, Result,
+    http::header::AUTHORIZATION, session::Session, web::Json, Endpoint, IntoResponse, Middleware,
+    Request, Response, Result,
 };
+use tracing::warn;
+
+/// the token in the Authorization header, eg: "Bearer <token>"
+fn bearer_token(req: &Request) -> Option<&str> {
+    req.headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .filter(|v| !v.is_empty())
+}
+
+/// the user of the api token or the emergency token in the Authorization header
+async fn token_user(req: &Request) -> anyhow::Result<Option<types::UserInfo>> {
+    let Some(secret) = bearer_token(req) else {
+        return Ok(None);
+    };
+    let Some(state) = req.data::<AppState>() else {
+        return Ok(None);
+    };
+    let svc = state.service();
+    let record = match svc.user.get_user_by_api_token(secret).await {
+        Ok(Some(v)) => Some(v),
+        Ok(None) => svc.user.get_user_by_emergency_token(secret).await?,
+        Err(e) => {
+            // the emergency tokens are kept in the database to work without redis
+            warn!("failed get api token, fallback to emergency token - {e}");
+            svc.user.get_user_by_emergency_token(secret).await?
+        }
+    };
+    let Some(record) = record else {
+        return Ok(None);
+    };
+
+    let permissions = state.get_permissions_for_user(&record.user_id).await?;
+    Ok(Some(types::UserInfo {
+        username: record.username,
+        nickname: record.nickname,
+        avatar: record.avatar,
+        email: record.email,
+        role_id: record.role_id,
+        is_root: record.is_root,
+        introduction: record.introduction,
+        phone: record.phone,
+        created_time: local_time!(record.created_time),
+        updated_time: local_time!(record.updated_time),
+        user_id: record.user_id,
+        gender: record.gender,
+        permissions,
+        role: record.role.unwrap_or_default(),
+        timezone: record.timezone,
+        // the tokens are not bound to the password
+        password_expired: false,
+    }))
+}
+
+/// the users whose password expired can only change it
+fn allowed_with_expired_password(path: &str) -> bool {
+    ["/user/update-info", "/user/info", "/user/logout"].contains(&path)
+}
 
 pub struct AuthMiddleware;
 
@@ -33,8 +97,24 @@ where
 
         let sess: &Session = req.extensions().get().expect("not init session");
 
-        if let Some(user_info) = sess.get::<types::UserInfo>(UserLogic::SESS_KEY) {
+        let user_info = match sess.get::<types::UserInfo>(UserLogic::SESS_KEY) {
+            Some(v) => Some(v),
+            None => token_user(&req).await?,
+        };
+
+        if let Some(user_info) = user_info {
+            if user_info.password_expired && !allowed_with_expired_password(req.uri().path()) {
+                return Ok(Json(serde_json::json! ({
+                    "code": 50402,
+                    "msg": "password expired, please change the password first",
+                }))
+                .into_response());
+            }
+            let timezone = user_info.timezone.clone();
             req.extensions_mut().insert(user_info);
+            return utils::timezone::with_timezone(&timezone, self.ep.call(req))
+                .await
+                .map(IntoResponse::into_response);
         } else {
             if vec!["/user/login", "/user/logout", "/migration/version/check"]
                 .contains(&req.uri().path())
@@ -43,6 +123,43 @@ where
             }
             return Ok(login_resp);
         }
-        self.ep.call(req).await.map(IntoResponse::into_response)
     }
 }
+
+#[test]
+fn test_bearer_token() {
+    let token = |v: &str| {
+        let req = Request::builder().header(AUTHORIZATION, v).finish();
+        bearer_token(&req).map(|v| v.to_string())
+    };
+    assert_eq!(token("Bearer abc"), Some("abc".to_string()));
+    assert_eq!(token("Bearer "), None);
+    assert_eq!(token("Basic abc"), None);
+    assert_eq!(bearer_token(&Request::default()), None);
+}
+
+#[test]
+fn test_allowed_with_expired_password() {
+    assert!(allowed_with_expired_password("/user/update-info"));
+    assert!(allowed_with_expired_password("/user/logout"));
+    assert!(!allowed_with_expired_password("/job/list"));
+
+    let user_info: types::UserInfo = serde_json::from_value(serde_json::json!({
+        "username": "admin",
+        "nickname": "admin",
+        "avatar": "",
+        "email": "",
+        "role_id": 1,
+        "is_root": true,
+        "introduction": "",
+        "phone": "",
+        "created_time": "",
+        "updated_time": "",
+        "user_id": "u-1",
+        "gender": "male",
+        "permissions": [],
+        "role": "admin",
+    }))
+    .unwrap();
+    assert!(!user_info.password_expired);
+}
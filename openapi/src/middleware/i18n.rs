@@ -0,0 +1,35 @@
+use poem::{http::header::ACCEPT_LANGUAGE, Endpoint, Middleware, Request, Result};
+
+use crate::i18n::{with_lang, Lang};
+
+/// negotiate the language of the messages from Accept-Language
+pub struct I18nMiddleware;
+
+impl<E: Endpoint> Middleware<E> for I18nMiddleware {
+    type Output = I18nMiddlewareEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        I18nMiddlewareEndpoint { ep }
+    }
+}
+
+pub struct I18nMiddlewareEndpoint<E> {
+    ep: E,
+}
+
+impl<E> Endpoint for I18nMiddlewareEndpoint<E>
+where
+    E: Endpoint,
+{
+    type Output = E::Output;
+
+    async fn call(&self, req: Request) -> Result<Self::Output> {
+        let lang = req
+            .headers()
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|v| v.to_str().ok())
+            .map(Lang::from_accept_language)
+            .unwrap_or_default();
+        with_lang(lang, self.ep.call(req)).await
+    }
+}
@@ -1,4 +1,12 @@
+mod api_version;
 mod auth;
+mod i18n;
+mod permission;
+mod request_id;
 mod team_permission;
+pub use api_version::ApiVersionMiddleware;
 pub use auth::AuthMiddleware;
+pub use i18n::I18nMiddleware;
+pub use permission::{Granted, PermissionMiddleware};
+pub use request_id::RequestIdMiddleware;
 pub use team_permission::TeamPermissionMiddleware;
@@ -0,0 +1,136 @@
+use crate::{
+    logic::{
+        self,
+        role::{POLICY_ALLOW_CHANGE_ALL_JOB, POLICY_ALLOW_MANAGE_ALL_INSTANCE},
+        types::Permission,
+    },
+    state::AppState,
+};
+use poem::{web::Json, Endpoint, IntoResponse, Middleware, Request, Response, Result};
+
+/// require the casbin permissions of the route group, eg: job dispatch, instance ssh,
+/// or only grant them, the handlers read the ones the user holds from Granted
+pub struct PermissionMiddleware {
+    permissions: Vec<&'static Permission>,
+    required: bool,
+}
+
+impl PermissionMiddleware {
+    pub fn new(permission: &'static Permission) -> Self {
+        Self {
+            permissions: vec![permission],
+            required: true,
+        }
+    }
+
+    /// never rejects, the handlers scope the data by the granted permissions,
+    /// eg: the users managing all jobs see the jobs of everyone
+    pub fn grant(permission: &'static Permission) -> Self {
+        Self {
+            permissions: vec![permission],
+            required: false,
+        }
+    }
+
+    pub fn and(mut self, permission: &'static Permission) -> Self {
+        self.permissions.push(permission);
+        self
+    }
+}
+
+/// the permissions of the route groups the user holds
+#[derive(Clone, Default)]
+pub struct Granted(Vec<&'static Permission>);
+
+impl Granted {
+    pub fn contains(&self, permission: &Permission) -> bool {
+        self.0
+            .iter()
+            .any(|v| v.object == permission.object && v.action == permission.action)
+    }
+
+    pub fn manage_job(&self) -> bool {
+        self.contains(&POLICY_ALLOW_CHANGE_ALL_JOB)
+    }
+
+    pub fn manage_instance(&self) -> bool {
+        self.contains(&POLICY_ALLOW_MANAGE_ALL_INSTANCE)
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for PermissionMiddleware {
+    type Output = PermissionMiddlewareEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        PermissionMiddlewareEndpoint {
+            ep,
+            permissions: self.permissions.clone(),
+            required: self.required,
+        }
+    }
+}
+
+pub struct PermissionMiddlewareEndpoint<E> {
+    ep: E,
+    permissions: Vec<&'static Permission>,
+    required: bool,
+}
+
+impl<E> Endpoint for PermissionMiddlewareEndpoint<E>
+where
+    E: Endpoint,
+{
+    type Output = Response;
+
+    async fn call(&self, mut req: Request) -> Result<Self::Output> {
+        let user_info: &logic::types::UserInfo =
+            req.extensions().get().expect("not init user info");
+        let state: &AppState = req.extensions().get().expect("not init state");
+        let mut granted: Granted = req.extensions().get().cloned().unwrap_or_default();
+
+        if self.required {
+            let ok = state
+                .check_permissions(&user_info.user_id, self.permissions.clone())
+                .await?;
+            if !ok {
+                let keys = self
+                    .permissions
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<String>>();
+                return Ok(Json(serde_json::json! ({
+                    "code": 50403,
+                    "msg": format!("No permission, require {}", keys.join(",")),
+                }))
+                .into_response());
+            }
+            granted.0.extend(self.permissions.iter().copied());
+        } else {
+            for permission in self.permissions.iter().copied() {
+                if state
+                    .check_permissions(&user_info.user_id, vec![permission])
+                    .await?
+                {
+                    granted.0.push(permission);
+                }
+            }
+        }
+
+        req.extensions_mut().insert(granted);
+        self.ep.call(req).await.map(IntoResponse::into_response)
+    }
+}
+
+#[test]
+fn test_granted() {
+    use crate::logic::role::POLICY_ALLOW_DISPATCH_JOB;
+
+    let granted = Granted(vec![
+        &POLICY_ALLOW_CHANGE_ALL_JOB,
+        &POLICY_ALLOW_DISPATCH_JOB,
+    ]);
+    assert!(granted.manage_job());
+    assert!(!granted.manage_instance());
+    assert!(granted.contains(&POLICY_ALLOW_DISPATCH_JOB));
+    assert!(!Granted::default().manage_job());
+}
@@ -0,0 +1,74 @@
+use poem::{http::HeaderValue, Endpoint, IntoResponse, Middleware, Request, Response, Result};
+use tracing::Instrument;
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// take the request id from X-Request-Id or generate one, it is attached to the logs
+/// and the jobs dispatched while handling the request
+pub struct RequestIdMiddleware;
+
+impl<E: Endpoint> Middleware<E> for RequestIdMiddleware {
+    type Output = RequestIdMiddlewareEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        RequestIdMiddlewareEndpoint { ep }
+    }
+}
+
+fn request_id(req: &Request) -> String {
+    req.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty() && v.len() <= 64)
+        .map(|v| v.to_string())
+        .unwrap_or_else(|| nanoid::nanoid!())
+}
+
+pub struct RequestIdMiddlewareEndpoint<E> {
+    ep: E,
+}
+
+impl<E> Endpoint for RequestIdMiddlewareEndpoint<E>
+where
+    E: Endpoint,
+{
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> Result<Self::Output> {
+        let request_id = request_id(&req);
+
+        let span =
+            tracing::info_span!("request", request_id = %request_id, path = %req.uri().path());
+        let resp = utils::with_request_id(request_id.clone(), self.ep.call(req))
+            .instrument(span)
+            .await;
+
+        let mut resp = match resp {
+            Ok(v) => v.into_response(),
+            Err(e) => e.into_response(),
+        };
+        if let Ok(v) = HeaderValue::from_str(&request_id) {
+            resp.headers_mut().insert(REQUEST_ID_HEADER, v);
+        }
+        Ok(resp)
+    }
+}
+
+#[test]
+fn test_request_id() {
+    let req = Request::builder()
+        .header(REQUEST_ID_HEADER, "abc-123")
+        .finish();
+    assert_eq!(request_id(&req), "abc-123");
+
+    let req = Request::builder().header(REQUEST_ID_HEADER, "").finish();
+    assert_eq!(request_id(&req).len(), 21);
+
+    let req = Request::builder()
+        .header(REQUEST_ID_HEADER, "a".repeat(65))
+        .finish();
+    assert_eq!(request_id(&req).len(), 21);
+
+    let req = Request::builder().finish();
+    assert_ne!(request_id(&req), request_id(&req));
+}
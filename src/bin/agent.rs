@@ -3,9 +3,13 @@ use clap::Parser;
 
 use tracing::error;
 
-use automate::scheduler::{
-    Scheduler,
-    types::{AssignUserOption, SshConnectionOption},
+use automate::{
+    bridge::msg::AgentConfig,
+    scheduler::{
+        Scheduler, agent_config, metrics,
+        types::{AssignUserOption, SshConnectionOption},
+    },
+    tls::TlsOptions,
 };
 
 #[derive(Parser, Debug)]
@@ -15,6 +19,7 @@ use automate::scheduler::{
     version
 )]
 struct AgentArgs {
+    /// Address of the health server serving /healthz and /metrics
     #[arg(short, long, default_value_t = String::from("0.0.0.0:3001"))]
     bind: String,
     #[arg(long, default_values_t = vec![String::from("ws://127.0.0.1:3000")])]
@@ -22,8 +27,26 @@ struct AgentArgs {
     /// Directory for saving job execution logs
     #[arg(long, default_value_t = String::from("./log"))]
     output_dir: String,
+    /// Max size in MiB of the cache of the upload files pulled from comet
+    #[arg(long, default_value_t = 1024)]
+    artifact_cache_size: u64,
     #[arg(long, default_value_t = String::from("rYzBYE+cXbtdMg=="))]
     comet_secret: String,
+    /// Bootstrap token generated in the console, exchanged for a credential used instead of comet_secret
+    #[arg(long)]
+    bootstrap_token: Option<String>,
+    /// File to save the credential exchanged by the bootstrap token
+    #[arg(long, default_value_t = String::from("./agent.credential"))]
+    credential_file: String,
+    /// CA certificate used to verify comet, connect with wss:// comet_addr to enable mTLS
+    #[arg(long)]
+    tls_ca: Option<String>,
+    /// Client certificate of this agent
+    #[arg(long)]
+    tls_cert: Option<String>,
+    /// Private key of the client certificate
+    #[arg(long)]
+    tls_key: Option<String>,
     #[arg(short, long, default_value_t = String::from("default"))]
     namespace: String,
     /// Set the login user of the instance for SSH remote connection
@@ -46,24 +69,65 @@ struct AgentArgs {
     /// Set log level, eg: "trace", "debug", "info", "warn", "error" etc.
     #[arg(long, default_value_t = String::from("error"))]
     log_level: String,
+    /// Output logs as JSON lines
+    #[arg(long)]
+    log_json: bool,
+    /// Number of the recent log lines kept in memory for the console, 0 to disable
+    #[arg(long, default_value_t = 2000)]
+    log_buffer_lines: usize,
+    /// Seconds between the heartbeats
+    #[arg(long, default_value_t = agent_config::DEFAULT_HEARTBEAT_INTERVAL)]
+    heartbeat_interval: u64,
+    /// File to save the config pushed by the console, which overrides the flags on restart
+    #[arg(long, default_value_t = String::from("./agent.config.json"))]
+    config_file: String,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = AgentArgs::parse();
-    unsafe {
-        std::env::set_var("RUST_LOG", args.log_level);
+    let config = agent_config::init(
+        args.config_file,
+        AgentConfig {
+            output_dir: Some(args.output_dir),
+            log_level: Some(args.log_level),
+            heartbeat_interval: Some(args.heartbeat_interval),
+            comet_addr: Some(args.comet_addr),
+        },
+    )?;
+    utils::init_tracing_with_buffer(
+        &config.log_level.unwrap_or_default(),
+        args.log_json,
+        args.log_buffer_lines,
+    )?;
+
+    if let Some(tls) = TlsOptions::build(args.tls_ca, args.tls_cert, args.tls_key)? {
+        automate::set_tls_options(tls)?;
     }
-    tracing_subscriber::fmt::init();
 
     let mut scheduler = Scheduler::new(
         args.namespace,
-        args.comet_addr,
+        config.comet_addr.unwrap_or_default(),
         args.comet_secret,
-        args.output_dir,
+        config.output_dir.unwrap_or_default(),
         SshConnectionOption::build(args.ssh_user, args.ssh_password, args.ssh_port),
         AssignUserOption::build(args.assign_username, args.assign_password),
-    );
+    )
+    .with_agent_version(env!("CARGO_PKG_VERSION"))
+    .with_artifact_cache_size(args.artifact_cache_size * 1024 * 1024);
+
+    if let Some(token) = args.bootstrap_token {
+        scheduler
+            .exchange_bootstrap_token(token, args.credential_file)
+            .await?;
+    }
+
+    let bind = args.bind;
+    tokio::spawn(async move {
+        if let Err(e) = metrics::serve(bind.clone()).await {
+            error!("failed serve health server on {bind} - {e}");
+        }
+    });
 
     if let Err(e) = scheduler.connect_comet().await {
         error!("failed connect to comet - {e}");
@@ -0,0 +1,72 @@
+use anyhow::Result;
+use automate::tls;
+use clap::{Parser, Subcommand};
+
+/// Generate the CA and issue certificates for mTLS between agent, comet and console
+#[derive(Parser, Debug)]
+#[command(
+    author = "iwannay <772648576@qq.com>",
+    about = "Generate the CA and issue certificates for mTLS between agent, comet and console",
+    version
+)]
+struct CertArgs {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Generate a self-signed CA, saved as ca.crt and ca.key
+    Ca {
+        #[arg(long, default_value_t = String::from("jiascheduler ca"))]
+        common_name: String,
+        /// Validity in days
+        #[arg(long, default_value_t = 3650)]
+        days: i64,
+        #[arg(long, default_value_t = String::from("./certs"))]
+        out_dir: String,
+    },
+    /// Issue a certificate signed by the CA, saved as <name>.crt and <name>.key.
+    /// The certificate can be used by comet as server certificate and by agent or console as client certificate
+    Issue {
+        /// Common name of the certificate, also used as file name, eg: comet, agent, console
+        #[arg(long)]
+        name: String,
+        /// Hostnames or IPs the certificate is valid for, required for comet, eg: --host 192.168.1.10 --host comet.local
+        #[arg(long)]
+        host: Vec<String>,
+        #[arg(long, default_value_t = String::from("./certs/ca.crt"))]
+        ca_cert: String,
+        #[arg(long, default_value_t = String::from("./certs/ca.key"))]
+        ca_key: String,
+        /// Validity in days
+        #[arg(long, default_value_t = 365)]
+        days: i64,
+        #[arg(long, default_value_t = String::from("./certs"))]
+        out_dir: String,
+    },
+}
+
+fn main() -> Result<()> {
+    let args = CertArgs::parse();
+
+    let (cert_file, key_file) = match args.command {
+        Command::Ca {
+            common_name,
+            days,
+            out_dir,
+        } => tls::generate_ca(&common_name, days, &out_dir)?,
+        Command::Issue {
+            name,
+            host,
+            ca_cert,
+            ca_key,
+            days,
+            out_dir,
+        } => tls::issue_cert(&ca_cert, &ca_key, &name, host, days, &out_dir)?,
+    };
+
+    println!("certificate: {cert_file}");
+    println!("private key: {key_file}");
+    Ok(())
+}
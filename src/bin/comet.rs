@@ -1,5 +1,8 @@
 use anyhow::Result;
-use automate::comet::{self, CometOptions};
+use automate::{
+    comet::{self, CometOptions},
+    tls::TlsOptions,
+};
 use clap::Parser;
 
 #[derive(Parser, Debug)]
@@ -18,26 +21,48 @@ struct CometArgs {
     redis_url: String,
     #[arg(long, default_value_t = String::from("rYzBYE+cXbtdMg=="))]
     secret: String,
+    /// Bus which agent events are sent to, eg: nats://127.0.0.1:4222, defaults to redis_url
+    #[arg(long)]
+    bus_url: Option<String>,
+    /// Comet group defined in the console config, agents of the namespaces assigned to other groups are refused
+    #[arg(long)]
+    group: Option<String>,
+    /// Refuse the comet secret from agents, they must connect with a credential exchanged by a bootstrap token
+    #[arg(long)]
+    require_agent_credential: bool,
+
+    /// CA certificate used to verify the client certificates of agents and console, enable mTLS together with tls_cert and tls_key
+    #[arg(long)]
+    tls_ca: Option<String>,
+    /// Server certificate of comet
+    #[arg(long)]
+    tls_cert: Option<String>,
+    /// Private key of the server certificate
+    #[arg(long)]
+    tls_key: Option<String>,
 
     /// Set log level, eg: "trace", "debug", "info", "warn", "error" etc.
     #[arg(long, default_value_t = String::from("error"))]
     log_level: String,
+    /// Output logs as JSON lines
+    #[arg(long)]
+    log_json: bool,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = CometArgs::parse();
-    unsafe {
-        std::env::set_var("RUST_LOG", args.log_level);
-    }
-
-    tracing_subscriber::fmt::init();
+    utils::init_tracing(&args.log_level, args.log_json)?;
 
     comet::run(
         CometOptions {
             redis_url: args.redis_url,
             bind_addr: args.bind,
             secret: args.secret,
+            tls: TlsOptions::build(args.tls_ca, args.tls_cert, args.tls_key)?,
+            bus_url: args.bus_url,
+            group: args.group,
+            require_agent_credential: args.require_agent_credential,
         },
         None,
     )
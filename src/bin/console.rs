@@ -20,6 +20,9 @@ struct WebapiArgs {
     /// Set log level, eg: "trace", "debug", "info", "warn", "error" etc.
     #[arg(long, default_value_t = String::from("error"))]
     log_level: String,
+    /// Output logs as JSON lines
+    #[arg(long)]
+    log_json: bool,
 
     /// where to read config file,
     /// you can temporarily overwrite the configuration file using command-line parameters
@@ -33,25 +36,38 @@ struct WebapiArgs {
     /// can be used to override configuration items in the configuration file
     #[arg(long)]
     database_url: Option<String>,
+    /// Accept the break_glass account of the configuration file,
+    /// which logs in as administrator without the user table,
+    /// the sessions are kept in memory instead of redis
+    #[arg(long)]
+    break_glass: bool,
+    /// Print the pending database migrations and their sql, then exit without applying them
+    #[arg(long)]
+    migrate_dry_run: bool,
+    /// Validate the configuration file with the overrides of command-line and
+    /// environment variables (eg: JIASCHEDULER__DATABASE_URL), then exit
+    #[arg(long)]
+    check_config: bool,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = WebapiArgs::parse();
-    unsafe {
-        std::env::set_var("RUST_LOG", args.log_level);
-    }
+    utils::init_tracing(&args.log_level, args.log_json)?;
 
-    tracing_subscriber::fmt::init();
+    let opts = WebapiOptions {
+        database_url: args.database_url,
+        redis_url: args.redis_url,
+        config_file: args.config,
+        bind_addr: args.bind_addr,
+        break_glass: args.break_glass,
+    };
+    if args.check_config {
+        return opts.check_conf();
+    }
+    if args.migrate_dry_run {
+        return openapi::migrate_dry_run(&opts).await;
+    }
 
-    openapi::run(
-        WebapiOptions {
-            database_url: args.database_url,
-            redis_url: args.redis_url,
-            config_file: args.config,
-            bind_addr: args.bind_addr,
-        },
-        None,
-    )
-    .await
+    openapi::run(opts, None).await
 }
@@ -0,0 +1,563 @@
+use std::time::Duration;
+
+use anyhow::{Result, anyhow, bail};
+use clap::{Args, Parser, Subcommand};
+use reqwest::header;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+
+/// Command line client of the jiascheduler console, authorized by an api token
+/// created in the console, eg: POST /api/user/api-token/create
+#[derive(Parser, Debug)]
+#[command(
+    author = "iwannay <772648576@qq.com>",
+    about = "Command line client of the jiascheduler console",
+    version
+)]
+struct CtlArgs {
+    /// Console address, eg: "http://127.0.0.1:9090"
+    #[arg(long, env = "JIASCHEDULER_SERVER", default_value_t = String::from("http://127.0.0.1:9090"))]
+    server: String,
+    /// Api token of the user
+    #[arg(long, env = "JIASCHEDULER_TOKEN", hide_env_values = true)]
+    token: String,
+    /// Act in the team, only the resources of the team are visible
+    #[arg(long, env = "JIASCHEDULER_TEAM_ID")]
+    team_id: Option<u64>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Manage jobs
+    #[command(subcommand)]
+    Job(JobCommand),
+    /// Dispatch a job to the instances matched by the selectors
+    Dispatch(DispatchArgs),
+    /// Show the run status of the dispatched jobs
+    Status {
+        /// once, timer, flow or daemon
+        #[arg(long, default_value_t = String::from("once"))]
+        schedule_type: String,
+        /// default, bundle or http
+        #[arg(long, default_value_t = String::from("default"))]
+        job_type: String,
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(long)]
+        ip: Option<String>,
+        /// Refresh until interrupted
+        #[arg(short, long)]
+        watch: bool,
+        /// Refresh interval in seconds
+        #[arg(long, default_value_t = 3)]
+        interval: u64,
+    },
+    /// Print the output of the latest runs of a job
+    Logs {
+        #[arg(long)]
+        eid: String,
+        #[arg(long)]
+        ip: Option<String>,
+        /// default, bundle or http
+        #[arg(long, default_value_t = String::from("default"))]
+        job_type: String,
+        /// Number of the latest runs to print
+        #[arg(short = 'n', long, default_value_t = 1)]
+        tail: u64,
+        /// Wait for new runs and print them
+        #[arg(short, long)]
+        follow: bool,
+        /// Poll interval in seconds
+        #[arg(long, default_value_t = 3)]
+        interval: u64,
+    },
+    /// Manage instances
+    #[command(subcommand)]
+    Instance(InstanceCommand),
+}
+
+#[derive(Subcommand, Debug)]
+enum JobCommand {
+    /// List jobs
+    List {
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(long, default_value_t = 1)]
+        page: u64,
+        #[arg(long, default_value_t = 20)]
+        page_size: u64,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum InstanceCommand {
+    /// List instances
+    List {
+        #[arg(long)]
+        ip: Option<String>,
+        /// 1 online, 0 offline
+        #[arg(long)]
+        status: Option<u8>,
+        #[arg(long, default_value_t = 1)]
+        page: u64,
+        #[arg(long, default_value_t = 20)]
+        page_size: u64,
+    },
+    /// Enable the instances, jobs can be dispatched to them
+    Enable {
+        #[arg(long, required = true)]
+        id: Vec<u64>,
+    },
+    /// Disable the instances
+    Disable {
+        #[arg(long, required = true)]
+        id: Vec<u64>,
+    },
+}
+
+#[derive(Args, Debug)]
+struct DispatchArgs {
+    #[arg(long)]
+    eid: String,
+    /// Select instances by ip, can be repeated
+    #[arg(long)]
+    ip: Vec<String>,
+    /// Select instances by instance id, can be repeated
+    #[arg(long)]
+    instance_id: Vec<String>,
+    /// Select instances by operating system, eg: linux
+    #[arg(long)]
+    os: Option<String>,
+    /// Select all the online instances
+    #[arg(long)]
+    all: bool,
+    /// exec, kill, start_timer, stop_timer, start_supervising or stop_supervising
+    #[arg(long, default_value_t = String::from("exec"))]
+    action: String,
+    /// once, timer, flow or daemon
+    #[arg(long, default_value_t = String::from("once"))]
+    schedule_type: String,
+    /// Name of the schedule, defaults to the eid and time
+    #[arg(long)]
+    name: Option<String>,
+    /// Actual args of the job in JSON, eg: '{"env":"prod"}'
+    #[arg(long)]
+    args: Option<String>,
+    /// Required when dispatching inside a blackout window
+    #[arg(long)]
+    override_reason: Option<String>,
+    /// Wait for the job to finish on the instances
+    #[arg(long)]
+    sync: bool,
+}
+
+struct Client {
+    http: reqwest::Client,
+    server: String,
+}
+
+impl Client {
+    const API_PREFIX: &'static str = "/api/v1";
+
+    fn new(args: &CtlArgs) -> Result<Self> {
+        let mut headers = header::HeaderMap::new();
+        let mut auth_value = header::HeaderValue::from_str(&format!("Bearer {}", args.token))?;
+        auth_value.set_sensitive(true);
+        headers.insert(header::AUTHORIZATION, auth_value);
+        if let Some(team_id) = args.team_id {
+            headers.insert("X-Team-Id", header::HeaderValue::from(team_id));
+        }
+
+        Ok(Self {
+            http: reqwest::Client::builder()
+                .default_headers(headers)
+                .timeout(Duration::from_secs(60))
+                .build()?,
+            server: args.server.trim_end_matches('/').to_string(),
+        })
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}{path}", self.server, Self::API_PREFIX)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str, query: &[(&str, String)]) -> Result<T> {
+        let resp = self.http.get(self.url(path)).query(query).send().await?;
+        Self::data(resp).await
+    }
+
+    async fn post<T: DeserializeOwned>(&self, path: &str, body: &Value) -> Result<T> {
+        let resp = self.http.post(self.url(path)).json(body).send().await?;
+        Self::data(resp).await
+    }
+
+    /// unwrap the data of the standard response, {"code":20000,"msg":"success","data":...}
+    async fn data<T: DeserializeOwned>(resp: reqwest::Response) -> Result<T> {
+        let status = resp.status();
+        let body: Value = resp
+            .json()
+            .await
+            .map_err(|e| anyhow!("invalid response, status {status} - {e}"))?;
+        match body["code"].as_i64() {
+            Some(20000) => Ok(serde_json::from_value(body["data"].clone())?),
+            Some(50401) => bail!("not login, check the api token"),
+            _ => bail!("{}", body["msg"].as_str().unwrap_or("unknown error")),
+        }
+    }
+}
+
+fn field(v: &Value, key: &str) -> String {
+    match &v[key] {
+        Value::Null => "-".to_string(),
+        Value::String(s) if s.is_empty() => "-".to_string(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn print_table(list: &[Value], columns: &[&str]) {
+    let rows: Vec<Vec<String>> = list
+        .iter()
+        .map(|v| columns.iter().map(|c| field(v, c)).collect())
+        .collect();
+    let widths: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            rows.iter()
+                .map(|r| r[i].chars().count())
+                .chain([c.len()])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+
+    let line = |cells: Vec<String>| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(c, w)| format!("{c:<w$}"))
+            .collect::<Vec<_>>()
+            .join("  ")
+    };
+    println!(
+        "{}",
+        line(columns.iter().map(|c| c.to_uppercase()).collect())
+    );
+    for row in rows {
+        println!("{}", line(row));
+    }
+}
+
+async fn list_job(client: &Client, name: Option<String>, page: u64, page_size: u64) -> Result<()> {
+    let mut query = vec![
+        ("page", page.to_string()),
+        ("page_size", page_size.to_string()),
+    ];
+    if let Some(name) = name {
+        query.push(("name", name));
+    }
+    let data: Value = client.get("/job/list", &query).await?;
+    print_table(
+        data["list"].as_array().map_or(&[], |v| v.as_slice()),
+        &[
+            "eid",
+            "name",
+            "job_type",
+            "executor_name",
+            "updated_user",
+            "updated_time",
+        ],
+    );
+    println!("total: {}", data["total"]);
+    Ok(())
+}
+
+async fn list_instance(
+    client: &Client,
+    ip: Option<String>,
+    status: Option<u8>,
+    page: u64,
+    page_size: u64,
+) -> Result<Vec<Value>> {
+    let mut query = vec![
+        ("page", page.to_string()),
+        ("page_size", page_size.to_string()),
+    ];
+    if let Some(ip) = ip {
+        query.push(("ip", ip));
+    }
+    if let Some(status) = status {
+        query.push(("status", status.to_string()));
+    }
+    let data: Value = client.get("/instance/list", &query).await?;
+    Ok(data["list"].as_array().cloned().unwrap_or_default())
+}
+
+/// instance ids matched by the selectors of the dispatch
+async fn select_instances(client: &Client, args: &DispatchArgs) -> Result<Vec<String>> {
+    let mut ids = args.instance_id.clone();
+    if args.ip.is_empty() && args.os.is_none() && !args.all {
+        return Ok(ids);
+    }
+
+    let list = list_instance(client, None, Some(1), 1, 10000).await?;
+    for v in list {
+        let ip_matched = args.ip.is_empty() || args.ip.iter().any(|ip| v["ip"] == *ip);
+        let os_matched = args
+            .os
+            .as_ref()
+            .is_none_or(|os| v["os"].as_str().is_some_and(|v| v.eq_ignore_ascii_case(os)));
+        if ip_matched
+            && os_matched
+            && let Some(id) = v["instance_id"].as_str()
+        {
+            ids.push(id.to_string());
+        }
+    }
+    ids.sort();
+    ids.dedup();
+    Ok(ids)
+}
+
+async fn dispatch(client: &Client, args: DispatchArgs) -> Result<()> {
+    let instance_ids = select_instances(client, &args).await?;
+    if instance_ids.is_empty() {
+        bail!("no instance is selected, use --ip, --instance-id, --os or --all");
+    }
+    let job_args = args
+        .args
+        .as_deref()
+        .map(serde_json::from_str::<Value>)
+        .transpose()
+        .map_err(|e| anyhow!("invalid args - {e}"))?;
+    let name = args.name.clone().unwrap_or_else(|| {
+        format!(
+            "{}-{}",
+            args.eid,
+            chrono::Local::now().format("%Y%m%d%H%M%S")
+        )
+    });
+
+    let data: Value = client
+        .post(
+            "/job/dispatch",
+            &json!({
+                "schedule_name": name,
+                "schedule_type": args.schedule_type,
+                "endpoints": instance_ids.iter().map(|v| json!({"instance_id": v})).collect::<Vec<_>>(),
+                "eid": args.eid,
+                "args": job_args,
+                "is_sync": args.sync,
+                "action": args.action,
+                "override_reason": args.override_reason,
+            }),
+        )
+        .await?;
+    println!(
+        "dispatched {} to {} instances, schedule {}",
+        args.eid,
+        instance_ids.len(),
+        data["result"]
+    );
+    Ok(())
+}
+
+async fn status(
+    client: &Client,
+    schedule_type: String,
+    job_type: String,
+    name: Option<String>,
+    ip: Option<String>,
+    watch: bool,
+    interval: u64,
+) -> Result<()> {
+    let mut query = vec![
+        ("schedule_type", schedule_type),
+        ("job_type", job_type),
+        ("page_size", "50".to_string()),
+    ];
+    if let Some(name) = name {
+        query.push(("schedule_name", name));
+    }
+    if let Some(ip) = ip {
+        query.push(("bind_ip", ip));
+    }
+
+    loop {
+        let data: Value = client.get("/job/running-status-list", &query).await?;
+        if watch {
+            // clear the screen before redraw
+            print!("\x1b[2J\x1b[H");
+        }
+        print_table(
+            data["list"].as_array().map_or(&[], |v| v.as_slice()),
+            &[
+                "schedule_name",
+                "bind_ip",
+                "run_status",
+                "schedule_status",
+                "result_status",
+                "exit_code",
+                "start_time",
+                "end_time",
+            ],
+        );
+        if !watch {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+    }
+}
+
+async fn logs(
+    client: &Client,
+    eid: String,
+    ip: Option<String>,
+    job_type: String,
+    tail: u64,
+    follow: bool,
+    interval: u64,
+) -> Result<()> {
+    let mut query = vec![
+        ("eid", eid),
+        ("job_type", job_type),
+        ("page_size", tail.max(1).to_string()),
+    ];
+    if let Some(ip) = ip {
+        query.push(("bind_ip", ip));
+    }
+
+    let mut last_id = 0;
+    loop {
+        let data: Value = client.get("/job/exec-list", &query).await?;
+        let mut list = data["list"].as_array().cloned().unwrap_or_default();
+        list.sort_by_key(|v| v["id"].as_u64().unwrap_or_default());
+
+        for v in list {
+            let id = v["id"].as_u64().unwrap_or_default();
+            if id <= last_id {
+                continue;
+            }
+            last_id = id;
+            println!(
+                "\x1b[33m==> {} {} exit {} ({}) {}\x1b[0m",
+                field(&v, "bind_ip"),
+                field(&v, "schedule_name"),
+                field(&v, "exit_code"),
+                field(&v, "result_status"),
+                field(&v, "end_time"),
+            );
+            println!("{}", v["output"].as_str().unwrap_or_default());
+        }
+        if !follow {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+    }
+}
+
+async fn set_instance_status(client: &Client, ids: Vec<u64>, status: bool) -> Result<()> {
+    let data: Value = client
+        .post(
+            "/instance/set_status",
+            &json!({"status": status, "instance_ids": ids}),
+        )
+        .await?;
+    println!("affected: {}", data["result"]);
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = CtlArgs::parse();
+    let client = Client::new(&args)?;
+
+    match args.command {
+        Command::Job(JobCommand::List {
+            name,
+            page,
+            page_size,
+        }) => list_job(&client, name, page, page_size).await,
+        Command::Dispatch(v) => dispatch(&client, v).await,
+        Command::Status {
+            schedule_type,
+            job_type,
+            name,
+            ip,
+            watch,
+            interval,
+        } => status(&client, schedule_type, job_type, name, ip, watch, interval).await,
+        Command::Logs {
+            eid,
+            ip,
+            job_type,
+            tail,
+            follow,
+            interval,
+        } => logs(&client, eid, ip, job_type, tail, follow, interval).await,
+        Command::Instance(InstanceCommand::List {
+            ip,
+            status,
+            page,
+            page_size,
+        }) => {
+            let list = list_instance(&client, ip, status, page, page_size).await?;
+            print_table(
+                &list,
+                &[
+                    "id",
+                    "instance_id",
+                    "ip",
+                    "namespace",
+                    "status",
+                    "os",
+                    "arch",
+                    "agent_version",
+                ],
+            );
+            Ok(())
+        }
+        Command::Instance(InstanceCommand::Enable { id }) => {
+            set_instance_status(&client, id, true).await
+        }
+        Command::Instance(InstanceCommand::Disable { id }) => {
+            set_instance_status(&client, id, false).await
+        }
+    }
+}
+
+#[test]
+fn test_ctl_args() {
+    assert_eq!(field(&json!({"name": "a"}), "name"), "a");
+    assert_eq!(field(&json!({"name": ""}), "name"), "-");
+    assert_eq!(field(&json!({"id": 1}), "id"), "1");
+    assert_eq!(field(&json!({}), "id"), "-");
+
+    let args = CtlArgs::try_parse_from([
+        "jiascheduler-ctl",
+        "--token",
+        "abc",
+        "dispatch",
+        "--eid",
+        "j-1",
+        "--ip",
+        "10.0.0.1",
+        "--ip",
+        "10.0.0.2",
+    ])
+    .unwrap();
+    let Command::Dispatch(v) = args.command else {
+        panic!("unexpected command {:?}", args.command);
+    };
+    assert_eq!(v.ip, ["10.0.0.1", "10.0.0.2"]);
+    assert_eq!(v.action, "exec");
+
+    assert!(
+        CtlArgs::try_parse_from(["jiascheduler-ctl", "--token", "abc", "instance", "enable"])
+            .is_err()
+    );
+}
@@ -32,6 +32,9 @@ struct WebapiArgs {
     /// Set log level, eg: "info", "debug", "warn", "error" etc.
     #[arg(long, default_value_t = String::from("error"))]
     log_level: String,
+    /// Output logs as JSON lines
+    #[arg(long)]
+    log_json: bool,
 
     /// Comet server listen address, eg: "0.0.0.0:3000"
     #[arg(short, long, default_value_t = String::from("0.0.0.0:3000"))]
@@ -68,14 +71,8 @@ struct WebapiArgs {
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = WebapiArgs::parse();
-    unsafe {
-        std::env::set_var("RUST_LOG", args.log_level);
-        if args.debug {
-            std::env::set_var("RUST_LOG", "debug");
-        }
-    }
-
-    tracing_subscriber::fmt::init();
+    let level = if args.debug { "debug" } else { &args.log_level };
+    utils::init_tracing(level, args.log_json)?;
 
     let (console_tx, console_rx) = channel::<Conf>();
     let (comet_tx, comet_rx) = channel::<()>();
@@ -84,7 +81,7 @@ async fn main() -> Result<()> {
     let console_conf_clone = console_conf.clone();
     let comet_bind_addr = args.comet_bind_addr.clone();
 
-    tokio::spawn(async move {
+    let comet_handle = tokio::spawn(async move {
         let conf = console_rx.await.unwrap();
         console_conf_clone.lock().await.replace(conf.clone());
         info!("starting comet");
@@ -93,6 +90,10 @@ async fn main() -> Result<()> {
                 redis_url: conf.redis_url,
                 bind_addr: comet_bind_addr.clone(),
                 secret: conf.comet_secret,
+                tls: None,
+                bus_url: conf.bus_url,
+                group: None,
+                require_agent_credential: false,
             },
             Some(comet_tx),
         )
@@ -113,7 +114,8 @@ async fn main() -> Result<()> {
             args.output_dir,
             SshConnectionOption::build(args.ssh_user, args.ssh_password, args.ssh_port),
             AssignUserOption::build(args.assign_username, args.assign_password),
-        );
+        )
+        .with_agent_version(env!("CARGO_PKG_VERSION"));
         info!("starting agent");
         if let Err(e) = scheduler.connect_comet().await {
             error!("failed connect to comet - {e}");
@@ -128,8 +130,15 @@ async fn main() -> Result<()> {
             redis_url: None,
             config_file: args.config,
             bind_addr: args.console_bind_addr,
+            break_glass: false,
         },
         Some(console_tx),
     )
-    .await
+    .await?;
+
+    // comet receives the same signal, wait for it to finish draining
+    if let Err(e) = comet_handle.await {
+        error!("comet exited abnormally - {e}");
+    }
+    Ok(())
 }
@@ -0,0 +1,100 @@
+use std::{net::Ipv4Addr, time::Duration};
+
+use anyhow::Result;
+use clap::Parser;
+use tokio::time::sleep;
+
+use automate::simulator::{Simulator, SimulatorOption};
+
+/// Fake agents speaking the bridge protocol to load test comet and the console,
+/// the dispatched jobs are not executed. Build with `--features simulator`
+#[derive(Parser, Debug)]
+#[command(
+    author = "iwannay <772648576@qq.com>",
+    about = "Simulated agents for load testing jiascheduler",
+    version
+)]
+struct SimulatorArgs {
+    #[arg(long, default_values_t = vec![String::from("ws://127.0.0.1:3000")])]
+    comet_addr: Vec<String>,
+    #[arg(long, default_value_t = String::from("rYzBYE+cXbtdMg=="))]
+    comet_secret: String,
+    #[arg(short, long, default_value_t = String::from("simulator"))]
+    namespace: String,
+    /// Number of the agents
+    #[arg(short, long, default_value_t = 100)]
+    agents: u32,
+    /// Ip of the first agent, the others take the following ips
+    #[arg(long, default_value_t = Ipv4Addr::new(10, 0, 0, 1))]
+    ip_start: Ipv4Addr,
+    /// Agents connected per second, 0 to connect all of them at once
+    #[arg(long, default_value_t = 100)]
+    connect_rate: u32,
+    /// Seconds between the heartbeats
+    #[arg(long, default_value_t = 5)]
+    heartbeat_interval: u64,
+    /// Min delay in milliseconds before answering a request of comet
+    #[arg(long, default_value_t = 0)]
+    min_latency: u64,
+    /// Max delay in milliseconds before answering a request of comet
+    #[arg(long, default_value_t = 50)]
+    max_latency: u64,
+    /// Min duration in milliseconds of a simulated run
+    #[arg(long, default_value_t = 100)]
+    min_run_duration: u64,
+    /// Max duration in milliseconds of a simulated run
+    #[arg(long, default_value_t = 2000)]
+    max_run_duration: u64,
+    /// Percent of the dispatches rejected
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=100))]
+    reject_rate: u8,
+    /// Percent of the runs exiting with code 1
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u8).range(0..=100))]
+    failure_rate: u8,
+    /// Percent chance every heartbeat interval that an agent drops its connection and reconnects
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=100))]
+    disconnect_rate: u8,
+    /// Seconds between the printed stats
+    #[arg(long, default_value_t = 10)]
+    report_interval: u64,
+    /// Set log level, eg: "trace", "debug", "info", "warn", "error" etc.
+    #[arg(long, default_value_t = String::from("error"))]
+    log_level: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = SimulatorArgs::parse();
+    utils::init_tracing(&args.log_level, false)?;
+
+    let simulator = Simulator::new(SimulatorOption {
+        namespace: args.namespace,
+        comet_addr: args.comet_addr,
+        comet_secret: args.comet_secret,
+        agents: args.agents,
+        ip_start: args.ip_start,
+        connect_rate: args.connect_rate,
+        heartbeat_interval: Duration::from_secs(args.heartbeat_interval.max(1)),
+        min_latency: Duration::from_millis(args.min_latency),
+        max_latency: Duration::from_millis(args.max_latency),
+        min_run_duration: Duration::from_millis(args.min_run_duration),
+        max_run_duration: Duration::from_millis(args.max_run_duration),
+        reject_rate: args.reject_rate,
+        failure_rate: args.failure_rate,
+        disconnect_rate: args.disconnect_rate,
+    });
+
+    let stats = simulator.stats();
+    let report_interval = Duration::from_secs(args.report_interval.max(1));
+    tokio::spawn(async move {
+        loop {
+            sleep(report_interval).await;
+            println!("{}", stats.summary());
+        }
+    });
+
+    simulator.run().await?;
+    tokio::signal::ctrl_c().await?;
+    println!("{}", simulator.stats().summary());
+    Ok(())
+}